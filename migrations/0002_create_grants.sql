@@ -0,0 +1,14 @@
+-- Roles of users and groups on buckets and on prefixes within them
+
+CREATE TABLE grants (
+    id BIGINT UNSIGNED NOT NULL AUTO_INCREMENT,
+    subject_type VARCHAR(8) NOT NULL,
+    subject VARCHAR(128) NOT NULL,
+    bucket VARCHAR(63) NOT NULL,
+    prefix VARCHAR(512) NOT NULL DEFAULT '',
+    role VARCHAR(16) NOT NULL,
+    created_at DATETIME(3) NOT NULL,
+    PRIMARY KEY (id),
+    UNIQUE KEY grants_subject_bucket_prefix (subject_type, subject, bucket, prefix),
+    KEY grants_bucket (bucket)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COLLATE = utf8mb4_bin;
@@ -0,0 +1,21 @@
+-- Groups of users and other groups, which roles can be granted to
+-- (the table is not named `groups`, which is a reserved word in MySQL 8)
+
+CREATE TABLE access_groups (
+    id BIGINT UNSIGNED NOT NULL AUTO_INCREMENT,
+    name VARCHAR(64) NOT NULL,
+    description VARCHAR(512) NOT NULL DEFAULT '',
+    created_at DATETIME(3) NOT NULL,
+    PRIMARY KEY (id),
+    UNIQUE KEY access_groups_name (name)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COLLATE = utf8mb4_bin;
+
+CREATE TABLE group_members (
+    group_id BIGINT UNSIGNED NOT NULL,
+    member_type VARCHAR(8) NOT NULL,
+    member VARCHAR(128) NOT NULL,
+    added_at DATETIME(3) NOT NULL,
+    PRIMARY KEY (group_id, member_type, member),
+    KEY group_members_member (member_type, member),
+    CONSTRAINT group_members_group FOREIGN KEY (group_id) REFERENCES access_groups (id) ON DELETE CASCADE
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COLLATE = utf8mb4_bin;
@@ -0,0 +1,14 @@
+-- Identities at OpenID Connect providers that users log in with, and the
+-- email addresses those providers know them by
+
+ALTER TABLE users ADD COLUMN email VARCHAR(255) NULL AFTER role;
+
+CREATE TABLE user_identities (
+    user_id BIGINT UNSIGNED NOT NULL,
+    issuer VARCHAR(255) NOT NULL,
+    subject VARCHAR(255) NOT NULL,
+    created_at DATETIME(3) NOT NULL,
+    PRIMARY KEY (issuer, subject),
+    KEY user_identities_user_id (user_id),
+    CONSTRAINT user_identities_user FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COLLATE = utf8mb4_bin;
@@ -0,0 +1,15 @@
+-- TOTP two-factor authentication of users who log in with a password,
+-- and the hashed recovery codes they can use instead of a code
+
+ALTER TABLE users
+    ADD COLUMN totp_secret VARCHAR(64) NULL AFTER email,
+    ADD COLUMN totp_enabled BOOLEAN NOT NULL DEFAULT FALSE AFTER totp_secret,
+    ADD COLUMN totp_last_step BIGINT NULL AFTER totp_enabled;
+
+CREATE TABLE recovery_codes (
+    user_id BIGINT UNSIGNED NOT NULL,
+    code_hash CHAR(64) NOT NULL,
+    created_at DATETIME(3) NOT NULL,
+    PRIMARY KEY (user_id, code_hash),
+    CONSTRAINT recovery_codes_user FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COLLATE = utf8mb4_bin;
@@ -0,0 +1,18 @@
+-- Server-side sessions of the web UI; only the SHA-256 hash of a session token is stored
+
+CREATE TABLE sessions (
+    id BIGINT UNSIGNED NOT NULL AUTO_INCREMENT,
+    token_hash CHAR(64) NOT NULL,
+    user_id BIGINT UNSIGNED NOT NULL,
+    csrf_token VARCHAR(64) NOT NULL,
+    user_agent VARCHAR(255) NULL,
+    ip_address VARCHAR(45) NULL,
+    created_at DATETIME(3) NOT NULL,
+    last_seen_at DATETIME(3) NOT NULL,
+    expires_at DATETIME(3) NOT NULL,
+    PRIMARY KEY (id),
+    UNIQUE KEY sessions_token_hash (token_hash),
+    KEY sessions_user_id (user_id),
+    KEY sessions_expires_at (expires_at),
+    CONSTRAINT sessions_user FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COLLATE = utf8mb4_bin;
@@ -0,0 +1,22 @@
+-- Audit log of the requests to the API; rows are only ever added
+
+CREATE TABLE audit_log (
+    id BIGINT UNSIGNED NOT NULL AUTO_INCREMENT,
+    occurred_at DATETIME(3) NOT NULL,
+    request_id VARCHAR(128) NULL,
+    actor VARCHAR(255) NULL,
+    ip_address VARCHAR(45) NULL,
+    action VARCHAR(64) NOT NULL,
+    method VARCHAR(16) NOT NULL,
+    bucket VARCHAR(255) NULL,
+    object_key VARCHAR(1024) NULL,
+    target VARCHAR(255) NULL,
+    outcome VARCHAR(16) NOT NULL,
+    status SMALLINT UNSIGNED NOT NULL,
+    bytes BIGINT UNSIGNED NULL,
+    PRIMARY KEY (id),
+    KEY audit_log_occurred_at (occurred_at),
+    KEY audit_log_actor (actor, id),
+    KEY audit_log_action (action, id),
+    KEY audit_log_bucket_key (bucket, object_key(255), id)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COLLATE = utf8mb4_bin;
@@ -0,0 +1,12 @@
+-- Short-lived tokens a password login hands out for setting up two-factor
+-- authentication; only the SHA-256 hash of a token is stored
+
+CREATE TABLE two_factor_setup_tokens (
+    token_hash CHAR(64) NOT NULL,
+    user_id BIGINT UNSIGNED NOT NULL,
+    created_at DATETIME(3) NOT NULL,
+    expires_at DATETIME(3) NOT NULL,
+    PRIMARY KEY (token_hash),
+    KEY two_factor_setup_tokens_user_id (user_id),
+    CONSTRAINT two_factor_setup_tokens_user FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COLLATE = utf8mb4_bin;
@@ -6,5 +6,12 @@
 //! The API is versioned, with the current version being v1.
 
 pub mod config;
+pub mod error;
 pub mod v1;
-pub mod health; 
\ No newline at end of file
+pub mod health;
+pub mod idempotency;
+pub mod request_id;
+pub mod auth;
+pub mod session;
+pub mod access;
+pub mod audit;
@@ -0,0 +1,93 @@
+//! # Authorization
+//!
+//! This module checks requests against the grants of their principal.
+//! Handlers take an `Access` argument, which holds what the client of the
+//! request may do, and check it before touching a bucket or object.
+//!
+//! Access is unrestricted for administrators and if authentication is
+//! disabled. Grants are kept in the user database, so without one, other
+//! principals have no access at all.
+
+use std::sync::Arc;
+
+use actix_web::dev::Payload;
+use actix_web::{web, Error, FromRequest, HttpMessage, HttpRequest};
+use futures::future::LocalBoxFuture;
+use log::error;
+
+use crate::api::error::{ApiError, ErrorCode};
+use crate::rdlib::access::authorizer::{Access, Authorizer};
+use crate::rdlib::access::grant::Permission;
+use crate::rdlib::auth::principal::Principal;
+
+/// Describes a key for error messages.
+fn describe(bucket: &str, key: &str) -> String {
+    if key.is_empty() {
+        format!("bucket '{}'", bucket)
+    } else {
+        format!("'{}' in bucket '{}'", key, bucket)
+    }
+}
+
+/// Checks if the client has a permission on a key.
+///
+/// # Arguments
+///
+/// * `access` - What the client may do
+/// * `bucket` - The bucket of the key
+/// * `key` - The key of an object, a prefix ending with `/`, or empty for the whole bucket
+/// * `permission` - What the request does with the key
+///
+/// # Returns
+///
+/// `Ok(())`, or a `403 Forbidden` error naming the role the request needs
+pub fn require(access: &Access, bucket: &str, key: &str, permission: Permission) -> Result<(), ApiError> {
+    if access.allows(bucket, key, permission) {
+        return Ok(());
+    }
+    let role = permission.minimum_role();
+    Err(ApiError::new(
+        ErrorCode::PermissionDenied,
+        format!("This request needs the {} role on {}", role, describe(bucket, key))
+    ).extension("required_role", role))
+}
+
+/// Checks if the client can see a key, i.e. has a role on it or on a
+/// prefix below it.
+///
+/// # Returns
+///
+/// `Ok(())`, or a `403 Forbidden` error
+pub fn require_visible(access: &Access, bucket: &str, key: &str) -> Result<(), ApiError> {
+    if access.can_see(bucket, key) {
+        Ok(())
+    } else {
+        Err(ApiError::new(ErrorCode::PermissionDenied, format!("You have no role on {}", describe(bucket, key))))
+    }
+}
+
+/// Handlers taking an `Access` argument get what the client of the request
+/// may do, looked up from the grants of its principal.
+impl FromRequest for Access {
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let principal = req.extensions().get::<Principal>().cloned();
+        let authorizer = req.app_data::<web::Data<Arc<Authorizer>>>().cloned();
+
+        Box::pin(async move {
+            let Some(principal) = principal else {
+                return Ok(Access::unrestricted());
+            };
+            let Some(authorizer) = authorizer else {
+                let access = if principal.is_admin() { Access::unrestricted() } else { Access::from_grants(Vec::new()) };
+                return Ok(access.with_scopes(principal.scopes()));
+            };
+            authorizer.access_for(&principal).await.map_err(|e| {
+                error!("Error looking up the grants of '{}': {:?}", principal.subject, e);
+                e.into()
+            })
+        })
+    }
+}
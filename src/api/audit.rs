@@ -0,0 +1,184 @@
+//! # Audit Middleware
+//!
+//! This module provides the middleware that records every request to the
+//! API in the audit log of the `AuditService` of the app. It wraps the
+//! authentication middleware, so it sees the principal of the request as
+//! well as the requests it rejected.
+//!
+//! The action is named after the endpoint the request matched; the bucket
+//! and object key are taken from the path. Request bodies are never
+//! recorded, and neither are the tokens in the paths of drop links. A
+//! request is answered even if its record cannot be stored; the failure
+//! is logged instead.
+
+use std::future::{ready, Ready};
+use std::rc::Rc;
+use std::sync::Arc;
+
+use actix_web::body::{BodySize, MessageBody};
+use actix_web::dev::{forward_ready, Path, ResourceDef, Service, ServiceRequest, ServiceResponse, Transform, Url};
+use actix_web::http::header::CONTENT_LENGTH;
+use actix_web::http::Method;
+use actix_web::{web, Error, HttpMessage, HttpRequest};
+use chrono::Utc;
+use futures::future::LocalBoxFuture;
+use log::error;
+
+use crate::api::request_id;
+use crate::api::session::client_ip;
+use crate::rdlib::audit::record::{action_for, AuditOutcome, NewAuditRecord};
+use crate::rdlib::audit::service::AuditService;
+use crate::rdlib::auth::principal::Principal;
+
+/// The path parameters that are not recorded as target: the bucket and key
+/// have their own columns, and drop link tokens are credentials
+const UNRECORDED_PARAMETERS: [&str; 3] = ["bucket", "key", "token"];
+
+/// The route whose `name` parameter is a bucket
+const DELETE_BUCKET_PATTERN: &str = "/api/v1/bucket/{name}";
+
+/// What the audit log records of a request before it is handled.
+struct RequestFacts {
+    method: Method,
+    ip_address: Option<String>,
+    request_id: Option<String>,
+    request_bytes: Option<u64>,
+}
+
+impl RequestFacts {
+    fn of(req: &ServiceRequest) -> Self {
+        let request_bytes = req.headers().get(CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u64>().ok());
+        Self {
+            method: req.method().clone(),
+            ip_address: client_ip(req.request()),
+            request_id: request_id::current().map(|context| context.id),
+            request_bytes,
+        }
+    }
+
+    /// Builds the record of the request once it is answered.
+    ///
+    /// # Arguments
+    ///
+    /// * `req` - The request, or `None` if it failed without a response
+    /// * `status` - The status of the response
+    /// * `response_size` - The size of the response body, if known
+    fn into_record(self, req: Option<&HttpRequest>, status: u16, response_size: Option<u64>) -> NewAuditRecord {
+        let pattern = req.and_then(HttpRequest::match_pattern);
+        let params = req.zip(pattern.as_deref()).map(|(req, pattern)| path_params(req, pattern));
+        let param = |name: &str| params.as_ref().and_then(|params| params.get(name)).map(str::to_string);
+
+        let bucket_is_name = pattern.as_deref() == Some(DELETE_BUCKET_PATTERN);
+        let bucket = if bucket_is_name { param("name") } else { param("bucket") };
+        let target = params
+            .as_ref()
+            .map(|params| params.iter()
+                .filter(|(name, _)| !(UNRECORDED_PARAMETERS.contains(name) || bucket_is_name && *name == "name"))
+                .map(|(name, value)| format!("{}={}", name, value))
+                .collect::<Vec<_>>()
+                .join(","))
+            .filter(|target| !target.is_empty());
+
+        // Uploads are measured by their body, downloads by the response
+        let bytes = if [Method::POST, Method::PUT, Method::PATCH].contains(&self.method) {
+            self.request_bytes
+        } else if self.method == Method::GET {
+            response_size
+        } else {
+            None
+        };
+
+        NewAuditRecord {
+            occurred_at: Utc::now(),
+            request_id: self.request_id,
+            actor: req.and_then(|req| req.extensions().get::<Principal>().map(Principal::grant_subject)),
+            ip_address: self.ip_address,
+            action: action_for(self.method.as_str(), pattern.as_deref()),
+            method: self.method.to_string(),
+            bucket,
+            key: param("key"),
+            target,
+            outcome: AuditOutcome::from_status(status),
+            status,
+            bytes,
+        }
+    }
+}
+
+/// The path parameters of a request. Requests that authentication
+/// rejected were never routed, so their parameters are captured from the
+/// pattern of the endpoint they would have reached.
+fn path_params(req: &HttpRequest, pattern: &str) -> Path<Url> {
+    let mut params = req.match_info().clone();
+    if params.segment_count() == 0 && pattern.contains('{') {
+        params = Path::new(Url::new(req.uri().clone()));
+        ResourceDef::new(pattern).capture_match_info(&mut params);
+    }
+    params
+}
+
+/// Middleware that records requests in the audit log.
+pub struct AuditLog;
+
+impl<S, B> Transform<S, ServiceRequest> for AuditLog
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = AuditLogMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(AuditLogMiddleware { service: Rc::new(service) }))
+    }
+}
+
+/// The service created by the `AuditLog` middleware.
+pub struct AuditLogMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for AuditLogMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let audit = req.app_data::<web::Data<Arc<AuditService>>>().cloned();
+
+        Box::pin(async move {
+            let Some(audit) = audit else {
+                return service.call(req).await;
+            };
+
+            let facts = RequestFacts::of(&req);
+            let result = service.call(req).await;
+            let record = match &result {
+                Ok(response) => {
+                    let response_size = match response.response().body().size() {
+                        BodySize::Sized(size) => Some(size),
+                        _ => None,
+                    };
+                    facts.into_record(Some(response.request()), response.status().as_u16(), response_size)
+                },
+                Err(e) => facts.into_record(None, e.as_response_error().status_code().as_u16(), None),
+            };
+            if let Err(e) = audit.record(record).await {
+                error!("Error recording a request in the audit log: {:?}", e);
+            }
+            result
+        })
+    }
+}
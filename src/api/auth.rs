@@ -0,0 +1,263 @@
+//! # Authentication Middleware
+//!
+//! This module provides the middleware that authenticates the requests to
+//! the API. Requests must carry a bearer token in the `Authorization`
+//! header; the principal it identifies is stored with the request, where
+//! handlers get it by taking a `Principal` argument. Requests without a
+//! valid token are rejected with `401 Unauthorized`.
+//!
+//! Service accounts authenticate with an API key instead, sent in the
+//! `X-API-Key` header or as bearer token. Keys are told apart from JWTs by
+//! their `rdk_` prefix and checked by the `ApiKeyService` of the app.
+//!
+//! Requests without either fall back to the session cookie of the web UI,
+//! checked with the `UserService` of the app; see the `session` module for
+//! the CSRF tokens these requests need. A bearer token takes precedence
+//! over the cookie.
+//!
+//! The anonymous drop link endpoints stay public, as their token in the
+//! path is the credential, and so do the endpoints that log users in and
+//! hand out tokens.
+
+use std::fmt;
+use std::future::{ready, Ready};
+use std::rc::Rc;
+use std::sync::Arc;
+
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::{HeaderMap, HeaderValue, AUTHORIZATION, WWW_AUTHENTICATE};
+use actix_web::{web, Error, FromRequest, HttpMessage, HttpRequest, HttpResponse, ResponseError};
+use futures::future::LocalBoxFuture;
+use log::{debug, error, warn};
+
+use crate::api::error::{ApiError, ErrorCode};
+use crate::api::session::authenticate_session;
+use crate::rdlib::apikeys::error::ApiKeyError;
+use crate::rdlib::apikeys::key::is_api_key;
+use crate::rdlib::apikeys::service::ApiKeyService;
+use crate::rdlib::auth::jwt::JwtVerifier;
+use crate::rdlib::auth::principal::Principal;
+
+/// The paths below which requests need no token
+pub const PUBLIC_PATH_PREFIXES: [&str; 6] = [
+    "/api/v1/drop/",
+    "/api/v1/auth/login",
+    "/api/v1/auth/refresh",
+    "/api/v1/auth/logout",
+    "/api/v1/auth/oidc/",
+    "/api/v1/auth/2fa/",
+];
+
+/// The header API keys can be sent in
+pub const API_KEY_HEADER: &str = "X-API-Key";
+
+/// The realm announced in `WWW-Authenticate` challenges
+pub const REALM: &str = "rustdok";
+
+/// Gets the bearer token of a request.
+///
+/// # Returns
+///
+/// The token, or `None` if the request has no `Authorization: Bearer` header
+pub fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    let value = headers.get(AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = value.split_once(' ')?;
+    let token = token.trim();
+    (scheme.eq_ignore_ascii_case("Bearer") && !token.is_empty()).then_some(token)
+}
+
+/// Gets the API key of a request, from the `X-API-Key` header or a bearer
+/// token that is an API key.
+///
+/// # Returns
+///
+/// The key, or `None` if the request has no API key
+pub fn api_key(headers: &HeaderMap) -> Option<&str> {
+    match headers.get(API_KEY_HEADER) {
+        Some(value) => value.to_str().ok().map(str::trim).filter(|key| !key.is_empty()),
+        None => bearer_token(headers).filter(|token| is_api_key(token)),
+    }
+}
+
+/// Checks if a path can be requested without a token.
+pub fn is_public_path(path: &str) -> bool {
+    PUBLIC_PATH_PREFIXES.iter().any(|prefix| path.starts_with(prefix))
+}
+
+/// Builds the `401 Unauthorized` response for a request without a valid token.
+///
+/// # Arguments
+///
+/// * `error` - Why the token or API key was rejected, or `None` if there was none
+pub fn unauthorized(error: Option<&dyn fmt::Display>) -> HttpResponse {
+    let (api_error, challenge) = match error {
+        None => (
+            ApiError::new(ErrorCode::Unauthorized, "This endpoint requires a bearer token"),
+            format!("Bearer realm=\"{}\"", REALM),
+        ),
+        Some(error) => (
+            ApiError::new(ErrorCode::InvalidToken, error.to_string()),
+            format!("Bearer realm=\"{}\", error=\"invalid_token\", error_description=\"{}\"", REALM, error),
+        ),
+    };
+    let mut response = api_error.field("Authorization").error_response();
+    if let Ok(value) = HeaderValue::from_str(&challenge) {
+        response.headers_mut().insert(WWW_AUTHENTICATE, value);
+    }
+    response
+}
+
+/// Checks if the principal may use the administration endpoints.
+///
+/// # Returns
+///
+/// `Ok(())`, or a `403 Forbidden` error for principals without the `admin` role
+pub fn require_admin(principal: &Principal) -> Result<(), ApiError> {
+    if principal.is_admin() {
+        Ok(())
+    } else {
+        Err(ApiError::new(ErrorCode::Forbidden, "This endpoint is reserved for administrators"))
+    }
+}
+
+/// Middleware that requires a valid bearer token.
+#[derive(Clone)]
+pub struct Authentication {
+    /// The verifier of tokens, or `None` if authentication is disabled
+    verifier: Option<Arc<JwtVerifier>>,
+}
+
+impl Authentication {
+    /// Creates the middleware, verifying tokens with `verifier`.
+    pub fn new(verifier: JwtVerifier) -> Self {
+        Self { verifier: Some(Arc::new(verifier)) }
+    }
+
+    /// Creates a middleware that lets every request through without a principal.
+    pub fn disabled() -> Self {
+        Self { verifier: None }
+    }
+
+    /// Creates the middleware from the environment.
+    ///
+    /// Authentication is disabled if `RUSTDOK_AUTH_DISABLED` is `true`;
+    /// otherwise tokens are verified as configured for `JwtVerifier::from_env`.
+    ///
+    /// # Panics
+    ///
+    /// If authentication is enabled but the JWT keys are missing or invalid
+    pub fn from_env() -> Self {
+        if std::env::var("RUSTDOK_AUTH_DISABLED").is_ok_and(|v| v == "true" || v == "1") {
+            warn!("Authentication is disabled: anyone who can reach the API has full access");
+            return Self::disabled();
+        }
+        match JwtVerifier::from_env() {
+            Ok(verifier) => Self::new(verifier),
+            Err(e) => panic!("{}", e),
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for Authentication
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Transform = AuthenticationMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(AuthenticationMiddleware { service: Rc::new(service), verifier: self.verifier.clone() }))
+    }
+}
+
+/// The service created by the `Authentication` middleware.
+pub struct AuthenticationMiddleware<S> {
+    service: Rc<S>,
+    verifier: Option<Arc<JwtVerifier>>,
+}
+
+impl<S, B> Service<ServiceRequest> for AuthenticationMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let verifier = self.verifier.clone();
+
+        Box::pin(async move {
+            let Some(verifier) = verifier.filter(|_| !is_public_path(req.path())) else {
+                return service.call(req).await.map(ServiceResponse::map_into_boxed_body);
+            };
+
+            if let Some(key) = api_key(req.headers()) {
+                let Some(api_keys) = req.app_data::<web::Data<Arc<ApiKeyService>>>().cloned() else {
+                    return Ok(req.into_response(unauthorized(Some(&"API keys are not enabled on this server"))));
+                };
+                match api_keys.authenticate(key).await {
+                    Ok(principal) => {
+                        debug!("Request {} {} by {}", req.method(), req.path(), principal.subject);
+                        req.extensions_mut().insert(principal);
+                    },
+                    Err(e @ (ApiKeyError::InvalidKey | ApiKeyError::Expired)) => {
+                        debug!("Rejected API key for {} {}: {}", req.method(), req.path(), e);
+                        return Ok(req.into_response(unauthorized(Some(&e))));
+                    },
+                    Err(e) => {
+                        error!("Error checking an API key: {:?}", e);
+                        return Ok(req.into_response(e.error_response()));
+                    }
+                }
+                return service.call(req).await.map(ServiceResponse::map_into_boxed_body);
+            }
+
+            let Some(token) = bearer_token(req.headers()) else {
+                return match authenticate_session(&req).await {
+                    Some(Ok(principal)) => {
+                        debug!("Request {} {} by {} in a session", req.method(), req.path(), principal.subject);
+                        req.extensions_mut().insert(principal);
+                        service.call(req).await.map(ServiceResponse::map_into_boxed_body)
+                    },
+                    Some(Err(response)) => Ok(req.into_response(response)),
+                    None => Ok(req.into_response(unauthorized(None))),
+                };
+            };
+            match verifier.verify(token) {
+                Ok(principal) => {
+                    debug!("Request {} {} by {}", req.method(), req.path(), principal.subject);
+                    req.extensions_mut().insert(principal);
+                },
+                Err(e) => {
+                    debug!("Rejected token for {} {}: {}", req.method(), req.path(), e);
+                    return Ok(req.into_response(unauthorized(Some(&e))));
+                }
+            }
+
+            service.call(req).await.map(ServiceResponse::map_into_boxed_body)
+        })
+    }
+}
+
+/// Handlers taking a `Principal` argument get the authenticated client of
+/// the request, and answer `401 Unauthorized` if there is none.
+impl FromRequest for Principal {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(req.extensions().get::<Principal>().cloned().ok_or_else(|| {
+            actix_web::error::InternalError::from_response("Unauthorized", unauthorized(None)).into()
+        }))
+    }
+}
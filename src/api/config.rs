@@ -5,17 +5,25 @@
 
 use actix_web::{web, Scope};
 
+use crate::api::error;
+
 /// Configure API v1 routes
 ///
 /// This function creates and returns a Scope for the v1 API endpoints.
 /// It registers all the bucket and object related routes with their
-/// respective handler functions.
+/// respective handler functions, and makes invalid bodies, query strings
+/// and paths as well as unknown paths answer with problem documents.
 ///
 /// # Returns
 ///
 /// * `Scope` - An Actix Web Scope configured with all v1 API routes
 pub fn configure_api_v1() -> Scope {
     web::scope("/api/v1")
+        // Error responses
+        .app_data(web::JsonConfig::default().error_handler(error::json_error_handler))
+        .app_data(web::QueryConfig::default().error_handler(error::query_error_handler))
+        .app_data(web::PathConfig::default().error_handler(error::path_error_handler))
+        .default_service(web::route().to(error::not_found))
         // Bucket routes
         .service(crate::api::v1::buckets::list_buckets)
         .service(crate::api::v1::buckets::create_bucket)
@@ -25,7 +33,79 @@ pub fn configure_api_v1() -> Scope {
         .service(crate::api::v1::objects::download_object_from_bucket)
         .service(crate::api::v1::objects::view_object_from_bucket)
         .service(crate::api::v1::objects::upload_object_to_bucket)
+        .service(crate::api::v1::objects::put_object_to_bucket)
         .service(crate::api::v1::objects::delete_object_from_bucket)
         .service(crate::api::v1::objects::create_folder)
         .service(crate::api::v1::objects::check_object_exists_in_bucket)
+        .service(crate::api::v1::objects::move_file_in_bucket)
+        // Drop link routes
+        .service(crate::api::v1::drops::create_drop_link)
+        .service(crate::api::v1::drops::list_drop_links)
+        .service(crate::api::v1::drops::delete_drop_link)
+        .service(crate::api::v1::drops::get_drop_link_info)
+        .service(crate::api::v1::drops::upload_to_drop_link)
+        // Upload policy routes
+        .service(crate::api::v1::policies::get_upload_policy)
+        .service(crate::api::v1::policies::put_upload_policy)
+        .service(crate::api::v1::policies::delete_upload_policy)
+        // Image preview routes
+        .service(crate::api::v1::previews::get_thumbnail)
+        // Archive routes
+        .service(crate::api::v1::archives::create_archive)
+        .service(crate::api::v1::archives::get_archive_contents)
+        // Resumable upload routes
+        .service(crate::api::v1::uploads::get_upload_options)
+        .service(crate::api::v1::uploads::create_upload)
+        .service(crate::api::v1::uploads::get_upload_offset)
+        .service(crate::api::v1::uploads::append_to_upload)
+        .service(crate::api::v1::uploads::terminate_upload)
+        // Authentication routes
+        .service(crate::api::v1::auth::login)
+        .service(crate::api::v1::auth::refresh)
+        .service(crate::api::v1::auth::logout)
+        .service(crate::api::v1::auth::get_current_principal)
+        .service(crate::api::v1::oidc::oidc_login)
+        .service(crate::api::v1::oidc::oidc_callback)
+        .service(crate::api::v1::two_factor::two_factor_setup_token)
+        .service(crate::api::v1::two_factor::setup_two_factor)
+        .service(crate::api::v1::two_factor::enable_two_factor)
+        .service(crate::api::v1::two_factor::disable_two_factor)
+        .service(crate::api::v1::two_factor::regenerate_recovery_codes)
+        .service(crate::api::v1::sessions::login_session)
+        .service(crate::api::v1::sessions::logout_session)
+        .service(crate::api::v1::sessions::list_sessions)
+        .service(crate::api::v1::sessions::revoke_session)
+        // User management routes
+        .service(crate::api::v1::users::list_users)
+        .service(crate::api::v1::users::create_user)
+        .service(crate::api::v1::users::get_user)
+        .service(crate::api::v1::users::update_user)
+        .service(crate::api::v1::users::delete_user)
+        .service(crate::api::v1::users::reset_two_factor)
+        .service(crate::api::v1::users::revoke_user_sessions)
+        // Grant management routes
+        .service(crate::api::v1::grants::list_grants)
+        .service(crate::api::v1::grants::create_grant)
+        .service(crate::api::v1::grants::delete_grant)
+        .service(crate::api::v1::grants::effective_access)
+        // Group management routes
+        .service(crate::api::v1::groups::list_groups)
+        .service(crate::api::v1::groups::create_group)
+        .service(crate::api::v1::groups::get_group)
+        .service(crate::api::v1::groups::delete_group)
+        .service(crate::api::v1::groups::list_members)
+        .service(crate::api::v1::groups::add_member)
+        .service(crate::api::v1::groups::remove_member)
+        // Service account and API key management routes
+        .service(crate::api::v1::service_accounts::list_service_accounts)
+        .service(crate::api::v1::service_accounts::create_service_account)
+        .service(crate::api::v1::service_accounts::get_service_account)
+        .service(crate::api::v1::service_accounts::delete_service_account)
+        .service(crate::api::v1::service_accounts::list_api_keys)
+        .service(crate::api::v1::service_accounts::create_api_key)
+        .service(crate::api::v1::service_accounts::revoke_api_key)
+        .service(crate::api::v1::service_accounts::rotate_api_key)
+        // Audit log routes
+        .service(crate::api::v1::audit::export_audit_records)
+        .service(crate::api::v1::audit::list_audit_records)
 } 
\ No newline at end of file
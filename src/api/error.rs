@@ -0,0 +1,663 @@
+//! # API Error Responses
+//!
+//! This module provides the error responses of the API. Every error is
+//! returned as an RFC 7807 problem document (`application/problem+json`)
+//! with a stable machine-readable `code`, a short `title` per code, a
+//! `detail` message for humans, the path and ID of the request and, where
+//! it applies, the offending `field`. Clients should branch on `code`;
+//! titles and details may change.
+//!
+//! Errors of the storage layer, of the user subsystem, of access control and of the audit log map to the same
+//! status code and error code on every endpoint. Handlers return them with
+//! `?` or `Err(e.into())`.
+
+use std::fmt;
+
+use actix_web::error::{JsonPayloadError, PathError, QueryPayloadError};
+use actix_web::http::StatusCode;
+use actix_web::http::header::ContentType;
+use actix_web::{HttpRequest, HttpResponse, ResponseError};
+use log::error;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::api::request_id;
+use crate::rdlib::access::error::AccessError;
+use crate::rdlib::apikeys::error::ApiKeyError;
+use crate::rdlib::audit::error::AuditError;
+use crate::rdlib::oidc::error::OidcError;
+use crate::rdlib::s3::error::S3Error;
+use crate::rdlib::users::error::UserError;
+
+/// The media type of error responses
+pub const PROBLEM_CONTENT_TYPE: &str = "application/problem+json";
+
+/// The prefix of the `type` URI of error responses, followed by the error code
+pub const PROBLEM_TYPE_PREFIX: &str = "urn:rustdok:problem:";
+
+/// How long clients are asked to wait before retrying a throttled request, in seconds
+pub const RETRY_AFTER_SECONDS: u32 = 1;
+
+/// The machine-readable codes of API errors.
+///
+/// The codes are part of the API: they are documented in the OpenAPI
+/// specification and must not be renamed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// A parameter, header or body of the request is invalid
+    InvalidRequest,
+    /// An object key or path is invalid
+    InvalidKey,
+    /// An `If-Match` or `If-None-Match` header is invalid
+    InvalidPrecondition,
+    /// The body does not match its `Content-MD5`
+    ChecksumMismatch,
+    /// An upload policy is invalid
+    InvalidPolicy,
+    /// The `Idempotency-Key` header is invalid
+    InvalidIdempotencyKey,
+    /// The storage backend rejected an argument of the request
+    InvalidArgument,
+    /// The request has no credentials
+    Unauthorized,
+    /// The bearer token is invalid, expired or not trusted
+    InvalidToken,
+    /// The username or password is wrong
+    InvalidCredentials,
+    /// The refresh token is unknown, used or expired
+    InvalidRefreshToken,
+    /// The identity provider refused the login, or vouched for no usable account
+    LoginRejected,
+    /// The user has two-factor authentication enabled, and the login has no code
+    TwoFactorRequired,
+    /// The TOTP or recovery code is wrong or was used before
+    InvalidTwoFactorCode,
+    /// The client is not allowed to use the endpoint
+    Forbidden,
+    /// The user is disabled
+    AccountDisabled,
+    /// The role of the user requires two-factor authentication, which it has not set up
+    TwoFactorSetupRequired,
+    /// A request in a cookie session lacks the `X-CSRF-Token` header of the session
+    InvalidCsrfToken,
+    /// The client's role on the bucket or prefix does not allow the request
+    PermissionDenied,
+    /// The key is inside RustDok's hidden system prefix
+    ReservedKey,
+    /// The upload is not allowed by the bucket's upload policy
+    PolicyViolation,
+    /// The object does not exist
+    NotFound,
+    /// The bucket does not exist
+    BucketNotFound,
+    /// The resumable upload does not exist
+    UploadNotFound,
+    /// The drop link does not exist
+    DropLinkNotFound,
+    /// The user does not exist
+    UserNotFound,
+    /// The grant does not exist
+    GrantNotFound,
+    /// The group does not exist
+    GroupNotFound,
+    /// The user or group is not a member of the group
+    MemberNotFound,
+    /// The service account does not exist
+    ServiceAccountNotFound,
+    /// The API key does not exist
+    ApiKeyNotFound,
+    /// The session does not exist or belongs to another user
+    SessionNotFound,
+    /// An object with the key already exists
+    AlreadyExists,
+    /// A bucket with the name already exists
+    BucketAlreadyExists,
+    /// The bucket still contains objects
+    BucketNotEmpty,
+    /// A user with the username already exists
+    UserAlreadyExists,
+    /// A group with the name already exists
+    GroupAlreadyExists,
+    /// A service account with the name already exists
+    ServiceAccountAlreadyExists,
+    /// The user has two-factor authentication enabled already
+    TwoFactorAlreadyEnabled,
+    /// The user has not set up or enabled two-factor authentication
+    TwoFactorNotEnabled,
+    /// Another request for the same resource is still being processed
+    RequestInProgress,
+    /// The `Upload-Offset` does not match the offset of the resumable upload
+    OffsetMismatch,
+    /// The resumable upload has expired
+    UploadExpired,
+    /// The drop link has expired, been revoked or used up
+    DropLinkInactive,
+    /// A precondition of the request did not hold
+    PreconditionFailed,
+    /// The client speaks an unsupported protocol version
+    UnsupportedProtocolVersion,
+    /// The payload exceeds a size limit
+    PayloadTooLarge,
+    /// The payload has an unsupported type
+    UnsupportedMediaType,
+    /// An archive is corrupt or cannot be read
+    InvalidArchive,
+    /// The `Idempotency-Key` was used for a different request
+    IdempotencyKeyReused,
+    /// Too many invalid codes were entered for the user recently
+    TooManyAttempts,
+    /// Some files of a multi-file upload could not be stored
+    UploadFailed,
+    /// The storage backend asked to reduce the request rate
+    SlowDown,
+    /// The storage backend did not answer in time
+    StorageTimeout,
+    /// The storage backend could not be reached
+    StorageUnavailable,
+    /// The storage backend rejected the server's credentials or denied it access
+    StorageAccessDenied,
+    /// The identity provider could not be reached or answered unexpectedly
+    IdentityProviderError,
+    /// The storage backend failed
+    StorageError,
+    /// The user database failed
+    DatabaseError,
+    /// The feature is not configured on this server
+    NotEnabled,
+    /// The server failed
+    InternalError,
+}
+
+impl ErrorCode {
+    /// The code as it appears in error responses.
+    pub fn as_str(&self) -> &'static str {
+        self.describe().0
+    }
+
+    /// The default HTTP status of errors with this code.
+    pub fn status(&self) -> StatusCode {
+        self.describe().1
+    }
+
+    /// The short, human-readable summary of errors with this code.
+    pub fn title(&self) -> &'static str {
+        self.describe().2
+    }
+
+    fn describe(&self) -> (&'static str, StatusCode, &'static str) {
+        match self {
+            ErrorCode::InvalidRequest => ("invalid_request", StatusCode::BAD_REQUEST, "Invalid request"),
+            ErrorCode::InvalidKey => ("invalid_key", StatusCode::BAD_REQUEST, "Invalid key"),
+            ErrorCode::InvalidPrecondition => ("invalid_precondition", StatusCode::BAD_REQUEST, "Invalid precondition"),
+            ErrorCode::ChecksumMismatch => ("checksum_mismatch", StatusCode::BAD_REQUEST, "Checksum mismatch"),
+            ErrorCode::InvalidPolicy => ("invalid_policy", StatusCode::BAD_REQUEST, "Invalid upload policy"),
+            ErrorCode::InvalidIdempotencyKey => ("invalid_idempotency_key", StatusCode::BAD_REQUEST, "Invalid idempotency key"),
+            ErrorCode::InvalidArgument => ("invalid_argument", StatusCode::BAD_REQUEST, "Invalid argument"),
+            ErrorCode::Unauthorized => ("unauthorized", StatusCode::UNAUTHORIZED, "Authentication required"),
+            ErrorCode::InvalidToken => ("invalid_token", StatusCode::UNAUTHORIZED, "Invalid token"),
+            ErrorCode::InvalidCredentials => ("invalid_credentials", StatusCode::UNAUTHORIZED, "Invalid credentials"),
+            ErrorCode::InvalidRefreshToken => ("invalid_refresh_token", StatusCode::UNAUTHORIZED, "Invalid refresh token"),
+            ErrorCode::LoginRejected => ("login_rejected", StatusCode::UNAUTHORIZED, "Login rejected"),
+            ErrorCode::TwoFactorRequired => ("two_factor_required", StatusCode::UNAUTHORIZED, "Two-factor code required"),
+            ErrorCode::InvalidTwoFactorCode => {
+                ("invalid_two_factor_code", StatusCode::UNAUTHORIZED, "Invalid two-factor code")
+            },
+            ErrorCode::Forbidden => ("forbidden", StatusCode::FORBIDDEN, "Forbidden"),
+            ErrorCode::AccountDisabled => ("account_disabled", StatusCode::FORBIDDEN, "Account disabled"),
+            ErrorCode::TwoFactorSetupRequired => {
+                ("two_factor_setup_required", StatusCode::FORBIDDEN, "Two-factor setup required")
+            },
+            ErrorCode::InvalidCsrfToken => ("invalid_csrf_token", StatusCode::FORBIDDEN, "Invalid CSRF token"),
+            ErrorCode::PermissionDenied => ("permission_denied", StatusCode::FORBIDDEN, "Permission denied"),
+            ErrorCode::ReservedKey => ("reserved_key", StatusCode::FORBIDDEN, "Reserved key"),
+            ErrorCode::PolicyViolation => ("policy_violation", StatusCode::FORBIDDEN, "Upload policy violation"),
+            ErrorCode::NotFound => ("not_found", StatusCode::NOT_FOUND, "Not found"),
+            ErrorCode::BucketNotFound => ("bucket_not_found", StatusCode::NOT_FOUND, "Bucket not found"),
+            ErrorCode::UploadNotFound => ("upload_not_found", StatusCode::NOT_FOUND, "Upload not found"),
+            ErrorCode::DropLinkNotFound => ("drop_link_not_found", StatusCode::NOT_FOUND, "Upload link not found"),
+            ErrorCode::UserNotFound => ("user_not_found", StatusCode::NOT_FOUND, "User not found"),
+            ErrorCode::GrantNotFound => ("grant_not_found", StatusCode::NOT_FOUND, "Grant not found"),
+            ErrorCode::GroupNotFound => ("group_not_found", StatusCode::NOT_FOUND, "Group not found"),
+            ErrorCode::MemberNotFound => ("member_not_found", StatusCode::NOT_FOUND, "Member not found"),
+            ErrorCode::ServiceAccountNotFound => {
+                ("service_account_not_found", StatusCode::NOT_FOUND, "Service account not found")
+            },
+            ErrorCode::ApiKeyNotFound => ("api_key_not_found", StatusCode::NOT_FOUND, "API key not found"),
+            ErrorCode::SessionNotFound => ("session_not_found", StatusCode::NOT_FOUND, "Session not found"),
+            ErrorCode::AlreadyExists => ("already_exists", StatusCode::CONFLICT, "Object already exists"),
+            ErrorCode::BucketAlreadyExists => ("bucket_already_exists", StatusCode::CONFLICT, "Bucket already exists"),
+            ErrorCode::BucketNotEmpty => ("bucket_not_empty", StatusCode::CONFLICT, "Bucket not empty"),
+            ErrorCode::UserAlreadyExists => ("user_already_exists", StatusCode::CONFLICT, "User already exists"),
+            ErrorCode::GroupAlreadyExists => ("group_already_exists", StatusCode::CONFLICT, "Group already exists"),
+            ErrorCode::ServiceAccountAlreadyExists => {
+                ("service_account_already_exists", StatusCode::CONFLICT, "Service account already exists")
+            },
+            ErrorCode::TwoFactorAlreadyEnabled => {
+                ("two_factor_already_enabled", StatusCode::CONFLICT, "Two-factor authentication already enabled")
+            },
+            ErrorCode::TwoFactorNotEnabled => {
+                ("two_factor_not_enabled", StatusCode::CONFLICT, "Two-factor authentication not enabled")
+            },
+            ErrorCode::RequestInProgress => ("request_in_progress", StatusCode::CONFLICT, "Request in progress"),
+            ErrorCode::OffsetMismatch => ("offset_mismatch", StatusCode::CONFLICT, "Offset mismatch"),
+            ErrorCode::UploadExpired => ("upload_expired", StatusCode::GONE, "Upload expired"),
+            ErrorCode::DropLinkInactive => ("drop_link_inactive", StatusCode::GONE, "Upload link inactive"),
+            ErrorCode::PreconditionFailed => ("precondition_failed", StatusCode::PRECONDITION_FAILED, "Precondition failed"),
+            ErrorCode::UnsupportedProtocolVersion => {
+                ("unsupported_protocol_version", StatusCode::PRECONDITION_FAILED, "Unsupported protocol version")
+            },
+            ErrorCode::PayloadTooLarge => ("payload_too_large", StatusCode::PAYLOAD_TOO_LARGE, "Payload too large"),
+            ErrorCode::UnsupportedMediaType => {
+                ("unsupported_media_type", StatusCode::UNSUPPORTED_MEDIA_TYPE, "Unsupported media type")
+            },
+            ErrorCode::InvalidArchive => ("invalid_archive", StatusCode::UNPROCESSABLE_ENTITY, "Invalid archive"),
+            ErrorCode::IdempotencyKeyReused => {
+                ("idempotency_key_reused", StatusCode::UNPROCESSABLE_ENTITY, "Idempotency key reused")
+            },
+            ErrorCode::TooManyAttempts => ("too_many_attempts", StatusCode::TOO_MANY_REQUESTS, "Too many attempts"),
+            ErrorCode::UploadFailed => ("upload_failed", StatusCode::INTERNAL_SERVER_ERROR, "Upload failed"),
+            ErrorCode::SlowDown => ("slow_down", StatusCode::SERVICE_UNAVAILABLE, "Storage busy"),
+            ErrorCode::StorageTimeout => ("storage_timeout", StatusCode::GATEWAY_TIMEOUT, "Storage timeout"),
+            ErrorCode::StorageUnavailable => ("storage_unavailable", StatusCode::BAD_GATEWAY, "Storage unavailable"),
+            ErrorCode::StorageAccessDenied => ("storage_access_denied", StatusCode::BAD_GATEWAY, "Storage access denied"),
+            ErrorCode::IdentityProviderError => {
+                ("identity_provider_error", StatusCode::BAD_GATEWAY, "Identity provider error")
+            },
+            ErrorCode::StorageError => ("storage_error", StatusCode::INTERNAL_SERVER_ERROR, "Storage error"),
+            ErrorCode::DatabaseError => ("database_error", StatusCode::INTERNAL_SERVER_ERROR, "Database error"),
+            ErrorCode::NotEnabled => ("not_enabled", StatusCode::NOT_IMPLEMENTED, "Not enabled"),
+            ErrorCode::InternalError => ("internal_error", StatusCode::INTERNAL_SERVER_ERROR, "Internal error"),
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The body of an error response, as defined by RFC 7807.
+#[derive(Debug, Serialize)]
+pub struct ProblemDetails {
+    /// A URI identifying the kind of problem, derived from the code
+    #[serde(rename = "type")]
+    pub problem_type: String,
+    /// The short summary of the code
+    pub title: &'static str,
+    /// The HTTP status
+    pub status: u16,
+    /// What went wrong in this request
+    pub detail: String,
+    /// The machine-readable error code
+    pub code: ErrorCode,
+    /// The path of the request
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instance: Option<String>,
+    /// The ID of the request, also sent in the `X-Request-Id` header
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    /// The parameter, header or body field that caused the error
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+    /// Additional members, e.g. the files uploaded before the error
+    #[serde(flatten)]
+    pub extensions: Map<String, Value>,
+}
+
+/// An error returned by an API endpoint.
+#[derive(Debug, Clone)]
+pub struct ApiError {
+    code: ErrorCode,
+    status: StatusCode,
+    detail: String,
+    field: Option<String>,
+    extensions: Map<String, Value>,
+}
+
+impl ApiError {
+    /// Creates an error with the default status of its code.
+    ///
+    /// # Arguments
+    ///
+    /// * `code` - The machine-readable error code
+    /// * `detail` - What went wrong in this request
+    pub fn new(code: ErrorCode, detail: impl Into<String>) -> Self {
+        Self {
+            code,
+            status: code.status(),
+            detail: detail.into(),
+            field: None,
+            extensions: Map::new(),
+        }
+    }
+
+    /// Names the parameter, header or body field that caused the error.
+    pub fn field(mut self, field: impl Into<String>) -> Self {
+        self.field = Some(field.into());
+        self
+    }
+
+    /// Overrides the HTTP status of the error.
+    pub fn status(mut self, status: StatusCode) -> Self {
+        self.status = status;
+        self
+    }
+
+    /// Adds a member to the error response.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the member
+    /// * `value` - The value of the member
+    pub fn extension(mut self, name: &str, value: impl Serialize) -> Self {
+        self.extensions.insert(name.to_string(), serde_json::to_value(value).unwrap_or(Value::Null));
+        self
+    }
+
+    /// The machine-readable code of the error.
+    pub fn code(&self) -> ErrorCode {
+        self.code
+    }
+
+    /// What went wrong in this request.
+    pub fn detail(&self) -> &str {
+        &self.detail
+    }
+
+    /// Builds the problem document of the error.
+    ///
+    /// The request path and ID are taken from the `RequestId` middleware,
+    /// and left out if the request did not pass through it.
+    pub fn problem(&self) -> ProblemDetails {
+        let context = request_id::current();
+        ProblemDetails {
+            problem_type: format!("{}{}", PROBLEM_TYPE_PREFIX, self.code),
+            title: self.code.title(),
+            status: self.status.as_u16(),
+            detail: self.detail.clone(),
+            code: self.code,
+            instance: context.as_ref().map(|c| c.path.clone()),
+            request_id: context.map(|c| c.id),
+            field: self.field.clone(),
+            extensions: self.extensions.clone(),
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.code, self.detail)
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        self.status
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status);
+        if self.code == ErrorCode::SlowDown {
+            response.insert_header(("Retry-After", RETRY_AFTER_SECONDS.to_string()));
+        }
+        let body = serde_json::to_string(&self.problem()).unwrap_or_default();
+        response.insert_header(ContentType(PROBLEM_CONTENT_TYPE.parse().unwrap())).body(body)
+    }
+}
+
+impl From<S3Error> for ApiError {
+    /// Maps a storage error to its API error.
+    ///
+    /// The messages of server-side failures are not passed on to clients,
+    /// as they may contain internals of the storage backend; they are
+    /// logged by the handlers instead. A denied access means the server's
+    /// own credentials or permissions are wrong, not the client's, so it is
+    /// a server-side failure that is always logged here.
+    fn from(err: S3Error) -> Self {
+        if let S3Error::AccessDenied(message) = &err {
+            error!("The storage backend denied access: {}", message);
+        }
+
+        let code = match &err {
+            S3Error::NotFound(_) => ErrorCode::NotFound,
+            S3Error::BucketNotFound(_) => ErrorCode::BucketNotFound,
+            S3Error::BucketAlreadyExists(_) => ErrorCode::BucketAlreadyExists,
+            S3Error::BucketNotEmpty(_) => ErrorCode::BucketNotEmpty,
+            S3Error::AccessDenied(_) => ErrorCode::StorageAccessDenied,
+            S3Error::PreconditionFailed(_) => ErrorCode::PreconditionFailed,
+            S3Error::InvalidArgument(_) => ErrorCode::InvalidArgument,
+            S3Error::SlowDown(_) => ErrorCode::SlowDown,
+            S3Error::Timeout(_) => ErrorCode::StorageTimeout,
+            S3Error::Unavailable(_) => ErrorCode::StorageUnavailable,
+            S3Error::AwsError(_) | S3Error::Other(_) => ErrorCode::StorageError,
+        };
+        let detail = match err {
+            S3Error::AwsError(_) | S3Error::Other(_) => "The storage backend failed to process the request".to_string(),
+            S3Error::Timeout(_) => "The storage backend did not answer in time".to_string(),
+            S3Error::Unavailable(_) => "The storage backend could not be reached".to_string(),
+            S3Error::AccessDenied(_) => "The storage backend rejected the server's credentials".to_string(),
+            other => other.message().to_string(),
+        };
+        ApiError::new(code, detail)
+    }
+}
+
+impl ResponseError for S3Error {
+    fn status_code(&self) -> StatusCode {
+        ApiError::from(self.clone()).status_code()
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        ApiError::from(self.clone()).error_response()
+    }
+}
+
+impl From<UserError> for ApiError {
+    /// Maps an error of the user subsystem to its API error. Database
+    /// failures are logged by the handlers, not passed on to clients.
+    fn from(err: UserError) -> Self {
+        match err {
+            UserError::InvalidUsername(msg) => ApiError::new(ErrorCode::InvalidRequest, msg).field("username"),
+            UserError::InvalidPassword(msg) => ApiError::new(ErrorCode::InvalidRequest, msg).field("password"),
+            UserError::InvalidCredentials => ApiError::new(ErrorCode::InvalidCredentials, err.to_string()),
+            UserError::AccountDisabled => ApiError::new(ErrorCode::AccountDisabled, err.to_string()),
+            UserError::InvalidRefreshToken => {
+                ApiError::new(ErrorCode::InvalidRefreshToken, err.to_string()).field("refresh_token")
+            },
+            UserError::InvalidSession => ApiError::new(ErrorCode::InvalidToken, err.to_string()),
+            UserError::UnknownIdentity => ApiError::new(ErrorCode::LoginRejected, err.to_string()),
+            UserError::TwoFactorRequired => ApiError::new(ErrorCode::TwoFactorRequired, err.to_string()).field("otp"),
+            UserError::InvalidTwoFactorCode => ApiError::new(ErrorCode::InvalidTwoFactorCode, err.to_string()).field("otp"),
+            UserError::TwoFactorSetupRequired(ref setup_token) => {
+                let error = ApiError::new(ErrorCode::TwoFactorSetupRequired, err.to_string());
+                match setup_token {
+                    Some(setup_token) => error.extension("setup_token", setup_token),
+                    None => error,
+                }
+            },
+            UserError::InvalidSetupToken => ApiError::new(ErrorCode::InvalidToken, err.to_string()).field("setup_token"),
+            UserError::TwoFactorAlreadyEnabled => ApiError::new(ErrorCode::TwoFactorAlreadyEnabled, err.to_string()),
+            UserError::TwoFactorNotEnabled | UserError::TwoFactorNotSetUp => {
+                ApiError::new(ErrorCode::TwoFactorNotEnabled, err.to_string())
+            },
+            UserError::TwoFactorMandatory(_) => ApiError::new(ErrorCode::Forbidden, err.to_string()),
+            UserError::TooManyTwoFactorAttempts | UserError::TooManyRequests => {
+                ApiError::new(ErrorCode::TooManyAttempts, err.to_string())
+            },
+            UserError::NotFound => ApiError::new(ErrorCode::UserNotFound, err.to_string()),
+            UserError::SessionNotFound => ApiError::new(ErrorCode::SessionNotFound, err.to_string()),
+            UserError::UsernameTaken(_) => ApiError::new(ErrorCode::UserAlreadyExists, err.to_string()).field("username"),
+            UserError::Database(_) => ApiError::new(ErrorCode::DatabaseError, "The user database failed to process the request"),
+            UserError::Internal(_) => ApiError::new(ErrorCode::InternalError, "The server failed to process the request"),
+        }
+    }
+}
+
+impl ResponseError for UserError {
+    fn status_code(&self) -> StatusCode {
+        ApiError::from(self.clone()).status_code()
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        ApiError::from(self.clone()).error_response()
+    }
+}
+
+impl From<AccessError> for ApiError {
+    /// Maps an error of access control to its API error. Database failures
+    /// are logged by the handlers, not passed on to clients.
+    fn from(err: AccessError) -> Self {
+        match err {
+            AccessError::InvalidSubject(msg) => ApiError::new(ErrorCode::InvalidRequest, msg).field("subject"),
+            AccessError::InvalidBucket(msg) => ApiError::new(ErrorCode::InvalidRequest, msg).field("bucket"),
+            AccessError::InvalidPrefix(msg) => ApiError::new(ErrorCode::InvalidRequest, msg).field("prefix"),
+            AccessError::InvalidGroupName(msg) => ApiError::new(ErrorCode::InvalidRequest, msg).field("name"),
+            AccessError::InvalidDescription(msg) => ApiError::new(ErrorCode::InvalidRequest, msg).field("description"),
+            AccessError::InvalidMember(msg) => ApiError::new(ErrorCode::InvalidRequest, msg).field("member"),
+            AccessError::NotFound => ApiError::new(ErrorCode::GrantNotFound, err.to_string()),
+            AccessError::GroupNotFound => ApiError::new(ErrorCode::GroupNotFound, err.to_string()),
+            AccessError::MemberNotFound => ApiError::new(ErrorCode::MemberNotFound, err.to_string()),
+            AccessError::GroupTaken(_) => ApiError::new(ErrorCode::GroupAlreadyExists, err.to_string()).field("name"),
+            AccessError::Database(_) => ApiError::new(ErrorCode::DatabaseError, "The grant database failed to process the request"),
+        }
+    }
+}
+
+impl ResponseError for AccessError {
+    fn status_code(&self) -> StatusCode {
+        ApiError::from(self.clone()).status_code()
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        ApiError::from(self.clone()).error_response()
+    }
+}
+
+impl From<ApiKeyError> for ApiError {
+    /// Maps an error of service accounts and API keys to its API error.
+    /// Database failures are logged by the handlers, not passed on to clients.
+    fn from(err: ApiKeyError) -> Self {
+        match err {
+            ApiKeyError::InvalidName(msg) => ApiError::new(ErrorCode::InvalidRequest, msg).field("name"),
+            ApiKeyError::InvalidDescription(msg) => ApiError::new(ErrorCode::InvalidRequest, msg).field("description"),
+            ApiKeyError::InvalidScopes(msg) => ApiError::new(ErrorCode::InvalidRequest, msg).field("scopes"),
+            ApiKeyError::InvalidExpiry(msg) => ApiError::new(ErrorCode::InvalidRequest, msg).field("expires_at"),
+            ApiKeyError::InvalidGracePeriod(msg) => ApiError::new(ErrorCode::InvalidRequest, msg).field("grace_seconds"),
+            ApiKeyError::InvalidKey | ApiKeyError::Expired => ApiError::new(ErrorCode::InvalidToken, err.to_string()),
+            ApiKeyError::AccountNotFound => ApiError::new(ErrorCode::ServiceAccountNotFound, err.to_string()),
+            ApiKeyError::KeyNotFound => ApiError::new(ErrorCode::ApiKeyNotFound, err.to_string()),
+            ApiKeyError::NameTaken(_) => ApiError::new(ErrorCode::ServiceAccountAlreadyExists, err.to_string()).field("name"),
+            ApiKeyError::Database(_) => ApiError::new(ErrorCode::DatabaseError, "The API key database failed to process the request"),
+        }
+    }
+}
+
+impl ResponseError for ApiKeyError {
+    fn status_code(&self) -> StatusCode {
+        ApiError::from(self.clone()).status_code()
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        ApiError::from(self.clone()).error_response()
+    }
+}
+
+impl From<OidcError> for ApiError {
+    /// Maps an error of the OpenID Connect login to its API error. Failures
+    /// of the identity provider are logged by the handlers.
+    fn from(err: OidcError) -> Self {
+        match err {
+            OidcError::InvalidState => ApiError::new(ErrorCode::InvalidRequest, err.to_string()).field("state"),
+            OidcError::Rejected(_) | OidcError::MissingClaim(_) => ApiError::new(ErrorCode::LoginRejected, err.to_string()),
+            OidcError::InvalidIdToken(_) => ApiError::new(ErrorCode::InvalidToken, err.to_string()),
+            OidcError::Discovery(_) | OidcError::TokenExchange(_) | OidcError::Http(_) => {
+                ApiError::new(ErrorCode::IdentityProviderError, "The identity provider failed to complete the login")
+            },
+        }
+    }
+}
+
+impl ResponseError for OidcError {
+    fn status_code(&self) -> StatusCode {
+        ApiError::from(self.clone()).status_code()
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        ApiError::from(self.clone()).error_response()
+    }
+}
+
+impl From<AuditError> for ApiError {
+    /// Maps an error of the audit log to its API error. Database failures
+    /// are logged by the handlers, not passed on to clients.
+    fn from(err: AuditError) -> Self {
+        match err {
+            AuditError::InvalidLimit(msg) => ApiError::new(ErrorCode::InvalidRequest, msg).field("limit"),
+            AuditError::InvalidTimeRange(msg) => ApiError::new(ErrorCode::InvalidRequest, msg).field("until"),
+            AuditError::Database(_) => ApiError::new(ErrorCode::DatabaseError, "The audit database failed to process the request"),
+        }
+    }
+}
+
+impl ResponseError for AuditError {
+    fn status_code(&self) -> StatusCode {
+        ApiError::from(self.clone()).status_code()
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        ApiError::from(self.clone()).error_response()
+    }
+}
+
+/// Finds the field named in a deserialization error, e.g. "missing field `name`".
+fn field_of(message: &str) -> Option<String> {
+    let start = message.find('`')? + 1;
+    let end = start + message[start..].find('`')?;
+    Some(message[start..end].to_string())
+}
+
+/// Builds the error response for request bodies that are not valid JSON
+/// or do not match the expected structure.
+pub fn json_error_handler(err: JsonPayloadError, _req: &HttpRequest) -> actix_web::Error {
+    let error = match &err {
+        JsonPayloadError::OverflowKnownLength { .. } | JsonPayloadError::Overflow { .. } => {
+            ApiError::new(ErrorCode::PayloadTooLarge, err.to_string())
+        },
+        JsonPayloadError::ContentType => ApiError::new(ErrorCode::UnsupportedMediaType, "The body must be JSON"),
+        JsonPayloadError::Deserialize(e) => {
+            let error = ApiError::new(ErrorCode::InvalidRequest, format!("Invalid JSON body: {}", e));
+            match field_of(&e.to_string()) {
+                Some(field) => error.field(field),
+                None => error,
+            }
+        },
+        _ => ApiError::new(ErrorCode::InvalidRequest, err.to_string()),
+    };
+    error.into()
+}
+
+/// Builds the error response for invalid query strings.
+pub fn query_error_handler(err: QueryPayloadError, _req: &HttpRequest) -> actix_web::Error {
+    let message = match &err {
+        QueryPayloadError::Deserialize(e) => e.to_string(),
+        other => other.to_string(),
+    };
+    let error = ApiError::new(ErrorCode::InvalidRequest, format!("Invalid query string: {}", message));
+    match field_of(&message) {
+        Some(field) => error.field(field).into(),
+        None => error.into(),
+    }
+}
+
+/// Builds the error response for invalid path parameters.
+pub fn path_error_handler(err: PathError, _req: &HttpRequest) -> actix_web::Error {
+    ApiError::new(ErrorCode::InvalidRequest, format!("Invalid path: {}", err)).into()
+}
+
+/// Answers requests for API paths that do not exist.
+pub async fn not_found(req: HttpRequest) -> Result<HttpResponse, ApiError> {
+    Err(ApiError::new(ErrorCode::NotFound, format!("No endpoint at {} {}", req.method(), req.path())))
+}
+
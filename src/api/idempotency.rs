@@ -0,0 +1,273 @@
+//! # Idempotency Middleware
+//!
+//! This module provides the middleware behind the `Idempotency-Key` header
+//! of the mutating POST endpoints. A request with a key that was seen before
+//! gets the response of the first request, marked with `Idempotent-Replayed`,
+//! instead of running again. Server errors are not kept, so a failed request
+//! can be retried with the same key.
+//!
+//! Keys belong to the client that sent them, so clients cannot see each
+//! other's responses. A key is bound to the method, URI and a SHA-256 digest
+//! of the body of its first request. The digest is computed while the handler
+//! reads the body, so bodies are never buffered for it.
+
+use std::cell::RefCell;
+use std::future::{ready, Ready};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::Poll;
+
+use actix_web::body::{self, BoxBody, MessageBody};
+use actix_web::dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::error::PayloadError;
+use actix_web::http::StatusCode;
+use actix_web::http::header::HeaderMap;
+use actix_web::{Error, HttpMessage, HttpResponse};
+use bytes::Bytes;
+use chrono::Utc;
+use futures::future::LocalBoxFuture;
+use futures::{Stream, StreamExt};
+use once_cell::sync::Lazy;
+use sha2::{Digest, Sha256};
+
+use crate::api::error::{ApiError, ErrorCode};
+use crate::rdlib::auth::principal::Principal;
+use crate::rdlib::idempotency::{IdempotencyStore, Lookup};
+
+/// The request header carrying the idempotency key
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+
+/// The response header marking replayed responses
+pub const REPLAYED_HEADER: &str = "Idempotent-Replayed";
+
+/// How much of its body a handler may leave unread for its response to be
+/// kept; the rest is read after the handler to complete the digest
+const MAX_UNREAD_BODY: usize = 1024 * 1024;
+
+/// The responses of requests with idempotency keys
+static STORE: Lazy<IdempotencyStore<CachedResponse>> = Lazy::new(IdempotencyStore::from_env);
+
+/// A response kept for replaying.
+#[derive(Clone, Debug)]
+pub struct CachedResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+    /// The digest of the body of the request that led to the response
+    request_digest: String,
+}
+
+impl CachedResponse {
+    fn to_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status);
+        for (name, value) in self.headers.iter() {
+            response.append_header((name.clone(), value.clone()));
+        }
+        response.insert_header((REPLAYED_HEADER, "true")).body(self.body.clone())
+    }
+}
+
+/// The body of a request, hashed as it is read.
+struct BodyDigest {
+    /// The part of the body not read yet, `None` once it has been read to the end
+    payload: Option<Payload>,
+    hasher: Sha256,
+}
+
+impl BodyDigest {
+    /// Takes over the body of a request, leaving a stream in its place that
+    /// passes the body through the digest.
+    fn attach(req: &mut ServiceRequest) -> Rc<RefCell<Self>> {
+        let digest = Rc::new(RefCell::new(Self { payload: Some(req.take_payload()), hasher: Sha256::new() }));
+        req.set_payload(Payload::Stream { payload: Box::pin(Self::stream(digest.clone())) });
+        digest
+    }
+
+    /// Reads the rest of the body through the digest.
+    fn stream(digest: Rc<RefCell<Self>>) -> impl Stream<Item = Result<Bytes, PayloadError>> {
+        futures::stream::poll_fn(move |cx| {
+            let mut digest = digest.borrow_mut();
+            let digest = &mut *digest;
+            let Some(payload) = digest.payload.as_mut() else {
+                return Poll::Ready(None);
+            };
+            match Pin::new(payload).poll_next(cx) {
+                Poll::Ready(Some(Ok(chunk))) => {
+                    digest.hasher.update(&chunk);
+                    Poll::Ready(Some(Ok(chunk)))
+                },
+                Poll::Ready(None) => {
+                    digest.payload = None;
+                    Poll::Ready(None)
+                },
+                poll => poll,
+            }
+        })
+    }
+
+    /// Reads what is left of the body and finishes the digest.
+    ///
+    /// # Arguments
+    ///
+    /// * `digest` - The digest attached to the request
+    /// * `limit` - How many bytes may be left to read
+    ///
+    /// # Returns
+    ///
+    /// The hex-encoded digest, or `None` if the body could not be read within the limit
+    async fn finish(digest: Rc<RefCell<Self>>, limit: usize) -> Option<String> {
+        let mut rest = Box::pin(Self::stream(digest.clone()));
+        let mut read = 0;
+        while let Some(chunk) = rest.next().await {
+            read += chunk.ok()?.len();
+            if read > limit {
+                return None;
+            }
+        }
+
+        let hash = digest.borrow().hasher.clone().finalize();
+        Some(hash.iter().map(|b| format!("{:02x}", b)).collect())
+    }
+}
+
+/// Builds the key a client's idempotency key is stored under, so the keys
+/// of different clients never collide. Anonymous requests, such as uploads
+/// through drop links, are told apart by their path, which holds the token
+/// they are allowed by.
+fn scoped_key(req: &ServiceRequest, key: &str) -> String {
+    match req.extensions().get::<Principal>() {
+        Some(principal) => format!("{}\n{}\n{}", principal.issuer.as_deref().unwrap_or_default(), principal.subject, key),
+        None => format!("\n\n{}\n{}", req.path(), key),
+    }
+}
+
+/// Builds the `422 Unprocessable Entity` response for a key used for another request.
+fn key_reused() -> ApiError {
+    ApiError::new(ErrorCode::IdempotencyKeyReused, "This Idempotency-Key was used for a different request")
+        .field(IDEMPOTENCY_KEY_HEADER)
+}
+
+/// Abandons a key unless its request completes, e.g. if the handler fails
+/// or the client disconnects, so the request can be retried with the key.
+struct PendingKey(Option<String>);
+
+impl PendingKey {
+    fn complete(mut self, response: CachedResponse) {
+        if let Some(key) = self.0.take() {
+            STORE.complete(&key, response);
+        }
+    }
+}
+
+impl Drop for PendingKey {
+    fn drop(&mut self) {
+        if let Some(key) = self.0.take() {
+            STORE.abandon(&key);
+        }
+    }
+}
+
+/// Middleware that replays the responses of requests with a known `Idempotency-Key`.
+///
+/// Keys are matched to requests by client, method, URI and body; reusing a
+/// key for a different request is rejected with `422 Unprocessable Entity`,
+/// and a request whose key is still being processed with `409 Conflict`.
+pub struct Idempotency;
+
+impl<S, B> Transform<S, ServiceRequest> for Idempotency
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Transform = IdempotencyMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(IdempotencyMiddleware { service: Rc::new(service) }))
+    }
+}
+
+/// The service created by the `Idempotency` middleware.
+pub struct IdempotencyMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for IdempotencyMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+
+        Box::pin(async move {
+            let key = match req.headers().get(IDEMPOTENCY_KEY_HEADER) {
+                None => return service.call(req).await.map(ServiceResponse::map_into_boxed_body),
+                Some(value) => value.to_str().ok()
+                    .filter(|key| IdempotencyStore::<CachedResponse>::is_valid_key(key))
+                    .map(str::to_string),
+            };
+            let Some(key) = key else {
+                return Ok(req.error_response(ApiError::new(
+                    ErrorCode::InvalidIdempotencyKey,
+                    "Idempotency-Key must be 1 to 255 visible ASCII characters"
+                ).field(IDEMPOTENCY_KEY_HEADER)));
+            };
+
+            let key = scoped_key(&req, &key);
+            let fingerprint = format!("{} {}", req.method(), req.uri());
+            let digest = BodyDigest::attach(&mut req);
+            match STORE.begin(&key, &fingerprint, Utc::now()) {
+                Lookup::New => {},
+                Lookup::Replay(response) => {
+                    // The body is read in full, as the client sent it anyway
+                    if BodyDigest::finish(digest, usize::MAX).await.as_ref() != Some(&response.request_digest) {
+                        return Ok(req.error_response(key_reused()));
+                    }
+                    return Ok(req.into_response(response.to_response()));
+                },
+                Lookup::InProgress => {
+                    return Ok(req.error_response(ApiError::new(
+                        ErrorCode::RequestInProgress,
+                        "A request with this Idempotency-Key is still being processed"
+                    )));
+                },
+                Lookup::Mismatch => return Ok(req.error_response(key_reused())),
+            }
+
+            let pending = PendingKey(Some(key));
+            let response = service.call(req).await?;
+            if response.status().is_server_error() {
+                return Ok(response.map_into_boxed_body());
+            }
+
+            let (req, response) = response.into_parts();
+            let (response, body) = response.into_parts();
+            let body = body::to_bytes(body).await.map_err(|e| {
+                let e: Box<dyn std::error::Error> = e.into();
+                actix_web::error::ErrorInternalServerError(e.to_string())
+            })?;
+            // Responses to requests whose body was left largely unread are not
+            // kept, as their digest is unknown
+            if let Some(request_digest) = BodyDigest::finish(digest, MAX_UNREAD_BODY).await {
+                pending.complete(CachedResponse {
+                    status: response.status(),
+                    headers: response.headers().clone(),
+                    body: body.clone(),
+                    request_digest,
+                });
+            }
+
+            Ok(ServiceResponse::new(req, response.set_body(body).map_into_boxed_body()))
+        })
+    }
+}
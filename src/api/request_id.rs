@@ -0,0 +1,108 @@
+//! # Request IDs
+//!
+//! This module provides the middleware that gives every request an ID. The
+//! ID is taken from the `X-Request-Id` header if the client sent a usable
+//! one, generated otherwise, and returned in the `X-Request-Id` response
+//! header. Error responses include it, so a report from a client can be
+//! matched with the server logs.
+
+use std::future::{ready, Ready};
+use std::rc::Rc;
+
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::Error;
+use futures::future::LocalBoxFuture;
+use log::debug;
+use uuid::Uuid;
+
+/// The header carrying the request ID
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+/// The maximum length of a request ID sent by a client
+pub const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+/// What error responses need to know about the request being handled.
+#[derive(Clone, Debug)]
+pub struct RequestContext {
+    /// The ID of the request
+    pub id: String,
+    /// The path of the request
+    pub path: String,
+}
+
+tokio::task_local! {
+    static REQUEST_CONTEXT: RequestContext;
+}
+
+/// Returns the context of the request handled by the current task.
+///
+/// # Returns
+///
+/// The context, or `None` outside of the `RequestId` middleware
+pub fn current() -> Option<RequestContext> {
+    REQUEST_CONTEXT.try_with(RequestContext::clone).ok()
+}
+
+/// Checks if a request ID sent by a client can be used: 1 to 128 letters,
+/// digits, `-`, `_` or `.`, so it is safe to log and to echo back.
+pub fn is_valid_request_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LENGTH
+        && id.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+/// Middleware that assigns every request an ID.
+pub struct RequestId;
+
+impl<S, B> Transform<S, ServiceRequest> for RequestId
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = RequestIdMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequestIdMiddleware { service: Rc::new(service) }))
+    }
+}
+
+/// The service created by the `RequestId` middleware.
+pub struct RequestIdMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for RequestIdMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let id = req.headers().get(REQUEST_ID_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .filter(|id| is_valid_request_id(id))
+            .map(str::to_string)
+            .unwrap_or_else(|| Uuid::new_v4().to_string());
+        let context = RequestContext { id: id.clone(), path: req.path().to_string() };
+        debug!("Handling {} {} as request {}", req.method(), req.path(), id);
+
+        Box::pin(REQUEST_CONTEXT.scope(context, async move {
+            let mut response = service.call(req).await?;
+            if let Ok(value) = HeaderValue::from_str(&id) {
+                response.headers_mut().insert(HeaderName::from_static("x-request-id"), value);
+            }
+            Ok(response)
+        }))
+    }
+}
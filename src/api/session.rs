@@ -0,0 +1,281 @@
+//! # Cookie Sessions
+//!
+//! This module lets the web UI authenticate with a session cookie instead
+//! of holding bearer tokens in JavaScript. Logging in through
+//! `/auth/login/session`, or through an OpenID Connect provider with
+//! `/auth/oidc/login`, sets two cookies:
+//!
+//! * `rustdok_session` - The session token; `HttpOnly`, so scripts cannot read it
+//! * `rustdok_csrf` - The CSRF token of the session, which the UI reads and
+//!   sends back in the `X-CSRF-Token` header
+//!
+//! Requests that change something (`POST`, `PUT`, `PATCH` and `DELETE`)
+//! must carry the header, and it must match both the cookie and the token
+//! stored with the session. Another site can make the browser send the
+//! cookies, but cannot read them to set the header.
+//!
+//! A login through an OpenID Connect provider keeps its state in the
+//! `rustdok_oidc_login` cookie until the provider sends the browser back.
+
+use std::env;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+
+use actix_web::cookie::time::Duration as CookieDuration;
+use actix_web::cookie::{Cookie, SameSite};
+use actix_web::dev::ServiceRequest;
+use actix_web::http::Method;
+use actix_web::{web, HttpRequest, HttpResponse, ResponseError};
+use log::{debug, error, warn};
+use once_cell::sync::Lazy;
+
+use crate::api::auth::unauthorized;
+use crate::api::error::{ApiError, ErrorCode};
+use crate::rdlib::auth::principal::Principal;
+use crate::rdlib::oidc::service::LOGIN_TIMEOUT;
+use crate::rdlib::users::error::UserError;
+use crate::rdlib::users::service::UserService;
+use crate::rdlib::users::session::SessionClient;
+
+/// The cookie holding the session token
+pub const SESSION_COOKIE: &str = "rustdok_session";
+
+/// The cookie holding the CSRF token, readable by the web UI
+pub const CSRF_COOKIE: &str = "rustdok_csrf";
+
+/// The cookie holding the OpenID Connect login that has begun
+pub const OIDC_LOGIN_COOKIE: &str = "rustdok_oidc_login";
+
+/// The header the web UI echoes the CSRF token in
+pub const CSRF_HEADER: &str = "X-CSRF-Token";
+
+/// The addresses of the reverse proxies whose `X-Forwarded-For` header
+/// tells the address of the client.
+///
+/// Controlled by the `RUSTDOK_TRUSTED_PROXIES` environment variable, a
+/// comma-separated list of IP addresses (default: none).
+static TRUSTED_PROXIES: Lazy<Vec<IpAddr>> = Lazy::new(|| {
+    env::var("RUSTDOK_TRUSTED_PROXIES").unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|proxy| !proxy.is_empty())
+        .filter_map(|proxy| match proxy.parse::<IpAddr>() {
+            Ok(ip) => Some(ip),
+            Err(_) => {
+                warn!("Ignoring invalid address '{}' in RUSTDOK_TRUSTED_PROXIES", proxy);
+                None
+            }
+        })
+        .collect()
+});
+
+/// The attributes of the session cookies.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CookieSettings {
+    /// Whether the cookies are only sent over HTTPS
+    pub secure: bool,
+    /// When browsers send the cookies with requests from other sites
+    pub same_site: SameSite,
+    /// The domain the cookies are sent to, or `None` for the host of the API only
+    pub domain: Option<String>,
+}
+
+impl Default for CookieSettings {
+    fn default() -> Self {
+        Self { secure: true, same_site: SameSite::Strict, domain: None }
+    }
+}
+
+impl CookieSettings {
+    /// Reads the settings from the environment.
+    ///
+    /// * `RUSTDOK_SESSION_COOKIE_SECURE` - `false` to allow the cookies over plain HTTP, for development (default `true`)
+    /// * `RUSTDOK_SESSION_SAME_SITE` - `strict` (default), `lax` or `none`
+    /// * `RUSTDOK_SESSION_COOKIE_DOMAIN` - The domain of the cookies (optional)
+    ///
+    /// # Returns
+    ///
+    /// The settings, or an error if `RUSTDOK_SESSION_SAME_SITE` is invalid
+    pub fn from_env() -> Result<Self, String> {
+        let secure = !env::var("RUSTDOK_SESSION_COOKIE_SECURE").is_ok_and(|v| v == "false" || v == "0");
+        let same_site = match env::var("RUSTDOK_SESSION_SAME_SITE").map(|v| v.to_lowercase()).as_deref() {
+            Err(_) | Ok("strict") => SameSite::Strict,
+            Ok("lax") => SameSite::Lax,
+            Ok("none") => SameSite::None,
+            Ok(other) => return Err(format!("Invalid RUSTDOK_SESSION_SAME_SITE '{}': expected strict, lax or none", other)),
+        };
+        let domain = env::var("RUSTDOK_SESSION_COOKIE_DOMAIN").ok().filter(|d| !d.trim().is_empty());
+        Ok(Self { secure, same_site, domain })
+    }
+
+    /// Builds the cookie holding the session token. It lasts as long as
+    /// the browser; the server ends the session on its own.
+    pub fn session_cookie(&self, token: &str) -> Cookie<'static> {
+        let mut cookie = self.cookie(SESSION_COOKIE, token.to_string());
+        cookie.set_http_only(true);
+        cookie
+    }
+
+    /// Builds the cookie holding the CSRF token, which scripts can read.
+    pub fn csrf_cookie(&self, csrf_token: &str) -> Cookie<'static> {
+        let mut cookie = self.cookie(CSRF_COOKIE, csrf_token.to_string());
+        cookie.set_http_only(false);
+        cookie
+    }
+
+    /// Builds the cookies that remove the session and CSRF cookies.
+    pub fn removal_cookies(&self) -> [Cookie<'static>; 2] {
+        let mut session = self.session_cookie("");
+        session.make_removal();
+        let mut csrf = self.csrf_cookie("");
+        csrf.make_removal();
+        [session, csrf]
+    }
+
+    /// Builds the cookie holding an OpenID Connect login until the
+    /// callback. It is sent on the redirect back from the provider, which
+    /// comes from another site, and expires with the login.
+    pub fn oidc_login_cookie(&self, login: &str) -> Cookie<'static> {
+        let mut cookie = self.cookie(OIDC_LOGIN_COOKIE, login.to_string());
+        cookie.set_http_only(true);
+        cookie.set_same_site(SameSite::Lax);
+        cookie.set_max_age(CookieDuration::seconds(LOGIN_TIMEOUT.as_secs() as i64));
+        cookie
+    }
+
+    /// Builds the cookie that removes the OpenID Connect login cookie.
+    pub fn oidc_login_removal_cookie(&self) -> Cookie<'static> {
+        let mut cookie = self.oidc_login_cookie("");
+        cookie.make_removal();
+        cookie
+    }
+
+    fn cookie(&self, name: &'static str, value: String) -> Cookie<'static> {
+        let mut cookie = Cookie::new(name, value);
+        cookie.set_path("/");
+        cookie.set_secure(self.secure);
+        cookie.set_same_site(self.same_site);
+        if let Some(domain) = &self.domain {
+            cookie.set_domain(domain.clone());
+        }
+        cookie
+    }
+}
+
+/// Gets the cookie settings of the app, or the defaults if it has none.
+pub fn cookie_settings(settings: Option<web::Data<CookieSettings>>) -> CookieSettings {
+    settings.map(|settings| settings.get_ref().clone()).unwrap_or_default()
+}
+
+/// Checks if requests with the method must carry the CSRF token.
+pub fn requires_csrf(method: &Method) -> bool {
+    [Method::POST, Method::PUT, Method::PATCH, Method::DELETE].contains(method)
+}
+
+/// Checks the CSRF token of a request: the `X-CSRF-Token` header must
+/// match the CSRF cookie and, if given, the token stored with the session.
+///
+/// # Returns
+///
+/// `Ok(())`, or a `403 Forbidden` error if the header is missing or does not match
+pub fn check_csrf(req: &HttpRequest, expected: Option<&str>) -> Result<(), ApiError> {
+    let header = req.headers().get(CSRF_HEADER).and_then(|v| v.to_str().ok()).map(str::trim).unwrap_or("");
+    let cookie = req.cookie(CSRF_COOKIE);
+    let matches = !header.is_empty()
+        && cookie.is_some_and(|cookie| constant_time_eq(header, cookie.value()))
+        && expected.is_none_or(|expected| constant_time_eq(header, expected));
+    if matches {
+        Ok(())
+    } else {
+        Err(ApiError::new(ErrorCode::InvalidCsrfToken, "The X-CSRF-Token header does not match the session").field(CSRF_HEADER))
+    }
+}
+
+/// Gets the IP address of the client of a request: the peer address, or
+/// the address told by the proxies of `RUSTDOK_TRUSTED_PROXIES`.
+pub fn client_ip(req: &HttpRequest) -> Option<String> {
+    client_ip_behind(req, &TRUSTED_PROXIES)
+}
+
+/// Gets the IP address of the client of a request behind reverse proxies.
+///
+/// The `X-Forwarded-For` header is only believed as far as it was written by
+/// trusted proxies: starting from the peer, each trusted proxy names the
+/// address it received the request from, which is the last entry it added.
+/// Entries added by the client itself are never reached.
+///
+/// # Arguments
+///
+/// * `req` - The request
+/// * `proxies` - The addresses of the trusted proxies
+///
+/// # Returns
+///
+/// The IP address, or `None` if the peer address is not known
+pub fn client_ip_behind(req: &HttpRequest, proxies: &[IpAddr]) -> Option<String> {
+    let mut ip = req.peer_addr()?.ip();
+    let hops = req.headers().get_all("X-Forwarded-For")
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .collect::<Vec<_>>();
+    for hop in hops.into_iter().rev() {
+        if !proxies.contains(&ip) {
+            break;
+        }
+        let Some(hop) = hop.parse::<IpAddr>().ok().or_else(|| hop.parse::<SocketAddr>().ok().map(|addr| addr.ip())) else {
+            break;
+        };
+        ip = hop;
+    }
+    Some(ip.to_string())
+}
+
+/// Gets the browser a session is started in.
+pub fn session_client(req: &HttpRequest) -> SessionClient {
+    SessionClient {
+        user_agent: req.headers().get("User-Agent").and_then(|v| v.to_str().ok()).map(str::to_string),
+        ip_address: client_ip(req),
+    }
+}
+
+/// Authenticates a request by its session cookie, checking the CSRF token
+/// for requests that change something.
+///
+/// # Returns
+///
+/// `None` if the request has no session cookie, or else the principal of
+/// the session or the response rejecting the request
+pub async fn authenticate_session(req: &ServiceRequest) -> Option<Result<Principal, HttpResponse>> {
+    let token = req.cookie(SESSION_COOKIE).map(|cookie| cookie.value().to_string()).filter(|token| !token.is_empty())?;
+    let Some(users) = req.app_data::<web::Data<Arc<UserService>>>().cloned() else {
+        return Some(Err(unauthorized(Some(&"Sessions are not enabled on this server"))));
+    };
+
+    let (session, principal) = match users.authenticate_session(&token).await {
+        Ok(authenticated) => authenticated,
+        Err(e @ UserError::InvalidSession) => {
+            debug!("Rejected session for {} {}: {}", req.method(), req.path(), e);
+            return Some(Err(unauthorized(Some(&e))));
+        },
+        Err(e) => {
+            if e.status_code().is_server_error() {
+                error!("Error checking a session: {:?}", e);
+            }
+            return Some(Err(e.error_response()));
+        }
+    };
+
+    if requires_csrf(req.method())
+        && let Err(e) = check_csrf(req.request(), Some(&session.csrf_token))
+    {
+        debug!("Rejected CSRF token for {} {}", req.method(), req.path());
+        return Some(Err(e.error_response()));
+    }
+    Some(Ok(principal))
+}
+
+/// Compares two strings in time independent of where they differ.
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len() && a.bytes().zip(b.bytes()).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
@@ -4,4 +4,18 @@
 //! It defines the routes and handlers for bucket and object operations.
 
 pub mod buckets;
-pub mod objects;
\ No newline at end of file
+pub mod objects;
+pub mod drops;
+pub mod policies;
+pub mod previews;
+pub mod archives;
+pub mod uploads;
+pub mod auth;
+pub mod users;
+pub mod grants;
+pub mod groups;
+pub mod service_accounts;
+pub mod oidc;
+pub mod two_factor;
+pub mod sessions;
+pub mod audit;
\ No newline at end of file
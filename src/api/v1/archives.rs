@@ -0,0 +1,812 @@
+//! # Archive API Endpoints
+//!
+//! This module provides the API endpoints for downloading several objects,
+//! or whole folders, as a single ZIP archive. Archives are built on the fly
+//! while the objects are streamed from S3, so neither the objects nor the
+//! archive are held in memory.
+//!
+//! It also provides the extraction of uploaded ZIP and tar(.gz) archives,
+//! used by the upload endpoint in extract mode, and the inspection of
+//! archives stored in a bucket without downloading them.
+
+use actix_web::{get, post, web, HttpResponse, Error, ResponseError};
+use actix_web::http::StatusCode;
+use actix_multipart::Multipart;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use aws_sdk_s3::primitives::ByteStream;
+use futures::channel::mpsc::{self, Sender};
+use futures::channel::oneshot;
+use futures::{SinkExt, TryStreamExt};
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::HashSet;
+use std::io::Read;
+use std::path::Path;
+use crate::rdlib::archive::zip_stream::ZipStreamWriter;
+use crate::rdlib::archive::extract::{self, ArchiveFormat, ExtractedItem, ExtractionError, ExtractionLimits};
+use crate::rdlib::archive::inspect::{
+    self, ChannelReader, DirectoryLocation, ZipEntry,
+    EOCD_SEARCH_LENGTH, LOCAL_HEADER_LENGTH, MAX_CENTRAL_DIRECTORY_SIZE, MAX_ENTRY_SIZE, ZIP64_EOCD_LENGTH,
+};
+use crate::rdlib::s3::types::UploadPolicy;
+use crate::rdlib::s3::error::S3Error;
+use crate::rdlib::s3::service::S3Service;
+use crate::api::access::require;
+use crate::api::error::{ApiError, ErrorCode};
+use crate::rdlib::access::authorizer::Access;
+use crate::rdlib::access::grant::Permission;
+use crate::models::s3::ArchiveRequest;
+use crate::api::v1::objects::{
+    field_filename, object_key, read_field_data, store_with_conflict_policy, system_key_forbidden, ConflictPolicy
+};
+use log::{error, info};
+use std::sync::Arc;
+
+/// The maximum number of objects in a single archive
+pub const MAX_ARCHIVE_ENTRIES: usize = 100_000;
+
+/// The number of archive chunks buffered ahead of a slow client
+const ARCHIVE_CHANNEL_CAPACITY: usize = 16;
+
+/// The size of the chunks an archive entry is sent to the client in
+const ENTRY_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Query parameters for inspecting archives
+#[derive(Deserialize)]
+pub struct ArchiveContentsQuery {
+    /// The path of an entry to download instead of listing the archive
+    entry: Option<String>,
+}
+
+/// An object to be written into an archive.
+struct ArchiveEntry {
+    /// The key of the object
+    key: String,
+    /// The path of the entry inside the archive
+    name: String,
+    /// The last modified timestamp of the object in RFC3339 format
+    last_modified: Option<String>,
+}
+
+/// Normalizes the archive root to either an empty string or a prefix ending with a slash.
+pub(crate) fn normalize_root(root: &str) -> String {
+    let root = root.trim_matches('/');
+    if root.is_empty() {
+        String::new()
+    } else {
+        format!("{}/", root)
+    }
+}
+
+/// Builds the path of a key inside the archive, relative to the root.
+///
+/// Empty, `.` and `..` segments are dropped, so entries never start with a
+/// slash or point outside the directory the archive is extracted into.
+///
+/// # Returns
+///
+/// The path, or `None` if the key is not inside the root or has no name left
+pub(crate) fn entry_name(key: &str, root: &str) -> Option<String> {
+    let relative = key.strip_prefix(root)?;
+    let segments = relative
+        .split('/')
+        .filter(|s| !s.is_empty() && *s != "." && *s != "..")
+        .collect::<Vec<_>>();
+    if segments.is_empty() {
+        return None;
+    }
+
+    let mut name = segments.join("/");
+    if relative.ends_with('/') {
+        name.push('/');
+    }
+    Some(name)
+}
+
+/// Builds the file name of the archive.
+fn archive_filename(name: Option<&str>, root: &str, bucket: &str) -> String {
+    let base = name
+        .map(|n| n.trim_end_matches(".zip").to_string())
+        .or_else(|| root.trim_end_matches('/').rsplit('/').next().map(|s| s.to_string()))
+        .filter(|n| !n.is_empty())
+        .unwrap_or_else(|| bucket.to_string());
+
+    format!("{}.zip", sanitize_filename::sanitize(base))
+}
+
+/// Sends a chunk of the archive to the client.
+///
+/// # Returns
+///
+/// `false` if the client has gone away
+async fn send_chunk(tx: &mut Sender<Result<Bytes, Error>>, chunk: Vec<u8>) -> bool {
+    chunk.is_empty() || tx.send(Ok(Bytes::from(chunk))).await.is_ok()
+}
+
+/// Writes the archive of the given entries into the channel.
+async fn stream_archive(
+    s3: &S3Service,
+    bucket: &str,
+    entries: Vec<ArchiveEntry>,
+    tx: &mut Sender<Result<Bytes, Error>>
+) -> Result<(), S3Error> {
+    let mut zip = ZipStreamWriter::new();
+
+    for entry in entries {
+        let modified = entry.last_modified
+            .as_deref()
+            .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+            .map_or_else(Utc::now, |t| t.with_timezone(&Utc));
+
+        if !send_chunk(tx, zip.start_entry(&entry.name, modified)).await {
+            return Ok(());
+        }
+
+        // Folder placeholders become directory entries without data
+        if !entry.name.ends_with('/') {
+            let mut body = s3.get_object_stream(&entry.key, bucket).await?;
+            while let Some(chunk) = body.try_next().await? {
+                if !send_chunk(tx, zip.write_data(&chunk)).await {
+                    return Ok(());
+                }
+            }
+        }
+
+        if !send_chunk(tx, zip.finish_entry()).await {
+            return Ok(());
+        }
+    }
+
+    send_chunk(tx, zip.finish()).await;
+    Ok(())
+}
+
+/// Downloads objects and folders as a ZIP archive.
+///
+/// Prefixes are expanded to all objects below them, including nested
+/// folders. Paths inside the archive are relative to `root`, so selecting
+/// `docs/reports/` with root `docs/` yields entries under `reports/`. The
+/// archive uses ZIP64, so it may exceed 4 GiB and hold any number of entries.
+///
+/// The selection is validated before the download starts. Errors that occur
+/// while the archive is streamed can only abort the download.
+///
+/// # Path Parameters
+///
+/// * `bucket` - The name of the bucket containing the objects
+///
+/// # Request Body
+///
+/// * `keys` - Optional list of object keys to include
+/// * `prefixes` - Optional list of folders to include
+/// * `root` - Optional folder that paths inside the archive are relative to
+/// * `name` - Optional file name of the archive
+///
+/// # Returns
+///
+/// * `200 OK` - The ZIP archive, streamed
+/// * `400 Bad Request` - If nothing is selected, a key or folder lies outside the root, or the selection is too large
+/// * `403 Forbidden` - If a key or folder points into the system prefix, or the client is not a viewer of it
+/// * `404 Not Found` - If a key or folder does not exist
+/// * `500 Internal Server Error` - If there was an error reading the selection
+#[post("/bucket/{bucket}/archive")]
+pub async fn create_archive(
+    bucket: web::Path<String>,
+    request: web::Json<ArchiveRequest>,
+    access: Access,
+    s3_service: web::Data<Arc<S3Service>>
+) -> Result<HttpResponse, Error> {
+    let bucket = bucket.into_inner();
+    let s3 = s3_service.as_ref();
+    let root = normalize_root(&request.root);
+
+    for path in request.keys.iter().chain(&request.prefixes) {
+        if S3Service::is_system_key(path) {
+            return Ok(system_key_forbidden(path));
+        }
+    }
+    for key in &request.keys {
+        require(&access, &bucket, key.trim_start_matches('/'), Permission::Read)?;
+    }
+    for prefix in &request.prefixes {
+        require(&access, &bucket, &normalize_root(prefix), Permission::Read)?;
+    }
+    if request.keys.is_empty() && request.prefixes.is_empty() {
+        return Err(ApiError::new(ErrorCode::InvalidRequest, "No keys or prefixes selected").field("keys").into());
+    }
+
+    let mut seen = HashSet::new();
+    let mut entries = Vec::new();
+
+    for key in &request.keys {
+        let key = key.trim_start_matches('/');
+        let Some(name) = entry_name(key, &root) else {
+            return Err(ApiError::new(ErrorCode::InvalidKey, format!("Key '{}' is not inside root '{}'", key, root))
+                .field("keys")
+                .into());
+        };
+
+        match s3.head_object(key, &bucket).await {
+            Ok(Some(head)) => {
+                if seen.insert(key.to_string()) {
+                    entries.push(ArchiveEntry { key: key.to_string(), name, last_modified: head.last_modified });
+                }
+            },
+            Ok(None) => {
+                return Err(ApiError::new(ErrorCode::NotFound, format!("Object {} does not exist in bucket {}", key, bucket))
+                    .field("keys")
+                    .into());
+            },
+            Err(e) => {
+                error!("Error reading {}/{} for archive: {:?}", bucket, key, e);
+                return Err(e.into());
+            }
+        }
+    }
+
+    for prefix in &request.prefixes {
+        let prefix = normalize_root(prefix);
+        if !prefix.starts_with(&root) {
+            return Err(ApiError::new(ErrorCode::InvalidKey, format!("Prefix '{}' is not inside root '{}'", prefix, root))
+                .field("prefixes")
+                .into());
+        }
+
+        // One object more than the remaining room is enough to reject the selection
+        let remaining = MAX_ARCHIVE_ENTRIES.saturating_sub(entries.len()) + 1;
+        let objects = match s3.list_all_objects(&prefix, &bucket, remaining).await {
+            Ok(objects) => objects,
+            Err(e) => {
+                error!("Error listing {}/{} for archive: {:?}", bucket, prefix, e);
+                return Err(e.into());
+            }
+        };
+        if objects.is_empty() {
+            return Err(ApiError::new(ErrorCode::NotFound, format!("Folder {} does not exist in bucket {}", prefix, bucket))
+                .field("prefixes")
+                .into());
+        }
+
+        for obj in objects {
+            if let Some(name) = entry_name(&obj.name, &root) && seen.insert(obj.name.clone()) {
+                entries.push(ArchiveEntry { key: obj.name, name, last_modified: obj.last_modified });
+            }
+        }
+        if entries.len() > MAX_ARCHIVE_ENTRIES {
+            return Err(ApiError::new(
+                ErrorCode::InvalidRequest,
+                format!("Archives are limited to {} objects", MAX_ARCHIVE_ENTRIES)
+            ).into());
+        }
+    }
+
+    if entries.len() > MAX_ARCHIVE_ENTRIES {
+        return Err(ApiError::new(
+            ErrorCode::InvalidRequest,
+            format!("Archives are limited to {} objects, {} selected", MAX_ARCHIVE_ENTRIES, entries.len())
+        ).into());
+    }
+
+    let filename = archive_filename(request.name.as_deref(), &root, &bucket);
+    info!("Streaming archive {} with {} entries from bucket {}", filename, entries.len(), bucket);
+
+    let (mut tx, rx) = mpsc::channel(ARCHIVE_CHANNEL_CAPACITY);
+    let s3 = s3_service.get_ref().clone();
+    actix_web::rt::spawn(async move {
+        if let Err(e) = stream_archive(&s3, &bucket, entries, &mut tx).await {
+            error!("Error streaming archive from bucket {}: {:?}", bucket, e);
+            let _ = tx.send(Err(actix_web::error::ErrorInternalServerError(e.to_string()))).await;
+        }
+    });
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/zip")
+        .append_header(("Content-Disposition", format!("attachment; filename=\"{}\"", filename)))
+        .streaming(rx))
+}
+
+/// Stores one extracted item in the bucket.
+///
+/// Files are stored according to the conflict policy, so an entry whose key
+/// is taken is skipped, renamed or replaced; `ConflictPolicy::Fail` is
+/// treated like `ConflictPolicy::Skip`.
+///
+/// # Returns
+///
+/// The result of the item for the response
+async fn store_extracted_item(
+    s3: &S3Service,
+    bucket: &str,
+    prefix: &str,
+    conflict: ConflictPolicy,
+    policy: &UploadPolicy,
+    item: ExtractedItem
+) -> serde_json::Value {
+    let (path, data) = match item {
+        ExtractedItem::Skipped { path, reason } => {
+            return json!({ "path": path, "status": "skipped", "reason": reason });
+        },
+        ExtractedItem::Directory { path } => (path, None),
+        ExtractedItem::File { path, data } => (path, Some(data)),
+    };
+
+    let key = object_key(prefix, &path);
+    if S3Service::is_system_key(&key) {
+        return json!({ "path": path, "status": "rejected", "reason": "system paths are not allowed" });
+    }
+    let check = match &data {
+        Some(data) => policy.check_key(&key)
+            .and_then(|_| policy.check_file(&path, None))
+            .and_then(|_| policy.check_content(&path, data)),
+        None => policy.check_key(&key),
+    };
+    if let Err(violation) = check {
+        return json!({ "path": path, "key": key, "status": "rejected", "reason": violation.to_string() });
+    }
+
+    let Some(data) = data else {
+        return match s3.put_object(&key, vec![], bucket).await {
+            Ok(_) => json!({ "path": path, "key": key, "status": "created" }),
+            Err(e) => {
+                error!("Error creating folder {}/{} from archive: {:?}", bucket, key, e);
+                json!({ "path": path, "key": key, "status": "failed", "reason": e.to_string() })
+            }
+        };
+    };
+
+    let size = data.len();
+    match store_with_conflict_policy(s3, bucket, &key, data, conflict).await {
+        Ok(Some(stored_key)) if stored_key != key => {
+            json!({ "path": path, "key": stored_key, "requested_key": key, "status": "renamed", "size": size })
+        },
+        Ok(Some(_)) => json!({ "path": path, "key": key, "status": "uploaded", "size": size }),
+        Ok(None) => json!({ "path": path, "key": key, "status": "skipped", "reason": "file already exists" }),
+        Err(e) => {
+            error!("Error uploading {}/{} from archive: {:?}", bucket, key, e);
+            json!({ "path": path, "key": key, "status": "failed", "reason": e.to_string() })
+        }
+    }
+}
+
+/// Extracts an archive into a bucket.
+///
+/// The archive is decompressed on a blocking thread, which hands the
+/// entries over one at a time, so at most one entry waits in memory while
+/// the previous one is being stored.
+///
+/// # Returns
+///
+/// The results of the extracted entries, and whether the archive was extracted completely
+async fn extract_into_bucket(
+    s3: &S3Service,
+    bucket: &str,
+    prefix: &str,
+    conflict: ConflictPolicy,
+    policy: &UploadPolicy,
+    archive: (Vec<u8>, ArchiveFormat),
+    limits: ExtractionLimits
+) -> (Vec<serde_json::Value>, Result<(), ExtractionError>) {
+    let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+    let (data, format) = archive;
+    let extraction = web::block(move || {
+        extract::extract(&data, format, &limits, |item| tx.blocking_send(item).is_ok())
+    });
+
+    let mut results = Vec::new();
+    while let Some(item) = rx.recv().await {
+        results.push(store_extracted_item(s3, bucket, prefix, conflict, policy, item).await);
+    }
+
+    let outcome = extraction.await.unwrap_or_else(|e| {
+        Err(ExtractionError::Invalid(format!("extraction was interrupted: {}", e)))
+    });
+    (results, outcome)
+}
+
+/// Uploads archives and extracts them into a folder.
+///
+/// This is the extract mode of the upload endpoint. Every file in the
+/// multipart payload must be a ZIP, tar or tar.gz archive. Each entry is
+/// stored under `prefix` with its sanitized path; the bucket's upload policy
+/// applies to every entry, and entries whose key is taken are handled by
+/// `conflict`. Entries that cannot be stored are reported and skipped, while
+/// an archive that is malformed or exceeds the extraction limits aborts the
+/// upload.
+///
+/// # Returns
+///
+/// * `200 OK` - The per-entry results of every archive
+/// * `400 Bad Request` - If an archive is malformed
+/// * `413 Payload Too Large` - If an archive exceeds the extraction limits
+/// * `415 Unsupported Media Type` - If a file is not a supported archive
+pub(crate) async fn upload_archives(
+    s3: &S3Service,
+    bucket: &str,
+    prefix: &str,
+    conflict: ConflictPolicy,
+    policy: &UploadPolicy,
+    mut payload: Multipart
+) -> Result<HttpResponse, Error> {
+    let mut archives = Vec::new();
+
+    while let Ok(Some(mut field)) = payload.try_next().await {
+        let filename = field_filename(&field);
+        let limits = ExtractionLimits {
+            max_entry_size: policy.max_object_size,
+            ..Default::default()
+        };
+
+        let data = match read_field_data(&mut field, Some(limits.max_archive_size)).await? {
+            Some(data) => data,
+            None => {
+                return Err(ApiError::new(
+                    ErrorCode::PayloadTooLarge,
+                    format!("Archive '{}' exceeds the maximum size of {} bytes", filename, limits.max_archive_size)
+                ).extension("archives", &archives).into());
+            }
+        };
+        let Some(format) = extract::detect_format(&data) else {
+            return Err(ApiError::new(
+                ErrorCode::UnsupportedMediaType,
+                format!("File '{}' is not a ZIP, tar or tar.gz archive", filename)
+            ).extension("archives", &archives).into());
+        };
+
+        info!("Extracting {:?} archive {} into {}/{}", format, filename, bucket, prefix);
+        let (entries, outcome) = extract_into_bucket(s3, bucket, prefix, conflict, policy, (data, format), limits).await;
+
+        if let Err(e) = outcome {
+            error!("Extraction of archive {} aborted: {}", filename, e);
+            let detail = format!("Extraction of '{}' aborted: {}", filename, e);
+            let error = match e {
+                ExtractionError::LimitExceeded(_) => ApiError::new(ErrorCode::PayloadTooLarge, detail),
+                ExtractionError::Invalid(_) => ApiError::new(ErrorCode::InvalidArchive, detail).status(StatusCode::BAD_REQUEST),
+            };
+            archives.push(json!({ "filename": filename, "entries": entries, "code": error.code(), "error": e.to_string() }));
+            return Err(error.extension("archives", &archives).into());
+        }
+        archives.push(json!({ "filename": filename, "entries": entries }));
+    }
+
+    Ok(HttpResponse::Ok().json(json!({
+        "archives": archives
+    })))
+}
+
+/// Errors that occur while inspecting an archive stored in a bucket.
+#[derive(Debug)]
+enum InspectError {
+    /// The archive could not be read from S3
+    S3(S3Error),
+    /// The archive is malformed or exceeds the inspection limits
+    Archive(ExtractionError),
+}
+
+impl From<S3Error> for InspectError {
+    fn from(e: S3Error) -> Self {
+        InspectError::S3(e)
+    }
+}
+
+impl From<ExtractionError> for InspectError {
+    fn from(e: ExtractionError) -> Self {
+        InspectError::Archive(e)
+    }
+}
+
+/// The data of an archive entry, as it is streamed to the client.
+type EntryBody = mpsc::Receiver<std::io::Result<Bytes>>;
+
+/// The result of inspecting an archive.
+enum ArchiveContents {
+    /// The entries of the archive
+    Listing(Vec<inspect::ArchiveEntry>),
+    /// The data of the requested entry, or `None` if the archive has no such file
+    Entry(Option<EntryBody>),
+}
+
+/// Builds the response for archives that could not be inspected.
+fn inspect_error_response(bucket: &str, key: &str, e: InspectError) -> HttpResponse {
+    match e {
+        InspectError::S3(e) => {
+            error!("Error reading archive {}/{}: {:?}", bucket, key, e);
+            e.error_response()
+        },
+        InspectError::Archive(e) => {
+            let code = match e {
+                ExtractionError::LimitExceeded(_) => ErrorCode::PayloadTooLarge,
+                ExtractionError::Invalid(_) => ErrorCode::InvalidArchive,
+            };
+            ApiError::new(code, e.to_string()).error_response()
+        },
+    }
+}
+
+/// Reads the central directory of a ZIP archive with ranged reads.
+async fn read_zip_directory(s3: &S3Service, bucket: &str, key: &str, size: u64) -> Result<Vec<ZipEntry>, InspectError> {
+    let tail_length = size.min(EOCD_SEARCH_LENGTH);
+    let tail_offset = size - tail_length;
+    let tail = s3.get_object_range(key, bucket, tail_offset, tail_length).await?;
+
+    let (offset, length) = match inspect::find_central_directory(&tail, tail_offset)? {
+        DirectoryLocation::Found { offset, size } => (offset, size),
+        DirectoryLocation::Zip64Record(record_offset) => {
+            let record = s3.get_object_range(key, bucket, record_offset, ZIP64_EOCD_LENGTH).await?;
+            inspect::parse_zip64_record(&record)?
+        },
+    };
+
+    if length > MAX_CENTRAL_DIRECTORY_SIZE {
+        return Err(ExtractionError::LimitExceeded(format!(
+            "the central directory exceeds {} bytes", MAX_CENTRAL_DIRECTORY_SIZE
+        )).into());
+    }
+    if offset.saturating_add(length) > size {
+        return Err(ExtractionError::Invalid("the central directory lies outside the archive".to_string()).into());
+    }
+
+    let directory = s3.get_object_range(key, bucket, offset, length).await?;
+    Ok(inspect::parse_central_directory(&directory)?)
+}
+
+/// Forwards an object streamed from S3 to a blocking reader.
+///
+/// Once the reader is dropped, the remaining data is no longer fetched.
+fn channel_reader(mut body: ByteStream) -> ChannelReader {
+    let (tx, rx) = tokio::sync::mpsc::channel(ARCHIVE_CHANNEL_CAPACITY);
+    actix_web::rt::spawn(async move {
+        loop {
+            let chunk = match body.try_next().await {
+                Ok(Some(chunk)) => Ok(chunk),
+                Ok(None) => break,
+                Err(e) => Err(std::io::Error::other(e)),
+            };
+            let failed = chunk.is_err();
+            if tx.send(chunk).await.is_err() || failed {
+                break;
+            }
+        }
+    });
+    ChannelReader::new(rx)
+}
+
+/// Sends the data of an archive entry to the client from a blocking thread.
+///
+/// An error while reading the entry is sent to the client as well, which
+/// aborts the download.
+fn send_entry(entry: &mut dyn Read, tx: &mut Sender<std::io::Result<Bytes>>) -> std::io::Result<()> {
+    let mut buffer = vec![0; ENTRY_CHUNK_SIZE];
+    loop {
+        let length = match entry.read(&mut buffer) {
+            Ok(0) => return Ok(()),
+            Ok(length) => length,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => {
+                let _ = futures::executor::block_on(tx.send(Err(std::io::Error::new(e.kind(), e.to_string()))));
+                return Err(e);
+            }
+        };
+        if futures::executor::block_on(tx.send(Ok(Bytes::copy_from_slice(&buffer[..length])))).is_err() {
+            // The client has gone away
+            return Ok(());
+        }
+    }
+}
+
+/// Streams a single entry of an archive that is read on a blocking thread.
+///
+/// `read` looks for the entry and passes it to the function it is called
+/// with, which sends it to the client. The download starts as soon as the
+/// entry is found, so the entry is never held in memory as a whole; errors
+/// that occur after that, such as a checksum mismatch at the end of the
+/// entry, can only abort the download.
+///
+/// # Returns
+///
+/// The data of the entry, or `None` if `read` did not find it
+async fn stream_entry<F>(bucket: &str, key: &str, read: F) -> Result<Option<EntryBody>, InspectError>
+where
+    F: FnOnce(&mut dyn FnMut(&mut dyn Read) -> std::io::Result<()>) -> Result<bool, ExtractionError> + Send + 'static,
+{
+    let (found_tx, found_rx) = oneshot::channel();
+    let (mut tx, rx) = mpsc::channel(ARCHIVE_CHANNEL_CAPACITY);
+    let reading = web::block(move || {
+        let mut found_tx = Some(found_tx);
+        read(&mut |entry| {
+            if let Some(found_tx) = found_tx.take() {
+                let _ = found_tx.send(());
+            }
+            send_entry(entry, &mut tx)
+        })
+    });
+
+    if found_rx.await.is_ok() {
+        let (bucket, key) = (bucket.to_string(), key.to_string());
+        actix_web::rt::spawn(async move {
+            if let Ok(Err(e)) = reading.await {
+                error!("Error streaming entry of archive {}/{}: {}", bucket, key, e);
+            }
+        });
+        return Ok(Some(rx));
+    }
+
+    // The entry was not found, or the archive could not be read up to it
+    reading.await.map_err(|e| ExtractionError::Invalid(format!("reading was interrupted: {}", e)))??;
+    Ok(None)
+}
+
+/// Streams a single file from a ZIP archive with ranged reads.
+///
+/// The local header and data of the entry are checked to lie within the
+/// archive before they are read.
+///
+/// # Returns
+///
+/// The data of the entry, or `None` if the archive has no file with this name
+async fn stream_zip_entry(
+    s3: &S3Service,
+    bucket: &str,
+    key: &str,
+    size: u64,
+    entries: Vec<ZipEntry>,
+    name: &str
+) -> Result<Option<EntryBody>, InspectError> {
+    let Some(entry) = entries.into_iter().find(|entry| entry.name == name && !entry.is_dir()) else {
+        return Ok(None);
+    };
+    if entry.compressed_size > MAX_ENTRY_SIZE || entry.size > MAX_ENTRY_SIZE {
+        return Err(ExtractionError::LimitExceeded(format!("the entry exceeds {} bytes", MAX_ENTRY_SIZE)).into());
+    }
+    let outside = || ExtractionError::Invalid("the entry lies outside the archive".to_string());
+
+    if entry.header_offset.checked_add(LOCAL_HEADER_LENGTH).filter(|end| *end <= size).is_none() {
+        return Err(outside().into());
+    }
+    let header = s3.get_object_range(key, bucket, entry.header_offset, LOCAL_HEADER_LENGTH).await?;
+    let data_offset = inspect::entry_data_offset(&entry, &header)?;
+    if data_offset.checked_add(entry.compressed_size).filter(|end| *end <= size).is_none() {
+        return Err(outside().into());
+    }
+
+    let data = channel_reader(s3.get_object_range_stream(key, bucket, data_offset, entry.compressed_size).await?);
+    stream_entry(bucket, key, move |send| {
+        let mut reader = inspect::entry_reader(&entry, data)?;
+        send(&mut reader).map_err(|e| ExtractionError::Invalid(e.to_string()))?;
+        Ok(true)
+    }).await
+}
+
+/// Reads a tar archive from a stream on a blocking thread.
+///
+/// The archive is forwarded chunk by chunk, so it is never held in memory
+/// as a whole. Once `read` returns, the remaining data is no longer fetched.
+async fn read_tar<T, F>(s3: &S3Service, bucket: &str, key: &str, read: F) -> Result<T, InspectError>
+where
+    T: Send + 'static,
+    F: FnOnce(ChannelReader) -> Result<T, ExtractionError> + Send + 'static,
+{
+    let reader = channel_reader(s3.get_object_stream(key, bucket).await?);
+    let result = web::block(move || read(reader))
+        .await
+        .map_err(|e| ExtractionError::Invalid(format!("reading was interrupted: {}", e)))??;
+    Ok(result)
+}
+
+/// Streams a single file from a tar archive.
+///
+/// The archive is read sequentially up to the entry, which is then streamed
+/// to the client while the rest of it is read.
+///
+/// # Returns
+///
+/// The data of the entry, or `None` if the archive has no file with this name
+async fn stream_tar_entry(
+    s3: &S3Service,
+    bucket: &str,
+    key: &str,
+    format: ArchiveFormat,
+    name: String
+) -> Result<Option<EntryBody>, InspectError> {
+    let reader = channel_reader(s3.get_object_stream(key, bucket).await?);
+    stream_entry(bucket, key, move |send| inspect::read_tar_entry(reader, format, &name, send)).await
+}
+
+/// Lists the contents of an archive, or downloads a single entry from it.
+///
+/// ZIP archives are inspected with ranged reads of their central directory,
+/// so only a small part of the archive is downloaded from S3. Tar archives
+/// have no index; they are streamed from S3 and read sequentially.
+///
+/// # Path Parameters
+///
+/// * `bucket` - The name of the bucket containing the archive
+/// * `key` - The key (path) of the ZIP, tar or tar.gz archive
+///
+/// # Query Parameters
+///
+/// * `entry` - Optional path of an entry to download instead of listing the archive
+///
+/// # Returns
+///
+/// * `200 OK` - The entries of the archive, or the data of the requested entry
+/// * `403 Forbidden` - If the key points into the system prefix, or the client is not a viewer of the archive
+/// * `404 Not Found` - If the archive or the entry does not exist
+/// * `413 Payload Too Large` - If the archive or the entry exceeds the inspection limits
+/// * `415 Unsupported Media Type` - If the object is not a ZIP, tar or tar.gz archive
+/// * `422 Unprocessable Entity` - If the archive is malformed
+/// * `500 Internal Server Error` - If there was an error reading the archive
+#[get("/bucket/{bucket}/archive-contents/{key:.*}")]
+pub async fn get_archive_contents(
+    path: web::Path<(String, String)>,
+    query: web::Query<ArchiveContentsQuery>,
+    access: Access,
+    s3_service: web::Data<Arc<S3Service>>
+) -> Result<HttpResponse, Error> {
+    let (bucket, key) = path.into_inner();
+    let s3 = s3_service.as_ref();
+
+    if S3Service::is_system_key(&key) {
+        return Ok(system_key_forbidden(&key));
+    }
+    require(&access, &bucket, &key, Permission::Read)?;
+
+    let size = match s3.head_object(&key, &bucket).await {
+        Ok(Some(head)) => head.size,
+        Ok(None) => {
+            return Err(ApiError::new(ErrorCode::NotFound, format!("Object {} does not exist in bucket {}", key, bucket)).into());
+        },
+        Err(e) => return Ok(inspect_error_response(&bucket, &key, e.into())),
+    };
+
+    // The tar magic is at offset 257, so the first block is enough to detect every format
+    let format = match s3.get_object_range(&key, &bucket, 0, size.min(512)).await {
+        Ok(head) => extract::detect_format(&head),
+        Err(e) => return Ok(inspect_error_response(&bucket, &key, e.into())),
+    };
+    let Some(format) = format else {
+        return Err(ApiError::new(
+            ErrorCode::UnsupportedMediaType,
+            format!("Object {} is not a ZIP, tar or tar.gz archive", key)
+        ).into());
+    };
+
+    let result = match (format, query.entry.clone()) {
+        (ArchiveFormat::Zip, None) => read_zip_directory(s3, &bucket, &key, size).await
+            .map(|entries| ArchiveContents::Listing(entries.iter().map(ZipEntry::to_archive_entry).collect())),
+        (ArchiveFormat::Zip, Some(name)) => match read_zip_directory(s3, &bucket, &key, size).await {
+            Ok(entries) => stream_zip_entry(s3, &bucket, &key, size, entries, &name).await.map(ArchiveContents::Entry),
+            Err(e) => Err(e),
+        },
+        (format, None) => read_tar(s3, &bucket, &key, move |reader| inspect::list_tar(reader, format)).await
+            .map(ArchiveContents::Listing),
+        (format, Some(name)) => stream_tar_entry(s3, &bucket, &key, format, name).await.map(ArchiveContents::Entry),
+    };
+
+    match result {
+        Ok(ArchiveContents::Listing(entries)) => Ok(HttpResponse::Ok().json(json!({
+            "bucket": bucket,
+            "key": key,
+            "format": match format {
+                ArchiveFormat::Zip => "zip",
+                ArchiveFormat::Tar => "tar",
+                ArchiveFormat::TarGz => "tar.gz",
+            },
+            "entries": entries
+        }))),
+        Ok(ArchiveContents::Entry(Some(body))) => {
+            let name = query.entry.as_deref().unwrap_or_default();
+            let filename = sanitize_filename::sanitize(Path::new(name).file_name().unwrap_or_default().to_string_lossy());
+            Ok(HttpResponse::Ok()
+                .content_type("application/octet-stream")
+                .append_header(("X-Content-Type-Options", "nosniff"))
+                .append_header(("Content-Disposition", format!("attachment; filename=\"{}\"", filename)))
+                .streaming(body))
+        },
+        Ok(ArchiveContents::Entry(None)) => Err(ApiError::new(
+            ErrorCode::NotFound,
+            format!("Archive {} has no file {}", key, query.entry.as_deref().unwrap_or_default())
+        ).field("entry").into()),
+        Err(e) => Ok(inspect_error_response(&bucket, &key, e)),
+    }
+}
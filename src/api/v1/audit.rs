@@ -0,0 +1,216 @@
+//! # Audit Log API Endpoints
+//!
+//! This module provides the API endpoints for querying and exporting the
+//! audit log. They are reserved for administrators and answer
+//! `501 Not Implemented` if the audit log is not enabled.
+
+use std::sync::Arc;
+
+use actix_web::{get, web, Error, HttpResponse, ResponseError};
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use futures::stream;
+use log::error;
+use serde::Deserialize;
+
+use crate::api::auth::require_admin;
+use crate::api::error::{ApiError, ErrorCode};
+use crate::rdlib::audit::error::AuditError;
+use crate::rdlib::audit::record::{AuditFilter, AuditOutcome, AuditRecord, CSV_HEADER, MAX_PAGE_SIZE};
+use crate::rdlib::audit::service::AuditService;
+use crate::rdlib::auth::principal::Principal;
+
+/// Query parameters for listing and exporting audit records
+#[derive(Deserialize)]
+pub struct AuditQuery {
+    /// Only records of this actor
+    actor: Option<String>,
+    /// Only records of this action, such as `object.delete`
+    action: Option<String>,
+    /// Only records for this bucket
+    bucket: Option<String>,
+    /// Only records for this object key
+    key: Option<String>,
+    /// Only records for object keys starting with this prefix
+    prefix: Option<String>,
+    /// Only records with this outcome
+    outcome: Option<AuditOutcome>,
+    /// Only records from this IP address
+    ip: Option<String>,
+    /// Only records at or after this time
+    since: Option<DateTime<Utc>>,
+    /// Only records before this time
+    until: Option<DateTime<Utc>>,
+    /// Only records with a lower ID, the `next_before` of the previous page
+    before: Option<u64>,
+    /// The most records to return
+    limit: Option<u32>,
+}
+
+impl AuditQuery {
+    fn filter(&self) -> AuditFilter {
+        AuditFilter {
+            actor: self.actor.clone(),
+            action: self.action.clone(),
+            bucket: self.bucket.clone(),
+            key: self.key.clone(),
+            prefix: self.prefix.clone(),
+            outcome: self.outcome,
+            ip_address: self.ip.clone(),
+            since: self.since,
+            until: self.until,
+            before: self.before,
+        }
+    }
+}
+
+/// The formats the audit log can be exported in.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    /// One JSON record per line
+    #[default]
+    Ndjson,
+    /// Comma-separated values with a header line
+    Csv,
+}
+
+/// Query parameters for exporting audit records, besides their filters
+#[derive(Deserialize)]
+pub struct ExportQuery {
+    /// The format of the export
+    #[serde(default)]
+    format: ExportFormat,
+}
+
+/// Gets the audit service, if the audit log is enabled.
+///
+/// # Returns
+///
+/// The service, or a `501 Not Implemented` error if the audit log is not enabled
+pub fn audit_service(audit: Option<web::Data<Arc<AuditService>>>) -> Result<web::Data<Arc<AuditService>>, ApiError> {
+    audit.ok_or_else(|| ApiError::new(ErrorCode::NotEnabled, "The audit log is not enabled on this server"))
+}
+
+/// Converts an error of the audit log, logging server-side failures.
+pub fn audit_error(context: &str, e: AuditError) -> Error {
+    if e.status_code().is_server_error() {
+        error!("Error {}: {:?}", context, e);
+    }
+    e.into()
+}
+
+/// Lists audit records, newest first.
+///
+/// # Query Parameters
+///
+/// * `actor`, `action`, `bucket`, `key`, `outcome`, `ip` - Optional values the records must have
+/// * `prefix` - Optional prefix of the object keys of the records
+/// * `since`, `until` - Optional RFC 3339 times the records must be at or after, and before
+/// * `before` - Optional ID the records must be below, the `next_before` of the previous page
+/// * `limit` - The most records to return, 100 unless set and at most 1000
+///
+/// # Returns
+///
+/// * `200 OK` - The `records` and the `next_before` of the next page, `null` on the last page
+/// * `400 Bad Request` - If the limit or time range is not acceptable
+/// * `403 Forbidden` - If the client is not an administrator
+#[get("/admin/audit")]
+pub async fn list_audit_records(
+    principal: Principal,
+    query: web::Query<AuditQuery>,
+    audit: Option<web::Data<Arc<AuditService>>>
+) -> Result<HttpResponse, Error> {
+    require_admin(&principal)?;
+    let audit = audit_service(audit)?;
+
+    match audit.list(&query.filter(), query.limit).await {
+        Ok(page) => Ok(HttpResponse::Ok().json(page)),
+        Err(e) => Err(audit_error("listing audit records", e)),
+    }
+}
+
+/// Exports all audit records matching the filters of `list_audit_records`,
+/// newest first. The export is streamed page by page, so it can be large.
+///
+/// # Query Parameters
+///
+/// * `format` - `ndjson` (default) or `csv`
+/// * The filters of `GET /admin/audit`; `limit` is ignored
+///
+/// # Returns
+///
+/// * `200 OK` - The records as attachment
+/// * `400 Bad Request` - If the format or time range is not acceptable
+/// * `403 Forbidden` - If the client is not an administrator
+#[get("/admin/audit/export")]
+pub async fn export_audit_records(
+    principal: Principal,
+    query: web::Query<AuditQuery>,
+    export: web::Query<ExportQuery>,
+    audit: Option<web::Data<Arc<AuditService>>>
+) -> Result<HttpResponse, Error> {
+    require_admin(&principal)?;
+    let audit = audit_service(audit)?.into_inner();
+    let format = export.format;
+
+    // The first page is read before answering, so invalid filters fail with a problem document
+    let filter = query.filter();
+    let first = audit.list(&filter, Some(MAX_PAGE_SIZE)).await.map_err(|e| audit_error("exporting audit records", e))?;
+
+    let mut header = Some(Bytes::from_static(CSV_HEADER.as_bytes())).filter(|_| format == ExportFormat::Csv);
+    let pages = stream::unfold(Some((Ok(first), filter)), move |state| {
+        let audit = audit.clone();
+        async move {
+            let (page, mut filter) = state?;
+            match page {
+                Ok(page) => {
+                    let chunk = format_records(&page.records, format);
+                    let next = page.next_before.map(|before| {
+                        filter.before = Some(before);
+                        filter
+                    });
+                    let next = match next {
+                        Some(filter) => Some((audit.list(&filter, Some(MAX_PAGE_SIZE)).await, filter)),
+                        None => None,
+                    };
+                    Some((Ok(chunk), next))
+                },
+                Err(e) => {
+                    error!("Error exporting audit records: {:?}", e);
+                    Some((Err(actix_web::error::ErrorInternalServerError("The audit log export failed")), None))
+                }
+            }
+        }
+    });
+    let body = futures::StreamExt::map(pages, move |chunk: Result<Bytes, Error>| {
+        chunk.map(|chunk| match header.take() {
+            Some(header) => [header, chunk].concat().into(),
+            None => chunk,
+        })
+    });
+
+    let (content_type, extension) = match format {
+        ExportFormat::Ndjson => ("application/x-ndjson", "ndjson"),
+        ExportFormat::Csv => ("text/csv; charset=utf-8", "csv"),
+    };
+    Ok(HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header(("Content-Disposition", format!("attachment; filename=\"audit-log.{}\"", extension)))
+        .streaming(body))
+}
+
+/// Formats audit records as lines of an export.
+fn format_records(records: &[AuditRecord], format: ExportFormat) -> Bytes {
+    let mut out = String::new();
+    for record in records {
+        match format {
+            ExportFormat::Ndjson => {
+                out.push_str(&serde_json::to_string(record).unwrap_or_default());
+                out.push('\n');
+            },
+            ExportFormat::Csv => out.push_str(&record.to_csv_line()),
+        }
+    }
+    Bytes::from(out)
+}
@@ -0,0 +1,108 @@
+//! # Authentication API Endpoints
+//!
+//! This module provides the API endpoints for logging in with a password,
+//! refreshing and revoking the issued tokens, and showing the
+//! authenticated client. Logins need a configured user database.
+
+use std::sync::Arc;
+
+use actix_web::{get, post, web, Error, HttpResponse};
+use log::info;
+
+use crate::api::v1::users::{user_error, user_service};
+use crate::models::users::{LoginRequest, RefreshTokenRequest};
+use crate::rdlib::auth::principal::Principal;
+use crate::rdlib::users::service::UserService;
+
+/// Logs in with a username and password.
+///
+/// # Request Body
+///
+/// * `username` - The name of the user
+/// * `password` - The password of the user
+/// * `otp` - The code from the authenticator app, or a recovery code, if the user has two-factor authentication enabled
+///
+/// # Returns
+///
+/// * `200 OK` - An access token for the `Authorization` header and a refresh token
+/// * `401 Unauthorized` - If the username, password or two-factor code is wrong or the code is missing
+/// * `403 Forbidden` - If the user is disabled, or its role requires two-factor authentication it has not set up; the
+///   problem document then carries a `setup_token` for `/auth/2fa/setup`
+/// * `429 Too Many Requests` - If too many invalid two-factor codes were entered for the user
+/// * `501 Not Implemented` - If no user database is configured
+#[post("/auth/login")]
+pub async fn login(
+    request: web::Json<LoginRequest>,
+    users: Option<web::Data<Arc<UserService>>>
+) -> Result<HttpResponse, Error> {
+    let users = user_service(users)?;
+
+    match users.login(&request.username, &request.password, request.otp.as_deref()).await {
+        Ok(tokens) => {
+            info!("User '{}' logged in", request.username);
+            Ok(HttpResponse::Ok().insert_header(("Cache-Control", "no-store")).json(tokens))
+        },
+        Err(e) => Err(user_error("logging in", e)),
+    }
+}
+
+/// Exchanges a refresh token for a new access token and refresh token.
+/// Each refresh token can be used once.
+///
+/// # Request Body
+///
+/// * `refresh_token` - The refresh token returned at login or by the last refresh
+///
+/// # Returns
+///
+/// * `200 OK` - The new tokens
+/// * `401 Unauthorized` - If the refresh token is invalid, used or expired
+/// * `403 Forbidden` - If the user has been disabled, or its role now requires two-factor authentication
+/// * `501 Not Implemented` - If no user database is configured
+#[post("/auth/refresh")]
+pub async fn refresh(
+    request: web::Json<RefreshTokenRequest>,
+    users: Option<web::Data<Arc<UserService>>>
+) -> Result<HttpResponse, Error> {
+    let users = user_service(users)?;
+
+    match users.refresh(&request.refresh_token).await {
+        Ok(tokens) => Ok(HttpResponse::Ok().insert_header(("Cache-Control", "no-store")).json(tokens)),
+        Err(e) => Err(user_error("refreshing tokens", e)),
+    }
+}
+
+/// Revokes a refresh token. The access tokens issued with it stay valid
+/// until they expire.
+///
+/// # Request Body
+///
+/// * `refresh_token` - The refresh token to revoke
+///
+/// # Returns
+///
+/// * `204 No Content` - The token is revoked, or was not valid
+/// * `501 Not Implemented` - If no user database is configured
+#[post("/auth/logout")]
+pub async fn logout(
+    request: web::Json<RefreshTokenRequest>,
+    users: Option<web::Data<Arc<UserService>>>
+) -> Result<HttpResponse, Error> {
+    let users = user_service(users)?;
+
+    match users.logout(&request.refresh_token).await {
+        Ok(()) => Ok(HttpResponse::NoContent().finish()),
+        Err(e) => Err(user_error("logging out", e)),
+    }
+}
+
+/// Returns the authenticated client of the request.
+///
+/// # Returns
+///
+/// * `200 OK` - The subject, issuer and claims of the bearer token
+/// * `401 Unauthorized` - If the request has no valid bearer token
+#[get("/auth/me")]
+pub async fn get_current_principal(principal: Principal) -> HttpResponse {
+    HttpResponse::Ok().json(principal)
+}
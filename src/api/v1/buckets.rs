@@ -2,8 +2,15 @@
 //! 
 //! This module provides the API endpoints for bucket operations.
 //! It includes handlers for listing, creating, and deleting buckets.
+//! Clients only see the buckets they have a role in; creating buckets is
+//! reserved for administrators, and deleting them needs the `admin` role
+//! on the bucket.
 
-use actix_web::{post, get, delete, web, HttpResponse, Error};
+use actix_web::{post, get, delete, web, HttpResponse, Error, ResponseError};
+use crate::api::access::require;
+use crate::api::error::{ApiError, ErrorCode};
+use crate::rdlib::access::authorizer::Access;
+use crate::rdlib::access::grant::Permission;
 use crate::rdlib::s3::service::S3Service;
 use serde_json::json;
 use crate::models::s3::CreateBucketRequest;
@@ -12,23 +19,22 @@ use log::error;
 
 /// Lists all buckets.
 ///
-/// This endpoint retrieves a list of all buckets available in the storage.
+/// This endpoint retrieves a list of the buckets available in the storage
+/// that the client has a role in.
 ///
 /// # Returns
 ///
 /// * `200 OK` - A JSON array of bucket names
 /// * `500 Internal Server Error` - If there was an error listing the buckets
 #[get("/buckets")]
-pub async fn list_buckets(s3_service: web::Data<Arc<S3Service>>) -> Result<HttpResponse, Error> {
+pub async fn list_buckets(access: Access, s3_service: web::Data<Arc<S3Service>>) -> Result<HttpResponse, Error> {
     let s3 = s3_service.as_ref();
     
     match s3.list_buckets().await {
-        Ok(buckets) => Ok(HttpResponse::Ok().json(buckets)),
+        Ok(buckets) => Ok(HttpResponse::Ok().json(access.filter_buckets(buckets))),
         Err(e) => {
             error!("Error listing buckets: {:?}", e);
-            Ok(HttpResponse::InternalServerError().json(json!({
-                "error": format!("Failed to list buckets: {}", e)
-            })))
+            Err(e.into())
         }
     }
 }
@@ -45,20 +51,24 @@ pub async fn list_buckets(s3_service: web::Data<Arc<S3Service>>) -> Result<HttpR
 ///
 /// * `201 Created` - If the bucket was created successfully
 /// * `400 Bad Request` - If the bucket name is invalid
+/// * `403 Forbidden` - If the client is not an administrator
 /// * `409 Conflict` - If the bucket already exists
 /// * `500 Internal Server Error` - If there was an error creating the bucket
-#[post("/buckets")]
+#[post("/buckets", wrap = "crate::api::idempotency::Idempotency")]
 pub async fn create_bucket(
     bucket_info: web::Json<CreateBucketRequest>,
+    access: Access,
     s3_service: web::Data<Arc<S3Service>>
 ) -> Result<HttpResponse, Error> {
     let s3 = s3_service.as_ref();
     
+    if !access.is_unrestricted() {
+        return Err(ApiError::new(ErrorCode::PermissionDenied, "Only administrators can create buckets").into());
+    }
+    
     let bucket_name = &bucket_info.name;
     if bucket_name.is_empty() {
-        return Ok(HttpResponse::BadRequest().json(json!({
-            "error": "Bucket name cannot be empty"
-        })));
+        return Err(ApiError::new(ErrorCode::InvalidRequest, "Bucket name cannot be empty").field("name").into());
     }
     
     // Create the bucket
@@ -67,16 +77,10 @@ pub async fn create_bucket(
             "message": format!("Bucket '{}' created successfully", bucket_name)
         }))),
         Err(e) => {
-            if e.contains("BucketAlreadyExists") || e.contains("already exists") {
-                Ok(HttpResponse::Conflict().json(json!({
-                    "error": format!("Bucket '{}' already exists", bucket_name)
-                })))
-            } else {
+            if e.status_code().is_server_error() {
                 error!("Error creating bucket: {:?}", e);
-                Ok(HttpResponse::InternalServerError().json(json!({
-                    "error": format!("Failed to create bucket: {}", e)
-                })))
             }
+            Err(e.into())
         }
     }
 }
@@ -92,35 +96,29 @@ pub async fn create_bucket(
 /// # Returns
 ///
 /// * `200 OK` - If the bucket was deleted successfully
+/// * `403 Forbidden` - If the client is not an admin of the bucket
 /// * `404 Not Found` - If the bucket does not exist
 /// * `409 Conflict` - If the bucket is not empty
 /// * `500 Internal Server Error` - If there was an error deleting the bucket
 #[delete("/bucket/{name}")]
 pub async fn delete_bucket(
     bucket_name: web::Path<String>,
+    access: Access,
     s3_service: web::Data<Arc<S3Service>>
 ) -> Result<HttpResponse, Error> {
     let s3 = s3_service.as_ref();
     
+    require(&access, &bucket_name, "", Permission::Manage)?;
+    
     match s3.delete_bucket(&bucket_name).await {
         Ok(_) => Ok(HttpResponse::Ok().json(json!({
             "message": format!("Bucket '{}' deleted successfully", bucket_name)
         }))),
         Err(e) => {
-            if e.contains("NoSuchBucket") || e.contains("not found") {
-                Ok(HttpResponse::NotFound().json(json!({
-                    "error": format!("Bucket '{}' not found", bucket_name)
-                })))
-            } else if e.contains("BucketNotEmpty") || e.contains("not empty") {
-                Ok(HttpResponse::Conflict().json(json!({
-                    "error": format!("Bucket '{}' is not empty", bucket_name)
-                })))
-            } else {
+            if e.status_code().is_server_error() {
                 error!("Error deleting bucket: {:?}", e);
-                Ok(HttpResponse::InternalServerError().json(json!({
-                    "error": format!("Failed to delete bucket: {}", e)
-                })))
             }
+            Err(e.into())
         }
     }
 }
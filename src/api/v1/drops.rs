@@ -0,0 +1,353 @@
+//! # Drop Link API Endpoints
+//!
+//! This module provides the API endpoints for anonymous upload ("file drop") links.
+//! It includes handlers for managing drop links of a bucket, and the public
+//! endpoints a link holder uses to upload files into the link's folder.
+//! Managing the links of a folder needs the `admin` role on it.
+
+use actix_web::{post, get, delete, web, HttpResponse, Error, ResponseError};
+use actix_multipart::Multipart;
+use futures::TryStreamExt;
+use serde_json::json;
+use chrono::{TimeDelta, Utc};
+use crate::api::access::require;
+use crate::api::error::{ApiError, ErrorCode};
+use crate::rdlib::access::authorizer::Access;
+use crate::rdlib::access::grant::{normalize_prefix, Permission};
+use crate::rdlib::s3::drop_link::MAX_DROP_LINK_LIFETIME_SECONDS;
+use crate::rdlib::s3::service::S3Service;
+use crate::rdlib::s3::types::DropLink;
+use crate::models::s3::CreateDropLinkRequest;
+use crate::api::v1::objects::{field_filename, object_key, read_field_data, policy_violation_response};
+use log::{error, info};
+use std::sync::Arc;
+
+/// Builds the public view of a drop link.
+///
+/// The public view tells the uploader which limits apply, but does not
+/// reveal the bucket or folder the files end up in.
+fn public_drop_link(link: &DropLink) -> serde_json::Value {
+    json!({
+        "expires_at": link.expires_at,
+        "max_file_size": link.max_file_size,
+        "allowed_extensions": link.allowed_extensions,
+        "remaining_uploads": link.remaining_uploads()
+    })
+}
+
+/// Looks up a drop link, mapping a missing link to a `404 Not Found` response.
+async fn find_drop_link(s3: &S3Service, bucket: &str, token: &str) -> Result<DropLink, HttpResponse> {
+    match s3.get_drop_link(bucket, token).await {
+        Ok(Some(link)) => Ok(link),
+        Ok(None) => Err(ApiError::new(ErrorCode::DropLinkNotFound, "Upload link not found").error_response()),
+        Err(e) => {
+            error!("Error reading a drop link of bucket {}: {:?}", bucket, e);
+            Err(e.error_response())
+        }
+    }
+}
+
+/// Creates a drop link for a folder in a bucket.
+///
+/// # Path Parameters
+///
+/// * `bucket` - The name of the bucket uploads are stored in
+///
+/// # Request Body
+///
+/// * `prefix` - The folder uploads are stored under
+/// * `expires_in` - Optional number of seconds after which the link expires, at most a year
+/// * `max_file_size` - Optional maximum size of a single file in bytes
+/// * `allowed_extensions` - Optional list of accepted file extensions
+/// * `max_uploads` - Optional maximum number of files the link accepts
+///
+/// # Returns
+///
+/// * `201 Created` - The created drop link, including its token
+/// * `400 Bad Request` - If the prefix or limits are invalid
+/// * `403 Forbidden` - If the client is not an admin of the folder
+/// * `500 Internal Server Error` - If there was an error storing the link
+#[post("/bucket/{bucket}/drops", wrap = "crate::api::idempotency::Idempotency")]
+pub async fn create_drop_link(
+    bucket: web::Path<String>,
+    request: web::Json<CreateDropLinkRequest>,
+    access: Access,
+    s3_service: web::Data<Arc<S3Service>>
+) -> Result<HttpResponse, Error> {
+    let s3 = s3_service.as_ref();
+
+    let prefix = match normalize_prefix(&request.prefix) {
+        Ok(prefix) if !S3Service::is_system_key(&prefix) => prefix,
+        Ok(_) => return Err(ApiError::new(ErrorCode::InvalidKey, format!("Invalid prefix '{}'", request.prefix)).field("prefix").into()),
+        Err(reason) => return Err(ApiError::new(ErrorCode::InvalidKey, reason).field("prefix").into()),
+    };
+    if request.max_uploads == Some(0) || request.max_file_size == Some(0) {
+        let field = if request.max_uploads == Some(0) { "max_uploads" } else { "max_file_size" };
+        return Err(ApiError::new(ErrorCode::InvalidRequest, "max_uploads and max_file_size must be greater than zero")
+            .field(field)
+            .into());
+    }
+
+    let expires_at = match request.expires_in {
+        None => None,
+        Some(secs) => {
+            let expires_at = Some(secs)
+                .filter(|secs| (1..=MAX_DROP_LINK_LIFETIME_SECONDS).contains(secs))
+                .and_then(|secs| TimeDelta::try_seconds(secs as i64))
+                .and_then(|lifetime| Utc::now().checked_add_signed(lifetime));
+            match expires_at {
+                Some(expires_at) => Some(expires_at),
+                None => return Err(ApiError::new(
+                    ErrorCode::InvalidRequest,
+                    format!("expires_in must be between 1 and {} seconds", MAX_DROP_LINK_LIFETIME_SECONDS)
+                ).field("expires_in").into()),
+            }
+        },
+    };
+
+    let mut link = DropLink::new(&bucket, &prefix);
+    require(&access, &bucket, &object_key(&link.prefix, ""), Permission::Manage)?;
+    link.expires_at = expires_at;
+    link.max_file_size = request.max_file_size;
+    link.allowed_extensions = request.allowed_extensions.as_deref().map(DropLink::normalize_extensions);
+    link.max_uploads = request.max_uploads;
+
+    match s3.save_drop_link(&link).await {
+        Ok(_) => {
+            info!("Created drop link for {}/{}", bucket, link.prefix);
+            Ok(HttpResponse::Created().json(link))
+        },
+        Err(e) => {
+            error!("Error creating drop link in bucket {}: {:?}", bucket, e);
+            Err(e.into())
+        }
+    }
+}
+
+/// Lists the drop links of a bucket that the client may manage.
+///
+/// # Path Parameters
+///
+/// * `bucket` - The name of the bucket
+///
+/// # Returns
+///
+/// * `200 OK` - A JSON array of drop links
+/// * `500 Internal Server Error` - If there was an error listing the links
+#[get("/bucket/{bucket}/drops")]
+pub async fn list_drop_links(
+    bucket: web::Path<String>,
+    access: Access,
+    s3_service: web::Data<Arc<S3Service>>
+) -> Result<HttpResponse, Error> {
+    let s3 = s3_service.as_ref();
+
+    match s3.list_drop_links(&bucket).await {
+        Ok(links) => Ok(HttpResponse::Ok().json(links.into_iter()
+            .filter(|link| access.allows(&bucket, &object_key(&link.prefix, ""), Permission::Manage))
+            .collect::<Vec<_>>())),
+        Err(e) => {
+            error!("Error listing drop links of bucket {}: {:?}", bucket, e);
+            Err(e.into())
+        }
+    }
+}
+
+/// Revokes a drop link.
+///
+/// # Path Parameters
+///
+/// * `bucket` - The name of the bucket
+/// * `token` - The token of the drop link
+///
+/// # Returns
+///
+/// * `200 OK` - If the link was revoked
+/// * `403 Forbidden` - If the client is not an admin of the link's folder
+/// * `404 Not Found` - If the link does not exist
+/// * `500 Internal Server Error` - If there was an error revoking the link
+#[delete("/bucket/{bucket}/drops/{token}")]
+pub async fn delete_drop_link(
+    path: web::Path<(String, String)>,
+    access: Access,
+    s3_service: web::Data<Arc<S3Service>>
+) -> Result<HttpResponse, Error> {
+    let (bucket, token) = path.into_inner();
+    let s3 = s3_service.as_ref();
+
+    let link = match find_drop_link(s3, &bucket, &token).await {
+        Ok(link) => link,
+        Err(response) => return Ok(response),
+    };
+    require(&access, &bucket, &object_key(&link.prefix, ""), Permission::Manage)?;
+
+    match s3.delete_drop_link(&bucket, &token).await {
+        Ok(_) => {
+            info!("Revoked drop link for {}/{}", bucket, link.prefix);
+            Ok(HttpResponse::Ok().json(json!({
+                "message": "Upload link revoked successfully",
+                "token": token,
+                "bucket": bucket
+            })))
+        },
+        Err(e) => {
+            error!("Error revoking drop link for {}/{}: {:?}", bucket, link.prefix, e);
+            Err(e.into())
+        }
+    }
+}
+
+/// Shows the limits of a drop link to its holder.
+///
+/// This endpoint is meant to be called anonymously by the link holder.
+///
+/// # Path Parameters
+///
+/// * `bucket` - The name of the bucket
+/// * `token` - The token of the drop link
+///
+/// # Returns
+///
+/// * `200 OK` - The limits of the link
+/// * `404 Not Found` - If the link does not exist
+/// * `410 Gone` - If the link has expired or reached its upload limit
+#[get("/drop/{bucket}/{token}")]
+pub async fn get_drop_link_info(
+    path: web::Path<(String, String)>,
+    s3_service: web::Data<Arc<S3Service>>
+) -> Result<HttpResponse, Error> {
+    let (bucket, token) = path.into_inner();
+
+    let link = match find_drop_link(s3_service.as_ref(), &bucket, &token).await {
+        Ok(link) => link,
+        Err(response) => return Ok(response),
+    };
+
+    if let Err(message) = link.validate_active(Utc::now()) {
+        return Err(ApiError::new(ErrorCode::DropLinkInactive, message).into());
+    }
+
+    Ok(HttpResponse::Ok().json(public_drop_link(&link)))
+}
+
+/// Uploads files through a drop link.
+///
+/// This endpoint is meant to be called anonymously by the link holder.
+/// Files are stored under the prefix fixed by the link, and are renamed
+/// (`name (1).ext`, ...) instead of overwriting existing files. The upload
+/// policy of the bucket applies on top of the link's own limits.
+///
+/// # Path Parameters
+///
+/// * `bucket` - The name of the bucket
+/// * `token` - The token of the drop link
+///
+/// # Request Body
+///
+/// * Multipart form data containing the files to upload
+///
+/// # Returns
+///
+/// * `200 OK` - The uploaded files
+/// * `404 Not Found` - If the link does not exist
+/// * `410 Gone` - If the link has expired or reached its upload limit
+/// * `403 Forbidden` - If the link's folder is not allowed by the bucket's upload policy
+/// * `412 Precondition Failed` - If concurrent uploads through the link kept it busy
+/// * `413 Payload Too Large` - If a file exceeds the link's or the bucket's size limit
+/// * `415 Unsupported Media Type` - If a file type is not allowed
+/// * `500 Internal Server Error` - If there was an error uploading the files
+#[post("/drop/{bucket}/{token}", wrap = "crate::api::idempotency::Idempotency")]
+pub async fn upload_to_drop_link(
+    path: web::Path<(String, String)>,
+    mut payload: Multipart,
+    s3_service: web::Data<Arc<S3Service>>
+) -> Result<HttpResponse, Error> {
+    let (bucket, token) = path.into_inner();
+    let s3 = s3_service.as_ref();
+
+    let mut link = match find_drop_link(s3, &bucket, &token).await {
+        Ok(link) => link,
+        Err(response) => return Ok(response),
+    };
+
+    let policy = match s3.get_upload_policy(&bucket).await {
+        Ok(policy) => policy,
+        Err(e) => {
+            error!("Error reading upload policy of bucket {}: {:?}", bucket, e);
+            return Err(e.into());
+        }
+    };
+    let max_file_size = policy.size_limit(link.max_file_size);
+
+    let mut uploaded_files = Vec::new();
+
+    while let Ok(Some(mut field)) = payload.try_next().await {
+        if let Err(message) = link.validate_active(Utc::now()) {
+            return Err(ApiError::new(ErrorCode::DropLinkInactive, message).extension("files", &uploaded_files).into());
+        }
+
+        let filename = field_filename(&field);
+        if let Err(message) = link.validate_filename(&filename) {
+            return Err(ApiError::new(ErrorCode::UnsupportedMediaType, message)
+                .field("filename")
+                .extension("files", &uploaded_files)
+                .into());
+        }
+
+        let declared_type = field.content_type().map(|m| m.to_string());
+        if let Err(violation) = policy.check_key(&object_key(&link.prefix, &filename))
+            .and_then(|_| policy.check_file(&filename, declared_type.as_deref()))
+        {
+            return Ok(policy_violation_response(&violation, &uploaded_files));
+        }
+
+        let data = match read_field_data(&mut field, max_file_size).await? {
+            Some(data) => data,
+            None => {
+                return Err(ApiError::new(
+                    ErrorCode::PayloadTooLarge,
+                    format!("File '{}' exceeds the maximum size of {} bytes", filename, max_file_size.unwrap_or_default())
+                ).extension("files", &uploaded_files).into());
+            }
+        };
+        if let Err(violation) = policy.check_content(&filename, &data) {
+            return Ok(policy_violation_response(&violation, &uploaded_files));
+        }
+        let size = data.len();
+
+        // The upload is counted before the file is stored, so concurrent
+        // uploads through the link cannot together go past its limit
+        match s3.claim_drop_link_upload(&mut link).await {
+            Ok(true) => {},
+            Ok(false) => {
+                let message = link.validate_active(Utc::now()).err().unwrap_or_default();
+                return Err(ApiError::new(ErrorCode::DropLinkInactive, message).extension("files", &uploaded_files).into());
+            },
+            Err(e) => {
+                error!("Error recording upload on drop link for {}/{}: {:?}", bucket, link.prefix, e);
+                return Err(ApiError::from(e).extension("files", &uploaded_files).into());
+            }
+        }
+
+        match s3.put_object_with_available_key(&object_key(&link.prefix, &filename), data.into(), &bucket).await {
+            Ok(key) => {
+                info!("Uploaded {} through drop link for {}/{}", key, bucket, link.prefix);
+                // Only the stored file name is returned, the link's folder stays hidden
+                let stored_name = key.rsplit('/').next().unwrap_or(&key).to_string();
+                uploaded_files.push(json!({
+                    "filename": filename,
+                    "stored_as": stored_name,
+                    "size": size
+                }));
+            },
+            Err(e) => {
+                error!("Error uploading file through drop link for {}/{}: {:?}", bucket, link.prefix, e);
+                return Err(ApiError::from(e).extension("files", &uploaded_files).into());
+            }
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(json!({
+        "files": uploaded_files
+    })))
+}
@@ -0,0 +1,212 @@
+//! # Grant Management API Endpoints
+//!
+//! This module provides the API endpoints for granting users and groups
+//! roles on buckets and prefixes, and for explaining the resulting access
+//! of a user. They are reserved for administrators and answer
+//! `501 Not Implemented` if no user database is configured.
+
+use std::sync::Arc;
+
+use actix_web::{delete, get, post, web, Error, HttpResponse, ResponseError};
+use log::{error, info};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::api::auth::require_admin;
+use crate::api::error::{ApiError, ErrorCode};
+use crate::api::v1::users::user_error;
+use crate::models::access::CreateGrantRequest;
+use crate::rdlib::access::authorizer::Authorizer;
+use crate::rdlib::access::error::AccessError;
+use crate::rdlib::access::grant::{GrantFilter, NewGrant, SubjectType};
+use crate::rdlib::auth::principal::Principal;
+use crate::rdlib::users::service::UserService;
+use crate::rdlib::users::user::Role;
+
+/// Query parameters for listing grants
+#[derive(Deserialize)]
+pub struct GrantQuery {
+    /// Only list grants for users or for groups
+    subject_type: Option<SubjectType>,
+    /// Only list grants of this user or group
+    subject: Option<String>,
+    /// Only list grants on this bucket
+    bucket: Option<String>,
+}
+
+/// Query parameters for explaining the access of a user
+#[derive(Deserialize)]
+pub struct EffectiveAccessQuery {
+    /// The name of the user
+    user: String,
+    /// The bucket
+    bucket: String,
+    /// The key of an object or a prefix; the whole bucket unless set
+    #[serde(default)]
+    prefix: String,
+}
+
+/// Gets the authorizer, if grants are enabled.
+///
+/// # Returns
+///
+/// The authorizer, or a `501 Not Implemented` error if no user database is configured
+pub fn authorizer(authorizer: Option<web::Data<Arc<Authorizer>>>) -> Result<web::Data<Arc<Authorizer>>, ApiError> {
+    authorizer.ok_or_else(|| ApiError::new(ErrorCode::NotEnabled, "Grants and groups are not enabled on this server"))
+}
+
+/// Converts an error of access control, logging server-side failures.
+pub fn access_error(context: &str, e: AccessError) -> Error {
+    if e.status_code().is_server_error() {
+        error!("Error {}: {:?}", context, e);
+    }
+    e.into()
+}
+
+/// Lists grants.
+///
+/// # Query Parameters
+///
+/// * `subject_type` - Optional `user` or `group` to filter by
+/// * `subject` - Optional name of a user or group to filter by
+/// * `bucket` - Optional bucket to filter by
+///
+/// # Returns
+///
+/// * `200 OK` - A JSON array of grants
+/// * `403 Forbidden` - If the client is not an administrator
+#[get("/admin/grants")]
+pub async fn list_grants(
+    principal: Principal,
+    query: web::Query<GrantQuery>,
+    authorizer_data: Option<web::Data<Arc<Authorizer>>>
+) -> Result<HttpResponse, Error> {
+    require_admin(&principal)?;
+    let authorizer = authorizer(authorizer_data)?;
+
+    let filter = GrantFilter {
+        subject_type: query.subject_type,
+        subject: query.subject.clone(),
+        bucket: query.bucket.clone(),
+    };
+    match authorizer.list_grants(&filter).await {
+        Ok(grants) => Ok(HttpResponse::Ok().json(grants)),
+        Err(e) => Err(access_error("listing grants", e)),
+    }
+}
+
+/// Grants a role to a user or group on a bucket or prefix. A role the
+/// subject already has on the same bucket and prefix is replaced.
+///
+/// # Request Body
+///
+/// * `subject_type` - `user` or `group`
+/// * `subject` - The name of the user or group
+/// * `bucket` - The bucket to grant the role on
+/// * `prefix` - The prefix to grant the role on, the whole bucket unless set
+/// * `role` - `viewer`, `uploader`, `editor` or `admin`
+///
+/// # Returns
+///
+/// * `201 Created` - The grant, if the subject had no role on the bucket and prefix
+/// * `200 OK` - The grant, if it replaced a role
+/// * `400 Bad Request` - If the subject, bucket or prefix is not acceptable
+/// * `403 Forbidden` - If the client is not an administrator
+#[post("/admin/grants", wrap = "crate::api::idempotency::Idempotency")]
+pub async fn create_grant(
+    principal: Principal,
+    request: web::Json<CreateGrantRequest>,
+    authorizer_data: Option<web::Data<Arc<Authorizer>>>
+) -> Result<HttpResponse, Error> {
+    require_admin(&principal)?;
+    let authorizer = authorizer(authorizer_data)?;
+
+    let request = request.into_inner();
+    let grant = NewGrant {
+        subject_type: request.subject_type,
+        subject: request.subject,
+        bucket: request.bucket,
+        prefix: request.prefix,
+        role: request.role,
+    };
+    match authorizer.put_grant(grant).await {
+        Ok((grant, created)) => {
+            info!(
+                "User '{}' granted {} '{}' the {} role on {}/{}",
+                principal.subject, grant.subject_type, grant.subject, grant.role, grant.bucket, grant.prefix
+            );
+            let mut response = if created { HttpResponse::Created() } else { HttpResponse::Ok() };
+            Ok(response.json(grant))
+        },
+        Err(e) => Err(access_error("granting role", e)),
+    }
+}
+
+/// Revokes a grant.
+///
+/// # Path Parameters
+///
+/// * `id` - The ID of the grant
+///
+/// # Returns
+///
+/// * `200 OK` - If the grant was revoked
+/// * `403 Forbidden` - If the client is not an administrator
+/// * `404 Not Found` - If the grant does not exist
+#[delete("/admin/grants/{id}")]
+pub async fn delete_grant(
+    principal: Principal,
+    id: web::Path<u64>,
+    authorizer_data: Option<web::Data<Arc<Authorizer>>>
+) -> Result<HttpResponse, Error> {
+    require_admin(&principal)?;
+    let authorizer = authorizer(authorizer_data)?;
+
+    match authorizer.delete_grant(*id).await {
+        Ok(()) => {
+            info!("User '{}' revoked grant {}", principal.subject, id);
+            Ok(HttpResponse::Ok().json(json!({
+                "message": format!("Grant {} revoked successfully", id)
+            })))
+        },
+        Err(e) => Err(access_error("revoking grant", e)),
+    }
+}
+
+/// Explains what a user may do with a bucket or prefix: the groups of the
+/// user, including the groups containing them, its role and the grants
+/// that apply. Groups that only appear in the tokens of the user are not
+/// known to the server and not included.
+///
+/// # Query Parameters
+///
+/// * `user` - The name of the user
+/// * `bucket` - The bucket
+/// * `prefix` - Optional key of an object or prefix ending with `/`; the whole bucket unless set
+///
+/// # Returns
+///
+/// * `200 OK` - The effective access of the user
+/// * `400 Bad Request` - If the user or bucket is not acceptable
+/// * `403 Forbidden` - If the client is not an administrator
+#[get("/admin/access")]
+pub async fn effective_access(
+    principal: Principal,
+    query: web::Query<EffectiveAccessQuery>,
+    users: Option<web::Data<Arc<UserService>>>,
+    authorizer_data: Option<web::Data<Arc<Authorizer>>>
+) -> Result<HttpResponse, Error> {
+    require_admin(&principal)?;
+    let authorizer = authorizer(authorizer_data)?;
+
+    let administrator = match users {
+        Some(users) => users.find_user(&query.user).await
+            .map_err(|e| user_error("reading user", e))?
+            .is_some_and(|user| user.role == Role::Admin && !user.disabled),
+        None => false,
+    };
+    match authorizer.effective_access(&query.user, administrator, &query.bucket, &query.prefix).await {
+        Ok(access) => Ok(HttpResponse::Ok().json(access)),
+        Err(e) => Err(access_error("explaining access", e)),
+    }
+}
@@ -0,0 +1,231 @@
+//! # Group Management API Endpoints
+//!
+//! This module provides the API endpoints for managing groups and their
+//! members, which may be users or other groups. They are reserved for
+//! administrators and answer `501 Not Implemented` if no user database is
+//! configured.
+
+use std::sync::Arc;
+
+use actix_web::{delete, get, post, web, Error, HttpResponse};
+use log::info;
+use serde_json::json;
+
+use crate::api::auth::require_admin;
+use crate::api::v1::grants::{access_error, authorizer};
+use crate::models::access::{AddMemberRequest, CreateGroupRequest};
+use crate::rdlib::access::authorizer::Authorizer;
+use crate::rdlib::access::grant::SubjectType;
+use crate::rdlib::auth::principal::Principal;
+
+/// Lists all groups.
+///
+/// # Returns
+///
+/// * `200 OK` - A JSON array of groups
+/// * `403 Forbidden` - If the client is not an administrator
+#[get("/admin/groups")]
+pub async fn list_groups(
+    principal: Principal,
+    authorizer_data: Option<web::Data<Arc<Authorizer>>>
+) -> Result<HttpResponse, Error> {
+    require_admin(&principal)?;
+    let authorizer = authorizer(authorizer_data)?;
+
+    match authorizer.list_groups().await {
+        Ok(groups) => Ok(HttpResponse::Ok().json(groups)),
+        Err(e) => Err(access_error("listing groups", e)),
+    }
+}
+
+/// Creates a group.
+///
+/// # Request Body
+///
+/// * `name` - The name of the group, used in grants
+/// * `description` - Optional description of the group
+///
+/// # Returns
+///
+/// * `201 Created` - The created group
+/// * `400 Bad Request` - If the name or description is not acceptable
+/// * `403 Forbidden` - If the client is not an administrator
+/// * `409 Conflict` - If the name is in use
+#[post("/admin/groups", wrap = "crate::api::idempotency::Idempotency")]
+pub async fn create_group(
+    principal: Principal,
+    request: web::Json<CreateGroupRequest>,
+    authorizer_data: Option<web::Data<Arc<Authorizer>>>
+) -> Result<HttpResponse, Error> {
+    require_admin(&principal)?;
+    let authorizer = authorizer(authorizer_data)?;
+
+    match authorizer.create_group(&request.name, &request.description).await {
+        Ok(group) => {
+            info!("User '{}' created group '{}'", principal.subject, group.name);
+            Ok(HttpResponse::Created().json(group))
+        },
+        Err(e) => Err(access_error("creating group", e)),
+    }
+}
+
+/// Shows a group.
+///
+/// # Path Parameters
+///
+/// * `id` - The ID of the group
+///
+/// # Returns
+///
+/// * `200 OK` - The group
+/// * `403 Forbidden` - If the client is not an administrator
+/// * `404 Not Found` - If the group does not exist
+#[get("/admin/groups/{id}")]
+pub async fn get_group(
+    principal: Principal,
+    id: web::Path<u64>,
+    authorizer_data: Option<web::Data<Arc<Authorizer>>>
+) -> Result<HttpResponse, Error> {
+    require_admin(&principal)?;
+    let authorizer = authorizer(authorizer_data)?;
+
+    match authorizer.get_group(*id).await {
+        Ok(group) => Ok(HttpResponse::Ok().json(group)),
+        Err(e) => Err(access_error("reading group", e)),
+    }
+}
+
+/// Deletes a group. Its grants are revoked and it is removed from the
+/// groups it is a member of.
+///
+/// # Path Parameters
+///
+/// * `id` - The ID of the group
+///
+/// # Returns
+///
+/// * `200 OK` - If the group was deleted
+/// * `403 Forbidden` - If the client is not an administrator
+/// * `404 Not Found` - If the group does not exist
+#[delete("/admin/groups/{id}")]
+pub async fn delete_group(
+    principal: Principal,
+    id: web::Path<u64>,
+    authorizer_data: Option<web::Data<Arc<Authorizer>>>
+) -> Result<HttpResponse, Error> {
+    require_admin(&principal)?;
+    let authorizer = authorizer(authorizer_data)?;
+
+    match authorizer.delete_group(*id).await {
+        Ok(group) => {
+            info!("User '{}' deleted group '{}'", principal.subject, group.name);
+            Ok(HttpResponse::Ok().json(json!({
+                "message": format!("Group {} deleted successfully", id)
+            })))
+        },
+        Err(e) => Err(access_error("deleting group", e)),
+    }
+}
+
+/// Lists the direct members of a group.
+///
+/// # Path Parameters
+///
+/// * `id` - The ID of the group
+///
+/// # Returns
+///
+/// * `200 OK` - A JSON array of members
+/// * `403 Forbidden` - If the client is not an administrator
+/// * `404 Not Found` - If the group does not exist
+#[get("/admin/groups/{id}/members")]
+pub async fn list_members(
+    principal: Principal,
+    id: web::Path<u64>,
+    authorizer_data: Option<web::Data<Arc<Authorizer>>>
+) -> Result<HttpResponse, Error> {
+    require_admin(&principal)?;
+    let authorizer = authorizer(authorizer_data)?;
+
+    match authorizer.list_members(*id).await {
+        Ok(members) => Ok(HttpResponse::Ok().json(members)),
+        Err(e) => Err(access_error("listing group members", e)),
+    }
+}
+
+/// Adds a user or group to a group.
+///
+/// # Path Parameters
+///
+/// * `id` - The ID of the group
+///
+/// # Request Body
+///
+/// * `member_type` - `user` or `group`
+/// * `member` - The name of the user or group
+///
+/// # Returns
+///
+/// * `201 Created` - If the user or group was added
+/// * `200 OK` - If it already was a member
+/// * `400 Bad Request` - If the member group does not exist or contains the group
+/// * `403 Forbidden` - If the client is not an administrator
+/// * `404 Not Found` - If the group does not exist
+#[post("/admin/groups/{id}/members")]
+pub async fn add_member(
+    principal: Principal,
+    id: web::Path<u64>,
+    request: web::Json<AddMemberRequest>,
+    authorizer_data: Option<web::Data<Arc<Authorizer>>>
+) -> Result<HttpResponse, Error> {
+    require_admin(&principal)?;
+    let authorizer = authorizer(authorizer_data)?;
+
+    match authorizer.add_member(*id, request.member_type, &request.member).await {
+        Ok(added) => {
+            if added {
+                info!("User '{}' added {} '{}' to group {}", principal.subject, request.member_type, request.member, id);
+            }
+            let mut response = if added { HttpResponse::Created() } else { HttpResponse::Ok() };
+            Ok(response.json(json!({
+                "member_type": request.member_type,
+                "member": request.member,
+            })))
+        },
+        Err(e) => Err(access_error("adding group member", e)),
+    }
+}
+
+/// Removes a user or group from a group.
+///
+/// # Path Parameters
+///
+/// * `id` - The ID of the group
+/// * `member_type` - `user` or `group`
+/// * `member` - The name of the user or group
+///
+/// # Returns
+///
+/// * `200 OK` - If the member was removed
+/// * `403 Forbidden` - If the client is not an administrator
+/// * `404 Not Found` - If the group does not exist or the user or group is not its member
+#[delete("/admin/groups/{id}/members/{member_type}/{member}")]
+pub async fn remove_member(
+    principal: Principal,
+    path: web::Path<(u64, SubjectType, String)>,
+    authorizer_data: Option<web::Data<Arc<Authorizer>>>
+) -> Result<HttpResponse, Error> {
+    require_admin(&principal)?;
+    let authorizer = authorizer(authorizer_data)?;
+
+    let (id, member_type, member) = path.into_inner();
+    match authorizer.remove_member(id, member_type, &member).await {
+        Ok(()) => {
+            info!("User '{}' removed {} '{}' from group {}", principal.subject, member_type, member, id);
+            Ok(HttpResponse::Ok().json(json!({
+                "message": format!("Member '{}' removed from group {}", member, id)
+            })))
+        },
+        Err(e) => Err(access_error("removing group member", e)),
+    }
+}
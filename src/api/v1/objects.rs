@@ -3,21 +3,60 @@
 //! This module provides the API endpoints for object operations.
 //! It includes handlers for listing, uploading, downloading, viewing,
 //! deleting, and managing objects in buckets.
+//!
+//! Every handler checks the client's role on the keys it touches: reading
+//! needs `viewer`, adding objects `uploader`, and replacing, moving or
+//! deleting them `editor`. Listings only show what the client can see.
 
-use actix_web::{post, get, delete, web, HttpResponse, Error};
-use actix_multipart::Multipart;
+use actix_web::{post, get, put, delete, web, HttpRequest, HttpResponse, Error, ResponseError};
+use actix_web::http::StatusCode;
+use actix_web::error::PayloadError;
+use actix_multipart::{Field, Multipart};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use bytes::{Bytes, BytesMut};
 use futures::{StreamExt, TryStreamExt};
+use md5::{Digest, Md5};
 use serde_json::json;
+use crate::api::access::{require, require_visible};
+use crate::api::error::{ApiError, ErrorCode};
+use crate::rdlib::access::authorizer::Access;
+use crate::rdlib::access::grant::Permission;
 use crate::rdlib::s3::service::S3Service;
+use crate::rdlib::s3::policy::PolicyViolation;
+use crate::rdlib::s3::resumable::DEFAULT_PART_SIZE;
+use crate::rdlib::s3::types::{Preconditions, UploadPolicy, UploadedPart, WriteOptions};
+use crate::rdlib::s3::error::S3Error;
+use crate::rdlib::archive::extract::sanitize_entry_path;
+use crate::rdlib::content;
+use crate::rdlib::imaging::{ImageTransform, ImagingError, OutputFormat};
+use crate::api::v1::previews::cached_rendition;
+use crate::api::v1::archives::upload_archives;
 use uuid::Uuid;
 use serde::Deserialize;
 use crate::models::s3::CreateFolderRequest;
 use std::io::Write;
 use sanitize_filename;
 use std::path::Path;
-use log::error;
+use std::collections::HashSet;
+use log::{error, warn};
 use std::sync::Arc;
 
+/// How uploads handle files whose key is already taken
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictPolicy {
+    /// Keep the existing object and report a conflict
+    #[default]
+    Fail,
+    /// Overwrite the existing object
+    Replace,
+    /// Store the file under the first free numbered name, such as `name (1).ext`
+    Rename,
+    /// Keep the existing object and skip the file
+    Skip,
+}
+
 /// Query parameters for listing objects with an optional prefix
 #[derive(Deserialize)]
 pub struct PrefixQuery {
@@ -25,6 +64,12 @@ pub struct PrefixQuery {
     prefix: Option<String>,
     /// Whether to replace existing objects with the same name
     replace: Option<bool>,
+    /// How to handle files whose key is already taken; overrides `replace`
+    conflict: Option<ConflictPolicy>,
+    /// Whether to extract uploaded archives into the prefix
+    extract: Option<bool>,
+    /// Whether file names are relative paths whose folders are recreated
+    preserve_paths: Option<bool>,
 }
 
 /// Query parameters for checking if a file exists
@@ -34,7 +79,47 @@ pub struct FileExistsQuery {
     filename: String,
 }
 
-/// Request body for moving a file within a bucket
+/// Query parameters for transforming images on view
+#[derive(Deserialize)]
+pub struct ViewQuery {
+    /// Target width in pixels
+    w: Option<u32>,
+    /// Target height in pixels
+    h: Option<u32>,
+    /// Region to crop to, as `x,y,width,height`
+    crop: Option<String>,
+    /// Clockwise rotation in degrees
+    rotate: Option<u16>,
+    /// Output format: `jpeg`, `png` or `webp`
+    format: Option<String>,
+    /// Output quality from 1 to 100
+    quality: Option<u8>,
+}
+
+impl ViewQuery {
+    /// Converts the query parameters into an image transformation.
+    fn to_transform(&self) -> Result<ImageTransform, ImagingError> {
+        let format = match &self.format {
+            Some(name) => Some(OutputFormat::from_name(name).ok_or_else(|| {
+                ImagingError::InvalidRequest(format!("Unsupported format '{}', use jpeg, png or webp", name))
+            })?),
+            None => None,
+        };
+
+        let transform = ImageTransform {
+            width: self.w,
+            height: self.h,
+            crop: self.crop.as_deref().map(ImageTransform::parse_crop).transpose()?,
+            rotate: self.rotate.unwrap_or(0),
+            format,
+            quality: self.quality,
+        };
+        transform.validate()?;
+        Ok(transform)
+    }
+}
+
+/// Request body for moving a file within a bucket.
 #[derive(Deserialize)]
 pub struct MoveFileRequest {
     /// The source key (path) of the file to move
@@ -43,6 +128,198 @@ pub struct MoveFileRequest {
     destination_key: String,
 }
 
+/// Resolves the file name of a multipart field.
+///
+/// The name is taken from the field's content disposition and sanitized.
+/// Fields without a file name get a random UUID as name.
+pub(crate) fn field_filename(field: &Field) -> String {
+    field.content_disposition()
+        .and_then(|cd| cd.get_filename())
+        .map_or_else(
+            || Uuid::new_v4().to_string(),
+            sanitize_filename::sanitize
+        )
+}
+
+/// Normalizes the relative path of a file in a folder upload.
+///
+/// The path is rejected if it could leave the upload prefix, and each of
+/// its segments is sanitized like a file name.
+///
+/// # Arguments
+///
+/// * `path` - The relative path sent by the client, such as `photos/2024/beach.jpg`
+///
+/// # Returns
+///
+/// * `Ok(String)` - The normalized path
+/// * `Err(String)` - A description of why the path is rejected
+pub(crate) fn normalize_relative_path(path: &str) -> Result<String, String> {
+    let path = sanitize_entry_path(path)?;
+    if path.ends_with('/') {
+        return Err("the path does not name a file".to_string());
+    }
+
+    let segments = path
+        .split('/')
+        .map(|segment| match sanitize_filename::sanitize(segment) {
+            sanitized if sanitized.is_empty() => Err(format!("'{}' is not a valid name", segment)),
+            sanitized => Ok(sanitized),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(segments.join("/"))
+}
+
+/// Resolves the relative path of a multipart field in a folder upload.
+///
+/// Browsers send the `webkitRelativePath` of each file as its file name.
+/// Fields without a file name get a random UUID as name.
+pub(crate) fn field_relative_path(field: &Field) -> Result<String, String> {
+    match field.content_disposition().and_then(|cd| cd.get_filename()) {
+        Some(path) => normalize_relative_path(path),
+        None => Ok(Uuid::new_v4().to_string()),
+    }
+}
+
+/// Lists the folders leading to a relative path, outermost first.
+///
+/// For `photos/2024/beach.jpg` these are `photos/` and `photos/2024/`.
+pub(crate) fn parent_folders(path: &str) -> Vec<String> {
+    path.match_indices('/')
+        .map(|(index, _)| path[..=index].to_string())
+        .collect()
+}
+
+/// Creates the placeholder objects of the folders leading to a file.
+///
+/// Folders that were already handled during the upload, already exist, do
+/// not match the upload policy's key pattern, or that the client may not
+/// write to are left alone.
+///
+/// # Returns
+///
+/// The keys of the created folders
+async fn create_parent_folders(
+    s3: &S3Service,
+    bucket: &str,
+    prefix: &str,
+    path: &str,
+    policy: &UploadPolicy,
+    access: &Access,
+    seen: &mut HashSet<String>
+) -> Result<Vec<String>, S3Error> {
+    let mut created = Vec::new();
+    for folder in parent_folders(path) {
+        let key = object_key(prefix, &folder);
+        if !seen.insert(key.clone()) || policy.check_key(&key).is_err() || !access.allows(bucket, &key, Permission::Write) {
+            continue;
+        }
+        if !s3.check_object_exists(&key, bucket).await? {
+            s3.put_object(&key, vec![], bucket).await?;
+            created.push(key);
+        }
+    }
+    Ok(created)
+}
+
+/// Stores an uploaded file according to the conflict policy.
+///
+/// Only `ConflictPolicy::Replace` overwrites existing objects. The other
+/// policies use conditional puts, so a file uploaded concurrently under the
+/// same key is never overwritten.
+///
+/// # Returns
+///
+/// * `Ok(Some(key))` - The key the file was stored under
+/// * `Ok(None)` - If the key is taken and the file was not stored
+/// * `Err(S3Error)` - If there was an error storing the file
+pub(crate) async fn store_with_conflict_policy(
+    s3: &S3Service,
+    bucket: &str,
+    key: &str,
+    data: Vec<u8>,
+    conflict: ConflictPolicy
+) -> Result<Option<String>, S3Error> {
+    match conflict {
+        ConflictPolicy::Replace => s3.put_object(key, data, bucket).await.map(|_| Some(key.to_string())),
+        ConflictPolicy::Rename => s3.put_object_with_available_key(key, data.into(), bucket).await.map(Some),
+        ConflictPolicy::Fail | ConflictPolicy::Skip => {
+            let stored = s3.put_object_if_absent(key, data.into(), bucket).await?;
+            Ok(stored.then(|| key.to_string()))
+        },
+    }
+}
+
+/// Joins a prefix and a file name into an object key.
+pub(crate) fn object_key(prefix: &str, filename: &str) -> String {
+    if prefix.is_empty() {
+        filename.to_string()
+    } else {
+        format!("{}/{}", prefix.trim_end_matches('/'), filename)
+    }
+}
+
+/// Reads the data of a multipart field into memory.
+///
+/// If `max_size` is set, reading stops as soon as the data grows beyond it
+/// and `Ok(None)` is returned, so oversized uploads are not buffered.
+pub(crate) async fn read_field_data(field: &mut Field, max_size: Option<u64>) -> Result<Option<Vec<u8>>, Error> {
+    let mut data = Vec::new();
+    while let Some(chunk) = field.next().await {
+        let data_chunk = chunk?;
+        if max_size.is_some_and(|max| (data.len() + data_chunk.len()) as u64 > max) {
+            return Ok(None);
+        }
+        data.write_all(&data_chunk)?;
+    }
+    Ok(Some(data))
+}
+
+/// Builds the response for uploads rejected by a bucket's upload policy.
+///
+/// Oversized objects are rejected with `413 Payload Too Large`, disallowed
+/// types with `415 Unsupported Media Type` and disallowed keys with `403 Forbidden`.
+/// The files uploaded before the rejection are listed in the response.
+pub(crate) fn policy_violation_response(violation: &PolicyViolation, uploaded_files: &[serde_json::Value]) -> HttpResponse {
+    let status = match violation {
+        PolicyViolation::TooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+        PolicyViolation::UnsupportedType(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+        PolicyViolation::KeyNotAllowed(_) => StatusCode::FORBIDDEN,
+    };
+    ApiError::new(ErrorCode::PolicyViolation, violation.to_string())
+        .status(status)
+        .extension("files", uploaded_files)
+        .error_response()
+}
+
+/// Builds the response for requests that target RustDok's hidden system prefix.
+pub(crate) fn system_key_forbidden(key: &str) -> HttpResponse {
+    ApiError::new(ErrorCode::ReservedKey, format!("Access to '{}' is not allowed", key)).error_response()
+}
+
+/// Reads the `If-Match` and `If-None-Match` preconditions of a request.
+///
+/// # Returns
+///
+/// * `Ok(Preconditions)` - The preconditions, empty if neither header is set
+/// * `Err(HttpResponse)` - `400 Bad Request` if a header is invalid
+pub(crate) fn request_preconditions(req: &HttpRequest) -> Result<Preconditions, HttpResponse> {
+    let header = |name: &str| req.headers().get(name).map(|v| v.to_str().unwrap_or_default());
+    Preconditions::parse(header("If-Match"), header("If-None-Match")).map_err(|e| {
+        ApiError::new(ErrorCode::InvalidPrecondition, e).error_response()
+    })
+}
+
+/// Builds the response for writes and deletes whose preconditions do not hold.
+pub(crate) fn precondition_failed(key: &str, bucket: &str, preconditions: &Preconditions) -> HttpResponse {
+    let error = if preconditions.requires_absence() {
+        format!("File {} already exists in bucket {}", key, bucket)
+    } else {
+        format!("File {} in bucket {} does not match the request's preconditions", key, bucket)
+    };
+    ApiError::new(ErrorCode::PreconditionFailed, error).error_response()
+}
+
 /// Lists objects in a bucket, optionally filtered by prefix.
 ///
 /// This endpoint retrieves a list of objects in the specified bucket.
@@ -58,25 +335,30 @@ pub struct MoveFileRequest {
 ///
 /// # Returns
 ///
-/// * `200 OK` - A JSON array of objects
+/// * `200 OK` - A JSON array of the objects the client can see
+/// * `403 Forbidden` - If the client has no role on the prefix or below it
 /// * `404 Not Found` - If the bucket does not exist
 /// * `500 Internal Server Error` - If there was an error listing the objects
 #[get("/bucket/{bucket}/objects")]
 pub async fn list_objects_in_bucket(
     bucket: web::Path<String>, 
     query: web::Query<PrefixQuery>,
+    access: Access,
     s3_service: web::Data<Arc<S3Service>>
 ) -> Result<HttpResponse, Error> {
     let s3 = s3_service.as_ref();
     let prefix = query.prefix.clone().unwrap_or_default();
     
+    if S3Service::is_system_key(&prefix) {
+        return Ok(system_key_forbidden(&prefix));
+    }
+    require_visible(&access, &bucket, &prefix)?;
+    
     match s3.list_objects(Some(&prefix), &bucket).await {
-        Ok(objects) => Ok(HttpResponse::Ok().json(objects)),
+        Ok(objects) => Ok(HttpResponse::Ok().json(access.filter_objects(&bucket, objects))),
         Err(e) => {
             error!("Error listing objects in bucket {}: {:?}", bucket, e);
-            Ok(HttpResponse::InternalServerError().json(json!({
-                "error": format!("Failed to list objects in bucket {}: {}", bucket, e)
-            })))
+            Err(e.into())
         }
     }
 }
@@ -93,29 +375,35 @@ pub async fn list_objects_in_bucket(
 /// # Returns
 ///
 /// * `200 OK` - The binary data of the object with appropriate content type
+/// * `403 Forbidden` - If the client is not a viewer of the object
 /// * `404 Not Found` - If the object does not exist
 /// * `500 Internal Server Error` - If there was an error downloading the object
 #[get("/bucket/{bucket}/download/{key:.*}")]
 pub async fn download_object_from_bucket(
     path: web::Path<(String, String)>,
+    access: Access,
     s3_service: web::Data<Arc<S3Service>>
 ) -> Result<HttpResponse, Error> {
     let (bucket, key) = path.into_inner();
     let s3 = s3_service.as_ref();
     
+    if S3Service::is_system_key(&key) {
+        return Ok(system_key_forbidden(&key));
+    }
+    require(&access, &bucket, &key, Permission::Read)?;
+    
     match s3.get_object(&key, &bucket).await {
         Ok(data) => {
             let filename = Path::new(&key).file_name().unwrap_or_default().to_string_lossy();
             Ok(HttpResponse::Ok()
                 .content_type("application/octet-stream")
+                .append_header(("X-Content-Type-Options", "nosniff"))
                 .append_header(("Content-Disposition", format!("attachment; filename=\"{}\"", filename)))
                 .body(data))
         },
         Err(e) => {
             error!("Error downloading file {}/{}: {:?}", bucket, key, e);
-            Ok(HttpResponse::InternalServerError().json(json!({
-                "error": format!("Failed to download file: {}", e)
-            })))
+            Err(e.into())
         }
     }
 }
@@ -135,23 +423,28 @@ pub async fn download_object_from_bucket(
 /// # Returns
 ///
 /// * `200 OK` - A JSON object with `exists` field indicating whether the object exists
+/// * `403 Forbidden` - If the client is not a viewer of the object
 /// * `500 Internal Server Error` - If there was an error checking the object
 #[get("/bucket/{bucket}/exists")]
 pub async fn check_object_exists_in_bucket(
     bucket: web::Path<String>, 
     query: web::Query<FileExistsQuery>,
+    access: Access,
     s3_service: web::Data<Arc<S3Service>>
 ) -> Result<HttpResponse, Error> {
     let s3 = s3_service.as_ref();
     let filename = &query.filename;
     
+    if S3Service::is_system_key(filename) {
+        return Ok(system_key_forbidden(filename));
+    }
+    require(&access, &bucket, filename, Permission::Read)?;
+    
     match s3.check_object_exists(filename, &bucket).await {
         Ok(exists) => Ok(HttpResponse::Ok().json(json!({ "exists": exists }))),
         Err(e) => {
             error!("Error checking if file exists in bucket {}/{}: {:?}", bucket, filename, e);
-            Ok(HttpResponse::InternalServerError().json(json!({
-                "error": format!("Failed to check if file exists in bucket: {}", e)
-            })))
+            Err(e.into())
         }
     }
 }
@@ -160,6 +453,23 @@ pub async fn check_object_exists_in_bucket(
 ///
 /// This endpoint uploads a file to the specified bucket.
 /// If a prefix is provided, the file is stored under that prefix.
+/// With `extract=true`, the files must be ZIP, tar or tar.gz archives, which
+/// are extracted into the prefix; see `archives::upload_archives`.
+/// With `preserve_paths=true`, file names are relative paths, as sent by
+/// browsers for directory uploads, and the folder hierarchy is recreated
+/// under the prefix, including placeholder objects for the folders.
+///
+/// Files whose key is taken are handled according to `conflict`. Each file
+/// is reported with its outcome: `uploaded`, `renamed`, `skipped`,
+/// `conflict`, `precondition_failed` or `failed`. A conflicting or failed
+/// file does not stop the upload of the files after it; only policy
+/// violations abort the request.
+///
+/// With `If-Match` or `If-None-Match`, every file is only written if the
+/// existing object at its key meets the preconditions, and `conflict` is ignored.
+///
+/// Uploading needs the `uploader` role on the key of every file; replacing
+/// objects, with `conflict=replace` or `If-Match`, needs the `editor` role.
 ///
 /// # Path Parameters
 ///
@@ -168,7 +478,15 @@ pub async fn check_object_exists_in_bucket(
 /// # Query Parameters
 ///
 /// * `prefix` - Optional prefix to store the file under
-/// * `replace` - Whether to replace an existing file with the same name
+/// * `replace` - Whether to replace an existing file with the same name, same as `conflict=replace`
+/// * `conflict` - How to handle taken keys: `fail` (default), `replace`, `rename` or `skip`
+/// * `extract` - Whether to extract uploaded archives into the prefix
+/// * `preserve_paths` - Whether file names are relative paths to recreate under the prefix
+///
+/// # Headers
+///
+/// * `If-Match` - ETags, or `*`, the existing object of every file must have
+/// * `If-None-Match` - ETags the existing object of every file must not have, or `*` if it must not exist
 ///
 /// # Request Body
 ///
@@ -176,80 +494,496 @@ pub async fn check_object_exists_in_bucket(
 ///
 /// # Returns
 ///
-/// * `201 Created` - If the file was uploaded successfully
-/// * `400 Bad Request` - If the file is invalid or missing
-/// * `500 Internal Server Error` - If there was an error uploading the file
-#[post("/bucket/{bucket}/objects")]
+/// * `200 OK` - If every file was uploaded, renamed or skipped
+/// * `400 Bad Request` - If the file is invalid or missing, a relative path or a precondition header is invalid
+/// * `403 Forbidden` - If the key does not match the bucket's key prefix pattern, or the client's role does not allow the upload
+/// * `409 Conflict` - If a file already exists and `conflict` is `fail`
+/// * `412 Precondition Failed` - If the existing object of a file does not meet the preconditions
+/// * `413 Payload Too Large` - If the file exceeds the bucket's maximum object size
+/// * `415 Unsupported Media Type` - If the file type is not allowed in the bucket
+/// * `500 Internal Server Error` - If there was an error uploading a file
+#[post("/bucket/{bucket}/objects", wrap = "crate::api::idempotency::Idempotency")]
 pub async fn upload_object_to_bucket(
     bucket: web::Path<String>, 
     query: web::Query<PrefixQuery>, 
+    req: HttpRequest,
     mut payload: Multipart,
+    access: Access,
     s3_service: web::Data<Arc<S3Service>>
 ) -> Result<HttpResponse, Error> {
     let s3 = s3_service.as_ref();
     let prefix = query.prefix.clone().unwrap_or_default();
-    let replace = query.replace.unwrap_or(false);
+    let preconditions = match request_preconditions(&req) {
+        Ok(preconditions) => preconditions,
+        Err(response) => return Ok(response),
+    };
+    if query.extract.unwrap_or(false) && !preconditions.is_empty() {
+        return Err(ApiError::new(ErrorCode::InvalidRequest, "Preconditions are not supported when extracting archives")
+            .field("extract")
+            .into());
+    }
+    let conflict = query.conflict.unwrap_or(if query.replace.unwrap_or(false) {
+        ConflictPolicy::Replace
+    } else {
+        ConflictPolicy::Fail
+    });
+    if query.extract.unwrap_or(false) && conflict == ConflictPolicy::Fail && query.conflict.is_some() {
+        return Err(ApiError::new(ErrorCode::InvalidRequest, "conflict=fail is not supported when extracting archives")
+            .field("conflict")
+            .into());
+    }
+    let preserve_paths = query.preserve_paths.unwrap_or(false);
+    // Writes that may replace an existing object need the permission to delete it
+    let permission = if conflict == ConflictPolicy::Replace || !(preconditions.is_empty() || preconditions.requires_absence()) {
+        Permission::Delete
+    } else {
+        Permission::Write
+    };
+    
+    if S3Service::is_system_key(&prefix) {
+        return Ok(system_key_forbidden(&prefix));
+    }
+    
+    let policy = match s3.get_upload_policy(&bucket).await {
+        Ok(policy) => policy,
+        Err(e) => {
+            error!("Error reading upload policy of bucket {}: {:?}", bucket, e);
+            return Err(e.into());
+        }
+    };
+    
+    if query.extract.unwrap_or(false) {
+        require(&access, &bucket, &object_key(&prefix, ""), permission)?;
+        // Extraction skips taken keys unless the client asks to replace or rename them
+        let conflict = if conflict == ConflictPolicy::Fail { ConflictPolicy::Skip } else { conflict };
+        return upload_archives(s3, &bucket, &prefix, conflict, &policy, payload).await;
+    }
     
     let mut uploaded_files = Vec::new();
+    let mut created_folders = Vec::new();
+    let mut seen_folders = HashSet::new();
+    let mut conflicts = 0;
+    let mut unmet_preconditions = 0;
+    let mut failures = 0;
     
     while let Ok(Some(mut field)) = payload.try_next().await {
-        let content_disposition = field.content_disposition();
-        let filename = content_disposition.as_ref().and_then(|cd| cd.get_filename()).map_or_else(
-            || Uuid::new_v4().to_string(),
-            |f| sanitize_filename::sanitize(f)
-        );
-        
-        let key = if prefix.is_empty() {
-            filename.clone()
+        let path = if preserve_paths {
+            match field_relative_path(&field) {
+                Ok(path) => path,
+                Err(reason) => {
+                    return Err(ApiError::new(ErrorCode::InvalidKey, format!("Invalid relative path: {}", reason))
+                        .field("filename")
+                        .extension("files", &uploaded_files)
+                        .into());
+                }
+            }
         } else {
-            format!("{}/{}", prefix.trim_end_matches('/'), filename)
+            field_filename(&field)
         };
+        let filename = path.rsplit('/').next().unwrap_or(&path).to_string();
+        let key = object_key(&prefix, &path);
         
-        // Check if file exists and we're not replacing
-        if !replace {
-            match s3.check_object_exists(&key, &bucket).await {
-                Ok(true) => {
-                    return Ok(HttpResponse::Conflict().json(json!({
-                        "error": format!("File {} already exists in bucket {}", key, bucket)
-                    })));
-                },
-                Ok(false) => {},
-                Err(e) => {
-                    error!("Error checking if file exists: {:?}", e);
-                    return Ok(HttpResponse::InternalServerError().json(json!({
-                        "error": format!("Failed to check if file exists: {}", e)
-                    })));
-                }
-            }
+        if S3Service::is_system_key(&key) {
+            return Ok(system_key_forbidden(&key));
+        }
+        if let Err(e) = require(&access, &bucket, &key, permission) {
+            return Err(e.extension("files", &uploaded_files).into());
         }
         
-        let mut data = Vec::new();
-        while let Some(chunk) = field.next().await {
-            let data_chunk = chunk?;
-            data.write_all(&data_chunk)?;
+        let declared_type = field.content_type().map(|m| m.to_string());
+        if let Err(violation) = policy.check_key(&key).and_then(|_| policy.check_file(&filename, declared_type.as_deref())) {
+            return Ok(policy_violation_response(&violation, &uploaded_files));
         }
         
-        match s3.put_object(&key, data.to_vec(), &bucket).await {
-            Ok(_) => {
-                uploaded_files.push(json!({
-                    "filename": filename,
-                    "key": key,
-                    "size": data.len(),
-                    "bucket": bucket.to_string()
-                }));
+        let data = match read_field_data(&mut field, policy.max_object_size).await? {
+            Some(data) => data,
+            None => {
+                let violation = PolicyViolation::TooLarge(policy.max_object_size.unwrap_or_default());
+                return Ok(policy_violation_response(&violation, &uploaded_files));
+            }
+        };
+        if let Err(violation) = policy.check_content(&filename, &data) {
+            return Ok(policy_violation_response(&violation, &uploaded_files));
+        }
+        let size = data.len();
+        
+        let stored = match create_parent_folders(s3, &bucket, &prefix, &path, &policy, &access, &mut seen_folders).await {
+            Ok(created) if preconditions.is_empty() => {
+                created_folders.extend(created);
+                store_with_conflict_policy(s3, &bucket, &key, data, conflict).await
+            },
+            Ok(created) => {
+                created_folders.extend(created);
+                let options = WriteOptions { preconditions: preconditions.clone(), ..Default::default() };
+                s3.put_object_with_options(&key, &bucket, data.into(), &options).await.map(|etag| etag.map(|_| key.clone()))
+            },
+            Err(e) => Err(e),
+        };
+        
+        let mut file = json!({
+            "filename": filename,
+            "path": path,
+            "key": key,
+            "bucket": bucket.to_string()
+        });
+        match stored {
+            Ok(Some(stored_key)) if stored_key != key => {
+                file["status"] = json!("renamed");
+                file["requested_key"] = json!(key);
+                file["key"] = json!(stored_key);
+                file["size"] = json!(size);
+            },
+            Ok(Some(_)) => {
+                file["status"] = json!("uploaded");
+                file["size"] = json!(size);
+            },
+            Ok(None) if !preconditions.is_empty() => {
+                unmet_preconditions += 1;
+                file["status"] = json!("precondition_failed");
+                file["code"] = json!(ErrorCode::PreconditionFailed);
+                file["error"] = json!(format!("File {} does not match the request's preconditions", key));
+            },
+            Ok(None) if conflict == ConflictPolicy::Skip => {
+                file["status"] = json!("skipped");
+                file["reason"] = json!("file already exists");
+            },
+            Ok(None) => {
+                conflicts += 1;
+                file["status"] = json!("conflict");
+                file["code"] = json!(ErrorCode::AlreadyExists);
+                file["error"] = json!(format!("File {} already exists in bucket {}", key, bucket));
             },
             Err(e) => {
-                error!("Error uploading file: {:?}", e);
-                return Ok(HttpResponse::InternalServerError().json(json!({
-                    "error": format!("Failed to upload file: {}", e)
-                })));
+                error!("Error uploading file {}/{}: {:?}", bucket, key, e);
+                failures += 1;
+                let e = ApiError::from(e);
+                file["status"] = json!("failed");
+                file["code"] = json!(e.code());
+                file["error"] = json!(e.detail());
             }
         }
+        uploaded_files.push(file);
+    }
+    
+    let rejected = conflicts + unmet_preconditions + failures;
+    if rejected == 0 {
+        return Ok(HttpResponse::Ok().json(json!({
+            "files": uploaded_files,
+            "folders": created_folders
+        })));
     }
     
-    Ok(HttpResponse::Ok().json(json!({
-        "files": uploaded_files
-    })))
+    let code = if failures > 0 {
+        ErrorCode::UploadFailed
+    } else if unmet_preconditions > 0 {
+        ErrorCode::PreconditionFailed
+    } else {
+        ErrorCode::AlreadyExists
+    };
+    Err(ApiError::new(code, format!("{} of {} files could not be uploaded", rejected, uploaded_files.len()))
+        .extension("files", &uploaded_files)
+        .extension("folders", &created_folders)
+        .into())
+}
+
+/// The state of a raw upload once its body has been read.
+enum RawUpload {
+    /// The body fits into one part and is stored with a single request
+    Single(Bytes),
+    /// The body was uploaded in parts; the multipart upload still has to be completed
+    Multipart { upload_id: String, parts: Vec<UploadedPart> },
+}
+
+/// Why the body of a raw upload could not be stored.
+enum RawUploadError {
+    /// The body exceeds the bucket's maximum object size, or its content is not allowed
+    Rejected(PolicyViolation),
+    /// The body could not be read from the client
+    Payload(PayloadError),
+    /// The data could not be uploaded to S3
+    S3(S3Error),
+}
+
+/// Parses a `Content-MD5` header, which must be a base64-encoded 128 bit digest.
+pub(crate) fn parse_content_md5(value: &str) -> Option<String> {
+    let value = value.trim();
+    BASE64.decode(value).ok().filter(|digest| digest.len() == 16).map(|_| value.to_string())
+}
+
+/// Streams the body of a raw upload to S3.
+///
+/// Bodies up to one part are collected in memory; larger bodies are
+/// uploaded part by part as they arrive, so only one part is buffered.
+/// The content is checked against the upload policy before the first part
+/// is uploaded. If reading or uploading fails, a started multipart upload
+/// is aborted.
+///
+/// # Returns
+///
+/// * `Ok((RawUpload, u64))` - The uploaded body and its size in bytes
+/// * `Err(RawUploadError)` - If the body is not allowed, could not be read or uploaded
+async fn stream_raw_upload(
+    s3: &S3Service,
+    bucket: &str,
+    key: &str,
+    content_type: &str,
+    payload: &mut web::Payload,
+    policy: &UploadPolicy,
+    hasher: &mut Md5
+) -> Result<(RawUpload, u64), RawUploadError> {
+    let mut upload_id = None;
+    let mut parts = Vec::new();
+    let result = stream_raw_parts(s3, bucket, key, content_type, payload, policy, hasher, &mut upload_id, &mut parts).await;
+
+    match (result, upload_id) {
+        (Ok((buffer, size)), None) => Ok((RawUpload::Single(buffer), size)),
+        (Ok((_, size)), Some(upload_id)) => Ok((RawUpload::Multipart { upload_id, parts }, size)),
+        (Err(e), Some(upload_id)) => {
+            if let Err(abort_error) = s3.abort_multipart_upload(key, bucket, &upload_id).await {
+                warn!("Error aborting multipart upload of {}/{}: {}", bucket, key, abort_error);
+            }
+            Err(e)
+        },
+        (Err(e), None) => Err(e),
+    }
+}
+
+/// Reads the body of a raw upload, uploading a part whenever one is full.
+///
+/// Returns the data that was not uploaded as a part, which is the whole
+/// body if it never filled a part, and the total size of the body.
+#[allow(clippy::too_many_arguments)]
+async fn stream_raw_parts(
+    s3: &S3Service,
+    bucket: &str,
+    key: &str,
+    content_type: &str,
+    payload: &mut web::Payload,
+    policy: &UploadPolicy,
+    hasher: &mut Md5,
+    upload_id: &mut Option<String>,
+    parts: &mut Vec<UploadedPart>
+) -> Result<(Bytes, u64), RawUploadError> {
+    let filename = key.rsplit('/').next().unwrap_or(key);
+    let mut buffer = BytesMut::new();
+    let mut size = 0u64;
+    let mut content_checked = false;
+
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk.map_err(RawUploadError::Payload)?;
+        size += chunk.len() as u64;
+        if let Some(max) = policy.max_object_size.filter(|max| size > *max) {
+            return Err(RawUploadError::Rejected(PolicyViolation::TooLarge(max)));
+        }
+        hasher.update(&chunk);
+        buffer.extend_from_slice(&chunk);
+
+        // The first bytes are still in the buffer until the first part is uploaded
+        if !content_checked && buffer.len() >= content::SNIFF_LENGTH {
+            policy.check_content(filename, &buffer).map_err(RawUploadError::Rejected)?;
+            content_checked = true;
+        }
+
+        while buffer.len() as u64 >= DEFAULT_PART_SIZE {
+            let part = buffer.split_to(DEFAULT_PART_SIZE as usize).freeze();
+            let id = match upload_id {
+                Some(id) => id.clone(),
+                None => {
+                    let id = s3.create_multipart_upload(key, bucket, content_type).await.map_err(RawUploadError::S3)?;
+                    upload_id.insert(id).clone()
+                }
+            };
+            let part = s3.upload_part(key, bucket, &id, parts.len() as i32 + 1, part).await.map_err(RawUploadError::S3)?;
+            parts.push(part);
+        }
+    }
+
+    if !content_checked {
+        policy.check_content(filename, &buffer).map_err(RawUploadError::Rejected)?;
+    }
+
+    // The rest of a body uploaded in parts becomes its last part
+    if let Some(id) = upload_id.as_deref() && !buffer.is_empty() {
+        let part = s3.upload_part(key, bucket, id, parts.len() as i32 + 1, buffer.split().freeze())
+            .await
+            .map_err(RawUploadError::S3)?;
+        parts.push(part);
+    }
+
+    Ok((buffer.freeze(), size))
+}
+
+/// Uploads the raw request body as an object.
+///
+/// Unlike the multipart form upload, the body is the object itself, which
+/// makes this endpoint convenient for scripts, e.g.
+/// `curl -T report.pdf .../object/docs/report.pdf`. The body is streamed to S3;
+/// bodies larger than one part are stored as a multipart upload.
+///
+/// Uploaders, who may not replace objects, can only create objects: their
+/// requests without preconditions are treated as `If-None-Match: *`, and
+/// other preconditions need the `editor` role.
+///
+/// # Path Parameters
+///
+/// * `bucket` - The name of the bucket to upload to
+/// * `key` - The key (path) of the object
+///
+/// # Headers
+///
+/// * `Content-Type` - The content type to store; guessed from the key if missing
+/// * `Content-MD5` - Optional base64-encoded MD5 digest the body is verified against
+/// * `If-Match` - ETags, or `*`, the existing object must have for it to be replaced
+/// * `If-None-Match` - ETags the existing object must not have, or `*` to only create the object
+///
+/// # Returns
+///
+/// * `200 OK` - If the object was stored, with its ETag in the `ETag` header
+/// * `201 Created` - If the object was created with `If-None-Match: *`
+/// * `400 Bad Request` - If the key or a header is invalid, the body does not match `Content-MD5`, or the body could not be read
+/// * `403 Forbidden` - If the key points into the system prefix, is not allowed by the upload policy, or the client's role does not allow the write
+/// * `412 Precondition Failed` - If the existing object does not meet the preconditions
+/// * `413 Payload Too Large` - If the body exceeds the bucket's maximum object size
+/// * `415 Unsupported Media Type` - If the file type is not allowed in the bucket
+/// * `500 Internal Server Error` - If there was an error storing the object
+#[put("/bucket/{bucket}/object/{key:.*}")]
+pub async fn put_object_to_bucket(
+    path: web::Path<(String, String)>,
+    req: HttpRequest,
+    mut payload: web::Payload,
+    access: Access,
+    s3_service: web::Data<Arc<S3Service>>
+) -> Result<HttpResponse, Error> {
+    let (bucket, key) = path.into_inner();
+    let s3 = s3_service.as_ref();
+
+    if S3Service::is_system_key(&key) {
+        return Ok(system_key_forbidden(&key));
+    }
+    let key = match normalize_relative_path(&key) {
+        Ok(key) => key,
+        Err(reason) => {
+            return Err(ApiError::new(ErrorCode::InvalidKey, format!("Invalid key: {}", reason)).field("key").into());
+        }
+    };
+    if S3Service::is_system_key(&key) {
+        return Ok(system_key_forbidden(&key));
+    }
+
+    let headers = req.headers();
+    let content_md5 = match headers.get("Content-MD5").map(|v| v.to_str().ok().and_then(parse_content_md5)) {
+        None => None,
+        Some(Some(digest)) => Some(digest),
+        Some(None) => {
+            return Err(ApiError::new(ErrorCode::InvalidRequest, "Content-MD5 must be a base64-encoded MD5 digest")
+                .field("Content-MD5")
+                .into());
+        }
+    };
+    let mut preconditions = match request_preconditions(&req) {
+        Ok(preconditions) => preconditions,
+        Err(response) => return Ok(response),
+    };
+    if !access.allows(&bucket, &key, Permission::Delete) {
+        if preconditions.is_empty() {
+            preconditions.if_none_match = Some(vec!["*".to_string()]);
+        }
+        let permission = if preconditions.requires_absence() { Permission::Write } else { Permission::Delete };
+        require(&access, &bucket, &key, permission)?;
+    }
+
+    let filename = key.rsplit('/').next().unwrap_or(&key).to_string();
+    let content_type = headers.get("Content-Type")
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string())
+        .unwrap_or_else(|| mime_guess::from_path(&filename).first_or_octet_stream().essence_str().to_string());
+
+    let policy = match s3.get_upload_policy(&bucket).await {
+        Ok(policy) => policy,
+        Err(e) => {
+            error!("Error reading upload policy of bucket {}: {:?}", bucket, e);
+            return Err(e.into());
+        }
+    };
+    let declared_length = headers.get("Content-Length")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+    let check = policy.check_key(&key)
+        .and(policy.check_file(&filename, Some(&content_type)))
+        .and(match (policy.max_object_size, declared_length) {
+            (Some(max), Some(length)) if length > max => Err(PolicyViolation::TooLarge(max)),
+            _ => Ok(()),
+        });
+    if let Err(violation) = check {
+        return Ok(policy_violation_response(&violation, &[]));
+    }
+
+    let mut hasher = Md5::new();
+    let streamed = stream_raw_upload(s3, &bucket, &key, &content_type, &mut payload, &policy, &mut hasher).await;
+    let (upload, size) = match streamed {
+        Ok(streamed) => streamed,
+        Err(RawUploadError::Rejected(violation)) => {
+            return Ok(policy_violation_response(&violation, &[]));
+        },
+        Err(RawUploadError::Payload(e)) => {
+            warn!("Error reading body of upload to {}/{}: {}", bucket, key, e);
+            return Err(ApiError::new(ErrorCode::InvalidRequest, format!("Failed to read request body: {}", e)).into());
+        },
+        Err(RawUploadError::S3(e)) => {
+            error!("Error uploading file {}/{}: {:?}", bucket, key, e);
+            return Err(e.into());
+        }
+    };
+
+    // Multipart uploads are not verified by S3, so the digest is checked here for both kinds
+    if content_md5.as_ref().is_some_and(|expected| *expected != BASE64.encode(hasher.finalize())) {
+        if let RawUpload::Multipart { upload_id, .. } = &upload
+            && let Err(e) = s3.abort_multipart_upload(&key, &bucket, upload_id).await {
+            warn!("Error aborting multipart upload of {}/{}: {}", bucket, key, e);
+        }
+        return Err(ApiError::new(ErrorCode::ChecksumMismatch, "The body does not match its Content-MD5")
+            .field("Content-MD5")
+            .into());
+    }
+
+    let options = WriteOptions {
+        content_type: Some(content_type.clone()),
+        content_md5,
+        preconditions,
+    };
+    let stored = match &upload {
+        RawUpload::Single(data) => s3.put_object_with_options(&key, &bucket, data.clone(), &options).await,
+        RawUpload::Multipart { upload_id, parts } => {
+            let completed = s3.complete_multipart_upload_with_options(&key, &bucket, upload_id, parts, &options).await;
+            if !matches!(completed, Ok(Some(_)))
+                && let Err(e) = s3.abort_multipart_upload(&key, &bucket, upload_id).await {
+                warn!("Error aborting multipart upload of {}/{}: {}", bucket, key, e);
+            }
+            completed
+        }
+    };
+
+    match stored {
+        Ok(Some(etag)) => {
+            let created = options.preconditions.requires_absence();
+            let mut response = if created { HttpResponse::Created() } else { HttpResponse::Ok() };
+            Ok(response
+                .insert_header(("ETag", etag.clone()))
+                .json(json!({
+                    "message": "File uploaded successfully",
+                    "key": key,
+                    "bucket": bucket,
+                    "size": size,
+                    "content_type": content_type,
+                    "etag": etag
+                })))
+        },
+        Ok(None) => Ok(precondition_failed(&key, &bucket, &options.preconditions)),
+        Err(e) => {
+            error!("Error uploading file {}/{}: {:?}", bucket, key, e);
+            Err(e.into())
+        }
+    }
 }
 
 /// Deletes an object from a bucket.
@@ -261,30 +995,53 @@ pub async fn upload_object_to_bucket(
 /// * `bucket` - The name of the bucket containing the object
 /// * `key` - The key (path) of the object to delete
 ///
+/// # Headers
+///
+/// * `If-Match` - ETags, or `*`, the object must have to be deleted
+/// * `If-None-Match` - ETags the object must not have to be deleted
+///
 /// # Returns
 ///
 /// * `204 No Content` - If the object was deleted successfully
+/// * `400 Bad Request` - If a precondition header is invalid
+/// * `403 Forbidden` - If the client is not an editor of the object
 /// * `404 Not Found` - If the object does not exist
+/// * `412 Precondition Failed` - If the object does not meet the preconditions
 /// * `500 Internal Server Error` - If there was an error deleting the object
 #[delete("/bucket/{bucket}/object/{key:.*}")]
 pub async fn delete_object_from_bucket(
     path: web::Path<(String, String)>,
+    req: HttpRequest,
+    access: Access,
     s3_service: web::Data<Arc<S3Service>>
 ) -> Result<HttpResponse, Error> {
     let (bucket, key) = path.into_inner();
     let s3 = s3_service.as_ref();
     
-    match s3.delete_objects(vec![&key], &bucket).await {
-        Ok(_) => Ok(HttpResponse::Ok().json(json!({
+    if S3Service::is_system_key(&key) {
+        return Ok(system_key_forbidden(&key));
+    }
+    require(&access, &bucket, &key, Permission::Delete)?;
+    let preconditions = match request_preconditions(&req) {
+        Ok(preconditions) => preconditions,
+        Err(response) => return Ok(response),
+    };
+    
+    let deleted = if preconditions.is_empty() {
+        s3.delete_objects(vec![&key], &bucket).await.map(|_| true)
+    } else {
+        s3.delete_object_with_preconditions(&key, &bucket, &preconditions).await
+    };
+    match deleted {
+        Ok(false) => Ok(precondition_failed(&key, &bucket, &preconditions)),
+        Ok(true) => Ok(HttpResponse::Ok().json(json!({
             "message": "File deleted successfully",
             "key": key,
             "bucket": bucket
         }))),
         Err(e) => {
             error!("Error deleting file {}/{}: {:?}", bucket, key, e);
-            Ok(HttpResponse::InternalServerError().json(json!({
-                "error": format!("Failed to delete file: {}", e)
-            })))
+            Err(e.into())
         }
     }
 }
@@ -305,12 +1062,14 @@ pub async fn delete_object_from_bucket(
 ///
 /// * `201 Created` - If the folder was created successfully
 /// * `400 Bad Request` - If the folder name is invalid
+/// * `403 Forbidden` - If the client is not an uploader of the folder
 /// * `409 Conflict` - If the folder already exists
 /// * `500 Internal Server Error` - If there was an error creating the folder
-#[post("/bucket/{bucket}/folders")]
+#[post("/bucket/{bucket}/folders", wrap = "crate::api::idempotency::Idempotency")]
 pub async fn create_folder(
     bucket: web::Path<String>, 
     folder_info: web::Json<CreateFolderRequest>,
+    access: Access,
     s3_service: web::Data<Arc<S3Service>>
 ) -> Result<HttpResponse, Error> {
     let s3 = s3_service.as_ref();
@@ -320,6 +1079,11 @@ pub async fn create_folder(
         format!("{}/", folder_info.name)
     };
     
+    if S3Service::is_system_key(&folder_path) {
+        return Ok(system_key_forbidden(&folder_path));
+    }
+    require(&access, &bucket, &folder_path, Permission::Write)?;
+    
     match s3.put_object(&folder_path, vec![], &bucket).await {
         Ok(_) => Ok(HttpResponse::Ok().json(json!({
             "message": "Folder created successfully",
@@ -328,9 +1092,7 @@ pub async fn create_folder(
         }))),
         Err(e) => {
             error!("Error creating folder {}/{}: {:?}", bucket, folder_path, e);
-            Ok(HttpResponse::InternalServerError().json(json!({
-                "error": format!("Failed to create folder: {}", e)
-            })))
+            Err(e.into())
         }
     }
 }
@@ -339,119 +1101,210 @@ pub async fn create_folder(
 ///
 /// This endpoint retrieves the binary data of an object from the specified bucket
 /// and returns it with appropriate content type for viewing in a browser.
+/// The type guessed from the file extension is checked against the type
+/// detected from the object's first bytes; if they disagree, the object is
+/// served with the detected type as an attachment. HTML, SVG and XML objects
+/// are only rendered inline if `RUSTDOK_VIEW_INLINE_ACTIVE_CONTENT` is enabled.
+///
+/// If any transformation parameter is given, the object is treated as an
+/// image: it is cropped, rotated, resized and encoded in that order, and the
+/// result is cached until the object changes.
 ///
 /// # Path Parameters
 ///
 /// * `bucket` - The name of the bucket containing the object
 /// * `key` - The key (path) of the object to view
 ///
+/// # Query Parameters
+///
+/// * `w` - Optional target width in pixels, at most 4096
+/// * `h` - Optional target height in pixels, at most 4096
+/// * `crop` - Optional region to crop to, as `x,y,width,height`
+/// * `rotate` - Optional clockwise rotation: 90, 180 or 270
+/// * `format` - Optional output format: `jpeg`, `png` or `webp`
+/// * `quality` - Optional JPEG quality from 1 to 100, rejected for other output formats
+///
 /// # Returns
 ///
 /// * `200 OK` - The binary data of the object with appropriate content type
+/// * `400 Bad Request` - If the transformation parameters are invalid
+/// * `403 Forbidden` - If the client is not a viewer of the object
 /// * `404 Not Found` - If the object does not exist
+/// * `413 Payload Too Large` - If the image is too large to transform
+/// * `415 Unsupported Media Type` - If a transformation is requested for an object that is not a supported image
 /// * `500 Internal Server Error` - If there was an error retrieving the object
 #[get("/bucket/{bucket}/view/{key:.*}")]
 pub async fn view_object_from_bucket(
     path: web::Path<(String, String)>,
+    query: web::Query<ViewQuery>,
+    access: Access,
     s3_service: web::Data<Arc<S3Service>>
 ) -> Result<HttpResponse, Error> {
     let (bucket, key) = path.into_inner();
     let s3 = s3_service.as_ref();
     
+    if S3Service::is_system_key(&key) {
+        return Ok(system_key_forbidden(&key));
+    }
+    require(&access, &bucket, &key, Permission::Read)?;
+    
+    let transform = match query.to_transform() {
+        Ok(transform) => transform,
+        Err(e) => {
+            return Err(ApiError::new(ErrorCode::InvalidRequest, e.to_string()).into());
+        }
+    };
+    if !transform.is_empty() {
+        let variant = transform.cache_variant();
+        return cached_rendition(s3, &bucket, &key, &variant, move |data| transform.apply(&data)).await;
+    }
+    
     match s3.get_object(&key, &bucket).await {
         Ok(data) => {
             let filename = Path::new(&key).file_name().unwrap_or_default().to_string_lossy();
-            let content_type = mime_guess::from_path(&*filename).first_or_octet_stream();
+            let decision = content::decide(&filename, &data, content::inline_active_content_allowed());
+            if decision.mismatch {
+                warn!("Content of {}/{} does not match its extension, serving as {}", bucket, key, decision.content_type);
+            }
+            let disposition = if decision.inline { "inline" } else { "attachment" };
             
             Ok(HttpResponse::Ok()
-                .content_type(content_type.as_ref())
+                .content_type(decision.content_type)
+                .append_header(("X-Content-Type-Options", "nosniff"))
+                .append_header(("Content-Disposition", format!("{}; filename=\"{}\"", disposition, filename)))
                 .body(data))
         },
         Err(e) => {
             error!("Error viewing file {}/{}: {:?}", bucket, key, e);
-            Ok(HttpResponse::InternalServerError().json(json!({
-                "error": format!("Failed to view file: {}", e)
-            })))
+            Err(e.into())
         }
     }
 }
 
-#[post("/bucket/{bucket}/move")]
+/// Moves a file within a bucket by copying it to its destination and
+/// deleting the source.
+///
+/// The destination is only created unless preconditions say otherwise, and
+/// the source is only deleted while it is still the version that was copied.
+/// If the source changes after the copy, the copy is kept and the source is
+/// left in place.
+///
+/// # Path Parameters
+///
+/// * `bucket` - The name of the bucket
+///
+/// # Headers
+///
+/// * `If-Match` - ETags, or `*`, the existing destination must have
+/// * `If-None-Match` - ETags the existing destination must not have, or `*` if it must not exist
+///
+/// # Request Body
+///
+/// * `source_key` - The key of the file to move
+/// * `destination_key` - The key to move the file to
+///
+/// # Returns
+///
+/// * `200 OK` - If the file was moved
+/// * `400 Bad Request` - If a precondition header is invalid
+/// * `403 Forbidden` - If a key is a system key, or the client may not delete the source or write the destination
+/// * `404 Not Found` - If the source file does not exist
+/// * `409 Conflict` - If the destination file already exists and no preconditions were given
+/// * `412 Precondition Failed` - If the destination does not meet the preconditions, or the source changed during the move
+/// * `500 Internal Server Error` - If there was an error moving the file
+#[post("/bucket/{bucket}/move", wrap = "crate::api::idempotency::Idempotency")]
 pub async fn move_file_in_bucket(
     bucket: web::Path<String>,
     move_request: web::Json<MoveFileRequest>,
+    req: HttpRequest,
+    access: Access,
     s3_service: web::Data<Arc<S3Service>>
 ) -> Result<HttpResponse, Error> {
     let s3 = s3_service.as_ref();
-    
-    // Check if source file exists
-    match s3.check_object_exists(&move_request.source_key, &bucket).await {
-        Ok(false) => {
-            return Ok(HttpResponse::NotFound().json(json!({
-                "error": format!("Source file {} does not exist in bucket {}", move_request.source_key, bucket)
-            })));
-        },
-        Ok(true) => {},
-        Err(e) => {
-            error!("Error checking if source file exists: {:?}", e);
-            return Ok(HttpResponse::InternalServerError().json(json!({
-                "error": format!("Failed to check if source file exists: {}", e)
-            })));
+    let (source_key, destination_key) = (&move_request.source_key, &move_request.destination_key);
+    let preconditions = match request_preconditions(&req) {
+        Ok(preconditions) => preconditions,
+        Err(response) => return Ok(response),
+    };
+
+    for key in [source_key, destination_key] {
+        if S3Service::is_system_key(key) {
+            return Ok(system_key_forbidden(key));
         }
     }
-    
-    // Check if destination file already exists
-    match s3.check_object_exists(&move_request.destination_key, &bucket).await {
-        Ok(true) => {
-            return Ok(HttpResponse::Conflict().json(json!({
-                "error": format!("Destination file {} already exists in bucket {}", move_request.destination_key, bucket)
-            })));
+    // Moves that may replace the destination need the permission to delete it
+    let permission = if preconditions.is_empty() || preconditions.requires_absence() {
+        Permission::Write
+    } else {
+        Permission::Delete
+    };
+    require(&access, &bucket, source_key, Permission::Delete)?;
+    require(&access, &bucket, destination_key, permission)?;
+
+    let source_etag = match s3.head_object(source_key, &bucket).await {
+        Ok(Some(head)) => head.etag.unwrap_or_default(),
+        Ok(None) => {
+            return Err(ApiError::new(
+                ErrorCode::NotFound,
+                format!("Source file {} does not exist in bucket {}", source_key, bucket)
+            ).field("source_key").into());
         },
-        Ok(false) => {},
         Err(e) => {
-            error!("Error checking if destination file exists: {:?}", e);
-            return Ok(HttpResponse::InternalServerError().json(json!({
-                "error": format!("Failed to check if destination file exists: {}", e)
-            })));
+            error!("Error checking if source file exists: {:?}", e);
+            return Err(e.into());
         }
-    }
-    
-    // Copy the file to the new location
-    match s3.client.copy_object()
-        .bucket(&*bucket)
-        .copy_source(format!("{}/{}", &*bucket, &move_request.source_key))
-        .key(&move_request.destination_key)
-        .send()
-        .await {
-        Ok(_) => {
-            // Delete the source object
-            match s3.client.delete_object()
-                .bucket(&*bucket)
-                .key(&move_request.source_key)
-                .send()
-                .await {
-                Ok(_) => Ok(HttpResponse::Ok().json(json!({
-                    "message": "File moved successfully",
-                    "source": move_request.source_key,
-                    "destination": move_request.destination_key,
-                    "bucket": bucket.to_string()
-                }))),
+    };
+    let source_changed = || ApiError::new(
+        ErrorCode::PreconditionFailed,
+        format!("File {} in bucket {} changed while it was being moved", source_key, bucket)
+    ).field("source_key");
+
+    let destination_preconditions = if preconditions.is_empty() { Preconditions::create_only() } else { preconditions.clone() };
+    match s3.copy_object_with_preconditions(source_key, &source_etag, destination_key, &bucket, &destination_preconditions).await {
+        Ok(Some(_)) => {},
+        Ok(None) => {
+            // The copy fails the same way if the source changed in the meantime
+            return match s3.head_object(source_key, &bucket).await {
+                Ok(head) if head.as_ref().and_then(|head| head.etag.as_deref()) != Some(source_etag.as_str()) => {
+                    Err(source_changed().into())
+                },
+                Ok(_) if preconditions.is_empty() => Err(ApiError::new(
+                    ErrorCode::AlreadyExists,
+                    format!("Destination file {} already exists in bucket {}", destination_key, bucket)
+                ).field("destination_key").into()),
+                Ok(_) => Ok(precondition_failed(destination_key, &bucket, &preconditions)),
                 Err(e) => {
-                    error!("Error deleting source file after copy: {:?}", e);
-                    Ok(HttpResponse::InternalServerError().json(json!({
-                        "error": format!("File was copied but could not be deleted from source: {}", e),
-                        "source": move_request.source_key,
-                        "destination": move_request.destination_key,
-                        "bucket": bucket.to_string()
-                    })))
+                    error!("Error checking if source file changed: {:?}", e);
+                    Err(e.into())
                 }
-            }
+            };
         },
         Err(e) => {
             error!("Error copying file: {:?}", e);
-            Ok(HttpResponse::InternalServerError().json(json!({
-                "error": format!("Failed to copy file: {}", e)
-            })))
+            return Err(e.into());
+        }
+    }
+
+    let copied = Preconditions { if_match: Some(vec![Preconditions::normalize_strong_etag(&source_etag)]), if_none_match: None };
+    match s3.delete_object_with_preconditions(source_key, &bucket, &copied).await {
+        Ok(true) => Ok(HttpResponse::Ok().json(json!({
+            "message": "File moved successfully",
+            "source": source_key,
+            "destination": destination_key,
+            "bucket": bucket.to_string()
+        }))),
+        Ok(false) => Err(source_changed()
+            .extension("source", source_key)
+            .extension("destination", destination_key)
+            .extension("bucket", bucket.as_str())
+            .into()),
+        Err(e) => {
+            error!("Error deleting source file after copy: {:?}", e);
+            Err(ApiError::new(ErrorCode::StorageError, "File was copied but could not be deleted from source")
+                .extension("source", source_key)
+                .extension("destination", destination_key)
+                .extension("bucket", bucket.as_str())
+                .into())
         }
     }
-} 
\ No newline at end of file
+}
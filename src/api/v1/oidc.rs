@@ -0,0 +1,145 @@
+//! # OpenID Connect Login Endpoints
+//!
+//! This module provides the endpoints of the login through an OpenID
+//! Connect identity provider. A client sends the browser to
+//! `/auth/oidc/login`, which redirects it to the provider and keeps the
+//! login in a cookie; the provider sends it back to `/auth/oidc/callback`,
+//! which starts a session like a login to the web UI with a password and
+//! redirects the browser to the web UI. Both endpoints are public and
+//! answer `501 Not Implemented` if no provider or user database is
+//! configured.
+
+use std::sync::Arc;
+
+use actix_web::{get, web, Error, HttpRequest, HttpResponse, ResponseError};
+use log::{error, info, warn};
+use serde::Deserialize;
+
+use crate::api::error::{ApiError, ErrorCode};
+use crate::api::session::{cookie_settings, session_client, CookieSettings, OIDC_LOGIN_COOKIE};
+use crate::api::v1::grants::access_error;
+use crate::api::v1::users::{user_error, user_service};
+use crate::rdlib::access::authorizer::Authorizer;
+use crate::rdlib::oidc::error::OidcError;
+use crate::rdlib::oidc::service::{OidcService, PendingLogin};
+use crate::rdlib::users::service::UserService;
+
+/// Query parameters the identity provider sends the browser back with
+#[derive(Deserialize)]
+pub struct CallbackQuery {
+    /// The authorization code, if the login succeeded
+    code: Option<String>,
+    /// The state of the login
+    state: Option<String>,
+    /// Why the provider refused the login
+    error: Option<String>,
+    /// A description of the refusal for humans
+    error_description: Option<String>,
+}
+
+/// Gets the OpenID Connect service, if OpenID Connect login is enabled.
+///
+/// # Returns
+///
+/// The service, or a `501 Not Implemented` error if no identity provider is configured
+pub fn oidc_service(oidc: Option<web::Data<Arc<OidcService>>>) -> Result<web::Data<Arc<OidcService>>, ApiError> {
+    oidc.ok_or_else(|| ApiError::new(ErrorCode::NotEnabled, "OpenID Connect login is not enabled on this server"))
+}
+
+/// Converts an error of the OpenID Connect login, logging failures of the provider.
+pub fn oidc_error(context: &str, e: OidcError) -> Error {
+    if e.status_code().is_server_error() {
+        error!("Error {}: {:?}", context, e);
+    }
+    e.into()
+}
+
+/// Starts a login through the identity provider. The state of the login
+/// is kept in an `HttpOnly` cookie until the provider sends the browser
+/// back.
+///
+/// # Returns
+///
+/// * `302 Found` - A redirect to the authorization endpoint of the provider
+/// * `501 Not Implemented` - If no identity provider is configured
+#[get("/auth/oidc/login")]
+pub async fn oidc_login(
+    oidc: Option<web::Data<Arc<OidcService>>>,
+    settings: Option<web::Data<CookieSettings>>
+) -> Result<HttpResponse, Error> {
+    let oidc = oidc_service(oidc)?;
+    let settings = cookie_settings(settings);
+
+    let (url, login) = oidc.begin_login();
+    Ok(HttpResponse::Found()
+        .insert_header(("Location", url))
+        .insert_header(("Cache-Control", "no-store"))
+        .cookie(settings.oidc_login_cookie(&login.to_cookie_value()))
+        .finish())
+}
+
+/// Completes a login through the identity provider and starts a session
+/// for the web UI, as `/auth/login/session` does. The user is created at
+/// its first login if automatic provisioning is enabled, and its groups
+/// are set to the groups the provider names that exist in RustDok.
+///
+/// # Query Parameters
+///
+/// * `code` - The authorization code
+/// * `state` - The state of the login, which must match the login cookie
+/// * `error` - Why the provider refused the login, instead of a code
+///
+/// # Returns
+///
+/// * `302 Found` - A redirect to the web UI, setting the session and CSRF cookies
+/// * `400 Bad Request` - If the code or state is missing, or the login is not that of the browser or expired
+/// * `401 Unauthorized` - If the provider refused the login or its ID token is invalid
+/// * `403 Forbidden` - If the user is disabled
+/// * `409 Conflict` - If a user that did not log in through the provider has the username
+/// * `502 Bad Gateway` - If the provider failed
+/// * `501 Not Implemented` - If no identity provider or user database is configured
+#[get("/auth/oidc/callback")]
+pub async fn oidc_callback(
+    req: HttpRequest,
+    query: web::Query<CallbackQuery>,
+    oidc: Option<web::Data<Arc<OidcService>>>,
+    users: Option<web::Data<Arc<UserService>>>,
+    authorizer: Option<web::Data<Arc<Authorizer>>>,
+    settings: Option<web::Data<CookieSettings>>
+) -> Result<HttpResponse, Error> {
+    let oidc = oidc_service(oidc)?;
+    let users = user_service(users)?;
+    let settings = cookie_settings(settings);
+
+    let query = query.into_inner();
+    if let Some(reason) = query.error {
+        let reason = match query.error_description {
+            Some(description) => format!("{}: {}", reason, description),
+            None => reason,
+        };
+        warn!("The identity provider refused a login: {}", reason);
+        return Err(OidcError::Rejected(reason).into());
+    }
+    let code = query.code.ok_or_else(|| ApiError::new(ErrorCode::InvalidRequest, "The code is missing").field("code"))?;
+    let state = query.state.ok_or_else(|| ApiError::new(ErrorCode::InvalidRequest, "The state is missing").field("state"))?;
+    let pending = req.cookie(OIDC_LOGIN_COOKIE)
+        .and_then(|cookie| PendingLogin::from_cookie_value(cookie.value()))
+        .ok_or(OidcError::InvalidState)?;
+
+    let login = oidc.complete_login(&code, &state, &pending).await.map_err(|e| oidc_error("completing OpenID Connect login", e))?;
+    let (user, started) = users.start_external_session(&login.identity, oidc.config().auto_provision, session_client(&req)).await
+        .map_err(|e| user_error("logging in through OpenID Connect", e))?;
+    if let Some(authorizer) = authorizer {
+        authorizer.sync_groups(&user.username, &login.groups).await
+            .map_err(|e| access_error("synchronizing groups of OpenID Connect user", e))?;
+    }
+
+    info!("User '{}' started session {} through {}", user.username, started.session.id, login.identity.issuer);
+    Ok(HttpResponse::Found()
+        .insert_header(("Location", oidc.config().post_login_url.as_str()))
+        .insert_header(("Cache-Control", "no-store"))
+        .cookie(settings.session_cookie(&started.token))
+        .cookie(settings.csrf_cookie(&started.session.csrf_token))
+        .cookie(settings.oidc_login_removal_cookie())
+        .finish())
+}
@@ -0,0 +1,118 @@
+//! # Upload Policy API Endpoints
+//!
+//! This module provides the API endpoints for managing the upload policy of a bucket.
+//! The policy limits the size, content type, extension and key of uploaded objects.
+//! Everyone with a role in the bucket can read the policy; changing it
+//! needs the `admin` role on the bucket.
+
+use actix_web::{put, get, delete, web, HttpResponse, Error};
+use serde_json::json;
+use crate::api::access::{require, require_visible};
+use crate::api::error::{ApiError, ErrorCode};
+use crate::rdlib::access::authorizer::Access;
+use crate::rdlib::access::grant::Permission;
+use crate::rdlib::s3::service::S3Service;
+use crate::rdlib::s3::types::UploadPolicy;
+use log::error;
+use std::sync::Arc;
+
+/// Gets the upload policy of a bucket.
+///
+/// # Path Parameters
+///
+/// * `bucket` - The name of the bucket
+///
+/// # Returns
+///
+/// * `200 OK` - The upload policy; a policy without limits if none is set
+/// * `403 Forbidden` - If the client has no role in the bucket
+/// * `500 Internal Server Error` - If there was an error reading the policy
+#[get("/bucket/{bucket}/policy")]
+pub async fn get_upload_policy(
+    bucket: web::Path<String>,
+    access: Access,
+    s3_service: web::Data<Arc<S3Service>>
+) -> Result<HttpResponse, Error> {
+    let s3 = s3_service.as_ref();
+    require_visible(&access, &bucket, "")?;
+
+    match s3.get_upload_policy(&bucket).await {
+        Ok(policy) => Ok(HttpResponse::Ok().json(policy)),
+        Err(e) => {
+            error!("Error reading upload policy of bucket {}: {:?}", bucket, e);
+            Err(e.into())
+        }
+    }
+}
+
+/// Sets the upload policy of a bucket, replacing any existing policy.
+///
+/// # Path Parameters
+///
+/// * `bucket` - The name of the bucket
+///
+/// # Request Body
+///
+/// * The upload policy. Omitted fields mean no limit
+///
+/// # Returns
+///
+/// * `200 OK` - The stored upload policy
+/// * `400 Bad Request` - If the policy is invalid
+/// * `403 Forbidden` - If the client is not an admin of the bucket
+/// * `500 Internal Server Error` - If there was an error storing the policy
+#[put("/bucket/{bucket}/policy")]
+pub async fn put_upload_policy(
+    bucket: web::Path<String>,
+    policy: web::Json<UploadPolicy>,
+    access: Access,
+    s3_service: web::Data<Arc<S3Service>>
+) -> Result<HttpResponse, Error> {
+    let s3 = s3_service.as_ref();
+    require(&access, &bucket, "", Permission::Manage)?;
+    let policy = policy.into_inner().normalized();
+
+    if let Err(message) = policy.validate() {
+        return Err(ApiError::new(ErrorCode::InvalidPolicy, message).into());
+    }
+
+    match s3.put_upload_policy(&bucket, &policy).await {
+        Ok(_) => Ok(HttpResponse::Ok().json(policy)),
+        Err(e) => {
+            error!("Error storing upload policy of bucket {}: {:?}", bucket, e);
+            Err(e.into())
+        }
+    }
+}
+
+/// Removes the upload policy of a bucket.
+///
+/// # Path Parameters
+///
+/// * `bucket` - The name of the bucket
+///
+/// # Returns
+///
+/// * `200 OK` - If the policy was removed
+/// * `403 Forbidden` - If the client is not an admin of the bucket
+/// * `500 Internal Server Error` - If there was an error removing the policy
+#[delete("/bucket/{bucket}/policy")]
+pub async fn delete_upload_policy(
+    bucket: web::Path<String>,
+    access: Access,
+    s3_service: web::Data<Arc<S3Service>>
+) -> Result<HttpResponse, Error> {
+    let s3 = s3_service.as_ref();
+    require(&access, &bucket, "", Permission::Manage)?;
+
+    match s3.delete_upload_policy(&bucket).await {
+        Ok(_) => Ok(HttpResponse::Ok().json(json!({
+            "message": "Upload policy removed successfully",
+            "bucket": bucket.to_string()
+        }))),
+        Err(e) => {
+            error!("Error removing upload policy of bucket {}: {:?}", bucket, e);
+            Err(e.into())
+        }
+    }
+}
@@ -0,0 +1,189 @@
+//! # Image Preview API Endpoints
+//!
+//! This module provides the API endpoints for image thumbnails, and the
+//! rendition cache shared with image transformations on view. Renditions
+//! are generated on first request, cached under RustDok's hidden system
+//! prefix, and regenerated when the source object changes.
+
+use actix_web::{get, web, HttpResponse, Error, ResponseError};
+use serde::Deserialize;
+use crate::rdlib::imaging::{self, ImagingError, Rendition, MAX_SOURCE_SIZE, MAX_THUMBNAIL_SIZE, DEFAULT_THUMBNAIL_SIZE};
+use crate::rdlib::s3::service::S3Service;
+use crate::api::access::require;
+use crate::api::error::{ApiError, ErrorCode};
+use crate::rdlib::access::authorizer::Access;
+use crate::rdlib::access::grant::Permission;
+use crate::api::v1::objects::system_key_forbidden;
+use log::{error, info};
+use std::sync::Arc;
+
+/// Query parameters for thumbnails
+#[derive(Deserialize)]
+pub struct ThumbnailQuery {
+    /// The maximum width of the thumbnail in pixels
+    w: Option<u32>,
+    /// The maximum height of the thumbnail in pixels
+    h: Option<u32>,
+}
+
+/// Builds the response for images that could not be processed.
+fn imaging_error_response(e: &ImagingError) -> HttpResponse {
+    let code = match e {
+        ImagingError::SourceTooLarge(_) => ErrorCode::PayloadTooLarge,
+        ImagingError::Unsupported(_) => ErrorCode::UnsupportedMediaType,
+        ImagingError::InvalidRequest(_) => ErrorCode::InvalidRequest,
+        ImagingError::Encoding(_) => ErrorCode::InternalError,
+    };
+    ApiError::new(code, e.to_string()).error_response()
+}
+
+/// Builds the response for a rendition.
+fn rendition_response(rendition: Rendition) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(rendition.content_type)
+        .append_header(("X-Content-Type-Options", "nosniff"))
+        .body(rendition.data)
+}
+
+/// Serves a rendition of an object, generating and caching it if needed.
+///
+/// The cached rendition is used as long as it was derived from the current
+/// ETag of the source object. Otherwise the source is downloaded, `render`
+/// is run on a blocking thread, and the result is cached for later requests
+/// under the ETag of the downloaded data.
+///
+/// # Arguments
+///
+/// * `s3` - The S3 service
+/// * `bucket` - The name of the bucket containing the source object
+/// * `key` - The key of the source object
+/// * `variant` - The kind of rendition, used as part of the cache key
+/// * `render` - Produces the rendition from the source data
+///
+/// # Returns
+///
+/// The response carrying the rendition, or an error response
+pub(crate) async fn cached_rendition<F>(
+    s3: &S3Service,
+    bucket: &str,
+    key: &str,
+    variant: &str,
+    render: F
+) -> Result<HttpResponse, Error>
+where
+    F: FnOnce(Vec<u8>) -> Result<Rendition, ImagingError> + Send + 'static,
+{
+    let head = match s3.head_object(key, bucket).await {
+        Ok(Some(head)) => head,
+        Ok(None) => {
+            return Err(ApiError::new(ErrorCode::NotFound, format!("Object {} does not exist in bucket {}", key, bucket)).into());
+        },
+        Err(e) => {
+            error!("Error reading metadata of {}/{}: {:?}", bucket, key, e);
+            return Err(e.into());
+        }
+    };
+
+    if head.size > MAX_SOURCE_SIZE {
+        return Ok(imaging_error_response(&ImagingError::SourceTooLarge(head.size)));
+    }
+
+    // Without an ETag there is nothing to validate a cached rendition against
+    if let Some(etag) = head.etag.as_deref().filter(|etag| !etag.is_empty()) {
+        match s3.get_derived_object(bucket, key, variant, etag).await {
+            Ok(Some(rendition)) => return Ok(rendition_response(rendition)),
+            Ok(None) => {},
+            Err(e) => error!("Error reading cached rendition {} of {}/{}: {:?}", variant, bucket, key, e),
+        }
+    }
+
+    // The object may have changed since the HEAD request, so the rendition
+    // is cached under the ETag of the data it is rendered from
+    let (data, etag) = match s3.get_object_with_etag(key, bucket).await {
+        Ok((data, etag)) => (data, etag.unwrap_or_default()),
+        Err(e) => {
+            error!("Error reading {}/{}: {:?}", bucket, key, e);
+            return Err(e.into());
+        }
+    };
+    if data.len() as u64 > MAX_SOURCE_SIZE {
+        return Ok(imaging_error_response(&ImagingError::SourceTooLarge(data.len() as u64)));
+    }
+
+    let rendition = match web::block(move || render(data)).await {
+        Ok(Ok(rendition)) => rendition,
+        Ok(Err(e)) => return Ok(imaging_error_response(&e)),
+        Err(e) => {
+            error!("Error rendering {} of {}/{}: {:?}", variant, bucket, key, e);
+            return Err(ApiError::new(ErrorCode::InternalError, "Failed to process image").into());
+        }
+    };
+
+    if !etag.is_empty() {
+        match s3.put_derived_object(bucket, key, variant, &etag, &rendition).await {
+            Ok(_) => info!("Cached rendition {} of {}/{}", variant, bucket, key),
+            Err(e) => error!("Error caching rendition {} of {}/{}: {:?}", variant, bucket, key, e),
+        }
+    }
+
+    Ok(rendition_response(rendition))
+}
+
+/// Returns a thumbnail of an image.
+///
+/// The image is scaled down to fit within `w` x `h` pixels, keeping its
+/// aspect ratio. If only one dimension is given, it is used for both.
+/// Thumbnails are cached and regenerated when the image changes.
+///
+/// # Path Parameters
+///
+/// * `bucket` - The name of the bucket containing the image
+/// * `key` - The key (path) of the image
+///
+/// # Query Parameters
+///
+/// * `w` - Optional maximum width in pixels (default 256, at most 1024)
+/// * `h` - Optional maximum height in pixels (default 256, at most 1024)
+///
+/// # Returns
+///
+/// * `200 OK` - The thumbnail as JPEG, or as PNG for images with transparency
+/// * `400 Bad Request` - If the requested size is invalid
+/// * `403 Forbidden` - If the key points into the system prefix, or the client is not a viewer of the image
+/// * `404 Not Found` - If the image does not exist
+/// * `413 Payload Too Large` - If the image is too large to process
+/// * `415 Unsupported Media Type` - If the object is not a supported image
+/// * `500 Internal Server Error` - If there was an error creating the thumbnail
+#[get("/bucket/{bucket}/thumbnail/{key:.*}")]
+pub async fn get_thumbnail(
+    path: web::Path<(String, String)>,
+    query: web::Query<ThumbnailQuery>,
+    access: Access,
+    s3_service: web::Data<Arc<S3Service>>
+) -> Result<HttpResponse, Error> {
+    let (bucket, key) = path.into_inner();
+
+    if S3Service::is_system_key(&key) {
+        return Ok(system_key_forbidden(&key));
+    }
+    require(&access, &bucket, &key, Permission::Read)?;
+
+    let (width, height) = match (query.w, query.h) {
+        (None, None) => (DEFAULT_THUMBNAIL_SIZE, DEFAULT_THUMBNAIL_SIZE),
+        (Some(w), None) => (w, w),
+        (None, Some(h)) => (h, h),
+        (Some(w), Some(h)) => (w, h),
+    };
+    if !(1..=MAX_THUMBNAIL_SIZE).contains(&width) || !(1..=MAX_THUMBNAIL_SIZE).contains(&height) {
+        let field = if (1..=MAX_THUMBNAIL_SIZE).contains(&width) { "h" } else { "w" };
+        return Err(ApiError::new(
+            ErrorCode::InvalidRequest,
+            format!("Thumbnail size must be between 1 and {} pixels", MAX_THUMBNAIL_SIZE)
+        ).field(field).into());
+    }
+
+    let variant = format!("thumbnails/{}x{}", width, height);
+    cached_rendition(s3_service.as_ref(), &bucket, &key, &variant, move |data| {
+        imaging::thumbnail(&data, width, height)
+    }).await
+}
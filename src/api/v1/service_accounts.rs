@@ -0,0 +1,298 @@
+//! # Service Account API Endpoints
+//!
+//! This module provides the API endpoints for managing service accounts
+//! and their API keys. They are reserved for administrators and answer
+//! `501 Not Implemented` if no user database is configured.
+//!
+//! Roles are granted to a service account under its subject `sa:<name>`,
+//! like to any user. The secret of a key is only returned when the key is
+//! created or rotated.
+
+use std::sync::Arc;
+
+use actix_web::{delete, get, post, web, Error, HttpResponse, ResponseError};
+use log::{error, info};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::api::auth::require_admin;
+use crate::api::error::{ApiError, ErrorCode};
+use crate::api::v1::grants::access_error;
+use crate::models::apikeys::{CreateApiKeyRequest, CreateServiceAccountRequest, CreatedApiKey};
+use crate::rdlib::access::authorizer::Authorizer;
+use crate::rdlib::apikeys::error::ApiKeyError;
+use crate::rdlib::apikeys::service::ApiKeyService;
+use crate::rdlib::auth::principal::Principal;
+
+/// Query parameters for rotating an API key
+#[derive(Deserialize)]
+pub struct RotateQuery {
+    /// How many seconds the replaced key keeps working; it is revoked at once unless set
+    #[serde(default)]
+    grace_seconds: u64,
+}
+
+/// Gets the API key service, if API keys are enabled.
+///
+/// # Returns
+///
+/// The service, or a `501 Not Implemented` error if no user database is configured
+pub fn api_key_service(api_keys: Option<web::Data<Arc<ApiKeyService>>>) -> Result<web::Data<Arc<ApiKeyService>>, ApiError> {
+    api_keys.ok_or_else(|| ApiError::new(ErrorCode::NotEnabled, "API keys are not enabled on this server"))
+}
+
+/// Converts an error of service accounts and API keys, logging server-side failures.
+pub fn api_key_error(context: &str, e: ApiKeyError) -> Error {
+    if e.status_code().is_server_error() {
+        error!("Error {}: {:?}", context, e);
+    }
+    e.into()
+}
+
+/// Lists all service accounts.
+///
+/// # Returns
+///
+/// * `200 OK` - A JSON array of service accounts
+/// * `403 Forbidden` - If the client is not an administrator
+#[get("/admin/service-accounts")]
+pub async fn list_service_accounts(
+    principal: Principal,
+    api_keys: Option<web::Data<Arc<ApiKeyService>>>
+) -> Result<HttpResponse, Error> {
+    require_admin(&principal)?;
+    let api_keys = api_key_service(api_keys)?;
+
+    match api_keys.list_accounts().await {
+        Ok(accounts) => Ok(HttpResponse::Ok().json(accounts)),
+        Err(e) => Err(api_key_error("listing service accounts", e)),
+    }
+}
+
+/// Creates a service account.
+///
+/// # Request Body
+///
+/// * `name` - The name of the service account
+/// * `description` - Optional description of the service account
+///
+/// # Returns
+///
+/// * `201 Created` - The created service account
+/// * `400 Bad Request` - If the name or description is not acceptable
+/// * `403 Forbidden` - If the client is not an administrator
+/// * `409 Conflict` - If the name is in use
+#[post("/admin/service-accounts", wrap = "crate::api::idempotency::Idempotency")]
+pub async fn create_service_account(
+    principal: Principal,
+    request: web::Json<CreateServiceAccountRequest>,
+    api_keys: Option<web::Data<Arc<ApiKeyService>>>
+) -> Result<HttpResponse, Error> {
+    require_admin(&principal)?;
+    let api_keys = api_key_service(api_keys)?;
+
+    match api_keys.create_account(&request.name, &request.description).await {
+        Ok(account) => {
+            info!("User '{}' created service account '{}'", principal.subject, account.name);
+            Ok(HttpResponse::Created().json(account))
+        },
+        Err(e) => Err(api_key_error("creating service account", e)),
+    }
+}
+
+/// Shows a service account.
+///
+/// # Path Parameters
+///
+/// * `id` - The ID of the service account
+///
+/// # Returns
+///
+/// * `200 OK` - The service account
+/// * `403 Forbidden` - If the client is not an administrator
+/// * `404 Not Found` - If the service account does not exist
+#[get("/admin/service-accounts/{id}")]
+pub async fn get_service_account(
+    principal: Principal,
+    id: web::Path<u64>,
+    api_keys: Option<web::Data<Arc<ApiKeyService>>>
+) -> Result<HttpResponse, Error> {
+    require_admin(&principal)?;
+    let api_keys = api_key_service(api_keys)?;
+
+    match api_keys.get_account(*id).await {
+        Ok(account) => Ok(HttpResponse::Ok().json(account)),
+        Err(e) => Err(api_key_error("reading service account", e)),
+    }
+}
+
+/// Deletes a service account with its keys and revokes its grants.
+///
+/// # Path Parameters
+///
+/// * `id` - The ID of the service account
+///
+/// # Returns
+///
+/// * `200 OK` - If the service account was deleted
+/// * `403 Forbidden` - If the client is not an administrator
+/// * `404 Not Found` - If the service account does not exist
+#[delete("/admin/service-accounts/{id}")]
+pub async fn delete_service_account(
+    principal: Principal,
+    id: web::Path<u64>,
+    api_keys: Option<web::Data<Arc<ApiKeyService>>>,
+    authorizer: Option<web::Data<Arc<Authorizer>>>
+) -> Result<HttpResponse, Error> {
+    require_admin(&principal)?;
+    let api_keys = api_key_service(api_keys)?;
+
+    match api_keys.delete_account(*id).await {
+        Ok(account) => {
+            info!("User '{}' deleted service account '{}'", principal.subject, account.name);
+            // A new service account of the same name must not inherit the roles and groups
+            if let Some(authorizer) = authorizer {
+                authorizer.forget_user(&account.subject).await
+                    .map_err(|e| access_error("revoking grants of deleted service account", e))?;
+            }
+            Ok(HttpResponse::Ok().json(json!({
+                "message": format!("Service account {} deleted successfully", id)
+            })))
+        },
+        Err(e) => Err(api_key_error("deleting service account", e)),
+    }
+}
+
+/// Lists the API keys of a service account, without their secrets.
+///
+/// # Path Parameters
+///
+/// * `id` - The ID of the service account
+///
+/// # Returns
+///
+/// * `200 OK` - A JSON array of keys
+/// * `403 Forbidden` - If the client is not an administrator
+/// * `404 Not Found` - If the service account does not exist
+#[get("/admin/service-accounts/{id}/keys")]
+pub async fn list_api_keys(
+    principal: Principal,
+    id: web::Path<u64>,
+    api_keys: Option<web::Data<Arc<ApiKeyService>>>
+) -> Result<HttpResponse, Error> {
+    require_admin(&principal)?;
+    let api_keys = api_key_service(api_keys)?;
+
+    match api_keys.list_keys(*id).await {
+        Ok(keys) => Ok(HttpResponse::Ok().json(keys)),
+        Err(e) => Err(api_key_error("listing API keys", e)),
+    }
+}
+
+/// Creates an API key for a service account.
+///
+/// # Path Parameters
+///
+/// * `id` - The ID of the service account
+///
+/// # Request Body
+///
+/// * `name` - What the key is used for
+/// * `scopes` - Optional limits of the key: buckets, prefixes and the highest role
+/// * `expires_at` - Optional time the key stops working
+///
+/// # Returns
+///
+/// * `201 Created` - The key with its secret, which is not shown again
+/// * `400 Bad Request` - If the name, scopes or expiry are not acceptable
+/// * `403 Forbidden` - If the client is not an administrator
+/// * `404 Not Found` - If the service account does not exist
+#[post("/admin/service-accounts/{id}/keys")]
+pub async fn create_api_key(
+    principal: Principal,
+    id: web::Path<u64>,
+    request: web::Json<CreateApiKeyRequest>,
+    api_keys: Option<web::Data<Arc<ApiKeyService>>>
+) -> Result<HttpResponse, Error> {
+    require_admin(&principal)?;
+    let api_keys = api_key_service(api_keys)?;
+
+    let request = request.into_inner();
+    match api_keys.create_key(*id, &request.name, request.scopes, request.expires_at).await {
+        Ok((api_key, key)) => {
+            info!("User '{}' created API key {} for service account {}", principal.subject, api_key.id, id);
+            Ok(HttpResponse::Created().json(CreatedApiKey { api_key, key }))
+        },
+        Err(e) => Err(api_key_error("creating API key", e)),
+    }
+}
+
+/// Revokes an API key.
+///
+/// # Path Parameters
+///
+/// * `id` - The ID of the service account
+/// * `key_id` - The ID of the key
+///
+/// # Returns
+///
+/// * `200 OK` - If the key was revoked
+/// * `403 Forbidden` - If the client is not an administrator
+/// * `404 Not Found` - If the service account or key does not exist
+#[delete("/admin/service-accounts/{id}/keys/{key_id}")]
+pub async fn revoke_api_key(
+    principal: Principal,
+    path: web::Path<(u64, u64)>,
+    api_keys: Option<web::Data<Arc<ApiKeyService>>>
+) -> Result<HttpResponse, Error> {
+    require_admin(&principal)?;
+    let api_keys = api_key_service(api_keys)?;
+
+    let (id, key_id) = path.into_inner();
+    match api_keys.revoke_key(id, key_id).await {
+        Ok(_) => {
+            info!("User '{}' revoked API key {} of service account {}", principal.subject, key_id, id);
+            Ok(HttpResponse::Ok().json(json!({
+                "message": format!("API key {} revoked successfully", key_id)
+            })))
+        },
+        Err(e) => Err(api_key_error("revoking API key", e)),
+    }
+}
+
+/// Replaces an API key with a new one of the same name, scopes and expiry.
+///
+/// # Path Parameters
+///
+/// * `id` - The ID of the service account
+/// * `key_id` - The ID of the key
+///
+/// # Query Parameters
+///
+/// * `grace_seconds` - How long the replaced key keeps working, at most 7 days; it is revoked at once unless set
+///
+/// # Returns
+///
+/// * `201 Created` - The new key with its secret, which is not shown again
+/// * `400 Bad Request` - If the grace period is too long
+/// * `403 Forbidden` - If the client is not an administrator
+/// * `404 Not Found` - If the service account or key does not exist
+#[post("/admin/service-accounts/{id}/keys/{key_id}/rotate")]
+pub async fn rotate_api_key(
+    principal: Principal,
+    path: web::Path<(u64, u64)>,
+    query: web::Query<RotateQuery>,
+    api_keys: Option<web::Data<Arc<ApiKeyService>>>
+) -> Result<HttpResponse, Error> {
+    require_admin(&principal)?;
+    let api_keys = api_key_service(api_keys)?;
+
+    let (id, key_id) = path.into_inner();
+    match api_keys.rotate_key(id, key_id, query.grace_seconds).await {
+        Ok((api_key, key)) => {
+            info!("User '{}' rotated API key {} of service account {} to {}", principal.subject, key_id, id, api_key.id);
+            Ok(HttpResponse::Created().json(CreatedApiKey { api_key, key }))
+        },
+        Err(e) => Err(api_key_error("rotating API key", e)),
+    }
+}
@@ -0,0 +1,156 @@
+//! # Session API Endpoints
+//!
+//! This module provides the API endpoints for logging in to the web UI
+//! with a session cookie instead of tokens, logging out, and listing and
+//! revoking the sessions of the authenticated user. Sessions need a
+//! configured user database.
+
+use std::sync::Arc;
+
+use actix_web::{delete, get, post, web, Error, HttpRequest, HttpResponse};
+use log::info;
+use serde_json::json;
+
+use crate::api::error::{ApiError, ErrorCode};
+use crate::api::session::{check_csrf, cookie_settings, session_client, CookieSettings, SESSION_COOKIE};
+use crate::api::v1::users::{user_error, user_service};
+use crate::models::users::LoginRequest;
+use crate::rdlib::auth::principal::Principal;
+use crate::rdlib::users::service::UserService;
+
+/// Gets the ID of the user account of the principal. Only the `uid` claim
+/// of tokens RustDok issued itself names a user.
+///
+/// # Returns
+///
+/// The ID, or a `403 Forbidden` error for clients that are no user, such as
+/// service accounts and clients with tokens of other issuers
+fn own_user_id(principal: &Principal) -> Result<u64, ApiError> {
+    principal.claims.get("uid").and_then(|uid| uid.as_u64())
+        .filter(|_| principal.local)
+        .ok_or_else(|| ApiError::new(ErrorCode::Forbidden, "Only users have sessions"))
+}
+
+/// Logs in to the web UI with a username and password, starting a
+/// session. The session token is set in an `HttpOnly` cookie, and the
+/// CSRF token in a cookie the UI reads and sends back in the
+/// `X-CSRF-Token` header of requests that change something.
+///
+/// # Request Body
+///
+/// * `username` - The name of the user
+/// * `password` - The password of the user
+/// * `otp` - The code from the authenticator app, or a recovery code, if the user has two-factor authentication enabled
+///
+/// # Returns
+///
+/// * `200 OK` - The user, the CSRF token and when the session ends unless it is used
+/// * `401 Unauthorized` - If the username, password or two-factor code is wrong or the code is missing
+/// * `403 Forbidden` - If the user is disabled, or its role requires two-factor authentication it has not set up; the
+///   problem document then carries a `setup_token` for `/auth/2fa/setup`
+/// * `429 Too Many Requests` - If too many invalid two-factor codes were entered for the user
+/// * `501 Not Implemented` - If no user database is configured
+#[post("/auth/login/session")]
+pub async fn login_session(
+    req: HttpRequest,
+    request: web::Json<LoginRequest>,
+    users: Option<web::Data<Arc<UserService>>>,
+    settings: Option<web::Data<CookieSettings>>
+) -> Result<HttpResponse, Error> {
+    let users = user_service(users)?;
+    let settings = cookie_settings(settings);
+
+    match users.start_session(&request.username, &request.password, request.otp.as_deref(), session_client(&req)).await {
+        Ok((user, started)) => {
+            info!("User '{}' started session {}", user.username, started.session.id);
+            Ok(HttpResponse::Ok()
+                .insert_header(("Cache-Control", "no-store"))
+                .cookie(settings.session_cookie(&started.token))
+                .cookie(settings.csrf_cookie(&started.session.csrf_token))
+                .json(json!({
+                    "user": user,
+                    "csrf_token": started.session.csrf_token,
+                    "expires_at": started.session.expires_at,
+                })))
+        },
+        Err(e) => Err(user_error("starting a session", e)),
+    }
+}
+
+/// Ends the session of the request and removes its cookies. Needs the
+/// `X-CSRF-Token` header, so other sites cannot log the user out.
+///
+/// # Returns
+///
+/// * `204 No Content` - The session is ended, or was not valid
+/// * `403 Forbidden` - If the `X-CSRF-Token` header does not match the CSRF cookie
+/// * `501 Not Implemented` - If no user database is configured
+#[post("/auth/logout/session")]
+pub async fn logout_session(
+    req: HttpRequest,
+    users: Option<web::Data<Arc<UserService>>>,
+    settings: Option<web::Data<CookieSettings>>
+) -> Result<HttpResponse, Error> {
+    let users = user_service(users)?;
+    let settings = cookie_settings(settings);
+
+    if let Some(cookie) = req.cookie(SESSION_COOKIE) {
+        check_csrf(&req, None)?;
+        users.end_session(cookie.value()).await.map_err(|e| user_error("ending a session", e))?;
+    }
+
+    let [session_cookie, csrf_cookie] = settings.removal_cookies();
+    Ok(HttpResponse::NoContent().cookie(session_cookie).cookie(csrf_cookie).finish())
+}
+
+/// Lists the web UI sessions of the authenticated user.
+///
+/// # Returns
+///
+/// * `200 OK` - A JSON array of sessions, with the browser and IP address they were started from
+/// * `403 Forbidden` - If the client is not a user
+/// * `501 Not Implemented` - If no user database is configured
+#[get("/auth/sessions")]
+pub async fn list_sessions(
+    principal: Principal,
+    users: Option<web::Data<Arc<UserService>>>
+) -> Result<HttpResponse, Error> {
+    let user_id = own_user_id(&principal)?;
+    let users = user_service(users)?;
+
+    match users.list_sessions(user_id).await {
+        Ok(sessions) => Ok(HttpResponse::Ok().json(sessions)),
+        Err(e) => Err(user_error("listing sessions", e)),
+    }
+}
+
+/// Revokes a web UI session of the authenticated user, such as one left
+/// open on another computer.
+///
+/// # Path Parameters
+///
+/// * `id` - The ID of the session
+///
+/// # Returns
+///
+/// * `204 No Content` - The session is revoked
+/// * `403 Forbidden` - If the client is not a user
+/// * `404 Not Found` - If the user has no such session
+/// * `501 Not Implemented` - If no user database is configured
+#[delete("/auth/sessions/{id}")]
+pub async fn revoke_session(
+    principal: Principal,
+    id: web::Path<u64>,
+    users: Option<web::Data<Arc<UserService>>>
+) -> Result<HttpResponse, Error> {
+    let user_id = own_user_id(&principal)?;
+    let users = user_service(users)?;
+
+    match users.revoke_session(user_id, *id).await {
+        Ok(()) => {
+            info!("User '{}' revoked session {}", principal.subject, id);
+            Ok(HttpResponse::NoContent().finish())
+        },
+        Err(e) => Err(user_error("revoking a session", e)),
+    }
+}
@@ -0,0 +1,211 @@
+//! # Two-Factor Authentication API Endpoints
+//!
+//! This module provides the API endpoints for users who log in with a
+//! password to set up, enable and disable TOTP two-factor authentication
+//! and to replace their recovery codes. A second factor is set up with the
+//! short-lived setup token a login hands out when the role of the user
+//! requires one, so an administrator can set it up before being able to
+//! log in; other users get a setup token with their password. Disabling
+//! the second factor and replacing recovery codes take the password and a
+//! code.
+//!
+//! The endpoints are public, so each client may only send a few requests
+//! a minute to them.
+
+use std::sync::Arc;
+
+use actix_web::{post, web, Error, HttpRequest, HttpResponse};
+use log::info;
+use serde_json::json;
+
+use crate::api::error::{ApiError, ErrorCode};
+use crate::api::session::client_ip;
+use crate::api::v1::users::{user_error, user_service};
+use crate::models::users::{TwoFactorRequest, TwoFactorSetupRequest};
+use crate::rdlib::users::service::UserService;
+
+/// Gets the code of a request that needs one.
+fn required_otp(otp: Option<&str>) -> Result<&str, ApiError> {
+    otp.filter(|otp| !otp.trim().is_empty())
+        .ok_or_else(|| ApiError::new(ErrorCode::InvalidRequest, "A code from the authenticator app is required").field("otp"))
+}
+
+/// Gets the user service and counts the request against its client.
+///
+/// # Returns
+///
+/// The service, a `429 Too Many Requests` error if the client sent too
+/// many requests recently, or a `501 Not Implemented` error if no user
+/// database is configured
+fn throttled_user_service(
+    req: &HttpRequest,
+    users: Option<web::Data<Arc<UserService>>>
+) -> Result<web::Data<Arc<UserService>>, Error> {
+    let users = user_service(users)?;
+    users.throttle_client(client_ip(req).as_deref()).map_err(|e| user_error("throttling two-factor requests", e))?;
+    Ok(users)
+}
+
+/// Hands out a setup token to a user who wants to set up two-factor
+/// authentication. Users whose role requires it get one from their login.
+///
+/// # Request Body
+///
+/// * `username` - The name of the user
+/// * `password` - The password of the user
+///
+/// # Returns
+///
+/// * `200 OK` - The setup token, valid for ten minutes
+/// * `401 Unauthorized` - If the username or password is wrong
+/// * `403 Forbidden` - If the user is disabled
+/// * `409 Conflict` - If two-factor authentication is already enabled
+/// * `429 Too Many Requests` - If the client sent too many requests recently
+/// * `501 Not Implemented` - If no user database is configured
+#[post("/auth/2fa/setup-token")]
+pub async fn two_factor_setup_token(
+    req: HttpRequest,
+    request: web::Json<TwoFactorRequest>,
+    users: Option<web::Data<Arc<UserService>>>
+) -> Result<HttpResponse, Error> {
+    let users = throttled_user_service(&req, users)?;
+
+    match users.two_factor_setup_token(&request.username, &request.password).await {
+        Ok(setup_token) => Ok(HttpResponse::Ok().insert_header(("Cache-Control", "no-store")).json(json!({
+            "setup_token": setup_token
+        }))),
+        Err(e) => Err(user_error("handing out a two-factor setup token", e)),
+    }
+}
+
+/// Creates a TOTP secret for the user. Two-factor authentication is not
+/// enabled until the user confirms a code with `/auth/2fa/enable`.
+///
+/// # Request Body
+///
+/// * `setup_token` - The setup token handed out by the login or `/auth/2fa/setup-token`
+///
+/// # Returns
+///
+/// * `200 OK` - The secret and its `otpauth://` URI, to be shown as QR code
+/// * `401 Unauthorized` - If the setup token is invalid or expired
+/// * `403 Forbidden` - If the user is disabled
+/// * `409 Conflict` - If two-factor authentication is already enabled
+/// * `429 Too Many Requests` - If the client sent too many requests recently
+/// * `501 Not Implemented` - If no user database is configured
+#[post("/auth/2fa/setup")]
+pub async fn setup_two_factor(
+    req: HttpRequest,
+    request: web::Json<TwoFactorSetupRequest>,
+    users: Option<web::Data<Arc<UserService>>>
+) -> Result<HttpResponse, Error> {
+    let users = throttled_user_service(&req, users)?;
+
+    match users.setup_two_factor(&request.setup_token).await {
+        Ok(setup) => Ok(HttpResponse::Ok().insert_header(("Cache-Control", "no-store")).json(setup)),
+        Err(e) => Err(user_error("setting up two-factor authentication", e)),
+    }
+}
+
+/// Enables two-factor authentication for the user, using up the setup token.
+///
+/// # Request Body
+///
+/// * `setup_token` - The setup token the secret was created with
+/// * `otp` - The current code from the authenticator app
+///
+/// # Returns
+///
+/// * `200 OK` - The recovery codes of the user, which are shown only once
+/// * `400 Bad Request` - If the code is missing
+/// * `401 Unauthorized` - If the setup token is invalid or expired, or the code is wrong
+/// * `403 Forbidden` - If the user is disabled
+/// * `409 Conflict` - If two-factor authentication is already enabled or has not been set up
+/// * `429 Too Many Requests` - If too many invalid codes were entered for the user, or the client sent too many requests
+/// * `501 Not Implemented` - If no user database is configured
+#[post("/auth/2fa/enable")]
+pub async fn enable_two_factor(
+    req: HttpRequest,
+    request: web::Json<TwoFactorSetupRequest>,
+    users: Option<web::Data<Arc<UserService>>>
+) -> Result<HttpResponse, Error> {
+    let users = throttled_user_service(&req, users)?;
+    let otp = required_otp(request.otp.as_deref())?;
+
+    match users.enable_two_factor(&request.setup_token, otp).await {
+        Ok(recovery_codes) => Ok(HttpResponse::Ok().insert_header(("Cache-Control", "no-store")).json(json!({
+            "recovery_codes": recovery_codes
+        }))),
+        Err(e) => Err(user_error("enabling two-factor authentication", e)),
+    }
+}
+
+/// Disables two-factor authentication for the user, unless its role
+/// requires it.
+///
+/// # Request Body
+///
+/// * `username` - The name of the user
+/// * `password` - The password of the user
+/// * `otp` - The current code from the authenticator app, or a recovery code
+///
+/// # Returns
+///
+/// * `204 No Content` - Two-factor authentication is disabled
+/// * `400 Bad Request` - If the code is missing
+/// * `401 Unauthorized` - If the username, password or code is wrong
+/// * `403 Forbidden` - If the user is disabled, or its role requires two-factor authentication
+/// * `409 Conflict` - If two-factor authentication is not enabled
+/// * `429 Too Many Requests` - If too many invalid codes were entered for the user, or the client sent too many requests
+/// * `501 Not Implemented` - If no user database is configured
+#[post("/auth/2fa/disable")]
+pub async fn disable_two_factor(
+    req: HttpRequest,
+    request: web::Json<TwoFactorRequest>,
+    users: Option<web::Data<Arc<UserService>>>
+) -> Result<HttpResponse, Error> {
+    let users = throttled_user_service(&req, users)?;
+    let otp = required_otp(request.otp.as_deref())?;
+
+    match users.disable_two_factor(&request.username, &request.password, otp).await {
+        Ok(()) => {
+            info!("User '{}' disabled two-factor authentication", request.username);
+            Ok(HttpResponse::NoContent().finish())
+        },
+        Err(e) => Err(user_error("disabling two-factor authentication", e)),
+    }
+}
+
+/// Replaces the recovery codes of the user; the old codes stop working.
+///
+/// # Request Body
+///
+/// * `username` - The name of the user
+/// * `password` - The password of the user
+/// * `otp` - The current code from the authenticator app, or a recovery code
+///
+/// # Returns
+///
+/// * `200 OK` - The new recovery codes, which are shown only once
+/// * `400 Bad Request` - If the code is missing
+/// * `401 Unauthorized` - If the username, password or code is wrong
+/// * `403 Forbidden` - If the user is disabled
+/// * `409 Conflict` - If two-factor authentication is not enabled
+/// * `429 Too Many Requests` - If too many invalid codes were entered for the user, or the client sent too many requests
+/// * `501 Not Implemented` - If no user database is configured
+#[post("/auth/2fa/recovery-codes")]
+pub async fn regenerate_recovery_codes(
+    req: HttpRequest,
+    request: web::Json<TwoFactorRequest>,
+    users: Option<web::Data<Arc<UserService>>>
+) -> Result<HttpResponse, Error> {
+    let users = throttled_user_service(&req, users)?;
+    let otp = required_otp(request.otp.as_deref())?;
+
+    match users.regenerate_recovery_codes(&request.username, &request.password, otp).await {
+        Ok(recovery_codes) => Ok(HttpResponse::Ok().insert_header(("Cache-Control", "no-store")).json(json!({
+            "recovery_codes": recovery_codes
+        }))),
+        Err(e) => Err(user_error("replacing recovery codes", e)),
+    }
+}
@@ -0,0 +1,710 @@
+//! # Resumable Upload API Endpoints
+//!
+//! This module implements the tus 1.0 resumable upload protocol with the
+//! creation, termination and expiration extensions. Uploads are backed by
+//! S3 multipart uploads and their state is stored in the target bucket, so
+//! a client can resume an interrupted upload from the last received byte,
+//! even after a server restart. See <https://tus.io/protocols/resumable-upload>.
+
+use actix_web::{delete, patch, post, route, web, Error, HttpRequest, HttpResponse, HttpResponseBuilder, ResponseError};
+use actix_web::http::StatusCode;
+use actix_web::http::header::{HeaderName, HeaderValue};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use bytes::{Bytes, BytesMut};
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use log::{error, info, warn};
+use crate::rdlib::s3::error::S3Error;
+use crate::rdlib::s3::policy::PolicyViolation;
+use crate::rdlib::s3::resumable::MAX_UPLOAD_SIZE;
+use crate::rdlib::s3::service::S3Service;
+use crate::api::access::require;
+use crate::api::error::{ApiError, ErrorCode};
+use crate::rdlib::access::authorizer::Access;
+use crate::rdlib::access::grant::Permission;
+use crate::rdlib::s3::types::{Preconditions, ResumableUpload, WriteOptions};
+use crate::api::v1::objects::{normalize_relative_path, object_key, policy_violation_response, system_key_forbidden};
+
+/// The version of the tus protocol implemented by this server
+pub const TUS_VERSION: &str = "1.0.0";
+
+/// The tus extensions implemented by this server
+pub const TUS_EXTENSIONS: &str = "creation,termination,expiration";
+
+/// The content type of PATCH requests
+const OFFSET_CONTENT_TYPE: &str = "application/offset+octet-stream";
+
+/// Query parameters for creating uploads
+#[derive(Deserialize)]
+pub struct CreateUploadQuery {
+    /// Optional prefix to store the object under
+    prefix: Option<String>,
+}
+
+/// Parses the `Upload-Metadata` header.
+///
+/// The header is a comma-separated list of keys, each optionally followed
+/// by a space and its base64-encoded value.
+///
+/// # Arguments
+///
+/// * `header` - The value of the header
+///
+/// # Returns
+///
+/// * `Ok(BTreeMap)` - The decoded metadata; keys without value map to an empty string
+/// * `Err(String)` - A description of why the header is invalid
+pub(crate) fn parse_upload_metadata(header: &str) -> Result<BTreeMap<String, String>, String> {
+    let mut metadata = BTreeMap::new();
+
+    for pair in header.split(',').map(str::trim).filter(|pair| !pair.is_empty()) {
+        let (key, encoded) = pair.split_once(' ').unwrap_or((pair, ""));
+        if key.is_empty() || !key.chars().all(|c| c.is_ascii_graphic()) {
+            return Err(format!("Invalid metadata key '{}'", key));
+        }
+
+        let value = BASE64.decode(encoded.trim())
+            .ok()
+            .and_then(|decoded| String::from_utf8(decoded).ok())
+            .ok_or_else(|| format!("Invalid base64 value for metadata key '{}'", key))?;
+        if metadata.insert(key.to_string(), value).is_some() {
+            return Err(format!("Duplicate metadata key '{}'", key));
+        }
+    }
+
+    Ok(metadata)
+}
+
+/// Formats a timestamp as HTTP date, as used by the `Upload-Expires` header.
+pub(crate) fn http_date(timestamp: DateTime<Utc>) -> String {
+    timestamp.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+/// Starts a response carrying the `Tus-Resumable` header.
+fn tus_response(status: StatusCode) -> HttpResponseBuilder {
+    let mut response = HttpResponse::build(status);
+    response.insert_header(("Tus-Resumable", TUS_VERSION));
+    response
+}
+
+/// Builds an error response carrying the `Tus-Resumable` header.
+fn tus_error(error: impl Into<ApiError>) -> HttpResponse {
+    with_tus_header(error.into().error_response())
+}
+
+/// Adds the `Tus-Resumable` header to a response built elsewhere.
+fn with_tus_header(mut response: HttpResponse) -> HttpResponse {
+    response.headers_mut().insert(HeaderName::from_static("tus-resumable"), HeaderValue::from_static(TUS_VERSION));
+    response
+}
+
+/// Checks that the client speaks the supported protocol version.
+///
+/// # Returns
+///
+/// `None` if the version is supported, otherwise the `412 Precondition Failed` response
+fn check_tus_version(req: &HttpRequest) -> Option<HttpResponse> {
+    let version = req.headers().get("Tus-Resumable").and_then(|v| v.to_str().ok());
+    if version == Some(TUS_VERSION) {
+        return None;
+    }
+    let mut response = tus_error(ApiError::new(
+        ErrorCode::UnsupportedProtocolVersion,
+        format!("Only tus version {} is supported", TUS_VERSION)
+    ).field("Tus-Resumable"));
+    response.headers_mut().insert(HeaderName::from_static("tus-version"), HeaderValue::from_static(TUS_VERSION));
+    Some(response)
+}
+
+/// Reads a header holding a non-negative integer.
+fn header_u64(req: &HttpRequest, name: &str) -> Result<Option<u64>, String> {
+    match req.headers().get(name) {
+        None => Ok(None),
+        Some(value) => value.to_str().ok()
+            .and_then(|v| v.trim().parse().ok())
+            .map(Some)
+            .ok_or_else(|| format!("Invalid {} header", name)),
+    }
+}
+
+/// Builds the URL of an upload.
+fn upload_url(bucket: &str, id: &str) -> String {
+    format!("/api/v1/bucket/{}/uploads/{}", bucket, id)
+}
+
+/// Loads an upload, removing it if it has expired.
+///
+/// # Returns
+///
+/// * `Ok(ResumableUpload)` - The upload
+/// * `Err(HttpResponse)` - `404 Not Found`, `410 Gone` for expired uploads, or `500` if the state could not be read
+async fn load_upload(s3: &S3Service, bucket: &str, id: &str) -> Result<ResumableUpload, HttpResponse> {
+    let upload = match s3.get_resumable_upload(bucket, id).await {
+        Ok(Some(upload)) => upload,
+        Ok(None) => return Err(tus_error(ApiError::new(ErrorCode::UploadNotFound, format!("Upload {} does not exist", id)))),
+        Err(e) => {
+            error!("Error reading upload {} of bucket {}: {:?}", id, bucket, e);
+            return Err(tus_error(e));
+        }
+    };
+
+    if upload.is_expired(Utc::now()) {
+        if let Err(e) = s3.delete_resumable_upload(&upload, true).await {
+            error!("Error removing expired upload {} of bucket {}: {:?}", id, bucket, e);
+        }
+        return Err(tus_error(ApiError::new(ErrorCode::UploadExpired, format!("Upload {} has expired", id))));
+    }
+
+    Ok(upload)
+}
+
+/// Locks an upload for the current request.
+///
+/// # Returns
+///
+/// * `Ok(())` - If the upload is locked by this request
+/// * `Err(HttpResponse)` - `409 Conflict` if another request holds it, or `500` if the lock could not be stored
+async fn lock_upload(s3: &S3Service, upload: &mut ResumableUpload) -> Result<(), HttpResponse> {
+    match s3.lock_resumable_upload(upload).await {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(upload_in_progress(&upload.id)),
+        Err(e) => {
+            error!("Error locking upload {} of bucket {}: {:?}", upload.id, upload.bucket, e);
+            Err(tus_error(e))
+        }
+    }
+}
+
+/// Builds the `409 Conflict` response for an upload held by another request.
+fn upload_in_progress(id: &str) -> HttpResponse {
+    tus_error(ApiError::new(ErrorCode::RequestInProgress, format!("Upload {} is receiving data in another request", id)))
+}
+
+/// Converts an error saving the progress of an upload.
+///
+/// A failed precondition means another request took over the upload after
+/// this request's lock ran out, which is answered with `409 Conflict`.
+fn save_error(upload: &ResumableUpload, e: S3Error) -> HttpResponse {
+    match e {
+        S3Error::PreconditionFailed(_) => upload_in_progress(&upload.id),
+        e => tus_error(e),
+    }
+}
+
+/// Uploads the next part of an upload and saves its progress, extending the lock.
+async fn upload_next_part(s3: &S3Service, upload: &mut ResumableUpload, data: Bytes) -> Result<(), S3Error> {
+    let part_number = upload.parts.len() as i32 + 1;
+    let part = s3.upload_part(&upload.key, &upload.bucket, &upload.multipart_id, part_number, data).await?;
+    upload.parts.push(part);
+    // The pending data is part of this part now
+    upload.pending_size = 0;
+    upload.lock(Utc::now());
+    s3.save_resumable_upload(upload).await
+}
+
+/// Checks the content of an upload against the bucket's upload policy,
+/// removing the upload if it is not allowed.
+///
+/// # Arguments
+///
+/// * `data` - The first part of the upload, which starts with the bytes the content type is sniffed from
+///
+/// # Returns
+///
+/// * `Ok(())` - If the content is allowed
+/// * `Err(HttpResponse)` - `403 Forbidden` if it is not, or `500` if the policy could not be read
+async fn check_upload_content(s3: &S3Service, upload: &ResumableUpload, data: &[u8]) -> Result<(), HttpResponse> {
+    let policy = match s3.get_upload_policy(&upload.bucket).await {
+        Ok(policy) => policy,
+        Err(e) => {
+            error!("Error reading upload policy of bucket {}: {:?}", upload.bucket, e);
+            return Err(tus_error(e));
+        }
+    };
+    let filename = upload.key.rsplit('/').next().unwrap_or(&upload.key);
+    if let Err(violation) = policy.check_content(filename, data) {
+        if let Err(e) = s3.delete_resumable_upload(upload, true).await {
+            error!("Error removing rejected upload {}: {:?}", upload.id, e);
+        }
+        return Err(with_tus_header(policy_violation_response(&violation, &[])));
+    }
+    Ok(())
+}
+
+/// Gets the options of writing the object of an upload. Uploaders may not
+/// replace objects, including ones created while the upload was in progress.
+fn target_write_options(access: &Access, bucket: &str, key: &str) -> WriteOptions {
+    let preconditions = match access.allows(bucket, key, Permission::Delete) {
+        true => Preconditions::default(),
+        false => Preconditions::create_only(),
+    };
+    WriteOptions { preconditions, ..Default::default() }
+}
+
+/// Builds the error for an upload whose target the client may not replace.
+fn target_exists(bucket: &str, key: &str) -> ApiError {
+    ApiError::new(ErrorCode::AlreadyExists, format!("File {} already exists in bucket {}", key, bucket))
+}
+
+/// Describes the tus protocol support of the server.
+///
+/// # Path Parameters
+///
+/// * `bucket` - The name of the bucket
+///
+/// # Returns
+///
+/// * `204 No Content` - With the `Tus-Version`, `Tus-Extension` and `Tus-Max-Size` headers
+#[route("/bucket/{bucket}/uploads", method = "OPTIONS")]
+pub async fn get_upload_options() -> HttpResponse {
+    tus_response(StatusCode::NO_CONTENT)
+        .insert_header(("Tus-Version", TUS_VERSION))
+        .insert_header(("Tus-Extension", TUS_EXTENSIONS))
+        .insert_header(("Tus-Max-Size", MAX_UPLOAD_SIZE.to_string()))
+        .finish()
+}
+
+/// Creates a resumable upload.
+///
+/// The target key is taken from the `key` metadata, a relative path, or
+/// else from the `filename` metadata; either is stored under `prefix`. The
+/// content type is taken from the `filetype` metadata or guessed from the
+/// file name. The bucket's upload policy is checked before any data is sent.
+///
+/// # Path Parameters
+///
+/// * `bucket` - The name of the bucket to upload to
+///
+/// # Query Parameters
+///
+/// * `prefix` - Optional prefix to store the object under
+///
+/// # Headers
+///
+/// * `Tus-Resumable` - Must be `1.0.0`
+/// * `Upload-Length` - The size of the object in bytes
+/// * `Upload-Metadata` - The metadata of the upload, with `filename` or `key`
+///
+/// # Returns
+///
+/// * `201 Created` - With the URL of the upload in the `Location` header
+/// * `400 Bad Request` - If the headers or metadata are invalid
+/// * `403 Forbidden` - If the key points into the system prefix, is not allowed by the upload policy, or the client is not an uploader of it
+/// * `409 Conflict` - If the object exists and the client may not replace it
+/// * `412 Precondition Failed` - If the tus version is not supported
+/// * `413 Payload Too Large` - If the object exceeds the maximum upload or object size
+/// * `415 Unsupported Media Type` - If the file type is not allowed in the bucket
+/// * `500 Internal Server Error` - If there was an error creating the upload
+#[post("/bucket/{bucket}/uploads", wrap = "crate::api::idempotency::Idempotency")]
+pub async fn create_upload(
+    bucket: web::Path<String>,
+    query: web::Query<CreateUploadQuery>,
+    req: HttpRequest,
+    access: Access,
+    s3_service: web::Data<Arc<S3Service>>
+) -> Result<HttpResponse, Error> {
+    if let Some(response) = check_tus_version(&req) {
+        return Ok(response);
+    }
+    let s3 = s3_service.as_ref();
+
+    let length = match header_u64(&req, "Upload-Length") {
+        Ok(Some(length)) => length,
+        Ok(None) => return Ok(tus_error(ApiError::new(ErrorCode::InvalidRequest, "The Upload-Length header is required").field("Upload-Length"))),
+        Err(e) => return Ok(tus_error(ApiError::new(ErrorCode::InvalidRequest, e).field("Upload-Length"))),
+    };
+    if length > MAX_UPLOAD_SIZE {
+        return Ok(tus_error(
+            ApiError::new(ErrorCode::PayloadTooLarge, format!("Uploads are limited to {} bytes", MAX_UPLOAD_SIZE))
+                .field("Upload-Length")
+        ));
+    }
+
+    let header = req.headers().get("Upload-Metadata").and_then(|v| v.to_str().ok()).unwrap_or_default();
+    let metadata = match parse_upload_metadata(header) {
+        Ok(metadata) => metadata,
+        Err(e) => return Ok(tus_error(ApiError::new(ErrorCode::InvalidRequest, e).field("Upload-Metadata"))),
+    };
+
+    let path = match (metadata.get("key"), metadata.get("filename")) {
+        (Some(key), _) => normalize_relative_path(key),
+        (None, Some(filename)) => match sanitize_filename::sanitize(filename) {
+            sanitized if sanitized.is_empty() => Err(format!("'{}' is not a valid name", filename)),
+            sanitized => Ok(sanitized),
+        },
+        (None, None) => Err("the metadata must contain a filename or key".to_string()),
+    };
+    let path = match path {
+        Ok(path) => path,
+        Err(e) => return Ok(tus_error(ApiError::new(ErrorCode::InvalidKey, format!("Invalid upload target: {}", e)).field("Upload-Metadata"))),
+    };
+
+    let prefix = query.prefix.clone().unwrap_or_default();
+    let key = object_key(&prefix, &path);
+    if S3Service::is_system_key(&key) {
+        return Ok(with_tus_header(system_key_forbidden(&key)));
+    }
+    if let Err(e) = require(&access, &bucket, &key, Permission::Write) {
+        return Ok(tus_error(e));
+    }
+    // Uploaders may not replace objects
+    if !access.allows(&bucket, &key, Permission::Delete) {
+        match s3.check_object_exists(&key, &bucket).await {
+            Ok(false) => {},
+            Ok(true) => return Ok(tus_error(target_exists(&bucket, &key))),
+            Err(e) => {
+                error!("Error checking if {}/{} exists: {:?}", bucket, key, e);
+                return Ok(tus_error(e));
+            }
+        }
+    }
+
+    let filename = path.rsplit('/').next().unwrap_or(&path);
+    let content_type = metadata.get("filetype")
+        .filter(|filetype| !filetype.is_empty())
+        .cloned()
+        .unwrap_or_else(|| mime_guess::from_path(filename).first_or_octet_stream().essence_str().to_string());
+
+    let policy = match s3.get_upload_policy(&bucket).await {
+        Ok(policy) => policy,
+        Err(e) => {
+            error!("Error reading upload policy of bucket {}: {:?}", bucket, e);
+            return Ok(tus_error(e));
+        }
+    };
+    let check = policy.check_key(&key)
+        .and(policy.check_file(filename, Some(&content_type)))
+        .and(match policy.max_object_size {
+            Some(max) if length > max => Err(PolicyViolation::TooLarge(max)),
+            _ => Ok(()),
+        });
+    if let Err(violation) = check {
+        return Ok(with_tus_header(policy_violation_response(&violation, &[])));
+    }
+
+    // Empty objects are complete right away and need no multipart upload;
+    // their state is kept until it expires, so clients can still query it
+    if length == 0 {
+        match s3.put_object_with_options(&key, &bucket, Bytes::new(), &target_write_options(&access, &bucket, &key)).await {
+            Ok(Some(_)) => {},
+            Ok(None) => return Ok(tus_error(target_exists(&bucket, &key))),
+            Err(e) => {
+                error!("Error uploading {}/{}: {:?}", bucket, key, e);
+                return Ok(tus_error(e));
+            }
+        }
+        let mut upload = ResumableUpload::new(&bucket, &key, &content_type, "", length, metadata);
+        if let Err(e) = s3.save_resumable_upload(&mut upload).await {
+            error!("Error saving upload {} of bucket {}: {:?}", upload.id, bucket, e);
+            return Ok(tus_error(e));
+        }
+        return Ok(tus_response(StatusCode::CREATED)
+            .insert_header(("Location", upload_url(&bucket, &upload.id)))
+            .insert_header(("Upload-Offset", "0"))
+            .insert_header(("Upload-Expires", http_date(upload.expires_at)))
+            .finish());
+    }
+
+    let mut upload = match s3.create_multipart_upload(&key, &bucket, &content_type).await {
+        Ok(multipart_id) => ResumableUpload::new(&bucket, &key, &content_type, &multipart_id, length, metadata),
+        Err(e) => {
+            error!("Error starting multipart upload of {}/{}: {:?}", bucket, key, e);
+            return Ok(tus_error(e));
+        }
+    };
+    if let Err(e) = s3.save_resumable_upload(&mut upload).await {
+        error!("Error saving upload {} of bucket {}: {:?}", upload.id, bucket, e);
+        return Ok(tus_error(e));
+    }
+
+    info!("Created resumable upload {} of {} bytes to {}/{}", upload.id, length, bucket, key);
+    Ok(tus_response(StatusCode::CREATED)
+        .insert_header(("Location", upload_url(&bucket, &upload.id)))
+        .insert_header(("Upload-Expires", http_date(upload.expires_at)))
+        .finish())
+}
+
+/// Returns the progress of a resumable upload.
+///
+/// # Path Parameters
+///
+/// * `bucket` - The name of the bucket
+/// * `id` - The ID of the upload
+///
+/// # Returns
+///
+/// * `200 OK` - With the `Upload-Offset`, `Upload-Length` and `Upload-Expires` headers
+/// * `403 Forbidden` - If the client is not an uploader of the upload's key
+/// * `404 Not Found` - If the upload does not exist
+/// * `410 Gone` - If the upload has expired
+/// * `412 Precondition Failed` - If the tus version is not supported
+#[route("/bucket/{bucket}/uploads/{id}", method = "HEAD")]
+pub async fn get_upload_offset(
+    path: web::Path<(String, String)>,
+    req: HttpRequest,
+    access: Access,
+    s3_service: web::Data<Arc<S3Service>>
+) -> Result<HttpResponse, Error> {
+    if let Some(response) = check_tus_version(&req) {
+        return Ok(response);
+    }
+    let (bucket, id) = path.into_inner();
+
+    let upload = match load_upload(s3_service.as_ref(), &bucket, &id).await {
+        Ok(upload) => upload,
+        Err(response) => return Ok(response),
+    };
+    if let Err(e) = require(&access, &bucket, &upload.key, Permission::Write) {
+        return Ok(tus_error(e));
+    }
+
+    Ok(tus_response(StatusCode::OK)
+        .insert_header(("Upload-Offset", upload.offset().to_string()))
+        .insert_header(("Upload-Length", upload.length.to_string()))
+        .insert_header(("Upload-Expires", http_date(upload.expires_at)))
+        .insert_header(("Cache-Control", "no-store"))
+        .finish())
+}
+
+/// Appends data to a resumable upload.
+///
+/// The data is uploaded to S3 in parts as it arrives. If the connection
+/// drops, the data received so far is kept and the client can resume from
+/// the offset reported by a HEAD request. Once all data has been received,
+/// the object is created at its target key.
+///
+/// # Path Parameters
+///
+/// * `bucket` - The name of the bucket
+/// * `id` - The ID of the upload
+///
+/// # Headers
+///
+/// * `Tus-Resumable` - Must be `1.0.0`
+/// * `Content-Type` - Must be `application/offset+octet-stream`
+/// * `Upload-Offset` - The offset the data starts at, which must match the upload's offset
+///
+/// # Returns
+///
+/// * `204 No Content` - With the new offset in the `Upload-Offset` header
+/// * `400 Bad Request` - If the headers are invalid, or the data exceeds the upload's length
+/// * `403 Forbidden` - If the client is not an uploader of the upload's key
+/// * `404 Not Found` - If the upload does not exist
+/// * `409 Conflict` - If the offset does not match, the upload is receiving data in another request, or the client may
+///   not replace a file created at the upload's key in the meantime, which removes the upload
+/// * `410 Gone` - If the upload has expired
+/// * `412 Precondition Failed` - If the tus version is not supported
+/// * `415 Unsupported Media Type` - If the content type is wrong, or the content is not allowed by the upload policy, which removes the upload
+/// * `500 Internal Server Error` - If there was an error storing the data
+#[patch("/bucket/{bucket}/uploads/{id}")]
+pub async fn append_to_upload(
+    path: web::Path<(String, String)>,
+    req: HttpRequest,
+    mut payload: web::Payload,
+    access: Access,
+    s3_service: web::Data<Arc<S3Service>>
+) -> Result<HttpResponse, Error> {
+    if let Some(response) = check_tus_version(&req) {
+        return Ok(response);
+    }
+    let (bucket, id) = path.into_inner();
+    let s3 = s3_service.as_ref();
+
+    let content_type = req.headers().get("Content-Type").and_then(|v| v.to_str().ok());
+    if content_type != Some(OFFSET_CONTENT_TYPE) {
+        return Ok(tus_error(
+            ApiError::new(ErrorCode::UnsupportedMediaType, format!("The Content-Type must be {}", OFFSET_CONTENT_TYPE))
+                .field("Content-Type")
+        ));
+    }
+    let offset = match header_u64(&req, "Upload-Offset") {
+        Ok(Some(offset)) => offset,
+        Ok(None) => return Ok(tus_error(ApiError::new(ErrorCode::InvalidRequest, "The Upload-Offset header is required").field("Upload-Offset"))),
+        Err(e) => return Ok(tus_error(ApiError::new(ErrorCode::InvalidRequest, e).field("Upload-Offset"))),
+    };
+
+    let mut upload = match load_upload(s3, &bucket, &id).await {
+        Ok(upload) => upload,
+        Err(response) => return Ok(response),
+    };
+    if let Err(e) = require(&access, &bucket, &upload.key, Permission::Write) {
+        return Ok(tus_error(e));
+    }
+    if upload.is_locked(Utc::now()) {
+        return Ok(upload_in_progress(&id));
+    }
+    if offset != upload.offset() {
+        let mut response = tus_error(
+            ApiError::new(ErrorCode::OffsetMismatch, format!("Upload-Offset must be {}", upload.offset())).field("Upload-Offset")
+        );
+        response.headers_mut().insert(HeaderName::from_static("upload-offset"), HeaderValue::from(upload.offset()));
+        return Ok(response);
+    }
+    // Empty uploads were completed when they were created
+    if upload.length == 0 {
+        return Ok(tus_response(StatusCode::NO_CONTENT).insert_header(("Upload-Offset", "0")).finish());
+    }
+    if let Err(response) = lock_upload(s3, &mut upload).await {
+        return Ok(response);
+    }
+
+    let mut buffer = match s3.get_pending_upload_data(&upload).await {
+        Ok(data) => BytesMut::from(&data[..]),
+        Err(e) => {
+            error!("Error reading pending data of upload {}: {:?}", id, e);
+            return Ok(tus_error(e));
+        }
+    };
+    let mut received = offset;
+    let mut overflow = false;
+
+    while let Some(chunk) = payload.next().await {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                warn!("Connection of upload {} dropped at {} bytes: {}", id, received, e);
+                break;
+            }
+        };
+        if received + chunk.len() as u64 > upload.length {
+            overflow = true;
+            break;
+        }
+        received += chunk.len() as u64;
+        buffer.extend_from_slice(&chunk);
+
+        while buffer.len() as u64 >= upload.part_size {
+            let part = buffer.split_to(upload.part_size as usize).freeze();
+            if upload.parts.is_empty()
+                && let Err(response) = check_upload_content(s3, &upload, &part).await {
+                return Ok(response);
+            }
+            if let Err(e) = upload_next_part(s3, &mut upload, part).await {
+                // The progress saved with the previous part is still consistent
+                error!("Error uploading part of upload {}: {:?}", id, e);
+                return Ok(save_error(&upload, e));
+            }
+        }
+    }
+
+    if received == upload.length {
+        // The last part may be smaller than the part size
+        if upload.parts.is_empty()
+            && let Err(response) = check_upload_content(s3, &upload, &buffer).await {
+            return Ok(response);
+        }
+        if !buffer.is_empty()
+            && let Err(e) = upload_next_part(s3, &mut upload, buffer.freeze()).await {
+            error!("Error uploading last part of upload {}: {:?}", id, e);
+            return Ok(save_error(&upload, e));
+        }
+        let options = target_write_options(&access, &bucket, &upload.key);
+        match s3.complete_multipart_upload_with_options(&upload.key, &bucket, &upload.multipart_id, &upload.parts, &options).await {
+            Ok(Some(_)) => {},
+            Ok(None) => {
+                // The object was created while the upload was in progress
+                if let Err(e) = s3.delete_resumable_upload(&upload, true).await {
+                    error!("Error removing rejected upload {}: {:?}", id, e);
+                }
+                return Ok(tus_error(target_exists(&bucket, &upload.key)));
+            },
+            Err(e) => {
+                error!("Error completing upload {} to {}/{}: {:?}", id, bucket, upload.key, e);
+                return Ok(tus_error(e));
+            }
+        }
+        if let Err(e) = s3.delete_resumable_upload(&upload, false).await {
+            error!("Error removing state of completed upload {}: {:?}", id, e);
+        }
+
+        info!("Completed resumable upload {} to {}/{}", id, bucket, upload.key);
+        return Ok(tus_response(StatusCode::NO_CONTENT)
+            .insert_header(("Upload-Offset", upload.length.to_string()))
+            .finish());
+    }
+
+    // Keep what did not fill a part, so the client can resume after it
+    let saved = match buffer.is_empty() {
+        true => Ok(()),
+        false => s3.put_pending_upload_data(&upload, buffer.clone().freeze()).await,
+    };
+    let saved = match saved {
+        Ok(_) => {
+            // The next request may continue right away
+            upload.pending_size = buffer.len() as u64;
+            upload.locked_until = None;
+            s3.save_resumable_upload(&mut upload).await
+        },
+        Err(e) => Err(e),
+    };
+    if let Err(e) = saved {
+        error!("Error saving progress of upload {}: {:?}", id, e);
+        return Ok(save_error(&upload, e));
+    }
+
+    if overflow {
+        let mut response = tus_error(
+            ApiError::new(ErrorCode::InvalidRequest, format!("The data exceeds the Upload-Length of {} bytes", upload.length))
+        );
+        response.headers_mut().insert(HeaderName::from_static("upload-offset"), HeaderValue::from(upload.offset()));
+        return Ok(response);
+    }
+
+    Ok(tus_response(StatusCode::NO_CONTENT)
+        .insert_header(("Upload-Offset", upload.offset().to_string()))
+        .insert_header(("Upload-Expires", http_date(upload.expires_at)))
+        .finish())
+}
+
+/// Terminates a resumable upload and discards the data received so far.
+///
+/// # Path Parameters
+///
+/// * `bucket` - The name of the bucket
+/// * `id` - The ID of the upload
+///
+/// # Returns
+///
+/// * `204 No Content` - If the upload was terminated
+/// * `403 Forbidden` - If the client is not an uploader of the upload's key
+/// * `404 Not Found` - If the upload does not exist
+/// * `409 Conflict` - If the upload is receiving data in another request
+/// * `410 Gone` - If the upload has already expired
+/// * `412 Precondition Failed` - If the tus version is not supported
+/// * `500 Internal Server Error` - If there was an error terminating the upload
+#[delete("/bucket/{bucket}/uploads/{id}")]
+pub async fn terminate_upload(
+    path: web::Path<(String, String)>,
+    req: HttpRequest,
+    access: Access,
+    s3_service: web::Data<Arc<S3Service>>
+) -> Result<HttpResponse, Error> {
+    if let Some(response) = check_tus_version(&req) {
+        return Ok(response);
+    }
+    let (bucket, id) = path.into_inner();
+    let s3 = s3_service.as_ref();
+
+    let mut upload = match load_upload(s3, &bucket, &id).await {
+        Ok(upload) => upload,
+        Err(response) => return Ok(response),
+    };
+    if let Err(e) = require(&access, &bucket, &upload.key, Permission::Write) {
+        return Ok(tus_error(e));
+    }
+    if let Err(response) = lock_upload(s3, &mut upload).await {
+        return Ok(response);
+    }
+
+    match s3.delete_resumable_upload(&upload, true).await {
+        Ok(_) => Ok(tus_response(StatusCode::NO_CONTENT).finish()),
+        Err(e) => {
+            error!("Error terminating upload {} of bucket {}: {:?}", id, bucket, e);
+            Ok(tus_error(e))
+        }
+    }
+}
@@ -0,0 +1,274 @@
+//! # User Management API Endpoints
+//!
+//! This module provides the API endpoints for managing user accounts.
+//! They are reserved for administrators and answer `501 Not Implemented`
+//! if no user database is configured.
+
+use std::sync::Arc;
+
+use actix_web::{delete, get, patch, post, web, Error, HttpResponse, ResponseError};
+use log::{error, info};
+use serde_json::json;
+
+use crate::api::auth::require_admin;
+use crate::api::error::{ApiError, ErrorCode};
+use crate::api::v1::grants::access_error;
+use crate::models::users::{CreateUserRequest, UpdateUserRequest};
+use crate::rdlib::access::authorizer::Authorizer;
+use crate::rdlib::auth::principal::Principal;
+use crate::rdlib::users::error::UserError;
+use crate::rdlib::users::service::{UserService, UserUpdate};
+use crate::rdlib::users::user::Role;
+
+/// Gets the user service, if user accounts are enabled.
+///
+/// # Returns
+///
+/// The service, or a `501 Not Implemented` error if no user database is configured
+pub fn user_service(users: Option<web::Data<Arc<UserService>>>) -> Result<web::Data<Arc<UserService>>, ApiError> {
+    users.ok_or_else(|| ApiError::new(ErrorCode::NotEnabled, "User accounts are not enabled on this server"))
+}
+
+/// Converts an error of the user subsystem, logging server-side failures.
+pub fn user_error(context: &str, e: UserError) -> Error {
+    if e.status_code().is_server_error() {
+        error!("Error {}: {:?}", context, e);
+    }
+    e.into()
+}
+
+/// Checks if a user is the principal, whose own account must not be locked
+/// out. Only the `uid` claim of tokens RustDok issued itself names a user.
+fn is_own_account(principal: &Principal, id: u64) -> bool {
+    principal.local && principal.claims.get("uid").and_then(|uid| uid.as_u64()) == Some(id)
+}
+
+/// Lists all users.
+///
+/// # Returns
+///
+/// * `200 OK` - A JSON array of users
+/// * `403 Forbidden` - If the client is not an administrator
+#[get("/admin/users")]
+pub async fn list_users(
+    principal: Principal,
+    users: Option<web::Data<Arc<UserService>>>
+) -> Result<HttpResponse, Error> {
+    require_admin(&principal)?;
+    let users = user_service(users)?;
+
+    match users.list_users().await {
+        Ok(list) => Ok(HttpResponse::Ok().json(list)),
+        Err(e) => Err(user_error("listing users", e)),
+    }
+}
+
+/// Creates a user.
+///
+/// # Request Body
+///
+/// * `username` - The name the user logs in with
+/// * `password` - The password of the user
+/// * `role` - `admin` or `user` (default)
+///
+/// # Returns
+///
+/// * `201 Created` - The created user
+/// * `400 Bad Request` - If the username or password is not acceptable
+/// * `403 Forbidden` - If the client is not an administrator
+/// * `409 Conflict` - If the username is in use
+#[post("/admin/users", wrap = "crate::api::idempotency::Idempotency")]
+pub async fn create_user(
+    principal: Principal,
+    request: web::Json<CreateUserRequest>,
+    users: Option<web::Data<Arc<UserService>>>
+) -> Result<HttpResponse, Error> {
+    require_admin(&principal)?;
+    let users = user_service(users)?;
+
+    let role = request.role.unwrap_or(Role::User);
+    match users.create_user(&request.username, &request.password, role).await {
+        Ok(user) => {
+            info!("User '{}' created user '{}' with role {}", principal.subject, user.username, user.role);
+            Ok(HttpResponse::Created().json(user))
+        },
+        Err(e) => Err(user_error("creating user", e)),
+    }
+}
+
+/// Shows a user.
+///
+/// # Path Parameters
+///
+/// * `id` - The ID of the user
+///
+/// # Returns
+///
+/// * `200 OK` - The user
+/// * `403 Forbidden` - If the client is not an administrator
+/// * `404 Not Found` - If the user does not exist
+#[get("/admin/users/{id}")]
+pub async fn get_user(
+    principal: Principal,
+    id: web::Path<u64>,
+    users: Option<web::Data<Arc<UserService>>>
+) -> Result<HttpResponse, Error> {
+    require_admin(&principal)?;
+    let users = user_service(users)?;
+
+    match users.get_user(*id).await {
+        Ok(user) => Ok(HttpResponse::Ok().json(user)),
+        Err(e) => Err(user_error("reading user", e)),
+    }
+}
+
+/// Changes the password, role or disabled state of a user. Disabling a
+/// user or changing its password revokes its refresh tokens.
+///
+/// # Path Parameters
+///
+/// * `id` - The ID of the user
+///
+/// # Returns
+///
+/// * `200 OK` - The changed user
+/// * `400 Bad Request` - If the password is not acceptable, or administrators would lock themselves out
+/// * `403 Forbidden` - If the client is not an administrator
+/// * `404 Not Found` - If the user does not exist
+#[patch("/admin/users/{id}")]
+pub async fn update_user(
+    principal: Principal,
+    id: web::Path<u64>,
+    request: web::Json<UpdateUserRequest>,
+    users: Option<web::Data<Arc<UserService>>>
+) -> Result<HttpResponse, Error> {
+    require_admin(&principal)?;
+    let users = user_service(users)?;
+
+    if is_own_account(&principal, *id) {
+        if request.disabled == Some(true) {
+            return Err(ApiError::new(ErrorCode::InvalidRequest, "Administrators cannot disable their own account")
+                .field("disabled")
+                .into());
+        }
+        if request.role.is_some_and(|role| role != Role::Admin) {
+            return Err(ApiError::new(ErrorCode::InvalidRequest, "Administrators cannot give up their own role")
+                .field("role")
+                .into());
+        }
+    }
+
+    let update = UserUpdate { password: request.password.clone(), role: request.role, disabled: request.disabled };
+    match users.update_user(*id, update).await {
+        Ok(user) => {
+            info!("User '{}' changed user '{}'", principal.subject, user.username);
+            Ok(HttpResponse::Ok().json(user))
+        },
+        Err(e) => Err(user_error("changing user", e)),
+    }
+}
+
+/// Deletes a user and the roles granted to it.
+///
+/// # Path Parameters
+///
+/// * `id` - The ID of the user
+///
+/// # Returns
+///
+/// * `200 OK` - If the user was deleted
+/// * `400 Bad Request` - If administrators try to delete their own account
+/// * `403 Forbidden` - If the client is not an administrator
+/// * `404 Not Found` - If the user does not exist
+#[delete("/admin/users/{id}")]
+pub async fn delete_user(
+    principal: Principal,
+    id: web::Path<u64>,
+    users: Option<web::Data<Arc<UserService>>>,
+    authorizer: Option<web::Data<Arc<Authorizer>>>
+) -> Result<HttpResponse, Error> {
+    require_admin(&principal)?;
+    let users = user_service(users)?;
+
+    if is_own_account(&principal, *id) {
+        return Err(ApiError::new(ErrorCode::InvalidRequest, "Administrators cannot delete their own account").into());
+    }
+
+    let user = users.get_user(*id).await.map_err(|e| user_error("reading user", e))?;
+    // A new user of the same name must not inherit the roles and groups, so
+    // they are revoked first; if that fails, the user is kept
+    if let Some(authorizer) = authorizer {
+        authorizer.forget_user(&user.username).await
+            .map_err(|e| access_error("revoking grants of user", e))?;
+    }
+    match users.delete_user(*id).await {
+        Ok(()) => {
+            info!("User '{}' deleted user '{}'", principal.subject, user.username);
+            Ok(HttpResponse::Ok().json(json!({
+                "message": format!("User {} deleted successfully", id)
+            })))
+        },
+        Err(e) => Err(user_error("deleting user", e)),
+    }
+}
+
+/// Removes the second factor of a user who lost it, so it can log in with
+/// its password and set up a new one. Revokes the refresh tokens and sessions of the user.
+///
+/// # Path Parameters
+///
+/// * `id` - The ID of the user
+///
+/// # Returns
+///
+/// * `200 OK` - The changed user
+/// * `403 Forbidden` - If the client is not an administrator
+/// * `404 Not Found` - If the user does not exist
+#[delete("/admin/users/{id}/2fa")]
+pub async fn reset_two_factor(
+    principal: Principal,
+    id: web::Path<u64>,
+    users: Option<web::Data<Arc<UserService>>>
+) -> Result<HttpResponse, Error> {
+    require_admin(&principal)?;
+    let users = user_service(users)?;
+
+    match users.reset_two_factor(*id).await {
+        Ok(user) => {
+            info!("User '{}' reset two-factor authentication of user '{}'", principal.subject, user.username);
+            Ok(HttpResponse::Ok().json(user))
+        },
+        Err(e) => Err(user_error("resetting two-factor authentication", e)),
+    }
+}
+
+/// Ends all sessions of a user, such as after its device was lost: its web
+/// UI sessions and refresh tokens are revoked, and its access tokens
+/// expire within `RUSTDOK_ACCESS_TOKEN_TTL`.
+///
+/// # Path Parameters
+///
+/// * `id` - The ID of the user
+///
+/// # Returns
+///
+/// * `204 No Content` - The sessions are revoked
+/// * `403 Forbidden` - If the client is not an administrator
+/// * `404 Not Found` - If the user does not exist
+#[delete("/admin/users/{id}/sessions")]
+pub async fn revoke_user_sessions(
+    principal: Principal,
+    id: web::Path<u64>,
+    users: Option<web::Data<Arc<UserService>>>
+) -> Result<HttpResponse, Error> {
+    require_admin(&principal)?;
+    let users = user_service(users)?;
+
+    match users.revoke_all_sessions(*id).await {
+        Ok(()) => {
+            info!("User '{}' revoked the sessions of user {}", principal.subject, id);
+            Ok(HttpResponse::NoContent().finish())
+        },
+        Err(e) => Err(user_error("revoking sessions", e)),
+    }
+}
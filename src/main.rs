@@ -19,6 +19,7 @@ mod rdlib;
 mod api;
 
 #[cfg(test)]
+#[allow(clippy::empty_line_after_outer_attr)]
 mod tests;
 
 /// The main entry point for the RustDok server.
@@ -44,24 +45,102 @@ async fn main() -> std::io::Result<()> {
     
     // Create an S3Service instance to be shared across all workers
     let s3_service = Arc::new(rdlib::s3::service::S3Service::new().await);
+
+    // Abort the resumable uploads that clients gave up on
+    actix_web::rt::spawn(rdlib::s3::resumable::sweep_expired_uploads(s3_service.clone()));
+
+    // Read the JWT keys once; the server does not start without them
+    let authentication = api::auth::Authentication::from_env();
+
+    // Connect to the user database, if one is configured
+    let user_service = rdlib::users::service::UserService::from_env().await
+        .unwrap_or_else(|e| panic!("{}", e))
+        .map(Arc::new);
+
+    // Look up grants in the same database; without it, access is unrestricted
+    let authorizer = rdlib::access::authorizer::Authorizer::from_env().await
+        .unwrap_or_else(|e| panic!("{}", e))
+        .map(Arc::new);
+
+    // Authenticate service accounts by their API keys, kept in the same database
+    let api_key_service = rdlib::apikeys::service::ApiKeyService::from_env().await
+        .unwrap_or_else(|e| panic!("{}", e))
+        .map(Arc::new);
+
+    // Read the attributes of the web UI session cookies
+    let cookie_settings = api::session::CookieSettings::from_env().unwrap_or_else(|e| panic!("{}", e));
+
+    // Discover the OpenID Connect provider, if one is configured
+    let oidc_service = rdlib::oidc::service::OidcService::from_env().await
+        .unwrap_or_else(|e| panic!("{}", e))
+        .map(Arc::new);
+
+    // Record every API request in the audit log, kept in the same database
+    let audit_service = rdlib::audit::service::AuditService::from_env().await
+        .unwrap_or_else(|e| panic!("{}", e))
+        .map(Arc::new);
     
     HttpServer::new(move || {
         // Create a new Cors instance for each worker
         let cors = Cors::default()
-            .allowed_origin(&env::var("RUSTDOK_WEBUI_URL").unwrap_or_else(|_| "http://localhost:3000".to_string()))
-            .allowed_methods(vec!["GET", "POST", "PUT", "DELETE"])
+            .allowed_origin(&env::var("RUSTDOK_WEBUI_URL").unwrap_or_else(|_| rdlib::oidc::config::DEFAULT_WEBUI_URL.to_string()))
+            .allowed_methods(vec!["GET", "HEAD", "POST", "PUT", "PATCH", "DELETE", "OPTIONS"])
             .allowed_headers(vec![header::AUTHORIZATION, header::ACCEPT, header::CONTENT_TYPE])
+            .allowed_headers(vec!["If-Match", "If-None-Match", "Content-MD5", "Idempotency-Key", "X-Request-Id", "X-API-Key"])
+            // The web UI sends its session cookie and echoes the CSRF token
+            .allowed_header(api::session::CSRF_HEADER)
+            .supports_credentials()
+            // Headers of the tus resumable upload protocol
+            .allowed_headers(vec!["Tus-Resumable", "Upload-Length", "Upload-Offset", "Upload-Metadata"])
+            .expose_headers(vec![
+                "Location", "ETag", "WWW-Authenticate", "Idempotent-Replayed", "X-Request-Id", "Retry-After",
+                "Tus-Resumable", "Tus-Version", "Tus-Extension", "Tus-Max-Size",
+                "Upload-Offset", "Upload-Length", "Upload-Expires",
+            ])
             .max_age(3600);
 
         App::new()
             .wrap(cors)
+            .wrap(api::request_id::RequestId)
             // Share the S3Service instance with all routes
             .app_data(web::Data::new(s3_service.clone()))
+            .app_data(web::Data::new(cookie_settings.clone()))
+            // Share the UserService instance, if user accounts are enabled
+            .configure(|cfg| {
+                if let Some(users) = &user_service {
+                    cfg.app_data(web::Data::new(users.clone()));
+                }
+            })
+            // Share the Authorizer instance, if grants are enforced
+            .configure(|cfg| {
+                if let Some(authorizer) = &authorizer {
+                    cfg.app_data(web::Data::new(authorizer.clone()));
+                }
+            })
+            // Share the ApiKeyService instance, if API keys are enabled
+            .configure(|cfg| {
+                if let Some(api_keys) = &api_key_service {
+                    cfg.app_data(web::Data::new(api_keys.clone()));
+                }
+            })
+            // Share the OidcService instance, if OpenID Connect login is enabled
+            .configure(|cfg| {
+                if let Some(oidc) = &oidc_service {
+                    cfg.app_data(web::Data::new(oidc.clone()));
+                }
+            })
+            // Share the AuditService instance, if the audit log is enabled
+            .configure(|cfg| {
+                if let Some(audit) = &audit_service {
+                    cfg.app_data(web::Data::new(audit.clone()));
+                }
+            })
             // Health check endpoints
             .service(api::health::liveness)
             .service(api::health::readiness)
-            // API v1 routes
-            .service(api::config::configure_api_v1())
+            // API v1 routes, which require a bearer token and are recorded
+            // in the audit log, including the requests authentication rejects
+            .service(api::config::configure_api_v1().wrap(authentication.clone()).wrap(api::audit::AuditLog))
     })
     .bind(("0.0.0.0", 8080))?
     .run()
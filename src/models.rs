@@ -3,4 +3,7 @@
 //! This module contains the data models used throughout the RustDok server.
 //! It defines the structures for request and response data.
 
-pub mod s3; 
\ No newline at end of file
+pub mod s3; 
+pub mod users;
+pub mod access;
+pub mod apikeys;
\ No newline at end of file
@@ -0,0 +1,43 @@
+//! # Access Control Data Models
+//!
+//! This module contains the data models of grant and group management.
+//! It defines the structures for its request data.
+
+use serde::Deserialize;
+
+use crate::rdlib::access::grant::{AccessRole, SubjectType};
+
+/// Request model for granting a role.
+#[derive(Deserialize)]
+pub struct CreateGrantRequest {
+    /// Whether the role is granted to a user or a group
+    pub subject_type: SubjectType,
+    /// The name of the user or group
+    pub subject: String,
+    /// The bucket the role is granted on
+    pub bucket: String,
+    /// The prefix the role is granted on, the whole bucket unless set
+    #[serde(default)]
+    pub prefix: String,
+    /// The granted role
+    pub role: AccessRole,
+}
+
+/// Request model for creating a group.
+#[derive(Deserialize)]
+pub struct CreateGroupRequest {
+    /// The name of the group
+    pub name: String,
+    /// What the group is for
+    #[serde(default)]
+    pub description: String,
+}
+
+/// Request model for adding a member to a group.
+#[derive(Deserialize)]
+pub struct AddMemberRequest {
+    /// Whether the member is a user or a group
+    pub member_type: SubjectType,
+    /// The name of the user or group
+    pub member: String,
+}
@@ -0,0 +1,42 @@
+//! # API Key Data Models
+//!
+//! This module contains the data models of service account and API key
+//! management. It defines the structures for their request and response
+//! data.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::rdlib::apikeys::key::{ApiKey, KeyScope};
+
+/// Request model for creating a service account.
+#[derive(Deserialize)]
+pub struct CreateServiceAccountRequest {
+    /// The name of the service account
+    pub name: String,
+    /// What the service account is for
+    #[serde(default)]
+    pub description: String,
+}
+
+/// Request model for creating an API key.
+#[derive(Deserialize)]
+pub struct CreateApiKeyRequest {
+    /// What the key is used for
+    pub name: String,
+    /// The limits of the key, none to let it do what its account may
+    #[serde(default)]
+    pub scopes: Vec<KeyScope>,
+    /// When the key stops working, never unless set
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// Response model of a created API key, the only time its secret is shown.
+#[derive(Serialize)]
+pub struct CreatedApiKey {
+    /// The stored key
+    #[serde(flatten)]
+    pub api_key: ApiKey,
+    /// The secret to authenticate with
+    pub key: String,
+}
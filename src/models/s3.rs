@@ -21,4 +21,39 @@ pub struct CreateFolderRequest {
 pub struct CreateBucketRequest {
     /// The name of the bucket to create
     pub name: String,
-} 
\ No newline at end of file
+} 
+
+/// Request model for creating an anonymous upload ("file drop") link.
+///
+/// This structure represents the request body for the create drop link API endpoint.
+#[derive(Deserialize)]
+pub struct CreateDropLinkRequest {
+    /// The prefix (folder) uploads are stored under
+    pub prefix: String,
+    /// Number of seconds after which the link expires
+    pub expires_in: Option<u64>,
+    /// The maximum size of a single uploaded file in bytes
+    pub max_file_size: Option<u64>,
+    /// The file extensions accepted by the link, e.g. `["pdf", "docx"]`
+    pub allowed_extensions: Option<Vec<String>>,
+    /// The maximum number of files the link accepts
+    pub max_uploads: Option<u32>,
+}
+
+/// Request model for downloading objects as a ZIP archive.
+///
+/// This structure represents the request body for the archive API endpoint.
+#[derive(Deserialize)]
+pub struct ArchiveRequest {
+    /// The keys of the objects to include
+    #[serde(default)]
+    pub keys: Vec<String>,
+    /// The prefixes (folders) whose objects are included, including nested folders
+    #[serde(default)]
+    pub prefixes: Vec<String>,
+    /// The folder that paths inside the archive are relative to; defaults to the bucket root
+    #[serde(default)]
+    pub root: String,
+    /// The file name of the archive, without the `.zip` extension
+    pub name: Option<String>,
+}
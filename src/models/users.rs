@@ -0,0 +1,72 @@
+//! # User Data Models
+//!
+//! This module contains the data models of logins, two-factor
+//! authentication and user management.
+//! It defines the structures for their request data.
+
+use serde::Deserialize;
+
+use crate::rdlib::users::user::Role;
+
+/// Request model for logging in with a password.
+#[derive(Deserialize)]
+pub struct LoginRequest {
+    /// The name of the user
+    pub username: String,
+    /// The password of the user
+    pub password: String,
+    /// The code from the authenticator app, or a recovery code, if the
+    /// user has two-factor authentication enabled
+    pub otp: Option<String>,
+}
+
+/// Request model for managing the two-factor authentication of a user,
+/// who confirms it with its password.
+#[derive(Deserialize)]
+pub struct TwoFactorRequest {
+    /// The name of the user
+    pub username: String,
+    /// The password of the user
+    pub password: String,
+    /// The code from the authenticator app, or a recovery code; not needed for a setup token
+    pub otp: Option<String>,
+}
+
+/// Request model for setting up two-factor authentication with the setup
+/// token handed out by a login.
+#[derive(Deserialize)]
+pub struct TwoFactorSetupRequest {
+    /// The setup token
+    pub setup_token: String,
+    /// The code from the authenticator app; not needed for creating the secret
+    pub otp: Option<String>,
+}
+
+/// Request model for refreshing tokens or logging out.
+#[derive(Deserialize)]
+pub struct RefreshTokenRequest {
+    /// The refresh token returned at login
+    pub refresh_token: String,
+}
+
+/// Request model for creating a user.
+#[derive(Deserialize)]
+pub struct CreateUserRequest {
+    /// The name the user logs in with
+    pub username: String,
+    /// The password of the user
+    pub password: String,
+    /// What the user is allowed to do, `user` unless set
+    pub role: Option<Role>,
+}
+
+/// Request model for changing a user; fields that are not set stay unchanged.
+#[derive(Deserialize)]
+pub struct UpdateUserRequest {
+    /// The new password
+    pub password: Option<String>,
+    /// The new role
+    pub role: Option<Role>,
+    /// Whether the user is prevented from logging in
+    pub disabled: Option<bool>,
+}
@@ -3,4 +3,14 @@
 //! This module contains the core functionality of the RustDok server.
 //! It provides utilities for interacting with S3-compatible storage services.
 
-pub mod s3; 
\ No newline at end of file
+pub mod s3;
+pub mod content;
+pub mod imaging;
+pub mod archive;
+pub mod idempotency;
+pub mod auth;
+pub mod users;
+pub mod access;
+pub mod apikeys;
+pub mod oidc;
+pub mod audit;
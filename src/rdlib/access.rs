@@ -0,0 +1,13 @@
+//! # Access Control Module
+//!
+//! This module provides role-based access control: users and groups are
+//! granted roles on buckets, or on prefixes within them, which are
+//! inherited by everything below. Groups can contain users and other
+//! groups. Grants and groups are stored in MySQL next to the user accounts.
+
+pub mod grant;
+pub mod group;
+pub mod error;
+pub mod store;
+pub mod mysql;
+pub mod authorizer;
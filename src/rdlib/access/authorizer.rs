@@ -0,0 +1,402 @@
+//! # Authorization
+//!
+//! This module decides what a principal may do. The `Authorizer` looks up
+//! the grants of a principal and of its groups: the groups it is a member
+//! of, the groups named in its token, and the groups containing those,
+//! however deeply nested. The resulting `Access` answers which role the
+//! principal has on a key and which buckets and folders it can see.
+//!
+//! A role on a prefix applies to everything below it. Where several grants
+//! apply, the highest role wins. The folders leading to a granted prefix
+//! are visible, so clients can navigate to it, but their other contents
+//! are not.
+//!
+//! Requests made with a scoped API key are further limited to the scopes
+//! of the key: the role on a key is the lower of the granted role and the
+//! highest role a covering scope allows.
+
+use std::collections::BTreeSet;
+use std::env;
+use std::sync::Arc;
+
+use chrono::Utc;
+use log::{info, warn};
+use serde::Serialize;
+
+use crate::rdlib::access::error::AccessError;
+use crate::rdlib::access::grant::{
+    normalize_prefix, validate_subject, AccessRole, Grant, GrantFilter, NewGrant, Permission, SubjectType
+};
+use crate::rdlib::access::group::{validate_description, validate_group_name, Group, GroupMember, NewGroup, MAX_NESTING_DEPTH};
+use crate::rdlib::access::mysql::{MySqlGrantStore, MySqlGroupStore};
+use crate::rdlib::access::store::{GrantStore, GroupStore};
+use crate::rdlib::apikeys::key::KeyScope;
+use crate::rdlib::auth::principal::Principal;
+use crate::rdlib::s3::service::S3Service;
+use crate::rdlib::s3::types::S3Object;
+use crate::rdlib::users::mysql::connect_pool;
+
+/// What a principal may do with buckets and objects.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Access {
+    /// The grants of the principal, or `None` if it may do anything
+    grants: Option<Vec<Grant>>,
+    /// The scopes limiting the principal, or `None` if it is not limited
+    scopes: Option<Vec<KeyScope>>,
+}
+
+impl Access {
+    /// Creates the access of a principal that may do anything.
+    pub fn unrestricted() -> Self {
+        Self { grants: None, scopes: None }
+    }
+
+    /// Creates the access of a principal with the given grants.
+    pub fn from_grants(grants: Vec<Grant>) -> Self {
+        Self { grants: Some(grants), scopes: None }
+    }
+
+    /// Limits the access to the scopes of an API key.
+    pub fn with_scopes(mut self, scopes: Option<Vec<KeyScope>>) -> Self {
+        self.scopes = scopes;
+        self
+    }
+
+    /// Checks if the principal may do anything.
+    pub fn is_unrestricted(&self) -> bool {
+        self.grants.is_none() && self.scopes.is_none()
+    }
+
+    /// The highest role the principal has on a key.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket` - The bucket of the key
+    /// * `key` - The key of an object, or a prefix ending with `/`
+    ///
+    /// # Returns
+    ///
+    /// The role, or `None` if no grant applies to the key
+    pub fn role(&self, bucket: &str, key: &str) -> Option<AccessRole> {
+        let granted = match &self.grants {
+            None => Some(AccessRole::Admin),
+            Some(grants) => grants.iter().filter(|grant| grant.covers(bucket, key)).map(|grant| grant.role).max(),
+        };
+        match &self.scopes {
+            None => granted,
+            Some(scopes) => {
+                let allowed = scopes.iter().filter(|scope| scope.covers(bucket, key)).map(|scope| scope.role).max();
+                granted.min(allowed)
+            },
+        }
+    }
+
+    /// Checks if the principal has a permission on a key.
+    pub fn allows(&self, bucket: &str, key: &str, permission: Permission) -> bool {
+        self.role(bucket, key).is_some_and(|role| role.allows(permission))
+    }
+
+    /// Checks if the principal can see a bucket, which is the case if it
+    /// has a role on the bucket or on any prefix in it.
+    pub fn can_see_bucket(&self, bucket: &str) -> bool {
+        let granted = match &self.grants {
+            None => true,
+            Some(grants) => grants.iter().any(|grant| grant.bucket == bucket),
+        };
+        granted && self.scopes.as_ref().is_none_or(|scopes| {
+            scopes.iter().any(|scope| scope.bucket.as_deref().is_none_or(|b| b == bucket))
+        })
+    }
+
+    /// Checks if the principal can see a key: the key is readable, or it
+    /// is a folder leading to a prefix the principal has a role on.
+    pub fn can_see(&self, bucket: &str, key: &str) -> bool {
+        let is_folder = key.is_empty() || key.ends_with('/');
+        let granted = match &self.grants {
+            None => true,
+            Some(grants) => grants.iter().any(|grant| {
+                grant.bucket == bucket && (key.starts_with(&grant.prefix) || (is_folder && grant.prefix.starts_with(key)))
+            }),
+        };
+        granted && self.scopes.as_ref().is_none_or(|scopes| scopes.iter().any(|scope| scope.reveals(bucket, key)))
+    }
+
+    /// The grants that apply to a key.
+    pub fn grants_covering(&self, bucket: &str, key: &str) -> Vec<Grant> {
+        match &self.grants {
+            None => Vec::new(),
+            Some(grants) => grants.iter().filter(|grant| grant.covers(bucket, key)).cloned().collect(),
+        }
+    }
+
+    /// Removes the buckets the principal cannot see.
+    pub fn filter_buckets(&self, buckets: Vec<String>) -> Vec<String> {
+        buckets.into_iter().filter(|bucket| self.can_see_bucket(bucket)).collect()
+    }
+
+    /// Removes the objects and folders of a listing that the principal cannot see.
+    pub fn filter_objects(&self, bucket: &str, objects: Vec<S3Object>) -> Vec<S3Object> {
+        objects.into_iter().filter(|object| self.can_see(bucket, &object.name)).collect()
+    }
+}
+
+/// What a user may do with a key, and why.
+#[derive(Clone, Debug, Serialize)]
+pub struct EffectiveAccess {
+    /// The user
+    pub user: String,
+    /// The bucket
+    pub bucket: String,
+    /// The normalized prefix, ending with `/`, or empty for the whole bucket
+    pub prefix: String,
+    /// Whether the user is an administrator, who may do anything
+    pub administrator: bool,
+    /// The groups of the user, including the groups containing them
+    pub groups: Vec<String>,
+    /// The highest role of the user on the key
+    pub role: Option<AccessRole>,
+    /// The grants that apply to the key
+    pub grants: Vec<Grant>,
+}
+
+/// Looks up and manages grants and groups.
+pub struct Authorizer {
+    store: Arc<dyn GrantStore>,
+    groups: Arc<dyn GroupStore>,
+}
+
+impl Authorizer {
+    /// Creates the authorizer.
+    ///
+    /// # Arguments
+    ///
+    /// * `store` - Where grants are kept
+    /// * `groups` - Where groups are kept
+    pub fn new(store: Arc<dyn GrantStore>, groups: Arc<dyn GroupStore>) -> Self {
+        Self { store, groups }
+    }
+
+    /// Creates the authorizer from the environment.
+    ///
+    /// Grants and groups are kept in the database of `RUSTDOK_DATABASE_URL`,
+    /// whose schema is created by `UserService::from_env`.
+    ///
+    /// # Returns
+    ///
+    /// The authorizer, `None` if no database is configured, or an error if
+    /// the database cannot be reached
+    pub async fn from_env() -> Result<Option<Self>, String> {
+        let Ok(url) = env::var("RUSTDOK_DATABASE_URL") else {
+            info!("RUSTDOK_DATABASE_URL is not set; only administrators have access to buckets");
+            return Ok(None);
+        };
+        let pool = connect_pool(&url).await.map_err(|e| format!("Cannot connect to the grant database: {}", e))?;
+        Ok(Some(Self::new(Arc::new(MySqlGrantStore::new(pool.clone())), Arc::new(MySqlGroupStore::new(pool)))))
+    }
+
+    /// Looks up what a principal may do. Administrators may do anything
+    /// within the scopes of their key. Grants and memberships are looked up
+    /// by the grant subject of the principal.
+    pub async fn access_for(&self, principal: &Principal) -> Result<Access, AccessError> {
+        if principal.is_admin() {
+            return Ok(Access::unrestricted().with_scopes(principal.scopes()));
+        }
+        let subject = principal.grant_subject();
+        let claimed = principal.groups().into_iter().map(str::to_string).collect::<Vec<_>>();
+        let groups = self.effective_groups(&subject, &claimed).await?;
+        let grants = self.store.grants_for(&subject, &groups).await?;
+        Ok(Access::from_grants(grants).with_scopes(principal.scopes()))
+    }
+
+    /// Resolves the groups of a user: the groups it is a member of and the
+    /// claimed ones, plus the groups containing any of them, up to
+    /// `MAX_NESTING_DEPTH` levels.
+    ///
+    /// # Arguments
+    ///
+    /// * `user` - The name of the user
+    /// * `claimed` - The groups named in the token of the user
+    ///
+    /// # Returns
+    ///
+    /// The names of the groups, sorted
+    pub async fn effective_groups(&self, user: &str, claimed: &[String]) -> Result<Vec<String>, AccessError> {
+        let mut level = self.groups.parent_groups(SubjectType::User, &[user.to_string()]).await?
+            .into_iter()
+            .chain(claimed.iter().cloned())
+            .collect::<BTreeSet<_>>();
+        let mut groups = level.clone();
+
+        for _ in 0..MAX_NESTING_DEPTH {
+            if level.is_empty() {
+                break;
+            }
+            let members = level.into_iter().collect::<Vec<_>>();
+            level = self.groups.parent_groups(SubjectType::Group, &members).await?
+                .into_iter()
+                .filter(|group| groups.insert(group.clone()))
+                .collect();
+        }
+        if !level.is_empty() {
+            warn!("Groups of '{}' are nested more than {} levels deep; deeper groups are ignored", user, MAX_NESTING_DEPTH);
+        }
+        Ok(groups.into_iter().collect())
+    }
+
+    /// Explains what a user may do with a key: its groups, its role and the
+    /// grants that apply.
+    ///
+    /// # Arguments
+    ///
+    /// * `user` - The name of the user
+    /// * `administrator` - Whether the user is an administrator, who may do anything
+    /// * `bucket` - The bucket of the key
+    /// * `prefix` - A folder, or empty for the whole bucket; it is normalized like the prefix of a grant
+    pub async fn effective_access(&self, user: &str, administrator: bool, bucket: &str, prefix: &str) -> Result<EffectiveAccess, AccessError> {
+        validate_subject(user).map_err(AccessError::InvalidSubject)?;
+        S3Service::validate_bucket_name(bucket).map_err(AccessError::InvalidBucket)?;
+        let prefix = normalize_prefix(prefix).map_err(AccessError::InvalidPrefix)?;
+        let prefix = prefix.as_str();
+
+        let groups = self.effective_groups(user, &[]).await?;
+        let access = Access::from_grants(self.store.grants_for(user, &groups).await?);
+        let role = if administrator { Some(AccessRole::Admin) } else { access.role(bucket, prefix) };
+        Ok(EffectiveAccess {
+            user: user.to_string(),
+            bucket: bucket.to_string(),
+            prefix: prefix.to_string(),
+            administrator,
+            groups,
+            role,
+            grants: access.grants_covering(bucket, prefix),
+        })
+    }
+
+    /// Lists the grants matching a filter.
+    pub async fn list_grants(&self, filter: &GrantFilter) -> Result<Vec<Grant>, AccessError> {
+        self.store.list_grants(filter).await
+    }
+
+    /// Grants a role to a user or group, replacing the role it has on the
+    /// same bucket and prefix.
+    ///
+    /// # Returns
+    ///
+    /// The grant and whether it is new
+    pub async fn put_grant(&self, grant: NewGrant) -> Result<(Grant, bool), AccessError> {
+        validate_subject(&grant.subject).map_err(AccessError::InvalidSubject)?;
+        S3Service::validate_bucket_name(&grant.bucket).map_err(AccessError::InvalidBucket)?;
+        let prefix = normalize_prefix(&grant.prefix).map_err(AccessError::InvalidPrefix)?;
+        if S3Service::is_system_key(&prefix) {
+            return Err(AccessError::InvalidPrefix(format!("Prefix '{}' is reserved", prefix)));
+        }
+
+        self.store.put_grant(NewGrant { prefix, ..grant }, Utc::now()).await
+    }
+
+    /// Deletes a grant.
+    pub async fn delete_grant(&self, id: u64) -> Result<(), AccessError> {
+        if self.store.delete_grant(id).await? { Ok(()) } else { Err(AccessError::NotFound) }
+    }
+
+    /// Deletes the grants and group memberships of a deleted user, so they
+    /// do not pass on to a new user of the same name.
+    pub async fn forget_user(&self, username: &str) -> Result<(), AccessError> {
+        self.store.delete_subject_grants(SubjectType::User, username).await?;
+        self.groups.delete_memberships(SubjectType::User, username).await?;
+        Ok(())
+    }
+
+    /// Lists all groups.
+    pub async fn list_groups(&self) -> Result<Vec<Group>, AccessError> {
+        self.groups.list_groups().await
+    }
+
+    /// Gets a group by ID.
+    pub async fn get_group(&self, id: u64) -> Result<Group, AccessError> {
+        self.groups.get_group(id).await?.ok_or(AccessError::GroupNotFound)
+    }
+
+    /// Creates a group.
+    pub async fn create_group(&self, name: &str, description: &str) -> Result<Group, AccessError> {
+        validate_group_name(name).map_err(AccessError::InvalidGroupName)?;
+        validate_description(description).map_err(AccessError::InvalidDescription)?;
+        let group = NewGroup { name: name.to_string(), description: description.to_string() };
+        self.groups.create_group(group, Utc::now()).await
+    }
+
+    /// Deletes a group, its grants and its membership in other groups.
+    ///
+    /// # Returns
+    ///
+    /// The deleted group
+    pub async fn delete_group(&self, id: u64) -> Result<Group, AccessError> {
+        let group = self.get_group(id).await?;
+        if !self.groups.delete_group(id).await? {
+            return Err(AccessError::GroupNotFound);
+        }
+        Ok(group)
+    }
+
+    /// Lists the members of a group.
+    pub async fn list_members(&self, id: u64) -> Result<Vec<GroupMember>, AccessError> {
+        self.get_group(id).await?;
+        self.groups.list_members(id).await
+    }
+
+    /// Adds a user or group to a group. A group cannot become a member of
+    /// itself or of a group it contains.
+    ///
+    /// # Returns
+    ///
+    /// Whether the user or group was not a member yet
+    pub async fn add_member(&self, id: u64, member_type: SubjectType, member: &str) -> Result<bool, AccessError> {
+        match member_type {
+            SubjectType::User => {
+                validate_subject(member).map_err(AccessError::InvalidMember)?;
+                self.get_group(id).await?;
+                self.groups.add_member(id, member_type, member, Utc::now()).await
+            },
+            SubjectType::Group => self.groups.add_member_group(id, member, Utc::now()).await,
+        }
+    }
+
+    /// Removes a user or group from a group.
+    pub async fn remove_member(&self, id: u64, member_type: SubjectType, member: &str) -> Result<(), AccessError> {
+        self.get_group(id).await?;
+        if self.groups.remove_member(id, member_type, member).await? { Ok(()) } else { Err(AccessError::MemberNotFound) }
+    }
+
+    /// Makes a user a direct member of exactly the groups its identity
+    /// provider names, as far as they exist; groups unknown to RustDok are
+    /// not created. The provider is authoritative for the memberships of
+    /// users logging in through it, so memberships added by administrators
+    /// are removed too.
+    ///
+    /// # Returns
+    ///
+    /// The names of the groups the user is now a direct member of, sorted
+    pub async fn sync_groups(&self, user: &str, claimed: &[String]) -> Result<Vec<String>, AccessError> {
+        let current = self.groups.parent_groups(SubjectType::User, &[user.to_string()]).await?
+            .into_iter()
+            .collect::<BTreeSet<_>>();
+        let mut synced = BTreeSet::new();
+        let now = Utc::now();
+
+        for name in claimed.iter().collect::<BTreeSet<_>>() {
+            let Some(group) = self.groups.find_group(name).await? else {
+                continue;
+            };
+            if !current.contains(name) {
+                self.groups.add_member(group.id, SubjectType::User, user, now).await?;
+            }
+            synced.insert(group.name);
+        }
+        for name in current.difference(&synced) {
+            if let Some(group) = self.groups.find_group(name).await? {
+                self.groups.remove_member(group.id, SubjectType::User, user).await?;
+            }
+        }
+        Ok(synced.into_iter().collect())
+    }
+}
@@ -0,0 +1,59 @@
+//! # Access Control Errors
+//!
+//! This module defines the errors of managing and looking up grants and
+//! groups.
+
+use std::fmt;
+
+/// Errors of access control.
+#[derive(Clone, Debug, PartialEq)]
+pub enum AccessError {
+    /// The subject of a grant is not acceptable
+    InvalidSubject(String),
+    /// The bucket of a grant is not acceptable
+    InvalidBucket(String),
+    /// The prefix of a grant is not acceptable
+    InvalidPrefix(String),
+    /// The name of a group is not acceptable
+    InvalidGroupName(String),
+    /// The description of a group is not acceptable
+    InvalidDescription(String),
+    /// The member cannot be added to the group
+    InvalidMember(String),
+    /// The grant does not exist
+    NotFound,
+    /// The group does not exist
+    GroupNotFound,
+    /// The user or group is not a member of the group
+    MemberNotFound,
+    /// A group with the name already exists
+    GroupTaken(String),
+    /// The database failed
+    Database(String),
+}
+
+impl fmt::Display for AccessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AccessError::InvalidSubject(msg)
+            | AccessError::InvalidBucket(msg)
+            | AccessError::InvalidPrefix(msg)
+            | AccessError::InvalidGroupName(msg)
+            | AccessError::InvalidDescription(msg)
+            | AccessError::InvalidMember(msg) => f.write_str(msg),
+            AccessError::NotFound => f.write_str("Grant not found"),
+            AccessError::GroupNotFound => f.write_str("Group not found"),
+            AccessError::MemberNotFound => f.write_str("Member not found"),
+            AccessError::GroupTaken(name) => write!(f, "Group '{}' already exists", name),
+            AccessError::Database(msg) => write!(f, "Database error: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for AccessError {}
+
+impl From<sqlx::Error> for AccessError {
+    fn from(err: sqlx::Error) -> Self {
+        AccessError::Database(err.to_string())
+    }
+}
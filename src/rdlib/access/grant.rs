@@ -0,0 +1,224 @@
+//! # Grants
+//!
+//! This module defines the roles that can be granted on buckets and
+//! prefixes, the permissions they include and the rules for the subjects
+//! and prefixes of grants.
+//!
+//! Roles are ordered: each includes the permissions of the ones before it.
+//!
+//! | Role | Permissions |
+//! |------|-------------|
+//! | `viewer` | List, download, view |
+//! | `uploader` | Add objects and folders, without replacing existing objects |
+//! | `editor` | Replace, move and delete objects |
+//! | `admin` | Manage drop links and upload policies, delete the bucket |
+
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// The longest allowed subject of a grant
+pub const MAX_SUBJECT_LENGTH: usize = 128;
+
+/// The longest allowed prefix of a grant
+pub const MAX_PREFIX_LENGTH: usize = 512;
+
+/// A role on a bucket or prefix.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AccessRole {
+    /// May list and read objects
+    Viewer,
+    /// May also add objects and folders
+    Uploader,
+    /// May also replace, move and delete objects
+    Editor,
+    /// May also manage the settings of the bucket
+    Admin,
+}
+
+impl AccessRole {
+    /// The role as it is stored.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AccessRole::Viewer => "viewer",
+            AccessRole::Uploader => "uploader",
+            AccessRole::Editor => "editor",
+            AccessRole::Admin => "admin",
+        }
+    }
+
+    /// Checks if the role includes a permission.
+    pub fn allows(&self, permission: Permission) -> bool {
+        *self >= permission.minimum_role()
+    }
+}
+
+impl fmt::Display for AccessRole {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for AccessRole {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "viewer" => Ok(AccessRole::Viewer),
+            "uploader" => Ok(AccessRole::Uploader),
+            "editor" => Ok(AccessRole::Editor),
+            "admin" => Ok(AccessRole::Admin),
+            other => Err(format!("Unknown role '{}'", other)),
+        }
+    }
+}
+
+/// What a request does with the objects it touches.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Permission {
+    /// List, download or view objects
+    Read,
+    /// Add objects or folders
+    Write,
+    /// Replace, move or delete objects
+    Delete,
+    /// Change the settings of a bucket or delete it
+    Manage,
+}
+
+impl Permission {
+    /// The least role that includes the permission.
+    pub fn minimum_role(&self) -> AccessRole {
+        match self {
+            Permission::Read => AccessRole::Viewer,
+            Permission::Write => AccessRole::Uploader,
+            Permission::Delete => AccessRole::Editor,
+            Permission::Manage => AccessRole::Admin,
+        }
+    }
+}
+
+/// Who a grant is for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SubjectType {
+    /// A user, named by the subject of its tokens
+    User,
+    /// A group, named in the `groups` claim of its members' tokens
+    Group,
+}
+
+impl SubjectType {
+    /// The subject type as it is stored.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SubjectType::User => "user",
+            SubjectType::Group => "group",
+        }
+    }
+}
+
+impl fmt::Display for SubjectType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for SubjectType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "user" => Ok(SubjectType::User),
+            "group" => Ok(SubjectType::Group),
+            other => Err(format!("Unknown subject type '{}'", other)),
+        }
+    }
+}
+
+/// A role granted to a user or group on a bucket or prefix.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Grant {
+    /// The ID of the grant
+    pub id: u64,
+    /// Whether the grant is for a user or a group
+    pub subject_type: SubjectType,
+    /// The name of the user or group
+    pub subject: String,
+    /// The bucket the role is granted on
+    pub bucket: String,
+    /// The prefix the role is granted on, ending with `/`, or empty for the whole bucket
+    pub prefix: String,
+    /// The granted role
+    pub role: AccessRole,
+    /// When the role was granted
+    pub created_at: DateTime<Utc>,
+}
+
+impl Grant {
+    /// Checks if the grant applies to a key, which is the case for keys
+    /// at or below its prefix.
+    pub fn covers(&self, bucket: &str, key: &str) -> bool {
+        self.bucket == bucket && key.starts_with(&self.prefix)
+    }
+}
+
+/// A grant to be created or changed.
+#[derive(Clone, Debug)]
+pub struct NewGrant {
+    pub subject_type: SubjectType,
+    pub subject: String,
+    pub bucket: String,
+    pub prefix: String,
+    pub role: AccessRole,
+}
+
+/// Which grants to list; fields that are `None` match every grant.
+#[derive(Clone, Debug, Default)]
+pub struct GrantFilter {
+    pub subject_type: Option<SubjectType>,
+    pub subject: Option<String>,
+    pub bucket: Option<String>,
+}
+
+/// Checks if the subject of a grant is acceptable: 1 to 128 characters
+/// without control characters.
+///
+/// # Returns
+///
+/// `Ok(())`, or why the subject is not acceptable
+pub fn validate_subject(subject: &str) -> Result<(), String> {
+    if subject.is_empty() || subject.chars().count() > MAX_SUBJECT_LENGTH {
+        return Err(format!("Subject must be 1 to {} characters long", MAX_SUBJECT_LENGTH));
+    }
+    if subject.chars().any(char::is_control) {
+        return Err("Subject must not contain control characters".to_string());
+    }
+    Ok(())
+}
+
+/// Normalizes the prefix of a grant, so that it names a folder: leading
+/// slashes are removed and a trailing slash is added. The empty prefix
+/// stands for the whole bucket.
+///
+/// # Returns
+///
+/// The normalized prefix, or why the prefix is not acceptable
+pub fn normalize_prefix(prefix: &str) -> Result<String, String> {
+    let prefix = prefix.trim_start_matches('/');
+    if prefix.is_empty() {
+        return Ok(String::new());
+    }
+    let prefix = if prefix.ends_with('/') { prefix.to_string() } else { format!("{}/", prefix) };
+
+    if prefix.chars().count() > MAX_PREFIX_LENGTH {
+        return Err(format!("Prefix must be at most {} characters long", MAX_PREFIX_LENGTH));
+    }
+    if prefix.trim_end_matches('/').split('/').any(|segment| segment.is_empty() || segment == "." || segment == "..") {
+        return Err(format!("Prefix '{}' contains an empty, '.' or '..' segment", prefix));
+    }
+    Ok(prefix)
+}
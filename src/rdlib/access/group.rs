@@ -0,0 +1,105 @@
+//! # Groups
+//!
+//! This module defines the groups that roles can be granted to. Members of
+//! a group are users, named by their grant subject (see `Principal`), or other
+//! groups. Members of a nested group are members of every group that
+//! contains it, so a role granted to `engineering` also applies to the
+//! members of its member group `backend`.
+//!
+//! Groups named in the `groups` claim of a token count like groups the
+//! user is a member of, including the groups that contain them.
+
+use std::collections::BTreeSet;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::rdlib::access::grant::SubjectType;
+
+/// The longest allowed group name
+pub const MAX_GROUP_NAME_LENGTH: usize = 64;
+
+/// The longest allowed group description
+pub const MAX_DESCRIPTION_LENGTH: usize = 512;
+
+/// How deeply groups may be nested; membership is not followed further
+pub const MAX_NESTING_DEPTH: usize = 16;
+
+/// A group of users and other groups.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Group {
+    /// The ID of the group
+    pub id: u64,
+    /// The name of the group, used in grants and the `groups` claim
+    pub name: String,
+    /// What the group is for
+    pub description: String,
+    /// When the group was created
+    pub created_at: DateTime<Utc>,
+}
+
+/// A member of a group.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct GroupMember {
+    /// Whether the member is a user or a group
+    pub member_type: SubjectType,
+    /// The name of the user or group
+    pub member: String,
+    /// When the member was added
+    pub added_at: DateTime<Utc>,
+}
+
+/// A group to be created.
+#[derive(Clone, Debug)]
+pub struct NewGroup {
+    pub name: String,
+    pub description: String,
+}
+
+/// Checks if making `member` a member of `group` would make a group contain
+/// itself: `member` is `group` or one of the groups containing it.
+///
+/// # Arguments
+///
+/// * `nesting` - The groups that are members of groups, as pairs of the containing group and its member
+/// * `group` - The group to add the member to
+/// * `member` - The group to add
+pub fn would_contain_itself(nesting: &[(String, String)], group: &str, member: &str) -> bool {
+    let mut containing = BTreeSet::from([group]);
+    let mut level = vec![group];
+    while !level.is_empty() {
+        level = nesting.iter()
+            .filter(|(_, child)| level.contains(&child.as_str()))
+            .map(|(parent, _)| parent.as_str())
+            .filter(|parent| containing.insert(parent))
+            .collect();
+    }
+    containing.contains(member)
+}
+
+/// Checks if a group name is acceptable: 1 to 64 letters, digits, `.`,
+/// `_` or `-`, starting with a letter or digit.
+///
+/// # Returns
+///
+/// `Ok(())`, or why the name is not acceptable
+pub fn validate_group_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || name.len() > MAX_GROUP_NAME_LENGTH {
+        return Err(format!("Group name must be 1 to {} characters long", MAX_GROUP_NAME_LENGTH));
+    }
+    if !name.starts_with(|c: char| c.is_ascii_alphanumeric()) {
+        return Err("Group name must start with a letter or digit".to_string());
+    }
+    if !name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-')) {
+        return Err("Group name may only contain letters, digits, '.', '_' and '-'".to_string());
+    }
+    Ok(())
+}
+
+/// Checks if a group description is acceptable: at most 512 characters.
+pub fn validate_description(description: &str) -> Result<(), String> {
+    if description.chars().count() > MAX_DESCRIPTION_LENGTH {
+        return Err(format!("Description must be at most {} characters long", MAX_DESCRIPTION_LENGTH));
+    }
+    Ok(())
+}
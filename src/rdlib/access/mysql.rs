@@ -0,0 +1,366 @@
+//! # MySQL Grant and Group Stores
+//!
+//! This module provides the `GrantStore` and `GroupStore` backed by MySQL.
+//! Their tables are created by the migrations that `MySqlUserStore::migrate`
+//! runs.
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::mysql::MySqlPool;
+use sqlx::{FromRow, QueryBuilder};
+
+use crate::rdlib::access::error::AccessError;
+use crate::rdlib::access::grant::{AccessRole, Grant, GrantFilter, NewGrant, SubjectType};
+use crate::rdlib::access::group::{would_contain_itself, Group, GroupMember, NewGroup};
+use crate::rdlib::access::store::{GrantStore, GroupStore};
+
+const GRANT_COLUMNS: &str = "id, subject_type, subject, bucket, prefix, role, created_at";
+
+const GROUP_COLUMNS: &str = "id, name, description, created_at";
+
+/// A row of the `grants` table.
+#[derive(FromRow)]
+struct GrantRow {
+    id: u64,
+    subject_type: String,
+    subject: String,
+    bucket: String,
+    prefix: String,
+    role: String,
+    created_at: DateTime<Utc>,
+}
+
+impl TryFrom<GrantRow> for Grant {
+    type Error = AccessError;
+
+    fn try_from(row: GrantRow) -> Result<Self, Self::Error> {
+        Ok(Grant {
+            id: row.id,
+            subject_type: row.subject_type.parse::<SubjectType>().map_err(AccessError::Database)?,
+            subject: row.subject,
+            bucket: row.bucket,
+            prefix: row.prefix,
+            role: row.role.parse::<AccessRole>().map_err(AccessError::Database)?,
+            created_at: row.created_at,
+        })
+    }
+}
+
+/// A row of the `access_groups` table.
+#[derive(FromRow)]
+struct GroupRow {
+    id: u64,
+    name: String,
+    description: String,
+    created_at: DateTime<Utc>,
+}
+
+impl From<GroupRow> for Group {
+    fn from(row: GroupRow) -> Self {
+        Group {
+            id: row.id,
+            name: row.name,
+            description: row.description,
+            created_at: row.created_at,
+        }
+    }
+}
+
+/// A row of the `group_members` table.
+#[derive(FromRow)]
+struct MemberRow {
+    member_type: String,
+    member: String,
+    added_at: DateTime<Utc>,
+}
+
+impl TryFrom<MemberRow> for GroupMember {
+    type Error = AccessError;
+
+    fn try_from(row: MemberRow) -> Result<Self, Self::Error> {
+        Ok(GroupMember {
+            member_type: row.member_type.parse::<SubjectType>().map_err(AccessError::Database)?,
+            member: row.member,
+            added_at: row.added_at,
+        })
+    }
+}
+
+/// Stores grants in MySQL.
+pub struct MySqlGrantStore {
+    pool: MySqlPool,
+}
+
+impl MySqlGrantStore {
+    /// Creates the store on a connection pool.
+    pub fn new(pool: MySqlPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl GrantStore for MySqlGrantStore {
+    async fn grants_for(&self, user: &str, groups: &[String]) -> Result<Vec<Grant>, AccessError> {
+        let mut query = QueryBuilder::new(format!("SELECT {} FROM grants WHERE (subject_type = 'user' AND subject = ", GRANT_COLUMNS));
+        query.push_bind(user).push(")");
+        if !groups.is_empty() {
+            query.push(" OR (subject_type = 'group' AND subject IN (");
+            let mut separated = query.separated(", ");
+            for group in groups {
+                separated.push_bind(group);
+            }
+            query.push("))");
+        }
+        query.push(" ORDER BY id");
+
+        query.build_query_as::<GrantRow>()
+            .fetch_all(&self.pool)
+            .await?
+            .into_iter()
+            .map(Grant::try_from)
+            .collect()
+    }
+
+    async fn list_grants(&self, filter: &GrantFilter) -> Result<Vec<Grant>, AccessError> {
+        let mut query = QueryBuilder::new(format!("SELECT {} FROM grants WHERE TRUE", GRANT_COLUMNS));
+        if let Some(subject_type) = filter.subject_type {
+            query.push(" AND subject_type = ").push_bind(subject_type.as_str());
+        }
+        if let Some(subject) = &filter.subject {
+            query.push(" AND subject = ").push_bind(subject);
+        }
+        if let Some(bucket) = &filter.bucket {
+            query.push(" AND bucket = ").push_bind(bucket);
+        }
+        query.push(" ORDER BY id");
+
+        query.build_query_as::<GrantRow>()
+            .fetch_all(&self.pool)
+            .await?
+            .into_iter()
+            .map(Grant::try_from)
+            .collect()
+    }
+
+    async fn put_grant(&self, grant: NewGrant, now: DateTime<Utc>) -> Result<(Grant, bool), AccessError> {
+        // MySQL reports 1 affected row for an insert, 2 for a changed and 0 for an unchanged row
+        let result = sqlx::query(
+            "INSERT INTO grants (subject_type, subject, bucket, prefix, role, created_at) VALUES (?, ?, ?, ?, ?, ?) \
+             ON DUPLICATE KEY UPDATE role = VALUES(role)"
+        )
+            .bind(grant.subject_type.as_str())
+            .bind(&grant.subject)
+            .bind(&grant.bucket)
+            .bind(&grant.prefix)
+            .bind(grant.role.as_str())
+            .bind(now)
+            .execute(&self.pool)
+            .await?;
+
+        let stored = sqlx::query_as::<_, GrantRow>(&format!(
+            "SELECT {} FROM grants WHERE subject_type = ? AND subject = ? AND bucket = ? AND prefix = ?", GRANT_COLUMNS
+        ))
+            .bind(grant.subject_type.as_str())
+            .bind(&grant.subject)
+            .bind(&grant.bucket)
+            .bind(&grant.prefix)
+            .fetch_optional(&self.pool)
+            .await?
+            .ok_or_else(|| AccessError::Database("Stored grant not found".to_string()))?;
+        Ok((Grant::try_from(stored)?, result.rows_affected() == 1))
+    }
+
+    async fn delete_grant(&self, id: u64) -> Result<bool, AccessError> {
+        let result = sqlx::query("DELETE FROM grants WHERE id = ?").bind(id).execute(&self.pool).await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn delete_subject_grants(&self, subject_type: SubjectType, subject: &str) -> Result<u64, AccessError> {
+        let result = sqlx::query("DELETE FROM grants WHERE subject_type = ? AND subject = ?")
+            .bind(subject_type.as_str())
+            .bind(subject)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+}
+
+/// Stores groups in MySQL.
+pub struct MySqlGroupStore {
+    pool: MySqlPool,
+}
+
+impl MySqlGroupStore {
+    /// Creates the store on a connection pool.
+    pub fn new(pool: MySqlPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl GroupStore for MySqlGroupStore {
+    async fn list_groups(&self) -> Result<Vec<Group>, AccessError> {
+        let rows = sqlx::query_as::<_, GroupRow>(&format!("SELECT {} FROM access_groups ORDER BY name", GROUP_COLUMNS))
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rows.into_iter().map(Group::from).collect())
+    }
+
+    async fn get_group(&self, id: u64) -> Result<Option<Group>, AccessError> {
+        let row = sqlx::query_as::<_, GroupRow>(&format!("SELECT {} FROM access_groups WHERE id = ?", GROUP_COLUMNS))
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.map(Group::from))
+    }
+
+    async fn find_group(&self, name: &str) -> Result<Option<Group>, AccessError> {
+        let row = sqlx::query_as::<_, GroupRow>(&format!("SELECT {} FROM access_groups WHERE name = ?", GROUP_COLUMNS))
+            .bind(name)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.map(Group::from))
+    }
+
+    async fn create_group(&self, group: NewGroup, now: DateTime<Utc>) -> Result<Group, AccessError> {
+        let result = sqlx::query("INSERT INTO access_groups (name, description, created_at) VALUES (?, ?, ?)")
+            .bind(&group.name)
+            .bind(&group.description)
+            .bind(now)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| match e {
+                sqlx::Error::Database(db) if db.is_unique_violation() => AccessError::GroupTaken(group.name.clone()),
+                e => e.into(),
+            })?;
+
+        Ok(Group {
+            id: result.last_insert_id(),
+            name: group.name,
+            description: group.description,
+            created_at: now,
+        })
+    }
+
+    async fn delete_group(&self, id: u64) -> Result<bool, AccessError> {
+        let mut tx = self.pool.begin().await?;
+        let name = sqlx::query_scalar::<_, String>("SELECT name FROM access_groups WHERE id = ? FOR UPDATE")
+            .bind(id)
+            .fetch_optional(&mut *tx)
+            .await?;
+        let Some(name) = name else {
+            return Ok(false);
+        };
+
+        // The members of the group go with it through the foreign key
+        sqlx::query("DELETE FROM group_members WHERE member_type = 'group' AND member = ?")
+            .bind(&name)
+            .execute(&mut *tx)
+            .await?;
+        // A new group of the same name must not inherit the roles
+        sqlx::query("DELETE FROM grants WHERE subject_type = 'group' AND subject = ?")
+            .bind(&name)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM access_groups WHERE id = ?").bind(id).execute(&mut *tx).await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    async fn list_members(&self, group_id: u64) -> Result<Vec<GroupMember>, AccessError> {
+        sqlx::query_as::<_, MemberRow>(
+            "SELECT member_type, member, added_at FROM group_members WHERE group_id = ? \
+             ORDER BY member_type = 'group', member"
+        )
+            .bind(group_id)
+            .fetch_all(&self.pool)
+            .await?
+            .into_iter()
+            .map(GroupMember::try_from)
+            .collect()
+    }
+
+    async fn add_member(&self, group_id: u64, member_type: SubjectType, member: &str, now: DateTime<Utc>) -> Result<bool, AccessError> {
+        let result = sqlx::query("INSERT IGNORE INTO group_members (group_id, member_type, member, added_at) VALUES (?, ?, ?, ?)")
+            .bind(group_id)
+            .bind(member_type.as_str())
+            .bind(member)
+            .bind(now)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn add_member_group(&self, group_id: u64, member: &str, now: DateTime<Utc>) -> Result<bool, AccessError> {
+        let mut tx = self.pool.begin().await?;
+        let group = sqlx::query_scalar::<_, String>("SELECT name FROM access_groups WHERE id = ? FOR UPDATE")
+            .bind(group_id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or(AccessError::GroupNotFound)?;
+        let exists = sqlx::query_scalar::<_, u64>("SELECT id FROM access_groups WHERE name = ? FOR SHARE")
+            .bind(member)
+            .fetch_optional(&mut *tx)
+            .await?
+            .is_some();
+        if !exists {
+            return Err(AccessError::InvalidMember(format!("Group '{}' does not exist", member)));
+        }
+
+        // Locking the nesting of all groups keeps concurrent additions from closing a cycle together
+        let nesting = sqlx::query_as::<_, (String, String)>(
+            "SELECT g.name, m.member FROM group_members m JOIN access_groups g ON g.id = m.group_id \
+             WHERE m.member_type = 'group' FOR UPDATE"
+        )
+            .fetch_all(&mut *tx)
+            .await?;
+        if would_contain_itself(&nesting, &group, member) {
+            return Err(AccessError::InvalidMember(format!("Group '{}' contains '{}' and cannot become its member", member, group)));
+        }
+
+        let result = sqlx::query("INSERT IGNORE INTO group_members (group_id, member_type, member, added_at) VALUES (?, 'group', ?, ?)")
+            .bind(group_id)
+            .bind(member)
+            .bind(now)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn remove_member(&self, group_id: u64, member_type: SubjectType, member: &str) -> Result<bool, AccessError> {
+        let result = sqlx::query("DELETE FROM group_members WHERE group_id = ? AND member_type = ? AND member = ?")
+            .bind(group_id)
+            .bind(member_type.as_str())
+            .bind(member)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn parent_groups(&self, member_type: SubjectType, members: &[String]) -> Result<Vec<String>, AccessError> {
+        if members.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut query = QueryBuilder::new(
+            "SELECT DISTINCT g.name FROM group_members m JOIN access_groups g ON g.id = m.group_id WHERE m.member_type = "
+        );
+        query.push_bind(member_type.as_str()).push(" AND m.member IN (");
+        let mut separated = query.separated(", ");
+        for member in members {
+            separated.push_bind(member);
+        }
+        query.push(") ORDER BY g.name");
+
+        Ok(query.build_query_scalar::<String>().fetch_all(&self.pool).await?)
+    }
+
+    async fn delete_memberships(&self, member_type: SubjectType, member: &str) -> Result<u64, AccessError> {
+        let result = sqlx::query("DELETE FROM group_members WHERE member_type = ? AND member = ?")
+            .bind(member_type.as_str())
+            .bind(member)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+}
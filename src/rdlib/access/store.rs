@@ -0,0 +1,77 @@
+//! # Grant and Group Stores
+//!
+//! This module defines the storage of grants and groups. The server keeps
+//! them in MySQL (see `mysql`); the traits let tests use in-memory
+//! substitutes.
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+use crate::rdlib::access::error::AccessError;
+use crate::rdlib::access::grant::{Grant, GrantFilter, NewGrant, SubjectType};
+use crate::rdlib::access::group::{Group, GroupMember, NewGroup};
+
+/// Storage of grants.
+#[async_trait]
+pub trait GrantStore: Send + Sync {
+    /// Gets the grants of a user and of the groups it is a member of. The
+    /// user is named by its grant subject, e.g. `alice`, `sa:backup` or
+    /// `https://idp.example.com#u-1001`, and only matches grants stored
+    /// under exactly that subject.
+    async fn grants_for(&self, user: &str, groups: &[String]) -> Result<Vec<Grant>, AccessError>;
+
+    /// Lists the grants matching a filter, ordered by ID.
+    async fn list_grants(&self, filter: &GrantFilter) -> Result<Vec<Grant>, AccessError>;
+
+    /// Grants a role, replacing the role the subject has on the same bucket
+    /// and prefix. Returns the grant and whether it is new.
+    async fn put_grant(&self, grant: NewGrant, now: DateTime<Utc>) -> Result<(Grant, bool), AccessError>;
+
+    /// Deletes a grant, returning whether it existed.
+    async fn delete_grant(&self, id: u64) -> Result<bool, AccessError>;
+
+    /// Deletes all grants of a subject, returning how many there were.
+    async fn delete_subject_grants(&self, subject_type: SubjectType, subject: &str) -> Result<u64, AccessError>;
+}
+
+/// Storage of groups and their members.
+#[async_trait]
+pub trait GroupStore: Send + Sync {
+    /// Lists all groups, ordered by name.
+    async fn list_groups(&self) -> Result<Vec<Group>, AccessError>;
+
+    /// Gets a group by ID.
+    async fn get_group(&self, id: u64) -> Result<Option<Group>, AccessError>;
+
+    /// Gets a group by name.
+    async fn find_group(&self, name: &str) -> Result<Option<Group>, AccessError>;
+
+    /// Creates a group. Fails with `GroupTaken` if the name is in use.
+    async fn create_group(&self, group: NewGroup, now: DateTime<Utc>) -> Result<Group, AccessError>;
+
+    /// Deletes a group with its members, its membership in other groups
+    /// and the grants to it, returning whether it existed.
+    async fn delete_group(&self, id: u64) -> Result<bool, AccessError>;
+
+    /// Lists the members of a group, users first, then ordered by name.
+    async fn list_members(&self, group_id: u64) -> Result<Vec<GroupMember>, AccessError>;
+
+    /// Adds a member to a group, returning whether it was not a member yet.
+    async fn add_member(&self, group_id: u64, member_type: SubjectType, member: &str, now: DateTime<Utc>) -> Result<bool, AccessError>;
+
+    /// Adds a group to a group, returning whether it was not a member yet.
+    /// Checking that the member group exists and does not contain the group
+    /// is atomic with adding it, so concurrent changes cannot create a cycle.
+    /// Fails with `GroupNotFound` if the group does not exist, or with
+    /// `InvalidMember` if the member cannot be added.
+    async fn add_member_group(&self, group_id: u64, member: &str, now: DateTime<Utc>) -> Result<bool, AccessError>;
+
+    /// Removes a member from a group, returning whether it was a member.
+    async fn remove_member(&self, group_id: u64, member_type: SubjectType, member: &str) -> Result<bool, AccessError>;
+
+    /// Gets the names of the groups that directly contain any of the members.
+    async fn parent_groups(&self, member_type: SubjectType, members: &[String]) -> Result<Vec<String>, AccessError>;
+
+    /// Removes a user or group from all groups, returning from how many.
+    async fn delete_memberships(&self, member_type: SubjectType, member: &str) -> Result<u64, AccessError>;
+}
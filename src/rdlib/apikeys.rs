@@ -0,0 +1,11 @@
+//! # API Keys Module
+//!
+//! This module provides service accounts and their long-lived API keys,
+//! the non-interactive credentials of CI pipelines and batch jobs. Keys
+//! are stored as hashes in MySQL next to the user accounts.
+
+pub mod key;
+pub mod error;
+pub mod store;
+pub mod mysql;
+pub mod service;
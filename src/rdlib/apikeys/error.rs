@@ -0,0 +1,59 @@
+//! # API Key Errors
+//!
+//! This module defines the errors of managing service accounts and their
+//! keys, and of authenticating with a key.
+
+use std::fmt;
+
+/// Errors of service accounts and API keys.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ApiKeyError {
+    /// The name of a service account or key is not acceptable
+    InvalidName(String),
+    /// The description of a service account is not acceptable
+    InvalidDescription(String),
+    /// The scopes of a key are not acceptable
+    InvalidScopes(String),
+    /// The expiry of a key is not acceptable
+    InvalidExpiry(String),
+    /// The grace period of a rotation is not acceptable
+    InvalidGracePeriod(String),
+    /// The key is unknown or revoked
+    InvalidKey,
+    /// The key has expired
+    Expired,
+    /// The service account does not exist
+    AccountNotFound,
+    /// The key does not exist
+    KeyNotFound,
+    /// A service account with the name already exists
+    NameTaken(String),
+    /// The database failed
+    Database(String),
+}
+
+impl fmt::Display for ApiKeyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiKeyError::InvalidName(msg)
+            | ApiKeyError::InvalidDescription(msg)
+            | ApiKeyError::InvalidScopes(msg)
+            | ApiKeyError::InvalidExpiry(msg)
+            | ApiKeyError::InvalidGracePeriod(msg) => f.write_str(msg),
+            ApiKeyError::InvalidKey => f.write_str("The API key is invalid or revoked"),
+            ApiKeyError::Expired => f.write_str("The API key has expired"),
+            ApiKeyError::AccountNotFound => f.write_str("Service account not found"),
+            ApiKeyError::KeyNotFound => f.write_str("API key not found"),
+            ApiKeyError::NameTaken(name) => write!(f, "Service account '{}' already exists", name),
+            ApiKeyError::Database(msg) => write!(f, "Database error: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for ApiKeyError {}
+
+impl From<sqlx::Error> for ApiKeyError {
+    fn from(err: sqlx::Error) -> Self {
+        ApiKeyError::Database(err.to_string())
+    }
+}
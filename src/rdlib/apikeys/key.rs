@@ -0,0 +1,200 @@
+//! # API Keys
+//!
+//! This module defines service accounts, the API keys they authenticate
+//! with and the scopes that limit what a key may do.
+//!
+//! A key looks like `rdk_` followed by 44 random characters. Only its
+//! SHA-256 hash is stored; the first characters are kept as `key_prefix`
+//! so administrators can tell keys apart.
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::rdlib::access::grant::{normalize_prefix, AccessRole};
+use crate::rdlib::s3::service::S3Service;
+
+/// The prefix of every API key, which tells keys apart from JWTs
+pub const API_KEY_PREFIX: &str = "rdk_";
+
+/// How many characters of a key are kept to identify it
+pub const KEY_PREFIX_LENGTH: usize = 12;
+
+/// The prefix of the subject of service accounts, which usernames cannot contain
+pub const SERVICE_ACCOUNT_SUBJECT_PREFIX: &str = "sa:";
+
+/// The longest allowed name of a service account or key
+pub const MAX_NAME_LENGTH: usize = 64;
+
+/// The longest allowed description of a service account
+pub const MAX_DESCRIPTION_LENGTH: usize = 512;
+
+/// The most scopes a key may have
+pub const MAX_SCOPES: usize = 32;
+
+/// A non-interactive client, such as a CI pipeline, that authenticates
+/// with API keys.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ServiceAccount {
+    /// The ID of the service account
+    pub id: u64,
+    /// The name of the service account
+    pub name: String,
+    /// What the service account is for
+    pub description: String,
+    /// The subject of its requests, under which it is granted roles
+    pub subject: String,
+    /// When the service account was created
+    pub created_at: DateTime<Utc>,
+}
+
+impl ServiceAccount {
+    /// The subject of the requests of a service account.
+    pub fn subject_of(name: &str) -> String {
+        format!("{}{}", SERVICE_ACCOUNT_SUBJECT_PREFIX, name)
+    }
+}
+
+/// A limit on what a key may do: at most `role` on `prefix` in `bucket`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct KeyScope {
+    /// The bucket, or `None` for every bucket
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bucket: Option<String>,
+    /// The prefix, ending with `/`, or empty for the whole bucket
+    #[serde(default)]
+    pub prefix: String,
+    /// The highest role the key may use
+    pub role: AccessRole,
+}
+
+impl KeyScope {
+    /// Checks if the scope applies to a key.
+    pub fn covers(&self, bucket: &str, key: &str) -> bool {
+        self.bucket.as_deref().is_none_or(|b| b == bucket) && key.starts_with(&self.prefix)
+    }
+
+    /// Checks if a key is visible in the scope: it is covered, or it is a
+    /// folder leading to the prefix of the scope.
+    pub fn reveals(&self, bucket: &str, key: &str) -> bool {
+        self.bucket.as_deref().is_none_or(|b| b == bucket) && (key.starts_with(&self.prefix) || self.prefix.starts_with(key))
+    }
+}
+
+/// An API key of a service account.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ApiKey {
+    /// The ID of the key
+    pub id: u64,
+    /// The ID of the service account the key belongs to
+    pub service_account_id: u64,
+    /// What the key is used for
+    pub name: String,
+    /// The first characters of the key
+    pub key_prefix: String,
+    /// The limits of the key; the key may do what its account may if empty
+    pub scopes: Vec<KeyScope>,
+    /// When the key stops working, if ever
+    pub expires_at: Option<DateTime<Utc>>,
+    /// When the key was last used, to the minute
+    pub last_used_at: Option<DateTime<Utc>>,
+    /// When the key was created
+    pub created_at: DateTime<Utc>,
+}
+
+impl ApiKey {
+    /// Checks if the key has expired.
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+}
+
+/// An API key to be stored.
+#[derive(Clone, Debug)]
+pub struct NewApiKey {
+    pub service_account_id: u64,
+    pub name: String,
+    pub key_prefix: String,
+    pub key_hash: String,
+    pub scopes: Vec<KeyScope>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// Generates a new API key.
+///
+/// # Returns
+///
+/// The key, which is shown to the client once, and the prefix it is identified by
+pub fn generate_key() -> (String, String) {
+    let key = format!(
+        "{}{}{}", API_KEY_PREFIX, URL_SAFE_NO_PAD.encode(Uuid::new_v4().as_bytes()), URL_SAFE_NO_PAD.encode(Uuid::new_v4().as_bytes())
+    );
+    let prefix = key[..KEY_PREFIX_LENGTH].to_string();
+    (key, prefix)
+}
+
+/// Checks if a credential looks like an API key rather than a JWT.
+pub fn is_api_key(credential: &str) -> bool {
+    credential.starts_with(API_KEY_PREFIX)
+}
+
+/// The hash a key is stored by, so a leaked database does not leak usable keys.
+pub fn hash_api_key(key: &str) -> String {
+    Sha256::digest(key.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Checks if the name of a service account is acceptable: 1 to 64
+/// letters, digits, `.`, `_` or `-`, starting with a letter or digit.
+///
+/// # Returns
+///
+/// `Ok(())`, or why the name is not acceptable
+pub fn validate_account_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || name.len() > MAX_NAME_LENGTH {
+        return Err(format!("Service account name must be 1 to {} characters long", MAX_NAME_LENGTH));
+    }
+    if !name.starts_with(|c: char| c.is_ascii_alphanumeric()) {
+        return Err("Service account name must start with a letter or digit".to_string());
+    }
+    if !name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-')) {
+        return Err("Service account name may only contain letters, digits, '.', '_' and '-'".to_string());
+    }
+    Ok(())
+}
+
+/// Checks if the name of a key is acceptable: 1 to 64 characters without
+/// control characters.
+pub fn validate_key_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+        return Err(format!("Key name must be 1 to {} characters long", MAX_NAME_LENGTH));
+    }
+    if name.chars().any(char::is_control) {
+        return Err("Key name must not contain control characters".to_string());
+    }
+    Ok(())
+}
+
+/// Normalizes the scopes of a key: buckets must be valid and prefixes name
+/// folders, as for grants.
+///
+/// # Returns
+///
+/// The normalized scopes, or why they are not acceptable
+pub fn normalize_scopes(scopes: Vec<KeyScope>) -> Result<Vec<KeyScope>, String> {
+    if scopes.len() > MAX_SCOPES {
+        return Err(format!("A key may have at most {} scopes", MAX_SCOPES));
+    }
+    scopes.into_iter().map(|scope| {
+        if let Some(bucket) = &scope.bucket {
+            S3Service::validate_bucket_name(bucket)?;
+        }
+        let prefix = normalize_prefix(&scope.prefix)?;
+        if scope.bucket.is_none() && !prefix.is_empty() {
+            return Err("A scope with a prefix must name a bucket".to_string());
+        }
+        Ok(KeyScope { prefix, ..scope })
+    }).collect()
+}
@@ -0,0 +1,199 @@
+//! # MySQL API Key Store
+//!
+//! This module provides the `ApiKeyStore` backed by MySQL. Its tables are
+//! created by the migrations that `MySqlUserStore::migrate` runs. The
+//! scopes of a key are stored as JSON.
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::mysql::MySqlPool;
+use sqlx::FromRow;
+
+use crate::rdlib::apikeys::error::ApiKeyError;
+use crate::rdlib::apikeys::key::{ApiKey, KeyScope, NewApiKey, ServiceAccount};
+use crate::rdlib::apikeys::store::ApiKeyStore;
+
+const ACCOUNT_COLUMNS: &str = "id, name, description, created_at";
+
+const KEY_COLUMNS: &str = "id, service_account_id, name, key_prefix, scopes, expires_at, last_used_at, created_at";
+
+/// A row of the `service_accounts` table.
+#[derive(FromRow)]
+struct AccountRow {
+    id: u64,
+    name: String,
+    description: String,
+    created_at: DateTime<Utc>,
+}
+
+impl From<AccountRow> for ServiceAccount {
+    fn from(row: AccountRow) -> Self {
+        ServiceAccount {
+            id: row.id,
+            subject: ServiceAccount::subject_of(&row.name),
+            name: row.name,
+            description: row.description,
+            created_at: row.created_at,
+        }
+    }
+}
+
+/// A row of the `api_keys` table.
+#[derive(FromRow)]
+struct KeyRow {
+    id: u64,
+    service_account_id: u64,
+    name: String,
+    key_prefix: String,
+    scopes: String,
+    expires_at: Option<DateTime<Utc>>,
+    last_used_at: Option<DateTime<Utc>>,
+    created_at: DateTime<Utc>,
+}
+
+impl TryFrom<KeyRow> for ApiKey {
+    type Error = ApiKeyError;
+
+    fn try_from(row: KeyRow) -> Result<Self, Self::Error> {
+        Ok(ApiKey {
+            id: row.id,
+            service_account_id: row.service_account_id,
+            name: row.name,
+            key_prefix: row.key_prefix,
+            scopes: serde_json::from_str::<Vec<KeyScope>>(&row.scopes).map_err(|e| ApiKeyError::Database(e.to_string()))?,
+            expires_at: row.expires_at,
+            last_used_at: row.last_used_at,
+            created_at: row.created_at,
+        })
+    }
+}
+
+/// Stores service accounts and API keys in MySQL.
+pub struct MySqlApiKeyStore {
+    pool: MySqlPool,
+}
+
+impl MySqlApiKeyStore {
+    /// Creates the store on a connection pool.
+    pub fn new(pool: MySqlPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl ApiKeyStore for MySqlApiKeyStore {
+    async fn create_account(&self, name: &str, description: &str, now: DateTime<Utc>) -> Result<ServiceAccount, ApiKeyError> {
+        let result = sqlx::query("INSERT INTO service_accounts (name, description, created_at) VALUES (?, ?, ?)")
+            .bind(name)
+            .bind(description)
+            .bind(now)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| match e {
+                sqlx::Error::Database(db) if db.is_unique_violation() => ApiKeyError::NameTaken(name.to_string()),
+                e => e.into(),
+            })?;
+
+        Ok(ServiceAccount {
+            id: result.last_insert_id(),
+            name: name.to_string(),
+            description: description.to_string(),
+            subject: ServiceAccount::subject_of(name),
+            created_at: now,
+        })
+    }
+
+    async fn get_account(&self, id: u64) -> Result<Option<ServiceAccount>, ApiKeyError> {
+        let row = sqlx::query_as::<_, AccountRow>(&format!("SELECT {} FROM service_accounts WHERE id = ?", ACCOUNT_COLUMNS))
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.map(ServiceAccount::from))
+    }
+
+    async fn list_accounts(&self) -> Result<Vec<ServiceAccount>, ApiKeyError> {
+        let rows = sqlx::query_as::<_, AccountRow>(&format!("SELECT {} FROM service_accounts ORDER BY name", ACCOUNT_COLUMNS))
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rows.into_iter().map(ServiceAccount::from).collect())
+    }
+
+    async fn delete_account(&self, id: u64) -> Result<bool, ApiKeyError> {
+        // The keys of the account go with it through the foreign key
+        let result = sqlx::query("DELETE FROM service_accounts WHERE id = ?").bind(id).execute(&self.pool).await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn create_key(&self, key: NewApiKey, now: DateTime<Utc>) -> Result<ApiKey, ApiKeyError> {
+        let scopes = serde_json::to_string(&key.scopes).map_err(|e| ApiKeyError::Database(e.to_string()))?;
+        let result = sqlx::query(
+            "INSERT INTO api_keys (service_account_id, name, key_prefix, key_hash, scopes, expires_at, created_at) \
+             VALUES (?, ?, ?, ?, ?, ?, ?)"
+        )
+            .bind(key.service_account_id)
+            .bind(&key.name)
+            .bind(&key.key_prefix)
+            .bind(&key.key_hash)
+            .bind(scopes)
+            .bind(key.expires_at)
+            .bind(now)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(ApiKey {
+            id: result.last_insert_id(),
+            service_account_id: key.service_account_id,
+            name: key.name,
+            key_prefix: key.key_prefix,
+            scopes: key.scopes,
+            expires_at: key.expires_at,
+            last_used_at: None,
+            created_at: now,
+        })
+    }
+
+    async fn get_key(&self, id: u64) -> Result<Option<ApiKey>, ApiKeyError> {
+        sqlx::query_as::<_, KeyRow>(&format!("SELECT {} FROM api_keys WHERE id = ?", KEY_COLUMNS))
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?
+            .map(ApiKey::try_from)
+            .transpose()
+    }
+
+    async fn list_keys(&self, account_id: u64) -> Result<Vec<ApiKey>, ApiKeyError> {
+        sqlx::query_as::<_, KeyRow>(&format!("SELECT {} FROM api_keys WHERE service_account_id = ? ORDER BY id", KEY_COLUMNS))
+            .bind(account_id)
+            .fetch_all(&self.pool)
+            .await?
+            .into_iter()
+            .map(ApiKey::try_from)
+            .collect()
+    }
+
+    async fn find_key(&self, key_hash: &str) -> Result<Option<(ApiKey, ServiceAccount)>, ApiKeyError> {
+        let Some(row) = sqlx::query_as::<_, KeyRow>(&format!("SELECT {} FROM api_keys WHERE key_hash = ?", KEY_COLUMNS))
+            .bind(key_hash)
+            .fetch_optional(&self.pool)
+            .await? else {
+            return Ok(None);
+        };
+        let key = ApiKey::try_from(row)?;
+        Ok(self.get_account(key.service_account_id).await?.map(|account| (key, account)))
+    }
+
+    async fn touch_key(&self, id: u64, now: DateTime<Utc>) -> Result<(), ApiKeyError> {
+        sqlx::query("UPDATE api_keys SET last_used_at = ? WHERE id = ?").bind(now).bind(id).execute(&self.pool).await?;
+        Ok(())
+    }
+
+    async fn set_key_expiry(&self, id: u64, expires_at: DateTime<Utc>) -> Result<(), ApiKeyError> {
+        sqlx::query("UPDATE api_keys SET expires_at = ? WHERE id = ?").bind(expires_at).bind(id).execute(&self.pool).await?;
+        Ok(())
+    }
+
+    async fn delete_key(&self, id: u64) -> Result<bool, ApiKeyError> {
+        let result = sqlx::query("DELETE FROM api_keys WHERE id = ?").bind(id).execute(&self.pool).await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
@@ -0,0 +1,237 @@
+//! # API Key Service
+//!
+//! This module provides the operations on service accounts and their keys
+//! on top of an `ApiKeyStore`: managing them, rotating keys and
+//! authenticating requests made with a key.
+//!
+//! A request made with a key acts as the principal `sa:<name>` of its
+//! service account, which roles are granted to like to any user. The
+//! scopes of the key are passed on in the `scopes` claim and further limit
+//! what the key may do.
+
+use std::env;
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, Utc};
+use log::info;
+use serde_json::{json, Map};
+
+use crate::rdlib::apikeys::error::ApiKeyError;
+use crate::rdlib::apikeys::key::{
+    generate_key, hash_api_key, is_api_key, normalize_scopes, validate_account_name, validate_key_name,
+    ApiKey, KeyScope, NewApiKey, ServiceAccount, MAX_DESCRIPTION_LENGTH
+};
+use crate::rdlib::apikeys::mysql::MySqlApiKeyStore;
+use crate::rdlib::apikeys::store::ApiKeyStore;
+use crate::rdlib::auth::principal::Principal;
+use crate::rdlib::users::mysql::connect_pool;
+
+/// How often the last use of a key is recorded, so busy keys do not write
+/// on every request
+const LAST_USED_RESOLUTION_SECONDS: i64 = 60;
+
+/// The longest a replaced key keeps working after a rotation
+pub const MAX_ROTATION_GRACE_SECONDS: u64 = 7 * 24 * 3600;
+
+/// Manages service accounts and authenticates their keys.
+pub struct ApiKeyService {
+    store: Arc<dyn ApiKeyStore>,
+}
+
+impl ApiKeyService {
+    /// Creates the service.
+    ///
+    /// # Arguments
+    ///
+    /// * `store` - Where service accounts and keys are kept
+    pub fn new(store: Arc<dyn ApiKeyStore>) -> Self {
+        Self { store }
+    }
+
+    /// Creates the service from the environment.
+    ///
+    /// Service accounts are kept in the database of `RUSTDOK_DATABASE_URL`,
+    /// whose schema is created by `UserService::from_env`.
+    ///
+    /// # Returns
+    ///
+    /// The service, `None` if no database is configured, or an error if the
+    /// database cannot be reached
+    pub async fn from_env() -> Result<Option<Self>, String> {
+        let Ok(url) = env::var("RUSTDOK_DATABASE_URL") else {
+            info!("RUSTDOK_DATABASE_URL is not set; API keys are disabled");
+            return Ok(None);
+        };
+        let pool = connect_pool(&url).await.map_err(|e| format!("Cannot connect to the API key database: {}", e))?;
+        Ok(Some(Self::new(Arc::new(MySqlApiKeyStore::new(pool)))))
+    }
+
+    /// Authenticates a request made with a key.
+    ///
+    /// # Returns
+    ///
+    /// The principal of the service account of the key, `InvalidKey` if the
+    /// key is unknown, or `Expired` if it has expired
+    pub async fn authenticate(&self, key: &str) -> Result<Principal, ApiKeyError> {
+        if !is_api_key(key) {
+            return Err(ApiKeyError::InvalidKey);
+        }
+        let (api_key, account) = self.store.find_key(&hash_api_key(key)).await?.ok_or(ApiKeyError::InvalidKey)?;
+
+        let now = Utc::now();
+        if api_key.is_expired(now) {
+            return Err(ApiKeyError::Expired);
+        }
+        let stale = api_key.last_used_at.is_none_or(|at| now - at >= Duration::seconds(LAST_USED_RESOLUTION_SECONDS));
+        if stale {
+            self.store.touch_key(api_key.id, now).await?;
+        }
+
+        let mut claims = Map::new();
+        claims.insert("sub".to_string(), json!(account.subject));
+        claims.insert("service_account".to_string(), json!(account.name));
+        claims.insert("api_key".to_string(), json!(api_key.id));
+        if !api_key.scopes.is_empty() {
+            claims.insert("scopes".to_string(), json!(api_key.scopes));
+        }
+        Principal::from_claims(claims).map(Principal::into_local).ok_or(ApiKeyError::InvalidKey)
+    }
+
+    /// Lists all service accounts.
+    pub async fn list_accounts(&self) -> Result<Vec<ServiceAccount>, ApiKeyError> {
+        self.store.list_accounts().await
+    }
+
+    /// Gets a service account by ID.
+    pub async fn get_account(&self, id: u64) -> Result<ServiceAccount, ApiKeyError> {
+        self.store.get_account(id).await?.ok_or(ApiKeyError::AccountNotFound)
+    }
+
+    /// Creates a service account.
+    pub async fn create_account(&self, name: &str, description: &str) -> Result<ServiceAccount, ApiKeyError> {
+        validate_account_name(name).map_err(ApiKeyError::InvalidName)?;
+        if description.chars().count() > MAX_DESCRIPTION_LENGTH {
+            return Err(ApiKeyError::InvalidDescription(format!(
+                "Description must be at most {} characters long", MAX_DESCRIPTION_LENGTH
+            )));
+        }
+        self.store.create_account(name, description, Utc::now()).await
+    }
+
+    /// Deletes a service account and its keys.
+    ///
+    /// # Returns
+    ///
+    /// The deleted service account, whose grants the caller should revoke
+    pub async fn delete_account(&self, id: u64) -> Result<ServiceAccount, ApiKeyError> {
+        let account = self.get_account(id).await?;
+        if !self.store.delete_account(id).await? {
+            return Err(ApiKeyError::AccountNotFound);
+        }
+        Ok(account)
+    }
+
+    /// Lists the keys of a service account.
+    pub async fn list_keys(&self, account_id: u64) -> Result<Vec<ApiKey>, ApiKeyError> {
+        self.get_account(account_id).await?;
+        self.store.list_keys(account_id).await
+    }
+
+    /// Creates a key for a service account.
+    ///
+    /// # Arguments
+    ///
+    /// * `account_id` - The ID of the service account
+    /// * `name` - What the key is used for
+    /// * `scopes` - The limits of the key; none to let it do what its account may
+    /// * `expires_at` - When the key stops working, if ever
+    ///
+    /// # Returns
+    ///
+    /// The key and its secret, which is not stored and cannot be shown again
+    pub async fn create_key(
+        &self,
+        account_id: u64,
+        name: &str,
+        scopes: Vec<KeyScope>,
+        expires_at: Option<DateTime<Utc>>
+    ) -> Result<(ApiKey, String), ApiKeyError> {
+        self.get_account(account_id).await?;
+        validate_key_name(name).map_err(ApiKeyError::InvalidName)?;
+        let scopes = normalize_scopes(scopes).map_err(ApiKeyError::InvalidScopes)?;
+        let now = Utc::now();
+        if expires_at.is_some_and(|at| at <= now) {
+            return Err(ApiKeyError::InvalidExpiry("Expiry must be in the future".to_string()));
+        }
+        self.store_key(account_id, name, scopes, expires_at, now).await
+    }
+
+    /// Replaces a key with a new one of the same name, scopes and expiry.
+    ///
+    /// # Arguments
+    ///
+    /// * `account_id` - The ID of the service account
+    /// * `key_id` - The ID of the key
+    /// * `grace_seconds` - How long the old key keeps working, so clients can
+    ///   switch over; it is revoked at once if 0
+    ///
+    /// # Returns
+    ///
+    /// The new key and its secret
+    pub async fn rotate_key(&self, account_id: u64, key_id: u64, grace_seconds: u64) -> Result<(ApiKey, String), ApiKeyError> {
+        if grace_seconds > MAX_ROTATION_GRACE_SECONDS {
+            return Err(ApiKeyError::InvalidGracePeriod(format!(
+                "Grace period must be at most {} seconds", MAX_ROTATION_GRACE_SECONDS
+            )));
+        }
+        let old = self.get_key(account_id, key_id).await?;
+        let now = Utc::now();
+        let rotated = self.store_key(account_id, &old.name, old.scopes.clone(), old.expires_at, now).await?;
+
+        if grace_seconds == 0 {
+            self.store.delete_key(key_id).await?;
+        } else {
+            let grace_end = now + Duration::seconds(grace_seconds as i64);
+            let expires_at = old.expires_at.map_or(grace_end, |at| at.min(grace_end));
+            self.store.set_key_expiry(key_id, expires_at).await?;
+        }
+        Ok(rotated)
+    }
+
+    /// Revokes a key.
+    pub async fn revoke_key(&self, account_id: u64, key_id: u64) -> Result<ApiKey, ApiKeyError> {
+        let key = self.get_key(account_id, key_id).await?;
+        if !self.store.delete_key(key_id).await? {
+            return Err(ApiKeyError::KeyNotFound);
+        }
+        Ok(key)
+    }
+
+    /// Gets a key of a service account.
+    async fn get_key(&self, account_id: u64, key_id: u64) -> Result<ApiKey, ApiKeyError> {
+        self.get_account(account_id).await?;
+        self.store.get_key(key_id).await?
+            .filter(|key| key.service_account_id == account_id)
+            .ok_or(ApiKeyError::KeyNotFound)
+    }
+
+    async fn store_key(
+        &self,
+        account_id: u64,
+        name: &str,
+        scopes: Vec<KeyScope>,
+        expires_at: Option<DateTime<Utc>>,
+        now: DateTime<Utc>
+    ) -> Result<(ApiKey, String), ApiKeyError> {
+        let (secret, key_prefix) = generate_key();
+        let key = NewApiKey {
+            service_account_id: account_id,
+            name: name.to_string(),
+            key_prefix,
+            key_hash: hash_api_key(&secret),
+            scopes,
+            expires_at,
+        };
+        Ok((self.store.create_key(key, now).await?, secret))
+    }
+}
@@ -0,0 +1,48 @@
+//! # API Key Store
+//!
+//! This module defines the storage of service accounts and their keys. The
+//! server keeps them in MySQL (see `mysql`); the trait lets tests use an
+//! in-memory substitute.
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+use crate::rdlib::apikeys::error::ApiKeyError;
+use crate::rdlib::apikeys::key::{ApiKey, NewApiKey, ServiceAccount};
+
+/// Storage of service accounts and API keys.
+#[async_trait]
+pub trait ApiKeyStore: Send + Sync {
+    /// Creates a service account. Fails with `NameTaken` if the name is in use.
+    async fn create_account(&self, name: &str, description: &str, now: DateTime<Utc>) -> Result<ServiceAccount, ApiKeyError>;
+
+    /// Gets a service account by ID.
+    async fn get_account(&self, id: u64) -> Result<Option<ServiceAccount>, ApiKeyError>;
+
+    /// Lists all service accounts, ordered by name.
+    async fn list_accounts(&self) -> Result<Vec<ServiceAccount>, ApiKeyError>;
+
+    /// Deletes a service account with its keys, returning whether it existed.
+    async fn delete_account(&self, id: u64) -> Result<bool, ApiKeyError>;
+
+    /// Stores a key.
+    async fn create_key(&self, key: NewApiKey, now: DateTime<Utc>) -> Result<ApiKey, ApiKeyError>;
+
+    /// Gets a key by ID.
+    async fn get_key(&self, id: u64) -> Result<Option<ApiKey>, ApiKeyError>;
+
+    /// Lists the keys of a service account, ordered by ID.
+    async fn list_keys(&self, account_id: u64) -> Result<Vec<ApiKey>, ApiKeyError>;
+
+    /// Finds a key and its service account by the hash of the key.
+    async fn find_key(&self, key_hash: &str) -> Result<Option<(ApiKey, ServiceAccount)>, ApiKeyError>;
+
+    /// Records that a key was used.
+    async fn touch_key(&self, id: u64, now: DateTime<Utc>) -> Result<(), ApiKeyError>;
+
+    /// Changes when a key expires.
+    async fn set_key_expiry(&self, id: u64, expires_at: DateTime<Utc>) -> Result<(), ApiKeyError>;
+
+    /// Deletes a key, returning whether it existed.
+    async fn delete_key(&self, id: u64) -> Result<bool, ApiKeyError>;
+}
@@ -0,0 +1,9 @@
+//! # Archives
+//!
+//! This module provides functionality for working with archives of bucket
+//! contents: streaming ZIP downloads, extraction of uploaded ZIP and
+//! tar(.gz) archives, and inspection of archives stored in buckets.
+
+pub mod zip_stream;
+pub mod extract;
+pub mod inspect;
@@ -0,0 +1,337 @@
+//! # Archive Extraction
+//!
+//! This module provides extraction of uploaded ZIP and tar(.gz) archives.
+//! Entries are handed out one at a time, so callers can store each entry
+//! before the next one is decompressed. Entry paths are sanitized against
+//! path traversal ("zip slip"), links are never extracted, and limits on
+//! the number of entries, the total extracted size and the compression
+//! ratio stop archive bombs early.
+
+use std::fmt;
+use std::io::{Cursor, Read};
+
+use flate2::read::GzDecoder;
+
+/// The formats archives can be extracted from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArchiveFormat {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+/// Limits applied while extracting an archive.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExtractionLimits {
+    /// The maximum size of the archive itself in bytes
+    pub max_archive_size: u64,
+    /// The maximum number of entries
+    pub max_entries: usize,
+    /// The maximum total size of all extracted entries in bytes
+    pub max_total_size: u64,
+    /// The maximum ratio between the extracted size and the archive size
+    pub max_ratio: u64,
+    /// The maximum size of a single extracted file in bytes; larger files are skipped
+    pub max_entry_size: Option<u64>,
+}
+
+impl Default for ExtractionLimits {
+    fn default() -> Self {
+        Self {
+            max_archive_size: 512 * 1024 * 1024,
+            max_entries: 10_000,
+            max_total_size: 2 * 1024 * 1024 * 1024,
+            max_ratio: 100,
+            max_entry_size: None,
+        }
+    }
+}
+
+/// An item produced while extracting an archive.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExtractedItem {
+    /// A regular file with its sanitized path and data
+    File { path: String, data: Vec<u8> },
+    /// A directory with its sanitized path, ending with a slash
+    Directory { path: String },
+    /// An entry that was not extracted, with its original path and the reason
+    Skipped { path: String, reason: String },
+}
+
+/// Errors that abort the extraction of an archive.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExtractionError {
+    /// The archive is malformed or uses unsupported features
+    Invalid(String),
+    /// The archive exceeds one of the extraction limits
+    LimitExceeded(String),
+}
+
+impl fmt::Display for ExtractionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExtractionError::Invalid(e) => write!(f, "Invalid archive: {}", e),
+            ExtractionError::LimitExceeded(e) => write!(f, "Archive rejected: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ExtractionError {}
+
+/// Detects the format of an archive from its first bytes.
+///
+/// # Arguments
+///
+/// * `data` - The archive, or at least its first 512 bytes
+///
+/// # Returns
+///
+/// The archive format, or `None` if the data is not a supported archive
+pub fn detect_format(data: &[u8]) -> Option<ArchiveFormat> {
+    if data.starts_with(b"PK\x03\x04") || data.starts_with(b"PK\x05\x06") {
+        Some(ArchiveFormat::Zip)
+    } else if data.starts_with(&[0x1f, 0x8b]) {
+        Some(ArchiveFormat::TarGz)
+    } else if data.len() >= 262 && &data[257..262] == b"ustar" {
+        Some(ArchiveFormat::Tar)
+    } else {
+        None
+    }
+}
+
+/// Sanitizes the path of an archive entry.
+///
+/// Backslashes are treated as separators and empty or `.` segments are
+/// dropped. Absolute paths, drive letters, `..` segments and control
+/// characters are rejected, so an entry can never escape the target folder.
+///
+/// # Arguments
+///
+/// * `path` - The path as stored in the archive
+///
+/// # Returns
+///
+/// * `Ok(String)` - The sanitized relative path, ending with a slash if the entry is a directory
+/// * `Err(String)` - A description of why the path is rejected
+pub fn sanitize_entry_path(path: &str) -> Result<String, String> {
+    let normalized = path.replace('\\', "/");
+
+    if normalized.starts_with('/') {
+        return Err("absolute paths are not allowed".to_string());
+    }
+    if normalized.chars().any(|c| c.is_control()) {
+        return Err("control characters are not allowed".to_string());
+    }
+
+    let mut segments = Vec::new();
+    for segment in normalized.split('/') {
+        match segment {
+            "" | "." => {},
+            ".." => return Err("parent directory references are not allowed".to_string()),
+            s if segments.is_empty() && s.len() == 2 && s.ends_with(':') => {
+                return Err("drive letters are not allowed".to_string());
+            },
+            s => segments.push(s),
+        }
+    }
+
+    if segments.is_empty() {
+        return Err("the path is empty".to_string());
+    }
+
+    let mut sanitized = segments.join("/");
+    if normalized.ends_with('/') {
+        sanitized.push('/');
+    }
+    Ok(sanitized)
+}
+
+/// Keeps track of the limits while an archive is extracted.
+struct Budget<'a> {
+    limits: &'a ExtractionLimits,
+    archive_size: u64,
+    entries: usize,
+    total_size: u64,
+}
+
+impl Budget<'_> {
+    /// Counts an entry against the entry limit.
+    fn add_entry(&mut self) -> Result<(), ExtractionError> {
+        self.entries += 1;
+        if self.entries > self.limits.max_entries {
+            return Err(ExtractionError::LimitExceeded(format!(
+                "more than {} entries",
+                self.limits.max_entries
+            )));
+        }
+        Ok(())
+    }
+
+    /// The number of bytes that may still be extracted.
+    fn remaining(&self) -> u64 {
+        let by_ratio = self.archive_size.max(1).saturating_mul(self.limits.max_ratio);
+        self.limits.max_total_size.min(by_ratio).saturating_sub(self.total_size)
+    }
+
+    /// Reads an entry, stopping as soon as a limit is exceeded.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some(data))` - The data of the entry
+    /// * `Ok(None)` - If the entry is larger than the maximum entry size
+    /// * `Err(ExtractionError)` - If the archive exceeds its total size or ratio limit
+    fn read_entry(&mut self, reader: impl Read) -> Result<Option<Vec<u8>>, ExtractionError> {
+        let remaining = self.remaining();
+        let allowed = self.limits.max_entry_size.map_or(remaining, |max| max.min(remaining));
+
+        let mut data = Vec::new();
+        reader
+            .take(allowed.saturating_add(1))
+            .read_to_end(&mut data)
+            .map_err(|e| ExtractionError::Invalid(e.to_string()))?;
+        self.total_size += data.len() as u64;
+
+        if data.len() as u64 > remaining {
+            return Err(ExtractionError::LimitExceeded(format!(
+                "extracted data exceeds {} bytes or {} times the archive size",
+                self.limits.max_total_size,
+                self.limits.max_ratio
+            )));
+        }
+        if data.len() as u64 > allowed {
+            return Ok(None);
+        }
+        Ok(Some(data))
+    }
+}
+
+/// Builds the item for a file entry.
+fn file_item(path: String, data: Option<Vec<u8>>, limits: &ExtractionLimits) -> ExtractedItem {
+    match data {
+        Some(data) => ExtractedItem::File { path, data },
+        None => ExtractedItem::Skipped {
+            reason: format!("file exceeds the maximum size of {} bytes", limits.max_entry_size.unwrap_or_default()),
+            path,
+        },
+    }
+}
+
+/// Extracts a ZIP archive.
+fn extract_zip(
+    data: &[u8],
+    budget: &mut Budget,
+    on_item: &mut dyn FnMut(ExtractedItem) -> bool
+) -> Result<(), ExtractionError> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(data))
+        .map_err(|e| ExtractionError::Invalid(e.to_string()))?;
+
+    for index in 0..archive.len() {
+        budget.add_entry()?;
+
+        let (name, is_dir, is_symlink, encrypted) = {
+            let entry = archive.by_index_raw(index).map_err(|e| ExtractionError::Invalid(e.to_string()))?;
+            (String::from_utf8_lossy(entry.name_raw()).to_string(), entry.is_dir(), entry.is_symlink(), entry.encrypted())
+        };
+
+        let item = match sanitize_entry_path(&name) {
+            Err(reason) => ExtractedItem::Skipped { path: name, reason },
+            Ok(_) if is_symlink => ExtractedItem::Skipped { path: name, reason: "links are not extracted".to_string() },
+            Ok(_) if encrypted => ExtractedItem::Skipped { path: name, reason: "encrypted entries are not supported".to_string() },
+            Ok(path) if is_dir => ExtractedItem::Directory { path },
+            Ok(path) => {
+                let entry = archive.by_index(index).map_err(|e| ExtractionError::Invalid(e.to_string()))?;
+                file_item(path, budget.read_entry(entry)?, budget.limits)
+            },
+        };
+
+        if !on_item(item) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Extracts a tar archive.
+fn extract_tar(
+    reader: impl Read,
+    budget: &mut Budget,
+    on_item: &mut dyn FnMut(ExtractedItem) -> bool
+) -> Result<(), ExtractionError> {
+    let mut archive = tar::Archive::new(reader);
+    let entries = archive.entries().map_err(|e| ExtractionError::Invalid(e.to_string()))?;
+
+    for entry in entries {
+        let entry = entry.map_err(|e| ExtractionError::Invalid(e.to_string()))?;
+        let entry_type = entry.header().entry_type();
+
+        // Extended headers only describe the entry that follows them
+        if entry_type.is_pax_global_extensions() || entry_type.is_pax_local_extensions()
+            || entry_type.is_gnu_longname() || entry_type.is_gnu_longlink()
+        {
+            continue;
+        }
+        budget.add_entry()?;
+
+        let name = String::from_utf8_lossy(&entry.path_bytes()).to_string();
+        let item = match sanitize_entry_path(&name) {
+            Err(reason) => ExtractedItem::Skipped { path: name, reason },
+            Ok(_) if entry_type.is_symlink() || entry_type.is_hard_link() => {
+                ExtractedItem::Skipped { path: name, reason: "links are not extracted".to_string() }
+            },
+            Ok(path) if entry_type.is_dir() => {
+                let path = if path.ends_with('/') { path } else { format!("{}/", path) };
+                ExtractedItem::Directory { path }
+            },
+            Ok(path) if entry_type.is_file() || entry_type.is_contiguous() => {
+                let path = path.trim_end_matches('/').to_string();
+                file_item(path, budget.read_entry(entry)?, budget.limits)
+            },
+            Ok(_) => ExtractedItem::Skipped { path: name, reason: "unsupported entry type".to_string() },
+        };
+
+        if !on_item(item) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Extracts an archive, handing each entry to a callback.
+///
+/// # Arguments
+///
+/// * `data` - The archive
+/// * `format` - The format of the archive
+/// * `limits` - The limits to enforce
+/// * `on_item` - Receives each extracted item; returning `false` stops the extraction
+///
+/// # Returns
+///
+/// * `Ok(())` - If the archive was extracted, or the callback stopped the extraction
+/// * `Err(ExtractionError)` - If the archive is invalid or exceeds a limit
+pub fn extract(
+    data: &[u8],
+    format: ArchiveFormat,
+    limits: &ExtractionLimits,
+    mut on_item: impl FnMut(ExtractedItem) -> bool
+) -> Result<(), ExtractionError> {
+    if data.len() as u64 > limits.max_archive_size {
+        return Err(ExtractionError::LimitExceeded(format!(
+            "the archive exceeds {} bytes",
+            limits.max_archive_size
+        )));
+    }
+
+    let mut budget = Budget {
+        limits,
+        archive_size: data.len() as u64,
+        entries: 0,
+        total_size: 0,
+    };
+
+    match format {
+        ArchiveFormat::Zip => extract_zip(data, &mut budget, &mut on_item),
+        ArchiveFormat::Tar => extract_tar(data, &mut budget, &mut on_item),
+        ArchiveFormat::TarGz => extract_tar(GzDecoder::new(data), &mut budget, &mut on_item),
+    }
+}
@@ -0,0 +1,455 @@
+//! # Archive Inspection
+//!
+//! This module provides listing of archive contents and reading of single
+//! entries without downloading a whole archive. For ZIP archives only the
+//! end of central directory record, the central directory and the entry
+//! itself are needed, so callers can fetch them with ranged reads. Tar
+//! archives have no index and are read sequentially from a stream.
+
+use std::io::Read;
+
+use bytes::Bytes;
+use chrono::{DateTime, NaiveDate, Utc};
+use flate2::read::{DeflateDecoder, GzDecoder};
+use serde::Serialize;
+
+use crate::rdlib::archive::extract::{ArchiveFormat, ExtractionError};
+
+/// The number of bytes at the end of a ZIP archive that contain the end of
+/// central directory record: the record itself, the longest possible
+/// comment and the ZIP64 locator in front of it.
+pub const EOCD_SEARCH_LENGTH: u64 = 22 + 65535 + 20;
+
+/// The size of the ZIP64 end of central directory record without its extensible data.
+pub const ZIP64_EOCD_LENGTH: u64 = 56;
+
+/// The size of a local file header without its file name and extra field.
+pub const LOCAL_HEADER_LENGTH: u64 = 30;
+
+/// The maximum size of a central directory that is read.
+pub const MAX_CENTRAL_DIRECTORY_SIZE: u64 = 64 * 1024 * 1024;
+
+/// The maximum number of entries that are listed.
+pub const MAX_LISTED_ENTRIES: usize = 100_000;
+
+/// The maximum size of a single entry that is read, compressed or not.
+pub const MAX_ENTRY_SIZE: u64 = 512 * 1024 * 1024;
+
+const EOCD_SIGNATURE: u32 = 0x06054b50;
+const ZIP64_LOCATOR_SIGNATURE: u32 = 0x07064b50;
+const ZIP64_EOCD_SIGNATURE: u32 = 0x06064b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x02014b50;
+const LOCAL_HEADER_SIGNATURE: u32 = 0x04034b50;
+
+/// An entry of an archive, as listed to clients.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ArchiveEntry {
+    /// The path of the entry inside the archive
+    pub name: String,
+    /// The uncompressed size in bytes
+    pub size: u64,
+    /// The compressed size in bytes, if the entry is compressed individually
+    pub compressed_size: Option<u64>,
+    /// The modification time as RFC 3339 timestamp
+    pub modified: Option<String>,
+    /// Whether the entry is a directory
+    pub is_dir: bool,
+}
+
+/// The location of a ZIP central directory.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DirectoryLocation {
+    /// The central directory starts at `offset` and is `size` bytes long
+    Found { offset: u64, size: u64 },
+    /// The location is stored in the ZIP64 end of central directory record at this offset
+    Zip64Record(u64),
+}
+
+/// An entry of a ZIP central directory.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ZipEntry {
+    /// The path of the entry inside the archive
+    pub name: String,
+    /// The compression method, 0 for stored and 8 for deflated
+    pub method: u16,
+    /// The CRC-32 of the uncompressed data
+    pub crc32: u32,
+    /// The compressed size in bytes
+    pub compressed_size: u64,
+    /// The uncompressed size in bytes
+    pub size: u64,
+    /// The offset of the entry's local file header
+    pub header_offset: u64,
+    /// The modification time as RFC 3339 timestamp
+    pub modified: Option<String>,
+    /// Whether the entry is encrypted
+    pub encrypted: bool,
+}
+
+impl ZipEntry {
+    /// Whether the entry is a directory.
+    pub fn is_dir(&self) -> bool {
+        self.name.ends_with('/')
+    }
+
+    /// Converts the entry into its listing.
+    pub fn to_archive_entry(&self) -> ArchiveEntry {
+        ArchiveEntry {
+            name: self.name.clone(),
+            size: self.size,
+            compressed_size: Some(self.compressed_size),
+            modified: self.modified.clone(),
+            is_dir: self.is_dir(),
+        }
+    }
+}
+
+fn invalid(message: &str) -> ExtractionError {
+    ExtractionError::Invalid(message.to_string())
+}
+
+fn read_u16(data: &[u8], at: usize) -> Option<u16> {
+    data.get(at..at + 2).map(|b| u16::from_le_bytes([b[0], b[1]]))
+}
+
+fn read_u32(data: &[u8], at: usize) -> Option<u32> {
+    data.get(at..at + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+fn read_u64(data: &[u8], at: usize) -> Option<u64> {
+    data.get(at..at + 8).and_then(|b| b.try_into().ok()).map(u64::from_le_bytes)
+}
+
+/// Converts an MS-DOS date and time into an RFC 3339 timestamp.
+fn dos_datetime(date: u16, time: u16) -> Option<String> {
+    NaiveDate::from_ymd_opt(1980 + (date >> 9) as i32, ((date >> 5) & 0x0F) as u32, (date & 0x1F) as u32)?
+        .and_hms_opt((time >> 11) as u32, ((time >> 5) & 0x3F) as u32, ((time & 0x1F) * 2) as u32)
+        .map(|dt| dt.and_utc().to_rfc3339())
+}
+
+/// Locates the central directory of a ZIP archive.
+///
+/// # Arguments
+///
+/// * `tail` - The last bytes of the archive, at most `EOCD_SEARCH_LENGTH`
+/// * `tail_offset` - The offset of `tail` within the archive
+///
+/// # Returns
+///
+/// * `Ok(DirectoryLocation)` - Where the central directory, or the ZIP64 record describing it, is
+/// * `Err(ExtractionError)` - If the data contains no end of central directory record
+pub fn find_central_directory(tail: &[u8], tail_offset: u64) -> Result<DirectoryLocation, ExtractionError> {
+    let position = (0..tail.len().saturating_sub(21))
+        .rev()
+        .find(|&i| read_u32(tail, i) == Some(EOCD_SIGNATURE))
+        .ok_or_else(|| invalid("no end of central directory record found"))?;
+
+    let size = read_u32(tail, position + 12).unwrap_or_default();
+    let offset = read_u32(tail, position + 16).unwrap_or_default();
+
+    if position >= 20 && read_u32(tail, position - 20) == Some(ZIP64_LOCATOR_SIGNATURE) {
+        let record_offset = read_u64(tail, position - 12).unwrap_or_default();
+        return match record_offset.checked_sub(tail_offset) {
+            Some(start) if start + ZIP64_EOCD_LENGTH <= tail.len() as u64 => {
+                let (offset, size) = parse_zip64_record(&tail[start as usize..])?;
+                Ok(DirectoryLocation::Found { offset, size })
+            },
+            _ => Ok(DirectoryLocation::Zip64Record(record_offset)),
+        };
+    }
+
+    Ok(DirectoryLocation::Found { offset: offset as u64, size: size as u64 })
+}
+
+/// Parses a ZIP64 end of central directory record.
+///
+/// # Returns
+///
+/// * `Ok((offset, size))` - Where the central directory starts and how long it is
+/// * `Err(ExtractionError)` - If the data is not a ZIP64 end of central directory record
+pub fn parse_zip64_record(data: &[u8]) -> Result<(u64, u64), ExtractionError> {
+    if read_u32(data, 0) != Some(ZIP64_EOCD_SIGNATURE) {
+        return Err(invalid("invalid ZIP64 end of central directory record"));
+    }
+    match (read_u64(data, 40), read_u64(data, 48)) {
+        (Some(size), Some(offset)) => Ok((offset, size)),
+        _ => Err(invalid("truncated ZIP64 end of central directory record")),
+    }
+}
+
+/// Parses a ZIP central directory.
+///
+/// # Arguments
+///
+/// * `data` - The complete central directory
+///
+/// # Returns
+///
+/// * `Ok(Vec<ZipEntry>)` - The entries of the archive
+/// * `Err(ExtractionError)` - If the central directory is malformed or has too many entries
+pub fn parse_central_directory(data: &[u8]) -> Result<Vec<ZipEntry>, ExtractionError> {
+    let mut entries = Vec::new();
+    let mut position = 0;
+
+    while position < data.len() {
+        if read_u32(data, position) != Some(CENTRAL_HEADER_SIGNATURE) {
+            return Err(invalid("invalid central directory header"));
+        }
+        if entries.len() >= MAX_LISTED_ENTRIES {
+            return Err(ExtractionError::LimitExceeded(format!("more than {} entries", MAX_LISTED_ENTRIES)));
+        }
+
+        let header = |at: usize| read_u16(data, position + at).ok_or_else(|| invalid("truncated central directory"));
+        let header32 = |at: usize| read_u32(data, position + at).ok_or_else(|| invalid("truncated central directory"));
+        let flags = header(8)?;
+        let method = header(10)?;
+        let time = header(12)?;
+        let date = header(14)?;
+        let crc32 = header32(16)?;
+        let mut compressed_size = header32(20)? as u64;
+        let mut size = header32(24)? as u64;
+        let name_length = header(28)? as usize;
+        let extra_length = header(30)? as usize;
+        let comment_length = header(32)? as usize;
+        let mut header_offset = header32(42)? as u64;
+
+        let name_start = position + 46;
+        let extra_start = name_start + name_length;
+        let next = extra_start + extra_length + comment_length;
+        let (Some(name), Some(extra)) = (data.get(name_start..extra_start), data.get(extra_start..extra_start + extra_length)) else {
+            return Err(invalid("truncated central directory"));
+        };
+
+        // The ZIP64 extra field holds the values whose regular fields are saturated, in this order
+        let mut field = 0;
+        while field + 4 <= extra.len() {
+            let id = read_u16(extra, field).unwrap_or_default();
+            let length = read_u16(extra, field + 2).unwrap_or_default() as usize;
+            if id == 0x0001 {
+                let mut value = field + 4;
+                for target in [&mut size, &mut compressed_size, &mut header_offset] {
+                    if *target == 0xFFFF_FFFF {
+                        *target = read_u64(extra, value).ok_or_else(|| invalid("truncated ZIP64 extra field"))?;
+                        value += 8;
+                    }
+                }
+            }
+            field += 4 + length;
+        }
+
+        entries.push(ZipEntry {
+            name: String::from_utf8_lossy(name).to_string(),
+            method,
+            crc32,
+            compressed_size,
+            size,
+            header_offset,
+            modified: dos_datetime(date, time),
+            encrypted: flags & 1 != 0,
+        });
+        position = next;
+    }
+
+    Ok(entries)
+}
+
+/// Determines where the data of a ZIP entry starts.
+///
+/// # Arguments
+///
+/// * `entry` - The entry from the central directory
+/// * `header` - The first `LOCAL_HEADER_LENGTH` bytes of the entry's local file header
+///
+/// # Returns
+///
+/// * `Ok(u64)` - The offset of the entry's data within the archive
+/// * `Err(ExtractionError)` - If the data is not a local file header
+pub fn entry_data_offset(entry: &ZipEntry, header: &[u8]) -> Result<u64, ExtractionError> {
+    if read_u32(header, 0) != Some(LOCAL_HEADER_SIGNATURE) {
+        return Err(invalid("invalid local file header"));
+    }
+    match (read_u16(header, 26), read_u16(header, 28)) {
+        (Some(name_length), Some(extra_length)) => entry.header_offset
+            .checked_add(LOCAL_HEADER_LENGTH + name_length as u64 + extra_length as u64)
+            .ok_or_else(|| invalid("the entry lies outside the archive")),
+        _ => Err(invalid("truncated local file header")),
+    }
+}
+
+/// Opens the data of a ZIP entry for reading.
+///
+/// # Arguments
+///
+/// * `entry` - The entry from the central directory
+/// * `data` - The compressed data of the entry
+///
+/// # Returns
+///
+/// * `Ok(EntryReader)` - A reader over the uncompressed data
+/// * `Err(ExtractionError)` - If the entry is encrypted or uses an unsupported compression method
+pub fn entry_reader(entry: &ZipEntry, data: impl Read + Send + 'static) -> Result<EntryReader, ExtractionError> {
+    if entry.encrypted {
+        return Err(invalid("encrypted entries are not supported"));
+    }
+    let inner: Box<dyn Read + Send> = match entry.method {
+        0 => Box::new(data),
+        8 => Box::new(DeflateDecoder::new(data)),
+        method => return Err(ExtractionError::Invalid(format!("compression method {} is not supported", method))),
+    };
+
+    Ok(EntryReader {
+        inner,
+        hasher: crc32fast::Hasher::new(),
+        read: 0,
+        size: entry.size,
+        crc32: entry.crc32,
+    })
+}
+
+/// A reader over the uncompressed data of a ZIP entry.
+///
+/// The size and checksum of the data are verified as it is read. An entry
+/// that is corrupt or lies about its size fails with an `InvalidData` error,
+/// at the latest when the end of its data is reached.
+pub struct EntryReader {
+    inner: Box<dyn Read + Send>,
+    hasher: crc32fast::Hasher,
+    read: u64,
+    size: u64,
+    crc32: u32,
+}
+
+impl Read for EntryReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let corrupt = || std::io::Error::new(std::io::ErrorKind::InvalidData, "the entry is corrupt");
+
+        let length = self.inner.read(buf)?;
+        self.read += length as u64;
+        if self.read > self.size {
+            return Err(corrupt());
+        }
+        self.hasher.update(&buf[..length]);
+        if length == 0 && !buf.is_empty() && (self.read != self.size || self.hasher.clone().finalize() != self.crc32) {
+            return Err(corrupt());
+        }
+        Ok(length)
+    }
+}
+
+/// Opens a tar archive, decompressing it if needed.
+fn tar_archive(reader: impl Read + Send + 'static, format: ArchiveFormat) -> tar::Archive<Box<dyn Read + Send>> {
+    let reader: Box<dyn Read + Send> = match format {
+        ArchiveFormat::TarGz => Box::new(GzDecoder::new(reader)),
+        _ => Box::new(reader),
+    };
+    tar::Archive::new(reader)
+}
+
+/// Lists the entries of a tar archive.
+///
+/// # Arguments
+///
+/// * `reader` - The archive
+/// * `format` - `ArchiveFormat::Tar` or `ArchiveFormat::TarGz`
+///
+/// # Returns
+///
+/// * `Ok(Vec<ArchiveEntry>)` - The entries of the archive
+/// * `Err(ExtractionError)` - If the archive is malformed or has too many entries
+pub fn list_tar(reader: impl Read + Send + 'static, format: ArchiveFormat) -> Result<Vec<ArchiveEntry>, ExtractionError> {
+    let mut archive = tar_archive(reader, format);
+    let mut entries = Vec::new();
+
+    for entry in archive.entries().map_err(|e| ExtractionError::Invalid(e.to_string()))? {
+        let entry = entry.map_err(|e| ExtractionError::Invalid(e.to_string()))?;
+        if entries.len() >= MAX_LISTED_ENTRIES {
+            return Err(ExtractionError::LimitExceeded(format!("more than {} entries", MAX_LISTED_ENTRIES)));
+        }
+
+        let header = entry.header();
+        entries.push(ArchiveEntry {
+            name: String::from_utf8_lossy(&entry.path_bytes()).to_string(),
+            size: entry.size(),
+            compressed_size: None,
+            modified: header.mtime().ok()
+                .and_then(|mtime| DateTime::<Utc>::from_timestamp(mtime as i64, 0))
+                .map(|dt| dt.to_rfc3339()),
+            is_dir: header.entry_type().is_dir(),
+        });
+    }
+
+    Ok(entries)
+}
+
+/// Reads a single file from a tar archive.
+///
+/// # Arguments
+///
+/// * `reader` - The archive
+/// * `format` - `ArchiveFormat::Tar` or `ArchiveFormat::TarGz`
+/// * `name` - The path of the entry, as listed by `list_tar`
+/// * `read` - Called with a reader over the data of the entry, once it is found
+///
+/// # Returns
+///
+/// * `Ok(true)` - If the entry was found and read
+/// * `Ok(false)` - If the archive has no file with this name
+/// * `Err(ExtractionError)` - If the archive is malformed, the entry is too large or could not be read
+pub fn read_tar_entry<F>(
+    reader: impl Read + Send + 'static,
+    format: ArchiveFormat,
+    name: &str,
+    read: F
+) -> Result<bool, ExtractionError>
+where
+    F: FnOnce(&mut dyn Read) -> std::io::Result<()>,
+{
+    let mut archive = tar_archive(reader, format);
+
+    for entry in archive.entries().map_err(|e| ExtractionError::Invalid(e.to_string()))? {
+        let mut entry = entry.map_err(|e| ExtractionError::Invalid(e.to_string()))?;
+        let entry_type = entry.header().entry_type();
+        if *entry.path_bytes() != *name.as_bytes() || !(entry_type.is_file() || entry_type.is_contiguous()) {
+            continue;
+        }
+        if entry.size() > MAX_ENTRY_SIZE {
+            return Err(ExtractionError::LimitExceeded(format!("the entry exceeds {} bytes", MAX_ENTRY_SIZE)));
+        }
+
+        read(&mut entry).map_err(|e| ExtractionError::Invalid(e.to_string()))?;
+        return Ok(true);
+    }
+
+    Ok(false)
+}
+
+/// A blocking reader over chunks that arrive through a channel.
+///
+/// This lets archives streamed from S3 be read by the synchronous tar and
+/// gzip decoders on a blocking thread. The sender ends the data by closing
+/// the channel, or signals an error by sending one.
+pub struct ChannelReader {
+    receiver: tokio::sync::mpsc::Receiver<std::io::Result<Bytes>>,
+    current: Bytes,
+}
+
+impl ChannelReader {
+    /// Creates a reader over the chunks received from `receiver`.
+    pub fn new(receiver: tokio::sync::mpsc::Receiver<std::io::Result<Bytes>>) -> Self {
+        Self { receiver, current: Bytes::new() }
+    }
+}
+
+impl Read for ChannelReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.current.is_empty() {
+            match self.receiver.blocking_recv() {
+                Some(chunk) => self.current = chunk?,
+                None => return Ok(0),
+            }
+        }
+        let length = buf.len().min(self.current.len());
+        buf[..length].copy_from_slice(&self.current.split_to(length));
+        Ok(length)
+    }
+}
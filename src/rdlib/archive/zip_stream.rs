@@ -0,0 +1,299 @@
+//! # Streaming ZIP Writer
+//!
+//! This module provides a ZIP64 writer that produces an archive as a
+//! sequence of byte chunks, without seeking and without holding entries in
+//! memory. Sizes and checksums are not known when an entry starts, so each
+//! entry is followed by a data descriptor, and the central directory at the
+//! end of the archive carries the final values.
+
+use std::io::Write;
+
+use chrono::{DateTime, Datelike, Timelike, Utc};
+use flate2::Compression;
+use flate2::write::DeflateEncoder;
+
+const LOCAL_HEADER_SIGNATURE: u32 = 0x0403_4b50;
+const DATA_DESCRIPTOR_SIGNATURE: u32 = 0x0807_4b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x0201_4b50;
+const ZIP64_END_SIGNATURE: u32 = 0x0606_4b50;
+const ZIP64_LOCATOR_SIGNATURE: u32 = 0x0706_4b50;
+const END_SIGNATURE: u32 = 0x0605_4b50;
+
+/// ZIP 4.5, the first version supporting ZIP64
+const VERSION: u16 = 45;
+/// "Made by" a Unix system, so the external attributes hold Unix permissions
+const VERSION_MADE_BY: u16 = (3 << 8) | VERSION;
+/// Bit 3: sizes and CRC follow in a data descriptor; bit 11: names are UTF-8
+const FLAGS: u16 = (1 << 3) | (1 << 11);
+const ZIP64_EXTRA_ID: u16 = 0x0001;
+
+const METHOD_STORED: u16 = 0;
+const METHOD_DEFLATED: u16 = 8;
+
+/// File extensions of formats that are already compressed and are stored as-is
+const COMPRESSED_EXTENSIONS: &[&str] = &[
+    "7z", "aac", "avif", "br", "bz2", "docx", "epub", "flac", "gif", "gz", "heic", "jar",
+    "jpeg", "jpg", "m4a", "m4v", "mkv", "mov", "mp3", "mp4", "ogg", "odp", "ods", "odt",
+    "png", "pptx", "rar", "tgz", "webm", "webp", "xlsx", "xz", "zip", "zst",
+];
+
+/// An entry as recorded in the central directory.
+struct CentralEntry {
+    name: String,
+    method: u16,
+    dos_time: u16,
+    dos_date: u16,
+    crc: u32,
+    compressed_size: u64,
+    uncompressed_size: u64,
+    offset: u64,
+    is_dir: bool,
+}
+
+/// The entry whose data is currently being written.
+struct OpenEntry {
+    entry: CentralEntry,
+    hasher: crc32fast::Hasher,
+    encoder: Option<DeflateEncoder<Vec<u8>>>,
+}
+
+/// A ZIP64 writer producing the archive as byte chunks.
+///
+/// Every method returns the bytes to append to the archive. Entries are
+/// written one at a time: `start_entry`, any number of `write_data`, then
+/// `finish_entry`. `finish` finishes any open entry and writes the central
+/// directory.
+pub struct ZipStreamWriter {
+    offset: u64,
+    entries: Vec<CentralEntry>,
+    open: Option<OpenEntry>,
+}
+
+impl Default for ZipStreamWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Converts a timestamp into the MS-DOS time and date used by ZIP headers.
+fn dos_date_time(time: DateTime<Utc>) -> (u16, u16) {
+    // MS-DOS dates start in 1980
+    if time.year() < 1980 {
+        return (0, (1 << 5) | 1);
+    }
+    let dos_time = ((time.hour() << 11) | (time.minute() << 5) | (time.second() / 2)) as u16;
+    let dos_date = ((((time.year() - 1980) as u32) << 9) | (time.month() << 5) | time.day()) as u16;
+    (dos_time, dos_date)
+}
+
+/// Checks if an entry should be compressed, based on its file extension.
+pub fn should_compress(name: &str) -> bool {
+    let extension = name
+        .rsplit('/')
+        .next()
+        .and_then(|file| file.rsplit_once('.'))
+        .map(|(_, ext)| ext.to_lowercase())
+        .unwrap_or_default();
+    !COMPRESSED_EXTENSIONS.contains(&extension.as_str())
+}
+
+impl ZipStreamWriter {
+    /// Creates a writer for an empty archive.
+    pub fn new() -> Self {
+        Self {
+            offset: 0,
+            entries: Vec::new(),
+            open: None,
+        }
+    }
+
+    /// Records bytes as written and passes them on.
+    fn emit(&mut self, bytes: Vec<u8>) -> Vec<u8> {
+        self.offset += bytes.len() as u64;
+        bytes
+    }
+
+    /// Starts a new entry.
+    ///
+    /// Names ending with a slash are directories and must not receive data.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The path of the entry inside the archive
+    /// * `modified` - The modification time of the entry
+    ///
+    /// # Returns
+    ///
+    /// The local header of the entry
+    pub fn start_entry(&mut self, name: &str, modified: DateTime<Utc>) -> Vec<u8> {
+        assert!(self.open.is_none(), "the previous entry must be finished first");
+
+        let is_dir = name.ends_with('/');
+        let method = if !is_dir && should_compress(name) { METHOD_DEFLATED } else { METHOD_STORED };
+        let (dos_time, dos_date) = dos_date_time(modified);
+
+        let mut header = Vec::with_capacity(30 + name.len() + 20);
+        header.extend_from_slice(&LOCAL_HEADER_SIGNATURE.to_le_bytes());
+        header.extend_from_slice(&VERSION.to_le_bytes());
+        header.extend_from_slice(&FLAGS.to_le_bytes());
+        header.extend_from_slice(&method.to_le_bytes());
+        header.extend_from_slice(&dos_time.to_le_bytes());
+        header.extend_from_slice(&dos_date.to_le_bytes());
+        header.extend_from_slice(&0u32.to_le_bytes()); // CRC, in the data descriptor
+        // Sizes are in the data descriptor; the ZIP64 markers tell readers it uses 8-byte sizes
+        header.extend_from_slice(&u32::MAX.to_le_bytes());
+        header.extend_from_slice(&u32::MAX.to_le_bytes());
+        header.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        header.extend_from_slice(&20u16.to_le_bytes());
+        header.extend_from_slice(name.as_bytes());
+        header.extend_from_slice(&ZIP64_EXTRA_ID.to_le_bytes());
+        header.extend_from_slice(&16u16.to_le_bytes());
+        header.extend_from_slice(&0u64.to_le_bytes());
+        header.extend_from_slice(&0u64.to_le_bytes());
+
+        self.open = Some(OpenEntry {
+            entry: CentralEntry {
+                name: name.to_string(),
+                method,
+                dos_time,
+                dos_date,
+                crc: 0,
+                compressed_size: 0,
+                uncompressed_size: 0,
+                offset: self.offset,
+                is_dir,
+            },
+            hasher: crc32fast::Hasher::new(),
+            encoder: (method == METHOD_DEFLATED).then(|| DeflateEncoder::new(Vec::new(), Compression::default())),
+        });
+
+        self.emit(header)
+    }
+
+    /// Writes data of the current entry.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The next chunk of the entry's data
+    ///
+    /// # Returns
+    ///
+    /// The (possibly compressed) bytes to append, which may be empty
+    pub fn write_data(&mut self, data: &[u8]) -> Vec<u8> {
+        let open = self.open.as_mut().expect("an entry must be started first");
+        open.hasher.update(data);
+        open.entry.uncompressed_size += data.len() as u64;
+
+        let out = match &mut open.encoder {
+            Some(encoder) => {
+                // Writing into a Vec cannot fail
+                encoder.write_all(data).expect("writing to memory failed");
+                std::mem::take(encoder.get_mut())
+            },
+            None => data.to_vec(),
+        };
+        open.entry.compressed_size += out.len() as u64;
+
+        self.emit(out)
+    }
+
+    /// Finishes the current entry.
+    ///
+    /// # Returns
+    ///
+    /// The remaining compressed bytes of the entry and its data descriptor
+    pub fn finish_entry(&mut self) -> Vec<u8> {
+        let OpenEntry { mut entry, hasher, encoder } = self.open.take().expect("an entry must be started first");
+
+        let mut out = match encoder {
+            Some(encoder) => encoder.finish().expect("writing to memory failed"),
+            None => Vec::new(),
+        };
+        entry.compressed_size += out.len() as u64;
+        entry.crc = hasher.finalize();
+
+        out.extend_from_slice(&DATA_DESCRIPTOR_SIGNATURE.to_le_bytes());
+        out.extend_from_slice(&entry.crc.to_le_bytes());
+        out.extend_from_slice(&entry.compressed_size.to_le_bytes());
+        out.extend_from_slice(&entry.uncompressed_size.to_le_bytes());
+
+        self.entries.push(entry);
+        self.emit(out)
+    }
+
+    /// Finishes the archive.
+    ///
+    /// # Returns
+    ///
+    /// The central directory and the end records of the archive
+    pub fn finish(mut self) -> Vec<u8> {
+        let mut finished = if self.open.is_some() { self.finish_entry() } else { Vec::new() };
+
+        let central_start = self.offset;
+        let mut out = Vec::new();
+
+        for entry in &self.entries {
+            let external_attributes: u32 = if entry.is_dir {
+                (0o040755 << 16) | 0x10
+            } else {
+                0o100644 << 16
+            };
+
+            out.extend_from_slice(&CENTRAL_HEADER_SIGNATURE.to_le_bytes());
+            out.extend_from_slice(&VERSION_MADE_BY.to_le_bytes());
+            out.extend_from_slice(&VERSION.to_le_bytes());
+            out.extend_from_slice(&FLAGS.to_le_bytes());
+            out.extend_from_slice(&entry.method.to_le_bytes());
+            out.extend_from_slice(&entry.dos_time.to_le_bytes());
+            out.extend_from_slice(&entry.dos_date.to_le_bytes());
+            out.extend_from_slice(&entry.crc.to_le_bytes());
+            out.extend_from_slice(&u32::MAX.to_le_bytes());
+            out.extend_from_slice(&u32::MAX.to_le_bytes());
+            out.extend_from_slice(&(entry.name.len() as u16).to_le_bytes());
+            out.extend_from_slice(&28u16.to_le_bytes());
+            out.extend_from_slice(&0u16.to_le_bytes()); // comment length
+            out.extend_from_slice(&0u16.to_le_bytes()); // disk number
+            out.extend_from_slice(&0u16.to_le_bytes()); // internal attributes
+            out.extend_from_slice(&external_attributes.to_le_bytes());
+            out.extend_from_slice(&u32::MAX.to_le_bytes());
+            out.extend_from_slice(entry.name.as_bytes());
+            out.extend_from_slice(&ZIP64_EXTRA_ID.to_le_bytes());
+            out.extend_from_slice(&24u16.to_le_bytes());
+            out.extend_from_slice(&entry.uncompressed_size.to_le_bytes());
+            out.extend_from_slice(&entry.compressed_size.to_le_bytes());
+            out.extend_from_slice(&entry.offset.to_le_bytes());
+        }
+
+        let central_size = out.len() as u64;
+        let zip64_end_offset = central_start + central_size;
+        let count = self.entries.len() as u64;
+
+        out.extend_from_slice(&ZIP64_END_SIGNATURE.to_le_bytes());
+        out.extend_from_slice(&44u64.to_le_bytes()); // size of the remaining record
+        out.extend_from_slice(&VERSION_MADE_BY.to_le_bytes());
+        out.extend_from_slice(&VERSION.to_le_bytes());
+        out.extend_from_slice(&0u32.to_le_bytes()); // this disk
+        out.extend_from_slice(&0u32.to_le_bytes()); // disk with the central directory
+        out.extend_from_slice(&count.to_le_bytes());
+        out.extend_from_slice(&count.to_le_bytes());
+        out.extend_from_slice(&central_size.to_le_bytes());
+        out.extend_from_slice(&central_start.to_le_bytes());
+
+        out.extend_from_slice(&ZIP64_LOCATOR_SIGNATURE.to_le_bytes());
+        out.extend_from_slice(&0u32.to_le_bytes());
+        out.extend_from_slice(&zip64_end_offset.to_le_bytes());
+        out.extend_from_slice(&1u32.to_le_bytes()); // total number of disks
+
+        out.extend_from_slice(&END_SIGNATURE.to_le_bytes());
+        out.extend_from_slice(&0u16.to_le_bytes());
+        out.extend_from_slice(&0u16.to_le_bytes());
+        out.extend_from_slice(&u16::MAX.to_le_bytes());
+        out.extend_from_slice(&u16::MAX.to_le_bytes());
+        out.extend_from_slice(&u32::MAX.to_le_bytes());
+        out.extend_from_slice(&u32::MAX.to_le_bytes());
+        out.extend_from_slice(&0u16.to_le_bytes()); // comment length
+
+        finished.extend(self.emit(out));
+        finished
+    }
+}
@@ -0,0 +1,13 @@
+//! # Audit Module
+//!
+//! This module provides the audit log: a persistent record of every
+//! request to the API, with who made it, from where, what it did to which
+//! bucket and object, how it ended and how many bytes it moved. Records
+//! are stored in MySQL next to the user accounts, so questions like "who
+//! deleted this file, and when" can be answered later.
+
+pub mod record;
+pub mod error;
+pub mod store;
+pub mod mysql;
+pub mod service;
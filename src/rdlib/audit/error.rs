@@ -0,0 +1,33 @@
+//! # Audit Errors
+//!
+//! This module defines the errors of recording and querying the audit log.
+
+use std::fmt;
+
+/// Errors of the audit log.
+#[derive(Clone, Debug, PartialEq)]
+pub enum AuditError {
+    /// The page size of a query is not acceptable
+    InvalidLimit(String),
+    /// The time range of a query is not acceptable
+    InvalidTimeRange(String),
+    /// The database failed
+    Database(String),
+}
+
+impl fmt::Display for AuditError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuditError::InvalidLimit(msg) | AuditError::InvalidTimeRange(msg) => f.write_str(msg),
+            AuditError::Database(msg) => write!(f, "Database error: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for AuditError {}
+
+impl From<sqlx::Error> for AuditError {
+    fn from(err: sqlx::Error) -> Self {
+        AuditError::Database(err.to_string())
+    }
+}
@@ -0,0 +1,142 @@
+//! # MySQL Audit Store
+//!
+//! This module stores the audit log in MySQL, in the table created by the
+//! migrations that `MySqlUserStore::migrate` runs. Object keys are stored
+//! in the `object_key` column, as `key` is a reserved word.
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::mysql::MySqlPool;
+use sqlx::{FromRow, QueryBuilder};
+
+use crate::rdlib::audit::error::AuditError;
+use crate::rdlib::audit::record::{AuditFilter, AuditRecord, NewAuditRecord};
+use crate::rdlib::audit::store::AuditStore;
+
+const RECORD_COLUMNS: &str =
+    "id, occurred_at, request_id, actor, ip_address, action, method, bucket, object_key, target, outcome, status, bytes";
+
+/// A row of the `audit_log` table.
+#[derive(FromRow)]
+struct RecordRow {
+    id: u64,
+    occurred_at: DateTime<Utc>,
+    request_id: Option<String>,
+    actor: Option<String>,
+    ip_address: Option<String>,
+    action: String,
+    method: String,
+    bucket: Option<String>,
+    object_key: Option<String>,
+    target: Option<String>,
+    outcome: String,
+    status: u16,
+    bytes: Option<u64>,
+}
+
+impl TryFrom<RecordRow> for AuditRecord {
+    type Error = AuditError;
+
+    fn try_from(row: RecordRow) -> Result<Self, Self::Error> {
+        Ok(AuditRecord {
+            id: row.id,
+            occurred_at: row.occurred_at,
+            request_id: row.request_id,
+            actor: row.actor,
+            ip_address: row.ip_address,
+            action: row.action,
+            method: row.method,
+            bucket: row.bucket,
+            key: row.object_key,
+            target: row.target,
+            outcome: row.outcome.parse().map_err(AuditError::Database)?,
+            status: row.status,
+            bytes: row.bytes,
+        })
+    }
+}
+
+/// Escapes the wildcards of a `LIKE` pattern.
+fn escape_like(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
+
+/// Stores the audit log in MySQL.
+pub struct MySqlAuditStore {
+    pool: MySqlPool,
+}
+
+impl MySqlAuditStore {
+    /// Creates the store on a connection pool.
+    pub fn new(pool: MySqlPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl AuditStore for MySqlAuditStore {
+    async fn add_record(&self, record: NewAuditRecord) -> Result<u64, AuditError> {
+        let result = sqlx::query(
+            "INSERT INTO audit_log \
+             (occurred_at, request_id, actor, ip_address, action, method, bucket, object_key, target, outcome, status, bytes) \
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"
+        )
+            .bind(record.occurred_at)
+            .bind(&record.request_id)
+            .bind(&record.actor)
+            .bind(&record.ip_address)
+            .bind(&record.action)
+            .bind(&record.method)
+            .bind(&record.bucket)
+            .bind(&record.key)
+            .bind(&record.target)
+            .bind(record.outcome.as_str())
+            .bind(record.status)
+            .bind(record.bytes)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.last_insert_id())
+    }
+
+    async fn list_records(&self, filter: &AuditFilter, limit: u32) -> Result<Vec<AuditRecord>, AuditError> {
+        let mut query = QueryBuilder::new(format!("SELECT {} FROM audit_log WHERE TRUE", RECORD_COLUMNS));
+        if let Some(actor) = &filter.actor {
+            query.push(" AND actor = ").push_bind(actor);
+        }
+        if let Some(action) = &filter.action {
+            query.push(" AND action = ").push_bind(action);
+        }
+        if let Some(bucket) = &filter.bucket {
+            query.push(" AND bucket = ").push_bind(bucket);
+        }
+        if let Some(key) = &filter.key {
+            query.push(" AND object_key = ").push_bind(key);
+        }
+        if let Some(prefix) = &filter.prefix {
+            query.push(" AND object_key LIKE ").push_bind(format!("{}%", escape_like(prefix)));
+        }
+        if let Some(outcome) = filter.outcome {
+            query.push(" AND outcome = ").push_bind(outcome.as_str());
+        }
+        if let Some(ip_address) = &filter.ip_address {
+            query.push(" AND ip_address = ").push_bind(ip_address);
+        }
+        if let Some(since) = filter.since {
+            query.push(" AND occurred_at >= ").push_bind(since);
+        }
+        if let Some(until) = filter.until {
+            query.push(" AND occurred_at < ").push_bind(until);
+        }
+        if let Some(before) = filter.before {
+            query.push(" AND id < ").push_bind(before);
+        }
+        query.push(" ORDER BY id DESC LIMIT ").push_bind(limit);
+
+        query.build_query_as::<RecordRow>()
+            .fetch_all(&self.pool)
+            .await?
+            .into_iter()
+            .map(AuditRecord::try_from)
+            .collect()
+    }
+}
@@ -0,0 +1,313 @@
+//! # Audit Records
+//!
+//! This module defines the records of the audit log, the filters they are
+//! queried by, and how requests are named in them. Each endpoint has a
+//! stable action name like `object.delete`, so records can be filtered by
+//! what was done independent of the URL.
+
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// How many records a query returns unless it sets a limit
+pub const DEFAULT_PAGE_SIZE: u32 = 100;
+
+/// The most records a query returns at once
+pub const MAX_PAGE_SIZE: u32 = 1000;
+
+/// The longest stored object key, cut off beyond
+pub const MAX_KEY_LENGTH: usize = 1024;
+
+/// The longest stored value of the other text columns, cut off beyond
+pub const MAX_FIELD_LENGTH: usize = 255;
+
+/// The longest stored action, cut off beyond
+pub const MAX_ACTION_LENGTH: usize = 64;
+
+/// The longest stored request ID, as accepted by the request ID middleware
+pub const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+/// The longest stored HTTP method, cut off beyond
+pub const MAX_METHOD_LENGTH: usize = 16;
+
+/// The action of requests that matched no endpoint
+pub const UNKNOWN_ACTION: &str = "unknown";
+
+/// The actions of the endpoints, by method and path below `/api/v1`
+const ACTIONS: &[(&str, &str, &str)] = &[
+    ("GET", "/buckets", "bucket.list"),
+    ("POST", "/buckets", "bucket.create"),
+    ("DELETE", "/bucket/{name}", "bucket.delete"),
+    ("GET", "/bucket/{bucket}/objects", "object.list"),
+    ("POST", "/bucket/{bucket}/objects", "object.upload"),
+    ("GET", "/bucket/{bucket}/download/{key:.*}", "object.download"),
+    ("GET", "/bucket/{bucket}/view/{key:.*}", "object.view"),
+    ("PUT", "/bucket/{bucket}/object/{key:.*}", "object.put"),
+    ("DELETE", "/bucket/{bucket}/object/{key:.*}", "object.delete"),
+    ("GET", "/bucket/{bucket}/exists", "object.exists"),
+    ("POST", "/bucket/{bucket}/move", "object.move"),
+    ("POST", "/bucket/{bucket}/folders", "folder.create"),
+    ("GET", "/bucket/{bucket}/thumbnail/{key:.*}", "object.thumbnail"),
+    ("POST", "/bucket/{bucket}/archive", "archive.download"),
+    ("GET", "/bucket/{bucket}/archive-contents/{key:.*}", "archive.contents"),
+    ("GET", "/bucket/{bucket}/drops", "drop_link.list"),
+    ("POST", "/bucket/{bucket}/drops", "drop_link.create"),
+    ("DELETE", "/bucket/{bucket}/drops/{token}", "drop_link.delete"),
+    ("GET", "/drop/{bucket}/{token}", "drop_link.info"),
+    ("POST", "/drop/{bucket}/{token}", "drop_link.upload"),
+    ("GET", "/bucket/{bucket}/policy", "policy.get"),
+    ("PUT", "/bucket/{bucket}/policy", "policy.put"),
+    ("DELETE", "/bucket/{bucket}/policy", "policy.delete"),
+    ("OPTIONS", "/bucket/{bucket}/uploads", "upload.options"),
+    ("POST", "/bucket/{bucket}/uploads", "upload.create"),
+    ("HEAD", "/bucket/{bucket}/uploads/{id}", "upload.offset"),
+    ("PATCH", "/bucket/{bucket}/uploads/{id}", "upload.append"),
+    ("DELETE", "/bucket/{bucket}/uploads/{id}", "upload.terminate"),
+    ("POST", "/auth/login", "auth.login"),
+    ("POST", "/auth/refresh", "auth.refresh"),
+    ("POST", "/auth/logout", "auth.logout"),
+    ("GET", "/auth/me", "auth.me"),
+    ("GET", "/auth/oidc/login", "auth.oidc_login"),
+    ("GET", "/auth/oidc/callback", "auth.oidc_callback"),
+    ("POST", "/auth/2fa/setup-token", "two_factor.setup_token"),
+    ("POST", "/auth/2fa/setup", "two_factor.setup"),
+    ("POST", "/auth/2fa/enable", "two_factor.enable"),
+    ("POST", "/auth/2fa/disable", "two_factor.disable"),
+    ("POST", "/auth/2fa/recovery-codes", "two_factor.recovery_codes"),
+    ("POST", "/auth/login/session", "session.login"),
+    ("POST", "/auth/logout/session", "session.logout"),
+    ("GET", "/auth/sessions", "session.list"),
+    ("DELETE", "/auth/sessions/{id}", "session.revoke"),
+    ("GET", "/admin/users", "user.list"),
+    ("POST", "/admin/users", "user.create"),
+    ("GET", "/admin/users/{id}", "user.get"),
+    ("PATCH", "/admin/users/{id}", "user.update"),
+    ("DELETE", "/admin/users/{id}", "user.delete"),
+    ("DELETE", "/admin/users/{id}/2fa", "user.reset_two_factor"),
+    ("DELETE", "/admin/users/{id}/sessions", "user.revoke_sessions"),
+    ("GET", "/admin/grants", "grant.list"),
+    ("POST", "/admin/grants", "grant.create"),
+    ("DELETE", "/admin/grants/{id}", "grant.delete"),
+    ("GET", "/admin/access", "access.effective"),
+    ("GET", "/admin/groups", "group.list"),
+    ("POST", "/admin/groups", "group.create"),
+    ("GET", "/admin/groups/{id}", "group.get"),
+    ("DELETE", "/admin/groups/{id}", "group.delete"),
+    ("GET", "/admin/groups/{id}/members", "group.list_members"),
+    ("POST", "/admin/groups/{id}/members", "group.add_member"),
+    ("DELETE", "/admin/groups/{id}/members/{member_type}/{member}", "group.remove_member"),
+    ("GET", "/admin/service-accounts", "service_account.list"),
+    ("POST", "/admin/service-accounts", "service_account.create"),
+    ("GET", "/admin/service-accounts/{id}", "service_account.get"),
+    ("DELETE", "/admin/service-accounts/{id}", "service_account.delete"),
+    ("GET", "/admin/service-accounts/{id}/keys", "api_key.list"),
+    ("POST", "/admin/service-accounts/{id}/keys", "api_key.create"),
+    ("DELETE", "/admin/service-accounts/{id}/keys/{key_id}", "api_key.revoke"),
+    ("POST", "/admin/service-accounts/{id}/keys/{key_id}/rotate", "api_key.rotate"),
+    ("GET", "/admin/audit", "audit.list"),
+    ("GET", "/admin/audit/export", "audit.export"),
+];
+
+/// Names the action of a request.
+///
+/// # Arguments
+///
+/// * `method` - The HTTP method of the request
+/// * `pattern` - The route pattern the request matched, or `None` if it matched no endpoint
+///
+/// # Returns
+///
+/// The action of the endpoint, `METHOD pattern` for endpoints without a
+/// name, or `unknown` for requests that matched no endpoint
+pub fn action_for(method: &str, pattern: Option<&str>) -> String {
+    let Some(pattern) = pattern else {
+        return UNKNOWN_ACTION.to_string();
+    };
+    let path = pattern.strip_prefix("/api/v1").unwrap_or(pattern);
+    ACTIONS.iter()
+        .find(|(m, p, _)| *m == method && *p == path)
+        .map(|(_, _, action)| action.to_string())
+        .unwrap_or_else(|| format!("{} {}", method, path))
+}
+
+/// How a request ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditOutcome {
+    /// The request succeeded
+    Success,
+    /// The client was not authenticated or not allowed to make the request
+    Denied,
+    /// The request failed
+    Failure,
+}
+
+impl AuditOutcome {
+    /// The outcome of a response status: `denied` for 401 and 403,
+    /// `failure` for other errors, `success` otherwise.
+    pub fn from_status(status: u16) -> Self {
+        match status {
+            401 | 403 => AuditOutcome::Denied,
+            400.. => AuditOutcome::Failure,
+            _ => AuditOutcome::Success,
+        }
+    }
+
+    /// The outcome as it is stored.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditOutcome::Success => "success",
+            AuditOutcome::Denied => "denied",
+            AuditOutcome::Failure => "failure",
+        }
+    }
+}
+
+impl fmt::Display for AuditOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for AuditOutcome {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "success" => Ok(AuditOutcome::Success),
+            "denied" => Ok(AuditOutcome::Denied),
+            "failure" => Ok(AuditOutcome::Failure),
+            other => Err(format!("Unknown outcome '{}'", other)),
+        }
+    }
+}
+
+/// A record of the audit log.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct AuditRecord {
+    /// The ID of the record, increasing in the order records are added
+    pub id: u64,
+    /// When the request was answered
+    pub occurred_at: DateTime<Utc>,
+    /// The `X-Request-Id` of the request
+    pub request_id: Option<String>,
+    /// The grant subject of the authenticated client, or `None` for anonymous requests
+    pub actor: Option<String>,
+    /// The IP address of the client
+    pub ip_address: Option<String>,
+    /// What the request did, such as `object.delete`
+    pub action: String,
+    /// The HTTP method of the request
+    pub method: String,
+    /// The bucket the request was for
+    pub bucket: Option<String>,
+    /// The object key the request was for
+    pub key: Option<String>,
+    /// Other path parameters of the request, such as the ID of a changed user
+    pub target: Option<String>,
+    /// How the request ended
+    pub outcome: AuditOutcome,
+    /// The HTTP status of the response
+    pub status: u16,
+    /// The size of the uploaded or returned data, if known
+    pub bytes: Option<u64>,
+}
+
+/// A record to be added to the audit log.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NewAuditRecord {
+    pub occurred_at: DateTime<Utc>,
+    pub request_id: Option<String>,
+    pub actor: Option<String>,
+    pub ip_address: Option<String>,
+    pub action: String,
+    pub method: String,
+    pub bucket: Option<String>,
+    pub key: Option<String>,
+    pub target: Option<String>,
+    pub outcome: AuditOutcome,
+    pub status: u16,
+    pub bytes: Option<u64>,
+}
+
+impl NewAuditRecord {
+    /// Cuts the text fields to the lengths the audit log stores.
+    pub fn truncated(mut self) -> Self {
+        let cut = |value: Option<String>, max: usize| value.map(|v| v.chars().take(max).collect());
+        self.request_id = cut(self.request_id, MAX_REQUEST_ID_LENGTH);
+        self.action = self.action.chars().take(MAX_ACTION_LENGTH).collect();
+        self.method = self.method.chars().take(MAX_METHOD_LENGTH).collect();
+        self.actor = cut(self.actor, MAX_FIELD_LENGTH);
+        self.bucket = cut(self.bucket, MAX_FIELD_LENGTH);
+        self.key = cut(self.key, MAX_KEY_LENGTH);
+        self.target = cut(self.target, MAX_FIELD_LENGTH);
+        self
+    }
+}
+
+/// Which records of the audit log a query returns. Records come newest
+/// first; the next page starts before the ID of the last record.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AuditFilter {
+    /// Only records of this actor
+    pub actor: Option<String>,
+    /// Only records of this action
+    pub action: Option<String>,
+    /// Only records for this bucket
+    pub bucket: Option<String>,
+    /// Only records for this object key
+    pub key: Option<String>,
+    /// Only records for object keys starting with this prefix
+    pub prefix: Option<String>,
+    /// Only records with this outcome
+    pub outcome: Option<AuditOutcome>,
+    /// Only records from this IP address
+    pub ip_address: Option<String>,
+    /// Only records at or after this time
+    pub since: Option<DateTime<Utc>>,
+    /// Only records before this time
+    pub until: Option<DateTime<Utc>>,
+    /// Only records with a lower ID, to get the next page
+    pub before: Option<u64>,
+}
+
+/// The header of CSV exports.
+pub const CSV_HEADER: &str =
+    "id,occurred_at,request_id,actor,ip_address,action,method,bucket,key,target,outcome,status,bytes\r\n";
+
+impl AuditRecord {
+    /// Formats the record as a CSV line (RFC 4180) with the columns of `CSV_HEADER`.
+    pub fn to_csv_line(&self) -> String {
+        let text = |value: &Option<String>| value.as_deref().map(csv_field).unwrap_or_default();
+        format!(
+            "{},{},{},{},{},{},{},{},{},{},{},{},{}\r\n",
+            self.id,
+            self.occurred_at.to_rfc3339(),
+            text(&self.request_id),
+            text(&self.actor),
+            text(&self.ip_address),
+            csv_field(&self.action),
+            csv_field(&self.method),
+            text(&self.bucket),
+            text(&self.key),
+            text(&self.target),
+            self.outcome,
+            self.status,
+            self.bytes.map(|bytes| bytes.to_string()).unwrap_or_default(),
+        )
+    }
+}
+
+/// Quotes a CSV field if it needs it. Fields starting with a character
+/// spreadsheets treat as formula are prefixed with `'`, so an exported
+/// object key cannot run as formula.
+fn csv_field(value: &str) -> String {
+    let value = if value.starts_with(['=', '+', '-', '@']) { format!("'{}", value) } else { value.to_string() };
+    if value.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
@@ -0,0 +1,100 @@
+//! # Audit Service
+//!
+//! This module provides the operations on the audit log on top of an
+//! `AuditStore`: adding the records of requests and querying them page by
+//! page.
+
+use std::env;
+use std::sync::Arc;
+
+use log::{info, warn};
+use serde::Serialize;
+
+use crate::rdlib::audit::error::AuditError;
+use crate::rdlib::audit::mysql::MySqlAuditStore;
+use crate::rdlib::audit::record::{AuditFilter, AuditRecord, NewAuditRecord, DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE};
+use crate::rdlib::audit::store::AuditStore;
+use crate::rdlib::users::mysql::connect_pool;
+
+/// A page of audit records.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct AuditPage {
+    /// The records, newest first
+    pub records: Vec<AuditRecord>,
+    /// The `before` of the next page, or `None` if this is the last page
+    pub next_before: Option<u64>,
+}
+
+/// Records requests in the audit log and queries it.
+pub struct AuditService {
+    store: Arc<dyn AuditStore>,
+}
+
+impl AuditService {
+    /// Creates the service.
+    ///
+    /// # Arguments
+    ///
+    /// * `store` - Where the audit log is kept
+    pub fn new(store: Arc<dyn AuditStore>) -> Self {
+        Self { store }
+    }
+
+    /// Creates the service from the environment.
+    ///
+    /// The audit log is kept in the database of `RUSTDOK_DATABASE_URL`,
+    /// whose schema is created by `UserService::from_env`, unless
+    /// `RUSTDOK_AUDIT_DISABLED` is `true`.
+    ///
+    /// # Returns
+    ///
+    /// The service, `None` if no database is configured or the audit log is
+    /// disabled, or an error if the database cannot be reached
+    pub async fn from_env() -> Result<Option<Self>, String> {
+        let Ok(url) = env::var("RUSTDOK_DATABASE_URL") else {
+            info!("RUSTDOK_DATABASE_URL is not set; the audit log is disabled");
+            return Ok(None);
+        };
+        if env::var("RUSTDOK_AUDIT_DISABLED").is_ok_and(|v| v == "true" || v == "1") {
+            warn!("The audit log is disabled: requests are not recorded");
+            return Ok(None);
+        }
+        let pool = connect_pool(&url).await.map_err(|e| format!("Cannot connect to the audit database: {}", e))?;
+        Ok(Some(Self::new(Arc::new(MySqlAuditStore::new(pool)))))
+    }
+
+    /// Adds the record of a request to the audit log.
+    ///
+    /// # Returns
+    ///
+    /// The ID of the record
+    pub async fn record(&self, record: NewAuditRecord) -> Result<u64, AuditError> {
+        self.store.add_record(record.truncated()).await
+    }
+
+    /// Lists the records matching a filter, newest first.
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` - Which records to list; its `before` selects the page
+    /// * `limit` - The most records to return, 100 unless set and at most 1000
+    ///
+    /// # Returns
+    ///
+    /// A page of records, or an error if the limit or time range is not acceptable
+    pub async fn list(&self, filter: &AuditFilter, limit: Option<u32>) -> Result<AuditPage, AuditError> {
+        let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE);
+        if limit == 0 || limit > MAX_PAGE_SIZE {
+            return Err(AuditError::InvalidLimit(format!("Limit must be between 1 and {}", MAX_PAGE_SIZE)));
+        }
+        if let (Some(since), Some(until)) = (filter.since, filter.until)
+            && since >= until
+        {
+            return Err(AuditError::InvalidTimeRange("The end of the time range must be after its start".to_string()));
+        }
+
+        let records = self.store.list_records(filter, limit).await?;
+        let next_before = if records.len() == limit as usize { records.last().map(|record| record.id) } else { None };
+        Ok(AuditPage { records, next_before })
+    }
+}
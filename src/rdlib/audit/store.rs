@@ -0,0 +1,20 @@
+//! # Audit Store
+//!
+//! This module defines the storage of the audit log. The server keeps it
+//! in MySQL (see `mysql`); the trait lets tests use an in-memory
+//! substitute.
+
+use async_trait::async_trait;
+
+use crate::rdlib::audit::error::AuditError;
+use crate::rdlib::audit::record::{AuditFilter, AuditRecord, NewAuditRecord};
+
+/// Storage of the audit log. Records are only ever added.
+#[async_trait]
+pub trait AuditStore: Send + Sync {
+    /// Adds a record, returning its ID.
+    async fn add_record(&self, record: NewAuditRecord) -> Result<u64, AuditError>;
+
+    /// Lists up to `limit` records matching a filter, newest first.
+    async fn list_records(&self, filter: &AuditFilter, limit: u32) -> Result<Vec<AuditRecord>, AuditError>;
+}
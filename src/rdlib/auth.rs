@@ -0,0 +1,9 @@
+//! # Authentication
+//!
+//! This module provides the authentication of API clients. It defines the
+//! principal a request is made by, the verification of the bearer tokens
+//! that identify it and the issuing of tokens to users who log in.
+
+pub mod principal;
+pub mod jwt;
+pub mod tokens;
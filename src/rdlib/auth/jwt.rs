@@ -0,0 +1,311 @@
+//! # JWT Verification
+//!
+//! This module verifies the JSON Web Tokens that clients send as bearer
+//! tokens. Tokens are accepted if they are signed with one of the configured
+//! keys, using HS256 with a shared secret or RS256/ES256 with a public key
+//! given as a PEM file or in a JWKS file, and their `exp`, `nbf`, `iss` and
+//! `aud` claims hold.
+
+use std::{env, fmt, fs};
+
+use jsonwebtoken::errors::{Error as JwtError, ErrorKind};
+use jsonwebtoken::jwk::{AlgorithmParameters, EllipticCurve, Jwk, JwkSet};
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use log::{info, warn};
+use serde_json::{Map, Value};
+
+use crate::rdlib::auth::principal::Principal;
+
+/// How much clock skew is tolerated unless `RUSTDOK_JWT_LEEWAY` is set, in seconds
+pub const DEFAULT_LEEWAY_SECONDS: u64 = 60;
+
+/// The signature algorithms tokens may use
+pub const SUPPORTED_ALGORITHMS: [Algorithm; 3] = [Algorithm::HS256, Algorithm::RS256, Algorithm::ES256];
+
+/// The shortest shared secret that does not cause a warning, in bytes
+const MIN_SECRET_LENGTH: usize = 32;
+
+/// Why a token was rejected.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TokenError {
+    /// The token is not a JWT
+    Malformed,
+    /// The token is signed with an algorithm that is not accepted
+    UnsupportedAlgorithm,
+    /// No configured key can verify the token
+    UnknownKey,
+    /// The signature does not match
+    InvalidSignature,
+    /// The `exp` claim has passed
+    Expired,
+    /// The `nbf` claim has not been reached
+    NotYetValid,
+    /// The `aud` claim does not name this server
+    InvalidAudience,
+    /// The `iss` claim does not name a trusted issuer
+    InvalidIssuer,
+    /// A required claim is missing
+    MissingClaim(String),
+    /// The claims cannot be read
+    InvalidClaims,
+}
+
+impl fmt::Display for TokenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenError::Malformed => f.write_str("The token is not a valid JWT"),
+            TokenError::UnsupportedAlgorithm => f.write_str("The token is signed with an unsupported algorithm"),
+            TokenError::UnknownKey => f.write_str("The token is not signed with a trusted key"),
+            TokenError::InvalidSignature => f.write_str("The token signature is invalid"),
+            TokenError::Expired => f.write_str("The token has expired"),
+            TokenError::NotYetValid => f.write_str("The token is not valid yet"),
+            TokenError::InvalidAudience => f.write_str("The token is not intended for this server"),
+            TokenError::InvalidIssuer => f.write_str("The token is not issued by a trusted issuer"),
+            TokenError::MissingClaim(claim) => write!(f, "The token has no `{}` claim", claim),
+            TokenError::InvalidClaims => f.write_str("The token claims are invalid"),
+        }
+    }
+}
+
+impl std::error::Error for TokenError {}
+
+impl From<JwtError> for TokenError {
+    fn from(err: JwtError) -> Self {
+        match err.kind() {
+            ErrorKind::InvalidSignature => TokenError::InvalidSignature,
+            ErrorKind::ExpiredSignature => TokenError::Expired,
+            ErrorKind::ImmatureSignature => TokenError::NotYetValid,
+            ErrorKind::InvalidAudience => TokenError::InvalidAudience,
+            ErrorKind::InvalidIssuer => TokenError::InvalidIssuer,
+            ErrorKind::MissingRequiredClaim(claim) => TokenError::MissingClaim(claim.clone()),
+            ErrorKind::InvalidAlgorithm => TokenError::UnsupportedAlgorithm,
+            ErrorKind::Json(_) | ErrorKind::InvalidSubject => TokenError::InvalidClaims,
+            _ => TokenError::Malformed,
+        }
+    }
+}
+
+/// A key tokens can be signed with.
+struct VerificationKey {
+    /// The key ID, matched against the `kid` header of tokens
+    id: Option<String>,
+    algorithm: Algorithm,
+    key: DecodingKey,
+    /// Whether RustDok signs its own tokens with the key
+    local: bool,
+}
+
+/// Verifies bearer tokens against the configured keys and claims.
+pub struct JwtVerifier {
+    keys: Vec<VerificationKey>,
+    issuers: Vec<String>,
+    audiences: Vec<String>,
+    leeway: u64,
+}
+
+impl Default for JwtVerifier {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl JwtVerifier {
+    /// Creates a verifier without keys, which rejects every token.
+    pub fn new() -> Self {
+        Self { keys: Vec::new(), issuers: Vec::new(), audiences: Vec::new(), leeway: DEFAULT_LEEWAY_SECONDS }
+    }
+
+    /// Creates a verifier from the environment.
+    ///
+    /// # Environment Variables
+    ///
+    /// * `RUSTDOK_JWT_SECRET` - The shared secret of HS256 tokens
+    /// * `RUSTDOK_JWT_PUBLIC_KEY_FILE` - A PEM file with the RSA or EC public key of RS256 or ES256 tokens
+    /// * `RUSTDOK_JWT_JWKS_FILE` - A JWKS file with the keys of RS256, ES256 or HS256 tokens
+    /// * `RUSTDOK_JWT_ISSUER` - The trusted issuers, comma-separated (optional)
+    /// * `RUSTDOK_JWT_AUDIENCE` - The audiences naming this server, comma-separated (optional)
+    /// * `RUSTDOK_JWT_LEEWAY` - The tolerated clock skew in seconds (optional)
+    ///
+    /// # Returns
+    ///
+    /// The verifier, or an error if a key cannot be read or no key is configured
+    pub fn from_env() -> Result<Self, String> {
+        let mut verifier = Self::new()
+            .with_issuers(list_from_env("RUSTDOK_JWT_ISSUER"))
+            .with_audiences(list_from_env("RUSTDOK_JWT_AUDIENCE"));
+
+        if let Some(leeway) = env::var("RUSTDOK_JWT_LEEWAY").ok().and_then(|v| v.parse::<u64>().ok()) {
+            verifier = verifier.with_leeway(leeway);
+        }
+        if let Ok(secret) = env::var("RUSTDOK_JWT_SECRET") && !secret.is_empty() {
+            verifier = verifier.with_secret(secret.as_bytes());
+        }
+        if let Ok(path) = env::var("RUSTDOK_JWT_PUBLIC_KEY_FILE") {
+            let pem = fs::read(&path).map_err(|e| format!("Cannot read public key file '{}': {}", path, e))?;
+            verifier = verifier.with_public_key_pem(&pem).map_err(|e| format!("Invalid public key file '{}': {}", path, e))?;
+        }
+        if let Ok(path) = env::var("RUSTDOK_JWT_JWKS_FILE") {
+            let jwks = fs::read_to_string(&path).map_err(|e| format!("Cannot read JWKS file '{}': {}", path, e))?;
+            verifier = verifier.with_jwks(&jwks).map_err(|e| format!("Invalid JWKS file '{}': {}", path, e))?;
+        }
+
+        if verifier.keys.is_empty() {
+            return Err(
+                "No JWT key configured: set RUSTDOK_JWT_SECRET, RUSTDOK_JWT_PUBLIC_KEY_FILE or RUSTDOK_JWT_JWKS_FILE".to_string()
+            );
+        }
+        info!("JWT verification with {} key(s), issuers {:?}, audiences {:?}", verifier.keys.len(), verifier.issuers, verifier.audiences);
+        Ok(verifier)
+    }
+
+    /// Accepts HS256 tokens signed with a shared secret.
+    pub fn with_secret(mut self, secret: &[u8]) -> Self {
+        if secret.len() < MIN_SECRET_LENGTH {
+            warn!("The JWT secret is shorter than {} bytes and easy to guess", MIN_SECRET_LENGTH);
+        }
+        self.keys.push(VerificationKey { id: None, algorithm: Algorithm::HS256, key: DecodingKey::from_secret(secret), local: true });
+        self
+    }
+
+    /// Accepts RS256 tokens signed with the private key of an RSA public key,
+    /// or ES256 tokens signed with the private key of a P-256 public key.
+    ///
+    /// # Arguments
+    ///
+    /// * `pem` - The public key in PEM format
+    pub fn with_public_key_pem(mut self, pem: &[u8]) -> Result<Self, String> {
+        let (algorithm, key) = match DecodingKey::from_rsa_pem(pem) {
+            Ok(key) => (Algorithm::RS256, key),
+            Err(_) => (Algorithm::ES256, DecodingKey::from_ec_pem(pem).map_err(|_| "not an RSA or EC public key".to_string())?),
+        };
+        self.keys.push(VerificationKey { id: None, algorithm, key, local: false });
+        Ok(self)
+    }
+
+    /// Accepts tokens signed with the keys of a JSON Web Key Set.
+    ///
+    /// Keys whose algorithm is not supported, or that are not meant for
+    /// signatures, are skipped.
+    ///
+    /// # Arguments
+    ///
+    /// * `jwks` - The key set as JSON
+    pub fn with_jwks(mut self, jwks: &str) -> Result<Self, String> {
+        let set: JwkSet = serde_json::from_str(jwks).map_err(|e| e.to_string())?;
+        for jwk in &set.keys {
+            let Some(algorithm) = jwk_algorithm(jwk) else {
+                warn!("Skipping JWK {:?}: unsupported algorithm", jwk.common.key_id);
+                continue;
+            };
+            let key = DecodingKey::from_jwk(jwk).map_err(|e| format!("JWK {:?}: {}", jwk.common.key_id, e))?;
+            self.keys.push(VerificationKey { id: jwk.common.key_id.clone(), algorithm, key, local: false });
+        }
+        Ok(self)
+    }
+
+    /// Only accepts tokens whose `iss` claim is one of these issuers.
+    pub fn with_issuers(mut self, issuers: Vec<String>) -> Self {
+        self.issuers = issuers;
+        self
+    }
+
+    /// Only accepts tokens whose `aud` claim contains one of these audiences.
+    pub fn with_audiences(mut self, audiences: Vec<String>) -> Self {
+        self.audiences = audiences;
+        self
+    }
+
+    /// Sets the tolerated clock skew for the `exp` and `nbf` claims, in seconds.
+    pub fn with_leeway(mut self, leeway: u64) -> Self {
+        self.leeway = leeway;
+        self
+    }
+
+    /// Verifies a token.
+    ///
+    /// # Arguments
+    ///
+    /// * `token` - The token, without the `Bearer` scheme
+    ///
+    /// # Returns
+    ///
+    /// The principal identified by the token, or why the token was rejected
+    pub fn verify(&self, token: &str) -> Result<Principal, TokenError> {
+        let header = decode_header(token).map_err(|_| TokenError::Malformed)?;
+        if !SUPPORTED_ALGORITHMS.contains(&header.alg) {
+            return Err(TokenError::UnsupportedAlgorithm);
+        }
+
+        let candidates = self.keys.iter()
+            .filter(|key| key.algorithm == header.alg)
+            .filter(|key| match (&header.kid, &key.id) {
+                (Some(kid), Some(id)) => kid == id,
+                _ => true,
+            });
+        let validation = self.validation(header.alg);
+
+        let mut result = Err(TokenError::UnknownKey);
+        for key in candidates {
+            match decode::<Map<String, Value>>(token, &key.key, &validation) {
+                Ok(data) => {
+                    let principal = Principal::from_claims(data.claims).ok_or(TokenError::MissingClaim("sub".to_string()))?;
+                    return Ok(if self.is_local(key, &principal) { principal.into_local() } else { principal });
+                },
+                // Another key with the same algorithm may have signed the token
+                Err(e) if matches!(e.kind(), ErrorKind::InvalidSignature) => result = Err(TokenError::InvalidSignature),
+                Err(e) => return Err(e.into()),
+            }
+        }
+        result
+    }
+
+    /// Checks if a token was issued by RustDok: it is signed with the shared
+    /// secret and carries the issuer RustDok puts in its own tokens, the
+    /// first of `RUSTDOK_JWT_ISSUER`.
+    fn is_local(&self, key: &VerificationKey, principal: &Principal) -> bool {
+        key.local && principal.issuer.as_ref() == self.issuers.first()
+    }
+
+    fn validation(&self, algorithm: Algorithm) -> Validation {
+        let mut validation = Validation::new(algorithm);
+        validation.leeway = self.leeway;
+        validation.validate_nbf = true;
+
+        let mut required = vec!["exp", "sub"];
+        if !self.issuers.is_empty() {
+            validation.set_issuer(&self.issuers);
+            required.push("iss");
+        }
+        if self.audiences.is_empty() {
+            validation.validate_aud = false;
+        } else {
+            validation.set_audience(&self.audiences);
+            required.push("aud");
+        }
+        validation.set_required_spec_claims(&required);
+        validation
+    }
+}
+
+/// The supported algorithm a JWK is meant for, from its `alg` member or,
+/// without one, its key type.
+fn jwk_algorithm(jwk: &Jwk) -> Option<Algorithm> {
+    if jwk.common.public_key_use.as_ref().is_some_and(|usage| *usage != jsonwebtoken::jwk::PublicKeyUse::Signature) {
+        return None;
+    }
+    let algorithm = match (&jwk.common.key_algorithm, &jwk.algorithm) {
+        (Some(algorithm), _) => algorithm.to_string().parse().ok()?,
+        (None, AlgorithmParameters::RSA(_)) => Algorithm::RS256,
+        (None, AlgorithmParameters::EllipticCurve(params)) if params.curve == EllipticCurve::P256 => Algorithm::ES256,
+        (None, AlgorithmParameters::OctetKey(_)) => Algorithm::HS256,
+        (None, _) => return None,
+    };
+    SUPPORTED_ALGORITHMS.contains(&algorithm).then_some(algorithm)
+}
+
+/// Reads a comma-separated list from an environment variable.
+fn list_from_env(name: &str) -> Vec<String> {
+    env::var(name)
+        .map(|v| v.split(',').map(str::trim).filter(|s| !s.is_empty()).map(str::to_string).collect())
+        .unwrap_or_default()
+}
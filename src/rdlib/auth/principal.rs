@@ -0,0 +1,108 @@
+//! # Principals
+//!
+//! This module defines the principal: the authenticated client a request
+//! is made by, as identified by the claims of its token. Tokens issued at
+//! login carry the role of the user in their `roles` claim; tokens of
+//! identity providers may name the groups of the user in a `groups` claim.
+//! Requests made with an API key carry the scopes of the key in a `scopes`
+//! claim.
+//!
+//! Grants and group memberships name principals by their grant subject.
+//! Users of RustDok are named by their username and service accounts by
+//! `sa:<name>`; principals vouched for by another issuer are named by
+//! `<issuer>#<subject>`, so a token of another issuer cannot take on the
+//! grants of a local user or service account of the same name.
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::rdlib::apikeys::key::KeyScope;
+
+/// The authenticated client of a request.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Principal {
+    /// Who the client is, from the `sub` claim
+    pub subject: String,
+    /// Who vouched for the client, from the `iss` claim
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub issuer: Option<String>,
+    /// All claims of the token
+    pub claims: Map<String, Value>,
+    /// Whether RustDok itself vouched for the client: its own tokens, web
+    /// UI sessions and API keys
+    #[serde(skip)]
+    pub local: bool,
+}
+
+/// Separates the issuer from the subject in the grant subjects of principals
+/// of other issuers; usernames and service account names cannot contain it
+pub const ISSUER_SEPARATOR: char = '#';
+
+impl Principal {
+    /// Creates a principal from the claims of a verified token.
+    ///
+    /// # Arguments
+    ///
+    /// * `claims` - The claims of the token
+    ///
+    /// # Returns
+    ///
+    /// The principal, or `None` if the token has no string `sub` claim
+    pub fn from_claims(claims: Map<String, Value>) -> Option<Self> {
+        let subject = claims.get("sub")?.as_str().filter(|sub| !sub.is_empty())?.to_string();
+        let issuer = claims.get("iss").and_then(Value::as_str).map(str::to_string);
+        Some(Self { subject, issuer, claims, local: false })
+    }
+
+    /// Marks the principal as vouched for by RustDok itself.
+    pub fn into_local(mut self) -> Self {
+        self.local = true;
+        self
+    }
+
+    /// The subject grants and group memberships name the principal by: the
+    /// subject of local principals, `<issuer>#<subject>` for others.
+    pub fn grant_subject(&self) -> String {
+        if self.local {
+            self.subject.clone()
+        } else {
+            format!("{}{}{}", self.issuer.as_deref().unwrap_or_default(), ISSUER_SEPARATOR, self.subject)
+        }
+    }
+
+    /// The roles of the principal, from the `roles` claim.
+    pub fn roles(&self) -> Vec<&str> {
+        match self.claims.get("roles") {
+            Some(Value::Array(roles)) => roles.iter().filter_map(Value::as_str).collect(),
+            Some(Value::String(role)) => vec![role.as_str()],
+            _ => Vec::new(),
+        }
+    }
+
+    /// The groups the principal is a member of, from the `groups` claim.
+    pub fn groups(&self) -> Vec<&str> {
+        match self.claims.get("groups") {
+            Some(Value::Array(groups)) => groups.iter().filter_map(Value::as_str).collect(),
+            Some(Value::String(group)) => vec![group.as_str()],
+            _ => Vec::new(),
+        }
+    }
+
+    /// The scopes limiting the principal, from the `scopes` claim.
+    ///
+    /// # Returns
+    ///
+    /// The scopes, `None` if the principal is not limited by scopes, or no
+    /// scopes at all if the claim is malformed, so nothing is allowed
+    pub fn scopes(&self) -> Option<Vec<KeyScope>> {
+        let claim = self.claims.get("scopes")?;
+        Some(serde_json::from_value::<Vec<KeyScope>>(claim.clone()).unwrap_or_default())
+    }
+
+    /// Checks if the principal may manage users: a principal RustDok vouched
+    /// for with the `admin` role. The roles claimed by tokens of other issuers
+    /// do not count, as any trusted issuer could claim them.
+    pub fn is_admin(&self) -> bool {
+        self.local && self.roles().contains(&"admin")
+    }
+}
@@ -0,0 +1,135 @@
+//! # Token Issuing
+//!
+//! This module issues the tokens of users who log in with a password: a
+//! short-lived access token, a HS256 JWT signed with `RUSTDOK_JWT_SECRET`
+//! that the API accepts as bearer token, and a long-lived refresh token, a
+//! random string that can be exchanged once for a new pair of tokens.
+
+use std::env;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, Duration, Utc};
+use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+use serde::Serialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::rdlib::users::user::{RefreshToken, User};
+
+/// How long access tokens are valid unless `RUSTDOK_ACCESS_TOKEN_TTL` is set, in seconds
+pub const DEFAULT_ACCESS_TOKEN_TTL_SECONDS: i64 = 15 * 60;
+
+/// How long refresh tokens are valid unless `RUSTDOK_REFRESH_TOKEN_TTL` is set, in seconds
+pub const DEFAULT_REFRESH_TOKEN_TTL_SECONDS: i64 = 30 * 24 * 60 * 60;
+
+/// The tokens returned at login.
+#[derive(Clone, Debug, Serialize)]
+pub struct TokenPair {
+    /// The bearer token for API requests
+    pub access_token: String,
+    /// Always `Bearer`
+    pub token_type: &'static str,
+    /// How long the access token is valid, in seconds
+    pub expires_in: i64,
+    /// The token to get a new pair of tokens with
+    pub refresh_token: String,
+    /// How long the refresh token is valid, in seconds
+    pub refresh_expires_in: i64,
+}
+
+/// Issues access and refresh tokens.
+pub struct TokenIssuer {
+    key: EncodingKey,
+    issuer: Option<String>,
+    audience: Option<String>,
+    access_ttl: Duration,
+    refresh_ttl: Duration,
+}
+
+impl TokenIssuer {
+    /// Creates an issuer signing access tokens with a shared secret.
+    pub fn new(secret: &[u8]) -> Self {
+        Self {
+            key: EncodingKey::from_secret(secret),
+            issuer: None,
+            audience: None,
+            access_ttl: Duration::seconds(DEFAULT_ACCESS_TOKEN_TTL_SECONDS),
+            refresh_ttl: Duration::seconds(DEFAULT_REFRESH_TOKEN_TTL_SECONDS),
+        }
+    }
+
+    /// Creates an issuer from the environment.
+    ///
+    /// Access tokens are signed with `RUSTDOK_JWT_SECRET` and carry the
+    /// first of `RUSTDOK_JWT_ISSUER` and `RUSTDOK_JWT_AUDIENCE`, so the
+    /// API accepts them. Their lifetimes are read from
+    /// `RUSTDOK_ACCESS_TOKEN_TTL` and `RUSTDOK_REFRESH_TOKEN_TTL`, in seconds.
+    ///
+    /// # Returns
+    ///
+    /// The issuer, or an error if `RUSTDOK_JWT_SECRET` is not set
+    pub fn from_env() -> Result<Self, String> {
+        let secret = env::var("RUSTDOK_JWT_SECRET").ok().filter(|secret| !secret.is_empty())
+            .ok_or_else(|| "User accounts need RUSTDOK_JWT_SECRET to sign access tokens".to_string())?;
+        let first = |name: &str| env::var(name).ok()
+            .and_then(|v| v.split(',').map(str::trim).find(|s| !s.is_empty()).map(str::to_string));
+        let seconds = |name: &str, default: i64| env::var(name).ok()
+            .and_then(|v| v.parse::<i64>().ok())
+            .filter(|seconds| *seconds > 0)
+            .unwrap_or(default);
+
+        let mut issuer = Self::new(secret.as_bytes());
+        issuer.issuer = first("RUSTDOK_JWT_ISSUER");
+        issuer.audience = first("RUSTDOK_JWT_AUDIENCE");
+        issuer.access_ttl = Duration::seconds(seconds("RUSTDOK_ACCESS_TOKEN_TTL", DEFAULT_ACCESS_TOKEN_TTL_SECONDS));
+        issuer.refresh_ttl = Duration::seconds(seconds("RUSTDOK_REFRESH_TOKEN_TTL", DEFAULT_REFRESH_TOKEN_TTL_SECONDS));
+        Ok(issuer)
+    }
+
+    /// Issues an access token and a refresh token for a user.
+    ///
+    /// # Returns
+    ///
+    /// The tokens, and the refresh token as it must be stored
+    pub fn issue(&self, user: &User, now: DateTime<Utc>) -> Result<(TokenPair, RefreshToken), String> {
+        let mut claims = json!({
+            "sub": user.username,
+            "uid": user.id,
+            "roles": [user.role.as_str()],
+            "iat": now.timestamp(),
+            "exp": (now + self.access_ttl).timestamp(),
+            "jti": Uuid::new_v4().to_string(),
+        });
+        if let Some(issuer) = &self.issuer {
+            claims["iss"] = json!(issuer);
+        }
+        if let Some(audience) = &self.audience {
+            claims["aud"] = json!(audience);
+        }
+        let access_token = encode(&Header::new(Algorithm::HS256), &claims, &self.key).map_err(|e| e.to_string())?;
+
+        let refresh_token = format!(
+            "{}{}", URL_SAFE_NO_PAD.encode(Uuid::new_v4().as_bytes()), URL_SAFE_NO_PAD.encode(Uuid::new_v4().as_bytes())
+        );
+        let stored = RefreshToken {
+            token_hash: hash_refresh_token(&refresh_token),
+            user_id: user.id,
+            expires_at: now + self.refresh_ttl,
+        };
+
+        Ok((TokenPair {
+            access_token,
+            token_type: "Bearer",
+            expires_in: self.access_ttl.num_seconds(),
+            refresh_token,
+            refresh_expires_in: self.refresh_ttl.num_seconds(),
+        }, stored))
+    }
+}
+
+/// The hash a refresh token is stored by, so a leaked database does not leak usable tokens.
+pub fn hash_refresh_token(token: &str) -> String {
+    Sha256::digest(token.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect()
+}
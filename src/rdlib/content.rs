@@ -0,0 +1,204 @@
+//! # Content Type Detection
+//!
+//! This module provides content sniffing for objects served to browsers.
+//! The content type declared by an object's file name is checked against
+//! the type detected from the object's first bytes, so that a renamed
+//! executable is not served as an image, and active content such as HTML
+//! or SVG is only rendered inline when explicitly allowed.
+
+use std::env;
+use once_cell::sync::Lazy;
+
+/// The number of leading bytes inspected when sniffing content
+pub const SNIFF_LENGTH: usize = 8192;
+
+/// Whether HTML, SVG and XML objects may be rendered inline by `/view`.
+///
+/// Controlled by the `RUSTDOK_VIEW_INLINE_ACTIVE_CONTENT` environment variable
+/// (`true` or `false`, default `false`).
+static INLINE_ACTIVE_CONTENT: Lazy<bool> = Lazy::new(|| {
+    env::var("RUSTDOK_VIEW_INLINE_ACTIVE_CONTENT")
+        .map(|v| v.eq_ignore_ascii_case("true") || v == "1")
+        .unwrap_or(false)
+});
+
+/// Content types that are container formats based on ZIP
+const ZIP_BASED_TYPES: &[&str] = &[
+    "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+    "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+    "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+    "application/vnd.oasis.opendocument.text",
+    "application/vnd.oasis.opendocument.spreadsheet",
+    "application/vnd.oasis.opendocument.presentation",
+    "application/epub+zip",
+    "application/java-archive",
+    "application/vnd.android.package-archive",
+];
+
+/// How an object should be served to a browser.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServeDecision {
+    /// The content type to send
+    pub content_type: String,
+    /// Whether the object may be rendered inline, or must be downloaded as an attachment
+    pub inline: bool,
+    /// Whether the sniffed content contradicts the declared content type
+    pub mismatch: bool,
+}
+
+/// Returns whether HTML, SVG and XML objects may be rendered inline.
+pub fn inline_active_content_allowed() -> bool {
+    *INLINE_ACTIVE_CONTENT
+}
+
+/// Normalizes a content type for comparison.
+///
+/// Parameters are dropped, and the `x-` prefix of the subtype is removed so
+/// that e.g. `audio/x-wav` and `audio/wav` compare equal.
+fn normalize(content_type: &str) -> String {
+    let essence = content_type.split(';').next().unwrap_or_default().trim().to_lowercase();
+    match essence.split_once('/') {
+        Some((kind, subtype)) => format!("{}/{}", kind, subtype.trim_start_matches("x-")),
+        None => essence,
+    }
+}
+
+/// Checks if data starts (after whitespace, an XML prolog and comments) with an `<svg` element.
+fn looks_like_svg(data: &[u8]) -> bool {
+    let head = String::from_utf8_lossy(&data[..data.len().min(SNIFF_LENGTH)]).to_lowercase();
+    let mut rest = head.trim_start_matches('\u{feff}').trim_start();
+
+    loop {
+        if let Some(r) = rest.strip_prefix("<?") {
+            rest = r.split_once("?>").map_or("", |(_, r)| r).trim_start();
+        } else if let Some(r) = rest.strip_prefix("<!--") {
+            rest = r.split_once("-->").map_or("", |(_, r)| r).trim_start();
+        } else if let Some(r) = rest.strip_prefix("<!doctype") {
+            rest = r.split_once('>').map_or("", |(_, r)| r).trim_start();
+        } else {
+            return rest.starts_with("<svg");
+        }
+    }
+}
+
+/// Checks if data looks like text: no NUL bytes and valid UTF-8 (allowing a cut-off last character).
+fn looks_like_text(data: &[u8]) -> bool {
+    let head = &data[..data.len().min(SNIFF_LENGTH)];
+    if head.contains(&0) {
+        return false;
+    }
+    match std::str::from_utf8(head) {
+        Ok(_) => true,
+        Err(e) => e.error_len().is_none() && head.len() == SNIFF_LENGTH,
+    }
+}
+
+/// Checks if a content type is text that browsers display without executing it.
+fn is_textual(content_type: &str) -> bool {
+    let ct = normalize(content_type);
+    ct.starts_with("text/")
+        || ct.ends_with("+json")
+        || matches!(ct.as_str(), "application/json" | "application/javascript" | "application/sh" | "application/toml" | "application/yaml")
+}
+
+/// Checks if a content type can execute scripts when rendered by a browser.
+///
+/// # Arguments
+///
+/// * `content_type` - The content type to check
+///
+/// # Returns
+///
+/// `true` for HTML, SVG and XML content types
+pub fn is_active_content(content_type: &str) -> bool {
+    let ct = normalize(content_type);
+    ct == "text/html" || ct == "image/svg+xml" || ct.ends_with("/xml") || ct.ends_with("+xml")
+}
+
+/// Detects the content type of data from its first bytes.
+///
+/// # Arguments
+///
+/// * `data` - The data (or the first bytes of it) to inspect
+///
+/// # Returns
+///
+/// The detected content type, or `None` if the data has no recognizable signature
+pub fn sniff_content_type(data: &[u8]) -> Option<String> {
+    let head = &data[..data.len().min(SNIFF_LENGTH)];
+    if looks_like_svg(head) {
+        return Some("image/svg+xml".to_string());
+    }
+    infer::get(head).map(|t| t.mime_type().to_string())
+}
+
+/// Checks if a sniffed content type is consistent with a declared content type.
+///
+/// # Arguments
+///
+/// * `declared` - The content type declared by the file name
+/// * `sniffed` - The content type detected from the data
+///
+/// # Returns
+///
+/// `true` if both types describe the same kind of content
+pub fn types_match(declared: &str, sniffed: &str) -> bool {
+    let (declared, sniffed) = (normalize(declared), normalize(sniffed));
+
+    if declared == sniffed {
+        return true;
+    }
+    // XML flavours are interchangeable, as long as both sides are XML
+    if declared.ends_with("xml") && sniffed.ends_with("/xml") && declared != "image/svg+xml" {
+        return true;
+    }
+    // Office documents, e-books and Java archives are ZIP files
+    if sniffed == "application/zip" && ZIP_BASED_TYPES.iter().any(|t| normalize(t) == declared) {
+        return true;
+    }
+    // Plain scripts are recognized by their shebang only
+    if sniffed == "text/shellscript" && is_textual(&declared) {
+        return true;
+    }
+    false
+}
+
+/// Decides how an object should be served to a browser.
+///
+/// The declared type is guessed from the file name and checked against the
+/// type sniffed from the data. On a mismatch the object is served with the
+/// sniffed type as an attachment. Objects without a known extension get the
+/// sniffed type. Active content is only served inline if `allow_active` is set.
+///
+/// # Arguments
+///
+/// * `filename` - The file name of the object
+/// * `data` - The data (or the first bytes of it) of the object
+/// * `allow_active` - Whether HTML, SVG and XML may be rendered inline
+///
+/// # Returns
+///
+/// The `ServeDecision` for the object
+pub fn decide(filename: &str, data: &[u8], allow_active: bool) -> ServeDecision {
+    let declared = mime_guess::from_path(filename).first_or_octet_stream().essence_str().to_string();
+    let sniffed = sniff_content_type(data);
+
+    let (content_type, mismatch) = match sniffed {
+        _ if declared == "application/octet-stream" => (
+            sniffed.unwrap_or_else(|| if looks_like_text(data) && !data.is_empty() { "text/plain".to_string() } else { declared }),
+            false,
+        ),
+        Some(sniffed) if !types_match(&declared, &sniffed) => (sniffed, true),
+        // Text without a signature must at least look like text
+        None if is_textual(&declared) && !looks_like_text(data) => ("application/octet-stream".to_string(), true),
+        _ => (declared, false),
+    };
+
+    let inline = !mismatch && (allow_active || !is_active_content(&content_type));
+
+    ServeDecision {
+        content_type,
+        inline,
+        mismatch,
+    }
+}
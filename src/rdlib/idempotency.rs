@@ -0,0 +1,190 @@
+//! # Idempotency Keys
+//!
+//! This module provides the store behind the `Idempotency-Key` header. The
+//! first request with a key runs normally and its response is kept for a
+//! limited time; repeating the request with the same key returns the kept
+//! response instead of running it again. The store is held in memory, so keys
+//! are only known to the server instance that handled the first request.
+//!
+//! The store holds at most a configured number of keys. Keys are kept in the
+//! order they expire, so expired keys are removed without scanning the store,
+//! and the oldest keys make room for new ones once it is full.
+
+use std::collections::{HashMap, VecDeque};
+use std::env;
+use std::sync::Mutex;
+
+use chrono::{DateTime, Duration, Utc};
+
+/// How long responses are kept unless `RUSTDOK_IDEMPOTENCY_TTL` is set, in seconds
+pub const DEFAULT_TTL_SECONDS: i64 = 24 * 60 * 60;
+
+/// How many keys are kept unless `RUSTDOK_IDEMPOTENCY_MAX_KEYS` is set
+pub const DEFAULT_MAX_KEYS: usize = 10_000;
+
+/// The maximum length of an idempotency key
+pub const MAX_KEY_LENGTH: usize = 255;
+
+/// The state of a request with an idempotency key.
+enum Entry<T> {
+    /// The request is still running
+    InProgress { fingerprint: String, expires_at: DateTime<Utc> },
+    /// The request has finished with a kept response
+    Completed { fingerprint: String, response: T, expires_at: DateTime<Utc> },
+}
+
+impl<T> Entry<T> {
+    fn fingerprint(&self) -> &str {
+        match self {
+            Entry::InProgress { fingerprint, .. } | Entry::Completed { fingerprint, .. } => fingerprint,
+        }
+    }
+
+    fn expires_at(&self) -> DateTime<Utc> {
+        match self {
+            Entry::InProgress { expires_at, .. } | Entry::Completed { expires_at, .. } => *expires_at,
+        }
+    }
+}
+
+/// What to do with a request carrying an idempotency key.
+#[derive(Debug, PartialEq)]
+pub enum Lookup<T> {
+    /// The key is new; the request must run and its response be completed or abandoned
+    New,
+    /// A request with the key is still running
+    InProgress,
+    /// The key was used for a different request
+    Mismatch,
+    /// The request already ran; this is its response
+    Replay(T),
+}
+
+/// The keys of a store, with the order in which they expire.
+struct Entries<T> {
+    by_key: HashMap<String, Entry<T>>,
+    /// The expiry of every key when it was added, oldest first; keys that
+    /// were removed or added again since are skipped when they come up
+    expiry: VecDeque<(DateTime<Utc>, String)>,
+}
+
+impl<T> Entries<T> {
+    /// Removes keys from the front of the queue while `remove` holds for their expiry.
+    fn remove_oldest(&mut self, remove: impl Fn(DateTime<Utc>, usize) -> bool) {
+        while let Some((expires_at, _)) = self.expiry.front() {
+            if !remove(*expires_at, self.by_key.len()) {
+                break;
+            }
+            if let Some((expires_at, key)) = self.expiry.pop_front()
+                && self.by_key.get(&key).is_some_and(|entry| entry.expires_at() == expires_at) {
+                self.by_key.remove(&key);
+            }
+        }
+    }
+
+    /// Removes the keys that have expired.
+    fn remove_expired(&mut self, now: DateTime<Utc>) {
+        self.remove_oldest(|expires_at, _| expires_at <= now);
+    }
+
+    /// Removes the oldest keys until there is room for another one.
+    fn make_room(&mut self, max_keys: usize) {
+        self.remove_oldest(|_, len| len >= max_keys);
+
+        // Abandoned keys leave their place in the queue behind
+        if self.expiry.len() > 2 * max_keys {
+            let by_key = &self.by_key;
+            self.expiry.retain(|(expires_at, key)| by_key.get(key).is_some_and(|entry| entry.expires_at() == *expires_at));
+        }
+    }
+}
+
+/// Keeps the responses of requests with idempotency keys for a limited time.
+pub struct IdempotencyStore<T> {
+    entries: Mutex<Entries<T>>,
+    ttl: Duration,
+    max_keys: usize,
+}
+
+impl<T: Clone> IdempotencyStore<T> {
+    /// Creates an empty store.
+    ///
+    /// # Arguments
+    ///
+    /// * `ttl` - How long responses are kept
+    /// * `max_keys` - How many keys are kept at most
+    pub fn new(ttl: Duration, max_keys: usize) -> Self {
+        Self {
+            entries: Mutex::new(Entries { by_key: HashMap::new(), expiry: VecDeque::new() }),
+            ttl,
+            max_keys: max_keys.max(1),
+        }
+    }
+
+    /// Creates an empty store with the TTL from `RUSTDOK_IDEMPOTENCY_TTL`
+    /// and the size from `RUSTDOK_IDEMPOTENCY_MAX_KEYS`.
+    pub fn from_env() -> Self {
+        let seconds = env::var("RUSTDOK_IDEMPOTENCY_TTL")
+            .ok()
+            .and_then(|v| v.parse::<i64>().ok())
+            .filter(|seconds| *seconds > 0)
+            .unwrap_or(DEFAULT_TTL_SECONDS);
+        let max_keys = env::var("RUSTDOK_IDEMPOTENCY_MAX_KEYS")
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .filter(|max_keys| *max_keys > 0)
+            .unwrap_or(DEFAULT_MAX_KEYS);
+        Self::new(Duration::seconds(seconds), max_keys)
+    }
+
+    /// Checks if a key is acceptable: 1 to 255 visible ASCII characters.
+    pub fn is_valid_key(key: &str) -> bool {
+        !key.is_empty() && key.len() <= MAX_KEY_LENGTH && key.chars().all(|c| c.is_ascii_graphic())
+    }
+
+    /// Looks up a key, marking it as in progress if it is new.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The idempotency key
+    /// * `fingerprint` - What identifies the request, e.g. its method and path
+    /// * `now` - The current time
+    ///
+    /// # Returns
+    ///
+    /// What to do with the request
+    pub fn begin(&self, key: &str, fingerprint: &str, now: DateTime<Utc>) -> Lookup<T> {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.remove_expired(now);
+
+        match entries.by_key.get(key) {
+            Some(entry) if entry.fingerprint() != fingerprint => Lookup::Mismatch,
+            Some(Entry::InProgress { .. }) => Lookup::InProgress,
+            Some(Entry::Completed { response, .. }) => Lookup::Replay(response.clone()),
+            None => {
+                entries.make_room(self.max_keys);
+                let expires_at = now + self.ttl;
+                entries.by_key.insert(key.to_string(), Entry::InProgress { fingerprint: fingerprint.to_string(), expires_at });
+                entries.expiry.push_back((expires_at, key.to_string()));
+                Lookup::New
+            }
+        }
+    }
+
+    /// Keeps the response of a request started with `begin`, until the key expires.
+    pub fn complete(&self, key: &str, response: T) {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(Entry::InProgress { fingerprint, expires_at }) = entries.by_key.remove(key) {
+            entries.by_key.insert(key.to_string(), Entry::Completed { fingerprint, response, expires_at });
+        }
+    }
+
+    /// Forgets a request started with `begin` without keeping its response,
+    /// so it can be retried with the same key.
+    pub fn abandon(&self, key: &str) {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        if matches!(entries.by_key.get(key), Some(Entry::InProgress { .. })) {
+            entries.by_key.remove(key);
+        }
+    }
+}
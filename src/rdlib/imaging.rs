@@ -0,0 +1,407 @@
+//! # Image Processing
+//!
+//! This module provides decoding, transforming and encoding of images for
+//! thumbnails and previews. Decoding is guarded by limits on the source
+//! size, the image dimensions and the memory the decoder may allocate,
+//! so that small "decompression bomb" files cannot exhaust the server.
+
+use std::fmt;
+use std::io::Cursor;
+
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::PngEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageFormat, ImageReader, Limits};
+
+/// The maximum size of a source image in bytes
+pub const MAX_SOURCE_SIZE: u64 = 50 * 1024 * 1024;
+
+/// The maximum width and height of a source image in pixels
+pub const MAX_SOURCE_DIMENSION: u32 = 16_384;
+
+/// The maximum memory the decoder may allocate in bytes
+pub const MAX_DECODE_ALLOC: u64 = 512 * 1024 * 1024;
+
+/// The default width and height of a thumbnail in pixels
+pub const DEFAULT_THUMBNAIL_SIZE: u32 = 256;
+
+/// The maximum width and height of a thumbnail in pixels
+pub const MAX_THUMBNAIL_SIZE: u32 = 1024;
+
+/// The maximum width and height of a transformed image in pixels
+pub const MAX_OUTPUT_DIMENSION: u32 = 4096;
+
+/// The JPEG quality used when no quality is requested
+const DEFAULT_JPEG_QUALITY: u8 = 85;
+
+/// Errors that can occur while processing an image.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ImagingError {
+    /// The source is larger than `MAX_SOURCE_SIZE`
+    SourceTooLarge(u64),
+    /// The source is not an image in a supported format, or exceeds the decoding limits
+    Unsupported(String),
+    /// The requested output is invalid
+    InvalidRequest(String),
+    /// The image could not be encoded
+    Encoding(String),
+}
+
+impl fmt::Display for ImagingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImagingError::SourceTooLarge(size) => write!(
+                f,
+                "Image of {} bytes exceeds the maximum of {} bytes that can be processed",
+                size, MAX_SOURCE_SIZE
+            ),
+            ImagingError::Unsupported(e) => write!(f, "Unsupported image: {}", e),
+            ImagingError::InvalidRequest(e) => write!(f, "Invalid image request: {}", e),
+            ImagingError::Encoding(e) => write!(f, "Failed to encode image: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ImagingError {}
+
+/// The formats images can be encoded to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    Jpeg,
+    Png,
+    Webp,
+}
+
+impl OutputFormat {
+    /// Parses a format name as used in query parameters.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The format name: `jpeg` (or `jpg`), `png` or `webp`
+    ///
+    /// # Returns
+    ///
+    /// The format, or `None` if the name is unknown
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "jpeg" | "jpg" => Some(OutputFormat::Jpeg),
+            "png" => Some(OutputFormat::Png),
+            "webp" => Some(OutputFormat::Webp),
+            _ => None,
+        }
+    }
+
+    /// Returns the name of the format.
+    pub fn name(&self) -> &'static str {
+        match self {
+            OutputFormat::Jpeg => "jpeg",
+            OutputFormat::Png => "png",
+            OutputFormat::Webp => "webp",
+        }
+    }
+
+    /// Returns the content type of the format.
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            OutputFormat::Jpeg => "image/jpeg",
+            OutputFormat::Png => "image/png",
+            OutputFormat::Webp => "image/webp",
+        }
+    }
+}
+
+/// An encoded image together with its content type.
+#[derive(Debug, Clone)]
+pub struct Rendition {
+    /// The encoded image
+    pub data: Vec<u8>,
+    /// The content type of the encoded image
+    pub content_type: String,
+}
+
+/// Decodes an image, enforcing the source size and decoding limits.
+///
+/// # Arguments
+///
+/// * `data` - The encoded source image
+///
+/// # Returns
+///
+/// * `Ok(DynamicImage)` - The decoded image
+/// * `Err(ImagingError)` - If the source is too large, unsupported or exceeds the limits
+pub fn decode(data: &[u8]) -> Result<DynamicImage, ImagingError> {
+    if data.len() as u64 > MAX_SOURCE_SIZE {
+        return Err(ImagingError::SourceTooLarge(data.len() as u64));
+    }
+
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_SOURCE_DIMENSION);
+    limits.max_image_height = Some(MAX_SOURCE_DIMENSION);
+    limits.max_alloc = Some(MAX_DECODE_ALLOC);
+
+    let mut reader = ImageReader::new(Cursor::new(data))
+        .with_guessed_format()
+        .map_err(|e| ImagingError::Unsupported(e.to_string()))?;
+    if reader.format().is_none() {
+        return Err(ImagingError::Unsupported("unknown image format".to_string()));
+    }
+    reader.limits(limits);
+
+    reader.decode().map_err(|e| ImagingError::Unsupported(e.to_string()))
+}
+
+/// Encodes an image.
+///
+/// JPEG has no alpha channel, so transparent images are flattened when
+/// encoded as JPEG. WebP is always encoded lossless.
+///
+/// # Arguments
+///
+/// * `img` - The image to encode
+/// * `format` - The output format
+/// * `quality` - Optional quality from 1 to 100, used for JPEG
+///
+/// # Returns
+///
+/// * `Ok(Rendition)` - The encoded image
+/// * `Err(ImagingError)` - If the image could not be encoded
+pub fn encode(img: &DynamicImage, format: OutputFormat, quality: Option<u8>) -> Result<Rendition, ImagingError> {
+    let mut data = Vec::new();
+
+    let result = match format {
+        OutputFormat::Jpeg => {
+            let encoder = JpegEncoder::new_with_quality(&mut data, quality.unwrap_or(DEFAULT_JPEG_QUALITY));
+            DynamicImage::ImageRgb8(img.to_rgb8()).write_with_encoder(encoder)
+        },
+        OutputFormat::Png => img.write_with_encoder(PngEncoder::new(&mut data)),
+        OutputFormat::Webp => {
+            let encoder = WebPEncoder::new_lossless(&mut data);
+            if img.color().has_alpha() {
+                DynamicImage::ImageRgba8(img.to_rgba8()).write_with_encoder(encoder)
+            } else {
+                DynamicImage::ImageRgb8(img.to_rgb8()).write_with_encoder(encoder)
+            }
+        },
+    };
+    result.map_err(|e| ImagingError::Encoding(e.to_string()))?;
+
+    Ok(Rendition {
+        data,
+        content_type: format.content_type().to_string(),
+    })
+}
+
+/// Creates a thumbnail of an image.
+///
+/// The image is scaled down, keeping its aspect ratio, to fit within the
+/// given box. Smaller images are not scaled up. Opaque images are encoded
+/// as JPEG, images with transparency as PNG.
+///
+/// # Arguments
+///
+/// * `data` - The encoded source image
+/// * `width` - The maximum width of the thumbnail
+/// * `height` - The maximum height of the thumbnail
+///
+/// # Returns
+///
+/// * `Ok(Rendition)` - The encoded thumbnail
+/// * `Err(ImagingError)` - If the source could not be processed or the size is invalid
+pub fn thumbnail(data: &[u8], width: u32, height: u32) -> Result<Rendition, ImagingError> {
+    if width == 0 || height == 0 || width > MAX_THUMBNAIL_SIZE || height > MAX_THUMBNAIL_SIZE {
+        return Err(ImagingError::InvalidRequest(format!(
+            "Thumbnail size must be between 1 and {} pixels",
+            MAX_THUMBNAIL_SIZE
+        )));
+    }
+
+    let img = decode(data)?;
+    let img = if img.width() > width || img.height() > height {
+        img.thumbnail(width, height)
+    } else {
+        img
+    };
+
+    let format = if img.color().has_alpha() { OutputFormat::Png } else { OutputFormat::Jpeg };
+    encode(&img, format, None)
+}
+
+/// A transformation applied to an image before it is served.
+///
+/// The steps are applied in a fixed order: crop, rotate, resize, encode.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ImageTransform {
+    /// The target width in pixels
+    pub width: Option<u32>,
+    /// The target height in pixels
+    pub height: Option<u32>,
+    /// The region to crop to, as `(x, y, width, height)`
+    pub crop: Option<(u32, u32, u32, u32)>,
+    /// The clockwise rotation in degrees: 0, 90, 180 or 270
+    pub rotate: u16,
+    /// The output format; defaults to the source format if it is JPEG, PNG or WebP, otherwise PNG
+    pub format: Option<OutputFormat>,
+    /// The output quality from 1 to 100; only accepted for JPEG output
+    pub quality: Option<u8>,
+}
+
+impl ImageTransform {
+    /// Parses a crop region given as `x,y,width,height`.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The crop region, e.g. `10,20,300,200`
+    ///
+    /// # Returns
+    ///
+    /// * `Ok((x, y, width, height))` - The parsed region
+    /// * `Err(ImagingError)` - If the region is malformed
+    pub fn parse_crop(value: &str) -> Result<(u32, u32, u32, u32), ImagingError> {
+        let parts = value
+            .split(',')
+            .map(|p| p.trim().parse::<u32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| ImagingError::InvalidRequest(format!("Invalid crop '{}'", value)))?;
+
+        match parts[..] {
+            [x, y, width, height] => Ok((x, y, width, height)),
+            _ => Err(ImagingError::InvalidRequest(format!("Crop '{}' must be x,y,width,height", value))),
+        }
+    }
+
+    /// Checks if the transformation leaves the image unchanged.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Checks the parameters that can be validated without the source image.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the parameters are valid
+    /// * `Err(ImagingError)` - A description of the invalid parameter
+    pub fn validate(&self) -> Result<(), ImagingError> {
+        for dimension in [self.width, self.height].into_iter().flatten() {
+            if dimension == 0 || dimension > MAX_OUTPUT_DIMENSION {
+                return Err(ImagingError::InvalidRequest(format!(
+                    "Width and height must be between 1 and {} pixels",
+                    MAX_OUTPUT_DIMENSION
+                )));
+            }
+        }
+        if let Some((_, _, width, height)) = self.crop && (width == 0 || height == 0) {
+            return Err(ImagingError::InvalidRequest("Crop width and height must be greater than zero".to_string()));
+        }
+        if !matches!(self.rotate, 0 | 90 | 180 | 270) {
+            return Err(ImagingError::InvalidRequest("Rotation must be 0, 90, 180 or 270 degrees".to_string()));
+        }
+        if self.quality.is_some_and(|q| !(1..=100).contains(&q)) {
+            return Err(ImagingError::InvalidRequest("Quality must be between 1 and 100".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Builds a canonical name for the transformation, used as its cache key.
+    pub fn cache_variant(&self) -> String {
+        let dimension = |d: Option<u32>| d.map_or_else(|| "auto".to_string(), |d| d.to_string());
+        let crop = self.crop.map_or_else(
+            || "none".to_string(),
+            |(x, y, w, h)| format!("{}_{}_{}_{}", x, y, w, h)
+        );
+
+        format!(
+            "transforms/w{}-h{}-c{}-r{}-f{}-q{}",
+            dimension(self.width),
+            dimension(self.height),
+            crop,
+            self.rotate,
+            self.format.map_or("auto", |f| f.name()),
+            self.quality.map_or_else(|| "auto".to_string(), |q| q.to_string())
+        )
+    }
+
+    /// Applies the transformation to an image.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The encoded source image
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Rendition)` - The transformed and encoded image
+    /// * `Err(ImagingError)` - If the source could not be processed or the parameters do not fit it
+    pub fn apply(&self, data: &[u8]) -> Result<Rendition, ImagingError> {
+        self.validate()?;
+
+        let source_format = image::guess_format(data).ok();
+        let mut img = decode(data)?;
+
+        if let Some((x, y, width, height)) = self.crop {
+            let fits = x.checked_add(width).is_some_and(|right| right <= img.width())
+                && y.checked_add(height).is_some_and(|bottom| bottom <= img.height());
+            if !fits {
+                return Err(ImagingError::InvalidRequest(format!(
+                    "Crop region lies outside the {}x{} image",
+                    img.width(),
+                    img.height()
+                )));
+            }
+            img = img.crop_imm(x, y, width, height);
+        }
+
+        img = match self.rotate {
+            90 => img.rotate90(),
+            180 => img.rotate180(),
+            270 => img.rotate270(),
+            _ => img,
+        };
+
+        let format = self.format.unwrap_or(match source_format {
+            Some(ImageFormat::Jpeg) => OutputFormat::Jpeg,
+            Some(ImageFormat::WebP) => OutputFormat::Webp,
+            _ => OutputFormat::Png,
+        });
+        if self.quality.is_some() && format != OutputFormat::Jpeg {
+            return Err(ImagingError::InvalidRequest(format!(
+                "Quality is only supported for JPEG output, not {}",
+                format.name()
+            )));
+        }
+
+        // Only resized outputs are limited, and they are checked before resizing,
+        // so oversized outputs are never allocated
+        if let Some((width, height)) = self.target_size(img.width(), img.height()) {
+            if width > MAX_OUTPUT_DIMENSION || height > MAX_OUTPUT_DIMENSION {
+                return Err(ImagingError::InvalidRequest(format!(
+                    "Output of {}x{} pixels exceeds the maximum of {} pixels per side",
+                    width,
+                    height,
+                    MAX_OUTPUT_DIMENSION
+                )));
+            }
+            if (width, height) != (img.width(), img.height()) {
+                img = img.resize_exact(width, height, FilterType::Triangle);
+            }
+        }
+
+        encode(&img, format, self.quality)
+    }
+
+    /// Computes the size to resize an image to, keeping its aspect ratio.
+    ///
+    /// With a width and a height, the image is scaled to fit within both;
+    /// with only one of them, the other follows from the aspect ratio.
+    fn target_size(&self, width: u32, height: u32) -> Option<(u32, u32)> {
+        let (w, h) = (width as f64, height as f64);
+        let scale = match (self.width, self.height) {
+            (None, None) => return None,
+            (Some(tw), None) => tw as f64 / w,
+            (None, Some(th)) => th as f64 / h,
+            (Some(tw), Some(th)) => (tw as f64 / w).min(th as f64 / h),
+        };
+
+        let scaled = |d: f64| ((d * scale).round() as u32).max(1);
+        Some((scaled(w), scaled(h)))
+    }
+}
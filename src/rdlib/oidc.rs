@@ -0,0 +1,13 @@
+//! # OpenID Connect Module
+//!
+//! This module lets users log in through an OpenID Connect identity
+//! provider. RustDok acts as a relying party using the authorization code
+//! flow with PKCE: it sends the browser to the provider, exchanges the code
+//! it gets back for an ID token, verifies the token against the keys the
+//! provider publishes and maps its claims to a RustDok user and groups.
+
+pub mod error;
+pub mod config;
+pub mod pkce;
+pub mod http;
+pub mod service;
@@ -0,0 +1,141 @@
+//! # OpenID Connect Configuration
+//!
+//! This module defines how RustDok is registered with its identity
+//! provider and how the claims of ID tokens map to users.
+
+use std::env;
+
+use log::info;
+
+use crate::rdlib::users::user::Role;
+
+/// The scopes requested unless `RUSTDOK_OIDC_SCOPES` is set
+pub const DEFAULT_SCOPES: &str = "openid profile email";
+
+/// The claim usernames are taken from unless `RUSTDOK_OIDC_USERNAME_CLAIM` is set
+pub const DEFAULT_USERNAME_CLAIM: &str = "preferred_username";
+
+/// The claim groups are taken from unless `RUSTDOK_OIDC_GROUPS_CLAIM` is set
+pub const DEFAULT_GROUPS_CLAIM: &str = "groups";
+
+/// The URL of the web UI unless `RUSTDOK_WEBUI_URL` is set
+pub const DEFAULT_WEBUI_URL: &str = "http://localhost:3000";
+
+/// The registration of RustDok at an identity provider.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OidcConfig {
+    /// The issuer of the provider, whose discovery document names its endpoints
+    pub issuer: String,
+    /// The client ID of RustDok at the provider
+    pub client_id: String,
+    /// The client secret, or `None` for a public client
+    pub client_secret: Option<String>,
+    /// Where the provider sends the browser back to: the `/api/v1/auth/oidc/callback` endpoint
+    pub redirect_uri: String,
+    /// The scopes requested, space-separated; must contain `openid`
+    pub scopes: String,
+    /// The claim holding the username of provisioned users
+    pub username_claim: String,
+    /// The claim holding the groups of the user
+    pub groups_claim: String,
+    /// Groups of the provider whose members are administrators; if empty,
+    /// roles are left to administrators
+    pub admin_groups: Vec<String>,
+    /// Whether users are created at their first login
+    pub auto_provision: bool,
+    /// Where the browser is sent once the login has started a session: the web UI
+    pub post_login_url: String,
+}
+
+impl OidcConfig {
+    /// Creates a configuration with the default scopes and claims.
+    pub fn new(issuer: &str, client_id: &str, redirect_uri: &str) -> Self {
+        Self {
+            issuer: issuer.to_string(),
+            client_id: client_id.to_string(),
+            client_secret: None,
+            redirect_uri: redirect_uri.to_string(),
+            scopes: DEFAULT_SCOPES.to_string(),
+            username_claim: DEFAULT_USERNAME_CLAIM.to_string(),
+            groups_claim: DEFAULT_GROUPS_CLAIM.to_string(),
+            admin_groups: Vec::new(),
+            auto_provision: true,
+            post_login_url: DEFAULT_WEBUI_URL.to_string(),
+        }
+    }
+
+    /// Creates the configuration from the environment.
+    ///
+    /// # Environment Variables
+    ///
+    /// * `RUSTDOK_OIDC_ISSUER` - The issuer of the identity provider; OIDC login is disabled if unset
+    /// * `RUSTDOK_OIDC_CLIENT_ID` - The client ID of RustDok
+    /// * `RUSTDOK_OIDC_CLIENT_SECRET` - The client secret (optional)
+    /// * `RUSTDOK_OIDC_REDIRECT_URI` - The public URL of `/api/v1/auth/oidc/callback`
+    /// * `RUSTDOK_OIDC_SCOPES` - The scopes requested (optional)
+    /// * `RUSTDOK_OIDC_USERNAME_CLAIM` - The claim holding usernames (optional)
+    /// * `RUSTDOK_OIDC_GROUPS_CLAIM` - The claim holding groups (optional)
+    /// * `RUSTDOK_OIDC_ADMIN_GROUPS` - Groups of administrators, comma-separated (optional)
+    /// * `RUSTDOK_OIDC_AUTO_PROVISION` - Whether to create users at their first login, `true` unless `false`
+    /// * `RUSTDOK_WEBUI_URL` - The web UI the browser is sent to after the login (optional)
+    ///
+    /// # Returns
+    ///
+    /// The configuration, `None` if no issuer is set, or an error if a
+    /// required variable is missing
+    pub fn from_env() -> Result<Option<Self>, String> {
+        let Some(issuer) = non_empty_var("RUSTDOK_OIDC_ISSUER") else {
+            info!("RUSTDOK_OIDC_ISSUER is not set; OpenID Connect login is disabled");
+            return Ok(None);
+        };
+        let client_id = non_empty_var("RUSTDOK_OIDC_CLIENT_ID")
+            .ok_or("RUSTDOK_OIDC_CLIENT_ID must be set for OpenID Connect login")?;
+        let redirect_uri = non_empty_var("RUSTDOK_OIDC_REDIRECT_URI")
+            .ok_or("RUSTDOK_OIDC_REDIRECT_URI must be set for OpenID Connect login")?;
+
+        let mut config = Self::new(&issuer, &client_id, &redirect_uri);
+        config.client_secret = non_empty_var("RUSTDOK_OIDC_CLIENT_SECRET");
+        if let Some(scopes) = non_empty_var("RUSTDOK_OIDC_SCOPES") {
+            if !scopes.split_whitespace().any(|scope| scope == "openid") {
+                return Err("RUSTDOK_OIDC_SCOPES must contain 'openid'".to_string());
+            }
+            config.scopes = scopes;
+        }
+        if let Some(claim) = non_empty_var("RUSTDOK_OIDC_USERNAME_CLAIM") {
+            config.username_claim = claim;
+        }
+        if let Some(claim) = non_empty_var("RUSTDOK_OIDC_GROUPS_CLAIM") {
+            config.groups_claim = claim;
+        }
+        config.admin_groups = non_empty_var("RUSTDOK_OIDC_ADMIN_GROUPS")
+            .map(|v| v.split(',').map(str::trim).filter(|s| !s.is_empty()).map(str::to_string).collect())
+            .unwrap_or_default();
+        config.auto_provision = non_empty_var("RUSTDOK_OIDC_AUTO_PROVISION").is_none_or(|v| v != "false");
+        if let Some(url) = non_empty_var("RUSTDOK_WEBUI_URL") {
+            config.post_login_url = url;
+        }
+        Ok(Some(config))
+    }
+
+    /// The URL of the discovery document of the provider.
+    pub fn discovery_url(&self) -> String {
+        format!("{}/.well-known/openid-configuration", self.issuer.trim_end_matches('/'))
+    }
+
+    /// The role of a user who is a member of these groups, or `None` if
+    /// roles are not managed by the provider.
+    pub fn role_for(&self, groups: &[String]) -> Option<Role> {
+        if self.admin_groups.is_empty() {
+            return None;
+        }
+        if groups.iter().any(|group| self.admin_groups.contains(group)) {
+            Some(Role::Admin)
+        } else {
+            Some(Role::User)
+        }
+    }
+}
+
+fn non_empty_var(name: &str) -> Option<String> {
+    env::var(name).ok().filter(|v| !v.is_empty())
+}
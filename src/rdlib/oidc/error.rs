@@ -0,0 +1,41 @@
+//! # OpenID Connect Errors
+//!
+//! This module defines the errors of logging in through an identity
+//! provider.
+
+use std::fmt;
+
+/// Errors of the OpenID Connect login.
+#[derive(Clone, Debug, PartialEq)]
+pub enum OidcError {
+    /// The `state` of a callback is not that of the login the browser began, or the login has expired
+    InvalidState,
+    /// The identity provider refused the login
+    Rejected(String),
+    /// The ID token is not valid
+    InvalidIdToken(String),
+    /// The ID token lacks a claim needed to identify the user
+    MissingClaim(String),
+    /// The discovery document of the provider cannot be used
+    Discovery(String),
+    /// The provider did not exchange the authorization code
+    TokenExchange(String),
+    /// The provider could not be reached
+    Http(String),
+}
+
+impl fmt::Display for OidcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OidcError::InvalidState => f.write_str("The login is unknown or has expired; please start again"),
+            OidcError::Rejected(msg) => write!(f, "The identity provider refused the login: {}", msg),
+            OidcError::InvalidIdToken(msg) => write!(f, "The ID token is invalid: {}", msg),
+            OidcError::MissingClaim(claim) => write!(f, "The ID token has no `{}` claim", claim),
+            OidcError::Discovery(msg) => write!(f, "Cannot discover the identity provider: {}", msg),
+            OidcError::TokenExchange(msg) => write!(f, "The identity provider did not issue tokens: {}", msg),
+            OidcError::Http(msg) => write!(f, "Cannot reach the identity provider: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for OidcError {}
@@ -0,0 +1,131 @@
+//! # Identity Provider Client
+//!
+//! This module provides the small HTTP client RustDok talks to its
+//! identity provider with: fetching the discovery document and keys, and
+//! exchanging authorization codes. Providers are reached over HTTPS, or
+//! plain HTTP for local test providers.
+
+use std::time::Duration;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use hyper::body::HttpBody;
+use hyper::client::HttpConnector;
+use hyper::header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE};
+use hyper::{Body, Client, Method, Request, StatusCode};
+use hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
+use serde::de::DeserializeOwned;
+
+use crate::rdlib::oidc::error::OidcError;
+
+/// How long a request to the provider may take
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The largest response read from the provider, in bytes
+const MAX_RESPONSE_SIZE: usize = 1024 * 1024;
+
+/// A response of the provider.
+pub struct Response {
+    pub status: StatusCode,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    /// Parses the body as JSON.
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T, OidcError> {
+        serde_json::from_slice(&self.body).map_err(|e| OidcError::Http(format!("unexpected response: {}", e)))
+    }
+}
+
+/// An HTTP client for the identity provider.
+pub struct HttpClient {
+    client: Client<HttpsConnector<HttpConnector>, Body>,
+}
+
+impl Default for HttpClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HttpClient {
+    /// Creates a client trusting the root certificates of the system.
+    pub fn new() -> Self {
+        let connector = HttpsConnectorBuilder::new()
+            .with_native_roots()
+            .https_or_http()
+            .enable_http1()
+            .build();
+        Self { client: Client::builder().build(connector) }
+    }
+
+    /// Gets a JSON document.
+    ///
+    /// # Returns
+    ///
+    /// The document, or an error if the request fails or does not answer `200 OK`
+    pub async fn get_json<T: DeserializeOwned>(&self, url: &str) -> Result<T, OidcError> {
+        let request = Request::builder()
+            .method(Method::GET)
+            .uri(url)
+            .header(ACCEPT, "application/json")
+            .body(Body::empty())
+            .map_err(|e| OidcError::Http(format!("invalid URL '{}': {}", url, e)))?;
+        let response = self.send(request).await?;
+        if response.status != StatusCode::OK {
+            return Err(OidcError::Http(format!("{} answered {}", url, response.status)));
+        }
+        response.json()
+    }
+
+    /// Posts a form, authenticating with HTTP Basic if credentials are given.
+    ///
+    /// # Returns
+    ///
+    /// The response, whatever its status
+    pub async fn post_form(
+        &self,
+        url: &str,
+        form: &[(&str, &str)],
+        basic_auth: Option<(&str, &str)>
+    ) -> Result<Response, OidcError> {
+        let body = serde_urlencoded::to_string(form).map_err(|e| OidcError::Http(e.to_string()))?;
+        let mut builder = Request::builder()
+            .method(Method::POST)
+            .uri(url)
+            .header(ACCEPT, "application/json")
+            .header(CONTENT_TYPE, "application/x-www-form-urlencoded");
+        if let Some((user, password)) = basic_auth {
+            // RFC 6749 form-encodes the client ID and secret before joining them
+            let credentials = format!("{}:{}", form_encode(user), form_encode(password));
+            builder = builder.header(AUTHORIZATION, format!("Basic {}", STANDARD.encode(credentials)));
+        }
+        let request = builder.body(Body::from(body))
+            .map_err(|e| OidcError::Http(format!("invalid URL '{}': {}", url, e)))?;
+        self.send(request).await
+    }
+
+    async fn send(&self, request: Request<Body>) -> Result<Response, OidcError> {
+        let uri = request.uri().to_string();
+        let exchange = async {
+            let response = self.client.request(request).await.map_err(|e| OidcError::Http(format!("{}: {}", uri, e)))?;
+            let status = response.status();
+            let mut body = response.into_body();
+            let mut bytes = Vec::new();
+            while let Some(chunk) = body.data().await {
+                let chunk = chunk.map_err(|e| OidcError::Http(format!("{}: {}", uri, e)))?;
+                if bytes.len() + chunk.len() > MAX_RESPONSE_SIZE {
+                    return Err(OidcError::Http(format!("{}: response is larger than {} bytes", uri, MAX_RESPONSE_SIZE)));
+                }
+                bytes.extend_from_slice(&chunk);
+            }
+            Ok(Response { status, body: bytes })
+        };
+        tokio::time::timeout(REQUEST_TIMEOUT, exchange).await
+            .map_err(|_| OidcError::Http(format!("{}: no answer within {} seconds", uri, REQUEST_TIMEOUT.as_secs())))?
+    }
+}
+
+fn form_encode(value: &str) -> String {
+    serde_urlencoded::to_string([("", value)]).map(|s| s[1..].to_string()).unwrap_or_default()
+}
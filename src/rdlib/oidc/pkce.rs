@@ -0,0 +1,24 @@
+//! # PKCE
+//!
+//! This module generates the random values of a login and the code
+//! challenge of Proof Key for Code Exchange (RFC 7636), which binds the
+//! authorization code to the server that started the login.
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+/// Generates an unguessable value for the `state`, `nonce` and code
+/// verifier of a login: 43 URL-safe characters.
+pub fn random_token() -> String {
+    let mut bytes = [0u8; 32];
+    bytes[..16].copy_from_slice(Uuid::new_v4().as_bytes());
+    bytes[16..].copy_from_slice(Uuid::new_v4().as_bytes());
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// The `S256` code challenge of a code verifier.
+pub fn code_challenge(verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()))
+}
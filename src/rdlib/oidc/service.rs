@@ -0,0 +1,314 @@
+//! # OpenID Connect Service
+//!
+//! This module runs the login through an identity provider. `begin_login`
+//! creates a random `state`, `nonce` and PKCE code verifier and returns
+//! the URL the browser is sent to; `complete_login` takes the code the
+//! provider sends back, exchanges it for an ID token and verifies the
+//! token against the keys the provider publishes.
+//!
+//! The server keeps no record of logins that have begun. The browser holds
+//! the pending login in an `HttpOnly` cookie for ten minutes, so the
+//! callback can reach any server instance, and only the browser that began
+//! a login can complete it.
+
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::rdlib::auth::jwt::{JwtVerifier, TokenError};
+use crate::rdlib::auth::principal::Principal;
+use crate::rdlib::oidc::config::OidcConfig;
+use crate::rdlib::oidc::error::OidcError;
+use crate::rdlib::oidc::http::HttpClient;
+use crate::rdlib::oidc::pkce::{code_challenge, random_token};
+use crate::rdlib::users::user::ExternalIdentity;
+
+/// How long a login may take from its start to the callback
+pub const LOGIN_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+/// How often the keys of the provider may be fetched again when a token
+/// names an unknown key
+const MIN_KEY_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The endpoints of an identity provider, from its discovery document.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct ProviderMetadata {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub jwks_uri: String,
+}
+
+/// A user as vouched for by the identity provider.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExternalLogin {
+    /// The identity, with the role derived from the groups if the provider manages roles
+    pub identity: ExternalIdentity,
+    /// The groups of the user at the provider
+    pub groups: Vec<String>,
+}
+
+/// A login that has been sent to the provider, kept by the browser until
+/// the callback.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PendingLogin {
+    /// The `state` the provider sends back with the code
+    pub state: String,
+    nonce: String,
+    code_verifier: String,
+    started_at: DateTime<Utc>,
+}
+
+impl PendingLogin {
+    /// Encodes the login as the value of a cookie.
+    pub fn to_cookie_value(&self) -> String {
+        format!("{}.{}.{}.{}", self.state, self.nonce, self.code_verifier, self.started_at.timestamp())
+    }
+
+    /// Decodes a login from the value of a cookie.
+    ///
+    /// # Returns
+    ///
+    /// The login, or `None` if the value is malformed
+    pub fn from_cookie_value(value: &str) -> Option<Self> {
+        let mut parts = value.split('.');
+        let (state, nonce, code_verifier, started_at) = (parts.next()?, parts.next()?, parts.next()?, parts.next()?);
+        if parts.next().is_some() || [state, nonce, code_verifier].iter().any(|part| part.is_empty()) {
+            return None;
+        }
+        Some(Self {
+            state: state.to_string(),
+            nonce: nonce.to_string(),
+            code_verifier: code_verifier.to_string(),
+            started_at: DateTime::from_timestamp(started_at.parse().ok()?, 0)?,
+        })
+    }
+
+    /// Checks if the login has taken longer than `LOGIN_TIMEOUT`.
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        (now - self.started_at).to_std().is_ok_and(|elapsed| elapsed >= LOGIN_TIMEOUT)
+    }
+}
+
+/// The answer of the token endpoint.
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: Option<String>,
+}
+
+/// The error answer of the token endpoint.
+#[derive(Deserialize)]
+struct TokenErrorResponse {
+    error: String,
+    error_description: Option<String>,
+}
+
+/// Logs users in through an OpenID Connect identity provider.
+pub struct OidcService {
+    config: OidcConfig,
+    metadata: ProviderMetadata,
+    http: HttpClient,
+    verifier: RwLock<JwtVerifier>,
+    keys_fetched_at: Mutex<Instant>,
+}
+
+impl OidcService {
+    /// Creates the service, fetching the discovery document and keys of the
+    /// provider.
+    ///
+    /// # Returns
+    ///
+    /// The service, or an error if the provider cannot be reached or its
+    /// discovery document names another issuer
+    pub async fn discover(config: OidcConfig) -> Result<Self, OidcError> {
+        let http = HttpClient::new();
+        let metadata: ProviderMetadata = http.get_json(&config.discovery_url()).await
+            .map_err(|e| OidcError::Discovery(e.to_string()))?;
+        if metadata.issuer != config.issuer {
+            return Err(OidcError::Discovery(format!(
+                "the provider names its issuer '{}' instead of '{}'", metadata.issuer, config.issuer
+            )));
+        }
+        let verifier = fetch_verifier(&http, &metadata, &config).await?;
+        info!("OpenID Connect login through {} as client '{}'", config.issuer, config.client_id);
+        Ok(Self {
+            config,
+            metadata,
+            http,
+            verifier: RwLock::new(verifier),
+            keys_fetched_at: Mutex::new(Instant::now()),
+        })
+    }
+
+    /// Creates the service from the environment, see `OidcConfig::from_env`.
+    ///
+    /// # Returns
+    ///
+    /// The service, `None` if OpenID Connect login is not configured, or an
+    /// error if the configuration is incomplete or the provider cannot be used
+    pub async fn from_env() -> Result<Option<Self>, String> {
+        let Some(config) = OidcConfig::from_env()? else {
+            return Ok(None);
+        };
+        Self::discover(config).await.map(Some).map_err(|e| e.to_string())
+    }
+
+    /// The registration of RustDok at the provider.
+    pub fn config(&self) -> &OidcConfig {
+        &self.config
+    }
+
+    /// Begins a login.
+    ///
+    /// # Returns
+    ///
+    /// The authorization URL of the provider the browser is sent to, and
+    /// the login for the browser to keep until the callback
+    pub fn begin_login(&self) -> (String, PendingLogin) {
+        let login = PendingLogin {
+            state: random_token(),
+            nonce: random_token(),
+            code_verifier: random_token(),
+            started_at: Utc::now(),
+        };
+
+        let query = serde_urlencoded::to_string([
+            ("response_type", "code"),
+            ("client_id", self.config.client_id.as_str()),
+            ("redirect_uri", self.config.redirect_uri.as_str()),
+            ("scope", self.config.scopes.as_str()),
+            ("state", login.state.as_str()),
+            ("nonce", login.nonce.as_str()),
+            ("code_challenge", code_challenge(&login.code_verifier).as_str()),
+            ("code_challenge_method", "S256"),
+        ]).unwrap_or_default();
+        let separator = if self.metadata.authorization_endpoint.contains('?') { '&' } else { '?' };
+
+        (format!("{}{}{}", self.metadata.authorization_endpoint, separator, query), login)
+    }
+
+    /// Completes a login with the authorization code the provider sent back.
+    ///
+    /// # Arguments
+    ///
+    /// * `code` - The authorization code
+    /// * `state` - The state of the login, as sent back by the provider
+    /// * `login` - The login begun by the browser, from its cookie
+    ///
+    /// # Returns
+    ///
+    /// The user vouched for by the ID token, `InvalidState` if the state
+    /// is not that of the login or the login has expired, or why the
+    /// provider or its token failed
+    pub async fn complete_login(&self, code: &str, state: &str, login: &PendingLogin) -> Result<ExternalLogin, OidcError> {
+        // The state binds the callback to the browser that began the login
+        if state != login.state || login.is_expired(Utc::now()) {
+            return Err(OidcError::InvalidState);
+        }
+
+        let id_token = self.exchange_code(code, &login.code_verifier).await?;
+        let principal = self.verify_id_token(&id_token).await?;
+        if principal.claims.get("nonce").and_then(Value::as_str) != Some(login.nonce.as_str()) {
+            return Err(OidcError::InvalidIdToken("the nonce does not match the login".to_string()));
+        }
+        self.map_claims(&principal)
+    }
+
+    async fn exchange_code(&self, code: &str, code_verifier: &str) -> Result<String, OidcError> {
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", self.config.redirect_uri.as_str()),
+            ("code_verifier", code_verifier),
+        ];
+        let basic_auth = match &self.config.client_secret {
+            Some(secret) => Some((self.config.client_id.as_str(), secret.as_str())),
+            None => {
+                form.push(("client_id", self.config.client_id.as_str()));
+                None
+            },
+        };
+
+        let response = self.http.post_form(&self.metadata.token_endpoint, &form, basic_auth).await?;
+        if !response.status.is_success() {
+            let reason = match response.json::<TokenErrorResponse>() {
+                Ok(error) => match error.error_description {
+                    Some(description) => format!("{}: {}", error.error, description),
+                    None => error.error,
+                },
+                Err(_) => format!("the token endpoint answered {}", response.status),
+            };
+            return Err(OidcError::TokenExchange(reason));
+        }
+        response.json::<TokenResponse>()?.id_token
+            .ok_or_else(|| OidcError::TokenExchange("the provider returned no ID token".to_string()))
+    }
+
+    /// Verifies an ID token, fetching the keys of the provider again if the
+    /// token is signed with a key that is not known yet.
+    async fn verify_id_token(&self, id_token: &str) -> Result<Principal, OidcError> {
+        let result = self.verifier.read().unwrap().verify(id_token);
+        let result = match result {
+            Err(TokenError::UnknownKey) if self.may_refresh_keys() => {
+                warn!("ID token signed with an unknown key; fetching the keys of {}", self.config.issuer);
+                let verifier = fetch_verifier(&self.http, &self.metadata, &self.config).await?;
+                let result = verifier.verify(id_token);
+                *self.verifier.write().unwrap() = verifier;
+                result
+            },
+            result => result,
+        };
+        result.map_err(|e| OidcError::InvalidIdToken(e.to_string()))
+    }
+
+    fn may_refresh_keys(&self) -> bool {
+        let mut fetched_at = self.keys_fetched_at.lock().unwrap();
+        if fetched_at.elapsed() < MIN_KEY_REFRESH_INTERVAL {
+            return false;
+        }
+        *fetched_at = Instant::now();
+        true
+    }
+
+    /// Maps the claims of a verified ID token to a user.
+    fn map_claims(&self, principal: &Principal) -> Result<ExternalLogin, OidcError> {
+        let username = principal.claims.get(&self.config.username_claim)
+            .and_then(Value::as_str)
+            .filter(|username| !username.is_empty())
+            .ok_or_else(|| OidcError::MissingClaim(self.config.username_claim.clone()))?;
+        // An address the provider has not verified could claim another user's mailbox
+        let email = principal.claims.get("email")
+            .and_then(Value::as_str)
+            .filter(|_| principal.claims.get("email_verified").and_then(Value::as_bool) != Some(false))
+            .map(str::to_string);
+        let groups: Vec<String> = match principal.claims.get(&self.config.groups_claim) {
+            Some(Value::Array(groups)) => groups.iter().filter_map(Value::as_str).map(str::to_string).collect(),
+            Some(Value::String(group)) => vec![group.clone()],
+            _ => Vec::new(),
+        };
+
+        let identity = ExternalIdentity {
+            issuer: self.config.issuer.clone(),
+            subject: principal.subject.clone(),
+            username: username.to_string(),
+            email,
+            role: self.config.role_for(&groups),
+        };
+        Ok(ExternalLogin { identity, groups })
+    }
+}
+
+/// Fetches the keys of the provider into a verifier of its ID tokens.
+async fn fetch_verifier(http: &HttpClient, metadata: &ProviderMetadata, config: &OidcConfig) -> Result<JwtVerifier, OidcError> {
+    let jwks: Value = http.get_json(&metadata.jwks_uri).await?;
+    let verifier = JwtVerifier::new()
+        .with_jwks(&jwks.to_string())
+        .map_err(|e| OidcError::Discovery(format!("invalid keys at {}: {}", metadata.jwks_uri, e)))?
+        .with_issuers(vec![config.issuer.clone()])
+        .with_audiences(vec![config.client_id.clone()]);
+    Ok(verifier)
+}
@@ -7,4 +7,9 @@ pub mod service;
 pub mod bucket;
 pub mod object;
 pub mod error;
-pub mod types; 
\ No newline at end of file
+pub mod types;
+pub mod system;
+pub mod drop_link;
+pub mod policy;
+pub mod derived;
+pub mod resumable;
@@ -27,14 +27,14 @@ impl S3Service {
         info!("Creating bucket '{}'...", bucket_name);
         
         if let Err(validation_error) = Self::validate_bucket_name(bucket_name) {
-            return Err(S3Error::Other(validation_error));
+            return Err(S3Error::InvalidArgument(validation_error));
         }
         
         let buckets = self.list_buckets().await?;
         
         if buckets.contains(&bucket_name.to_string()) {
             info!("Bucket '{}' already exists", bucket_name);
-            return Err(S3Error::BucketAlreadyExists(bucket_name.to_string()));
+            return Err(S3Error::BucketAlreadyExists(format!("Bucket '{}' already exists", bucket_name)));
         }
         
         self.client
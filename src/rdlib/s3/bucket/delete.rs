@@ -28,7 +28,7 @@ impl S3Service {
         
         if !buckets.contains(&bucket_name.to_string()) {
             info!("Bucket '{}' does not exist", bucket_name);
-            return Err(S3Error::Other(format!("Bucket '{}' does not exist", bucket_name)));
+            return Err(S3Error::BucketNotFound(format!("Bucket '{}' not found", bucket_name)));
         }
         
         self.client
@@ -16,8 +16,7 @@ impl S3Service {
     /// * `Ok(Vec<String>)` - A vector of bucket names if successful
     /// * `Err(S3Error)` - If there was an error listing the buckets
     pub async fn list_buckets(&self) -> Result<Vec<String>, S3Error> {
-        let resp = self.client.list_buckets().send().await
-            .map_err(|e| S3Error::Other(format!("Failed to list buckets: {}", e)))?;
+        let resp = self.client.list_buckets().send().await?;
         
         let mut buckets = Vec::new();
         let bucket_list = resp.buckets();
@@ -0,0 +1,105 @@
+//! # Derived Objects
+//!
+//! This module provides a cache for renditions derived from objects, such as
+//! thumbnails. Renditions are stored as system objects and carry the ETag of
+//! the object they were derived from; once the source object changes, its
+//! ETag no longer matches and the cached rendition is treated as missing.
+
+use crate::rdlib::imaging::Rendition;
+use crate::rdlib::s3::error::S3Error;
+use crate::rdlib::s3::service::S3Service;
+
+/// The system folder derived objects are stored in
+const DERIVED_FOLDER: &str = "derived/";
+
+/// The metadata entry holding the ETag of the source object
+const SOURCE_ETAG_METADATA: &str = "source-etag";
+
+impl S3Service {
+    /// Builds the key of a derived object.
+    ///
+    /// # Arguments
+    ///
+    /// * `variant` - The kind of rendition, e.g. `thumbnails/256x256`
+    /// * `key` - The key of the source object
+    ///
+    /// # Returns
+    ///
+    /// The key the rendition is cached under
+    pub fn derived_key(variant: &str, key: &str) -> String {
+        Self::system_key(&format!("{}{}/{}", DERIVED_FOLDER, variant, key.trim_start_matches('/')))
+    }
+
+    /// Reads a cached rendition of an object.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket` - The name of the bucket containing the source object
+    /// * `key` - The key of the source object
+    /// * `variant` - The kind of rendition
+    /// * `source_etag` - The current ETag of the source object
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some(Rendition))` - If a rendition of the current source exists
+    /// * `Ok(None)` - If there is no rendition, or it was derived from an older source
+    /// * `Err(S3Error)` - If there was an error reading the rendition
+    pub async fn get_derived_object(&self, bucket: &str, key: &str, variant: &str, source_etag: &str) -> Result<Option<Rendition>, S3Error> {
+        let resp = match self.client
+            .get_object()
+            .bucket(bucket)
+            .key(Self::derived_key(variant, key))
+            .send()
+            .await
+        {
+            Ok(resp) => resp,
+            Err(aws_sdk_s3::error::SdkError::ServiceError(context)) if context.err().is_no_such_key() => {
+                return Ok(None);
+            },
+            Err(e) => return Err(S3Error::from(e)),
+        };
+
+        let cached_etag = resp.metadata()
+            .and_then(|m| m.get(SOURCE_ETAG_METADATA))
+            .map(|e| e.trim_matches('"'));
+        if cached_etag != Some(source_etag.trim_matches('"')) {
+            return Ok(None);
+        }
+
+        let content_type = resp.content_type().unwrap_or("application/octet-stream").to_string();
+        let data = resp.body.collect().await?;
+
+        Ok(Some(Rendition {
+            data: data.to_vec(),
+            content_type,
+        }))
+    }
+
+    /// Stores a rendition of an object, replacing any older rendition.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket` - The name of the bucket containing the source object
+    /// * `key` - The key of the source object
+    /// * `variant` - The kind of rendition
+    /// * `source_etag` - The ETag of the source the rendition was derived from
+    /// * `rendition` - The rendition to store
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the rendition was stored successfully
+    /// * `Err(S3Error)` - If there was an error storing the rendition
+    pub async fn put_derived_object(&self, bucket: &str, key: &str, variant: &str, source_etag: &str, rendition: &Rendition) -> Result<(), S3Error> {
+        self.client
+            .put_object()
+            .bucket(bucket)
+            .key(Self::derived_key(variant, key))
+            .content_type(&rendition.content_type)
+            .metadata(SOURCE_ETAG_METADATA, source_etag.trim_matches('"'))
+            .body(rendition.data.clone().into())
+            .send()
+            .await?;
+
+        Ok(())
+    }
+}
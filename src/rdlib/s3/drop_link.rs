@@ -0,0 +1,250 @@
+//! # Drop Links
+//!
+//! This module provides functionality for anonymous upload ("file drop") links.
+//! Drop links are stored as system objects in the bucket they upload into,
+//! and carry their own limits: expiry, maximum file size, allowed extensions
+//! and a maximum number of uploads.
+
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::rdlib::s3::error::S3Error;
+use crate::rdlib::s3::service::S3Service;
+use crate::rdlib::s3::types::{DropLink, Preconditions};
+
+/// The system folder drop links are stored in
+const DROP_LINK_FOLDER: &str = "drops/";
+
+/// How often claiming an upload is tried while other uploads change the link
+const MAX_CLAIM_ATTEMPTS: usize = 5;
+
+/// The longest a drop link can accept uploads
+pub const MAX_DROP_LINK_LIFETIME_SECONDS: u64 = 365 * 24 * 3600;
+
+impl DropLink {
+    /// Creates a new drop link with a fresh random token and no limits.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket` - The bucket uploads are stored in
+    /// * `prefix` - The prefix uploads are stored under, as normalized by `normalize_prefix`
+    ///
+    /// # Returns
+    ///
+    /// A new `DropLink`
+    pub fn new(bucket: &str, prefix: &str) -> Self {
+        Self {
+            token: Uuid::new_v4().simple().to_string(),
+            bucket: bucket.to_string(),
+            prefix: prefix.to_string(),
+            created_at: Utc::now(),
+            expires_at: None,
+            max_file_size: None,
+            allowed_extensions: None,
+            max_uploads: None,
+            upload_count: 0,
+            etag: None,
+        }
+    }
+
+    /// Normalizes a list of extensions to lowercase without leading dots.
+    ///
+    /// # Arguments
+    ///
+    /// * `extensions` - The extensions to normalize, e.g. `[".PDF", "docx"]`
+    ///
+    /// # Returns
+    ///
+    /// The normalized extensions, e.g. `["pdf", "docx"]`
+    pub fn normalize_extensions(extensions: &[String]) -> Vec<String> {
+        extensions
+            .iter()
+            .map(|e| e.trim().trim_start_matches('.').to_lowercase())
+            .filter(|e| !e.is_empty())
+            .collect()
+    }
+
+    /// Checks if the link has expired.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The current time
+    ///
+    /// # Returns
+    ///
+    /// `true` if the link has an expiry that lies in the past
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+
+    /// Returns the number of uploads the link still accepts.
+    ///
+    /// # Returns
+    ///
+    /// `None` if the link has no upload limit, otherwise the remaining count
+    pub fn remaining_uploads(&self) -> Option<u32> {
+        self.max_uploads.map(|max| max.saturating_sub(self.upload_count))
+    }
+
+    /// Checks if the link still accepts uploads at the given time.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The current time
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the link accepts uploads
+    /// * `Err(String)` - A description of why the link no longer accepts uploads
+    pub fn validate_active(&self, now: DateTime<Utc>) -> Result<(), String> {
+        if self.is_expired(now) {
+            return Err("This upload link has expired".to_string());
+        }
+        if self.remaining_uploads() == Some(0) {
+            return Err("This upload link has reached its upload limit".to_string());
+        }
+        Ok(())
+    }
+
+    /// Checks if a file name is accepted by the link's extension list.
+    ///
+    /// # Arguments
+    ///
+    /// * `filename` - The name of the file to check
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the file name is accepted
+    /// * `Err(String)` - A description of why the file name is rejected
+    pub fn validate_filename(&self, filename: &str) -> Result<(), String> {
+        let Some(allowed) = &self.allowed_extensions else {
+            return Ok(());
+        };
+
+        let extension = filename
+            .rsplit_once('.')
+            .map(|(_, ext)| ext.to_lowercase())
+            .unwrap_or_default();
+
+        if allowed.contains(&extension) {
+            Ok(())
+        } else {
+            Err(format!("File '{}' is not allowed. Allowed extensions: {}", filename, allowed.join(", ")))
+        }
+    }
+}
+
+impl S3Service {
+    /// Stores a drop link, creating or replacing it.
+    ///
+    /// # Arguments
+    ///
+    /// * `link` - The drop link to store
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the drop link was stored successfully
+    /// * `Err(S3Error)` - If there was an error storing the drop link
+    pub async fn save_drop_link(&self, link: &DropLink) -> Result<(), S3Error> {
+        let name = format!("{}{}.json", DROP_LINK_FOLDER, link.token);
+        self.put_system_object(&link.bucket, &name, link).await
+    }
+
+    /// Looks up a drop link by its token.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket` - The bucket the drop link uploads into
+    /// * `token` - The token of the drop link
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some(DropLink))` - If the drop link exists
+    /// * `Ok(None)` - If there is no drop link with this token
+    /// * `Err(S3Error)` - If there was an error reading the drop link
+    pub async fn get_drop_link(&self, bucket: &str, token: &str) -> Result<Option<DropLink>, S3Error> {
+        // Tokens are plain hex strings; anything else cannot be a valid link
+        if token.is_empty() || !token.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Ok(None);
+        }
+
+        let name = format!("{}{}.json", DROP_LINK_FOLDER, token);
+        let link = self.get_system_object_with_etag::<DropLink>(bucket, &name).await?;
+        Ok(link.map(|(mut link, etag)| {
+            link.etag = etag;
+            link
+        }))
+    }
+
+    /// Claims one upload of a drop link before a file is stored through it.
+    ///
+    /// The raised upload count is only saved if the stored link still has the
+    /// ETag it was read with, so concurrent uploads cannot together go past
+    /// the upload limit. If another upload changed the link in between, it is
+    /// read again and the claim retried. A claimed upload is not given back
+    /// if storing the file fails.
+    ///
+    /// # Arguments
+    ///
+    /// * `link` - The drop link, as read from storage; updated to the stored state
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(true)` - If the upload was claimed
+    /// * `Ok(false)` - If the link no longer accepts uploads
+    /// * `Err(S3Error::NotFound)` - If the link was revoked
+    /// * `Err(S3Error::PreconditionFailed)` - If other uploads kept changing the link
+    /// * `Err(S3Error)` - If there was an error reading or storing the link
+    pub async fn claim_drop_link_upload(&self, link: &mut DropLink) -> Result<bool, S3Error> {
+        let name = format!("{}{}.json", DROP_LINK_FOLDER, link.token);
+
+        for _ in 0..MAX_CLAIM_ATTEMPTS {
+            if link.validate_active(Utc::now()).is_err() {
+                return Ok(false);
+            }
+
+            let mut claimed = link.clone();
+            claimed.upload_count += 1;
+            let preconditions = Preconditions { if_match: link.etag.clone().map(|etag| vec![etag]), if_none_match: None };
+            if let Some(etag) = self.put_system_object_if(&link.bucket, &name, &claimed, &preconditions).await? {
+                claimed.etag = Some(etag);
+                *link = claimed;
+                return Ok(true);
+            }
+
+            *link = self.get_drop_link(&link.bucket, &link.token).await?
+                .ok_or_else(|| S3Error::NotFound(format!("Drop link for {}/{} was revoked", link.bucket, link.prefix)))?;
+        }
+        Err(S3Error::PreconditionFailed(format!("Drop link for {}/{} is busy with other uploads", link.bucket, link.prefix)))
+    }
+
+    /// Lists all drop links of a bucket.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket` - The bucket to list the drop links of
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<DropLink>)` - The drop links of the bucket
+    /// * `Err(S3Error)` - If there was an error listing the drop links
+    pub async fn list_drop_links(&self, bucket: &str) -> Result<Vec<DropLink>, S3Error> {
+        self.list_system_objects(bucket, DROP_LINK_FOLDER).await
+    }
+
+    /// Revokes a drop link.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket` - The bucket the drop link uploads into
+    /// * `token` - The token of the drop link
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the drop link was deleted
+    /// * `Err(S3Error)` - If there was an error deleting the drop link
+    pub async fn delete_drop_link(&self, bucket: &str, token: &str) -> Result<(), S3Error> {
+        let name = format!("{}{}.json", DROP_LINK_FOLDER, token);
+        self.delete_system_object(bucket, &name).await
+    }
+}
@@ -1,22 +1,13 @@
 //! # S3 Error Handling
-//! 
+//!
 //! This module provides error types and conversion implementations for
 //! handling errors that occur during S3 operations. It defines a custom
-//! error type `S3Error` and implements conversions from various AWS SDK
-//! error types to this custom type.
+//! error type `S3Error` and classifies AWS SDK errors into its variants
+//! using the error code and HTTP status reported by S3.
 
 use aws_sdk_s3::{Error as AwsS3Error};
-use aws_sdk_s3::error::SdkError;
-use aws_sdk_s3::operation::create_bucket::CreateBucketError;
-use aws_sdk_s3::operation::delete_bucket::DeleteBucketError;
-use aws_sdk_s3::operation::list_buckets::ListBucketsError;
-use aws_sdk_s3::operation::list_objects_v2::ListObjectsV2Error;
-use aws_sdk_s3::operation::put_object::PutObjectError;
-use aws_sdk_s3::operation::delete_object::DeleteObjectError;
-use aws_sdk_s3::operation::delete_objects::DeleteObjectsError;
-use aws_sdk_s3::operation::head_object::HeadObjectError;
-use aws_sdk_s3::operation::get_object::GetObjectError;
-use aws_sdk_s3::operation::copy_object::CopyObjectError;
+use aws_sdk_s3::config::http::HttpResponse;
+use aws_sdk_s3::error::{DisplayErrorContext, ProvideErrorMetadata, SdkError};
 use aws_sdk_s3::primitives::ByteStreamError;
 use std::fmt;
 
@@ -24,39 +15,87 @@ use std::fmt;
 ///
 /// This enum represents the various types of errors that can occur
 /// during S3 operations. It provides a unified error type for the
-/// application to handle.
-#[derive(Debug, Clone)]
+/// application to handle. Each variant carries a human readable message.
+#[derive(Debug, Clone, PartialEq)]
 pub enum S3Error {
-    /// An error from the AWS S3 SDK
-    AwsError(String),
+    /// The object, or multipart upload, does not exist
+    NotFound(String),
+    /// The bucket does not exist
+    BucketNotFound(String),
     /// Error when attempting to create a bucket that already exists
     BucketAlreadyExists(String),
-    /// Error when a bucket is not found. Used only in testing so surpressing the warning
-    #[allow(dead_code)]
-    BucketNotFound(String),
+    /// The bucket still contains objects
+    BucketNotEmpty(String),
+    /// The server's credentials were rejected, or are not allowed to perform the operation
+    AccessDenied(String),
+    /// A precondition of the request did not hold
+    PreconditionFailed(String),
+    /// S3 asked to reduce the request rate, or is temporarily unavailable
+    SlowDown(String),
+    /// The request was rejected as invalid
+    InvalidArgument(String),
+    /// The request to S3 timed out
+    Timeout(String),
+    /// S3 could not be reached
+    Unavailable(String),
+    /// Any other error reported by S3
+    AwsError(String),
     /// Other miscellaneous errors
     Other(String),
 }
 
 impl S3Error {
-    /// Check if the error message contains a specific string.
+    /// Classifies an error reported by S3.
     ///
-    /// This method is useful for checking the type of error when
-    /// the specific error variant is not known.
+    /// The error code is checked first; the HTTP status is used for responses
+    /// without a code, such as the bodiless errors of HEAD requests.
     ///
     /// # Arguments
     ///
-    /// * `s` - The string to check for in the error message
+    /// * `code` - The S3 error code, e.g. `NoSuchKey`
+    /// * `status` - The HTTP status of the response
+    /// * `message` - The message describing the error
     ///
     /// # Returns
     ///
-    /// `true` if the error message contains the specified string, `false` otherwise
-    pub fn contains(&self, s: &str) -> bool {
+    /// The matching `S3Error` variant
+    pub fn from_metadata(code: Option<&str>, status: Option<u16>, message: String) -> Self {
+        match (code, status) {
+            (Some("NoSuchBucket"), _) => S3Error::BucketNotFound(message),
+            (Some("NoSuchKey" | "NoSuchUpload" | "NotFound"), _) | (None, Some(404)) => S3Error::NotFound(message),
+            (Some("BucketAlreadyExists" | "BucketAlreadyOwnedByYou"), _) => S3Error::BucketAlreadyExists(message),
+            (Some("BucketNotEmpty"), _) => S3Error::BucketNotEmpty(message),
+            (Some("AccessDenied" | "AllAccessDisabled" | "InvalidAccessKeyId" | "SignatureDoesNotMatch"), _)
+            | (_, Some(401 | 403)) => S3Error::AccessDenied(message),
+            (Some("PreconditionFailed" | "ConditionalRequestConflict"), _) | (_, Some(412)) => {
+                S3Error::PreconditionFailed(message)
+            },
+            (Some("SlowDown" | "ServiceUnavailable" | "TooManyRequests"), _) | (_, Some(429 | 503)) => {
+                S3Error::SlowDown(message)
+            },
+            (Some("RequestTimeout"), _) | (_, Some(408)) => S3Error::Timeout(message),
+            (Some("InvalidArgument" | "InvalidBucketName" | "InvalidRequest" | "InvalidDigest" | "BadDigest"
+                | "EntityTooSmall" | "EntityTooLarge" | "InvalidPart" | "InvalidPartOrder" | "KeyTooLongError"), _)
+            | (_, Some(400)) => S3Error::InvalidArgument(message),
+            _ => S3Error::AwsError(message),
+        }
+    }
+
+    /// The message describing the error.
+    pub fn message(&self) -> &str {
         match self {
-            S3Error::AwsError(msg) => msg.contains(s),
-            S3Error::BucketAlreadyExists(msg) => msg.contains(s),
-            S3Error::BucketNotFound(msg) => msg.contains(s),
-            S3Error::Other(msg) => msg.contains(s),
+            S3Error::NotFound(msg)
+            | S3Error::BucketNotFound(msg)
+            | S3Error::BucketAlreadyExists(msg)
+            | S3Error::BucketNotEmpty(msg)
+            | S3Error::AccessDenied(msg)
+            | S3Error::PreconditionFailed(msg)
+            | S3Error::SlowDown(msg)
+            | S3Error::InvalidArgument(msg)
+            | S3Error::Timeout(msg)
+            | S3Error::Unavailable(msg)
+            | S3Error::AwsError(msg)
+            | S3Error::Other(msg) => msg,
         }
     }
 }
@@ -65,85 +104,53 @@ impl fmt::Display for S3Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             S3Error::AwsError(e) => write!(f, "AWS S3 Error: {}", e),
-            S3Error::BucketAlreadyExists(name) => write!(f, "Bucket '{}' already exists", name),
-            S3Error::BucketNotFound(name) => write!(f, "Bucket '{}' not found", name),
             S3Error::Other(e) => write!(f, "S3 Error: {}", e),
+            other => f.write_str(other.message()),
         }
     }
 }
 
 impl std::error::Error for S3Error {}
 
-impl From<AwsS3Error> for S3Error {
-    fn from(err: AwsS3Error) -> Self {
-        S3Error::AwsError(err.to_string())
+/// Builds the message of an error reported by S3 from its code and message.
+fn service_message(code: Option<&str>, message: Option<&str>) -> Option<String> {
+    match (code, message) {
+        (Some(code), Some(message)) => Some(format!("{}: {}", code, message)),
+        (Some(text), None) | (None, Some(text)) => Some(text.to_string()),
+        (None, None) => None,
     }
 }
 
-// Specific implementations for SdkError types
-impl From<SdkError<CreateBucketError>> for S3Error {
-    fn from(err: SdkError<CreateBucketError>) -> Self {
-        S3Error::AwsError(format!("{:?}", err))
-    }
-}
-
-impl From<SdkError<ListBucketsError>> for S3Error {
-    fn from(err: SdkError<ListBucketsError>) -> Self {
-        S3Error::AwsError(format!("{:?}", err))
-    }
-}
-
-impl From<SdkError<ListObjectsV2Error>> for S3Error {
-    fn from(err: SdkError<ListObjectsV2Error>) -> Self {
-        S3Error::AwsError(format!("{:?}", err))
-    }
-}
-
-impl From<SdkError<PutObjectError>> for S3Error {
-    fn from(err: SdkError<PutObjectError>) -> Self {
-        S3Error::AwsError(format!("{:?}", err))
-    }
-}
-
-impl From<SdkError<DeleteObjectError>> for S3Error {
-    fn from(err: SdkError<DeleteObjectError>) -> Self {
-        S3Error::AwsError(format!("{:?}", err))
-    }
-}
-
-impl From<SdkError<HeadObjectError>> for S3Error {
-    fn from(err: SdkError<HeadObjectError>) -> Self {
-        S3Error::AwsError(format!("{:?}", err))
-    }
-}
-
-impl From<SdkError<GetObjectError>> for S3Error {
-    fn from(err: SdkError<GetObjectError>) -> Self {
-        S3Error::AwsError(format!("{:?}", err))
-    }
-}
-
-impl From<SdkError<DeleteBucketError>> for S3Error {
-    fn from(err: SdkError<DeleteBucketError>) -> Self {
-        S3Error::AwsError(format!("{:?}", err))
-    }
-}
-
-impl From<SdkError<CopyObjectError>> for S3Error {
-    fn from(err: SdkError<CopyObjectError>) -> Self {
-        S3Error::AwsError(format!("{:?}", err))
+impl From<AwsS3Error> for S3Error {
+    fn from(err: AwsS3Error) -> Self {
+        let message = service_message(err.code(), err.message()).unwrap_or_else(|| err.to_string());
+        S3Error::from_metadata(err.code(), None, message)
     }
 }
 
-impl From<SdkError<DeleteObjectsError>> for S3Error {
-    fn from(err: SdkError<DeleteObjectsError>) -> Self {
-        S3Error::AwsError(format!("{:?}", err))
+impl<E> From<SdkError<E, HttpResponse>> for S3Error
+where
+    E: ProvideErrorMetadata + std::error::Error + 'static,
+{
+    fn from(err: SdkError<E, HttpResponse>) -> Self {
+        let message = service_message(err.code(), err.message())
+            .unwrap_or_else(|| DisplayErrorContext(&err).to_string());
+
+        match &err {
+            SdkError::TimeoutError(_) => S3Error::Timeout(message),
+            SdkError::DispatchFailure(failure) if failure.is_timeout() => S3Error::Timeout(message),
+            SdkError::DispatchFailure(failure) if failure.is_io() => S3Error::Unavailable(message),
+            SdkError::ServiceError(context) => {
+                S3Error::from_metadata(err.code(), Some(context.raw().status().as_u16()), message)
+            },
+            _ => S3Error::AwsError(message),
+        }
     }
 }
 
 impl From<ByteStreamError> for S3Error {
     fn from(err: ByteStreamError) -> Self {
-        S3Error::AwsError(format!("ByteStream Error: {:?}", err))
+        S3Error::AwsError(format!("ByteStream Error: {}", DisplayErrorContext(&err)))
     }
 }
 
@@ -151,4 +158,4 @@ impl From<Box<dyn std::error::Error + Send + Sync>> for S3Error {
     fn from(err: Box<dyn std::error::Error + Send + Sync>) -> Self {
         S3Error::Other(err.to_string())
     }
-} 
\ No newline at end of file
+}
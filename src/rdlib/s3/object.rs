@@ -2,10 +2,14 @@
 //! 
 //! This module provides functionality for working with S3 objects.
 //! It includes operations for uploading, downloading, listing, and deleting objects,
-//! as well as validation of object keys.
+//! copying objects, reading object metadata, multipart uploads and conditional writes, as well as validation of object keys.
 
 pub mod put;
 pub mod delete;
 pub mod list;
 pub mod get;
 pub mod validate;
+pub mod head;
+pub mod multipart;
+pub mod conditional;
+pub mod copy;
@@ -0,0 +1,221 @@
+//! # Conditional Writes
+//!
+//! This module provides the ETag preconditions of writes and deletes.
+//! Preconditions are enforced by the storage backend where it supports them:
+//! S3 accepts `If-Match` with a single ETag and `If-None-Match: *` on writes,
+//! and `If-Match` on deletes. Other preconditions, and all preconditions on
+//! backends without conditional writes (see `S3_CONDITIONAL_WRITES`), are
+//! checked with a HEAD request before the write. That check is best effort:
+//! a concurrent write between the check and the write is not detected.
+//!
+//! As in RFC 9110, `If-Match` uses strong comparison, so a weak ETag never
+//! matches, while `If-None-Match` uses weak comparison.
+
+use std::env;
+
+use aws_sdk_s3::config::http::HttpResponse;
+use aws_sdk_s3::error::SdkError;
+use once_cell::sync::Lazy;
+
+use crate::rdlib::s3::error::S3Error;
+use crate::rdlib::s3::service::S3Service;
+use crate::rdlib::s3::types::Preconditions;
+
+/// Whether the storage backend enforces conditional writes, read from `S3_CONDITIONAL_WRITES`
+static CONDITIONAL_WRITES: Lazy<bool> = Lazy::new(|| {
+    env::var("S3_CONDITIONAL_WRITES").map(|v| v != "false" && v != "0").unwrap_or(true)
+});
+
+/// The prefix of weak ETags
+const WEAK_PREFIX: &str = "W/";
+
+/// The headers sent to S3 to enforce preconditions, as `(If-Match, If-None-Match)`
+pub(crate) type ConditionHeaders = (Option<String>, Option<String>);
+
+impl Preconditions {
+    /// Preconditions that only allow creating a new object.
+    pub fn create_only() -> Self {
+        Self { if_match: None, if_none_match: Some(vec!["*".to_string()]) }
+    }
+
+    /// Parses the values of the `If-Match` and `If-None-Match` headers.
+    ///
+    /// # Arguments
+    ///
+    /// * `if_match` - The value of the `If-Match` header, if present
+    /// * `if_none_match` - The value of the `If-None-Match` header, if present
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Preconditions)` - The parsed preconditions
+    /// * `Err(String)` - A description of why a header is invalid
+    pub fn parse(if_match: Option<&str>, if_none_match: Option<&str>) -> Result<Self, String> {
+        Ok(Self {
+            if_match: if_match.map(|v| Self::parse_etags(v, "If-Match", Self::normalize_strong_etag)).transpose()?,
+            if_none_match: if_none_match.map(|v| Self::parse_etags(v, "If-None-Match", Self::normalize_etag)).transpose()?,
+        })
+    }
+
+    fn parse_etags(value: &str, header: &str, normalize: fn(&str) -> String) -> Result<Vec<String>, String> {
+        let etags: Vec<String> = value
+            .split(',')
+            .map(str::trim)
+            .filter(|etag| !etag.is_empty())
+            .map(normalize)
+            .collect();
+        if etags.is_empty() || etags.iter().any(|etag| etag.is_empty() || etag == WEAK_PREFIX || etag.contains('"')) {
+            return Err(format!("Invalid {} header", header));
+        }
+        if etags.len() > 1 && etags.iter().any(|etag| etag == "*") {
+            return Err(format!("{} must be either '*' or a list of ETags", header));
+        }
+        Ok(etags)
+    }
+
+    /// Strips the weak prefix and the quotes of an ETag, for weak comparison.
+    pub fn normalize_etag(etag: &str) -> String {
+        let etag = etag.trim();
+        let etag = etag.strip_prefix(WEAK_PREFIX).unwrap_or(etag);
+        etag.strip_prefix('"').and_then(|e| e.strip_suffix('"')).unwrap_or(etag).to_string()
+    }
+
+    /// Strips the quotes of an ETag, for strong comparison.
+    ///
+    /// Weak ETags keep their prefix, so they never equal the ETag of an
+    /// object, as `If-Match` requires.
+    pub fn normalize_strong_etag(etag: &str) -> String {
+        let etag = etag.trim();
+        match etag.strip_prefix(WEAK_PREFIX) {
+            Some(weak) => format!("{}{}", WEAK_PREFIX, Self::normalize_etag(weak)),
+            None => Self::normalize_etag(etag),
+        }
+    }
+
+    /// Checks if there are any preconditions.
+    pub fn is_empty(&self) -> bool {
+        self.if_match.is_none() && self.if_none_match.is_none()
+    }
+
+    /// Checks if the preconditions only allow creating a new object.
+    pub fn requires_absence(&self) -> bool {
+        self.if_none_match.as_ref().is_some_and(|etags| etags.iter().any(|etag| etag == "*"))
+    }
+
+    /// Evaluates the preconditions against the current state of the object.
+    ///
+    /// # Arguments
+    ///
+    /// * `current_etag` - The ETag of the existing object, or `None` if it does not exist
+    ///
+    /// # Returns
+    ///
+    /// `true` if the write or delete may happen
+    pub fn evaluate(&self, current_etag: Option<&str>) -> bool {
+        let matches = |etags: &Vec<String>, normalize: fn(&str) -> String| {
+            current_etag.map(normalize).is_some_and(|current| etags.iter().any(|etag| etag == "*" || *etag == current))
+        };
+
+        self.if_match.as_ref().is_none_or(|etags| matches(etags, Self::normalize_strong_etag))
+            && !self.if_none_match.as_ref().is_some_and(|etags| matches(etags, Self::normalize_etag))
+    }
+
+    /// The headers that let S3 enforce the preconditions of a write.
+    ///
+    /// # Returns
+    ///
+    /// `None` if S3 cannot enforce these preconditions
+    pub(crate) fn write_headers(&self) -> Option<ConditionHeaders> {
+        let if_none_match = match self.if_none_match.as_deref() {
+            None => None,
+            Some([etag]) if etag == "*" => Some("*".to_string()),
+            Some(_) => return None,
+        };
+        Some((self.native_if_match()?, if_none_match))
+    }
+
+    /// The `If-Match` header that lets S3 enforce the preconditions of a delete.
+    ///
+    /// # Returns
+    ///
+    /// `None` if S3 cannot enforce these preconditions
+    pub(crate) fn delete_header(&self) -> Option<Option<String>> {
+        match self.if_none_match {
+            None => self.native_if_match(),
+            Some(_) => None,
+        }
+    }
+
+    /// The `If-Match` header S3 accepts, which is a single ETag.
+    fn native_if_match(&self) -> Option<Option<String>> {
+        match self.if_match.as_deref() {
+            None => Some(None),
+            Some([etag]) if etag != "*" && !etag.starts_with(WEAK_PREFIX) => Some(Some(format!("\"{}\"", etag))),
+            Some(_) => None,
+        }
+    }
+}
+
+/// Checks if a request failed because a precondition did not hold.
+///
+/// S3 answers with `412 Precondition Failed` if the condition is not met,
+/// with `409 Conflict` if a concurrent conditional write to the key is in
+/// progress, and with `404 Not Found` if `If-Match` names a missing object.
+pub(crate) fn is_precondition_failure<E>(error: &SdkError<E, HttpResponse>, preconditions: &Preconditions) -> bool {
+    error.raw_response().is_some_and(|r| match r.status().as_u16() {
+        409 | 412 => true,
+        404 => preconditions.if_match.is_some(),
+        _ => false,
+    })
+}
+
+impl S3Service {
+    /// Checks if the storage backend enforces conditional writes.
+    ///
+    /// Enabled unless `S3_CONDITIONAL_WRITES` is `false`, which is needed for
+    /// backends that ignore `If-Match` and `If-None-Match` instead of rejecting them.
+    pub fn conditional_writes() -> bool {
+        *CONDITIONAL_WRITES
+    }
+
+    /// Checks preconditions against the current state of an object.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key (path) of the object
+    /// * `bucket` - The name of the bucket containing the object
+    /// * `preconditions` - The preconditions to check
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(bool)` - Whether the preconditions hold
+    /// * `Err(S3Error)` - If there was an error reading the object
+    pub async fn check_preconditions(&self, key: &str, bucket: &str, preconditions: &Preconditions) -> Result<bool, S3Error> {
+        if preconditions.is_empty() {
+            return Ok(true);
+        }
+        let head = self.head_object(key, bucket).await?;
+        Ok(preconditions.evaluate(head.map(|head| head.etag.unwrap_or_default()).as_deref()))
+    }
+
+    /// Prepares the preconditions of a write.
+    ///
+    /// If S3 can enforce them, the headers to send are returned. Otherwise they
+    /// are checked right away, and no headers are needed if they hold.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some(ConditionHeaders))` - The headers to send with the write
+    /// * `Ok(None)` - If the preconditions do not hold
+    /// * `Err(S3Error)` - If there was an error checking the preconditions
+    pub(crate) async fn write_condition_headers(
+        &self,
+        key: &str,
+        bucket: &str,
+        preconditions: &Preconditions
+    ) -> Result<Option<ConditionHeaders>, S3Error> {
+        if Self::conditional_writes() && let Some(headers) = preconditions.write_headers() {
+            return Ok(Some(headers));
+        }
+        Ok(self.check_preconditions(key, bucket, preconditions).await?.then_some((None, None)))
+    }
+}
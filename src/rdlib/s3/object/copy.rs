@@ -0,0 +1,70 @@
+//! # Object Copy
+//!
+//! This module provides copying of objects within a bucket. The copy reads
+//! a known version of the source and writes the destination under the same
+//! preconditions as uploads, as described in the `conditional` module.
+
+use crate::rdlib::s3::error::S3Error;
+use crate::rdlib::s3::object::conditional::is_precondition_failure;
+use crate::rdlib::s3::service::S3Service;
+use crate::rdlib::s3::types::Preconditions;
+
+impl S3Service {
+    /// Copies an object within a bucket if its preconditions hold.
+    ///
+    /// The copy only happens while the source still has `source_etag`.
+    /// The preconditions apply to the destination; S3 does not model them
+    /// for copies, so they are sent as raw headers where the backend
+    /// enforces conditional writes, and checked before the copy otherwise.
+    ///
+    /// # Arguments
+    ///
+    /// * `source_key` - The key (path) of the object to copy
+    /// * `source_etag` - The ETag the source must still have, including its quotes
+    /// * `key` - The key (path) to copy the object to
+    /// * `bucket` - The name of the bucket containing both objects
+    /// * `preconditions` - The preconditions the destination must meet
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some(String))` - The ETag of the copy
+    /// * `Ok(None)` - If the source changed or a precondition of the destination did not hold
+    /// * `Err(S3Error)` - If there was an error copying the object
+    pub async fn copy_object_with_preconditions(
+        &self,
+        source_key: &str,
+        source_etag: &str,
+        key: &str,
+        bucket: &str,
+        preconditions: &Preconditions
+    ) -> Result<Option<String>, S3Error> {
+        let Some((if_match, if_none_match)) = self.write_condition_headers(key, bucket, preconditions).await? else {
+            return Ok(None);
+        };
+
+        let result = self.client
+            .copy_object()
+            .bucket(bucket)
+            .copy_source(format!("{}/{}", bucket, source_key))
+            .copy_source_if_match(source_etag)
+            .key(key)
+            .customize()
+            .mutate_request(move |request| {
+                if let Some(if_match) = &if_match {
+                    request.headers_mut().insert("If-Match", if_match.clone());
+                }
+                if let Some(if_none_match) = &if_none_match {
+                    request.headers_mut().insert("If-None-Match", if_none_match.clone());
+                }
+            })
+            .send()
+            .await;
+
+        match result {
+            Ok(resp) => Ok(Some(resp.copy_object_result().and_then(|r| r.e_tag()).unwrap_or_default().to_string())),
+            // A changed source fails `copy_source_if_match` with 412 as well
+            Err(e) if is_precondition_failure(&e, preconditions) => Ok(None),
+            Err(e) => Err(S3Error::from(e)),
+        }
+    }
+}
@@ -7,10 +7,55 @@
 use aws_sdk_s3::types::{Delete, ObjectIdentifier};
 
 use crate::rdlib::s3::error::S3Error;
+use crate::rdlib::s3::object::conditional::is_precondition_failure;
 use crate::rdlib::s3::service::S3Service;
+use crate::rdlib::s3::types::Preconditions;
 use log::{info, error};
 
 impl S3Service {    
+    /// Deletes a single object if its preconditions hold.
+    ///
+    /// S3 enforces a single `If-Match` ETag itself; other preconditions are
+    /// checked before the delete, as described in the `conditional` module.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key (path) of the object to delete
+    /// * `bucket` - The name of the bucket to delete from
+    /// * `preconditions` - The preconditions the object must meet
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(true)` - If the object was deleted
+    /// * `Ok(false)` - If a precondition did not hold
+    /// * `Err(S3Error)` - If there was an error deleting the object
+    pub async fn delete_object_with_preconditions(
+        &self,
+        key: &str,
+        bucket: &str,
+        preconditions: &Preconditions
+    ) -> Result<bool, S3Error> {
+        let if_match = match preconditions.delete_header().filter(|_| Self::conditional_writes()) {
+            Some(if_match) => if_match,
+            None if self.check_preconditions(key, bucket, preconditions).await? => None,
+            None => return Ok(false),
+        };
+
+        match self.client
+            .delete_object()
+            .bucket(bucket)
+            .key(key)
+            .set_if_match(if_match)
+            .send()
+            .await
+        {
+            Ok(_) => Ok(true),
+            Err(e) if is_precondition_failure(&e, preconditions) => Ok(false),
+            Err(e) => Err(S3Error::from(e)),
+        }
+    }
+
+
     /// Deletes multiple objects from a bucket.
     ///
     /// This method deletes the specified objects from either the specified bucket
@@ -73,7 +118,7 @@ impl S3Service {
                                 .map_err(|err| {
                                     let error_msg = format!("Failed to build key for delete_object: {err:?}");
                                     error!("{}", error_msg);
-                                    S3Error::AwsError(error_msg)
+                                    S3Error::Other(error_msg)
                                 })?);
                         }
                     }
@@ -85,7 +130,7 @@ impl S3Service {
                     .map_err(|err| {
                         let error_msg = format!("Failed to build key for delete_object: {err:?}");
                         error!("{}", error_msg);
-                        S3Error::AwsError(error_msg)
+                        S3Error::Other(error_msg)
                     })?;
                 delete_object_ids.push(obj_id);
             }
@@ -108,7 +153,7 @@ impl S3Service {
                         .map_err(|err| {
                             let error_msg = format!("Failed to build delete_object input {err:?}");
                             error!("{}", error_msg);
-                            S3Error::AwsError(error_msg)
+                            S3Error::Other(error_msg)
                         })?,
                 )
                 .send()
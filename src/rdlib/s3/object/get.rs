@@ -1,7 +1,10 @@
 //! # Object Download
 //! 
 //! This module provides functionality for downloading objects from S3 buckets.
-//! It includes methods for getting objects from specific buckets or from the default bucket.
+//! It includes methods for getting objects from specific buckets or from the default bucket,
+//! either collected into memory or as a stream, and for reading byte ranges of objects.
+
+use aws_sdk_s3::primitives::ByteStream;
 
 use crate::rdlib::s3::error::S3Error;
 use crate::rdlib::s3::service::S3Service;
@@ -46,4 +49,106 @@ impl S3Service {
     pub async fn get_object(&self, key: &str, bucket: &str) -> Result<Vec<u8>, S3Error> {
         self.get_object_from_bucket(bucket, key).await
     }
-}
\ No newline at end of file
+
+    /// Downloads an object together with its ETag.
+    ///
+    /// The ETag is the one of the response that supplied the data, so it
+    /// always describes exactly this version of the object.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key (path) of the object
+    /// * `bucket` - The name of the bucket
+    ///
+    /// # Returns
+    ///
+    /// * `Ok((Vec<u8>, Option<String>))` - The data of the object and its ETag, if S3 returned one
+    /// * `Err(S3Error)` - If there was an error downloading the object
+    pub async fn get_object_with_etag(&self, key: &str, bucket: &str) -> Result<(Vec<u8>, Option<String>), S3Error> {
+        let resp = self.client
+            .get_object()
+            .bucket(bucket)
+            .key(key)
+            .send()
+            .await?;
+
+        let etag = resp.e_tag().map(|e| e.to_string());
+        let data = resp.body.collect().await?;
+        Ok((data.to_vec(), etag))
+    }
+
+    /// Opens an object for streaming reads.
+    ///
+    /// Unlike `get_object`, the data is not collected into memory; the
+    /// returned stream yields it chunk by chunk as it arrives from S3.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key (path) of the object
+    /// * `bucket` - The name of the bucket containing the object
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(ByteStream)` - The data of the object
+    /// * `Err(S3Error)` - If there was an error opening the object
+    pub async fn get_object_stream(&self, key: &str, bucket: &str) -> Result<ByteStream, S3Error> {
+        let resp = self.client
+            .get_object()
+            .bucket(bucket)
+            .key(key)
+            .send()
+            .await?;
+
+        Ok(resp.body)
+    }
+
+    /// Downloads a byte range of an object.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key (path) of the object
+    /// * `bucket` - The name of the bucket containing the object
+    /// * `start` - The offset of the first byte to download
+    /// * `length` - The number of bytes to download
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<u8>)` - The requested bytes, fewer if the object ends before the range does
+    /// * `Err(S3Error)` - If there was an error downloading the range
+    pub async fn get_object_range(&self, key: &str, bucket: &str, start: u64, length: u64) -> Result<Vec<u8>, S3Error> {
+        let data = self.get_object_range_stream(key, bucket, start, length).await?.collect().await?;
+        Ok(data.to_vec())
+    }
+
+    /// Opens a byte range of an object for streaming reads.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key (path) of the object
+    /// * `bucket` - The name of the bucket containing the object
+    /// * `start` - The offset of the first byte to download
+    /// * `length` - The number of bytes to download
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(ByteStream)` - The requested bytes, fewer if the object ends before the range does
+    /// * `Err(S3Error)` - If the range ends beyond the largest possible offset, or there was an error opening it
+    pub async fn get_object_range_stream(&self, key: &str, bucket: &str, start: u64, length: u64) -> Result<ByteStream, S3Error> {
+        if length == 0 {
+            return Ok(ByteStream::from_static(&[]));
+        }
+        let Some(end) = start.checked_add(length - 1) else {
+            return Err(S3Error::InvalidArgument(format!("The range of {} bytes at offset {} is out of bounds", length, start)));
+        };
+
+        let resp = self.client
+            .get_object()
+            .bucket(bucket)
+            .key(key)
+            .range(format!("bytes={}-{}", start, end))
+            .send()
+            .await?;
+
+        Ok(resp.body)
+    }
+}
@@ -0,0 +1,45 @@
+//! # Object Metadata
+//!
+//! This module provides functionality for reading the metadata of objects
+//! without downloading their data.
+
+use chrono::{DateTime, Utc};
+
+use crate::rdlib::s3::error::S3Error;
+use crate::rdlib::s3::service::S3Service;
+use crate::rdlib::s3::types::ObjectHead;
+
+impl S3Service {
+    /// Reads the metadata of an object.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key (path) of the object
+    /// * `bucket` - The name of the bucket containing the object
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some(ObjectHead))` - The metadata of the object
+    /// * `Ok(None)` - If the object does not exist
+    /// * `Err(S3Error)` - If there was an error reading the metadata
+    pub async fn head_object(&self, key: &str, bucket: &str) -> Result<Option<ObjectHead>, S3Error> {
+        match self.client
+            .head_object()
+            .bucket(bucket)
+            .key(key)
+            .send()
+            .await
+        {
+            Ok(resp) => Ok(Some(ObjectHead {
+                etag: resp.e_tag().map(|e| e.to_string()),
+                size: resp.content_length().unwrap_or(0).max(0) as u64,
+                content_type: resp.content_type().map(|c| c.to_string()),
+                last_modified: resp.last_modified()
+                    .and_then(|dt| DateTime::<Utc>::from_timestamp(dt.secs(), dt.subsec_nanos()))
+                    .map(|dt| dt.to_rfc3339()),
+            })),
+            Err(aws_sdk_s3::error::SdkError::ServiceError(context)) if context.err().is_not_found() => Ok(None),
+            Err(e) => Err(S3Error::from(e)),
+        }
+    }
+}
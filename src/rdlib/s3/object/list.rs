@@ -9,6 +9,7 @@ use chrono::{DateTime, Utc};
 use crate::rdlib::s3::error::S3Error;
 use crate::rdlib::s3::service::S3Service;
 use crate::rdlib::s3::types::S3Object;
+use crate::rdlib::s3::system::SYSTEM_PREFIX;
 use log::info;
 
 impl S3Service {
@@ -50,12 +51,18 @@ impl S3Service {
         info!("Found {} common prefixes (folders)", prefixes.len());
         for prefix_obj in prefixes {
             if let Some(prefix_str) = prefix_obj.prefix() {
+                // The system prefix holds RustDok metadata and is never listed
+                if prefix_str == SYSTEM_PREFIX {
+                    continue;
+                }
+
                 info!("Processing folder: {}", prefix_str);
                 
                 files.push(S3Object {
                     name: prefix_str.to_string(),
                     size: 0, 
                     last_modified: None, 
+                    etag: None,
                 });
             }
         }
@@ -89,6 +96,7 @@ impl S3Service {
                 name: key,
                 size,
                 last_modified,
+                etag: obj.e_tag().map(|e| e.to_string()),
             });
         }
 
@@ -116,4 +124,71 @@ impl S3Service {
         
         self.list_objects_in_bucket_with_prefix(bucket, prefix_to_use).await
     }
-} 
\ No newline at end of file
+
+    /// Lists all objects under a prefix, including those in nested folders.
+    ///
+    /// Unlike `list_objects`, this method does not group objects into folders
+    /// and follows continuation tokens until the listing is complete or
+    /// `limit` objects have been found. Objects under the hidden system prefix
+    /// are skipped. Folder placeholder objects (keys ending with a slash) are
+    /// included.
+    ///
+    /// # Arguments
+    ///
+    /// * `prefix` - The prefix to list objects under, empty for the whole bucket
+    /// * `bucket` - The name of the bucket to list objects from
+    /// * `limit` - The most objects to return
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<S3Object>)` - At most `limit` objects under the prefix
+    /// * `Err(S3Error)` - If there was an error listing the objects
+    pub async fn list_all_objects(&self, prefix: &str, bucket: &str, limit: usize) -> Result<Vec<S3Object>, S3Error> {
+        info!("Fetching all objects from bucket: {} with prefix: '{}'", bucket, prefix);
+
+        let mut files = Vec::new();
+        let mut continuation_token: Option<String> = None;
+
+        loop {
+            let mut req = self.client.list_objects_v2().bucket(bucket);
+            if !prefix.is_empty() {
+                req = req.prefix(prefix);
+            }
+            if let Some(token) = &continuation_token {
+                req = req.continuation_token(token);
+            }
+
+            let resp = req.send().await?;
+
+            for obj in resp.contents() {
+                let key = obj.key().unwrap_or_default();
+                if Self::is_system_key(key) {
+                    continue;
+                }
+
+                let last_modified = obj.last_modified()
+                    .and_then(|dt| DateTime::<Utc>::from_timestamp(dt.secs(), dt.subsec_nanos()))
+                    .map(|dt| dt.to_rfc3339());
+
+                files.push(S3Object {
+                    name: key.to_string(),
+                    size: obj.size().unwrap_or(0).max(0) as u64,
+                    last_modified,
+                    etag: obj.e_tag().map(|e| e.to_string()),
+                });
+                if files.len() >= limit {
+                    info!("Stopped listing prefix '{}' at {} objects", prefix, limit);
+                    return Ok(files);
+                }
+            }
+
+            match resp.next_continuation_token() {
+                Some(token) if resp.is_truncated().unwrap_or(false) => continuation_token = Some(token.to_string()),
+                _ => break,
+            }
+        }
+
+        info!("Found {} objects under prefix '{}'", files.len(), prefix);
+        Ok(files)
+    }
+}
@@ -0,0 +1,159 @@
+//! # Multipart Uploads
+//!
+//! This module provides functionality for uploading objects in parts.
+//! Parts can be uploaded over any period of time; the object only appears
+//! in the bucket once the upload is completed.
+
+use aws_sdk_s3::types::{CompletedMultipartUpload, CompletedPart};
+use bytes::Bytes;
+
+use crate::rdlib::s3::error::S3Error;
+use crate::rdlib::s3::object::conditional::is_precondition_failure;
+use crate::rdlib::s3::service::S3Service;
+use crate::rdlib::s3::types::{UploadedPart, WriteOptions};
+
+impl S3Service {
+    /// Starts a multipart upload.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key (path) of the object to create
+    /// * `bucket` - The name of the bucket to upload to
+    /// * `content_type` - The content type to store with the object
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(String)` - The ID of the multipart upload
+    /// * `Err(S3Error)` - If there was an error starting the upload
+    pub async fn create_multipart_upload(&self, key: &str, bucket: &str, content_type: &str) -> Result<String, S3Error> {
+        let resp = self.client
+            .create_multipart_upload()
+            .bucket(bucket)
+            .key(key)
+            .content_type(content_type)
+            .send()
+            .await?;
+
+        resp.upload_id()
+            .map(|id| id.to_string())
+            .ok_or_else(|| S3Error::Other(format!("No upload ID returned for '{}'", key)))
+    }
+
+    /// Uploads one part of a multipart upload.
+    ///
+    /// Every part except the last must be at least 5 MiB.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key (path) of the object
+    /// * `bucket` - The name of the bucket
+    /// * `upload_id` - The ID of the multipart upload
+    /// * `part_number` - The number of the part, starting at 1
+    /// * `data` - The data of the part
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(UploadedPart)` - The uploaded part, needed to complete the upload
+    /// * `Err(S3Error)` - If there was an error uploading the part
+    pub async fn upload_part(
+        &self,
+        key: &str,
+        bucket: &str,
+        upload_id: &str,
+        part_number: i32,
+        data: Bytes
+    ) -> Result<UploadedPart, S3Error> {
+        let size = data.len() as u64;
+        let resp = self.client
+            .upload_part()
+            .bucket(bucket)
+            .key(key)
+            .upload_id(upload_id)
+            .part_number(part_number)
+            .body(data.into())
+            .send()
+            .await?;
+
+        Ok(UploadedPart {
+            part_number,
+            etag: resp.e_tag().unwrap_or_default().to_string(),
+            size,
+        })
+    }
+
+    /// Completes a multipart upload if its preconditions hold, creating the
+    /// object from its parts.
+    ///
+    /// Only the preconditions of the options apply; the content type is set
+    /// when the upload is started.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key (path) of the object
+    /// * `bucket` - The name of the bucket
+    /// * `upload_id` - The ID of the multipart upload
+    /// * `parts` - The uploaded parts, in order
+    /// * `options` - The preconditions of the write
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some(String))` - The ETag of the created object
+    /// * `Ok(None)` - If a precondition did not hold; the upload is left open
+    /// * `Err(S3Error)` - If there was an error completing the upload
+    pub async fn complete_multipart_upload_with_options(
+        &self,
+        key: &str,
+        bucket: &str,
+        upload_id: &str,
+        parts: &[UploadedPart],
+        options: &WriteOptions
+    ) -> Result<Option<String>, S3Error> {
+        let Some((if_match, if_none_match)) = self.write_condition_headers(key, bucket, &options.preconditions).await? else {
+            return Ok(None);
+        };
+        let parts = parts
+            .iter()
+            .map(|part| CompletedPart::builder().part_number(part.part_number).e_tag(&part.etag).build())
+            .collect();
+
+        match self.client
+            .complete_multipart_upload()
+            .bucket(bucket)
+            .key(key)
+            .upload_id(upload_id)
+            .multipart_upload(CompletedMultipartUpload::builder().set_parts(Some(parts)).build())
+            .set_if_match(if_match)
+            .set_if_none_match(if_none_match)
+            .send()
+            .await
+        {
+            Ok(resp) => Ok(Some(resp.e_tag().unwrap_or_default().to_string())),
+            Err(e) if is_precondition_failure(&e, &options.preconditions) => Ok(None),
+            Err(e) => Err(S3Error::from(e)),
+        }
+    }
+
+    /// Aborts a multipart upload and discards its parts.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key (path) of the object
+    /// * `bucket` - The name of the bucket
+    /// * `upload_id` - The ID of the multipart upload
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the upload was aborted
+    /// * `Err(S3Error)` - If there was an error aborting the upload
+    pub async fn abort_multipart_upload(&self, key: &str, bucket: &str, upload_id: &str) -> Result<(), S3Error> {
+        self.client
+            .abort_multipart_upload()
+            .bucket(bucket)
+            .key(key)
+            .upload_id(upload_id)
+            .send()
+            .await?;
+
+        Ok(())
+    }
+}
@@ -1,10 +1,15 @@
 //! # Object Upload
 //! 
 //! This module provides functionality for uploading objects to S3 buckets.
-//! It includes methods for putting objects in specific buckets or in the default bucket.
+//! It includes methods for putting objects in specific buckets or in the default bucket,
+//! and for conditional puts that never overwrite existing objects.
+
+use bytes::Bytes;
 
 use crate::rdlib::s3::error::S3Error;
+use crate::rdlib::s3::object::conditional::is_precondition_failure;
 use crate::rdlib::s3::service::S3Service;
+use crate::rdlib::s3::types::{Preconditions, WriteOptions};
 
 impl S3Service {
     /// Uploads an object to a specific bucket.
@@ -48,4 +53,71 @@ impl S3Service {
     pub async fn put_object(&self, key: &str, data: Vec<u8>, bucket: &str) -> Result<(), S3Error> {
         self.put_object_in_bucket(bucket, key, data).await
     }
-}
\ No newline at end of file
+
+    /// Uploads an object only if no object with the same key exists.
+    ///
+    /// The check is done by S3 with an `If-None-Match: *` condition, so it is
+    /// atomic: of two concurrent uploads to the same key, only one succeeds.
+    /// Without conditional writes, see `S3_CONDITIONAL_WRITES`, it is best effort.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key (path) of the object
+    /// * `data` - The binary data of the object
+    /// * `bucket` - The name of the bucket to upload to
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(true)` - If the object was uploaded
+    /// * `Ok(false)` - If an object with the key already exists
+    /// * `Err(S3Error)` - If there was an error uploading the object
+    pub async fn put_object_if_absent(&self, key: &str, data: Bytes, bucket: &str) -> Result<bool, S3Error> {
+        let options = WriteOptions { preconditions: Preconditions::create_only(), ..Default::default() };
+        Ok(self.put_object_with_options(key, bucket, data, &options).await?.is_some())
+    }
+
+    /// Uploads an object with a content type, integrity check and write conditions.
+    ///
+    /// The preconditions are enforced as described in the `conditional` module.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key (path) of the object
+    /// * `bucket` - The name of the bucket to upload to
+    /// * `data` - The binary data of the object
+    /// * `options` - The content type, MD5 digest and conditions of the write
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some(String))` - The ETag of the uploaded object
+    /// * `Ok(None)` - If a precondition did not hold
+    /// * `Err(S3Error)` - If there was an error uploading the object
+    pub async fn put_object_with_options(
+        &self,
+        key: &str,
+        bucket: &str,
+        data: Bytes,
+        options: &WriteOptions
+    ) -> Result<Option<String>, S3Error> {
+        let Some((if_match, if_none_match)) = self.write_condition_headers(key, bucket, &options.preconditions).await? else {
+            return Ok(None);
+        };
+
+        match self.client
+            .put_object()
+            .bucket(bucket)
+            .key(key)
+            .set_content_type(options.content_type.clone())
+            .set_content_md5(options.content_md5.clone())
+            .set_if_match(if_match)
+            .set_if_none_match(if_none_match)
+            .body(data.into())
+            .send()
+            .await
+        {
+            Ok(resp) => Ok(Some(resp.e_tag().unwrap_or_default().to_string())),
+            Err(e) if is_precondition_failure(&e, &options.preconditions) => Ok(None),
+            Err(e) => Err(S3Error::from(e)),
+        }
+    }
+}
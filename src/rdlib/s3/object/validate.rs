@@ -1,11 +1,17 @@
 //! # Object Validation
 //! 
 //! This module provides functionality for validating S3 objects.
-//! It includes methods for checking if objects exist in buckets.
+//! It includes methods for checking if objects exist in buckets, and for
+//! finding free keys when names conflict.
+
+use bytes::Bytes;
 
 use crate::rdlib::s3::error::S3Error;
 use crate::rdlib::s3::service::S3Service;
 
+/// The maximum number of numbered variants tried when looking for a free key
+const MAX_RENAME_ATTEMPTS: u32 = 1000;
+
 impl S3Service {
     /// Checks if an object exists in a specific bucket.
     ///
@@ -31,18 +37,9 @@ impl S3Service {
             .await
         {
             Ok(_) => Ok(true),
-            Err(e) => {
-                // Check if the error is a 404 Not Found
-                match e {
-                    aws_sdk_s3::error::SdkError::ServiceError(context) => {
-                        if context.err().is_not_found() {
-                            return Ok(false);
-                        }
-                        // Create a new error from the context instead of using 'e'
-                        Err(S3Error::from(aws_sdk_s3::error::SdkError::ServiceError(context)))
-                    },
-                    other_error => Err(S3Error::from(other_error)),
-                }
+            Err(e) => match S3Error::from(e) {
+                S3Error::NotFound(_) => Ok(false),
+                e => Err(e),
             }
         }
     }
@@ -65,4 +62,64 @@ impl S3Service {
     pub async fn check_object_exists(&self, key: &str, bucket: &str) -> Result<bool, S3Error> {
         self.check_object_exists_in_bucket(bucket, key).await
     }
+
+    /// Builds the numbered variant of a key used to avoid name conflicts.
+    ///
+    /// The number is inserted before the extension of the file name, so
+    /// `docs/report.pdf` becomes `docs/report (1).pdf`. Files without an
+    /// extension, and dotfiles such as `.env`, get the number appended.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The original key of the object
+    /// * `n` - The number to insert
+    ///
+    /// # Returns
+    ///
+    /// The numbered key
+    pub fn numbered_key(key: &str, n: u32) -> String {
+        let (dir, filename) = match key.rfind('/') {
+            Some(idx) => key.split_at(idx + 1),
+            None => ("", key),
+        };
+
+        match filename.rfind('.') {
+            Some(idx) if idx > 0 => {
+                let (stem, ext) = filename.split_at(idx);
+                format!("{}{} ({}){}", dir, stem, n, ext)
+            },
+            _ => format!("{}{} ({})", dir, filename, n),
+        }
+    }
+
+    /// Uploads an object under the first free variant of its key.
+    ///
+    /// Free keys are probed with HEAD requests, so the body is only sent for
+    /// a key that looked free. The upload itself is a conditional put, so
+    /// concurrent uploads of the same name never overwrite each other: the
+    /// body is sent again, to the next free key, only if the put fails
+    /// because another upload took the key in the meantime.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The desired key of the object
+    /// * `data` - The binary data of the object
+    /// * `bucket` - The name of the bucket to upload to
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(String)` - The key the object was stored under
+    /// * `Err(S3Error)` - If there was an error uploading the object, or no free key was found
+    pub async fn put_object_with_available_key(&self, key: &str, data: Bytes, bucket: &str) -> Result<String, S3Error> {
+        for n in 0..=MAX_RENAME_ATTEMPTS {
+            let candidate = if n == 0 { key.to_string() } else { Self::numbered_key(key, n) };
+            if !self.check_object_exists(&candidate, bucket).await?
+                && self.put_object_if_absent(&candidate, data.clone(), bucket).await?
+            {
+                return Ok(candidate);
+            }
+        }
+
+        Err(S3Error::Other(format!("No free name found for '{}' after {} attempts", key, MAX_RENAME_ATTEMPTS)))
+    }
 }
@@ -0,0 +1,312 @@
+//! # Upload Policies
+//!
+//! This module provides functionality for per-bucket upload policies.
+//! A policy limits the size, content type, extension and key of the objects
+//! uploaded into a bucket. Policies are stored as system objects in the
+//! bucket they apply to.
+
+use std::fmt;
+
+use crate::rdlib::content::{sniff_content_type, types_match};
+use crate::rdlib::s3::error::S3Error;
+use crate::rdlib::s3::service::S3Service;
+use crate::rdlib::s3::types::UploadPolicy;
+use log::info;
+
+/// The name of the system object holding a bucket's upload policy
+const POLICY_OBJECT: &str = "policy.json";
+
+/// A reason an upload was rejected by a bucket's upload policy.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PolicyViolation {
+    /// The object is larger than the policy allows
+    TooLarge(u64),
+    /// The content type or extension of the object is not allowed
+    UnsupportedType(String),
+    /// The key of the object does not match the required prefix pattern
+    KeyNotAllowed(String),
+}
+
+impl fmt::Display for PolicyViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PolicyViolation::TooLarge(max) => write!(f, "Object exceeds the maximum size of {} bytes allowed in this bucket", max),
+            PolicyViolation::UnsupportedType(msg) => write!(f, "{}", msg),
+            PolicyViolation::KeyNotAllowed(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+/// Checks if a content type matches a pattern such as `image/png`, `image/*` or `*/*`.
+fn content_type_matches(pattern: &str, content_type: &str) -> bool {
+    let pattern = pattern.trim().to_lowercase();
+    match pattern.split_once('/') {
+        Some(("*", "*")) => true,
+        Some((kind, "*")) => content_type.split_once('/').is_some_and(|(k, _)| k == kind),
+        _ => pattern == content_type,
+    }
+}
+
+/// The lowercase essence of a declared content type, or `None` if it is
+/// missing or generic (`application/octet-stream`).
+fn specific_content_type(declared: &str) -> Option<String> {
+    Some(declared.split(';').next().unwrap_or_default().trim().to_lowercase())
+        .filter(|t| !t.is_empty() && t != "application/octet-stream")
+}
+
+/// Checks if a key starts with a match of a prefix pattern.
+///
+/// `*` matches any run of characters within a single path segment.
+fn key_matches_prefix_pattern(pattern: &str, key: &str) -> bool {
+    let pattern = pattern.trim_start_matches('/');
+    let (pattern, key) = (pattern.as_bytes(), key.as_bytes());
+
+    fn matches(pattern: &[u8], key: &[u8]) -> bool {
+        match pattern.split_first() {
+            None => true,
+            Some((b'*', rest)) => {
+                // Try every possible end of the segment matched by the wildcard
+                let segment_len = key.iter().position(|&c| c == b'/').unwrap_or(key.len());
+                (0..=segment_len).any(|n| matches(rest, &key[n..]))
+            },
+            Some((c, rest)) => key.first() == Some(c) && matches(rest, &key[1..]),
+        }
+    }
+
+    matches(pattern, key)
+}
+
+impl UploadPolicy {
+    /// Normalizes the policy's lists so they can be compared case-insensitively.
+    ///
+    /// # Returns
+    ///
+    /// The normalized policy
+    pub fn normalized(mut self) -> Self {
+        let lower = |list: Option<Vec<String>>| list.map(|l| {
+            l.into_iter()
+                .map(|v| v.trim().trim_start_matches('.').to_lowercase())
+                .filter(|v| !v.is_empty())
+                .collect()
+        });
+        self.allowed_content_types = lower(self.allowed_content_types);
+        self.denied_content_types = lower(self.denied_content_types);
+        self.allowed_extensions = lower(self.allowed_extensions);
+        self.denied_extensions = lower(self.denied_extensions);
+        self
+    }
+
+    /// Validates the policy itself.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the policy is valid
+    /// * `Err(String)` - A description of why the policy is invalid
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_object_size == Some(0) {
+            return Err("max_object_size must be greater than zero".to_string());
+        }
+        for content_type in self.allowed_content_types.iter().chain(self.denied_content_types.iter()).flatten() {
+            if !content_type.contains('/') {
+                return Err(format!("Invalid content type '{}'. Expected a type like 'image/png' or 'image/*'", content_type));
+            }
+        }
+        if let Some(pattern) = &self.key_prefix_pattern
+            && (pattern.split('/').any(|s| s == "..") || S3Service::is_system_key(pattern))
+        {
+            return Err(format!("Invalid key prefix pattern '{}'", pattern));
+        }
+        Ok(())
+    }
+
+    /// Checks if an object key is allowed by the policy.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key of the object to upload
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the key is allowed
+    /// * `Err(PolicyViolation)` - If the key does not match the prefix pattern
+    pub fn check_key(&self, key: &str) -> Result<(), PolicyViolation> {
+        match &self.key_prefix_pattern {
+            Some(pattern) if !key_matches_prefix_pattern(pattern, key) => Err(PolicyViolation::KeyNotAllowed(
+                format!("Key '{}' does not match the required prefix pattern '{}'", key, pattern)
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks if a file is allowed by the policy's content type and extension lists.
+    ///
+    /// Both the content type declared by the client and the type guessed
+    /// from the file name are checked, so a renamed file cannot slip past
+    /// the lists: neither may be denied, and both must be allowed. A missing
+    /// or generic declared type (`application/octet-stream`) is not checked.
+    ///
+    /// # Arguments
+    ///
+    /// * `filename` - The name of the file to upload
+    /// * `declared_type` - The content type declared by the client, if any
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the file is allowed
+    /// * `Err(PolicyViolation)` - If a content type or the extension is not allowed
+    pub fn check_file(&self, filename: &str, declared_type: Option<&str>) -> Result<(), PolicyViolation> {
+        let extension = filename
+            .rsplit_once('.')
+            .map(|(_, ext)| ext.to_lowercase())
+            .unwrap_or_default();
+
+        let mut content_types = Vec::new();
+        if let Some(declared) = declared_type.and_then(specific_content_type) {
+            content_types.push(declared);
+        }
+        if let Some(guessed) = mime_guess::from_path(filename).first() {
+            content_types.push(guessed.essence_str().to_string());
+        }
+        if content_types.is_empty() {
+            content_types.push("application/octet-stream".to_string());
+        }
+        for content_type in &content_types {
+            self.check_content_type(content_type)?;
+        }
+
+        if let Some(denied) = &self.denied_extensions
+            && denied.contains(&extension)
+        {
+            return Err(PolicyViolation::UnsupportedType(format!("Files with extension '{}' are not allowed in this bucket", extension)));
+        }
+        if let Some(allowed) = &self.allowed_extensions
+            && !allowed.contains(&extension)
+        {
+            return Err(PolicyViolation::UnsupportedType(format!(
+                "File '{}' is not allowed in this bucket. Allowed extensions: {}", filename, allowed.join(", ")
+            )));
+        }
+        Ok(())
+    }
+
+    /// Checks the content of a file against the policy's content type lists.
+    ///
+    /// The type is sniffed from the first bytes of the data. A sniffed type
+    /// must not be denied; it must be allowed unless it is consistent with
+    /// the type of the file name, such as a ZIP container for an Office
+    /// document. Data without a recognizable signature is not checked.
+    ///
+    /// # Arguments
+    ///
+    /// * `filename` - The name of the file to upload
+    /// * `data` - The data of the file, or at least its first `SNIFF_LENGTH` bytes
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the content is allowed
+    /// * `Err(PolicyViolation)` - If the content is of a type that is not allowed
+    pub fn check_content(&self, filename: &str, data: &[u8]) -> Result<(), PolicyViolation> {
+        let Some(sniffed) = sniff_content_type(data) else {
+            return Ok(());
+        };
+        if let Some(denied) = &self.denied_content_types
+            && denied.iter().any(|p| content_type_matches(p, &sniffed))
+        {
+            return Err(PolicyViolation::UnsupportedType(format!(
+                "The content of '{}' is of type '{}', which is not allowed in this bucket", filename, sniffed
+            )));
+        }
+        let named_type = mime_guess::from_path(filename).first();
+        let consistent = named_type.is_some_and(|named| types_match(named.essence_str(), &sniffed));
+        if let Some(allowed) = &self.allowed_content_types
+            && !consistent
+            && !allowed.iter().any(|p| content_type_matches(p, &sniffed))
+        {
+            return Err(PolicyViolation::UnsupportedType(format!(
+                "The content of '{}' is of type '{}', which is not allowed in this bucket. Allowed content types: {}",
+                filename, sniffed, allowed.join(", ")
+            )));
+        }
+        Ok(())
+    }
+
+    /// Checks a content type against the policy's content type lists.
+    fn check_content_type(&self, content_type: &str) -> Result<(), PolicyViolation> {
+        if let Some(denied) = &self.denied_content_types
+            && denied.iter().any(|p| content_type_matches(p, content_type))
+        {
+            return Err(PolicyViolation::UnsupportedType(format!("Content type '{}' is not allowed in this bucket", content_type)));
+        }
+        if let Some(allowed) = &self.allowed_content_types
+            && !allowed.iter().any(|p| content_type_matches(p, content_type))
+        {
+            return Err(PolicyViolation::UnsupportedType(format!(
+                "Content type '{}' is not allowed in this bucket. Allowed content types: {}", content_type, allowed.join(", ")
+            )));
+        }
+        Ok(())
+    }
+
+    /// Combines the policy's size limit with another optional limit.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - Another size limit, e.g. the one of a drop link
+    ///
+    /// # Returns
+    ///
+    /// The stricter of both limits
+    pub fn size_limit(&self, other: Option<u64>) -> Option<u64> {
+        match (self.max_object_size, other) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+}
+
+impl S3Service {
+    /// Reads the upload policy of a bucket.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket` - The name of the bucket
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(UploadPolicy)` - The policy of the bucket, or an empty policy if none is set
+    /// * `Err(S3Error)` - If there was an error reading the policy
+    pub async fn get_upload_policy(&self, bucket: &str) -> Result<UploadPolicy, S3Error> {
+        Ok(self.get_system_object(bucket, POLICY_OBJECT).await?.unwrap_or_default())
+    }
+
+    /// Stores the upload policy of a bucket, replacing any existing policy.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket` - The name of the bucket
+    /// * `policy` - The policy to store
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the policy was stored successfully
+    /// * `Err(S3Error)` - If there was an error storing the policy
+    pub async fn put_upload_policy(&self, bucket: &str, policy: &UploadPolicy) -> Result<(), S3Error> {
+        info!("Updating upload policy of bucket {}", bucket);
+        self.put_system_object(bucket, POLICY_OBJECT, policy).await
+    }
+
+    /// Removes the upload policy of a bucket.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket` - The name of the bucket
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the policy was removed
+    /// * `Err(S3Error)` - If there was an error removing the policy
+    pub async fn delete_upload_policy(&self, bucket: &str) -> Result<(), S3Error> {
+        info!("Removing upload policy of bucket {}", bucket);
+        self.delete_system_object(bucket, POLICY_OBJECT).await
+    }
+}
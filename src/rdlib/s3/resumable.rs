@@ -0,0 +1,350 @@
+//! # Resumable Uploads
+//!
+//! This module provides the state of resumable (tus) uploads. Each upload
+//! is backed by an S3 multipart upload; its progress is stored as a system
+//! object in the target bucket, together with the received data that does
+//! not fill a part yet. Uploads survive server restarts and expire if they
+//! are not completed in time.
+//!
+//! The state is only ever replaced with a conditional write against the
+//! ETag it was read with, so of several servers receiving data for the same
+//! upload only one can hold it at a time.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use chrono::{DateTime, Duration, Utc};
+use log::{info, warn};
+use uuid::Uuid;
+
+use crate::rdlib::s3::error::S3Error;
+use crate::rdlib::s3::service::S3Service;
+use crate::rdlib::s3::types::{Preconditions, ResumableUpload};
+
+/// The system folder the state of resumable uploads is stored in
+const UPLOAD_FOLDER: &str = "uploads/";
+
+/// The part size used unless the upload is too large for it; S3 requires
+/// at least 5 MiB for every part but the last
+pub const DEFAULT_PART_SIZE: u64 = 8 * 1024 * 1024;
+
+/// The maximum number of parts of an S3 multipart upload
+pub const MAX_PARTS: u64 = 10_000;
+
+/// The maximum size of a resumable upload in bytes
+pub const MAX_UPLOAD_SIZE: u64 = 100 * 1024 * 1024 * 1024;
+
+/// How long an upload may take before it expires
+pub const UPLOAD_LIFETIME_HOURS: i64 = 24;
+
+/// How long a request holds an upload without saving progress; a server
+/// that fails while holding it blocks the upload for at most this long
+pub const UPLOAD_LOCK_SECONDS: i64 = 300;
+
+/// How often expired uploads are removed
+pub const UPLOAD_SWEEP_INTERVAL_SECONDS: u64 = 3600;
+
+impl ResumableUpload {
+    /// Creates a new upload with a fresh random ID.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket` - The bucket the object is uploaded to
+    /// * `key` - The key the object is stored under
+    /// * `content_type` - The content type of the object
+    /// * `multipart_id` - The ID of the S3 multipart upload backing the upload
+    /// * `length` - The total size of the object in bytes
+    /// * `metadata` - The metadata sent by the client
+    ///
+    /// # Returns
+    ///
+    /// A new `ResumableUpload` expiring after `UPLOAD_LIFETIME_HOURS`
+    pub fn new(
+        bucket: &str,
+        key: &str,
+        content_type: &str,
+        multipart_id: &str,
+        length: u64,
+        metadata: BTreeMap<String, String>
+    ) -> Self {
+        let now = Utc::now();
+        Self {
+            id: Uuid::new_v4().simple().to_string(),
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+            content_type: content_type.to_string(),
+            multipart_id: multipart_id.to_string(),
+            length,
+            part_size: Self::part_size_for(length),
+            parts: Vec::new(),
+            pending_size: 0,
+            metadata,
+            created_at: now,
+            expires_at: now + Duration::hours(UPLOAD_LIFETIME_HOURS),
+            locked_until: None,
+            state_etag: None,
+        }
+    }
+
+    /// Chooses the part size for an upload, so it needs at most `MAX_PARTS` parts.
+    ///
+    /// # Arguments
+    ///
+    /// * `length` - The total size of the upload in bytes
+    ///
+    /// # Returns
+    ///
+    /// The part size in bytes, at least `DEFAULT_PART_SIZE`
+    pub fn part_size_for(length: u64) -> u64 {
+        DEFAULT_PART_SIZE.max(length.div_ceil(MAX_PARTS))
+    }
+
+    /// The number of bytes received so far, which is where the client resumes.
+    pub fn offset(&self) -> u64 {
+        self.parts.iter().map(|part| part.size).sum::<u64>() + self.pending_size
+    }
+
+    /// Checks if the upload has expired.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The current time
+    ///
+    /// # Returns
+    ///
+    /// `true` if the upload's expiry lies in the past
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at <= now
+    }
+
+    /// Checks if a request holds the upload to append data.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The current time
+    ///
+    /// # Returns
+    ///
+    /// `true` if the upload is locked and the lock has not run out
+    pub fn is_locked(&self, now: DateTime<Utc>) -> bool {
+        self.locked_until.is_some_and(|until| until > now)
+    }
+
+    /// Locks the upload, or extends the lock, for `UPLOAD_LOCK_SECONDS`.
+    ///
+    /// The lock only takes effect once the upload is saved.
+    pub fn lock(&mut self, now: DateTime<Utc>) {
+        self.locked_until = Some(now + Duration::seconds(UPLOAD_LOCK_SECONDS));
+    }
+
+    fn state_name(id: &str) -> String {
+        format!("{}{}.json", UPLOAD_FOLDER, id)
+    }
+
+    fn pending_name(id: &str) -> String {
+        format!("{}{}.pending", UPLOAD_FOLDER, id)
+    }
+}
+
+impl S3Service {
+    /// Stores the state of a resumable upload, creating or replacing it.
+    ///
+    /// New uploads are only created if the ID is free, and existing ones
+    /// only replaced if the stored state still has the ETag the upload was
+    /// read with. The upload takes the ETag of the new state.
+    ///
+    /// # Arguments
+    ///
+    /// * `upload` - The upload to store
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the state was stored successfully
+    /// * `Err(S3Error::PreconditionFailed)` - If the state was changed by another request
+    /// * `Err(S3Error)` - If there was an error storing the state
+    pub async fn save_resumable_upload(&self, upload: &mut ResumableUpload) -> Result<(), S3Error> {
+        let preconditions = match &upload.state_etag {
+            Some(etag) => Preconditions { if_match: Some(vec![etag.clone()]), if_none_match: None },
+            None => Preconditions::create_only(),
+        };
+
+        match self.put_system_object_if(&upload.bucket, &ResumableUpload::state_name(&upload.id), upload, &preconditions).await? {
+            Some(etag) => {
+                upload.state_etag = Some(etag);
+                Ok(())
+            },
+            None => Err(S3Error::PreconditionFailed(format!("Upload {} was changed by another request", upload.id))),
+        }
+    }
+
+    /// Locks a resumable upload for the current request.
+    ///
+    /// # Arguments
+    ///
+    /// * `upload` - The upload, as read from storage
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(true)` - If the upload is now locked by this request
+    /// * `Ok(false)` - If another request holds the upload
+    /// * `Err(S3Error)` - If there was an error storing the lock
+    pub async fn lock_resumable_upload(&self, upload: &mut ResumableUpload) -> Result<bool, S3Error> {
+        let now = Utc::now();
+        if upload.is_locked(now) {
+            return Ok(false);
+        }
+
+        upload.lock(now);
+        match self.save_resumable_upload(upload).await {
+            Ok(()) => Ok(true),
+            Err(S3Error::PreconditionFailed(_)) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Looks up a resumable upload by its ID.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket` - The bucket the upload targets
+    /// * `id` - The ID of the upload
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some(ResumableUpload))` - If the upload exists
+    /// * `Ok(None)` - If there is no upload with this ID
+    /// * `Err(S3Error)` - If there was an error reading the upload
+    pub async fn get_resumable_upload(&self, bucket: &str, id: &str) -> Result<Option<ResumableUpload>, S3Error> {
+        // IDs are plain hex strings; anything else cannot be a valid upload
+        if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Ok(None);
+        }
+
+        let upload = self.get_system_object_with_etag::<ResumableUpload>(bucket, &ResumableUpload::state_name(id)).await?;
+        Ok(upload.map(|(mut upload, etag)| {
+            upload.state_etag = etag;
+            upload
+        }))
+    }
+
+    /// Reads the data of an upload that has not been uploaded as a part yet.
+    ///
+    /// # Arguments
+    ///
+    /// * `upload` - The upload
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<u8>)` - The pending data, `pending_size` bytes long
+    /// * `Err(S3Error)` - If there was an error reading the data, or it is incomplete
+    pub async fn get_pending_upload_data(&self, upload: &ResumableUpload) -> Result<Vec<u8>, S3Error> {
+        if upload.pending_size == 0 {
+            return Ok(Vec::new());
+        }
+
+        let key = Self::system_key(&ResumableUpload::pending_name(&upload.id));
+        let mut data = self.get_object(&key, &upload.bucket).await?;
+        if (data.len() as u64) < upload.pending_size {
+            return Err(S3Error::Other(format!("Pending data of upload {} is incomplete", upload.id)));
+        }
+        data.truncate(upload.pending_size as usize);
+        Ok(data)
+    }
+
+    /// Stores the data of an upload that does not fill a part yet.
+    ///
+    /// The caller must save the upload with the new `pending_size` afterwards.
+    ///
+    /// # Arguments
+    ///
+    /// * `upload` - The upload
+    /// * `data` - The pending data
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the data was stored successfully
+    /// * `Err(S3Error)` - If there was an error storing the data
+    pub async fn put_pending_upload_data(&self, upload: &ResumableUpload, data: Bytes) -> Result<(), S3Error> {
+        let key = Self::system_key(&ResumableUpload::pending_name(&upload.id));
+        self.put_object(&key, data.to_vec(), &upload.bucket).await
+    }
+
+    /// Removes the state and pending data of an upload.
+    ///
+    /// If `abort` is set, the S3 multipart upload is aborted as well, which
+    /// discards the parts uploaded so far.
+    ///
+    /// # Arguments
+    ///
+    /// * `upload` - The upload
+    /// * `abort` - Whether to abort the multipart upload
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the upload was removed
+    /// * `Err(S3Error)` - If there was an error removing the upload
+    pub async fn delete_resumable_upload(&self, upload: &ResumableUpload, abort: bool) -> Result<(), S3Error> {
+        // Empty uploads are complete when created and have no multipart upload
+        if abort && !upload.multipart_id.is_empty() {
+            info!("Aborting resumable upload {} to {}/{}", upload.id, upload.bucket, upload.key);
+            if let Err(e) = self.abort_multipart_upload(&upload.key, &upload.bucket, &upload.multipart_id).await {
+                // The multipart upload may already be gone; the state must be removed regardless
+                warn!("Error aborting multipart upload of {}: {}", upload.id, e);
+            }
+        }
+
+        self.delete_system_object(&upload.bucket, &ResumableUpload::pending_name(&upload.id)).await?;
+        self.delete_system_object(&upload.bucket, &ResumableUpload::state_name(&upload.id)).await
+    }
+
+    /// Removes the expired uploads of all buckets and aborts their multipart uploads.
+    ///
+    /// Clients that give up on an upload never terminate it, so without this
+    /// their parts would be kept, and billed, by S3 indefinitely.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(usize)` - The number of uploads removed
+    /// * `Err(S3Error)` - If the buckets could not be listed
+    pub async fn remove_expired_uploads(&self) -> Result<usize, S3Error> {
+        let now = Utc::now();
+        let mut removed = 0;
+
+        for bucket in self.list_buckets().await? {
+            let uploads = match self.list_system_objects::<ResumableUpload>(&bucket, UPLOAD_FOLDER).await {
+                Ok(uploads) => uploads,
+                Err(e) => {
+                    warn!("Error listing uploads of bucket {}: {}", bucket, e);
+                    continue;
+                }
+            };
+
+            for upload in uploads.iter().filter(|upload| upload.is_expired(now)) {
+                match self.delete_resumable_upload(upload, true).await {
+                    Ok(()) => removed += 1,
+                    Err(e) => warn!("Error removing expired upload {} of bucket {}: {}", upload.id, bucket, e),
+                }
+            }
+        }
+
+        Ok(removed)
+    }
+}
+
+/// Removes expired uploads every `UPLOAD_SWEEP_INTERVAL_SECONDS`, for as long as the server runs.
+///
+/// # Arguments
+///
+/// * `s3` - The service whose buckets are swept
+pub async fn sweep_expired_uploads(s3: Arc<S3Service>) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(UPLOAD_SWEEP_INTERVAL_SECONDS));
+    loop {
+        interval.tick().await;
+        match s3.remove_expired_uploads().await {
+            Ok(0) => {},
+            Ok(removed) => info!("Removed {} expired resumable uploads", removed),
+            Err(e) => warn!("Error removing expired resumable uploads: {}", e),
+        }
+    }
+}
@@ -0,0 +1,217 @@
+//! # System Objects
+//!
+//! This module provides functionality for storing RustDok's own metadata
+//! inside a bucket. System objects are JSON documents kept under a hidden
+//! prefix that is never exposed through the object API.
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use log::warn;
+
+use crate::rdlib::s3::error::S3Error;
+use crate::rdlib::s3::service::S3Service;
+use crate::rdlib::s3::types::{Preconditions, WriteOptions};
+
+/// The hidden prefix under which RustDok keeps its system objects
+pub const SYSTEM_PREFIX: &str = ".rustdok/";
+
+impl S3Service {
+    /// Checks if a key or prefix points into the hidden system prefix.
+    ///
+    /// Leading slashes are ignored so that `/.rustdok/...` is treated the same
+    /// as `.rustdok/...`.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The object key or prefix to check
+    ///
+    /// # Returns
+    ///
+    /// `true` if the key is a system key, `false` otherwise
+    pub fn is_system_key(key: &str) -> bool {
+        let key = key.trim_start_matches('/');
+        key.starts_with(SYSTEM_PREFIX) || key == SYSTEM_PREFIX.trim_end_matches('/')
+    }
+
+    /// Builds the full key of a system object.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the system object relative to the system prefix
+    ///
+    /// # Returns
+    ///
+    /// The key of the system object
+    pub fn system_key(name: &str) -> String {
+        format!("{}{}", SYSTEM_PREFIX, name)
+    }
+
+    /// Reads and deserializes a system object from a bucket.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket` - The name of the bucket holding the system object
+    /// * `name` - The name of the system object relative to the system prefix
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some(T))` - If the system object exists
+    /// * `Ok(None)` - If the system object does not exist
+    /// * `Err(S3Error)` - If there was an error reading or parsing the object
+    pub async fn get_system_object<T: DeserializeOwned>(&self, bucket: &str, name: &str) -> Result<Option<T>, S3Error> {
+        Ok(self.get_system_object_with_etag(bucket, name).await?.map(|(value, _)| value))
+    }
+
+    /// Reads and deserializes a system object together with its ETag.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket` - The name of the bucket holding the system object
+    /// * `name` - The name of the system object relative to the system prefix
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some((T, Option<String>)))` - The system object and its normalized ETag, if it exists
+    /// * `Ok(None)` - If the system object does not exist
+    /// * `Err(S3Error)` - If there was an error reading or parsing the object
+    pub async fn get_system_object_with_etag<T: DeserializeOwned>(
+        &self,
+        bucket: &str,
+        name: &str
+    ) -> Result<Option<(T, Option<String>)>, S3Error> {
+        let key = Self::system_key(name);
+
+        let resp = match self.client
+            .get_object()
+            .bucket(bucket)
+            .key(&key)
+            .send()
+            .await
+        {
+            Ok(resp) => resp,
+            Err(aws_sdk_s3::error::SdkError::ServiceError(context)) if context.err().is_no_such_key() => {
+                return Ok(None);
+            },
+            Err(e) => return Err(S3Error::from(e)),
+        };
+
+        let etag = resp.e_tag().map(Preconditions::normalize_etag);
+        let data = resp.body.collect().await?;
+        serde_json::from_slice(&data.into_bytes())
+            .map(|value| Some((value, etag)))
+            .map_err(|e| S3Error::Other(format!("Invalid system object '{}': {}", key, e)))
+    }
+
+    /// Serializes and stores a system object in a bucket.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket` - The name of the bucket to store the system object in
+    /// * `name` - The name of the system object relative to the system prefix
+    /// * `value` - The value to store
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the system object was stored successfully
+    /// * `Err(S3Error)` - If there was an error storing the object
+    pub async fn put_system_object<T: Serialize>(&self, bucket: &str, name: &str, value: &T) -> Result<(), S3Error> {
+        let data = serde_json::to_vec(value)
+            .map_err(|e| S3Error::Other(format!("Failed to serialize system object '{}': {}", name, e)))?;
+
+        self.client
+            .put_object()
+            .bucket(bucket)
+            .key(Self::system_key(name))
+            .content_type("application/json")
+            .body(data.into())
+            .send()
+            .await?;
+
+        Ok(())
+    }
+
+    /// Serializes and stores a system object if the stored one meets the preconditions.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket` - The name of the bucket to store the system object in
+    /// * `name` - The name of the system object relative to the system prefix
+    /// * `value` - The value to store
+    /// * `preconditions` - The conditions the stored object must meet
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some(String))` - The normalized ETag of the stored object
+    /// * `Ok(None)` - If a precondition did not hold
+    /// * `Err(S3Error)` - If there was an error storing the object
+    pub async fn put_system_object_if<T: Serialize>(
+        &self,
+        bucket: &str,
+        name: &str,
+        value: &T,
+        preconditions: &Preconditions
+    ) -> Result<Option<String>, S3Error> {
+        let data = serde_json::to_vec(value)
+            .map_err(|e| S3Error::Other(format!("Failed to serialize system object '{}': {}", name, e)))?;
+        let options = WriteOptions {
+            content_type: Some("application/json".to_string()),
+            preconditions: preconditions.clone(),
+            ..Default::default()
+        };
+
+        let etag = self.put_object_with_options(&Self::system_key(name), bucket, data.into(), &options).await?;
+        Ok(etag.map(|etag| Preconditions::normalize_etag(&etag)))
+    }
+
+    /// Deletes a system object from a bucket.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket` - The name of the bucket holding the system object
+    /// * `name` - The name of the system object relative to the system prefix
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the system object was deleted (or did not exist)
+    /// * `Err(S3Error)` - If there was an error deleting the object
+    pub async fn delete_system_object(&self, bucket: &str, name: &str) -> Result<(), S3Error> {
+        self.client
+            .delete_object()
+            .bucket(bucket)
+            .key(Self::system_key(name))
+            .send()
+            .await?;
+
+        Ok(())
+    }
+
+    /// Lists and deserializes all system objects directly under a system folder.
+    ///
+    /// Objects that cannot be parsed are skipped.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket` - The name of the bucket holding the system objects
+    /// * `folder` - The folder relative to the system prefix, ending with a slash
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<T>)` - The parsed system objects
+    /// * `Err(S3Error)` - If there was an error listing or reading the objects
+    pub async fn list_system_objects<T: DeserializeOwned>(&self, bucket: &str, folder: &str) -> Result<Vec<T>, S3Error> {
+        let prefix = Self::system_key(folder);
+        let objects = self.list_objects(Some(&prefix), bucket).await?;
+
+        let mut values = Vec::new();
+        for obj in objects.iter().filter(|o| !o.name.ends_with('/')) {
+            let name = obj.name.strip_prefix(SYSTEM_PREFIX).unwrap_or(&obj.name);
+            match self.get_system_object(bucket, name).await {
+                Ok(Some(value)) => values.push(value),
+                Ok(None) => {},
+                Err(e) => warn!("Skipping system object {}: {}", obj.name, e),
+            }
+        }
+
+        Ok(values)
+    }
+}
@@ -4,11 +4,13 @@
 //! It includes types for representing S3 objects and their metadata.
 
 use serde::{Serialize, Deserialize};
+use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
 
 /// Represents an object in an S3 bucket.
 ///
 /// This structure contains metadata about an S3 object, including its name (key),
-/// size, and last modified timestamp.
+/// size, last modified timestamp and ETag.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct S3Object {
     /// The name (key) of the object
@@ -17,4 +19,151 @@ pub struct S3Object {
     pub size: u64,
     /// The last modified timestamp of the object in RFC3339 format
     pub last_modified: Option<String>,
-} 
\ No newline at end of file
+    /// The ETag of the object, including its quotes; `None` for folders
+    #[serde(default)]
+    pub etag: Option<String>,
+} 
+
+/// Represents an anonymous upload ("file drop") link.
+///
+/// A drop link lets anyone holding its token upload files into one fixed
+/// prefix of a bucket, without being able to list or read anything.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DropLink {
+    /// The secret token identifying the link
+    pub token: String,
+    /// The bucket uploads are stored in
+    pub bucket: String,
+    /// The prefix (folder) uploads are stored under
+    pub prefix: String,
+    /// When the link was created
+    pub created_at: DateTime<Utc>,
+    /// When the link stops accepting uploads, if ever
+    pub expires_at: Option<DateTime<Utc>>,
+    /// The maximum size of a single uploaded file in bytes
+    pub max_file_size: Option<u64>,
+    /// The file extensions accepted by the link, without the leading dot
+    pub allowed_extensions: Option<Vec<String>>,
+    /// The maximum number of files the link accepts
+    pub max_uploads: Option<u32>,
+    /// The number of files uploaded through the link so far
+    pub upload_count: u32,
+    /// The ETag of the stored link, which claiming an upload must still match
+    #[serde(skip)]
+    pub etag: Option<String>,
+}
+
+/// Represents the upload policy of a bucket.
+///
+/// Every limit is optional; a policy without any limits accepts everything.
+/// Content types may use wildcards such as `image/*`, extensions are given
+/// without the leading dot, and the key prefix pattern may use `*` to match
+/// a single path segment.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UploadPolicy {
+    /// The maximum size of a single object in bytes
+    #[serde(default)]
+    pub max_object_size: Option<u64>,
+    /// The content types that may be uploaded
+    #[serde(default)]
+    pub allowed_content_types: Option<Vec<String>>,
+    /// The content types that may never be uploaded
+    #[serde(default)]
+    pub denied_content_types: Option<Vec<String>>,
+    /// The file extensions that may be uploaded
+    #[serde(default)]
+    pub allowed_extensions: Option<Vec<String>>,
+    /// The file extensions that may never be uploaded
+    #[serde(default)]
+    pub denied_extensions: Option<Vec<String>>,
+    /// The pattern every object key must start with, e.g. `projects/*/`
+    #[serde(default)]
+    pub key_prefix_pattern: Option<String>,
+}
+
+/// Represents the metadata of an object returned by a HEAD request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ObjectHead {
+    /// The ETag of the object, including its quotes
+    pub etag: Option<String>,
+    /// The size of the object in bytes
+    pub size: u64,
+    /// The content type stored with the object
+    pub content_type: Option<String>,
+    /// The last modified timestamp of the object in RFC3339 format
+    pub last_modified: Option<String>,
+}
+
+/// Represents a part of a multipart upload that has been uploaded.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UploadedPart {
+    /// The number of the part, starting at 1
+    pub part_number: i32,
+    /// The ETag S3 returned for the part
+    pub etag: String,
+    /// The size of the part in bytes
+    pub size: u64,
+}
+
+/// Represents a resumable (tus) upload in progress.
+///
+/// The upload is backed by an S3 multipart upload. Data that does not yet
+/// fill a part is kept in a pending system object, so the upload can be
+/// resumed after a dropped connection or a server restart.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResumableUpload {
+    /// The random ID of the upload
+    pub id: String,
+    /// The bucket the object is uploaded to
+    pub bucket: String,
+    /// The key the object is stored under once the upload is complete
+    pub key: String,
+    /// The content type of the object
+    pub content_type: String,
+    /// The ID of the S3 multipart upload
+    pub multipart_id: String,
+    /// The total size of the object in bytes
+    pub length: u64,
+    /// The size of the parts uploaded to S3 in bytes
+    pub part_size: u64,
+    /// The parts uploaded to S3 so far
+    pub parts: Vec<UploadedPart>,
+    /// The number of bytes received but not yet uploaded as a part
+    pub pending_size: u64,
+    /// The metadata sent by the client when creating the upload
+    pub metadata: BTreeMap<String, String>,
+    /// When the upload was created
+    pub created_at: DateTime<Utc>,
+    /// When the upload expires if it is not completed
+    pub expires_at: DateTime<Utc>,
+    /// Until when a request holds the upload to append data, if one does
+    #[serde(default)]
+    pub locked_until: Option<DateTime<Utc>>,
+    /// The ETag of the stored state, which the next save must still match
+    #[serde(skip)]
+    pub state_etag: Option<String>,
+}
+
+/// ETag preconditions of a write or delete, as sent in `If-Match` and `If-None-Match`.
+///
+/// ETags are stored without quotes; weak ETags keep their `W/` prefix in
+/// `if_match`, where they never match, and lose it in `if_none_match`.
+/// `*` matches any existing object.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Preconditions {
+    /// The object must exist with one of these ETags
+    pub if_match: Option<Vec<String>>,
+    /// The object must not exist with one of these ETags
+    pub if_none_match: Option<Vec<String>>,
+}
+
+/// Options for writing an object.
+#[derive(Debug, Clone, Default)]
+pub struct WriteOptions {
+    /// The content type to store with the object
+    pub content_type: Option<String>,
+    /// The base64-encoded MD5 digest of the data, verified by S3
+    pub content_md5: Option<String>,
+    /// The conditions the existing object must meet for the write to happen
+    pub preconditions: Preconditions,
+}
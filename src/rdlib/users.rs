@@ -0,0 +1,12 @@
+//! # Users Module
+//!
+//! This module provides the user accounts that log in with a password,
+//! stored in MySQL.
+
+pub mod user;
+pub mod error;
+pub mod store;
+pub mod mysql;
+pub mod two_factor;
+pub mod session;
+pub mod service;
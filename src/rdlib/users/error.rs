@@ -0,0 +1,99 @@
+//! # User Errors
+//!
+//! This module defines the errors of the user subsystem: invalid input,
+//! failed logins, misuse of two-factor authentication and failures of the
+//! database.
+
+use std::fmt;
+
+use crate::rdlib::users::user::Role;
+
+/// Errors of user management and login.
+#[derive(Clone, Debug, PartialEq)]
+pub enum UserError {
+    /// The username is not acceptable
+    InvalidUsername(String),
+    /// The password is not acceptable
+    InvalidPassword(String),
+    /// The username or password is wrong
+    InvalidCredentials,
+    /// The user is disabled
+    AccountDisabled,
+    /// The refresh token is unknown, used or expired
+    InvalidRefreshToken,
+    /// The session is unknown, revoked or expired
+    InvalidSession,
+    /// No user is linked to the external identity, and none may be provisioned
+    UnknownIdentity,
+    /// The user has two-factor authentication enabled, and no code was given
+    TwoFactorRequired,
+    /// The TOTP or recovery code is wrong or was used before
+    InvalidTwoFactorCode,
+    /// The role of the user requires two-factor authentication, which it has
+    /// not enabled; carries the token for setting it up if the password was checked
+    TwoFactorSetupRequired(Option<String>),
+    /// The token for setting up two-factor authentication is invalid or expired
+    InvalidSetupToken,
+    /// The user has two-factor authentication enabled already
+    TwoFactorAlreadyEnabled,
+    /// The user does not have two-factor authentication enabled
+    TwoFactorNotEnabled,
+    /// The user has not set up two-factor authentication before enabling it
+    TwoFactorNotSetUp,
+    /// The role of the user does not allow disabling two-factor authentication
+    TwoFactorMandatory(Role),
+    /// Too many wrong codes were entered for the user recently
+    TooManyTwoFactorAttempts,
+    /// The client sent too many requests to the two-factor endpoints recently
+    TooManyRequests,
+    /// The user does not exist
+    NotFound,
+    /// The session does not exist or belongs to another user
+    SessionNotFound,
+    /// A user with the username already exists
+    UsernameTaken(String),
+    /// The database failed
+    Database(String),
+    /// Hashing a password failed
+    Internal(String),
+}
+
+impl fmt::Display for UserError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UserError::InvalidUsername(msg) | UserError::InvalidPassword(msg) => f.write_str(msg),
+            UserError::InvalidCredentials => f.write_str("Invalid username or password"),
+            UserError::AccountDisabled => f.write_str("The account is disabled"),
+            UserError::InvalidRefreshToken => f.write_str("The refresh token is invalid or expired"),
+            UserError::InvalidSession => f.write_str("The session is invalid or expired"),
+            UserError::UnknownIdentity => f.write_str("No account is linked to this identity"),
+            UserError::TwoFactorRequired => f.write_str("A code from the authenticator app or a recovery code is required"),
+            UserError::InvalidTwoFactorCode => f.write_str("The two-factor code is invalid or was already used"),
+            UserError::TwoFactorSetupRequired(_) => {
+                f.write_str("Two-factor authentication must be set up for this account before logging in")
+            },
+            UserError::InvalidSetupToken => f.write_str("The setup token is invalid or expired; please log in again"),
+            UserError::TwoFactorAlreadyEnabled => f.write_str("Two-factor authentication is already enabled"),
+            UserError::TwoFactorNotEnabled => f.write_str("Two-factor authentication is not enabled"),
+            UserError::TwoFactorNotSetUp => f.write_str("Two-factor authentication has not been set up"),
+            UserError::TwoFactorMandatory(role) => {
+                write!(f, "Two-factor authentication is required for users with role '{}'", role)
+            },
+            UserError::TooManyTwoFactorAttempts => f.write_str("Too many invalid two-factor codes; try again later"),
+            UserError::TooManyRequests => f.write_str("Too many requests from this address; try again later"),
+            UserError::NotFound => f.write_str("User not found"),
+            UserError::SessionNotFound => f.write_str("Session not found"),
+            UserError::UsernameTaken(username) => write!(f, "User '{}' already exists", username),
+            UserError::Database(msg) => write!(f, "Database error: {}", msg),
+            UserError::Internal(msg) => write!(f, "Internal error: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for UserError {}
+
+impl From<sqlx::Error> for UserError {
+    fn from(err: sqlx::Error) -> Self {
+        UserError::Database(err.to_string())
+    }
+}
@@ -0,0 +1,695 @@
+//! # User Service
+//!
+//! This module provides the operations of the user subsystem on top of a
+//! `UserStore`: creating, changing and deleting users, checking passwords
+//! and second factors at login, managing two-factor authentication,
+//! provisioning users who log in through an identity provider, issuing and
+//! refreshing tokens and keeping the sessions of the web UI.
+
+use std::env;
+use std::sync::Arc;
+
+use chrono::{Duration, Utc};
+use log::{info, warn};
+use once_cell::sync::Lazy;
+use serde_json::{json, Map};
+
+use crate::rdlib::auth::principal::Principal;
+use crate::rdlib::auth::tokens::{hash_refresh_token, TokenIssuer, TokenPair};
+use crate::rdlib::users::error::UserError;
+use crate::rdlib::users::mysql::MySqlUserStore;
+use crate::rdlib::users::session::{
+    generate_session_token, hash_session_token, NewSession, Session, SessionClient, SessionLifetime, StartedSession,
+    MAX_USER_AGENT_LENGTH, SESSION_TOUCH_RESOLUTION_SECONDS
+};
+use crate::rdlib::users::store::UserStore;
+use crate::rdlib::users::two_factor::{
+    generate_recovery_codes, generate_secret, hash_recovery_code, is_totp_code, otpauth_uri, verify_totp,
+    AttemptLimiter, ClientThrottle, TotpSetup, TwoFactorPolicy, DEFAULT_TOTP_ISSUER, SETUP_TOKEN_TTL_SECONDS
+};
+use crate::rdlib::users::user::{
+    validate_password, validate_username, ExternalIdentity, NewUser, Role, User, UserChanges
+};
+
+/// A hash checked against when a login names an unknown user, so such
+/// logins take as long as those with a wrong password.
+static DUMMY_HASH: Lazy<String> = Lazy::new(|| {
+    bcrypt::hash("rustdok-dummy-password", bcrypt::DEFAULT_COST).unwrap_or_default()
+});
+
+/// The range of bcrypt costs accepted by `RUSTDOK_BCRYPT_COST`.
+pub const BCRYPT_COST_RANGE: std::ops::RangeInclusive<u32> = 4..=31;
+
+/// Changes to a user requested by an administrator.
+#[derive(Clone, Debug, Default)]
+pub struct UserUpdate {
+    pub password: Option<String>,
+    pub role: Option<Role>,
+    pub disabled: Option<bool>,
+}
+
+/// Manages users and their logins.
+pub struct UserService {
+    store: Arc<dyn UserStore>,
+    issuer: TokenIssuer,
+    bcrypt_cost: u32,
+    two_factor_policy: TwoFactorPolicy,
+    totp_issuer: String,
+    attempts: AttemptLimiter,
+    clients: ClientThrottle,
+    session_lifetime: SessionLifetime,
+}
+
+impl UserService {
+    /// Creates the service.
+    ///
+    /// # Arguments
+    ///
+    /// * `store` - Where users are kept
+    /// * `issuer` - What issues the tokens at login
+    pub fn new(store: Arc<dyn UserStore>, issuer: TokenIssuer) -> Self {
+        Self {
+            store,
+            issuer,
+            bcrypt_cost: bcrypt::DEFAULT_COST,
+            two_factor_policy: TwoFactorPolicy::default(),
+            totp_issuer: DEFAULT_TOTP_ISSUER.to_string(),
+            attempts: AttemptLimiter::default(),
+            clients: ClientThrottle::default(),
+            session_lifetime: SessionLifetime::default(),
+        }
+    }
+
+    /// Sets the bcrypt cost of new password hashes.
+    pub fn with_bcrypt_cost(mut self, cost: u32) -> Self {
+        self.bcrypt_cost = cost;
+        self
+    }
+
+    /// Sets which roles must use two-factor authentication.
+    pub fn with_two_factor_policy(mut self, policy: TwoFactorPolicy) -> Self {
+        self.two_factor_policy = policy;
+        self
+    }
+
+    /// Sets how long web UI sessions last.
+    pub fn with_session_lifetime(mut self, lifetime: SessionLifetime) -> Self {
+        self.session_lifetime = lifetime;
+        self
+    }
+
+    /// Sets the name authenticator apps show for the accounts.
+    pub fn with_totp_issuer(mut self, issuer: &str) -> Self {
+        self.totp_issuer = issuer.to_string();
+        self
+    }
+
+    /// Creates the service from the environment.
+    ///
+    /// # Environment Variables
+    ///
+    /// * `RUSTDOK_DATABASE_URL` - The MySQL database of the users; without it, user accounts are disabled
+    /// * `RUSTDOK_BCRYPT_COST` - The bcrypt cost of password hashes, from 4 to 31 (optional)
+    /// * `RUSTDOK_2FA_REQUIRED_ROLES` - The roles that must use two-factor authentication, `admin` unless set
+    /// * `RUSTDOK_TOTP_ISSUER` - The name authenticator apps show for the accounts (optional)
+    /// * `RUSTDOK_SESSION_IDLE_TIMEOUT`, `RUSTDOK_SESSION_MAX_AGE` - How long web UI sessions last, in seconds (optional)
+    /// * `RUSTDOK_ADMIN_USERNAME`, `RUSTDOK_ADMIN_PASSWORD` - An administrator created if there are no users (optional)
+    ///
+    /// # Returns
+    ///
+    /// The service, `None` if no database is configured, or an error if the
+    /// database cannot be reached or migrated
+    pub async fn from_env() -> Result<Option<Self>, String> {
+        let Ok(url) = env::var("RUSTDOK_DATABASE_URL") else {
+            info!("RUSTDOK_DATABASE_URL is not set; user accounts are disabled");
+            return Ok(None);
+        };
+        let issuer = TokenIssuer::from_env()?;
+
+        let store = MySqlUserStore::connect(&url).await.map_err(|e| format!("Cannot connect to the user database: {}", e))?;
+        store.migrate().await.map_err(|e| format!("Cannot migrate the user database: {}", e))?;
+
+        let mut service = Self::new(Arc::new(store), issuer)
+            .with_two_factor_policy(TwoFactorPolicy::from_env()?)
+            .with_session_lifetime(SessionLifetime::from_env());
+        if let Ok(totp_issuer) = env::var("RUSTDOK_TOTP_ISSUER") {
+            service = service.with_totp_issuer(&totp_issuer);
+        }
+        if let Ok(cost) = env::var("RUSTDOK_BCRYPT_COST") {
+            service = service.with_bcrypt_cost(parse_bcrypt_cost(&cost)?);
+        }
+
+        if let (Ok(username), Ok(password)) = (env::var("RUSTDOK_ADMIN_USERNAME"), env::var("RUSTDOK_ADMIN_PASSWORD")) {
+            service.bootstrap_admin(&username, &password).await.map_err(|e| format!("Cannot create the administrator: {}", e))?;
+        }
+        Ok(Some(service))
+    }
+
+    /// Creates an administrator if there are no users yet.
+    pub async fn bootstrap_admin(&self, username: &str, password: &str) -> Result<(), UserError> {
+        if self.store.count_users().await? > 0 {
+            return Ok(());
+        }
+        self.create_user(username, password, Role::Admin).await?;
+        info!("Created administrator '{}'", username);
+        Ok(())
+    }
+
+    /// Lists all users.
+    pub async fn list_users(&self) -> Result<Vec<User>, UserError> {
+        self.store.list_users().await
+    }
+
+    /// Gets a user by ID.
+    pub async fn get_user(&self, id: u64) -> Result<User, UserError> {
+        self.store.get_user(id).await?.ok_or(UserError::NotFound)
+    }
+
+    /// Gets a user by username.
+    pub async fn find_user(&self, username: &str) -> Result<Option<User>, UserError> {
+        self.store.find_user(username).await
+    }
+
+    /// Creates a user.
+    ///
+    /// # Arguments
+    ///
+    /// * `username` - The name the user logs in with
+    /// * `password` - The password, which is stored as bcrypt hash
+    /// * `role` - What the user is allowed to do
+    pub async fn create_user(&self, username: &str, password: &str, role: Role) -> Result<User, UserError> {
+        validate_username(username).map_err(UserError::InvalidUsername)?;
+        validate_password(password).map_err(UserError::InvalidPassword)?;
+        let password_hash = self.hash_password(password).await?;
+
+        let user = NewUser { username: username.to_string(), password_hash, role, email: None };
+        self.store.create_user(user, Utc::now()).await
+    }
+
+    /// Changes a user. Disabling a user, demoting it from administrator or
+    /// changing its password ends its sessions: its refresh tokens and web
+    /// UI sessions are revoked, and its access tokens expire within
+    /// `RUSTDOK_ACCESS_TOKEN_TTL`.
+    pub async fn update_user(&self, id: u64, update: UserUpdate) -> Result<User, UserError> {
+        let current = self.get_user(id).await?;
+        let password_hash = match &update.password {
+            Some(password) => {
+                validate_password(password).map_err(UserError::InvalidPassword)?;
+                Some(self.hash_password(password).await?)
+            },
+            None => None,
+        };
+        let demoted = update.role.is_some_and(|role| is_demotion(current.role, role));
+        let ends_sessions = password_hash.is_some() || update.disabled == Some(true) || demoted;
+
+        let changes = UserChanges { password_hash, role: update.role, disabled: update.disabled, email: None };
+        let user = self.store.update_user(id, changes, Utc::now()).await?.ok_or(UserError::NotFound)?;
+        if ends_sessions {
+            self.store.revoke_refresh_tokens(id).await?;
+            self.store.revoke_sessions(id).await?;
+        }
+        Ok(user)
+    }
+
+    /// Deletes a user.
+    pub async fn delete_user(&self, id: u64) -> Result<(), UserError> {
+        if self.store.delete_user(id).await? { Ok(()) } else { Err(UserError::NotFound) }
+    }
+
+    /// Logs a user in with a password and, if it has two-factor
+    /// authentication enabled, a TOTP or recovery code.
+    ///
+    /// # Arguments
+    ///
+    /// * `username` - The name of the user
+    /// * `password` - The password of the user
+    /// * `otp` - The code from the authenticator app, or a recovery code
+    ///
+    /// # Returns
+    ///
+    /// The tokens of the user, or `InvalidCredentials` if the username or
+    /// password is wrong, `AccountDisabled` if the user is disabled,
+    /// `TwoFactorRequired` or `InvalidTwoFactorCode` if the code is missing
+    /// or wrong, or `TwoFactorSetupRequired` with a setup token if the role
+    /// of the user requires two-factor authentication and it has not enabled it
+    pub async fn login(&self, username: &str, password: &str, otp: Option<&str>) -> Result<TokenPair, UserError> {
+        let user = self.check_login(username, password, otp).await?;
+        self.issue_tokens(&user).await
+    }
+
+    /// Logs a user in to the web UI, starting a session instead of issuing
+    /// tokens. The checks are those of `login`.
+    ///
+    /// # Arguments
+    ///
+    /// * `username` - The name of the user
+    /// * `password` - The password of the user
+    /// * `otp` - The code from the authenticator app, or a recovery code
+    /// * `client` - The browser the session is started in
+    pub async fn start_session(
+        &self,
+        username: &str,
+        password: &str,
+        otp: Option<&str>,
+        client: SessionClient
+    ) -> Result<(User, StartedSession), UserError> {
+        let user = self.check_login(username, password, otp).await?;
+        let started = self.create_session(&user, client).await?;
+        Ok((user, started))
+    }
+
+    /// Authenticates a request made in a web UI session, extending the
+    /// session.
+    ///
+    /// # Returns
+    ///
+    /// The session and the principal of its user, with the same claims as
+    /// an access token and the session ID in the `sid` claim, or
+    /// `InvalidSession` if the session is unknown, revoked or expired
+    pub async fn authenticate_session(&self, token: &str) -> Result<(Session, Principal), UserError> {
+        let now = Utc::now();
+        let session = self.store.find_session(&hash_session_token(token)).await?
+            .filter(|session| session.expires_at > now)
+            .ok_or(UserError::InvalidSession)?;
+        let user = self.store.get_user(session.user_id).await?.ok_or(UserError::InvalidSession)?;
+        if user.disabled {
+            return Err(UserError::AccountDisabled);
+        }
+        if self.requires_two_factor_setup(&user) {
+            return Err(UserError::TwoFactorSetupRequired(None));
+        }
+
+        if now - session.last_seen_at >= Duration::seconds(SESSION_TOUCH_RESOLUTION_SECONDS) {
+            let expires_at = self.session_lifetime.expiry(session.created_at, now);
+            self.store.touch_session(session.id, now, expires_at).await?;
+        }
+
+        let mut claims = Map::new();
+        claims.insert("sub".to_string(), json!(user.username));
+        claims.insert("uid".to_string(), json!(user.id));
+        claims.insert("roles".to_string(), json!([user.role.as_str()]));
+        claims.insert("sid".to_string(), json!(session.id));
+        let principal = Principal::from_claims(claims).map(Principal::into_local).ok_or(UserError::InvalidSession)?;
+        Ok((session, principal))
+    }
+
+    /// Ends the session of a token, if it exists.
+    pub async fn end_session(&self, token: &str) -> Result<(), UserError> {
+        if let Some(session) = self.store.find_session(&hash_session_token(token)).await? {
+            self.store.delete_session(session.id).await?;
+        }
+        Ok(())
+    }
+
+    /// Lists the web UI sessions of a user that have not expired.
+    pub async fn list_sessions(&self, user_id: u64) -> Result<Vec<Session>, UserError> {
+        self.store.list_sessions(user_id, Utc::now()).await
+    }
+
+    /// Revokes a web UI session of a user.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())`, or `SessionNotFound` if the user has no such session
+    pub async fn revoke_session(&self, user_id: u64, session_id: u64) -> Result<(), UserError> {
+        let owned = self.list_sessions(user_id).await?.iter().any(|session| session.id == session_id);
+        if !owned || !self.store.delete_session(session_id).await? {
+            return Err(UserError::SessionNotFound);
+        }
+        Ok(())
+    }
+
+    /// Revokes all web UI sessions and refresh tokens of a user.
+    pub async fn revoke_all_sessions(&self, user_id: u64) -> Result<(), UserError> {
+        self.get_user(user_id).await?;
+        self.store.revoke_sessions(user_id).await?;
+        self.store.revoke_refresh_tokens(user_id).await
+    }
+
+    /// Checks that a client has not sent too many requests to the public
+    /// two-factor endpoints recently.
+    ///
+    /// # Arguments
+    ///
+    /// * `client_ip` - The IP address of the client, or `None` if it is not known
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())`, or `TooManyRequests` if the client must wait
+    pub fn throttle_client(&self, client_ip: Option<&str>) -> Result<(), UserError> {
+        match client_ip {
+            Some(ip) if !self.clients.allow(ip, Utc::now()) => {
+                warn!("Throttled two-factor requests from {}", ip);
+                Err(UserError::TooManyRequests)
+            },
+            _ => Ok(()),
+        }
+    }
+
+    /// Hands out a setup token to a user who wants to set up two-factor
+    /// authentication. Users whose role requires it get one from their
+    /// failed login instead.
+    ///
+    /// # Returns
+    ///
+    /// The setup token, valid for ten minutes, or `TwoFactorAlreadyEnabled`
+    pub async fn two_factor_setup_token(&self, username: &str, password: &str) -> Result<String, UserError> {
+        let user = self.authenticate(username, password).await?;
+        if user.two_factor_enabled {
+            return Err(UserError::TwoFactorAlreadyEnabled);
+        }
+        self.issue_setup_token(&user).await
+    }
+
+    /// Creates a TOTP secret for a user, to be added to its authenticator
+    /// app. Two-factor authentication is enabled by `enable_two_factor`
+    /// once the app shows the right codes; until then, calling this again
+    /// replaces the secret.
+    ///
+    /// # Arguments
+    ///
+    /// * `setup_token` - The token handed out by the login or `two_factor_setup_token`
+    ///
+    /// # Returns
+    ///
+    /// The secret and its `otpauth://` URI, `InvalidSetupToken`, or `TwoFactorAlreadyEnabled`
+    pub async fn setup_two_factor(&self, setup_token: &str) -> Result<TotpSetup, UserError> {
+        let user = self.setup_token_user(setup_token).await?;
+        if user.two_factor_enabled {
+            return Err(UserError::TwoFactorAlreadyEnabled);
+        }
+
+        let secret = generate_secret();
+        self.store.set_totp(user.id, Some(&secret), false, Utc::now()).await?;
+        let otpauth_uri = otpauth_uri(&self.totp_issuer, &user.username, &secret);
+        Ok(TotpSetup { secret, otpauth_uri })
+    }
+
+    /// Enables two-factor authentication for a user, confirming that its
+    /// authenticator app shows the code of the secret from `setup_two_factor`.
+    /// The setup token is used up.
+    ///
+    /// # Returns
+    ///
+    /// The recovery codes of the user, which are shown only once,
+    /// `InvalidSetupToken`, or `TwoFactorNotSetUp` if there is no secret yet
+    pub async fn enable_two_factor(&self, setup_token: &str, otp: &str) -> Result<Vec<String>, UserError> {
+        let user = self.setup_token_user(setup_token).await?;
+        if user.two_factor_enabled {
+            return Err(UserError::TwoFactorAlreadyEnabled);
+        }
+        let secret = user.totp_secret.clone().ok_or(UserError::TwoFactorNotSetUp)?;
+        self.verify_second_factor(&user, otp, false).await?;
+
+        let codes = self.replace_recovery_codes(user.id).await?;
+        self.store.set_totp(user.id, Some(&secret), true, Utc::now()).await?;
+        self.store.revoke_setup_token(user.id).await?;
+        info!("User '{}' enabled two-factor authentication", user.username);
+        Ok(codes)
+    }
+
+    /// Disables two-factor authentication for a user, unless its role
+    /// requires it.
+    ///
+    /// # Arguments
+    ///
+    /// * `otp` - The code from the authenticator app, or a recovery code
+    pub async fn disable_two_factor(&self, username: &str, password: &str, otp: &str) -> Result<(), UserError> {
+        let user = self.authenticate(username, password).await?;
+        if !user.two_factor_enabled {
+            return Err(UserError::TwoFactorNotEnabled);
+        }
+        if self.two_factor_policy.requires(user.role) {
+            return Err(UserError::TwoFactorMandatory(user.role));
+        }
+        self.verify_second_factor(&user, otp, true).await?;
+
+        self.store.set_totp(user.id, None, false, Utc::now()).await?;
+        self.store.replace_recovery_codes(user.id, &[], Utc::now()).await?;
+        Ok(())
+    }
+
+    /// Replaces the recovery codes of a user, e.g. when most are used up.
+    ///
+    /// # Returns
+    ///
+    /// The new recovery codes, which are shown only once
+    pub async fn regenerate_recovery_codes(&self, username: &str, password: &str, otp: &str) -> Result<Vec<String>, UserError> {
+        let user = self.authenticate(username, password).await?;
+        if !user.two_factor_enabled {
+            return Err(UserError::TwoFactorNotEnabled);
+        }
+        self.verify_second_factor(&user, otp, true).await?;
+        self.replace_recovery_codes(user.id).await
+    }
+
+    /// Removes the second factor of a user who lost it, so it can log in
+    /// with its password and set up a new one. Ends the sessions of the user.
+    pub async fn reset_two_factor(&self, id: u64) -> Result<User, UserError> {
+        if !self.store.set_totp(id, None, false, Utc::now()).await? {
+            return Err(UserError::NotFound);
+        }
+        self.store.replace_recovery_codes(id, &[], Utc::now()).await?;
+        self.store.revoke_refresh_tokens(id).await?;
+        self.store.revoke_sessions(id).await?;
+        self.attempts.clear(id);
+        self.get_user(id).await
+    }
+
+    /// Logs a user in to the web UI with an identity vouched for by an
+    /// identity provider, starting a session.
+    ///
+    /// The first login of an identity provisions a user with its username
+    /// and no password, unless `provision` is false; the user and the link
+    /// to its identity are created together. Later logins update the email
+    /// address and, if the identity carries one, the role.
+    ///
+    /// # Returns
+    ///
+    /// The user and its session, `UsernameTaken` if another account has the
+    /// username, `UnknownIdentity` if no user may be provisioned, or
+    /// `AccountDisabled` if the user is disabled
+    pub async fn start_external_session(
+        &self,
+        identity: &ExternalIdentity,
+        provision: bool,
+        client: SessionClient
+    ) -> Result<(User, StartedSession), UserError> {
+        let now = Utc::now();
+        let user = match self.store.find_user_by_identity(&identity.issuer, &identity.subject).await? {
+            Some(user) => {
+                let changes = UserChanges {
+                    role: identity.role.filter(|role| *role != user.role),
+                    email: identity.email.clone().filter(|email| user.email.as_ref() != Some(email)),
+                    ..UserChanges::default()
+                };
+                if changes.role.is_some() || changes.email.is_some() {
+                    let demoted = changes.role.is_some_and(|role| is_demotion(user.role, role));
+                    let user = self.store.update_user(user.id, changes, now).await?.ok_or(UserError::NotFound)?;
+                    if demoted {
+                        self.store.revoke_refresh_tokens(user.id).await?;
+                        self.store.revoke_sessions(user.id).await?;
+                    }
+                    user
+                } else {
+                    user
+                }
+            },
+            None if provision => {
+                validate_username(&identity.username).map_err(UserError::InvalidUsername)?;
+                // The empty hash matches no password, so the user can only log in through the provider
+                let new_user = NewUser {
+                    username: identity.username.clone(),
+                    password_hash: String::new(),
+                    role: identity.role.unwrap_or(Role::User),
+                    email: identity.email.clone(),
+                };
+                let user = self.store.create_linked_user(new_user, &identity.issuer, &identity.subject, now).await?;
+                info!("Provisioned user '{}' for {} at {}", user.username, identity.subject, identity.issuer);
+                user
+            },
+            None => return Err(UserError::UnknownIdentity),
+        };
+        if user.disabled {
+            return Err(UserError::AccountDisabled);
+        }
+
+        self.store.record_login(user.id, now).await?;
+        let started = self.create_session(&user, client).await?;
+        Ok((user, started))
+    }
+
+    /// Exchanges a refresh token for a new pair of tokens. Each refresh
+    /// token can be used once.
+    pub async fn refresh(&self, refresh_token: &str) -> Result<TokenPair, UserError> {
+        let stored = self.store.take_refresh_token(&hash_refresh_token(refresh_token)).await?
+            .filter(|stored| stored.expires_at > Utc::now())
+            .ok_or(UserError::InvalidRefreshToken)?;
+        let user = self.store.get_user(stored.user_id).await?.ok_or(UserError::InvalidRefreshToken)?;
+        if user.disabled {
+            return Err(UserError::AccountDisabled);
+        }
+        // Sessions from before the role required a second factor end here
+        if self.requires_two_factor_setup(&user) {
+            return Err(UserError::TwoFactorSetupRequired(None));
+        }
+        self.issue_tokens(&user).await
+    }
+
+    /// Revokes a refresh token.
+    pub async fn logout(&self, refresh_token: &str) -> Result<(), UserError> {
+        self.store.take_refresh_token(&hash_refresh_token(refresh_token)).await?;
+        Ok(())
+    }
+
+    /// Checks the password and second factor of a login and records it.
+    async fn check_login(&self, username: &str, password: &str, otp: Option<&str>) -> Result<User, UserError> {
+        let user = self.authenticate(username, password).await?;
+        if user.two_factor_enabled {
+            let otp = otp.filter(|otp| !otp.trim().is_empty()).ok_or(UserError::TwoFactorRequired)?;
+            self.verify_second_factor(&user, otp, true).await?;
+        } else if self.requires_two_factor_setup(&user) {
+            let setup_token = self.issue_setup_token(&user).await?;
+            return Err(UserError::TwoFactorSetupRequired(Some(setup_token)));
+        }
+
+        self.store.record_login(user.id, Utc::now()).await?;
+        Ok(user)
+    }
+
+    /// Checks the password of a user, taking as long for unknown users.
+    async fn authenticate(&self, username: &str, password: &str) -> Result<User, UserError> {
+        let user = self.store.find_user(username).await?;
+        let hash = user.as_ref().map_or_else(|| DUMMY_HASH.clone(), |user| user.password_hash.clone());
+        let matches = verify_password(password, hash).await?;
+
+        let user = match user {
+            Some(user) if matches => user,
+            _ => {
+                warn!("Failed login for user '{}'", username);
+                return Err(UserError::InvalidCredentials);
+            }
+        };
+        if user.disabled {
+            return Err(UserError::AccountDisabled);
+        }
+        Ok(user)
+    }
+
+    /// Hands out a setup token to a user whose password was checked.
+    async fn issue_setup_token(&self, user: &User) -> Result<String, UserError> {
+        let now = Utc::now();
+        let token = generate_session_token();
+        let expires_at = now + Duration::seconds(SETUP_TOKEN_TTL_SECONDS);
+        self.store.replace_setup_token(user.id, &hash_session_token(&token), expires_at, now).await?;
+        Ok(token)
+    }
+
+    /// Gets the user of a setup token.
+    async fn setup_token_user(&self, setup_token: &str) -> Result<User, UserError> {
+        let user_id = self.store.find_setup_token(&hash_session_token(setup_token), Utc::now()).await?
+            .ok_or(UserError::InvalidSetupToken)?;
+        let user = self.store.get_user(user_id).await?.ok_or(UserError::InvalidSetupToken)?;
+        if user.disabled {
+            return Err(UserError::AccountDisabled);
+        }
+        Ok(user)
+    }
+
+    /// Checks a TOTP code of a user and, if allowed, a recovery code.
+    async fn verify_second_factor(&self, user: &User, otp: &str, allow_recovery: bool) -> Result<(), UserError> {
+        let now = Utc::now();
+        if self.attempts.is_locked(user.id, now) {
+            warn!("Two-factor authentication of user '{}' is locked after too many invalid codes", user.username);
+            return Err(UserError::TooManyTwoFactorAttempts);
+        }
+
+        let accepted = if is_totp_code(otp) {
+            match user.totp_secret.as_deref().and_then(|secret| verify_totp(secret, otp, now)) {
+                Some(step) => self.store.use_totp_step(user.id, step).await?,
+                None => false,
+            }
+        } else if allow_recovery && self.store.take_recovery_code(user.id, &hash_recovery_code(otp)).await? {
+            info!("User '{}' used a recovery code", user.username);
+            true
+        } else {
+            false
+        };
+
+        if !accepted {
+            warn!("Invalid two-factor code for user '{}'", user.username);
+            self.attempts.record_failure(user.id, now);
+            return Err(UserError::InvalidTwoFactorCode);
+        }
+        self.attempts.clear(user.id);
+        Ok(())
+    }
+
+    /// Checks if the role of a user requires a second factor it has not
+    /// enabled. Users without a password log in through their identity
+    /// provider, which is in charge of their second factor.
+    fn requires_two_factor_setup(&self, user: &User) -> bool {
+        !user.two_factor_enabled && !user.password_hash.is_empty() && self.two_factor_policy.requires(user.role)
+    }
+
+    async fn replace_recovery_codes(&self, user_id: u64) -> Result<Vec<String>, UserError> {
+        let codes = generate_recovery_codes();
+        let hashes = codes.iter().map(|code| hash_recovery_code(code)).collect::<Vec<_>>();
+        self.store.replace_recovery_codes(user_id, &hashes, Utc::now()).await?;
+        Ok(codes)
+    }
+
+    async fn create_session(&self, user: &User, client: SessionClient) -> Result<StartedSession, UserError> {
+        let now = Utc::now();
+        let token = generate_session_token();
+        let new_session = NewSession {
+            token_hash: hash_session_token(&token),
+            user_id: user.id,
+            csrf_token: generate_session_token(),
+            user_agent: client.user_agent.map(|agent| agent.chars().take(MAX_USER_AGENT_LENGTH).collect()),
+            ip_address: client.ip_address,
+            expires_at: self.session_lifetime.expiry(now, now),
+        };
+        let session = self.store.create_session(new_session, now).await?;
+        Ok(StartedSession { session, token })
+    }
+
+    async fn issue_tokens(&self, user: &User) -> Result<TokenPair, UserError> {
+        let now = Utc::now();
+        let (tokens, stored) = self.issuer.issue(user, now).map_err(UserError::Internal)?;
+        self.store.add_refresh_token(stored, now).await?;
+        Ok(tokens)
+    }
+
+    async fn hash_password(&self, password: &str) -> Result<String, UserError> {
+        let password = password.to_string();
+        let cost = self.bcrypt_cost;
+        tokio::task::spawn_blocking(move || bcrypt::hash(password, cost))
+            .await
+            .map_err(|e| UserError::Internal(e.to_string()))?
+            .map_err(|e| UserError::Internal(e.to_string()))
+    }
+}
+
+/// Parses the value of `RUSTDOK_BCRYPT_COST`, which bcrypt only accepts
+/// from 4 to 31.
+pub fn parse_bcrypt_cost(value: &str) -> Result<u32, String> {
+    value.trim().parse::<u32>()
+        .ok()
+        .filter(|cost| BCRYPT_COST_RANGE.contains(cost))
+        .ok_or_else(|| format!("Invalid RUSTDOK_BCRYPT_COST '{}': must be a number from 4 to 31", value))
+}
+
+/// Checks if a role change takes away privileges, so the tokens and sessions
+/// issued with the old role must end.
+fn is_demotion(from: Role, to: Role) -> bool {
+    from == Role::Admin && to != Role::Admin
+}
+
+/// Checks a password against a bcrypt hash, off the async runtime.
+async fn verify_password(password: &str, hash: String) -> Result<bool, UserError> {
+    let password = password.to_string();
+    tokio::task::spawn_blocking(move || bcrypt::verify(password, &hash).unwrap_or(false))
+        .await
+        .map_err(|e| UserError::Internal(e.to_string()))
+}
@@ -0,0 +1,133 @@
+//! # Sessions
+//!
+//! This module defines the server-side sessions of the web UI. A session
+//! is identified by a random token the browser keeps in an HttpOnly
+//! cookie, so scripts never see a credential; only the SHA-256 hash of the
+//! token is stored. Each session also has a CSRF token, which the UI must
+//! echo in a header on requests that change something.
+//!
+//! Sessions end after a period without requests and at the latest after a
+//! fixed lifetime, and can be revoked at any time.
+
+use std::env;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+/// How long a session lasts without requests unless `RUSTDOK_SESSION_IDLE_TIMEOUT` is set, in seconds
+pub const DEFAULT_SESSION_IDLE_TIMEOUT_SECONDS: i64 = 2 * 60 * 60;
+
+/// How long a session lasts at most unless `RUSTDOK_SESSION_MAX_AGE` is set, in seconds
+pub const DEFAULT_SESSION_MAX_AGE_SECONDS: i64 = 24 * 60 * 60;
+
+/// How often the last request of a session is recorded, so busy sessions
+/// do not write on every request
+pub const SESSION_TOUCH_RESOLUTION_SECONDS: i64 = 60;
+
+/// The longest stored user agent
+pub const MAX_USER_AGENT_LENGTH: usize = 255;
+
+/// A session of the web UI.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Session {
+    /// The ID of the session
+    pub id: u64,
+    /// The hex SHA-256 hash of the session token
+    #[serde(skip)]
+    pub token_hash: String,
+    /// The user the session belongs to
+    pub user_id: u64,
+    /// The token the UI must send in the `X-CSRF-Token` header
+    #[serde(skip)]
+    pub csrf_token: String,
+    /// The `User-Agent` of the browser the session was started in
+    pub user_agent: Option<String>,
+    /// The IP address the session was started from
+    pub ip_address: Option<String>,
+    /// When the session was started
+    pub created_at: DateTime<Utc>,
+    /// When the session was last used
+    pub last_seen_at: DateTime<Utc>,
+    /// When the session ends unless it is used before
+    pub expires_at: DateTime<Utc>,
+}
+
+/// A session to be stored.
+#[derive(Clone, Debug)]
+pub struct NewSession {
+    pub token_hash: String,
+    pub user_id: u64,
+    pub csrf_token: String,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// The browser a session is started in, recorded so users can tell their
+/// sessions apart.
+#[derive(Clone, Debug, Default)]
+pub struct SessionClient {
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+}
+
+/// A session that has just been started, with the tokens for its cookies.
+#[derive(Clone, Debug)]
+pub struct StartedSession {
+    /// The stored session
+    pub session: Session,
+    /// The session token, for the HttpOnly cookie; not stored
+    pub token: String,
+}
+
+/// How long sessions last.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SessionLifetime {
+    /// How long a session lasts without requests
+    pub idle_timeout: Duration,
+    /// How long a session lasts at most
+    pub max_age: Duration,
+}
+
+impl Default for SessionLifetime {
+    fn default() -> Self {
+        Self {
+            idle_timeout: Duration::seconds(DEFAULT_SESSION_IDLE_TIMEOUT_SECONDS),
+            max_age: Duration::seconds(DEFAULT_SESSION_MAX_AGE_SECONDS),
+        }
+    }
+}
+
+impl SessionLifetime {
+    /// Reads the lifetime from `RUSTDOK_SESSION_IDLE_TIMEOUT` and
+    /// `RUSTDOK_SESSION_MAX_AGE`, in seconds.
+    pub fn from_env() -> Self {
+        let seconds = |name: &str, default: i64| env::var(name).ok()
+            .and_then(|v| v.parse::<i64>().ok())
+            .filter(|seconds| *seconds > 0)
+            .unwrap_or(default);
+        Self {
+            idle_timeout: Duration::seconds(seconds("RUSTDOK_SESSION_IDLE_TIMEOUT", DEFAULT_SESSION_IDLE_TIMEOUT_SECONDS)),
+            max_age: Duration::seconds(seconds("RUSTDOK_SESSION_MAX_AGE", DEFAULT_SESSION_MAX_AGE_SECONDS)),
+        }
+    }
+
+    /// When a session started at `created_at` ends if it is used at `now`.
+    pub fn expiry(&self, created_at: DateTime<Utc>, now: DateTime<Utc>) -> DateTime<Utc> {
+        (now + self.idle_timeout).min(created_at + self.max_age)
+    }
+}
+
+/// Generates a session or CSRF token.
+pub fn generate_session_token() -> String {
+    format!("{}{}", URL_SAFE_NO_PAD.encode(Uuid::new_v4().as_bytes()), URL_SAFE_NO_PAD.encode(Uuid::new_v4().as_bytes()))
+}
+
+/// The hash a session token is stored by, so a leaked database does not leak usable sessions.
+pub fn hash_session_token(token: &str) -> String {
+    Sha256::digest(token.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect()
+}
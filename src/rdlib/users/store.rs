@@ -0,0 +1,101 @@
+//! # User Store
+//!
+//! This module defines the storage of user accounts, the external
+//! identities linked to them, their second factors, refresh tokens and
+//! web UI sessions.
+//! The server keeps them in MySQL (see `mysql`); the trait lets tests use
+//! an in-memory substitute instead.
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+use crate::rdlib::users::error::UserError;
+use crate::rdlib::users::session::{NewSession, Session};
+use crate::rdlib::users::user::{NewUser, RefreshToken, User, UserChanges};
+
+/// Storage of user accounts, their refresh tokens and sessions.
+#[async_trait]
+pub trait UserStore: Send + Sync {
+    /// Creates a user, failing with `UsernameTaken` if the username is in use.
+    async fn create_user(&self, user: NewUser, now: DateTime<Utc>) -> Result<User, UserError>;
+
+    /// Gets a user by ID.
+    async fn get_user(&self, id: u64) -> Result<Option<User>, UserError>;
+
+    /// Gets a user by username.
+    async fn find_user(&self, username: &str) -> Result<Option<User>, UserError>;
+
+    /// Gets the user an external identity is linked to.
+    async fn find_user_by_identity(&self, issuer: &str, subject: &str) -> Result<Option<User>, UserError>;
+
+    /// Creates a user linked to an external identity, both or neither,
+    /// failing with `UsernameTaken` if the username is in use.
+    async fn create_linked_user(&self, user: NewUser, issuer: &str, subject: &str, now: DateTime<Utc>) -> Result<User, UserError>;
+
+    /// Lists all users, ordered by ID.
+    async fn list_users(&self) -> Result<Vec<User>, UserError>;
+
+    /// Counts the users.
+    async fn count_users(&self) -> Result<u64, UserError>;
+
+    /// Changes a user, returning the changed user or `None` if it does not exist.
+    async fn update_user(&self, id: u64, changes: UserChanges, now: DateTime<Utc>) -> Result<Option<User>, UserError>;
+
+    /// Records a successful login of a user.
+    async fn record_login(&self, id: u64, now: DateTime<Utc>) -> Result<(), UserError>;
+
+    /// Sets or clears the TOTP secret of a user and whether it is asked for
+    /// at login, returning whether the user exists.
+    async fn set_totp(&self, id: u64, secret: Option<&str>, enabled: bool, now: DateTime<Utc>) -> Result<bool, UserError>;
+
+    /// Records the time step of an accepted TOTP code, returning false if
+    /// it or a later step was recorded before, so each code is accepted once.
+    async fn use_totp_step(&self, id: u64, step: i64) -> Result<bool, UserError>;
+
+    /// Replaces the recovery codes of a user.
+    async fn replace_recovery_codes(&self, user_id: u64, code_hashes: &[String], now: DateTime<Utc>) -> Result<(), UserError>;
+
+    /// Removes a recovery code of a user, returning whether it existed, so
+    /// each code is used at most once.
+    async fn take_recovery_code(&self, user_id: u64, code_hash: &str) -> Result<bool, UserError>;
+
+    /// Stores the token for setting up two-factor authentication of a user,
+    /// replacing the one it had.
+    async fn replace_setup_token(&self, user_id: u64, token_hash: &str, expires_at: DateTime<Utc>, now: DateTime<Utc>) -> Result<(), UserError>;
+
+    /// Gets the user a setup token was handed out for, if it has not expired.
+    async fn find_setup_token(&self, token_hash: &str, now: DateTime<Utc>) -> Result<Option<u64>, UserError>;
+
+    /// Removes the setup token of a user.
+    async fn revoke_setup_token(&self, user_id: u64) -> Result<(), UserError>;
+
+    /// Deletes a user, its recovery codes, setup and refresh tokens and sessions, returning whether it existed.
+    async fn delete_user(&self, id: u64) -> Result<bool, UserError>;
+
+    /// Stores a refresh token.
+    async fn add_refresh_token(&self, token: RefreshToken, now: DateTime<Utc>) -> Result<(), UserError>;
+
+    /// Removes a refresh token and returns it, so each token is used at most once.
+    async fn take_refresh_token(&self, token_hash: &str) -> Result<Option<RefreshToken>, UserError>;
+
+    /// Removes all refresh tokens of a user.
+    async fn revoke_refresh_tokens(&self, user_id: u64) -> Result<(), UserError>;
+
+    /// Stores a session.
+    async fn create_session(&self, session: NewSession, now: DateTime<Utc>) -> Result<Session, UserError>;
+
+    /// Gets a session by the hash of its token.
+    async fn find_session(&self, token_hash: &str) -> Result<Option<Session>, UserError>;
+
+    /// Records a request of a session and when it ends now.
+    async fn touch_session(&self, id: u64, now: DateTime<Utc>, expires_at: DateTime<Utc>) -> Result<(), UserError>;
+
+    /// Lists the sessions of a user that have not expired, ordered by ID.
+    async fn list_sessions(&self, user_id: u64, now: DateTime<Utc>) -> Result<Vec<Session>, UserError>;
+
+    /// Deletes a session, returning whether it existed.
+    async fn delete_session(&self, id: u64) -> Result<bool, UserError>;
+
+    /// Deletes all sessions of a user.
+    async fn revoke_sessions(&self, user_id: u64) -> Result<(), UserError>;
+}
@@ -0,0 +1,328 @@
+//! # Two-Factor Authentication
+//!
+//! This module implements the second factor of password logins: time-based
+//! one-time passwords (TOTP, RFC 6238) as shown by authenticator apps, and
+//! the recovery codes that stand in for them when the app is lost. It also
+//! defines the policy of which roles must use a second factor.
+//!
+//! Codes have 6 digits and change every 30 seconds; the codes of the
+//! previous and next 30 seconds are accepted too, to allow for clock skew.
+//! Recovery codes are stored as SHA-256 hashes, like refresh tokens.
+//!
+//! A second factor is set up with a setup token, which a login with the
+//! right password hands out instead of tokens if the role of the user
+//! requires a second factor. The public endpoints that check passwords
+//! or codes are throttled by the IP address of the client.
+
+use std::collections::HashMap;
+use std::env;
+use std::sync::Mutex;
+
+use chrono::{DateTime, Duration, Utc};
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::rdlib::users::user::Role;
+
+/// The number of digits of a code
+pub const TOTP_DIGITS: usize = 6;
+
+/// How long a code is valid, in seconds
+pub const TOTP_STEP_SECONDS: i64 = 30;
+
+/// How many steps before and after the current one are accepted
+pub const TOTP_SKEW_STEPS: i64 = 1;
+
+/// The number of recovery codes generated at a time
+pub const RECOVERY_CODE_COUNT: usize = 10;
+
+/// The name authenticator apps show for the accounts unless `RUSTDOK_TOTP_ISSUER` is set
+pub const DEFAULT_TOTP_ISSUER: &str = "RustDok";
+
+/// How many wrong codes may be entered for a user before it is locked out
+pub const MAX_FAILED_ATTEMPTS: usize = 5;
+
+/// How long wrong codes count against a user, in seconds
+pub const FAILED_ATTEMPT_WINDOW_SECONDS: i64 = 15 * 60;
+
+/// How long a setup token is valid, in seconds
+pub const SETUP_TOKEN_TTL_SECONDS: i64 = 10 * 60;
+
+/// How many requests a client may send to the two-factor endpoints in a window
+pub const MAX_CLIENT_REQUESTS: usize = 10;
+
+/// How long requests of a client count against it, in seconds
+pub const CLIENT_REQUEST_WINDOW_SECONDS: i64 = 60;
+
+/// The alphabet of base32 (RFC 4648), which authenticator apps expect secrets in
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// The number of random bytes of a secret, as recommended by RFC 4226
+const SECRET_LENGTH: usize = 20;
+
+/// Which roles must log in with a second factor.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TwoFactorPolicy {
+    /// The roles whose users must have two-factor authentication enabled
+    pub required_roles: Vec<Role>,
+}
+
+impl Default for TwoFactorPolicy {
+    /// Administrators must use a second factor.
+    fn default() -> Self {
+        Self { required_roles: vec![Role::Admin] }
+    }
+}
+
+impl TwoFactorPolicy {
+    /// A policy that lets every user choose.
+    pub fn optional() -> Self {
+        Self { required_roles: Vec::new() }
+    }
+
+    /// Reads the policy from `RUSTDOK_2FA_REQUIRED_ROLES`: the roles that
+    /// must use a second factor, comma-separated, or `none`. Administrators
+    /// must use one if it is not set.
+    pub fn from_env() -> Result<Self, String> {
+        let Ok(roles) = env::var("RUSTDOK_2FA_REQUIRED_ROLES") else {
+            return Ok(Self::default());
+        };
+        if roles.trim() == "none" {
+            return Ok(Self::optional());
+        }
+        let required_roles = roles.split(',')
+            .map(str::trim)
+            .filter(|role| !role.is_empty())
+            .map(|role| role.parse::<Role>().map_err(|e| format!("Invalid RUSTDOK_2FA_REQUIRED_ROLES: {}", e)))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self { required_roles })
+    }
+
+    /// Checks if users of a role must use a second factor.
+    pub fn requires(&self, role: Role) -> bool {
+        self.required_roles.contains(&role)
+    }
+}
+
+/// A new TOTP secret, to be added to an authenticator app.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct TotpSetup {
+    /// The secret, base32-encoded, for entering it by hand
+    pub secret: String,
+    /// The `otpauth://` URI of the secret, to be shown as QR code
+    pub otpauth_uri: String,
+}
+
+/// Counts the wrong codes entered for each user, so codes cannot be
+/// guessed by trying them all. The counts are kept in memory, so each
+/// instance of the server counts on its own.
+#[derive(Default)]
+pub struct AttemptLimiter {
+    failures: Mutex<HashMap<u64, Vec<DateTime<Utc>>>>,
+}
+
+impl AttemptLimiter {
+    /// Checks if too many wrong codes were entered for a user recently.
+    pub fn is_locked(&self, user_id: u64, now: DateTime<Utc>) -> bool {
+        let mut failures = self.failures.lock().unwrap();
+        let Some(times) = failures.get_mut(&user_id) else {
+            return false;
+        };
+        times.retain(|time| *time > now - Duration::seconds(FAILED_ATTEMPT_WINDOW_SECONDS));
+        times.len() >= MAX_FAILED_ATTEMPTS
+    }
+
+    /// Records a wrong code entered for a user.
+    pub fn record_failure(&self, user_id: u64, now: DateTime<Utc>) {
+        self.failures.lock().unwrap().entry(user_id).or_default().push(now);
+    }
+
+    /// Forgets the wrong codes of a user after a right one.
+    pub fn clear(&self, user_id: u64) {
+        self.failures.lock().unwrap().remove(&user_id);
+    }
+}
+
+/// Counts the requests of each client to the two-factor endpoints, so
+/// passwords and codes cannot be guessed from one address by trying them
+/// across many users. Like `AttemptLimiter`, each instance counts on its own.
+#[derive(Default)]
+pub struct ClientThrottle {
+    requests: Mutex<HashMap<String, Vec<DateTime<Utc>>>>,
+}
+
+impl ClientThrottle {
+    /// Records a request of a client.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - The IP address of the client
+    /// * `now` - The current time
+    ///
+    /// # Returns
+    ///
+    /// Whether the request may be served, false if the client sent too many recently
+    pub fn allow(&self, client: &str, now: DateTime<Utc>) -> bool {
+        let since = now - Duration::seconds(CLIENT_REQUEST_WINDOW_SECONDS);
+        let mut requests = self.requests.lock().unwrap();
+        // Forget clients that have been quiet for a window, so the map does not grow
+        requests.retain(|_, times| times.last().is_some_and(|time| *time > since));
+
+        let times = requests.entry(client.to_string()).or_default();
+        times.retain(|time| *time > since);
+        if times.len() >= MAX_CLIENT_REQUESTS {
+            return false;
+        }
+        times.push(now);
+        true
+    }
+}
+
+/// Generates a new TOTP secret, base32-encoded.
+pub fn generate_secret() -> String {
+    let mut bytes = Vec::with_capacity(2 * 16);
+    bytes.extend_from_slice(Uuid::new_v4().as_bytes());
+    bytes.extend_from_slice(Uuid::new_v4().as_bytes());
+    base32_encode(&bytes[..SECRET_LENGTH])
+}
+
+/// The time step a moment falls in.
+pub fn totp_step(now: DateTime<Utc>) -> i64 {
+    now.timestamp().div_euclid(TOTP_STEP_SECONDS)
+}
+
+/// The code of a secret in a time step.
+///
+/// # Arguments
+///
+/// * `secret` - The secret, decoded from base32
+/// * `step` - The time step
+pub fn totp_code(secret: &[u8], step: i64) -> String {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(&(step as u64).to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    // Dynamic truncation of RFC 4226, section 5.3
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([hash[offset] & 0x7f, hash[offset + 1], hash[offset + 2], hash[offset + 3]]);
+    format!("{:0width$}", binary % 10u32.pow(TOTP_DIGITS as u32), width = TOTP_DIGITS)
+}
+
+/// Checks a code against a secret.
+///
+/// # Arguments
+///
+/// * `secret` - The secret, base32-encoded
+/// * `code` - The code entered by the user
+/// * `now` - The current time
+///
+/// # Returns
+///
+/// The time step of the code, which must not be accepted again, or `None`
+/// if the code does not match
+pub fn verify_totp(secret: &str, code: &str, now: DateTime<Utc>) -> Option<i64> {
+    let code = code.trim();
+    if !is_totp_code(code) {
+        return None;
+    }
+    let secret = decode_secret(secret)?;
+    let current = totp_step(now);
+    (current - TOTP_SKEW_STEPS..=current + TOTP_SKEW_STEPS)
+        .find(|step| constant_time_eq(totp_code(&secret, *step).as_bytes(), code.as_bytes()))
+}
+
+/// Decodes a base32-encoded secret, ignoring case, spaces and padding.
+pub fn decode_secret(secret: &str) -> Option<Vec<u8>> {
+    base32_decode(secret)
+}
+
+/// Checks if a second factor looks like a TOTP code rather than a recovery code.
+pub fn is_totp_code(code: &str) -> bool {
+    let code = code.trim();
+    code.len() == TOTP_DIGITS && code.chars().all(|c| c.is_ascii_digit())
+}
+
+/// The `otpauth://` URI that authenticator apps read from a QR code.
+///
+/// # Arguments
+///
+/// * `issuer` - The name the app shows for the account, e.g. `RustDok`
+/// * `username` - The name of the user
+/// * `secret` - The secret, base32-encoded
+pub fn otpauth_uri(issuer: &str, username: &str, secret: &str) -> String {
+    let query = serde_urlencoded::to_string([
+        ("secret", secret),
+        ("issuer", issuer),
+        ("algorithm", "SHA1"),
+        ("digits", &TOTP_DIGITS.to_string()),
+        ("period", &TOTP_STEP_SECONDS.to_string()),
+    ]).unwrap_or_default();
+    format!("otpauth://totp/{}:{}?{}", percent_encode(issuer), percent_encode(username), query)
+}
+
+/// Generates a set of recovery codes, like `k3x9q-7hd2m`.
+pub fn generate_recovery_codes() -> Vec<String> {
+    (0..RECOVERY_CODE_COUNT).map(|_| {
+        let code = base32_encode(&Uuid::new_v4().as_bytes()[..8]).to_lowercase();
+        format!("{}-{}", &code[..5], &code[5..10])
+    }).collect()
+}
+
+/// The hash a recovery code is stored by. Case, dashes and spaces are
+/// ignored, so codes can be entered as the user finds convenient.
+pub fn hash_recovery_code(code: &str) -> String {
+    let normalized: String = code.chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .flat_map(char::to_lowercase)
+        .collect();
+    Sha256::digest(normalized.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Encodes bytes in base32 without padding.
+fn base32_encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(5) * 8);
+    let (mut buffer, mut bits) = (0u32, 0u32);
+    for byte in bytes {
+        buffer = (buffer << 8) | u32::from(*byte);
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            encoded.push(BASE32_ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        encoded.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+    encoded
+}
+
+fn base32_decode(encoded: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(encoded.len() * 5 / 8);
+    let (mut buffer, mut bits) = (0u32, 0u32);
+    for c in encoded.chars().filter(|c| !c.is_whitespace() && *c != '=') {
+        let value = BASE32_ALPHABET.iter().position(|a| *a as char == c.to_ascii_uppercase())? as u32;
+        buffer = (buffer << 5) | value;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+        }
+    }
+    Some(bytes)
+}
+
+/// Percent-encodes the label of an `otpauth://` URI.
+fn percent_encode(value: &str) -> String {
+    value.bytes().map(|b| match b {
+        b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'@' => (b as char).to_string(),
+        _ => format!("%{:02X}", b),
+    }).collect()
+}
+
+/// Compares two codes in time independent of where they differ.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
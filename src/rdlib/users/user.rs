@@ -0,0 +1,168 @@
+//! # Users
+//!
+//! This module defines the user accounts that can log in with a password
+//! or through an identity provider, and the rules for their usernames and
+//! passwords.
+
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// The shortest allowed username
+pub const MIN_USERNAME_LENGTH: usize = 3;
+
+/// The longest allowed username
+pub const MAX_USERNAME_LENGTH: usize = 64;
+
+/// The shortest allowed password
+pub const MIN_PASSWORD_LENGTH: usize = 8;
+
+/// The longest allowed password; bcrypt ignores everything after 72 bytes
+pub const MAX_PASSWORD_LENGTH: usize = 72;
+
+/// What a user is allowed to do.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// May manage users in addition to using the API
+    Admin,
+    /// May use the API
+    User,
+}
+
+impl Role {
+    /// The role as it is stored and appears in tokens.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Admin => "admin",
+            Role::User => "user",
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "admin" => Ok(Role::Admin),
+            "user" => Ok(Role::User),
+            other => Err(format!("Unknown role '{}'", other)),
+        }
+    }
+}
+
+/// A user account.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct User {
+    /// The ID of the user
+    pub id: u64,
+    /// The name the user logs in with
+    pub username: String,
+    /// The bcrypt hash of the password
+    #[serde(skip)]
+    pub password_hash: String,
+    /// What the user is allowed to do
+    pub role: Role,
+    /// The email address of the user, as known to its identity provider
+    pub email: Option<String>,
+    /// The base32 TOTP secret, once two-factor authentication has been set up
+    #[serde(skip)]
+    pub totp_secret: Option<String>,
+    /// Whether the user must enter a code from its authenticator app at login
+    pub two_factor_enabled: bool,
+    /// Whether the user is prevented from logging in
+    pub disabled: bool,
+    /// When the user was created
+    pub created_at: DateTime<Utc>,
+    /// When the user was last changed
+    pub updated_at: DateTime<Utc>,
+    /// When the user last logged in
+    pub last_login_at: Option<DateTime<Utc>>,
+}
+
+/// A user account to be created.
+#[derive(Clone, Debug)]
+pub struct NewUser {
+    pub username: String,
+    pub password_hash: String,
+    pub role: Role,
+    pub email: Option<String>,
+}
+
+/// Changes to a user account; fields that are `None` stay unchanged.
+#[derive(Clone, Debug, Default)]
+pub struct UserChanges {
+    pub password_hash: Option<String>,
+    pub role: Option<Role>,
+    pub disabled: Option<bool>,
+    pub email: Option<String>,
+}
+
+/// A user as vouched for by an external identity provider.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExternalIdentity {
+    /// The identity provider, from the `iss` claim
+    pub issuer: String,
+    /// The ID of the user at the identity provider, from the `sub` claim
+    pub subject: String,
+    /// The username of the account the identity is linked to when it is provisioned
+    pub username: String,
+    /// The email address of the user, if known
+    pub email: Option<String>,
+    /// The role the user is given at each login, or `None` to leave it to administrators
+    pub role: Option<Role>,
+}
+
+/// A refresh token issued at login, stored by its SHA-256 hash.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RefreshToken {
+    /// The hex SHA-256 hash of the token
+    pub token_hash: String,
+    /// The user the token was issued to
+    pub user_id: u64,
+    /// When the token stops being accepted
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Checks if a username is acceptable: 3 to 64 letters, digits, `.`, `_`,
+/// `-` or `@`, starting with a letter or digit.
+///
+/// # Returns
+///
+/// `Ok(())`, or why the username is not acceptable
+pub fn validate_username(username: &str) -> Result<(), String> {
+    if username.len() < MIN_USERNAME_LENGTH || username.len() > MAX_USERNAME_LENGTH {
+        return Err(format!(
+            "Username must be {} to {} characters long", MIN_USERNAME_LENGTH, MAX_USERNAME_LENGTH
+        ));
+    }
+    if !username.starts_with(|c: char| c.is_ascii_alphanumeric())
+        || !username.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-' | '@')) {
+        return Err("Username may only contain letters, digits, '.', '_', '-' and '@', and must start with a letter or digit".to_string());
+    }
+    Ok(())
+}
+
+/// Checks if a password is acceptable: 8 to 72 bytes.
+///
+/// # Returns
+///
+/// `Ok(())`, or why the password is not acceptable
+pub fn validate_password(password: &str) -> Result<(), String> {
+    if password.chars().count() < MIN_PASSWORD_LENGTH {
+        return Err(format!("Password must be at least {} characters long", MIN_PASSWORD_LENGTH));
+    }
+    if password.len() > MAX_PASSWORD_LENGTH {
+        return Err(format!("Password must be at most {} bytes long", MAX_PASSWORD_LENGTH));
+    }
+    Ok(())
+}
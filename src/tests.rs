@@ -4,4 +4,16 @@
 
 // Import test modules
 pub mod s3;
-pub mod api; 
\ No newline at end of file
+pub mod api;
+pub mod users;
+pub mod access;
+pub mod apikeys;
+pub mod oidc;
+pub mod audit;
+pub mod content_tests;
+pub mod imaging_tests;
+pub mod archive_tests;
+pub mod extract_tests;
+pub mod inspect_tests;
+pub mod idempotency_tests;
+pub mod jwt_tests;
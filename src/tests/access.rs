@@ -0,0 +1,3 @@
+pub mod memory_store;
+pub mod access_tests;
+pub mod mysql_tests;
@@ -0,0 +1,260 @@
+#![cfg(test)]
+// Tests for roles, grants and the access they give
+// These tests use the in-memory store in place of MySQL
+
+use chrono::Utc;
+use serde_json::json;
+
+use crate::rdlib::access::authorizer::Access;
+use crate::rdlib::access::error::AccessError;
+use crate::rdlib::access::grant::{normalize_prefix, AccessRole, Grant, GrantFilter, NewGrant, Permission, SubjectType};
+use crate::rdlib::auth::principal::Principal;
+use crate::rdlib::s3::types::S3Object;
+use crate::tests::access::memory_store::create_test_authorizer;
+
+fn grant(bucket: &str, prefix: &str, role: AccessRole) -> Grant {
+    Grant {
+        id: 1,
+        subject_type: SubjectType::User,
+        subject: "alice".to_string(),
+        bucket: bucket.to_string(),
+        prefix: prefix.to_string(),
+        role,
+        created_at: Utc::now(),
+    }
+}
+
+fn new_grant(subject_type: SubjectType, subject: &str, bucket: &str, prefix: &str, role: AccessRole) -> NewGrant {
+    NewGrant {
+        subject_type,
+        subject: subject.to_string(),
+        bucket: bucket.to_string(),
+        prefix: prefix.to_string(),
+        role,
+    }
+}
+
+fn principal(claims: serde_json::Value) -> Principal {
+    Principal::from_claims(claims.as_object().unwrap().clone()).unwrap().into_local()
+}
+
+#[test]
+fn test_roles_include_the_permissions_below_them() {
+    assert!(AccessRole::Viewer.allows(Permission::Read));
+    assert!(!AccessRole::Viewer.allows(Permission::Write));
+    assert!(AccessRole::Uploader.allows(Permission::Write));
+    assert!(!AccessRole::Uploader.allows(Permission::Delete));
+    assert!(AccessRole::Editor.allows(Permission::Delete));
+    assert!(!AccessRole::Editor.allows(Permission::Manage));
+    assert!(AccessRole::Admin.allows(Permission::Manage));
+    assert_eq!("editor".parse::<AccessRole>(), Ok(AccessRole::Editor));
+    assert!("owner".parse::<AccessRole>().is_err());
+}
+
+#[test]
+fn test_normalize_prefix() {
+    assert_eq!(normalize_prefix(""), Ok(String::new()));
+    assert_eq!(normalize_prefix("/"), Ok(String::new()));
+    assert_eq!(normalize_prefix("docs"), Ok("docs/".to_string()));
+    assert_eq!(normalize_prefix("/docs/2024/"), Ok("docs/2024/".to_string()));
+    assert!(normalize_prefix("docs//2024").is_err());
+    assert!(normalize_prefix("docs/../secret").is_err());
+}
+
+#[test]
+fn test_roles_are_inherited_down_the_folder_tree() {
+    let access = Access::from_grants(vec![
+        grant("photos", "", AccessRole::Viewer),
+        grant("photos", "team/", AccessRole::Editor),
+        grant("docs", "public/", AccessRole::Uploader),
+    ]);
+
+    assert_eq!(access.role("photos", "beach.jpg"), Some(AccessRole::Viewer));
+    assert_eq!(access.role("photos", "team/2024/party.jpg"), Some(AccessRole::Editor), "The highest role wins");
+    assert!(access.allows("docs", "public/a/b.pdf", Permission::Write));
+    assert!(!access.allows("docs", "public/a/b.pdf", Permission::Delete));
+    assert_eq!(access.role("docs", "private/b.pdf"), None);
+    assert_eq!(access.role("docs", "public"), None, "The grant covers the folder, not a file of the same name");
+    assert_eq!(access.role("other", "file.txt"), None);
+
+    assert!(Access::unrestricted().allows("any", "key", Permission::Manage));
+}
+
+#[test]
+fn test_listings_only_show_what_is_visible() {
+    let access = Access::from_grants(vec![grant("docs", "projects/apollo/", AccessRole::Viewer)]);
+
+    let buckets = vec!["docs".to_string(), "photos".to_string()];
+    assert_eq!(access.filter_buckets(buckets), vec!["docs".to_string()]);
+
+    let object = |name: &str| S3Object { name: name.to_string(), size: 0, last_modified: None, etag: None };
+    let root = vec![object("projects/"), object("hr/"), object("readme.txt")];
+    let names = |objects: Vec<S3Object>| objects.into_iter().map(|o| o.name).collect::<Vec<_>>();
+    assert_eq!(names(access.filter_objects("docs", root)), vec!["projects/"], "Folders leading to a grant are visible");
+
+    let projects = vec![object("projects/apollo/"), object("projects/gemini/"), object("projects/budget.xlsx")];
+    assert_eq!(names(access.filter_objects("docs", projects)), vec!["projects/apollo/"]);
+
+    assert!(access.can_see("docs", ""));
+    assert!(access.can_see("docs", "projects/apollo/plan.pdf"));
+    assert!(!access.can_see("docs", "hr/"));
+    assert!(!access.can_see("photos", ""));
+}
+
+#[test]
+fn test_only_folders_lead_to_grants() {
+    let access = Access::from_grants(vec![grant("docs", "ab/", AccessRole::Viewer)]);
+
+    assert!(access.can_see("docs", "ab/"));
+    assert!(!access.can_see("docs", "a"), "An object whose name starts the granted prefix is not a folder leading to it");
+    assert!(!access.can_see("docs", "ab"));
+    assert!(!access.can_see("docs", "a/"));
+}
+
+#[tokio::test]
+async fn test_principals_get_the_grants_of_their_groups() {
+    let authorizer = create_test_authorizer();
+    authorizer.put_grant(new_grant(SubjectType::User, "alice", "docs", "alice", AccessRole::Editor)).await.unwrap();
+    authorizer.put_grant(new_grant(SubjectType::Group, "staff", "docs", "", AccessRole::Viewer)).await.unwrap();
+
+    let alice = principal(json!({ "sub": "alice", "groups": ["staff"] }));
+    let access = authorizer.access_for(&alice).await.unwrap();
+    assert_eq!(access.role("docs", "alice/notes.txt"), Some(AccessRole::Editor));
+    assert_eq!(access.role("docs", "bob/notes.txt"), Some(AccessRole::Viewer));
+
+    let bob = principal(json!({ "sub": "bob" }));
+    assert_eq!(authorizer.access_for(&bob).await.unwrap().role("docs", "bob/notes.txt"), None);
+
+    let admin = principal(json!({ "sub": "root", "roles": ["admin"] }));
+    assert!(authorizer.access_for(&admin).await.unwrap().is_unrestricted());
+}
+
+#[tokio::test]
+async fn test_principals_of_other_issuers_are_named_by_their_issuer() {
+    let authorizer = create_test_authorizer();
+    authorizer.put_grant(new_grant(SubjectType::User, "alice", "docs", "", AccessRole::Editor)).await.unwrap();
+    authorizer.put_grant(new_grant(SubjectType::User, "https://idp.example.com#alice", "docs", "shared/", AccessRole::Viewer)).await.unwrap();
+
+    let claims = json!({ "sub": "alice", "iss": "https://idp.example.com" });
+    let external = Principal::from_claims(claims.as_object().unwrap().clone()).unwrap();
+    assert_eq!(external.grant_subject(), "https://idp.example.com#alice");
+    let access = authorizer.access_for(&external).await.unwrap();
+    assert_eq!(access.role("docs", "notes.txt"), None, "Tokens of other issuers do not get the grants of local users");
+    assert_eq!(access.role("docs", "shared/notes.txt"), Some(AccessRole::Viewer));
+
+    let local = principal(json!({ "sub": "alice" }));
+    assert_eq!(local.grant_subject(), "alice");
+    assert_eq!(authorizer.access_for(&local).await.unwrap().role("docs", "notes.txt"), Some(AccessRole::Editor));
+}
+
+#[tokio::test]
+async fn test_manage_grants() {
+    let authorizer = create_test_authorizer();
+
+    let (grant, created) = authorizer.put_grant(new_grant(SubjectType::User, "alice", "docs", "/reports", AccessRole::Viewer)).await.unwrap();
+    assert!(created);
+    assert_eq!(grant.prefix, "reports/");
+
+    let (changed, created) = authorizer.put_grant(new_grant(SubjectType::User, "alice", "docs", "reports/", AccessRole::Editor)).await.unwrap();
+    assert!(!created, "Granting a role on the same prefix replaces the role");
+    assert_eq!((changed.id, changed.role), (grant.id, AccessRole::Editor));
+
+    assert!(matches!(
+        authorizer.put_grant(new_grant(SubjectType::User, "", "docs", "", AccessRole::Viewer)).await,
+        Err(AccessError::InvalidSubject(_))
+    ));
+    assert!(matches!(
+        authorizer.put_grant(new_grant(SubjectType::User, "alice", "Not A Bucket", "", AccessRole::Viewer)).await,
+        Err(AccessError::InvalidBucket(_))
+    ));
+    assert!(matches!(
+        authorizer.put_grant(new_grant(SubjectType::User, "alice", "docs", ".rustdok", AccessRole::Viewer)).await,
+        Err(AccessError::InvalidPrefix(_))
+    ));
+
+    authorizer.put_grant(new_grant(SubjectType::Group, "staff", "photos", "", AccessRole::Viewer)).await.unwrap();
+    let filter = GrantFilter { bucket: Some("photos".to_string()), ..GrantFilter::default() };
+    assert_eq!(authorizer.list_grants(&filter).await.unwrap().len(), 1);
+    assert_eq!(authorizer.list_grants(&GrantFilter::default()).await.unwrap().len(), 2);
+
+    authorizer.delete_grant(grant.id).await.unwrap();
+    assert_eq!(authorizer.delete_grant(grant.id).await, Err(AccessError::NotFound));
+    authorizer.put_grant(new_grant(SubjectType::User, "alice", "docs", "", AccessRole::Viewer)).await.unwrap();
+    authorizer.forget_user("alice").await.unwrap();
+    assert_eq!(authorizer.list_grants(&GrantFilter::default()).await.unwrap().len(), 1, "Only the grant of the group is left");
+}
+
+#[tokio::test]
+async fn test_nested_groups() {
+    let authorizer = create_test_authorizer();
+    let engineering = authorizer.create_group("engineering", "All engineers").await.unwrap();
+    let backend = authorizer.create_group("backend", "").await.unwrap();
+    let oncall = authorizer.create_group("oncall", "").await.unwrap();
+    authorizer.add_member(engineering.id, SubjectType::Group, "backend").await.unwrap();
+    authorizer.add_member(backend.id, SubjectType::User, "alice").await.unwrap();
+    authorizer.add_member(backend.id, SubjectType::Group, "oncall").await.unwrap();
+    assert!(!authorizer.add_member(backend.id, SubjectType::User, "alice").await.unwrap(), "Adding a member twice changes nothing");
+
+    assert_eq!(authorizer.effective_groups("alice", &[]).await.unwrap(), vec!["backend", "engineering"]);
+    assert_eq!(
+        authorizer.effective_groups("bob", &["oncall".to_string()]).await.unwrap(),
+        vec!["backend", "engineering", "oncall"],
+        "Claimed groups count like memberships"
+    );
+
+    authorizer.put_grant(new_grant(SubjectType::Group, "engineering", "docs", "", AccessRole::Viewer)).await.unwrap();
+    authorizer.put_grant(new_grant(SubjectType::Group, "backend", "docs", "services/", AccessRole::Editor)).await.unwrap();
+    let alice = principal(json!({ "sub": "alice" }));
+    let access = authorizer.access_for(&alice).await.unwrap();
+    assert_eq!(access.role("docs", "readme.md"), Some(AccessRole::Viewer));
+    assert_eq!(access.role("docs", "services/api.md"), Some(AccessRole::Editor));
+
+    let explained = authorizer.effective_access("alice", false, "docs", "services/api.md").await.unwrap();
+    assert_eq!(explained.role, Some(AccessRole::Editor));
+    assert_eq!(explained.grants.len(), 2);
+    let explained = authorizer.effective_access("alice", false, "docs", "/services").await.unwrap();
+    assert_eq!((explained.prefix.as_str(), explained.role), ("services/", Some(AccessRole::Editor)), "Prefixes are normalized like those of grants");
+    assert!(matches!(
+        authorizer.effective_access("alice", false, "docs", "services/../hr").await,
+        Err(AccessError::InvalidPrefix(_))
+    ));
+    let explained = authorizer.effective_access("carol", false, "docs", "").await.unwrap();
+    assert_eq!((explained.role, explained.groups.len()), (None, 0));
+    assert_eq!(authorizer.effective_access("root", true, "docs", "").await.unwrap().role, Some(AccessRole::Admin));
+
+    assert!(matches!(
+        authorizer.add_member(oncall.id, SubjectType::Group, "engineering").await,
+        Err(AccessError::InvalidMember(_))
+    ), "Groups cannot contain themselves");
+    assert!(matches!(authorizer.add_member(oncall.id, SubjectType::Group, "oncall").await, Err(AccessError::InvalidMember(_))));
+    assert!(matches!(authorizer.add_member(oncall.id, SubjectType::Group, "missing").await, Err(AccessError::InvalidMember(_))));
+    assert!(matches!(authorizer.create_group("backend", "").await, Err(AccessError::GroupTaken(_))));
+    assert!(matches!(authorizer.create_group("-bad name", "").await, Err(AccessError::InvalidGroupName(_))));
+
+    authorizer.delete_group(backend.id).await.unwrap();
+    assert!(authorizer.effective_groups("alice", &[]).await.unwrap().is_empty());
+    assert!(authorizer.list_members(engineering.id).await.unwrap().is_empty());
+    assert_eq!(authorizer.list_grants(&GrantFilter::default()).await.unwrap().len(), 1, "The grants of the group are revoked");
+    assert_eq!(authorizer.remove_member(engineering.id, SubjectType::Group, "backend").await, Err(AccessError::MemberNotFound));
+    assert_eq!(authorizer.get_group(backend.id).await, Err(AccessError::GroupNotFound));
+}
+
+#[tokio::test]
+async fn test_sync_groups() {
+    let authorizer = create_test_authorizer();
+    let engineering = authorizer.create_group("engineering", "").await.unwrap();
+    let backend = authorizer.create_group("backend", "").await.unwrap();
+    let sales = authorizer.create_group("sales", "").await.unwrap();
+    authorizer.add_member(sales.id, SubjectType::User, "alice").await.unwrap();
+    authorizer.add_member(backend.id, SubjectType::User, "bob").await.unwrap();
+
+    let claimed = ["engineering".to_string(), "backend".to_string(), "unknown".to_string()];
+    assert_eq!(authorizer.sync_groups("alice", &claimed).await.unwrap(), vec!["backend", "engineering"]);
+    assert_eq!(authorizer.effective_groups("alice", &[]).await.unwrap(), vec!["backend", "engineering"], "Groups not claimed are left");
+    assert!(authorizer.list_groups().await.unwrap().iter().all(|group| group.name != "unknown"), "Groups are not created");
+    assert_eq!(authorizer.list_members(backend.id).await.unwrap().len(), 2, "Other members stay");
+
+    assert!(authorizer.sync_groups("alice", &[]).await.unwrap().is_empty());
+    assert!(authorizer.effective_groups("alice", &[]).await.unwrap().is_empty());
+    assert!(authorizer.list_members(engineering.id).await.unwrap().is_empty());
+}
@@ -0,0 +1,225 @@
+#![cfg(test)]
+// In-memory grant and group stores standing in for MySQL in tests
+
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+use crate::rdlib::access::authorizer::Authorizer;
+use crate::rdlib::access::error::AccessError;
+use crate::rdlib::access::grant::{Grant, GrantFilter, NewGrant, SubjectType};
+use crate::rdlib::access::group::{would_contain_itself, Group, GroupMember, NewGroup};
+use crate::rdlib::access::store::{GrantStore, GroupStore};
+
+#[derive(Default)]
+struct State {
+    next_id: u64,
+    grants: Vec<Grant>,
+}
+
+/// Keeps grants in memory, with the same behavior as `MySqlGrantStore`.
+#[derive(Default)]
+pub struct MemoryGrantStore {
+    state: Mutex<State>,
+    /// Whether deleting the grants of a subject fails, as if the database were down
+    failing_deletes: bool,
+}
+
+#[async_trait]
+impl GrantStore for MemoryGrantStore {
+    async fn grants_for(&self, user: &str, groups: &[String]) -> Result<Vec<Grant>, AccessError> {
+        Ok(self.state.lock().unwrap().grants.iter()
+            .filter(|g| match g.subject_type {
+                SubjectType::User => g.subject == user,
+                SubjectType::Group => groups.contains(&g.subject),
+            })
+            .cloned()
+            .collect())
+    }
+
+    async fn list_grants(&self, filter: &GrantFilter) -> Result<Vec<Grant>, AccessError> {
+        Ok(self.state.lock().unwrap().grants.iter()
+            .filter(|g| filter.subject_type.is_none_or(|t| g.subject_type == t))
+            .filter(|g| filter.subject.as_ref().is_none_or(|s| g.subject == *s))
+            .filter(|g| filter.bucket.as_ref().is_none_or(|b| g.bucket == *b))
+            .cloned()
+            .collect())
+    }
+
+    async fn put_grant(&self, grant: NewGrant, now: DateTime<Utc>) -> Result<(Grant, bool), AccessError> {
+        let mut state = self.state.lock().unwrap();
+        let existing = state.grants.iter_mut().find(|g| {
+            g.subject_type == grant.subject_type && g.subject == grant.subject && g.bucket == grant.bucket && g.prefix == grant.prefix
+        });
+        if let Some(existing) = existing {
+            existing.role = grant.role;
+            return Ok((existing.clone(), false));
+        }
+
+        state.next_id += 1;
+        let grant = Grant {
+            id: state.next_id,
+            subject_type: grant.subject_type,
+            subject: grant.subject,
+            bucket: grant.bucket,
+            prefix: grant.prefix,
+            role: grant.role,
+            created_at: now,
+        };
+        state.grants.push(grant.clone());
+        Ok((grant, true))
+    }
+
+    async fn delete_grant(&self, id: u64) -> Result<bool, AccessError> {
+        let mut state = self.state.lock().unwrap();
+        let count = state.grants.len();
+        state.grants.retain(|g| g.id != id);
+        Ok(state.grants.len() < count)
+    }
+
+    async fn delete_subject_grants(&self, subject_type: SubjectType, subject: &str) -> Result<u64, AccessError> {
+        if self.failing_deletes {
+            return Err(AccessError::Database("connection refused".to_string()));
+        }
+        let mut state = self.state.lock().unwrap();
+        let count = state.grants.len();
+        state.grants.retain(|g| g.subject_type != subject_type || g.subject != subject);
+        Ok((count - state.grants.len()) as u64)
+    }
+}
+
+#[derive(Default)]
+struct GroupState {
+    next_id: u64,
+    groups: Vec<Group>,
+    members: Vec<(u64, GroupMember)>,
+}
+
+/// Keeps groups in memory, with the same behavior as `MySqlGroupStore`.
+#[derive(Default)]
+pub struct MemoryGroupStore {
+    state: Mutex<GroupState>,
+    /// The grants deleted with their group
+    grants: Arc<MemoryGrantStore>,
+}
+
+#[async_trait]
+impl GroupStore for MemoryGroupStore {
+    async fn list_groups(&self) -> Result<Vec<Group>, AccessError> {
+        let mut groups = self.state.lock().unwrap().groups.clone();
+        groups.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(groups)
+    }
+
+    async fn get_group(&self, id: u64) -> Result<Option<Group>, AccessError> {
+        Ok(self.state.lock().unwrap().groups.iter().find(|g| g.id == id).cloned())
+    }
+
+    async fn find_group(&self, name: &str) -> Result<Option<Group>, AccessError> {
+        Ok(self.state.lock().unwrap().groups.iter().find(|g| g.name == name).cloned())
+    }
+
+    async fn create_group(&self, group: NewGroup, now: DateTime<Utc>) -> Result<Group, AccessError> {
+        let mut state = self.state.lock().unwrap();
+        if state.groups.iter().any(|g| g.name == group.name) {
+            return Err(AccessError::GroupTaken(group.name));
+        }
+        state.next_id += 1;
+        let group = Group { id: state.next_id, name: group.name, description: group.description, created_at: now };
+        state.groups.push(group.clone());
+        Ok(group)
+    }
+
+    async fn delete_group(&self, id: u64) -> Result<bool, AccessError> {
+        let mut state = self.state.lock().unwrap();
+        let Some(index) = state.groups.iter().position(|g| g.id == id) else {
+            return Ok(false);
+        };
+        let group = state.groups.remove(index);
+        state.members.retain(|(group_id, m)| {
+            *group_id != id && !(m.member_type == SubjectType::Group && m.member == group.name)
+        });
+        self.grants.state.lock().unwrap().grants.retain(|g| g.subject_type != SubjectType::Group || g.subject != group.name);
+        Ok(true)
+    }
+
+    async fn list_members(&self, group_id: u64) -> Result<Vec<GroupMember>, AccessError> {
+        let mut members = self.state.lock().unwrap().members.iter()
+            .filter(|(id, _)| *id == group_id)
+            .map(|(_, m)| m.clone())
+            .collect::<Vec<_>>();
+        members.sort_by(|a, b| (a.member_type == SubjectType::Group, &a.member).cmp(&(b.member_type == SubjectType::Group, &b.member)));
+        Ok(members)
+    }
+
+    async fn add_member(&self, group_id: u64, member_type: SubjectType, member: &str, now: DateTime<Utc>) -> Result<bool, AccessError> {
+        let mut state = self.state.lock().unwrap();
+        if state.members.iter().any(|(id, m)| *id == group_id && m.member_type == member_type && m.member == member) {
+            return Ok(false);
+        }
+        state.members.push((group_id, GroupMember { member_type, member: member.to_string(), added_at: now }));
+        Ok(true)
+    }
+
+    async fn add_member_group(&self, group_id: u64, member: &str, now: DateTime<Utc>) -> Result<bool, AccessError> {
+        let mut state = self.state.lock().unwrap();
+        let group = state.groups.iter().find(|g| g.id == group_id).ok_or(AccessError::GroupNotFound)?.name.clone();
+        if !state.groups.iter().any(|g| g.name == member) {
+            return Err(AccessError::InvalidMember(format!("Group '{}' does not exist", member)));
+        }
+        let nesting = state.members.iter()
+            .filter(|(_, m)| m.member_type == SubjectType::Group)
+            .filter_map(|(id, m)| state.groups.iter().find(|g| g.id == *id).map(|g| (g.name.clone(), m.member.clone())))
+            .collect::<Vec<_>>();
+        if would_contain_itself(&nesting, &group, member) {
+            return Err(AccessError::InvalidMember(format!("Group '{}' contains '{}' and cannot become its member", member, group)));
+        }
+
+        if state.members.iter().any(|(id, m)| *id == group_id && m.member_type == SubjectType::Group && m.member == member) {
+            return Ok(false);
+        }
+        state.members.push((group_id, GroupMember { member_type: SubjectType::Group, member: member.to_string(), added_at: now }));
+        Ok(true)
+    }
+
+    async fn remove_member(&self, group_id: u64, member_type: SubjectType, member: &str) -> Result<bool, AccessError> {
+        let mut state = self.state.lock().unwrap();
+        let count = state.members.len();
+        state.members.retain(|(id, m)| *id != group_id || m.member_type != member_type || m.member != member);
+        Ok(state.members.len() < count)
+    }
+
+    async fn parent_groups(&self, member_type: SubjectType, members: &[String]) -> Result<Vec<String>, AccessError> {
+        let state = self.state.lock().unwrap();
+        let mut names = state.members.iter()
+            .filter(|(_, m)| m.member_type == member_type && members.contains(&m.member))
+            .filter_map(|(id, _)| state.groups.iter().find(|g| g.id == *id).map(|g| g.name.clone()))
+            .collect::<Vec<_>>();
+        names.sort();
+        names.dedup();
+        Ok(names)
+    }
+
+    async fn delete_memberships(&self, member_type: SubjectType, member: &str) -> Result<u64, AccessError> {
+        let mut state = self.state.lock().unwrap();
+        let count = state.members.len();
+        state.members.retain(|(_, m)| m.member_type != member_type || m.member != member);
+        Ok((count - state.members.len()) as u64)
+    }
+}
+
+/// Creates an authorizer on in-memory stores.
+pub fn create_test_authorizer() -> Arc<Authorizer> {
+    let grants = Arc::new(MemoryGrantStore::default());
+    let groups = MemoryGroupStore { grants: grants.clone(), ..MemoryGroupStore::default() };
+    Arc::new(Authorizer::new(grants, Arc::new(groups)))
+}
+
+/// Creates an authorizer on in-memory stores that fails to revoke the grants
+/// of deleted users.
+pub fn create_failing_test_authorizer() -> Arc<Authorizer> {
+    let grants = Arc::new(MemoryGrantStore { failing_deletes: true, ..MemoryGrantStore::default() });
+    let groups = MemoryGroupStore { grants: grants.clone(), ..MemoryGroupStore::default() };
+    Arc::new(Authorizer::new(grants, Arc::new(groups)))
+}
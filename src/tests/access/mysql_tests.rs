@@ -0,0 +1,81 @@
+#![cfg(test)]
+// Tests for the MySQL grant and group stores
+// These tests need a MySQL database and are ignored by default; see the
+// MySQL user store tests for how to start one and run them
+
+use chrono::Utc;
+use uuid::Uuid;
+
+use crate::rdlib::access::error::AccessError;
+use crate::rdlib::access::grant::{AccessRole, GrantFilter, NewGrant, SubjectType};
+use crate::rdlib::access::group::NewGroup;
+use crate::rdlib::access::mysql::{MySqlGrantStore, MySqlGroupStore};
+use crate::rdlib::access::store::{GrantStore, GroupStore};
+use crate::rdlib::users::mysql::{connect_pool, MySqlUserStore};
+
+#[tokio::test]
+#[ignore]
+async fn test_mysql_grants() {
+    let url = std::env::var("RUSTDOK_TEST_DATABASE_URL").expect("RUSTDOK_TEST_DATABASE_URL must be set");
+    MySqlUserStore::connect(&url).await.unwrap().migrate().await.unwrap();
+    let store = MySqlGrantStore::new(connect_pool(&url).await.unwrap());
+    let now = Utc::now();
+
+    let user = format!("user-{}", Uuid::new_v4().simple());
+    let group = format!("group-{}", Uuid::new_v4().simple());
+    let grant = |subject_type, subject: &str, prefix: &str, role| NewGrant {
+        subject_type,
+        subject: subject.to_string(),
+        bucket: "docs".to_string(),
+        prefix: prefix.to_string(),
+        role,
+    };
+
+    let (viewer, created) = store.put_grant(grant(SubjectType::User, &user, "", AccessRole::Viewer), now).await.unwrap();
+    assert!(created);
+    let (editor, created) = store.put_grant(grant(SubjectType::User, &user, "", AccessRole::Editor), now).await.unwrap();
+    assert!(!created);
+    assert_eq!((editor.id, editor.role), (viewer.id, AccessRole::Editor));
+    store.put_grant(grant(SubjectType::Group, &group, "team/", AccessRole::Uploader), now).await.unwrap();
+
+    assert_eq!(store.grants_for(&user, &[]).await.unwrap().len(), 1);
+    assert_eq!(store.grants_for(&user, std::slice::from_ref(&group)).await.unwrap().len(), 2);
+    let filter = GrantFilter { subject: Some(group.clone()), ..GrantFilter::default() };
+    assert_eq!(store.list_grants(&filter).await.unwrap()[0].prefix, "team/");
+
+    assert!(store.delete_grant(editor.id).await.unwrap());
+    assert!(!store.delete_grant(editor.id).await.unwrap());
+    assert_eq!(store.delete_subject_grants(SubjectType::Group, &group).await.unwrap(), 1);
+}
+
+#[tokio::test]
+#[ignore]
+async fn test_mysql_groups() {
+    let url = std::env::var("RUSTDOK_TEST_DATABASE_URL").expect("RUSTDOK_TEST_DATABASE_URL must be set");
+    MySqlUserStore::connect(&url).await.unwrap().migrate().await.unwrap();
+    let store = MySqlGroupStore::new(connect_pool(&url).await.unwrap());
+    let now = Utc::now();
+
+    let name = |prefix: &str| format!("{}-{}", prefix, Uuid::new_v4().simple());
+    let new_group = |name: &str| NewGroup { name: name.to_string(), description: String::new() };
+    let (outer, inner, user) = (name("outer"), name("inner"), name("user"));
+
+    let outer_group = store.create_group(new_group(&outer), now).await.unwrap();
+    let inner_group = store.create_group(new_group(&inner), now).await.unwrap();
+    assert!(matches!(store.create_group(new_group(&outer), now).await, Err(AccessError::GroupTaken(_))));
+    assert_eq!(store.find_group(&inner).await.unwrap(), Some(inner_group.clone()));
+
+    assert!(store.add_member(outer_group.id, SubjectType::Group, &inner, now).await.unwrap());
+    assert!(store.add_member(inner_group.id, SubjectType::User, &user, now).await.unwrap());
+    assert!(!store.add_member(inner_group.id, SubjectType::User, &user, now).await.unwrap());
+    assert_eq!(store.parent_groups(SubjectType::User, std::slice::from_ref(&user)).await.unwrap(), vec![inner.clone()]);
+    assert_eq!(store.parent_groups(SubjectType::Group, std::slice::from_ref(&inner)).await.unwrap(), vec![outer.clone()]);
+    assert_eq!(store.list_members(inner_group.id).await.unwrap()[0].member, user);
+
+    assert!(store.delete_group(inner_group.id).await.unwrap());
+    assert!(store.list_members(outer_group.id).await.unwrap().is_empty(), "Deleted groups leave the groups containing them");
+    assert!(store.parent_groups(SubjectType::User, std::slice::from_ref(&user)).await.unwrap().is_empty());
+    assert!(!store.remove_member(outer_group.id, SubjectType::Group, &inner).await.unwrap());
+    assert!(store.delete_group(outer_group.id).await.unwrap());
+    assert_eq!(store.get_group(outer_group.id).await.unwrap(), None);
+}
@@ -1,2 +1,5 @@
 #[cfg(test)]
-pub mod v1; 
\ No newline at end of file
+pub mod v1;
+pub mod idempotency;
+pub mod error;
+pub mod auth;
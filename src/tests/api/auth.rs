@@ -0,0 +1,106 @@
+#![cfg(test)]
+// Tests for the authentication middleware
+// Requests only go as far as the handlers that do not contact S3
+
+use actix_web::http::StatusCode;
+use actix_web::{test, web, App};
+use chrono::Utc;
+use jsonwebtoken::{encode, EncodingKey, Header};
+use serde_json::{json, Value};
+
+use crate::api::auth::{bearer_token, is_public_path, Authentication};
+use crate::api::config::configure_api_v1;
+use crate::rdlib::auth::jwt::JwtVerifier;
+use crate::tests::s3::service_test_helpers::create_test_s3_service;
+
+const SECRET: &[u8] = b"a-test-secret-of-at-least-32-bytes!";
+
+fn token(claims: Value) -> String {
+    encode(&Header::default(), &claims, &EncodingKey::from_secret(SECRET)).unwrap()
+}
+
+#[actix_web::test]
+async fn test_bearer_token() {
+    let req = test::TestRequest::default().insert_header(("Authorization", "Bearer abc.def.ghi")).to_http_request();
+    assert_eq!(bearer_token(req.headers()), Some("abc.def.ghi"));
+    let req = test::TestRequest::default().insert_header(("Authorization", "bearer abc")).to_http_request();
+    assert_eq!(bearer_token(req.headers()), Some("abc"));
+    let req = test::TestRequest::default().insert_header(("Authorization", "Basic YWxpY2U6c2VjcmV0")).to_http_request();
+    assert_eq!(bearer_token(req.headers()), None);
+    let req = test::TestRequest::default().insert_header(("Authorization", "Bearer ")).to_http_request();
+    assert_eq!(bearer_token(req.headers()), None);
+
+    assert!(is_public_path("/api/v1/drop/bucket/token"));
+    assert!(!is_public_path("/api/v1/bucket/bucket/drops"));
+}
+
+#[actix_web::test]
+async fn test_requests_need_a_valid_token() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(create_test_s3_service().await))
+            .service(configure_api_v1().wrap(Authentication::new(JwtVerifier::new().with_secret(SECRET))))
+    ).await;
+
+    let req = test::TestRequest::get().uri("/api/v1/auth/me").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(resp.headers().get("WWW-Authenticate").unwrap(), "Bearer realm=\"rustdok\"");
+    let problem: Value = test::read_body_json(resp).await;
+    assert_eq!(problem["code"], "unauthorized");
+
+    let expired = token(json!({ "sub": "alice", "exp": Utc::now().timestamp() - 3600 }));
+    let req = test::TestRequest::delete()
+        .uri("/api/v1/bucket/test-bucket")
+        .insert_header(("Authorization", format!("Bearer {}", expired)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let challenge = resp.headers().get("WWW-Authenticate").unwrap().to_str().unwrap().to_string();
+    assert!(challenge.contains("error=\"invalid_token\""));
+    let problem: Value = test::read_body_json(resp).await;
+    assert_eq!(problem["code"], "invalid_token");
+    assert_eq!(problem["detail"], "The token has expired");
+
+    let valid = token(json!({ "sub": "alice", "exp": Utc::now().timestamp() + 3600, "role": "admin" }));
+    let req = test::TestRequest::get()
+        .uri("/api/v1/auth/me")
+        .insert_header(("Authorization", format!("Bearer {}", valid)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let principal: Value = test::read_body_json(resp).await;
+    assert_eq!(principal["subject"], "alice");
+    assert_eq!(principal["claims"]["role"], "admin");
+}
+
+#[actix_web::test]
+async fn test_drop_links_stay_public() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(create_test_s3_service().await))
+            .service(configure_api_v1().wrap(Authentication::new(JwtVerifier::new().with_secret(SECRET))))
+    ).await;
+
+    let req = test::TestRequest::get().uri("/api/v1/drop/test-bucket/not-a-token.json").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn test_disabled_authentication() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(create_test_s3_service().await))
+            .service(configure_api_v1().wrap(Authentication::disabled()))
+    ).await;
+
+    // Requests pass, but there is no principal to report
+    let req = test::TestRequest::get().uri("/api/v1/nothing-here").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    let req = test::TestRequest::get().uri("/api/v1/auth/me").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+}
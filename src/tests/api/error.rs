@@ -0,0 +1,145 @@
+#![cfg(test)]
+// Tests for the problem documents returned by the API
+// Requests through the v1 scope only cover errors raised before S3 is contacted
+
+use actix_web::http::StatusCode;
+use actix_web::{body, test, web, App, HttpResponse, ResponseError};
+use serde_json::{json, Value};
+
+use crate::api::config::configure_api_v1;
+use crate::api::error::{ApiError, ErrorCode, PROBLEM_CONTENT_TYPE};
+use crate::api::request_id::RequestId;
+use crate::rdlib::s3::error::S3Error;
+use crate::tests::s3::service_test_helpers::create_test_s3_service;
+
+async fn problem_of(response: HttpResponse) -> Value {
+    let bytes = body::to_bytes(response.into_body()).await.unwrap();
+    serde_json::from_slice(&bytes).unwrap()
+}
+
+#[actix_web::test]
+async fn test_s3_error_status_codes() {
+    let message = || "message".to_string();
+    let cases = [
+        (S3Error::NotFound(message()), StatusCode::NOT_FOUND, "not_found"),
+        (S3Error::BucketNotFound(message()), StatusCode::NOT_FOUND, "bucket_not_found"),
+        (S3Error::BucketAlreadyExists(message()), StatusCode::CONFLICT, "bucket_already_exists"),
+        (S3Error::BucketNotEmpty(message()), StatusCode::CONFLICT, "bucket_not_empty"),
+        (S3Error::AccessDenied(message()), StatusCode::BAD_GATEWAY, "storage_access_denied"),
+        (S3Error::PreconditionFailed(message()), StatusCode::PRECONDITION_FAILED, "precondition_failed"),
+        (S3Error::InvalidArgument(message()), StatusCode::BAD_REQUEST, "invalid_argument"),
+        (S3Error::SlowDown(message()), StatusCode::SERVICE_UNAVAILABLE, "slow_down"),
+        (S3Error::Timeout(message()), StatusCode::GATEWAY_TIMEOUT, "storage_timeout"),
+        (S3Error::Unavailable(message()), StatusCode::BAD_GATEWAY, "storage_unavailable"),
+        (S3Error::AwsError(message()), StatusCode::INTERNAL_SERVER_ERROR, "storage_error"),
+        (S3Error::Other(message()), StatusCode::INTERNAL_SERVER_ERROR, "storage_error"),
+    ];
+
+    for (error, status, code) in cases {
+        assert_eq!(error.status_code(), status, "{:?}", error);
+        assert_eq!(error.error_response().status(), status, "{:?}", error);
+        assert_eq!(ApiError::from(error).code().as_str(), code);
+    }
+}
+
+#[actix_web::test]
+async fn test_problem_document() {
+    let response = ApiError::new(ErrorCode::InvalidKey, "Invalid key: parent directory references are not allowed")
+        .field("key")
+        .extension("files", json!([]))
+        .error_response();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(response.headers().get("Content-Type").unwrap(), PROBLEM_CONTENT_TYPE);
+
+    let problem = problem_of(response).await;
+    assert_eq!(problem["type"], "urn:rustdok:problem:invalid_key");
+    assert_eq!(problem["title"], "Invalid key");
+    assert_eq!(problem["status"], 400);
+    assert_eq!(problem["code"], "invalid_key");
+    assert_eq!(problem["detail"], "Invalid key: parent directory references are not allowed");
+    assert_eq!(problem["field"], "key");
+    assert_eq!(problem["files"], json!([]));
+    // Outside of the RequestId middleware there is no request to refer to
+    assert!(problem.get("request_id").is_none());
+    assert!(problem.get("instance").is_none());
+}
+
+#[actix_web::test]
+async fn test_s3_error_details() {
+    let problem = problem_of(S3Error::NotFound("NoSuchKey: The specified key does not exist.".to_string()).error_response()).await;
+    assert_eq!(problem["code"], "not_found");
+    assert_eq!(problem["detail"], "NoSuchKey: The specified key does not exist.");
+
+    // Server-side failures do not pass on what the storage backend reported
+    let problem = problem_of(S3Error::AwsError("InternalError: dispatch to 10.0.0.5 failed".to_string()).error_response()).await;
+    assert_eq!(problem["code"], "storage_error");
+    assert!(!problem["detail"].as_str().unwrap().contains("10.0.0.5"));
+}
+
+#[actix_web::test]
+async fn test_slow_down_sets_retry_after() {
+    let response = S3Error::SlowDown("SlowDown: Please reduce your request rate.".to_string()).error_response();
+    assert_eq!(response.headers().get("Retry-After").unwrap(), "1");
+
+    let response = S3Error::AwsError("InternalError".to_string()).error_response();
+    assert!(response.headers().get("Retry-After").is_none());
+}
+
+#[actix_web::test]
+async fn test_problem_refers_to_request() {
+    let app = test::init_service(
+        App::new()
+            .wrap(RequestId)
+            .route("/fail", web::get().to(|| async {
+                Err::<HttpResponse, _>(ApiError::new(ErrorCode::InternalError, "Failed"))
+            }))
+    ).await;
+
+    let req = test::TestRequest::get().uri("/fail").insert_header(("X-Request-Id", "req-123")).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.headers().get("X-Request-Id").unwrap(), "req-123");
+    let problem: Value = test::read_body_json(resp).await;
+    assert_eq!(problem["request_id"], "req-123");
+    assert_eq!(problem["instance"], "/fail");
+
+    // Unusable IDs are replaced by a generated one
+    let req = test::TestRequest::get().uri("/fail").insert_header(("X-Request-Id", "bad id\t")).to_request();
+    let resp = test::call_service(&app, req).await;
+    let id = resp.headers().get("X-Request-Id").unwrap().to_str().unwrap().to_string();
+    assert_ne!(id, "bad id\t");
+    let problem: Value = test::read_body_json(resp).await;
+    assert_eq!(problem["request_id"], id);
+}
+
+#[actix_web::test]
+async fn test_extractor_errors_are_problems() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(create_test_s3_service().await))
+            .service(configure_api_v1())
+    ).await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/buckets")
+        .set_json(json!({ "title": "test-bucket" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let problem: Value = test::read_body_json(resp).await;
+    assert_eq!(problem["code"], "invalid_request");
+    assert_eq!(problem["field"], "name");
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/bucket/test-bucket/thumbnail/photo.png?w=wide")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let problem: Value = test::read_body_json(resp).await;
+    assert_eq!(problem["code"], "invalid_request");
+
+    let req = test::TestRequest::get().uri("/api/v1/nothing-here").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    let problem: Value = test::read_body_json(resp).await;
+    assert_eq!(problem["code"], "not_found");
+}
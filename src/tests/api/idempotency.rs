@@ -0,0 +1,167 @@
+#![cfg(test)]
+// Tests for the idempotency middleware
+// These tests use a counting handler in place of the API endpoints
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use actix_web::{test, web, App, HttpMessage, HttpResponse};
+use actix_web::dev::Service;
+use actix_web::http::StatusCode;
+use serde_json::json;
+use crate::api::idempotency::{Idempotency, REPLAYED_HEADER};
+use crate::rdlib::auth::principal::Principal;
+
+/// Builds an app whose handler counts its calls and answers with the count,
+/// or with a server error for `/fail`. Requests are authenticated as the
+/// subject in the `X-Subject` header, if any.
+macro_rules! counting_app {
+    ($calls:expr) => {{
+        let calls = $calls.clone();
+        test::init_service(
+            App::new().service(
+                web::resource("/{name}")
+                    .wrap(Idempotency)
+                    .wrap_fn(|req, srv| {
+                        let subject = req.headers().get("X-Subject").and_then(|v| v.to_str().ok()).map(str::to_string);
+                        if let Some(subject) = subject {
+                            let claims = json!({ "sub": subject }).as_object().unwrap().clone();
+                            req.extensions_mut().insert(Principal::from_claims(claims).unwrap());
+                        }
+                        srv.call(req)
+                    })
+                    .route(web::post().to(move |name: web::Path<String>| {
+                        let count = calls.fetch_add(1, Ordering::SeqCst) + 1;
+                        async move {
+                            match name.as_str() {
+                                "fail" => HttpResponse::InternalServerError().finish(),
+                                _ => HttpResponse::Created().insert_header(("X-Count", count.to_string())).body(count.to_string()),
+                            }
+                        }
+                    }))
+            )
+        ).await
+    }};
+}
+
+#[actix_web::test]
+async fn test_replays_response_for_known_key() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let app = counting_app!(calls);
+
+    for _ in 0..2 {
+        let req = test::TestRequest::post()
+            .uri("/replay")
+            .insert_header(("Idempotency-Key", "replay-key"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        assert_eq!(resp.headers().get("X-Count").unwrap(), "1");
+        assert_eq!(test::read_body(resp).await, "1");
+    }
+    assert_eq!(calls.load(Ordering::SeqCst), 1, "The handler should only run once");
+
+    let req = test::TestRequest::post()
+        .uri("/replay")
+        .insert_header(("Idempotency-Key", "replay-key"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.headers().get(REPLAYED_HEADER).unwrap(), "true");
+
+    let req = test::TestRequest::post().uri("/replay").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.headers().get("X-Count").unwrap(), "2", "Requests without a key always run");
+}
+
+#[actix_web::test]
+async fn test_rejects_invalid_and_reused_keys() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let app = counting_app!(calls);
+
+    let req = test::TestRequest::post()
+        .uri("/first")
+        .insert_header(("Idempotency-Key", "reused-key"))
+        .insert_header(("X-Subject", "alice"))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::CREATED);
+
+    let req = test::TestRequest::post()
+        .uri("/second")
+        .insert_header(("Idempotency-Key", "reused-key"))
+        .insert_header(("X-Subject", "alice"))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    let req = test::TestRequest::post()
+        .uri("/first")
+        .insert_header(("Idempotency-Key", "x".repeat(256)))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+}
+
+#[actix_web::test]
+async fn test_server_errors_are_not_kept() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let app = counting_app!(calls);
+
+    for _ in 0..2 {
+        let req = test::TestRequest::post()
+            .uri("/fail")
+            .insert_header(("Idempotency-Key", "failing-key"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(!resp.headers().contains_key(REPLAYED_HEADER));
+    }
+    assert_eq!(calls.load(Ordering::SeqCst), 2, "Failed requests should be retried");
+}
+
+#[actix_web::test]
+async fn test_keys_are_bound_to_the_body() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let app = counting_app!(calls);
+
+    for (body, status) in [("first", StatusCode::CREATED), ("first", StatusCode::CREATED), ("second", StatusCode::UNPROCESSABLE_ENTITY)] {
+        let req = test::TestRequest::post()
+            .uri("/body")
+            .insert_header(("Idempotency-Key", "body-key"))
+            .set_payload(body)
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), status, "Body '{}'", body);
+    }
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+}
+
+#[actix_web::test]
+async fn test_keys_are_scoped_to_the_client() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let app = counting_app!(calls);
+
+    for subject in ["alice", "bob"] {
+        let req = test::TestRequest::post()
+            .uri("/scoped")
+            .insert_header(("Idempotency-Key", "shared-key"))
+            .insert_header(("X-Subject", subject))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(!resp.headers().contains_key(REPLAYED_HEADER), "{} should not get another client's response", subject);
+    }
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+}
+
+#[actix_web::test]
+async fn test_anonymous_keys_are_scoped_to_the_path() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let app = counting_app!(calls);
+
+    for path in ["/drop-a", "/drop-b"] {
+        let req = test::TestRequest::post()
+            .uri(path)
+            .insert_header(("Idempotency-Key", "anonymous-key"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::CREATED, "{} should not collide with another path", path);
+        assert!(!resp.headers().contains_key(REPLAYED_HEADER));
+    }
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+}
@@ -1,3 +1,19 @@
 #[cfg(test)]
+#[allow(clippy::empty_line_after_outer_attr, clippy::await_holding_lock, clippy::needless_borrows_for_generic_args)]
 pub mod buckets;
-pub mod objects; 
\ No newline at end of file
+#[allow(clippy::empty_line_after_outer_attr, clippy::needless_borrows_for_generic_args)]
+pub mod objects; 
+pub mod drops;
+pub mod previews;
+pub mod archives;
+
+pub mod uploads;
+
+pub mod users;
+pub mod grants;
+pub mod groups;
+pub mod service_accounts;
+pub mod oidc;
+pub mod two_factor;
+pub mod sessions;
+pub mod audit;
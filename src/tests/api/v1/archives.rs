@@ -0,0 +1,53 @@
+#![cfg(test)]
+// Tests for the archive API endpoints
+// These tests cover the request validation that happens before S3 is contacted
+
+use actix_web::{test, web, App};
+use actix_web::http::StatusCode;
+use serde_json::json;
+use crate::api::config::configure_api_v1;
+use crate::tests::s3::service_test_helpers::create_test_s3_service;
+
+#[actix_web::test]
+async fn test_archive_rejects_invalid_selections() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(create_test_s3_service().await))
+            .service(configure_api_v1())
+    ).await;
+
+    let cases = [
+        (json!({}), StatusCode::BAD_REQUEST),
+        (json!({ "keys": ["images/logo.png"], "root": "docs" }), StatusCode::BAD_REQUEST),
+        (json!({ "prefixes": ["images/"], "root": "docs/" }), StatusCode::BAD_REQUEST),
+        (json!({ "prefixes": [".rustdok/"] }), StatusCode::FORBIDDEN),
+        (json!({ "keys": ["/.rustdok/policy.json"] }), StatusCode::FORBIDDEN),
+    ];
+
+    for (body, status) in cases {
+        let req = test::TestRequest::post()
+            .uri("/api/v1/bucket/test-bucket/archive")
+            .set_json(&body)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), status, "Unexpected status for {}", body);
+    }
+}
+
+#[actix_web::test]
+async fn test_archive_contents_rejects_system_keys() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(create_test_s3_service().await))
+            .service(configure_api_v1())
+    ).await;
+
+    for uri in [
+        "/api/v1/bucket/test-bucket/archive-contents/.rustdok/derived/backup.zip",
+        "/api/v1/bucket/test-bucket/archive-contents/.rustdok/backup.tar?entry=policy.json",
+    ] {
+        let req = test::TestRequest::get().uri(uri).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN, "Unexpected status for {}", uri);
+    }
+}
@@ -0,0 +1,251 @@
+#![cfg(test)]
+// Tests for the audit middleware and the audit log API endpoints
+// These tests use the in-memory stores in place of MySQL; the requests
+// that are let through would need S3, so data operations are recorded as denials
+
+use std::sync::Arc;
+
+use actix_web::http::StatusCode;
+use actix_web::{test, web, App};
+use chrono::Utc;
+use jsonwebtoken::{encode, EncodingKey, Header};
+use serde_json::{json, Value};
+
+use crate::api::audit::AuditLog;
+use crate::api::auth::Authentication;
+use crate::api::config::configure_api_v1;
+use crate::rdlib::audit::record::AuditOutcome;
+use crate::rdlib::audit::service::AuditService;
+use crate::rdlib::auth::jwt::JwtVerifier;
+use crate::tests::access::memory_store::create_test_authorizer;
+use crate::tests::s3::service_test_helpers::create_test_s3_service;
+use crate::tests::apikeys::memory_store::create_test_api_key_service;
+use crate::tests::audit::memory_store::create_test_audit_service;
+use crate::tests::users::memory_store::TEST_JWT_SECRET;
+
+fn bearer(subject: &str, roles: &[&str]) -> String {
+    let claims = json!({ "sub": subject, "roles": roles, "exp": Utc::now().timestamp() + 3600 });
+    format!("Bearer {}", encode(&Header::default(), &claims, &EncodingKey::from_secret(TEST_JWT_SECRET)).unwrap())
+}
+
+macro_rules! init_app {
+    ($audit:expr) => {
+        test::init_service(
+            App::new()
+                .app_data(web::Data::new(create_test_s3_service().await))
+                .app_data(web::Data::new(create_test_authorizer()))
+                .app_data(web::Data::new(create_test_api_key_service()))
+                .app_data(web::Data::new($audit.clone()))
+                .service(configure_api_v1()
+                    .wrap(Authentication::new(JwtVerifier::new().with_secret(TEST_JWT_SECRET)))
+                    .wrap(AuditLog))
+        ).await
+    };
+}
+
+#[actix_web::test]
+async fn test_requests_are_recorded() {
+    let (audit, store) = create_test_audit_service();
+    let app = init_app!(audit);
+
+    let req = test::TestRequest::delete()
+        .uri("/api/v1/bucket/docs/object/reports/q1.pdf")
+        .insert_header(("Authorization", bearer("alice", &[])))
+        .insert_header(("X-Request-Id", "delete-1"))
+        .peer_addr("192.0.2.7:4711".parse().unwrap())
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/admin/service-accounts")
+        .insert_header(("Authorization", bearer("root", &["admin"])))
+        .insert_header(("Content-Type", "application/json"))
+        .insert_header(("Content-Length", "13"))
+        .set_payload(r#"{"name":"ci"}"#)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let account: Value = test::read_body_json(resp).await;
+
+    let req = test::TestRequest::delete()
+        .uri(&format!("/api/v1/admin/service-accounts/{}", account["id"]))
+        .insert_header(("Authorization", bearer("root", &["admin"])))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+
+    let req = test::TestRequest::delete().uri("/api/v1/bucket/docs").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::UNAUTHORIZED);
+
+    let claims = json!({ "sub": "alice", "iss": "https://idp.example.com", "exp": Utc::now().timestamp() + 3600 });
+    let token = encode(&Header::default(), &claims, &EncodingKey::from_secret(TEST_JWT_SECRET)).unwrap();
+    let req = test::TestRequest::delete()
+        .uri("/api/v1/bucket/docs/object/reports/q1.pdf")
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::FORBIDDEN);
+
+    let records = store.records();
+    assert_eq!(records.len(), 5);
+
+    let delete = &records[0];
+    assert_eq!(delete.action, "object.delete");
+    assert_eq!(delete.actor.as_deref(), Some("alice"));
+    assert_eq!(delete.ip_address.as_deref(), Some("192.0.2.7"));
+    assert_eq!(delete.request_id, None, "The request ID middleware is not installed");
+    assert_eq!((delete.bucket.as_deref(), delete.key.as_deref()), (Some("docs"), Some("reports/q1.pdf")));
+    assert_eq!((delete.outcome, delete.status), (AuditOutcome::Denied, 403));
+
+    let create = &records[1];
+    assert_eq!((create.action.as_str(), create.outcome), ("service_account.create", AuditOutcome::Success));
+    assert_eq!(create.bytes, Some(13), "Uploads are measured by their body");
+
+    let removed = &records[2];
+    assert_eq!(removed.action, "service_account.delete");
+    assert_eq!(removed.target, Some(format!("id={}", account["id"])));
+
+    let anonymous = &records[3];
+    assert_eq!((anonymous.action.as_str(), anonymous.actor.as_deref()), ("bucket.delete", None));
+    assert_eq!((anonymous.bucket.as_deref(), anonymous.target.as_deref()), (Some("docs"), None));
+    assert_eq!(anonymous.outcome, AuditOutcome::Denied);
+
+    let external = &records[4];
+    assert_eq!(external.actor.as_deref(), Some("https://idp.example.com#alice"), "Other issuers' clients are told apart from users");
+}
+
+#[actix_web::test]
+async fn test_drop_link_tokens_are_not_recorded() {
+    let (audit, store) = create_test_audit_service();
+    let app = init_app!(audit);
+
+    let req = test::TestRequest::delete()
+        .uri("/api/v1/bucket/docs/drops/secret-token")
+        .insert_header(("Authorization", bearer("alice", &[])))
+        .to_request();
+    test::call_service(&app, req).await;
+
+    let record = &store.records()[0];
+    assert_eq!(record.action, "drop_link.delete");
+    assert_eq!(record.target, None);
+}
+
+/// Records a denied delete of each key in the bucket `docs`.
+async fn record_deletes(audit: &Arc<AuditService>, keys: &[&str]) {
+    let app = init_app!(audit);
+    for key in keys {
+        let req = test::TestRequest::delete()
+            .uri(&format!("/api/v1/bucket/docs/object/{}", key))
+            .insert_header(("Authorization", bearer("alice", &[])))
+            .to_request();
+        test::call_service(&app, req).await;
+    }
+}
+
+#[actix_web::test]
+async fn test_list_audit_records() {
+    let (audit, _) = create_test_audit_service();
+    record_deletes(&audit, &["a.txt", "reports/1.pdf", "reports/2.pdf", "reports/3.pdf"]).await;
+    let app = init_app!(audit);
+    let admin = bearer("root", &["admin"]);
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/admin/audit?actor=alice&prefix=reports/&limit=2")
+        .insert_header(("Authorization", admin.clone()))
+        .to_request();
+    let page: Value = test::call_and_read_body_json(&app, req).await;
+    let keys = page["records"].as_array().unwrap().iter().map(|r| r["key"].clone()).collect::<Vec<_>>();
+    assert_eq!(keys, vec![json!("reports/3.pdf"), json!("reports/2.pdf")]);
+    assert_eq!(page["next_before"], 3);
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/admin/audit?actor=alice&prefix=reports/&limit=2&before=3")
+        .insert_header(("Authorization", admin.clone()))
+        .to_request();
+    let page: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(page["records"].as_array().unwrap().len(), 1);
+    assert_eq!(page["records"][0]["outcome"], "denied");
+    assert_eq!(page["next_before"], Value::Null);
+
+    // The listings themselves are recorded too
+    let req = test::TestRequest::get()
+        .uri("/api/v1/admin/audit?action=audit.list")
+        .insert_header(("Authorization", admin.clone()))
+        .to_request();
+    let page: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(page["records"].as_array().unwrap().len(), 2);
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/admin/audit?limit=5000")
+        .insert_header(("Authorization", admin.clone()))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let problem: Value = test::read_body_json(resp).await;
+    assert_eq!(problem["field"], "limit");
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/admin/audit")
+        .insert_header(("Authorization", bearer("alice", &[])))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::FORBIDDEN);
+}
+
+#[actix_web::test]
+async fn test_export_audit_records() {
+    let (audit, _) = create_test_audit_service();
+    record_deletes(&audit, &["a.txt", "reports/%22quoted%22,%20name.pdf"]).await;
+    let app = init_app!(audit);
+    let admin = bearer("root", &["admin"]);
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/admin/audit/export?bucket=docs")
+        .insert_header(("Authorization", admin.clone()))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.headers().get("Content-Type").unwrap(), "application/x-ndjson");
+    assert_eq!(resp.headers().get("Content-Disposition").unwrap(), "attachment; filename=\"audit-log.ndjson\"");
+    let body = test::read_body(resp).await;
+    let lines = std::str::from_utf8(&body).unwrap().lines()
+        .map(|line| serde_json::from_str::<Value>(line).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[1]["key"], "a.txt");
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/admin/audit/export?format=csv&bucket=docs")
+        .insert_header(("Authorization", admin.clone()))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.headers().get("Content-Type").unwrap(), "text/csv; charset=utf-8");
+    let body = test::read_body(resp).await;
+    let csv = std::str::from_utf8(&body).unwrap();
+    let lines = csv.split("\r\n").collect::<Vec<_>>();
+    assert!(lines[0].starts_with("id,occurred_at,"));
+    assert!(lines[1].contains(",docs,\"reports/\"\"quoted\"\", name.pdf\","));
+    assert!(lines[2].contains(",docs,a.txt,"));
+    assert_eq!(lines.len(), 4, "Every line ends with CRLF");
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/admin/audit/export?format=xml")
+        .insert_header(("Authorization", admin.clone()))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn test_audit_log_not_enabled() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(create_test_s3_service().await))
+            .service(configure_api_v1()
+                .wrap(Authentication::new(JwtVerifier::new().with_secret(TEST_JWT_SECRET)))
+                .wrap(AuditLog))
+    ).await;
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/admin/audit")
+        .insert_header(("Authorization", bearer("root", &["admin"])))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NOT_IMPLEMENTED);
+}
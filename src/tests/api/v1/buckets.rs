@@ -2,20 +2,15 @@
 // Tests for the buckets API endpoints
 // These tests focus on the API endpoints for bucket operations
 
-use actix_web::{test, web, App, HttpResponse};
+use actix_web::{test, web, App, HttpResponse, ResponseError};
 use actix_web::http::StatusCode;
 use serde_json::{json, Value};
 use crate::api::v1::buckets::create_bucket;
-use crate::rdlib::s3::service::S3Service;
 use crate::rdlib::s3::error::S3Error;
 use std::sync::{Arc, Mutex};
-use aws_sdk_s3::Client;
-use aws_sdk_s3::config::Builder;
-use aws_sdk_s3::config::Region;
-use aws_sdk_s3::config::Credentials;
-use aws_config::BehaviorVersion;
 use mockall::predicate::*;
 use mockall::mock;
+use crate::tests::s3::service_test_helpers::create_test_s3_service;
 
 mock! {
     pub S3Service {
@@ -25,31 +20,6 @@ mock! {
     }
 }
 
-async fn create_test_s3_service() -> Arc<S3Service> {
-    let config = aws_config::defaults(BehaviorVersion::latest())
-        .endpoint_url("http://localhost:7000")
-        .region(Region::new("eu-central-1"))
-        .credentials_provider(Credentials::new(
-            "test-access-key",
-            "test-secret-key",
-            None,
-            None,
-            "test-credentials",
-        ))
-        .load()
-        .await;
-
-    let s3_config = Builder::from(&config)
-        .force_path_style(true)
-        .build();
-    
-    let client = Client::from_conf(s3_config);
-    
-    Arc::new(S3Service {
-        client,
-    })
-}
-
 struct MockS3ServiceWrapper {
     mock: Mutex<MockS3Service>,
 }
@@ -83,7 +53,7 @@ impl MockS3ServiceWrapper {
         mock.expect_create_bucket()
             .with(eq(bucket_name.to_string()))
             .times(1)
-            .returning(|name| Err(S3Error::BucketAlreadyExists(format!("BucketAlreadyExists: {} already exists", name))));
+            .returning(|name| Err(S3Error::BucketAlreadyExists(format!("Bucket '{}' already exists", name))));
         self
     }
     
@@ -101,7 +71,7 @@ impl MockS3ServiceWrapper {
         mock.expect_delete_bucket()
             .with(eq(bucket_name.to_string()))
             .times(1)
-            .returning(|name| Err(S3Error::BucketNotFound(format!("NoSuchBucket: {} not found", name))));
+            .returning(|name| Err(S3Error::BucketNotFound(format!("Bucket '{}' not found", name))));
         self
     }
 }
@@ -171,17 +141,7 @@ async fn test_create_bucket() {
                     Ok(_) => HttpResponse::Created().json(json!({
                         "message": format!("Bucket '{}' created successfully", bucket_name)
                     })),
-                    Err(e) => {
-                        if e.contains("BucketAlreadyExists") || e.contains("already exists") {
-                            HttpResponse::Conflict().json(json!({
-                                "error": format!("Bucket '{}' already exists", bucket_name)
-                            }))
-                        } else {
-                            HttpResponse::InternalServerError().json(json!({
-                                "error": format!("Failed to create bucket: {}", e)
-                            }))
-                        }
-                    }
+                    Err(e) => e.error_response()
                 }
             }))
     ).await;
@@ -225,7 +185,9 @@ async fn test_create_bucket_empty_name() {
     let body = test::read_body(resp).await;
     let response: Value = serde_json::from_slice(&body).unwrap();
     
-    assert_eq!(response["error"], "Bucket name cannot be empty");
+    assert_eq!(response["code"], "invalid_request");
+    assert_eq!(response["field"], "name");
+    assert_eq!(response["detail"], "Bucket name cannot be empty");
 }
 
 #[actix_web::test]
@@ -248,17 +210,7 @@ async fn test_create_bucket_already_exists() {
                     Ok(_) => HttpResponse::Created().json(json!({
                         "message": format!("Bucket '{}' created successfully", bucket_name)
                     })),
-                    Err(e) => {
-                        if e.contains("BucketAlreadyExists") || e.contains("already exists") {
-                            HttpResponse::Conflict().json(json!({
-                                "error": format!("Bucket '{}' already exists", bucket_name)
-                            }))
-                        } else {
-                            HttpResponse::InternalServerError().json(json!({
-                                "error": format!("Failed to create bucket: {}", e)
-                            }))
-                        }
-                    }
+                    Err(e) => e.error_response()
                 }
             }))
     ).await;
@@ -276,7 +228,8 @@ async fn test_create_bucket_already_exists() {
     let body = test::read_body(resp).await;
     let response: Value = serde_json::from_slice(&body).unwrap();
     
-    assert_eq!(response["error"], "Bucket 'existing-bucket' already exists");
+    assert_eq!(response["code"], "bucket_already_exists");
+    assert_eq!(response["detail"], "Bucket 'existing-bucket' already exists");
 }
 
 #[actix_web::test]
@@ -292,21 +245,7 @@ async fn test_delete_bucket() {
                     Ok(_) => HttpResponse::Ok().json(json!({
                         "message": format!("Bucket '{}' deleted successfully", bucket_name)
                     })),
-                    Err(e) => {
-                        if e.contains("NoSuchBucket") || e.contains("not found") {
-                            HttpResponse::NotFound().json(json!({
-                                "error": format!("Bucket '{}' not found", bucket_name)
-                            }))
-                        } else if e.contains("BucketNotEmpty") || e.contains("not empty") {
-                            HttpResponse::Conflict().json(json!({
-                                "error": format!("Bucket '{}' is not empty", bucket_name)
-                            }))
-                        } else {
-                            HttpResponse::InternalServerError().json(json!({
-                                "error": format!("Failed to delete bucket: {}", e)
-                            }))
-                        }
-                    }
+                    Err(e) => e.error_response()
                 }
             }))
     ).await;
@@ -337,21 +276,7 @@ async fn test_delete_bucket_not_found() {
                     Ok(_) => HttpResponse::Ok().json(json!({
                         "message": format!("Bucket '{}' deleted successfully", bucket_name)
                     })),
-                    Err(e) => {
-                        if e.contains("NoSuchBucket") || e.contains("not found") {
-                            HttpResponse::NotFound().json(json!({
-                                "error": format!("Bucket '{}' not found", bucket_name)
-                            }))
-                        } else if e.contains("BucketNotEmpty") || e.contains("not empty") {
-                            HttpResponse::Conflict().json(json!({
-                                "error": format!("Bucket '{}' is not empty", bucket_name)
-                            }))
-                        } else {
-                            HttpResponse::InternalServerError().json(json!({
-                                "error": format!("Failed to delete bucket: {}", e)
-                            }))
-                        }
-                    }
+                    Err(e) => e.error_response()
                 }
             }))
     ).await;
@@ -366,5 +291,6 @@ async fn test_delete_bucket_not_found() {
     let body = test::read_body(resp).await;
     let response: Value = serde_json::from_slice(&body).unwrap();
     
-    assert_eq!(response["error"], "Bucket 'nonexistent-bucket' not found");
+    assert_eq!(response["code"], "bucket_not_found");
+    assert_eq!(response["detail"], "Bucket 'nonexistent-bucket' not found");
 } 
\ No newline at end of file
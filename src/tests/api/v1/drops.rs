@@ -0,0 +1,118 @@
+#![cfg(test)]
+// Tests for the drop link API endpoints
+// These tests cover the request validation that happens before S3 is contacted
+
+use actix_web::{test, web, App};
+use actix_web::http::StatusCode;
+use serde_json::{json, Value};
+use crate::api::config::configure_api_v1;
+use crate::tests::s3::service_test_helpers::create_test_s3_service;
+
+#[actix_web::test]
+async fn test_create_drop_link_rejects_invalid_requests() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(create_test_s3_service().await))
+            .service(configure_api_v1())
+    ).await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/bucket/test-bucket/drops")
+        .set_json(json!({ "prefix": ".rustdok/drops" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/bucket/test-bucket/drops")
+        .set_json(json!({ "prefix": "incoming/../private" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    for prefix in ["incoming//private", "incoming/./private", "/.rustdok"] {
+        let req = test::TestRequest::post()
+            .uri("/api/v1/bucket/test-bucket/drops")
+            .set_json(json!({ "prefix": prefix }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST, "Prefix {} should be rejected", prefix);
+
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["field"], "prefix");
+    }
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/bucket/test-bucket/drops")
+        .set_json(json!({ "prefix": "incoming", "max_uploads": 0 }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["code"], "invalid_request");
+    assert_eq!(body["field"], "max_uploads");
+    assert_eq!(body["detail"], "max_uploads and max_file_size must be greater than zero");
+
+    for expires_in in [0, u64::MAX] {
+        let req = test::TestRequest::post()
+            .uri("/api/v1/bucket/test-bucket/drops")
+            .set_json(json!({ "prefix": "incoming", "expires_in": expires_in }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["code"], "invalid_request");
+        assert_eq!(body["field"], "expires_in");
+    }
+}
+
+#[actix_web::test]
+async fn test_drop_link_with_malformed_token_is_not_found() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(create_test_s3_service().await))
+            .service(configure_api_v1())
+    ).await;
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/drop/test-bucket/not-a-token.json")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn test_object_endpoints_reject_system_prefix() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(create_test_s3_service().await))
+            .service(configure_api_v1())
+    ).await;
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/bucket/test-bucket/objects?prefix=.rustdok/drops/")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/bucket/test-bucket/download/.rustdok/drops/token.json")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+    let req = test::TestRequest::delete()
+        .uri("/api/v1/bucket/test-bucket/object/.rustdok/")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/bucket/test-bucket/move")
+        .set_json(json!({ "source_key": "notes.txt", "destination_key": ".rustdok/drops/token.json" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+}
@@ -0,0 +1,163 @@
+#![cfg(test)]
+// Tests for the grant management API endpoints and their enforcement
+// These tests use the in-memory grant store in place of MySQL; the
+// requests that are let through would need S3, so only denials are checked
+
+use actix_web::http::StatusCode;
+use actix_web::{test, web, App};
+use chrono::Utc;
+use jsonwebtoken::{encode, EncodingKey, Header};
+use serde_json::{json, Value};
+
+use crate::api::auth::Authentication;
+use crate::api::config::configure_api_v1;
+use crate::rdlib::auth::jwt::JwtVerifier;
+use crate::tests::access::memory_store::create_test_authorizer;
+use crate::tests::s3::service_test_helpers::create_test_s3_service;
+use crate::tests::users::memory_store::TEST_JWT_SECRET;
+
+fn bearer(subject: &str, roles: &[&str]) -> String {
+    let claims = json!({ "sub": subject, "roles": roles, "exp": Utc::now().timestamp() + 3600 });
+    format!("Bearer {}", encode(&Header::default(), &claims, &EncodingKey::from_secret(TEST_JWT_SECRET)).unwrap())
+}
+
+macro_rules! init_app {
+    () => {
+        test::init_service(
+            App::new()
+                .app_data(web::Data::new(create_test_s3_service().await))
+                .app_data(web::Data::new(create_test_authorizer()))
+                .service(configure_api_v1().wrap(Authentication::new(JwtVerifier::new().with_secret(TEST_JWT_SECRET))))
+        ).await
+    };
+}
+
+#[actix_web::test]
+async fn test_manage_grants() {
+    let app = init_app!();
+    let admin = bearer("root", &["admin"]);
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/admin/grants")
+        .insert_header(("Authorization", admin.clone()))
+        .set_json(json!({ "subject_type": "user", "subject": "alice", "bucket": "docs", "prefix": "reports", "role": "viewer" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let grant: Value = test::read_body_json(resp).await;
+    assert_eq!(grant["prefix"], "reports/");
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/admin/grants")
+        .insert_header(("Authorization", admin.clone()))
+        .set_json(json!({ "subject_type": "user", "subject": "alice", "bucket": "docs", "prefix": "reports/", "role": "editor" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK, "Granting a role on the same prefix replaces the role");
+    let changed: Value = test::read_body_json(resp).await;
+    assert_eq!((&changed["id"], &changed["role"]), (&grant["id"], &json!("editor")));
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/admin/grants")
+        .insert_header(("Authorization", admin.clone()))
+        .set_json(json!({ "subject_type": "group", "subject": "staff", "bucket": "docs", "prefix": "../up", "role": "viewer" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let problem: Value = test::read_body_json(resp).await;
+    assert_eq!(problem["field"], "prefix");
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/admin/grants?subject=alice")
+        .insert_header(("Authorization", admin.clone()))
+        .to_request();
+    let grants: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(grants.as_array().unwrap().len(), 1);
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/admin/grants")
+        .insert_header(("Authorization", bearer("alice", &[])))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+    let uri = format!("/api/v1/admin/grants/{}", grant["id"]);
+    let req = test::TestRequest::delete().uri(&uri).insert_header(("Authorization", admin.clone())).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+    let req = test::TestRequest::delete().uri(&uri).insert_header(("Authorization", admin.clone())).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    let problem: Value = test::read_body_json(resp).await;
+    assert_eq!(problem["code"], "grant_not_found");
+}
+
+#[actix_web::test]
+async fn test_grants_need_a_user_database() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(create_test_s3_service().await))
+            .service(configure_api_v1().wrap(Authentication::new(JwtVerifier::new().with_secret(TEST_JWT_SECRET))))
+    ).await;
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/admin/grants")
+        .insert_header(("Authorization", bearer("root", &["admin"])))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NOT_IMPLEMENTED);
+}
+
+#[actix_web::test]
+async fn test_requests_are_checked_against_grants() {
+    let app = init_app!();
+    let admin = bearer("root", &["admin"]);
+    let alice = bearer("alice", &[]);
+
+    for (prefix, role) in [("", "viewer"), ("inbox", "uploader")] {
+        let req = test::TestRequest::post()
+            .uri("/api/v1/admin/grants")
+            .insert_header(("Authorization", admin.clone()))
+            .set_json(json!({ "subject_type": "user", "subject": "alice", "bucket": "docs", "prefix": prefix, "role": role }))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::CREATED);
+    }
+
+    let req = test::TestRequest::delete()
+        .uri("/api/v1/bucket/docs/object/report.pdf")
+        .insert_header(("Authorization", alice.clone()))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    let problem: Value = test::read_body_json(resp).await;
+    assert_eq!(problem["code"], "permission_denied");
+    assert_eq!(problem["required_role"], "editor");
+
+    let req = test::TestRequest::put()
+        .uri("/api/v1/bucket/docs/object/inbox/report.pdf")
+        .insert_header(("Authorization", alice.clone()))
+        .insert_header(("If-Match", "\"abc\""))
+        .set_payload("new content")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN, "Uploaders cannot replace objects");
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/buckets")
+        .insert_header(("Authorization", alice.clone()))
+        .set_json(json!({ "name": "alice-bucket" }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::FORBIDDEN);
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/bucket/photos/objects")
+        .insert_header(("Authorization", alice.clone()))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::FORBIDDEN);
+
+    let req = test::TestRequest::put()
+        .uri("/api/v1/bucket/docs/policy")
+        .insert_header(("Authorization", alice.clone()))
+        .set_json(json!({ "max_object_size": 1024 }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::FORBIDDEN);
+}
@@ -0,0 +1,141 @@
+#![cfg(test)]
+// Tests for the group management API endpoints and the effective access of users
+// These tests use the in-memory grant and group stores in place of MySQL
+
+use actix_web::http::StatusCode;
+use actix_web::{test, web, App};
+use chrono::Utc;
+use jsonwebtoken::{encode, EncodingKey, Header};
+use serde_json::{json, Value};
+
+use crate::api::auth::Authentication;
+use crate::api::config::configure_api_v1;
+use crate::rdlib::auth::jwt::JwtVerifier;
+use crate::tests::access::memory_store::create_test_authorizer;
+use crate::tests::s3::service_test_helpers::create_test_s3_service;
+use crate::tests::users::memory_store::TEST_JWT_SECRET;
+
+fn bearer(subject: &str, roles: &[&str]) -> String {
+    let claims = json!({ "sub": subject, "roles": roles, "exp": Utc::now().timestamp() + 3600 });
+    format!("Bearer {}", encode(&Header::default(), &claims, &EncodingKey::from_secret(TEST_JWT_SECRET)).unwrap())
+}
+
+macro_rules! init_app {
+    () => {
+        test::init_service(
+            App::new()
+                .app_data(web::Data::new(create_test_s3_service().await))
+                .app_data(web::Data::new(create_test_authorizer()))
+                .service(configure_api_v1().wrap(Authentication::new(JwtVerifier::new().with_secret(TEST_JWT_SECRET))))
+        ).await
+    };
+}
+
+#[actix_web::test]
+async fn test_manage_groups_and_members() {
+    let app = init_app!();
+    let admin = bearer("root", &["admin"]);
+
+    let mut ids = Vec::new();
+    for name in ["engineering", "backend"] {
+        let req = test::TestRequest::post()
+            .uri("/api/v1/admin/groups")
+            .insert_header(("Authorization", admin.clone()))
+            .set_json(json!({ "name": name }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        let group: Value = test::read_body_json(resp).await;
+        ids.push(group["id"].as_u64().unwrap());
+    }
+    let (engineering, backend) = (ids[0], ids[1]);
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/admin/groups")
+        .insert_header(("Authorization", admin.clone()))
+        .set_json(json!({ "name": "backend" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+    let problem: Value = test::read_body_json(resp).await;
+    assert_eq!(problem["code"], "group_already_exists");
+
+    let add = |id: u64, member_type: &str, member: &str| {
+        test::TestRequest::post()
+            .uri(&format!("/api/v1/admin/groups/{}/members", id))
+            .insert_header(("Authorization", admin.clone()))
+            .set_json(json!({ "member_type": member_type, "member": member }))
+            .to_request()
+    };
+    assert_eq!(test::call_service(&app, add(engineering, "group", "backend")).await.status(), StatusCode::CREATED);
+    assert_eq!(test::call_service(&app, add(backend, "user", "alice")).await.status(), StatusCode::CREATED);
+    assert_eq!(test::call_service(&app, add(backend, "user", "alice")).await.status(), StatusCode::OK);
+
+    let resp = test::call_service(&app, add(backend, "group", "engineering")).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST, "Groups cannot contain themselves");
+    let problem: Value = test::read_body_json(resp).await;
+    assert_eq!(problem["field"], "member");
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/v1/admin/groups/{}/members", backend))
+        .insert_header(("Authorization", admin.clone()))
+        .to_request();
+    let members: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(members[0]["member"], "alice");
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/admin/grants")
+        .insert_header(("Authorization", admin.clone()))
+        .set_json(json!({ "subject_type": "group", "subject": "engineering", "bucket": "docs", "role": "editor" }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::CREATED);
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/admin/access?user=alice&bucket=docs&prefix=specs/api.md")
+        .insert_header(("Authorization", admin.clone()))
+        .to_request();
+    let access: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(access["role"], "editor");
+    assert_eq!(access["groups"], json!(["backend", "engineering"]));
+    assert_eq!(access["grants"][0]["subject"], "engineering");
+
+    let req = test::TestRequest::delete()
+        .uri("/api/v1/bucket/docs/object/specs/api.md")
+        .insert_header(("Authorization", bearer("bob", &[])))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::FORBIDDEN);
+
+    let req = test::TestRequest::delete()
+        .uri(&format!("/api/v1/admin/groups/{}/members/user/alice", backend))
+        .insert_header(("Authorization", admin.clone()))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+    let req = test::TestRequest::delete()
+        .uri(&format!("/api/v1/admin/groups/{}/members/user/alice", backend))
+        .insert_header(("Authorization", admin.clone()))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    let problem: Value = test::read_body_json(resp).await;
+    assert_eq!(problem["code"], "member_not_found");
+
+    let req = test::TestRequest::delete()
+        .uri(&format!("/api/v1/admin/groups/{}", engineering))
+        .insert_header(("Authorization", admin.clone()))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/v1/admin/groups/{}", engineering))
+        .insert_header(("Authorization", admin.clone()))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    let problem: Value = test::read_body_json(resp).await;
+    assert_eq!(problem["code"], "group_not_found");
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/admin/groups")
+        .insert_header(("Authorization", bearer("alice", &[])))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::FORBIDDEN);
+}
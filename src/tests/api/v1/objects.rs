@@ -90,8 +90,172 @@ async fn test_api_endpoints_registration() {
         }))
         .to_request();
     let resp = test::call_service(&app, req).await;
-    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
     debug!("move_file_in_bucket endpoint test completed");
     
     debug!("All API endpoints registration tests completed successfully");
-} 
\ No newline at end of file
+} 
+#[actix_web::test]
+async fn test_normalize_relative_path() {
+    use crate::api::v1::objects::normalize_relative_path;
+
+    assert_eq!(normalize_relative_path("photos/2024/beach.jpg"), Ok("photos/2024/beach.jpg".to_string()));
+    assert_eq!(normalize_relative_path("photos\\2024\\beach.jpg"), Ok("photos/2024/beach.jpg".to_string()));
+    assert_eq!(normalize_relative_path("./photos//beach.jpg"), Ok("photos/beach.jpg".to_string()));
+    assert_eq!(normalize_relative_path("notes/a<b>.txt"), Ok("notes/ab.txt".to_string()));
+    assert_eq!(normalize_relative_path("readme.md"), Ok("readme.md".to_string()));
+
+    assert!(normalize_relative_path("../secret.txt").is_err());
+    assert!(normalize_relative_path("photos/../../secret.txt").is_err());
+    assert!(normalize_relative_path("/etc/passwd").is_err());
+    assert!(normalize_relative_path("C:/Users/file.txt").is_err());
+    assert!(normalize_relative_path("photos/").is_err());
+    assert!(normalize_relative_path("photos/???/beach.jpg").is_err());
+    assert!(normalize_relative_path("").is_err());
+}
+
+#[actix_web::test]
+async fn test_parent_folders() {
+    use crate::api::v1::objects::parent_folders;
+
+    assert_eq!(parent_folders("photos/2024/beach.jpg"), vec!["photos/".to_string(), "photos/2024/".to_string()]);
+    assert!(parent_folders("beach.jpg").is_empty());
+}
+
+#[actix_web::test]
+async fn test_conflict_policy_parsing() {
+    use crate::api::v1::objects::ConflictPolicy;
+
+    assert_eq!(serde_json::from_value::<ConflictPolicy>(json!("fail")).unwrap(), ConflictPolicy::Fail);
+    assert_eq!(serde_json::from_value::<ConflictPolicy>(json!("replace")).unwrap(), ConflictPolicy::Replace);
+    assert_eq!(serde_json::from_value::<ConflictPolicy>(json!("rename")).unwrap(), ConflictPolicy::Rename);
+    assert_eq!(serde_json::from_value::<ConflictPolicy>(json!("skip")).unwrap(), ConflictPolicy::Skip);
+    assert!(serde_json::from_value::<ConflictPolicy>(json!("overwrite")).is_err());
+    assert_eq!(ConflictPolicy::default(), ConflictPolicy::Fail);
+}
+
+#[actix_web::test]
+async fn test_upload_rejects_unknown_conflict_policy() {
+    setup();
+
+    let app = test::init_service(
+        App::new()
+            .service(configure_api_v1())
+    ).await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/bucket/test-bucket/objects?conflict=overwrite")
+        .insert_header(("Content-Type", "multipart/form-data; boundary=boundary"))
+        .set_payload("--boundary--\r\n")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn test_parse_content_md5() {
+    use crate::api::v1::objects::parse_content_md5;
+
+    // MD5 of "hello world"
+    assert_eq!(parse_content_md5(" XrY7u+Ae7tCTyyK7j1rNww== "), Some("XrY7u+Ae7tCTyyK7j1rNww==".to_string()));
+    assert_eq!(parse_content_md5("5eb63bbbe01eeed093cb22bb8f5acdc3"), None, "Hex digests should be rejected");
+    assert_eq!(parse_content_md5("aGVsbG8="), None, "Digests must be 16 bytes");
+}
+
+#[actix_web::test]
+async fn test_raw_upload_rejects_invalid_requests() {
+    use actix_web::web;
+    use crate::tests::s3::service_test_helpers::create_test_s3_service;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(create_test_s3_service().await))
+            .service(configure_api_v1())
+    ).await;
+
+    let cases = [
+        (".rustdok/policy.json", None, StatusCode::FORBIDDEN),
+        ("docs/../.rustdok/policy.json", None, StatusCode::BAD_REQUEST),
+        ("docs/", None, StatusCode::BAD_REQUEST),
+        ("docs/readme.txt", Some(("Content-MD5", "not-a-digest")), StatusCode::BAD_REQUEST),
+        ("docs/readme.txt", Some(("If-Match", "\"\"")), StatusCode::BAD_REQUEST),
+    ];
+
+    for (key, header, status) in cases {
+        let mut req = test::TestRequest::put()
+            .uri(&format!("/api/v1/bucket/test-bucket/object/{}", key))
+            .set_payload("hello world");
+        if let Some(header) = header {
+            req = req.insert_header(header);
+        }
+        let resp = test::call_service(&app, req.to_request()).await;
+        assert_eq!(resp.status(), status, "Unexpected status for {} / {:?}", key, header);
+    }
+}
+
+#[actix_web::test]
+async fn test_writes_reject_invalid_preconditions() {
+    use actix_web::web;
+    use crate::tests::s3::service_test_helpers::create_test_s3_service;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(create_test_s3_service().await))
+            .service(configure_api_v1())
+    ).await;
+
+    let requests = [
+        test::TestRequest::put()
+            .uri("/api/v1/bucket/test-bucket/object/docs/readme.txt")
+            .insert_header(("If-None-Match", "*, \"abc\""))
+            .set_payload("hello world"),
+        test::TestRequest::delete()
+            .uri("/api/v1/bucket/test-bucket/object/docs/readme.txt")
+            .insert_header(("If-Match", "\"\"")),
+        test::TestRequest::post()
+            .uri("/api/v1/bucket/test-bucket/objects")
+            .insert_header(("If-Match", ""))
+            .insert_header(("Content-Type", "multipart/form-data; boundary=boundary"))
+            .set_payload("--boundary--\r\n"),
+        test::TestRequest::post()
+            .uri("/api/v1/bucket/test-bucket/move")
+            .insert_header(("If-None-Match", "*, \"abc\""))
+            .set_json(json!({ "source_key": "drafts/report.pdf", "destination_key": "reports/report.pdf" })),
+        test::TestRequest::post()
+            .uri("/api/v1/bucket/test-bucket/objects?extract=true")
+            .insert_header(("If-Match", "\"abc\""))
+            .insert_header(("Content-Type", "multipart/form-data; boundary=boundary"))
+            .set_payload("--boundary--\r\n"),
+    ];
+
+    for req in requests {
+        let req = req.to_request();
+        let uri = req.uri().to_string();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST, "Unexpected status for {}", uri);
+    }
+}
+
+#[actix_web::test]
+async fn test_extract_rejects_failing_on_conflicts() {
+    use actix_web::web;
+    use serde_json::Value;
+    use crate::tests::s3::service_test_helpers::create_test_s3_service;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(create_test_s3_service().await))
+            .service(configure_api_v1())
+    ).await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/bucket/test-bucket/objects?extract=true&conflict=fail")
+        .insert_header(("Content-Type", "multipart/form-data; boundary=boundary"))
+        .set_payload("--boundary--\r\n")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["field"], "conflict");
+}
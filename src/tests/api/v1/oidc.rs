@@ -0,0 +1,170 @@
+#![cfg(test)]
+// Tests for the OpenID Connect login endpoints
+// These tests log in through a mock identity provider and use the
+// in-memory stores in place of MySQL
+
+use actix_web::cookie::{Cookie, SameSite};
+use actix_web::http::StatusCode;
+use actix_web::{test, web, App};
+use serde_json::{json, Value};
+
+use crate::api::auth::Authentication;
+use crate::api::config::configure_api_v1;
+use crate::api::session::{CSRF_COOKIE, OIDC_LOGIN_COOKIE, SESSION_COOKIE};
+use crate::rdlib::access::grant::SubjectType;
+use crate::rdlib::auth::jwt::JwtVerifier;
+use crate::rdlib::oidc::config::DEFAULT_WEBUI_URL;
+use crate::rdlib::users::user::Role;
+use crate::tests::access::memory_store::create_test_authorizer;
+use crate::tests::s3::service_test_helpers::create_test_s3_service;
+use crate::tests::oidc::mock_idp::{create_test_oidc_service, start_mock_idp};
+use crate::tests::users::memory_store::{create_test_user_service, TEST_JWT_SECRET};
+
+macro_rules! init_app {
+    ($users:expr, $authorizer:expr, $oidc:expr) => {
+        test::init_service(
+            App::new()
+                .app_data(web::Data::new(create_test_s3_service().await))
+                .app_data(web::Data::new($users.clone()))
+                .app_data(web::Data::new($authorizer.clone()))
+                .app_data(web::Data::new($oidc.clone()))
+                .service(configure_api_v1().wrap(Authentication::new(JwtVerifier::new().with_secret(TEST_JWT_SECRET))))
+        ).await
+    };
+}
+
+/// Begins a login, returning the authorization URL and the login cookie.
+macro_rules! begin_login {
+    ($app:expr) => {{
+        let req = test::TestRequest::get().uri("/api/v1/auth/oidc/login").to_request();
+        let resp = test::call_service(&$app, req).await;
+        assert_eq!(resp.status(), StatusCode::FOUND);
+        assert_eq!(resp.headers().get("Cache-Control").unwrap(), "no-store");
+        let location = resp.headers().get("Location").unwrap().to_str().unwrap().to_string();
+        let cookie = resp.response().cookies().find(|cookie| cookie.name() == OIDC_LOGIN_COOKIE).unwrap().into_owned();
+        (location, cookie)
+    }};
+}
+
+/// Logs in through the mock provider and returns the response of the callback.
+macro_rules! login {
+    ($app:expr, $idp:expr) => {{
+        let (location, cookie) = begin_login!($app);
+        let query = $idp.authorize(&location).await;
+        let req = test::TestRequest::get().uri(&format!("/api/v1/auth/oidc/callback?{}", query)).cookie(cookie).to_request();
+        test::call_service(&$app, req).await
+    }};
+}
+
+#[actix_web::test]
+async fn test_oidc_login_provisions_users() {
+    let idp = start_mock_idp(json!({
+        "sub": "u-1001",
+        "preferred_username": "alice",
+        "email": "alice@example.com",
+        "groups": ["engineering", "rustdok-admins"],
+    })).await;
+    let (users, _) = create_test_user_service();
+    let authorizer = create_test_authorizer();
+    let engineering = authorizer.create_group("engineering", "").await.unwrap();
+    let oidc = create_test_oidc_service(&idp, &["rustdok-admins"]).await;
+    let app = init_app!(users, authorizer, oidc);
+
+    let resp = login!(app, idp);
+    assert_eq!(resp.status(), StatusCode::FOUND);
+    assert_eq!(resp.headers().get("Location").unwrap(), DEFAULT_WEBUI_URL);
+    assert_eq!(resp.headers().get("Cache-Control").unwrap(), "no-store");
+    let cookies: Vec<Cookie<'static>> = resp.response().cookies().map(|cookie| cookie.into_owned()).collect();
+    let session = cookies.iter().find(|cookie| cookie.name() == SESSION_COOKIE).unwrap().clone();
+    assert_eq!(session.http_only(), Some(true));
+    assert!(cookies.iter().any(|cookie| cookie.name() == CSRF_COOKIE && !cookie.value().is_empty()));
+    assert!(cookies.iter().any(|cookie| cookie.name() == OIDC_LOGIN_COOKIE && cookie.value().is_empty()), "The login cookie is removed");
+
+    let req = test::TestRequest::get().uri("/api/v1/auth/me").cookie(session).to_request();
+    let principal: Value = test::read_body_json(test::call_service(&app, req).await).await;
+    assert_eq!(principal["subject"], "alice");
+    assert_eq!(principal["claims"]["roles"], json!(["admin"]), "Members of the admin groups are administrators");
+
+    let alice = users.list_users().await.unwrap().into_iter().find(|user| user.username == "alice").unwrap();
+    assert_eq!(alice.email.as_deref(), Some("alice@example.com"));
+    let members = authorizer.list_members(engineering.id).await.unwrap();
+    assert_eq!((members.len(), members[0].member_type, members[0].member.as_str()), (1, SubjectType::User, "alice"));
+
+    // The provider is authoritative for groups and roles at every login
+    idp.set_claims(json!({ "sub": "u-1001", "preferred_username": "alice", "groups": [] }));
+    assert_eq!(login!(app, idp).status(), StatusCode::FOUND);
+    assert!(authorizer.list_members(engineering.id).await.unwrap().is_empty());
+    assert_eq!(users.get_user(alice.id).await.unwrap().role, Role::User);
+    assert_eq!(users.list_users().await.unwrap().len(), 1);
+}
+
+#[actix_web::test]
+async fn test_oidc_login_checks_the_login_cookie() {
+    let idp = start_mock_idp(json!({ "sub": "u-1001", "preferred_username": "alice" })).await;
+    let (users, _) = create_test_user_service();
+    let authorizer = create_test_authorizer();
+    let oidc = create_test_oidc_service(&idp, &[]).await;
+    let app = init_app!(users, authorizer, oidc);
+
+    let (location, cookie) = begin_login!(app);
+    assert_eq!((cookie.http_only(), cookie.same_site()), (Some(true), Some(SameSite::Lax)));
+    let query = idp.authorize(&location).await;
+
+    // Another browser, or one that began another login, cannot complete the login
+    let (_, other) = begin_login!(app);
+    for cookie in [None, Some(other), Some(Cookie::new(OIDC_LOGIN_COOKIE, "malformed"))] {
+        let mut req = test::TestRequest::get().uri(&format!("/api/v1/auth/oidc/callback?{}", query));
+        if let Some(cookie) = cookie {
+            req = req.cookie(cookie);
+        }
+        let resp = test::call_service(&app, req.to_request()).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let problem: Value = test::read_body_json(resp).await;
+        assert_eq!(problem["field"], "state");
+    }
+
+    let req = test::TestRequest::get().uri(&format!("/api/v1/auth/oidc/callback?{}", query)).cookie(cookie).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::FOUND);
+    assert!(users.list_users().await.unwrap().iter().any(|user| user.username == "alice"));
+}
+
+#[actix_web::test]
+async fn test_oidc_login_rejections() {
+    let idp = start_mock_idp(json!({ "sub": "u-1001", "preferred_username": "alice" })).await;
+    let (users, _) = create_test_user_service();
+    let authorizer = create_test_authorizer();
+    let oidc = create_test_oidc_service(&idp, &[]).await;
+    let app = init_app!(users, authorizer, oidc);
+
+    for (query, status, code) in [
+        ("state=unknown&code=abc", StatusCode::BAD_REQUEST, "invalid_request"),
+        ("code=abc", StatusCode::BAD_REQUEST, "invalid_request"),
+        ("error=access_denied&error_description=The%20user%20declined&state=abc", StatusCode::UNAUTHORIZED, "login_rejected"),
+    ] {
+        let req = test::TestRequest::get().uri(&format!("/api/v1/auth/oidc/callback?{}", query)).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), status, "{}", query);
+        let problem: Value = test::read_body_json(resp).await;
+        assert_eq!(problem["code"], code);
+    }
+
+    // A password account of the same name is not taken over
+    users.create_user("bob", "correct horse", Role::User).await.unwrap();
+    idp.set_claims(json!({ "sub": "u-1002", "preferred_username": "bob" }));
+    assert_eq!(login!(app, idp).status(), StatusCode::CONFLICT);
+    assert_eq!(users.list_users().await.unwrap().len(), 1);
+}
+
+#[actix_web::test]
+async fn test_oidc_login_not_enabled() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(create_test_s3_service().await))
+            .service(configure_api_v1().wrap(Authentication::new(JwtVerifier::new().with_secret(TEST_JWT_SECRET))))
+    ).await;
+
+    for uri in ["/api/v1/auth/oidc/login", "/api/v1/auth/oidc/callback?code=abc&state=abc"] {
+        let resp = test::call_service(&app, test::TestRequest::get().uri(uri).to_request()).await;
+        assert_eq!(resp.status(), StatusCode::NOT_IMPLEMENTED, "{}", uri);
+    }
+}
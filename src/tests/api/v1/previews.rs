@@ -0,0 +1,57 @@
+#![cfg(test)]
+// Tests for the image preview API endpoints and image transformations on view
+// These tests cover the request validation that happens before S3 is contacted
+
+use actix_web::{test, web, App};
+use actix_web::http::StatusCode;
+use crate::api::config::configure_api_v1;
+use crate::tests::s3::service_test_helpers::create_test_s3_service;
+
+#[actix_web::test]
+async fn test_thumbnail_rejects_invalid_sizes() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(create_test_s3_service().await))
+            .service(configure_api_v1())
+    ).await;
+
+    for query in ["w=0", "w=100&h=5000", "h=1025"] {
+        let req = test::TestRequest::get()
+            .uri(&format!("/api/v1/bucket/test-bucket/thumbnail/photo.jpg?{}", query))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST, "Size '{}' should be rejected", query);
+    }
+}
+
+#[actix_web::test]
+async fn test_thumbnail_of_system_object_is_forbidden() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(create_test_s3_service().await))
+            .service(configure_api_v1())
+    ).await;
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/bucket/test-bucket/thumbnail/.rustdok/derived/thumbnails/256x256/photo.jpg")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+}
+
+#[actix_web::test]
+async fn test_view_rejects_invalid_transformations() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(create_test_s3_service().await))
+            .service(configure_api_v1())
+    ).await;
+
+    for query in ["rotate=45", "format=gif", "quality=101", "crop=1,2,3", "w=0", "h=5000"] {
+        let req = test::TestRequest::get()
+            .uri(&format!("/api/v1/bucket/test-bucket/view/photo.jpg?{}", query))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST, "Transformation '{}' should be rejected", query);
+    }
+}
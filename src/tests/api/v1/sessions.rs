@@ -0,0 +1,329 @@
+#![cfg(test)]
+// Tests for the cookie sessions of the web UI and their CSRF protection
+// These tests use the in-memory user store in place of MySQL
+
+use actix_web::cookie::{Cookie, SameSite};
+use actix_web::http::StatusCode;
+use actix_web::{test, web, App};
+use chrono::Utc;
+use jsonwebtoken::{encode, EncodingKey, Header};
+use serde_json::{json, Value};
+
+use crate::api::auth::Authentication;
+use crate::api::config::configure_api_v1;
+use crate::api::session::{client_ip_behind, CookieSettings, CSRF_COOKIE, CSRF_HEADER, SESSION_COOKIE};
+use crate::rdlib::auth::jwt::JwtVerifier;
+use crate::rdlib::users::service::UserUpdate;
+use crate::rdlib::users::user::Role;
+use crate::tests::s3::service_test_helpers::create_test_s3_service;
+use crate::tests::users::memory_store::{create_test_user_service, TEST_JWT_SECRET};
+
+macro_rules! init_app {
+    ($users:expr) => {
+        test::init_service(
+            App::new()
+                .app_data(web::Data::new(create_test_s3_service().await))
+                .app_data(web::Data::new($users.clone()))
+                .service(configure_api_v1().wrap(Authentication::new(JwtVerifier::new().with_secret(TEST_JWT_SECRET))))
+        ).await
+    };
+}
+
+/// Logs in through the session endpoint, returning the session and CSRF cookies.
+macro_rules! login_session {
+    ($app:expr, $username:expr, $password:expr) => {{
+        let req = test::TestRequest::post()
+            .uri("/api/v1/auth/login/session")
+            .set_json(json!({ "username": $username, "password": $password }))
+            .to_request();
+        let resp = test::call_service(&$app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let cookies: Vec<Cookie<'static>> = resp.response().cookies().map(|cookie| cookie.into_owned()).collect();
+        let session = cookies.iter().find(|cookie| cookie.name() == SESSION_COOKIE).unwrap().clone();
+        let csrf = cookies.iter().find(|cookie| cookie.name() == CSRF_COOKIE).unwrap().clone();
+        (session, csrf)
+    }};
+}
+
+#[actix_web::test]
+async fn test_login_session_sets_cookies() {
+    let (users, _) = create_test_user_service();
+    users.create_user("alice", "correct horse", Role::User).await.unwrap();
+    let app = init_app!(users);
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/auth/login/session")
+        .set_json(json!({ "username": "alice", "password": "correct horse" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.headers().get("Cache-Control").unwrap(), "no-store");
+
+    let session = resp.response().cookies().find(|cookie| cookie.name() == SESSION_COOKIE).unwrap().into_owned();
+    assert_eq!(session.http_only(), Some(true));
+    assert_eq!(session.secure(), Some(true));
+    assert_eq!(session.same_site(), Some(SameSite::Strict));
+    assert_eq!(session.path(), Some("/"));
+    let csrf = resp.response().cookies().find(|cookie| cookie.name() == CSRF_COOKIE).unwrap().into_owned();
+    assert_ne!(csrf.http_only(), Some(true), "The web UI reads the CSRF token");
+
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["user"]["username"], "alice");
+    assert_eq!(body["csrf_token"], csrf.value());
+    assert!(body.get("access_token").is_none(), "No tokens are handed to scripts");
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/auth/login/session")
+        .set_json(json!({ "username": "alice", "password": "wrong password" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(resp.response().cookies().count(), 0);
+}
+
+#[actix_web::test]
+async fn test_session_cookie_authenticates_requests() {
+    let (users, _) = create_test_user_service();
+    users.create_user("alice", "correct horse", Role::Admin).await.unwrap();
+    let app = init_app!(users);
+    let (session, csrf) = login_session!(app, "alice", "correct horse");
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/auth/me")
+        .cookie(session.clone())
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let principal: Value = test::read_body_json(resp).await;
+    assert_eq!(principal["subject"], "alice");
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/auth/me")
+        .cookie(Cookie::new(SESSION_COOKIE, "made-up"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let problem: Value = test::read_body_json(resp).await;
+    assert_eq!(problem["code"], "invalid_token");
+
+    // Requests that change something need the CSRF token in the header
+    let create_user = || test::TestRequest::post()
+        .uri("/api/v1/admin/users")
+        .cookie(session.clone())
+        .cookie(csrf.clone())
+        .set_json(json!({ "username": "bob", "password": "battery staple" }));
+
+    let resp = test::call_service(&app, create_user().to_request()).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    let problem: Value = test::read_body_json(resp).await;
+    assert_eq!(problem["code"], "invalid_csrf_token");
+    assert_eq!(problem["field"], CSRF_HEADER);
+
+    let req = create_user().insert_header((CSRF_HEADER, "made-up")).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+    let req = create_user().insert_header((CSRF_HEADER, csrf.value())).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+}
+
+#[actix_web::test]
+async fn test_csrf_header_must_match_the_session() {
+    let (users, _) = create_test_user_service();
+    users.create_user("alice", "correct horse", Role::User).await.unwrap();
+    users.create_user("mallory", "mallory password", Role::User).await.unwrap();
+    let app = init_app!(users);
+    let (session, _) = login_session!(app, "alice", "correct horse");
+    let (_, other_csrf) = login_session!(app, "mallory", "mallory password");
+
+    // A CSRF token of another session is rejected, even with a matching cookie
+    let req = test::TestRequest::delete()
+        .uri("/api/v1/auth/sessions/1")
+        .cookie(session)
+        .cookie(other_csrf.clone())
+        .insert_header((CSRF_HEADER, other_csrf.value()))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    let problem: Value = test::read_body_json(resp).await;
+    assert_eq!(problem["code"], "invalid_csrf_token");
+}
+
+#[actix_web::test]
+async fn test_logout_session() {
+    let (users, store) = create_test_user_service();
+    users.create_user("alice", "correct horse", Role::User).await.unwrap();
+    let app = init_app!(users);
+    let (session, csrf) = login_session!(app, "alice", "correct horse");
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/auth/logout/session")
+        .cookie(session.clone())
+        .cookie(csrf.clone())
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN, "Other sites cannot log the user out");
+    assert_eq!(store.session_count(), 1);
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/auth/logout/session")
+        .cookie(session.clone())
+        .cookie(csrf.clone())
+        .insert_header((CSRF_HEADER, csrf.value()))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    let removed = resp.response().cookies().find(|cookie| cookie.name() == SESSION_COOKIE).unwrap().into_owned();
+    assert_eq!(removed.value(), "");
+    assert_eq!(store.session_count(), 0);
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/auth/me")
+        .cookie(session)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn test_list_and_revoke_sessions() {
+    let (users, store) = create_test_user_service();
+    let admin = users.create_user("admin", "admin password", Role::Admin).await.unwrap();
+    let alice = users.create_user("alice", "correct horse", Role::User).await.unwrap();
+    let app = init_app!(users);
+    let (first, csrf) = login_session!(app, "alice", "correct horse");
+    let (second, _) = login_session!(app, "alice", "correct horse");
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/auth/sessions")
+        .cookie(first.clone())
+        .to_request();
+    let sessions: Value = test::call_and_read_body_json(&app, req).await;
+    let sessions = sessions.as_array().unwrap();
+    assert_eq!(sessions.len(), 2);
+    assert!(sessions[0].get("csrf_token").is_none());
+    let second_id = sessions.iter()
+        .map(|session| session["id"].as_u64().unwrap())
+        .max()
+        .unwrap();
+
+    let req = test::TestRequest::delete()
+        .uri(&format!("/api/v1/auth/sessions/{}", second_id))
+        .cookie(first.clone())
+        .cookie(csrf.clone())
+        .insert_header((CSRF_HEADER, csrf.value()))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+    let req = test::TestRequest::get().uri("/api/v1/auth/me").cookie(second).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    let req = test::TestRequest::delete()
+        .uri(&format!("/api/v1/auth/sessions/{}", second_id))
+        .cookie(first.clone())
+        .cookie(csrf.clone())
+        .insert_header((CSRF_HEADER, csrf.value()))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    let problem: Value = test::read_body_json(resp).await;
+    assert_eq!(problem["code"], "session_not_found");
+
+    // Administrators end all sessions of a user
+    let tokens = users.login("admin", "admin password", None).await.unwrap();
+    let req = test::TestRequest::delete()
+        .uri(&format!("/api/v1/admin/users/{}/sessions", alice.id))
+        .insert_header(("Authorization", format!("Bearer {}", tokens.access_token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    assert_eq!(store.session_count(), 0);
+
+    let req = test::TestRequest::delete()
+        .uri(&format!("/api/v1/admin/users/{}/sessions", admin.id + alice.id + 1))
+        .insert_header(("Authorization", format!("Bearer {}", tokens.access_token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn test_tokens_of_other_issuers_have_no_sessions() {
+    let (users, _) = create_test_user_service();
+    let alice = users.create_user("alice", "correct horse", Role::User).await.unwrap();
+    let app = init_app!(users);
+    login_session!(app, "alice", "correct horse");
+
+    let claims = json!({ "sub": "mallory", "iss": "https://idp.example.com", "uid": alice.id, "exp": Utc::now().timestamp() + 3600 });
+    let token = encode(&Header::default(), &claims, &EncodingKey::from_secret(TEST_JWT_SECRET)).unwrap();
+    let req = test::TestRequest::get()
+        .uri("/api/v1/auth/sessions")
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    let problem: Value = test::read_body_json(resp).await;
+    assert_eq!(problem["code"], "forbidden");
+}
+
+#[actix_web::test]
+async fn test_sessions_of_disabled_users_are_rejected() {
+    let (users, _) = create_test_user_service();
+    let alice = users.create_user("alice", "correct horse", Role::User).await.unwrap();
+    let app = init_app!(users);
+    let (session, _) = login_session!(app, "alice", "correct horse");
+
+    let update = UserUpdate { disabled: Some(true), ..UserUpdate::default() };
+    users.update_user(alice.id, update).await.unwrap();
+
+    let req = test::TestRequest::get().uri("/api/v1/auth/me").cookie(session).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn test_cookie_settings() {
+    let (users, _) = create_test_user_service();
+    users.create_user("alice", "correct horse", Role::User).await.unwrap();
+    let settings = CookieSettings { secure: false, same_site: SameSite::Lax, domain: Some("example.com".to_string()) };
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(create_test_s3_service().await))
+            .app_data(web::Data::new(users.clone()))
+            .app_data(web::Data::new(settings))
+            .service(configure_api_v1().wrap(Authentication::new(JwtVerifier::new().with_secret(TEST_JWT_SECRET))))
+    ).await;
+
+    let (session, _) = login_session!(app, "alice", "correct horse");
+    assert_ne!(session.secure(), Some(true));
+    assert_eq!(session.same_site(), Some(SameSite::Lax));
+    assert_eq!(session.domain(), Some("example.com"));
+}
+
+#[actix_web::test]
+async fn test_client_ip_trusts_only_configured_proxies() {
+    let proxy = "10.0.0.2".parse().unwrap();
+    let request = |peer: &str, forwarded: Option<&str>| {
+        let req = test::TestRequest::default().peer_addr(peer.parse().unwrap());
+        match forwarded {
+            Some(forwarded) => req.insert_header(("X-Forwarded-For", forwarded)).to_http_request(),
+            None => req.to_http_request(),
+        }
+    };
+
+    let req = request("192.0.2.7:4711", Some("198.51.100.1"));
+    assert_eq!(client_ip_behind(&req, &[]).as_deref(), Some("192.0.2.7"), "Headers are ignored without trusted proxies");
+    assert_eq!(client_ip_behind(&req, &[proxy]).as_deref(), Some("192.0.2.7"), "Headers of other peers are ignored");
+
+    let req = request("10.0.0.2:4711", Some("198.51.100.1, 192.0.2.7"));
+    assert_eq!(client_ip_behind(&req, &[proxy]).as_deref(), Some("192.0.2.7"), "Entries the client added are ignored");
+
+    let req = request("10.0.0.2:4711", Some("not-an-address"));
+    assert_eq!(client_ip_behind(&req, &[proxy]).as_deref(), Some("10.0.0.2"));
+
+    let req = request("10.0.0.2:4711", None);
+    assert_eq!(client_ip_behind(&req, &[proxy]).as_deref(), Some("10.0.0.2"));
+}
@@ -0,0 +1,187 @@
+#![cfg(test)]
+// Tests for the two-factor authentication API endpoints
+// These tests use the in-memory user store in place of MySQL
+
+use actix_web::http::StatusCode;
+use actix_web::{test, web, App};
+use serde_json::{json, Value};
+
+use crate::api::auth::Authentication;
+use crate::api::config::configure_api_v1;
+use crate::rdlib::auth::jwt::JwtVerifier;
+use crate::rdlib::users::two_factor::{TwoFactorPolicy, MAX_CLIENT_REQUESTS, RECOVERY_CODE_COUNT};
+use crate::rdlib::users::user::Role;
+use crate::tests::s3::service_test_helpers::create_test_s3_service;
+use crate::tests::users::memory_store::{create_test_user_service_with_policy, TEST_JWT_SECRET};
+use crate::tests::users::two_factor_tests::{current_code, enroll};
+
+macro_rules! init_app {
+    ($users:expr) => {
+        test::init_service(
+            App::new()
+                .app_data(web::Data::new(create_test_s3_service().await))
+                .app_data(web::Data::new($users.clone()))
+                .service(configure_api_v1().wrap(Authentication::new(JwtVerifier::new().with_secret(TEST_JWT_SECRET))))
+        ).await
+    };
+}
+
+#[actix_web::test]
+async fn test_admin_sets_up_two_factor_before_logging_in() {
+    let (users, _) = create_test_user_service_with_policy(TwoFactorPolicy::default());
+    users.create_user("admin", "admin password", Role::Admin).await.unwrap();
+    let app = init_app!(users);
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/auth/login")
+        .set_json(json!({ "username": "admin", "password": "admin password" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    let problem: Value = test::read_body_json(resp).await;
+    assert_eq!(problem["code"], "two_factor_setup_required");
+    let setup_token = problem["setup_token"].as_str().unwrap().to_string();
+
+    // The password alone does not let anyone set up a second factor
+    let req = test::TestRequest::post()
+        .uri("/api/v1/auth/2fa/setup")
+        .set_json(json!({ "username": "admin", "password": "admin password" }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::BAD_REQUEST);
+    let req = test::TestRequest::post()
+        .uri("/api/v1/auth/2fa/setup")
+        .set_json(json!({ "setup_token": "unknown" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let problem: Value = test::read_body_json(resp).await;
+    assert_eq!((problem["code"].as_str(), problem["field"].as_str()), (Some("invalid_token"), Some("setup_token")));
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/auth/2fa/setup")
+        .set_json(json!({ "setup_token": setup_token }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.headers().get("Cache-Control").unwrap(), "no-store");
+    let setup: Value = test::read_body_json(resp).await;
+    let secret = setup["secret"].as_str().unwrap().to_string();
+    assert!(setup["otpauth_uri"].as_str().unwrap().starts_with("otpauth://totp/RustDok:admin?"));
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/auth/2fa/enable")
+        .set_json(json!({ "setup_token": setup_token }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/auth/2fa/enable")
+        .set_json(json!({ "setup_token": setup_token, "otp": current_code(&secret, 0) }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["recovery_codes"].as_array().unwrap().len(), RECOVERY_CODE_COUNT);
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/auth/login")
+        .set_json(json!({ "username": "admin", "password": "admin password" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let problem: Value = test::read_body_json(resp).await;
+    assert_eq!(problem["code"], "two_factor_required");
+    assert_eq!(problem["field"], "otp");
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/auth/login")
+        .set_json(json!({ "username": "admin", "password": "admin password", "otp": current_code(&secret, 1) }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let tokens: Value = test::read_body_json(resp).await;
+    assert!(tokens["access_token"].is_string());
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/auth/2fa/disable")
+        .set_json(json!({ "username": "admin", "password": "admin password", "otp": body["recovery_codes"][0] }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN, "The policy requires two-factor authentication of administrators");
+}
+
+#[actix_web::test]
+async fn test_admin_resets_two_factor() {
+    let (users, store) = create_test_user_service_with_policy(TwoFactorPolicy::default());
+    users.create_user("admin", "admin password", Role::Admin).await.unwrap();
+    let alice = users.create_user("alice", "correct horse", Role::User).await.unwrap();
+    let (admin_setup, _) = enroll(&users, "admin", "admin password").await;
+    enroll(&users, "alice", "correct horse").await;
+    let admin_tokens = users.login("admin", "admin password", Some(&current_code(&admin_setup.secret, 1))).await.unwrap();
+    let app = init_app!(users);
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/auth/login")
+        .set_json(json!({ "username": "alice", "password": "correct horse", "otp": "123" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let problem: Value = test::read_body_json(resp).await;
+    assert_eq!(problem["code"], "invalid_two_factor_code");
+
+    let req = test::TestRequest::delete().uri(&format!("/api/v1/admin/users/{}/2fa", alice.id)).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED, "Resetting needs a token");
+
+    let req = test::TestRequest::delete()
+        .uri(&format!("/api/v1/admin/users/{}/2fa", alice.id))
+        .insert_header(("Authorization", format!("Bearer {}", admin_tokens.access_token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let user: Value = test::read_body_json(resp).await;
+    assert_eq!(user["two_factor_enabled"], false);
+    assert_eq!(store.recovery_code_count(alice.id), 0);
+    assert!(users.login("alice", "correct horse", None).await.is_ok());
+}
+
+#[actix_web::test]
+async fn test_two_factor_endpoints_are_throttled_by_client() {
+    let (users, _) = create_test_user_service_with_policy(TwoFactorPolicy::optional());
+    users.create_user("alice", "correct horse", Role::User).await.unwrap();
+    let app = init_app!(users);
+
+    let request = |peer: &str| test::TestRequest::post()
+        .uri("/api/v1/auth/2fa/setup-token")
+        .peer_addr(peer.parse().unwrap())
+        .set_json(json!({ "username": "alice", "password": "wrong password" }))
+        .to_request();
+    for _ in 0..MAX_CLIENT_REQUESTS {
+        assert_eq!(test::call_service(&app, request("192.0.2.1:1234")).await.status(), StatusCode::UNAUTHORIZED);
+    }
+    let resp = test::call_service(&app, request("192.0.2.1:1235")).await;
+    assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+    let problem: Value = test::read_body_json(resp).await;
+    assert_eq!(problem["code"], "too_many_attempts");
+
+    // Clients cannot pose as another client without a trusted proxy
+    let req = test::TestRequest::post()
+        .uri("/api/v1/auth/2fa/setup-token")
+        .peer_addr("192.0.2.1:1236".parse().unwrap())
+        .insert_header(("X-Forwarded-For", "198.51.100.1"))
+        .set_json(json!({ "username": "alice", "password": "wrong password" }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(test::call_service(&app, request("192.0.2.2:1234")).await.status(), StatusCode::UNAUTHORIZED);
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/auth/2fa/setup-token")
+        .peer_addr("192.0.2.2:1234".parse().unwrap())
+        .set_json(json!({ "username": "alice", "password": "correct horse" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: Value = test::read_body_json(resp).await;
+    assert!(users.setup_two_factor(body["setup_token"].as_str().unwrap()).await.is_ok());
+}
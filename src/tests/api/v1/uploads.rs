@@ -0,0 +1,128 @@
+#![cfg(test)]
+// Tests for the resumable upload API endpoints
+// These tests cover the protocol handling that happens before S3 is contacted
+
+use actix_web::{test, web, App};
+use actix_web::http::StatusCode;
+use crate::api::config::configure_api_v1;
+use crate::api::v1::uploads::{parse_upload_metadata, TUS_EXTENSIONS, TUS_VERSION};
+use crate::tests::s3::service_test_helpers::create_test_s3_service;
+
+#[actix_web::test]
+async fn test_parse_upload_metadata() {
+    let metadata = parse_upload_metadata("filename d29ybGRfZG9taW5hdGlvbl9wbGFuLnBkZg==, filetype YXBwbGljYXRpb24vcGRm,is_confidential")
+        .unwrap();
+
+    assert_eq!(metadata.get("filename").unwrap(), "world_domination_plan.pdf");
+    assert_eq!(metadata.get("filetype").unwrap(), "application/pdf");
+    assert_eq!(metadata.get("is_confidential").unwrap(), "");
+    assert!(parse_upload_metadata("").unwrap().is_empty());
+
+    assert!(parse_upload_metadata("filename not-base64!").is_err());
+    assert!(parse_upload_metadata("filename YQ==,filename Yg==").is_err(), "Duplicate keys should be rejected");
+}
+
+#[actix_web::test]
+async fn test_upload_options_advertise_protocol() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(create_test_s3_service().await))
+            .service(configure_api_v1())
+    ).await;
+
+    let req = test::TestRequest::default()
+        .method(actix_web::http::Method::OPTIONS)
+        .uri("/api/v1/bucket/test-bucket/uploads")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    assert_eq!(resp.headers().get("Tus-Version").unwrap(), TUS_VERSION);
+    assert_eq!(resp.headers().get("Tus-Extension").unwrap(), TUS_EXTENSIONS);
+    assert!(resp.headers().contains_key("Tus-Max-Size"));
+}
+
+#[actix_web::test]
+async fn test_uploads_require_supported_tus_version() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(create_test_s3_service().await))
+            .service(configure_api_v1())
+    ).await;
+
+    for version in [None, Some("0.2.2")] {
+        let mut req = test::TestRequest::post()
+            .uri("/api/v1/bucket/test-bucket/uploads")
+            .insert_header(("Upload-Length", "10"));
+        if let Some(version) = version {
+            req = req.insert_header(("Tus-Resumable", version));
+        }
+        let resp = test::call_service(&app, req.to_request()).await;
+
+        assert_eq!(resp.status(), StatusCode::PRECONDITION_FAILED, "Unexpected status for {:?}", version);
+        assert_eq!(resp.headers().get("Tus-Version").unwrap(), TUS_VERSION);
+    }
+}
+
+#[actix_web::test]
+async fn test_create_upload_rejects_invalid_requests() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(create_test_s3_service().await))
+            .service(configure_api_v1())
+    ).await;
+
+    let cases = [
+        (None, Some("filename YS50eHQ="), StatusCode::BAD_REQUEST),
+        (Some("-1"), Some("filename YS50eHQ="), StatusCode::BAD_REQUEST),
+        (Some("10"), None, StatusCode::BAD_REQUEST),
+        (Some("10"), Some("filename !!"), StatusCode::BAD_REQUEST),
+        (Some("10"), Some("key Li4vZXNjYXBlLnR4dA=="), StatusCode::BAD_REQUEST),
+        (Some("10"), Some("key LnJ1c3Rkb2svcG9saWN5Lmpzb24="), StatusCode::FORBIDDEN),
+        (Some("1099511627776"), Some("filename YS50eHQ="), StatusCode::PAYLOAD_TOO_LARGE),
+    ];
+
+    for (length, metadata, status) in cases {
+        let mut req = test::TestRequest::post()
+            .uri("/api/v1/bucket/test-bucket/uploads")
+            .insert_header(("Tus-Resumable", TUS_VERSION));
+        if let Some(length) = length {
+            req = req.insert_header(("Upload-Length", length));
+        }
+        if let Some(metadata) = metadata {
+            req = req.insert_header(("Upload-Metadata", metadata));
+        }
+        let resp = test::call_service(&app, req.to_request()).await;
+
+        assert_eq!(resp.status(), status, "Unexpected status for {:?} / {:?}", length, metadata);
+        assert_eq!(resp.headers().get("Tus-Resumable").unwrap(), TUS_VERSION);
+    }
+}
+
+#[actix_web::test]
+async fn test_append_requires_offset_content_type() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(create_test_s3_service().await))
+            .service(configure_api_v1())
+    ).await;
+
+    let req = test::TestRequest::patch()
+        .uri("/api/v1/bucket/test-bucket/uploads/abc123")
+        .insert_header(("Tus-Resumable", TUS_VERSION))
+        .insert_header(("Upload-Offset", "0"))
+        .insert_header(("Content-Type", "application/octet-stream"))
+        .set_payload("data")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+    let req = test::TestRequest::patch()
+        .uri("/api/v1/bucket/test-bucket/uploads/abc123")
+        .insert_header(("Tus-Resumable", TUS_VERSION))
+        .insert_header(("Content-Type", "application/offset+octet-stream"))
+        .set_payload("data")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST, "A missing Upload-Offset should be rejected");
+}
@@ -0,0 +1,214 @@
+#![cfg(test)]
+// Tests for the login and user management API endpoints
+// These tests use the in-memory user store in place of MySQL
+
+use actix_web::http::StatusCode;
+use actix_web::{test, web, App};
+use chrono::Utc;
+use jsonwebtoken::{encode, EncodingKey, Header};
+use serde_json::{json, Value};
+
+use crate::api::auth::Authentication;
+use crate::api::config::configure_api_v1;
+use crate::rdlib::auth::jwt::JwtVerifier;
+use crate::rdlib::users::user::Role;
+use crate::tests::access::memory_store::create_failing_test_authorizer;
+use crate::tests::s3::service_test_helpers::create_test_s3_service;
+use crate::tests::users::memory_store::{create_test_user_service, TEST_JWT_SECRET};
+
+macro_rules! init_app {
+    ($users:expr) => {
+        test::init_service(
+            App::new()
+                .app_data(web::Data::new(create_test_s3_service().await))
+                .app_data(web::Data::new($users.clone()))
+                .service(configure_api_v1().wrap(Authentication::new(JwtVerifier::new().with_secret(TEST_JWT_SECRET))))
+        ).await
+    };
+}
+
+#[actix_web::test]
+async fn test_login_and_manage_users() {
+    let (users, _) = create_test_user_service();
+    users.create_user("admin", "admin password", Role::Admin).await.unwrap();
+    let app = init_app!(users);
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/auth/login")
+        .set_json(json!({ "username": "admin", "password": "admin password" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let tokens: Value = test::read_body_json(resp).await;
+    let bearer = format!("Bearer {}", tokens["access_token"].as_str().unwrap());
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/admin/users")
+        .insert_header(("Authorization", bearer.clone()))
+        .set_json(json!({ "username": "alice", "password": "correct horse" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let alice: Value = test::read_body_json(resp).await;
+    assert_eq!(alice["role"], "user");
+    assert!(alice.get("password_hash").is_none());
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/admin/users")
+        .insert_header(("Authorization", bearer.clone()))
+        .set_json(json!({ "username": "alice", "password": "correct horse" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+    let problem: Value = test::read_body_json(resp).await;
+    assert_eq!(problem["code"], "user_already_exists");
+
+    let req = test::TestRequest::patch()
+        .uri(&format!("/api/v1/admin/users/{}", alice["id"]))
+        .insert_header(("Authorization", bearer.clone()))
+        .set_json(json!({ "disabled": true }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/auth/login")
+        .set_json(json!({ "username": "alice", "password": "correct horse" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    let problem: Value = test::read_body_json(resp).await;
+    assert_eq!(problem["code"], "account_disabled");
+
+    let req = test::TestRequest::delete()
+        .uri(&format!("/api/v1/admin/users/{}", alice["id"]))
+        .insert_header(("Authorization", bearer.clone()))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/admin/users")
+        .insert_header(("Authorization", bearer))
+        .to_request();
+    let list: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(list.as_array().unwrap().len(), 1);
+}
+
+#[actix_web::test]
+async fn test_login_failures() {
+    let (users, _) = create_test_user_service();
+    users.create_user("alice", "correct horse", Role::User).await.unwrap();
+    let app = init_app!(users);
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/auth/login")
+        .set_json(json!({ "username": "alice", "password": "wrong password" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let problem: Value = test::read_body_json(resp).await;
+    assert_eq!(problem["code"], "invalid_credentials");
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/auth/refresh")
+        .set_json(json!({ "refresh_token": "made-up" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let problem: Value = test::read_body_json(resp).await;
+    assert_eq!(problem["code"], "invalid_refresh_token");
+}
+
+#[actix_web::test]
+async fn test_admin_endpoints_need_admin_role() {
+    let (users, _) = create_test_user_service();
+    let admin = users.create_user("admin", "admin password", Role::Admin).await.unwrap();
+    users.create_user("alice", "correct horse", Role::User).await.unwrap();
+    let app = init_app!(users);
+
+    let tokens = users.login("alice", "correct horse", None).await.unwrap();
+    let req = test::TestRequest::get()
+        .uri("/api/v1/admin/users")
+        .insert_header(("Authorization", format!("Bearer {}", tokens.access_token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    let problem: Value = test::read_body_json(resp).await;
+    assert_eq!(problem["code"], "forbidden");
+
+    // Administrators cannot lock themselves out
+    let tokens = users.login("admin", "admin password", None).await.unwrap();
+    let req = test::TestRequest::delete()
+        .uri(&format!("/api/v1/admin/users/{}", admin.id))
+        .insert_header(("Authorization", format!("Bearer {}", tokens.access_token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn test_tokens_of_other_issuers_are_no_administrators() {
+    let (users, _) = create_test_user_service();
+    let admin = users.create_user("admin", "admin password", Role::Admin).await.unwrap();
+    let app = init_app!(users);
+
+    // Signed with a trusted key, but claiming the role as another issuer
+    let claims = json!({
+        "sub": "admin",
+        "iss": "https://idp.example.com",
+        "uid": admin.id,
+        "roles": ["admin"],
+        "exp": Utc::now().timestamp() + 3600
+    });
+    let token = encode(&Header::default(), &claims, &EncodingKey::from_secret(TEST_JWT_SECRET)).unwrap();
+    let req = test::TestRequest::get()
+        .uri("/api/v1/admin/users")
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    let problem: Value = test::read_body_json(resp).await;
+    assert_eq!(problem["code"], "forbidden");
+}
+
+#[actix_web::test]
+async fn test_user_is_kept_if_its_grants_cannot_be_revoked() {
+    let (users, _) = create_test_user_service();
+    users.create_user("admin", "admin password", Role::Admin).await.unwrap();
+    let alice = users.create_user("alice", "correct horse", Role::User).await.unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(create_test_s3_service().await))
+            .app_data(web::Data::new(users.clone()))
+            .app_data(web::Data::new(create_failing_test_authorizer()))
+            .service(configure_api_v1().wrap(Authentication::new(JwtVerifier::new().with_secret(TEST_JWT_SECRET))))
+    ).await;
+
+    let tokens = users.login("admin", "admin password", None).await.unwrap();
+    let req = test::TestRequest::delete()
+        .uri(&format!("/api/v1/admin/users/{}", alice.id))
+        .insert_header(("Authorization", format!("Bearer {}", tokens.access_token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    assert!(users.get_user(alice.id).await.is_ok(), "A user whose grants are kept must not be deleted");
+}
+
+#[actix_web::test]
+async fn test_login_without_user_database() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(create_test_s3_service().await))
+            .service(configure_api_v1().wrap(Authentication::new(JwtVerifier::new().with_secret(TEST_JWT_SECRET))))
+    ).await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/auth/login")
+        .set_json(json!({ "username": "alice", "password": "correct horse" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NOT_IMPLEMENTED);
+    let problem: Value = test::read_body_json(resp).await;
+    assert_eq!(problem["code"], "not_enabled");
+}
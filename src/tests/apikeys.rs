@@ -0,0 +1,3 @@
+pub mod memory_store;
+pub mod apikey_tests;
+pub mod mysql_tests;
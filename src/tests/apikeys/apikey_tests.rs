@@ -0,0 +1,124 @@
+#![cfg(test)]
+// Tests for service accounts, API keys and their scopes
+// These tests use the in-memory store in place of MySQL
+
+use chrono::{Duration, Utc};
+
+use crate::rdlib::access::authorizer::Access;
+use crate::rdlib::access::grant::{AccessRole, Grant, Permission, SubjectType};
+use crate::rdlib::apikeys::error::ApiKeyError;
+use crate::rdlib::apikeys::key::{generate_key, hash_api_key, is_api_key, normalize_scopes, KeyScope, API_KEY_PREFIX};
+use crate::tests::apikeys::memory_store::create_test_api_key_service;
+
+fn scope(bucket: Option<&str>, prefix: &str, role: AccessRole) -> KeyScope {
+    KeyScope { bucket: bucket.map(str::to_string), prefix: prefix.to_string(), role }
+}
+
+#[test]
+fn test_generated_keys() {
+    let (key, prefix) = generate_key();
+    assert!(is_api_key(&key));
+    assert!(key.starts_with(&prefix) && prefix.starts_with(API_KEY_PREFIX));
+    assert_eq!(key.len(), API_KEY_PREFIX.len() + 44);
+    assert_ne!(key, generate_key().0);
+    assert_eq!(hash_api_key(&key).len(), 64);
+    assert!(!is_api_key("eyJhbGciOiJIUzI1NiJ9.e30.sig"));
+}
+
+#[test]
+fn test_normalize_scopes() {
+    let scopes = normalize_scopes(vec![scope(Some("docs"), "/reports", AccessRole::Viewer)]).unwrap();
+    assert_eq!(scopes, vec![scope(Some("docs"), "reports/", AccessRole::Viewer)]);
+
+    assert!(normalize_scopes(vec![scope(None, "reports", AccessRole::Viewer)]).is_err(), "Prefixes need a bucket");
+    assert!(normalize_scopes(vec![scope(Some("Not A Bucket"), "", AccessRole::Viewer)]).is_err());
+    assert!(normalize_scopes(vec![scope(Some("docs"), "../up", AccessRole::Viewer)]).is_err());
+}
+
+#[test]
+fn test_scopes_limit_access() {
+    let grant = Grant {
+        id: 1,
+        subject_type: SubjectType::User,
+        subject: "sa:ci".to_string(),
+        bucket: "docs".to_string(),
+        prefix: String::new(),
+        role: AccessRole::Editor,
+        created_at: Utc::now(),
+    };
+    let access = Access::from_grants(vec![grant]).with_scopes(Some(vec![scope(Some("docs"), "builds/", AccessRole::Admin)]));
+    assert_eq!(access.role("docs", "builds/app.zip"), Some(AccessRole::Editor), "Scopes do not raise the granted role");
+    assert_eq!(access.role("docs", "reports/q1.pdf"), None);
+    assert!(access.can_see("docs", "") && !access.can_see("docs", "reports/"));
+
+    let read_only = Access::unrestricted().with_scopes(Some(vec![scope(None, "", AccessRole::Viewer)]));
+    assert!(!read_only.is_unrestricted());
+    assert!(read_only.allows("photos", "cat.jpg", Permission::Read));
+    assert!(!read_only.allows("photos", "cat.jpg", Permission::Write));
+    assert!(read_only.can_see_bucket("photos"));
+
+    let none = Access::unrestricted().with_scopes(Some(Vec::new()));
+    assert!(!none.can_see_bucket("photos"), "A malformed scopes claim allows nothing");
+}
+
+#[tokio::test]
+async fn test_authenticate_with_a_key() {
+    let service = create_test_api_key_service();
+    let account = service.create_account("ci", "Builds").await.unwrap();
+    let scopes = vec![scope(Some("docs"), "builds", AccessRole::Uploader)];
+    let (key, secret) = service.create_key(account.id, "deploy", scopes, None).await.unwrap();
+    assert_eq!(key.scopes[0].prefix, "builds/");
+
+    let principal = service.authenticate(&secret).await.unwrap();
+    assert_eq!(principal.subject, "sa:ci");
+    assert!(!principal.is_admin());
+    assert_eq!(principal.scopes(), Some(key.scopes.clone()));
+
+    let listed = service.list_keys(account.id).await.unwrap();
+    assert!(listed[0].last_used_at.is_some(), "The last use is recorded");
+
+    assert_eq!(service.authenticate("rdk_unknown").await.unwrap_err(), ApiKeyError::InvalidKey);
+    service.revoke_key(account.id, key.id).await.unwrap();
+    assert_eq!(service.authenticate(&secret).await.unwrap_err(), ApiKeyError::InvalidKey);
+}
+
+#[tokio::test]
+async fn test_manage_keys() {
+    let service = create_test_api_key_service();
+    let account = service.create_account("ci", "").await.unwrap();
+    assert!(matches!(service.create_account("ci", "").await, Err(ApiKeyError::NameTaken(_))));
+    assert!(matches!(service.create_account("sa:ci", "").await, Err(ApiKeyError::InvalidName(_))));
+
+    let past = Utc::now() - Duration::hours(1);
+    assert!(matches!(service.create_key(account.id, "old", vec![], Some(past)).await, Err(ApiKeyError::InvalidExpiry(_))));
+    assert_eq!(service.create_key(42, "deploy", vec![], None).await.unwrap_err(), ApiKeyError::AccountNotFound);
+
+    let other = service.create_account("backup", "").await.unwrap();
+    let (key, _) = service.create_key(account.id, "deploy", vec![], None).await.unwrap();
+    assert_eq!(service.revoke_key(other.id, key.id).await.unwrap_err(), ApiKeyError::KeyNotFound, "Keys belong to their account");
+
+    service.delete_account(account.id).await.unwrap();
+    assert_eq!(service.list_keys(account.id).await.unwrap_err(), ApiKeyError::AccountNotFound);
+}
+
+#[tokio::test]
+async fn test_rotate_keys() {
+    let service = create_test_api_key_service();
+    let account = service.create_account("ci", "").await.unwrap();
+    let scopes = vec![scope(None, "", AccessRole::Viewer)];
+    let (old, old_secret) = service.create_key(account.id, "deploy", scopes, None).await.unwrap();
+
+    let (new, new_secret) = service.rotate_key(account.id, old.id, 3600).await.unwrap();
+    assert_ne!(new.id, old.id);
+    assert_eq!((&new.name, &new.scopes), (&old.name, &old.scopes));
+    assert!(service.authenticate(&old_secret).await.is_ok(), "The old key works during the grace period");
+    assert!(service.authenticate(&new_secret).await.is_ok());
+    let keys = service.list_keys(account.id).await.unwrap();
+    assert!(keys.iter().find(|key| key.id == old.id).unwrap().expires_at.is_some());
+
+    let (_, newest_secret) = service.rotate_key(account.id, new.id, 0).await.unwrap();
+    assert_eq!(service.authenticate(&new_secret).await.unwrap_err(), ApiKeyError::InvalidKey, "Without grace the old key is revoked");
+    assert!(service.authenticate(&newest_secret).await.is_ok());
+
+    assert!(matches!(service.rotate_key(account.id, old.id, 30 * 24 * 3600).await, Err(ApiKeyError::InvalidGracePeriod(_))));
+}
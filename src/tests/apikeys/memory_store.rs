@@ -0,0 +1,118 @@
+#![cfg(test)]
+// An in-memory API key store standing in for MySQL in tests
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+use crate::rdlib::apikeys::error::ApiKeyError;
+use crate::rdlib::apikeys::key::{ApiKey, NewApiKey, ServiceAccount};
+use crate::rdlib::apikeys::service::ApiKeyService;
+use crate::rdlib::apikeys::store::ApiKeyStore;
+
+#[derive(Default)]
+struct State {
+    next_id: u64,
+    accounts: BTreeMap<u64, ServiceAccount>,
+    keys: BTreeMap<u64, (ApiKey, String)>,
+}
+
+/// Keeps service accounts and keys in memory, with the same behavior as `MySqlApiKeyStore`.
+#[derive(Default)]
+pub struct MemoryApiKeyStore {
+    state: Mutex<State>,
+}
+
+#[async_trait]
+impl ApiKeyStore for MemoryApiKeyStore {
+    async fn create_account(&self, name: &str, description: &str, now: DateTime<Utc>) -> Result<ServiceAccount, ApiKeyError> {
+        let mut state = self.state.lock().unwrap();
+        if state.accounts.values().any(|account| account.name == name) {
+            return Err(ApiKeyError::NameTaken(name.to_string()));
+        }
+        state.next_id += 1;
+        let account = ServiceAccount {
+            id: state.next_id,
+            name: name.to_string(),
+            description: description.to_string(),
+            subject: ServiceAccount::subject_of(name),
+            created_at: now,
+        };
+        state.accounts.insert(account.id, account.clone());
+        Ok(account)
+    }
+
+    async fn get_account(&self, id: u64) -> Result<Option<ServiceAccount>, ApiKeyError> {
+        Ok(self.state.lock().unwrap().accounts.get(&id).cloned())
+    }
+
+    async fn list_accounts(&self) -> Result<Vec<ServiceAccount>, ApiKeyError> {
+        let mut accounts = self.state.lock().unwrap().accounts.values().cloned().collect::<Vec<_>>();
+        accounts.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(accounts)
+    }
+
+    async fn delete_account(&self, id: u64) -> Result<bool, ApiKeyError> {
+        let mut state = self.state.lock().unwrap();
+        state.keys.retain(|_, (key, _)| key.service_account_id != id);
+        Ok(state.accounts.remove(&id).is_some())
+    }
+
+    async fn create_key(&self, key: NewApiKey, now: DateTime<Utc>) -> Result<ApiKey, ApiKeyError> {
+        let mut state = self.state.lock().unwrap();
+        state.next_id += 1;
+        let stored = ApiKey {
+            id: state.next_id,
+            service_account_id: key.service_account_id,
+            name: key.name,
+            key_prefix: key.key_prefix,
+            scopes: key.scopes,
+            expires_at: key.expires_at,
+            last_used_at: None,
+            created_at: now,
+        };
+        state.keys.insert(stored.id, (stored.clone(), key.key_hash));
+        Ok(stored)
+    }
+
+    async fn get_key(&self, id: u64) -> Result<Option<ApiKey>, ApiKeyError> {
+        Ok(self.state.lock().unwrap().keys.get(&id).map(|(key, _)| key.clone()))
+    }
+
+    async fn list_keys(&self, account_id: u64) -> Result<Vec<ApiKey>, ApiKeyError> {
+        let state = self.state.lock().unwrap();
+        Ok(state.keys.values().filter(|(key, _)| key.service_account_id == account_id).map(|(key, _)| key.clone()).collect())
+    }
+
+    async fn find_key(&self, key_hash: &str) -> Result<Option<(ApiKey, ServiceAccount)>, ApiKeyError> {
+        let state = self.state.lock().unwrap();
+        Ok(state.keys.values()
+            .find(|(_, hash)| hash == key_hash)
+            .and_then(|(key, _)| state.accounts.get(&key.service_account_id).map(|account| (key.clone(), account.clone()))))
+    }
+
+    async fn touch_key(&self, id: u64, now: DateTime<Utc>) -> Result<(), ApiKeyError> {
+        if let Some((key, _)) = self.state.lock().unwrap().keys.get_mut(&id) {
+            key.last_used_at = Some(now);
+        }
+        Ok(())
+    }
+
+    async fn set_key_expiry(&self, id: u64, expires_at: DateTime<Utc>) -> Result<(), ApiKeyError> {
+        if let Some((key, _)) = self.state.lock().unwrap().keys.get_mut(&id) {
+            key.expires_at = Some(expires_at);
+        }
+        Ok(())
+    }
+
+    async fn delete_key(&self, id: u64) -> Result<bool, ApiKeyError> {
+        Ok(self.state.lock().unwrap().keys.remove(&id).is_some())
+    }
+}
+
+/// Creates an API key service on an in-memory store.
+pub fn create_test_api_key_service() -> Arc<ApiKeyService> {
+    Arc::new(ApiKeyService::new(Arc::new(MemoryApiKeyStore::default())))
+}
@@ -0,0 +1,52 @@
+#![cfg(test)]
+// Tests for the MySQL API key store
+// These tests need a MySQL database and are ignored by default; see the
+// MySQL user store tests for how to start one and run them
+
+use chrono::{Duration, Utc};
+use uuid::Uuid;
+
+use crate::rdlib::access::grant::AccessRole;
+use crate::rdlib::apikeys::error::ApiKeyError;
+use crate::rdlib::apikeys::key::{generate_key, hash_api_key, KeyScope, NewApiKey};
+use crate::rdlib::apikeys::mysql::MySqlApiKeyStore;
+use crate::rdlib::apikeys::store::ApiKeyStore;
+use crate::rdlib::users::mysql::{connect_pool, MySqlUserStore};
+
+#[tokio::test]
+#[ignore]
+async fn test_mysql_api_keys() {
+    let url = std::env::var("RUSTDOK_TEST_DATABASE_URL").expect("RUSTDOK_TEST_DATABASE_URL must be set");
+    MySqlUserStore::connect(&url).await.unwrap().migrate().await.unwrap();
+    let store = MySqlApiKeyStore::new(connect_pool(&url).await.unwrap());
+    let now = Utc::now();
+
+    let name = format!("sa-{}", Uuid::new_v4().simple());
+    let account = store.create_account(&name, "Builds", now).await.unwrap();
+    assert_eq!(account.subject, format!("sa:{}", name));
+    assert!(matches!(store.create_account(&name, "", now).await, Err(ApiKeyError::NameTaken(_))));
+
+    let (secret, key_prefix) = generate_key();
+    let scopes = vec![KeyScope { bucket: Some("docs".to_string()), prefix: "builds/".to_string(), role: AccessRole::Uploader }];
+    let key = store.create_key(NewApiKey {
+        service_account_id: account.id,
+        name: "deploy".to_string(),
+        key_prefix,
+        key_hash: hash_api_key(&secret),
+        scopes: scopes.clone(),
+        expires_at: None,
+    }, now).await.unwrap();
+
+    let (found, owner) = store.find_key(&hash_api_key(&secret)).await.unwrap().unwrap();
+    assert_eq!((found.id, found.scopes, owner.id), (key.id, scopes, account.id));
+    assert!(store.find_key(&hash_api_key("rdk_unknown")).await.unwrap().is_none());
+
+    store.touch_key(key.id, now).await.unwrap();
+    store.set_key_expiry(key.id, now + Duration::hours(1)).await.unwrap();
+    let stored = store.get_key(key.id).await.unwrap().unwrap();
+    assert!(stored.last_used_at.is_some() && stored.expires_at.is_some());
+    assert_eq!(store.list_keys(account.id).await.unwrap().len(), 1);
+
+    assert!(store.delete_account(account.id).await.unwrap());
+    assert!(store.get_key(key.id).await.unwrap().is_none(), "Keys are deleted with their account");
+}
@@ -0,0 +1,89 @@
+#![cfg(test)]
+// Tests for the streaming ZIP writer
+
+use std::io::{Cursor, Read};
+use chrono::{TimeZone, Utc};
+use crate::rdlib::archive::zip_stream::{should_compress, ZipStreamWriter};
+use crate::api::v1::archives::{entry_name, normalize_root};
+
+fn build_archive(entries: &[(&str, &[&[u8]])]) -> Vec<u8> {
+    let modified = Utc.with_ymd_and_hms(2024, 5, 17, 13, 45, 30).unwrap();
+    let mut zip = ZipStreamWriter::new();
+    let mut archive = Vec::new();
+
+    for (name, chunks) in entries {
+        archive.extend(zip.start_entry(name, modified));
+        for chunk in *chunks {
+            archive.extend(zip.write_data(chunk));
+        }
+        archive.extend(zip.finish_entry());
+    }
+    archive.extend(zip.finish());
+    archive
+}
+
+#[test]
+fn test_streamed_archive_can_be_read() {
+    let text = "hello world\n".repeat(1000);
+    let archive = build_archive(&[
+        ("reports/", &[]),
+        ("reports/2024.txt", &[text.as_bytes(), b"more text"]),
+        ("reports/photo.jpg", &[&[0xFF, 0xD8, 0xFF, 0xE0], &[1, 2, 3]]),
+        ("readme", &[]),
+    ]);
+
+    let mut zip = zip::ZipArchive::new(Cursor::new(archive)).expect("The archive should be readable");
+    assert_eq!(zip.len(), 4);
+
+    let mut file = zip.by_name("reports/2024.txt").unwrap();
+    assert_eq!(file.compression(), zip::CompressionMethod::Deflated);
+    let mut content = String::new();
+    file.read_to_string(&mut content).unwrap();
+    assert_eq!(content, format!("{}more text", text));
+    drop(file);
+
+    let mut file = zip.by_name("reports/photo.jpg").unwrap();
+    assert_eq!(file.compression(), zip::CompressionMethod::Stored, "JPEGs are already compressed");
+    let mut content = Vec::new();
+    file.read_to_end(&mut content).unwrap();
+    assert_eq!(content, vec![0xFF, 0xD8, 0xFF, 0xE0, 1, 2, 3]);
+    drop(file);
+
+    assert!(zip.by_name("reports/").unwrap().is_dir());
+    assert_eq!(zip.by_name("readme").unwrap().size(), 0);
+}
+
+#[test]
+fn test_empty_archive_can_be_read() {
+    let archive = build_archive(&[]);
+    let zip = zip::ZipArchive::new(Cursor::new(archive)).expect("An empty archive should be readable");
+    assert_eq!(zip.len(), 0);
+}
+
+#[test]
+fn test_should_compress() {
+    assert!(should_compress("docs/notes.txt"));
+    assert!(should_compress("Makefile"));
+    assert!(!should_compress("photos/IMG_0001.JPG"));
+    assert!(!should_compress("backup.tar.gz"));
+}
+
+#[test]
+fn test_archive_paths_are_relative_to_root() {
+    assert_eq!(normalize_root(""), "");
+    assert_eq!(normalize_root("/docs/reports"), "docs/reports/");
+
+    let root = normalize_root("docs");
+    assert_eq!(entry_name("docs/reports/2024.pdf", &root).as_deref(), Some("reports/2024.pdf"));
+    assert_eq!(entry_name("docs/", &root), None, "The root itself is not an entry");
+    assert_eq!(entry_name("images/logo.png", &root), None);
+    assert_eq!(entry_name("images/logo.png", "").as_deref(), Some("images/logo.png"));
+}
+
+#[test]
+fn test_archive_paths_are_sanitized() {
+    assert_eq!(entry_name("/etc/passwd", "").as_deref(), Some("etc/passwd"));
+    assert_eq!(entry_name("docs/../../etc/passwd", "").as_deref(), Some("docs/etc/passwd"));
+    assert_eq!(entry_name("docs//./reports/", "").as_deref(), Some("docs/reports/"), "Folders should keep their slash");
+    assert_eq!(entry_name("docs/../", &normalize_root("docs")), None, "Keys without a name left are skipped");
+}
@@ -0,0 +1,3 @@
+pub mod memory_store;
+pub mod audit_tests;
+pub mod mysql_tests;
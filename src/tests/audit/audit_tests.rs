@@ -0,0 +1,158 @@
+#![cfg(test)]
+// Tests for the audit log records and service
+// These tests use the in-memory store in place of MySQL
+
+use std::path::Path;
+
+use chrono::{Duration, Utc};
+
+use crate::rdlib::audit::error::AuditError;
+use crate::rdlib::audit::record::{action_for, AuditFilter, AuditOutcome, NewAuditRecord, CSV_HEADER, MAX_KEY_LENGTH};
+use crate::tests::audit::memory_store::create_test_audit_service;
+
+fn new_record(actor: &str, action: &str, key: Option<&str>) -> NewAuditRecord {
+    NewAuditRecord {
+        occurred_at: Utc::now(),
+        request_id: Some("req-1".to_string()),
+        actor: Some(actor.to_string()),
+        ip_address: Some("192.0.2.1".to_string()),
+        action: action.to_string(),
+        method: "DELETE".to_string(),
+        bucket: Some("docs".to_string()),
+        key: key.map(str::to_string),
+        target: None,
+        outcome: AuditOutcome::Success,
+        status: 204,
+        bytes: None,
+    }
+}
+
+#[test]
+fn test_action_for() {
+    assert_eq!(action_for("DELETE", Some("/api/v1/bucket/{bucket}/object/{key:.*}")), "object.delete");
+    assert_eq!(action_for("DELETE", Some("/api/v1/bucket/{name}")), "bucket.delete");
+    assert_eq!(action_for("GET", Some("/api/v1/admin/audit/export")), "audit.export");
+    assert_eq!(action_for("TRACE", Some("/api/v1/buckets")), "TRACE /buckets");
+    assert_eq!(action_for("GET", None), "unknown");
+}
+
+/// The attributes that register a handler for a route
+const ROUTE_ATTRIBUTES: &[&str] = &["#[get(", "#[post(", "#[put(", "#[patch(", "#[delete(", "#[route("];
+
+/// Lists the method and path of every endpoint `configure_api_v1` registers.
+///
+/// The handlers are read from the registrations in the sources, and their
+/// routes from the attribute in front of each handler.
+fn registered_routes() -> Vec<(String, String)> {
+    let read = |path: &str| std::fs::read_to_string(Path::new(env!("CARGO_MANIFEST_DIR")).join(path)).unwrap();
+    let config = read("src/api/config.rs");
+
+    config.lines()
+        .filter_map(|line| line.trim().strip_prefix(".service(crate::api::v1::")?.strip_suffix(')'))
+        .map(|handler| {
+            let (module, function) = handler.split_once("::").unwrap();
+            let source = read(&format!("src/api/v1/{}.rs", module));
+            let definition = source.find(&format!("pub async fn {}(", function))
+                .unwrap_or_else(|| panic!("Handler {} not found", handler));
+            let attribute = source[..definition].lines().rev()
+                .find(|line| ROUTE_ATTRIBUTES.iter().any(|a| line.starts_with(a)))
+                .unwrap_or_else(|| panic!("Handler {} has no route", handler));
+
+            let (kind, arguments) = attribute.trim_start_matches("#[").split_once('(').unwrap();
+            let path = arguments.split('"').nth(1).unwrap().to_string();
+            let method = match kind {
+                "route" => arguments.split("method = \"").nth(1).unwrap().split('"').next().unwrap().to_string(),
+                method => method.to_uppercase(),
+            };
+            (method, path)
+        })
+        .collect()
+}
+
+#[test]
+fn test_every_endpoint_has_an_action() {
+    let routes = registered_routes();
+    assert!(routes.len() > 60, "Only {} routes found", routes.len());
+
+    for (method, path) in routes {
+        let action = action_for(&method, Some(&format!("/api/v1{}", path)));
+        assert_ne!(action, format!("{} {}", method, path), "{} {} has no audit action", method, path);
+    }
+}
+
+#[test]
+fn test_outcome_from_status() {
+    assert_eq!(AuditOutcome::from_status(200), AuditOutcome::Success);
+    assert_eq!(AuditOutcome::from_status(304), AuditOutcome::Success);
+    assert_eq!(AuditOutcome::from_status(401), AuditOutcome::Denied);
+    assert_eq!(AuditOutcome::from_status(403), AuditOutcome::Denied);
+    assert_eq!(AuditOutcome::from_status(404), AuditOutcome::Failure);
+    assert_eq!(AuditOutcome::from_status(500), AuditOutcome::Failure);
+    assert_eq!("denied".parse::<AuditOutcome>(), Ok(AuditOutcome::Denied));
+    assert!("maybe".parse::<AuditOutcome>().is_err());
+}
+
+#[tokio::test]
+async fn test_record_is_truncated() {
+    let (audit, store) = create_test_audit_service();
+    let long_key = "k".repeat(MAX_KEY_LENGTH + 10);
+    audit.record(new_record(&"a".repeat(300), "object.delete", Some(&long_key))).await.unwrap();
+
+    let record = &store.records()[0];
+    assert_eq!(record.actor.as_ref().unwrap().len(), 255);
+    assert_eq!(record.key.as_ref().unwrap().len(), MAX_KEY_LENGTH);
+}
+
+#[tokio::test]
+async fn test_list_filters_and_pages() {
+    let (audit, _) = create_test_audit_service();
+    for i in 0..5 {
+        audit.record(new_record("alice", "object.delete", Some(&format!("reports/{}.pdf", i)))).await.unwrap();
+    }
+    audit.record(new_record("bob", "object.delete", Some("other/file.txt"))).await.unwrap();
+    audit.record(new_record("bob", "user.delete", None)).await.unwrap();
+
+    let all = audit.list(&AuditFilter::default(), None).await.unwrap();
+    assert_eq!(all.records.len(), 7);
+    assert_eq!(all.records[0].action, "user.delete");
+    assert_eq!(all.next_before, None);
+
+    let bob = AuditFilter { actor: Some("bob".to_string()), ..Default::default() };
+    assert_eq!(audit.list(&bob, None).await.unwrap().records.len(), 2);
+    let prefix = AuditFilter { prefix: Some("reports/".to_string()), ..Default::default() };
+    assert_eq!(audit.list(&prefix, None).await.unwrap().records.len(), 5);
+
+    let first = audit.list(&prefix, Some(3)).await.unwrap();
+    assert_eq!(first.records.iter().map(|r| r.id).collect::<Vec<_>>(), vec![5, 4, 3]);
+    assert_eq!(first.next_before, Some(3));
+    let next = AuditFilter { before: first.next_before, ..prefix };
+    let second = audit.list(&next, Some(3)).await.unwrap();
+    assert_eq!(second.records.iter().map(|r| r.id).collect::<Vec<_>>(), vec![2, 1]);
+    assert_eq!(second.next_before, None);
+}
+
+#[tokio::test]
+async fn test_list_rejects_invalid_queries() {
+    let (audit, _) = create_test_audit_service();
+    assert!(matches!(audit.list(&AuditFilter::default(), Some(0)).await, Err(AuditError::InvalidLimit(_))));
+    assert!(matches!(audit.list(&AuditFilter::default(), Some(1001)).await, Err(AuditError::InvalidLimit(_))));
+
+    let now = Utc::now();
+    let backwards = AuditFilter { since: Some(now), until: Some(now - Duration::hours(1)), ..Default::default() };
+    assert!(matches!(audit.list(&backwards, None).await, Err(AuditError::InvalidTimeRange(_))));
+}
+
+#[tokio::test]
+async fn test_csv_lines() {
+    let (audit, store) = create_test_audit_service();
+    let mut record = new_record("alice", "object.delete", Some("reports/a \"quoted\", file.csv"));
+    record.target = Some("=HYPERLINK(\"x\")".to_string());
+    audit.record(record).await.unwrap();
+
+    let line = store.records()[0].to_csv_line();
+    assert!(CSV_HEADER.starts_with("id,occurred_at,") && CSV_HEADER.ends_with("\r\n"));
+    assert!(line.starts_with("1,"));
+    assert!(line.contains(",\"reports/a \"\"quoted\"\", file.csv\","));
+    assert!(line.contains(",\"'=HYPERLINK(\"\"x\"\")\","));
+    assert!(line.ends_with(",success,204,\r\n"));
+}
@@ -0,0 +1,77 @@
+#![cfg(test)]
+// An in-memory audit store standing in for MySQL in tests
+
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+
+use crate::rdlib::audit::error::AuditError;
+use crate::rdlib::audit::record::{AuditFilter, AuditRecord, NewAuditRecord};
+use crate::rdlib::audit::service::AuditService;
+use crate::rdlib::audit::store::AuditStore;
+
+/// Keeps the audit log in memory, with the same behavior as `MySqlAuditStore`.
+#[derive(Default)]
+pub struct MemoryAuditStore {
+    records: Mutex<Vec<AuditRecord>>,
+}
+
+impl MemoryAuditStore {
+    /// All records, oldest first.
+    pub fn records(&self) -> Vec<AuditRecord> {
+        self.records.lock().unwrap().clone()
+    }
+}
+
+fn matches(record: &AuditRecord, filter: &AuditFilter) -> bool {
+    let equals = |wanted: &Option<String>, value: &Option<String>| wanted.is_none() || wanted == value;
+    equals(&filter.actor, &record.actor)
+        && filter.action.as_ref().is_none_or(|action| *action == record.action)
+        && equals(&filter.bucket, &record.bucket)
+        && equals(&filter.key, &record.key)
+        && filter.prefix.as_ref().is_none_or(|prefix| record.key.as_ref().is_some_and(|key| key.starts_with(prefix.as_str())))
+        && filter.outcome.is_none_or(|outcome| outcome == record.outcome)
+        && equals(&filter.ip_address, &record.ip_address)
+        && filter.since.is_none_or(|since| record.occurred_at >= since)
+        && filter.until.is_none_or(|until| record.occurred_at < until)
+        && filter.before.is_none_or(|before| record.id < before)
+}
+
+#[async_trait]
+impl AuditStore for MemoryAuditStore {
+    async fn add_record(&self, record: NewAuditRecord) -> Result<u64, AuditError> {
+        let mut records = self.records.lock().unwrap();
+        let id = records.len() as u64 + 1;
+        records.push(AuditRecord {
+            id,
+            occurred_at: record.occurred_at,
+            request_id: record.request_id,
+            actor: record.actor,
+            ip_address: record.ip_address,
+            action: record.action,
+            method: record.method,
+            bucket: record.bucket,
+            key: record.key,
+            target: record.target,
+            outcome: record.outcome,
+            status: record.status,
+            bytes: record.bytes,
+        });
+        Ok(id)
+    }
+
+    async fn list_records(&self, filter: &AuditFilter, limit: u32) -> Result<Vec<AuditRecord>, AuditError> {
+        Ok(self.records.lock().unwrap().iter()
+            .rev()
+            .filter(|record| matches(record, filter))
+            .take(limit as usize)
+            .cloned()
+            .collect())
+    }
+}
+
+/// Creates an audit service on an in-memory store.
+pub fn create_test_audit_service() -> (Arc<AuditService>, Arc<MemoryAuditStore>) {
+    let store = Arc::new(MemoryAuditStore::default());
+    (Arc::new(AuditService::new(store.clone())), store)
+}
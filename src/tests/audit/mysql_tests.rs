@@ -0,0 +1,66 @@
+#![cfg(test)]
+// Tests for the MySQL audit store
+// These tests need a MySQL database and are ignored by default; see the
+// MySQL user store tests for how to start one and run them
+
+use chrono::{Duration, Utc};
+use uuid::Uuid;
+
+use crate::rdlib::audit::mysql::MySqlAuditStore;
+use crate::rdlib::audit::record::{AuditFilter, AuditOutcome, NewAuditRecord};
+use crate::rdlib::audit::store::AuditStore;
+use crate::rdlib::users::mysql::{connect_pool, MySqlUserStore};
+
+#[tokio::test]
+#[ignore]
+async fn test_mysql_audit_log() {
+    let url = std::env::var("RUSTDOK_TEST_DATABASE_URL").expect("RUSTDOK_TEST_DATABASE_URL must be set");
+    MySqlUserStore::connect(&url).await.unwrap().migrate().await.unwrap();
+    let store = MySqlAuditStore::new(connect_pool(&url).await.unwrap());
+    let now = Utc::now();
+
+    let actor = format!("user-{}", Uuid::new_v4().simple());
+    let record = |key: &str, outcome: AuditOutcome| NewAuditRecord {
+        occurred_at: now,
+        request_id: Some("req-1".to_string()),
+        actor: Some(actor.clone()),
+        ip_address: Some("192.0.2.1".to_string()),
+        action: "object.delete".to_string(),
+        method: "DELETE".to_string(),
+        bucket: Some("docs".to_string()),
+        key: Some(key.to_string()),
+        target: None,
+        outcome,
+        status: outcome_status(outcome),
+        bytes: Some(42),
+    };
+    let first = store.add_record(record("reports/100%_done.pdf", AuditOutcome::Success)).await.unwrap();
+    let second = store.add_record(record("reports/1000_done.pdf", AuditOutcome::Denied)).await.unwrap();
+    assert!(second > first);
+
+    let by_actor = AuditFilter { actor: Some(actor.clone()), ..Default::default() };
+    let records = store.list_records(&by_actor, 10).await.unwrap();
+    assert_eq!(records.iter().map(|r| r.id).collect::<Vec<_>>(), vec![second, first]);
+    assert_eq!(records[1].key.as_deref(), Some("reports/100%_done.pdf"));
+    assert_eq!(records[1].bytes, Some(42));
+    assert_eq!(records[0].outcome, AuditOutcome::Denied);
+
+    // Wildcards in prefixes match literally
+    let prefix = AuditFilter { prefix: Some("reports/100%_".to_string()), ..by_actor.clone() };
+    assert_eq!(store.list_records(&prefix, 10).await.unwrap().len(), 1);
+
+    let denied = AuditFilter { outcome: Some(AuditOutcome::Denied), ..by_actor.clone() };
+    assert_eq!(store.list_records(&denied, 10).await.unwrap()[0].id, second);
+    let older = AuditFilter { before: Some(second), ..by_actor.clone() };
+    assert_eq!(store.list_records(&older, 10).await.unwrap()[0].id, first);
+    let later = AuditFilter { since: Some(now + Duration::minutes(1)), ..by_actor };
+    assert!(store.list_records(&later, 10).await.unwrap().is_empty());
+}
+
+fn outcome_status(outcome: AuditOutcome) -> u16 {
+    match outcome {
+        AuditOutcome::Success => 204,
+        AuditOutcome::Denied => 403,
+        AuditOutcome::Failure => 500,
+    }
+}
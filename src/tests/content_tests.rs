@@ -0,0 +1,88 @@
+#![cfg(test)]
+// Tests for content sniffing of viewed objects
+
+use crate::rdlib::content::{decide, is_active_content, sniff_content_type, types_match};
+
+const PNG_HEADER: &[u8] = &[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, 0, 0, 0, 0x0D, b'I', b'H', b'D', b'R'];
+const EXE_HEADER: &[u8] = &[b'M', b'Z', 0x90, 0, 3, 0, 0, 0, 4, 0, 0, 0, 0xFF, 0xFF, 0, 0];
+
+#[test]
+fn test_sniff_content_type() {
+    assert_eq!(sniff_content_type(PNG_HEADER).as_deref(), Some("image/png"));
+    assert_eq!(sniff_content_type(b"%PDF-1.7\n").as_deref(), Some("application/pdf"));
+    assert_eq!(sniff_content_type(b"<svg xmlns=\"http://www.w3.org/2000/svg\"></svg>").as_deref(), Some("image/svg+xml"));
+    assert_eq!(
+        sniff_content_type(b"<?xml version=\"1.0\"?>\n<!-- logo -->\n<svg></svg>").as_deref(),
+        Some("image/svg+xml"),
+        "An XML prolog and comments before the root element should be skipped"
+    );
+    assert_eq!(sniff_content_type(b"just some text"), None);
+}
+
+#[test]
+fn test_types_match() {
+    assert!(types_match("image/png", "image/png"));
+    assert!(types_match("audio/wav", "audio/x-wav"), "x- prefixed subtypes should match their plain form");
+    assert!(types_match("application/xml", "text/xml"));
+    assert!(types_match("application/vnd.openxmlformats-officedocument.wordprocessingml.document", "application/zip"));
+    assert!(!types_match("image/png", "image/jpeg"));
+    assert!(!types_match("image/svg+xml", "text/xml"));
+    assert!(!types_match("application/zip", "application/vnd.microsoft.portable-executable"));
+}
+
+#[test]
+fn test_is_active_content() {
+    assert!(is_active_content("text/html; charset=utf-8"));
+    assert!(is_active_content("image/svg+xml"));
+    assert!(is_active_content("application/xhtml+xml"));
+    assert!(!is_active_content("text/plain"));
+    assert!(!is_active_content("image/png"));
+}
+
+#[test]
+fn test_decide_matching_content_is_inline() {
+    let decision = decide("photo.png", PNG_HEADER, false);
+    assert_eq!(decision.content_type, "image/png");
+    assert!(decision.inline);
+    assert!(!decision.mismatch);
+
+    let decision = decide("notes.txt", b"hello world", false);
+    assert_eq!(decision.content_type, "text/plain");
+    assert!(decision.inline);
+}
+
+#[test]
+fn test_decide_mismatch_is_attachment() {
+    let decision = decide("cat.png", EXE_HEADER, false);
+    assert!(decision.mismatch, "An executable renamed to .png should be detected");
+    assert!(!decision.inline);
+    assert_ne!(decision.content_type, "image/png");
+
+    let decision = decide("readme.txt", b"text\0with\0nul bytes", false);
+    assert!(decision.mismatch, "Binary data declared as text should be detected");
+    assert_eq!(decision.content_type, "application/octet-stream");
+}
+
+#[test]
+fn test_decide_without_extension_uses_sniffed_type() {
+    let decision = decide("scan", b"%PDF-1.7\n", false);
+    assert_eq!(decision.content_type, "application/pdf");
+    assert!(decision.inline);
+    assert!(!decision.mismatch);
+}
+
+#[test]
+fn test_decide_active_content() {
+    let svg = b"<svg xmlns=\"http://www.w3.org/2000/svg\"><script>alert(1)</script></svg>";
+
+    let decision = decide("logo.svg", svg, false);
+    assert_eq!(decision.content_type, "image/svg+xml");
+    assert!(!decision.inline, "SVG should be an attachment unless active content is allowed");
+    assert!(!decision.mismatch);
+
+    let decision = decide("logo.svg", svg, true);
+    assert!(decision.inline);
+
+    let decision = decide("page.html", b"<!DOCTYPE html><html></html>", false);
+    assert!(!decision.inline);
+}
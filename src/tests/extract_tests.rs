@@ -0,0 +1,200 @@
+#![cfg(test)]
+// Tests for archive extraction
+
+use std::io::{Cursor, Write};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use zip::write::SimpleFileOptions;
+use crate::rdlib::archive::extract::{
+    detect_format, extract, sanitize_entry_path, ArchiveFormat, ExtractedItem, ExtractionError, ExtractionLimits,
+};
+
+fn build_zip(build: impl FnOnce(&mut zip::ZipWriter<Cursor<Vec<u8>>>)) -> Vec<u8> {
+    let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    build(&mut writer);
+    writer.finish().unwrap().into_inner()
+}
+
+fn build_tar(build: impl FnOnce(&mut tar::Builder<Vec<u8>>)) -> Vec<u8> {
+    let mut builder = tar::Builder::new(Vec::new());
+    build(&mut builder);
+    builder.into_inner().unwrap()
+}
+
+fn append_tar_file(builder: &mut tar::Builder<Vec<u8>>, path: &str, data: &[u8]) {
+    let mut header = tar::Header::new_ustar();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_entry_type(tar::EntryType::Regular);
+    builder.append_data(&mut header, path, data).unwrap();
+}
+
+fn collect(data: &[u8], format: ArchiveFormat, limits: &ExtractionLimits) -> (Vec<ExtractedItem>, Result<(), ExtractionError>) {
+    let mut items = Vec::new();
+    let result = extract(data, format, limits, |item| {
+        items.push(item);
+        true
+    });
+    (items, result)
+}
+
+#[test]
+fn test_sanitize_entry_path() {
+    assert_eq!(sanitize_entry_path("docs/readme.txt"), Ok("docs/readme.txt".to_string()));
+    assert_eq!(sanitize_entry_path("./docs//readme.txt"), Ok("docs/readme.txt".to_string()));
+    assert_eq!(sanitize_entry_path("docs\\readme.txt"), Ok("docs/readme.txt".to_string()));
+    assert_eq!(sanitize_entry_path("docs/"), Ok("docs/".to_string()));
+    assert_eq!(sanitize_entry_path("rapport été.pdf"), Ok("rapport été.pdf".to_string()));
+}
+
+#[test]
+fn test_sanitize_entry_path_rejects_traversal() {
+    assert!(sanitize_entry_path("../etc/passwd").is_err());
+    assert!(sanitize_entry_path("docs/../../secret").is_err());
+    assert!(sanitize_entry_path("..\\..\\windows\\system.ini").is_err());
+    assert!(sanitize_entry_path("/etc/passwd").is_err());
+    assert!(sanitize_entry_path("\\server\\share").is_err());
+    assert!(sanitize_entry_path("C:/Windows/win.ini").is_err());
+    assert!(sanitize_entry_path("docs/line\nbreak").is_err());
+    assert!(sanitize_entry_path("./").is_err());
+    assert!(sanitize_entry_path("").is_err());
+}
+
+#[test]
+fn test_detect_format() {
+    let zip = build_zip(|w| {
+        w.start_file("a.txt", SimpleFileOptions::default()).unwrap();
+    });
+    let tar = build_tar(|b| append_tar_file(b, "a.txt", b"a"));
+    let mut gz = GzEncoder::new(Vec::new(), Compression::default());
+    gz.write_all(&tar).unwrap();
+    let tar_gz = gz.finish().unwrap();
+
+    assert_eq!(detect_format(&zip), Some(ArchiveFormat::Zip));
+    assert_eq!(detect_format(&tar), Some(ArchiveFormat::Tar));
+    assert_eq!(detect_format(&tar_gz), Some(ArchiveFormat::TarGz));
+    assert_eq!(detect_format(b"just some text"), None);
+    assert_eq!(detect_format(&[]), None);
+}
+
+#[test]
+fn test_extract_zip() {
+    let archive = build_zip(|w| {
+        w.add_directory("docs/", SimpleFileOptions::default()).unwrap();
+        w.start_file("docs/readme.txt", SimpleFileOptions::default()).unwrap();
+        w.write_all(b"hello world").unwrap();
+        w.start_file("../escape.txt", SimpleFileOptions::default()).unwrap();
+        w.write_all(b"evil").unwrap();
+        w.add_symlink("docs/link", "/etc/passwd", SimpleFileOptions::default()).unwrap();
+    });
+
+    let (items, result) = collect(&archive, ArchiveFormat::Zip, &ExtractionLimits::default());
+    assert_eq!(result, Ok(()));
+    assert_eq!(items.len(), 4);
+    assert_eq!(items[0], ExtractedItem::Directory { path: "docs/".to_string() });
+    assert_eq!(items[1], ExtractedItem::File { path: "docs/readme.txt".to_string(), data: b"hello world".to_vec() });
+    assert!(matches!(&items[2], ExtractedItem::Skipped { path, .. } if path == "../escape.txt"));
+    assert!(matches!(&items[3], ExtractedItem::Skipped { path, reason } if path == "docs/link" && reason.contains("links")));
+}
+
+#[test]
+fn test_extract_tar_and_tar_gz() {
+    let archive = build_tar(|b| {
+        let mut header = tar::Header::new_ustar();
+        header.set_entry_type(tar::EntryType::Directory);
+        header.set_mode(0o755);
+        header.set_size(0);
+        b.append_data(&mut header, "photos/", std::io::empty()).unwrap();
+
+        append_tar_file(b, "photos/cat.jpg", &[0xFF, 0xD8, 0xFF]);
+
+        let mut header = tar::Header::new_ustar();
+        header.set_entry_type(tar::EntryType::Symlink);
+        header.set_size(0);
+        b.append_link(&mut header, "photos/link", "/etc/shadow").unwrap();
+    });
+    let mut gz = GzEncoder::new(Vec::new(), Compression::default());
+    gz.write_all(&archive).unwrap();
+    let compressed = gz.finish().unwrap();
+
+    for (data, format) in [(archive, ArchiveFormat::Tar), (compressed, ArchiveFormat::TarGz)] {
+        let (items, result) = collect(&data, format, &ExtractionLimits::default());
+        assert_eq!(result, Ok(()));
+        assert_eq!(items.len(), 3);
+        assert_eq!(items[0], ExtractedItem::Directory { path: "photos/".to_string() });
+        assert_eq!(items[1], ExtractedItem::File { path: "photos/cat.jpg".to_string(), data: vec![0xFF, 0xD8, 0xFF] });
+        assert!(matches!(&items[2], ExtractedItem::Skipped { path, .. } if path == "photos/link"));
+    }
+}
+
+#[test]
+fn test_extract_stops_when_callback_returns_false() {
+    let archive = build_tar(|b| {
+        append_tar_file(b, "a.txt", b"a");
+        append_tar_file(b, "b.txt", b"b");
+    });
+
+    let mut items = Vec::new();
+    let result = extract(&archive, ArchiveFormat::Tar, &ExtractionLimits::default(), |item| {
+        items.push(item);
+        false
+    });
+    assert_eq!(result, Ok(()));
+    assert_eq!(items.len(), 1);
+}
+
+#[test]
+fn test_extract_enforces_entry_limit() {
+    let archive = build_zip(|w| {
+        for i in 0..5 {
+            w.start_file(format!("file{}.txt", i), SimpleFileOptions::default()).unwrap();
+        }
+    });
+    let limits = ExtractionLimits { max_entries: 3, ..Default::default() };
+
+    let (items, result) = collect(&archive, ArchiveFormat::Zip, &limits);
+    assert!(matches!(result, Err(ExtractionError::LimitExceeded(_))));
+    assert_eq!(items.len(), 3);
+}
+
+#[test]
+fn test_extract_rejects_compression_bombs() {
+    let archive = build_zip(|w| {
+        w.start_file("zeros.bin", SimpleFileOptions::default()).unwrap();
+        w.write_all(&vec![0u8; 1024 * 1024]).unwrap();
+    });
+    assert!(archive.len() < 10 * 1024);
+
+    let (items, result) = collect(&archive, ArchiveFormat::Zip, &ExtractionLimits::default());
+    assert!(matches!(result, Err(ExtractionError::LimitExceeded(_))));
+    assert!(items.is_empty());
+
+    let limits = ExtractionLimits { max_total_size: 1000, max_ratio: u64::MAX, ..Default::default() };
+    let (_, result) = collect(&archive, ArchiveFormat::Zip, &limits);
+    assert!(matches!(result, Err(ExtractionError::LimitExceeded(_))));
+}
+
+#[test]
+fn test_extract_skips_entries_over_size_limit() {
+    let archive = build_tar(|b| {
+        append_tar_file(b, "small.txt", b"small");
+        append_tar_file(b, "large.txt", &[b'x'; 100]);
+    });
+    let limits = ExtractionLimits { max_entry_size: Some(10), ..Default::default() };
+
+    let (items, result) = collect(&archive, ArchiveFormat::Tar, &limits);
+    assert_eq!(result, Ok(()));
+    assert_eq!(items[0], ExtractedItem::File { path: "small.txt".to_string(), data: b"small".to_vec() });
+    assert!(matches!(&items[1], ExtractedItem::Skipped { path, .. } if path == "large.txt"));
+}
+
+#[test]
+fn test_extract_rejects_invalid_and_oversized_archives() {
+    let (_, result) = collect(b"PK\x03\x04 not really a zip", ArchiveFormat::Zip, &ExtractionLimits::default());
+    assert!(matches!(result, Err(ExtractionError::Invalid(_))));
+
+    let archive = build_tar(|b| append_tar_file(b, "a.txt", b"a"));
+    let limits = ExtractionLimits { max_archive_size: 100, ..Default::default() };
+    let (_, result) = collect(&archive, ArchiveFormat::Tar, &limits);
+    assert!(matches!(result, Err(ExtractionError::LimitExceeded(_))));
+}
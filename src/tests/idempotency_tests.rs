@@ -0,0 +1,87 @@
+#![cfg(test)]
+// Tests for the store behind idempotency keys
+
+use chrono::{Duration, Utc};
+use crate::rdlib::idempotency::{IdempotencyStore, Lookup, MAX_KEY_LENGTH};
+
+#[test]
+fn test_replays_completed_requests() {
+    let store = IdempotencyStore::new(Duration::hours(1), 100);
+    let now = Utc::now();
+
+    assert_eq!(store.begin("key-1", "POST /buckets", now), Lookup::New);
+    assert_eq!(store.begin("key-1", "POST /buckets", now), Lookup::InProgress);
+
+    store.complete("key-1", "created".to_string());
+    assert_eq!(store.begin("key-1", "POST /buckets", now), Lookup::Replay("created".to_string()));
+    assert_eq!(store.begin("key-1", "POST /bucket/a/folders", now), Lookup::Mismatch);
+    assert_eq!(store.begin("key-2", "POST /buckets", now), Lookup::New, "Keys are independent");
+}
+
+#[test]
+fn test_abandoned_requests_can_be_retried() {
+    let store = IdempotencyStore::<String>::new(Duration::hours(1), 100);
+    let now = Utc::now();
+
+    assert_eq!(store.begin("key-1", "POST /buckets", now), Lookup::New);
+    store.abandon("key-1");
+    assert_eq!(store.begin("key-1", "POST /buckets", now), Lookup::New);
+
+    // Abandoning never removes a kept response
+    store.complete("key-1", "created".to_string());
+    store.abandon("key-1");
+    assert_eq!(store.begin("key-1", "POST /buckets", now), Lookup::Replay("created".to_string()));
+}
+
+#[test]
+fn test_responses_expire() {
+    let store = IdempotencyStore::new(Duration::minutes(10), 100);
+    let now = Utc::now();
+
+    assert_eq!(store.begin("key-1", "POST /buckets", now), Lookup::New);
+    store.complete("key-1", "created".to_string());
+
+    let later = now + Duration::minutes(11);
+    assert_eq!(store.begin("key-1", "POST /buckets", later), Lookup::New);
+}
+
+#[test]
+fn test_oldest_keys_make_room() {
+    let store = IdempotencyStore::new(Duration::hours(1), 2);
+    let now = Utc::now();
+
+    for (i, key) in ["key-1", "key-2", "key-3"].into_iter().enumerate() {
+        let at = now + Duration::seconds(i as i64);
+        assert_eq!(store.begin(key, "POST /buckets", at), Lookup::New);
+        store.complete(key, key.to_string());
+    }
+
+    let later = now + Duration::seconds(3);
+    assert_eq!(store.begin("key-3", "POST /buckets", later), Lookup::Replay("key-3".to_string()));
+    assert_eq!(store.begin("key-1", "POST /buckets", later), Lookup::New, "The oldest key should have been removed");
+}
+
+#[test]
+fn test_abandoned_keys_do_not_grow_the_store() {
+    let store = IdempotencyStore::<String>::new(Duration::hours(1), 2);
+    let now = Utc::now();
+
+    for i in 0..100 {
+        assert_eq!(store.begin(&format!("key-{}", i), "POST /buckets", now), Lookup::New);
+        store.abandon(&format!("key-{}", i));
+    }
+    assert_eq!(store.begin("kept", "POST /buckets", now), Lookup::New);
+    store.complete("kept", "created".to_string());
+    assert_eq!(store.begin("kept", "POST /buckets", now), Lookup::Replay("created".to_string()));
+}
+
+#[test]
+fn test_key_validation() {
+    assert!(IdempotencyStore::<String>::is_valid_key("8e03978e-40d5-43e8-bc93-6894a57f9324"));
+    assert!(IdempotencyStore::<String>::is_valid_key(&"k".repeat(MAX_KEY_LENGTH)));
+
+    assert!(!IdempotencyStore::<String>::is_valid_key(""));
+    assert!(!IdempotencyStore::<String>::is_valid_key("with space"));
+    assert!(!IdempotencyStore::<String>::is_valid_key("schlüssel"));
+    assert!(!IdempotencyStore::<String>::is_valid_key(&"k".repeat(MAX_KEY_LENGTH + 1)));
+}
@@ -0,0 +1,146 @@
+#![cfg(test)]
+// Tests for image decoding, thumbnails and encoding
+
+use std::io::Cursor;
+use image::{DynamicImage, ImageFormat, RgbImage, RgbaImage};
+use crate::rdlib::imaging::{decode, thumbnail, ImageTransform, ImagingError, OutputFormat, MAX_OUTPUT_DIMENSION, MAX_SOURCE_SIZE};
+
+fn encode_test_image(img: DynamicImage, format: ImageFormat) -> Vec<u8> {
+    let mut data = Vec::new();
+    img.write_to(&mut Cursor::new(&mut data), format).unwrap();
+    data
+}
+
+#[test]
+fn test_thumbnail_fits_box_and_keeps_aspect_ratio() {
+    let source = encode_test_image(DynamicImage::ImageRgb8(RgbImage::new(800, 400)), ImageFormat::Png);
+
+    let rendition = thumbnail(&source, 200, 200).unwrap();
+    assert_eq!(rendition.content_type, "image/jpeg", "Opaque images should become JPEG thumbnails");
+
+    let img = decode(&rendition.data).unwrap();
+    assert_eq!((img.width(), img.height()), (200, 100));
+}
+
+#[test]
+fn test_thumbnail_does_not_upscale() {
+    let source = encode_test_image(DynamicImage::ImageRgb8(RgbImage::new(64, 48)), ImageFormat::Png);
+
+    let img = decode(&thumbnail(&source, 256, 256).unwrap().data).unwrap();
+    assert_eq!((img.width(), img.height()), (64, 48));
+}
+
+#[test]
+fn test_thumbnail_keeps_transparency() {
+    let source = encode_test_image(DynamicImage::ImageRgba8(RgbaImage::new(300, 300)), ImageFormat::Png);
+
+    let rendition = thumbnail(&source, 100, 100).unwrap();
+    assert_eq!(rendition.content_type, "image/png");
+}
+
+#[test]
+fn test_thumbnail_rejects_invalid_input() {
+    assert!(matches!(thumbnail(b"not an image", 100, 100), Err(ImagingError::Unsupported(_))));
+
+    let source = encode_test_image(DynamicImage::ImageRgb8(RgbImage::new(10, 10)), ImageFormat::Png);
+    assert!(matches!(thumbnail(&source, 0, 100), Err(ImagingError::InvalidRequest(_))));
+
+    let oversized = vec![0u8; MAX_SOURCE_SIZE as usize + 1];
+    assert!(matches!(decode(&oversized), Err(ImagingError::SourceTooLarge(_))));
+}
+
+#[test]
+fn test_decode_rejects_huge_dimensions() {
+    // A PNG header announcing a 100000 x 100000 image, without the pixel data
+    let mut header = vec![0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, 0, 0, 0, 0x0D, b'I', b'H', b'D', b'R'];
+    header.extend_from_slice(&100_000u32.to_be_bytes());
+    header.extend_from_slice(&100_000u32.to_be_bytes());
+    header.extend_from_slice(&[8, 2, 0, 0, 0, 0, 0, 0, 0]);
+
+    assert!(matches!(decode(&header), Err(ImagingError::Unsupported(_))));
+}
+
+#[test]
+fn test_transform_crop_rotate_resize() {
+    let source = encode_test_image(DynamicImage::ImageRgb8(RgbImage::new(400, 300)), ImageFormat::Jpeg);
+
+    let transform = ImageTransform {
+        crop: Some((0, 0, 200, 100)),
+        rotate: 90,
+        width: Some(50),
+        ..Default::default()
+    };
+    let rendition = transform.apply(&source).unwrap();
+    assert_eq!(rendition.content_type, "image/jpeg", "JPEG sources should stay JPEG by default");
+
+    let img = decode(&rendition.data).unwrap();
+    assert_eq!((img.width(), img.height()), (50, 100));
+}
+
+#[test]
+fn test_transform_format_conversion() {
+    let source = encode_test_image(DynamicImage::ImageRgb8(RgbImage::new(40, 30)), ImageFormat::Png);
+
+    for (format, content_type) in [(OutputFormat::Webp, "image/webp"), (OutputFormat::Jpeg, "image/jpeg")] {
+        let quality = Some(60).filter(|_| format == OutputFormat::Jpeg);
+        let transform = ImageTransform { format: Some(format), quality, ..Default::default() };
+        let rendition = transform.apply(&source).unwrap();
+        assert_eq!(rendition.content_type, content_type);
+
+        let img = decode(&rendition.data).unwrap();
+        assert_eq!((img.width(), img.height()), (40, 30));
+    }
+}
+
+#[test]
+fn test_transform_validation() {
+    assert!(ImageTransform::default().is_empty());
+    assert!(ImageTransform { rotate: 45, ..Default::default() }.validate().is_err());
+    assert!(ImageTransform { quality: Some(0), ..Default::default() }.validate().is_err());
+    assert!(ImageTransform { width: Some(MAX_OUTPUT_DIMENSION + 1), ..Default::default() }.validate().is_err());
+
+    assert_eq!(ImageTransform::parse_crop("10, 20,30,40"), Ok((10, 20, 30, 40)));
+    assert!(ImageTransform::parse_crop("10,20,30").is_err());
+    assert!(ImageTransform::parse_crop("a,b,c,d").is_err());
+
+    let source = encode_test_image(DynamicImage::ImageRgb8(RgbImage::new(100, 100)), ImageFormat::Png);
+    let transform = ImageTransform { crop: Some((50, 50, 60, 10)), ..Default::default() };
+    assert!(matches!(transform.apply(&source), Err(ImagingError::InvalidRequest(_))), "Crops outside the image should be rejected");
+
+    let transform = ImageTransform { width: Some(4096), ..Default::default() };
+    let tall = encode_test_image(DynamicImage::ImageRgb8(RgbImage::new(10, 20)), ImageFormat::Png);
+    assert!(matches!(transform.apply(&tall), Err(ImagingError::InvalidRequest(_))), "Outputs beyond the limit should be rejected");
+}
+
+#[test]
+fn test_transform_rejects_quality_for_lossless_output() {
+    let source = encode_test_image(DynamicImage::ImageRgb8(RgbImage::new(40, 30)), ImageFormat::Png);
+
+    for format in [Some(OutputFormat::Webp), Some(OutputFormat::Png), None] {
+        let transform = ImageTransform { format, quality: Some(60), ..Default::default() };
+        assert!(matches!(transform.apply(&source), Err(ImagingError::InvalidRequest(_))), "Quality should be rejected for {:?}", format);
+    }
+}
+
+#[test]
+fn test_transform_without_resize_ignores_output_limit() {
+    let source = encode_test_image(DynamicImage::ImageRgb8(RgbImage::new(MAX_OUTPUT_DIMENSION + 1, 2)), ImageFormat::Png);
+
+    let transform = ImageTransform { rotate: 90, format: Some(OutputFormat::Png), ..Default::default() };
+    let img = decode(&transform.apply(&source).unwrap().data).unwrap();
+    assert_eq!((img.width(), img.height()), (2, MAX_OUTPUT_DIMENSION + 1));
+
+    let transform = ImageTransform { width: Some(MAX_OUTPUT_DIMENSION), ..Default::default() };
+    assert!(transform.apply(&source).is_ok(), "Downscaling a large source should stay allowed");
+}
+
+#[test]
+fn test_transform_cache_variant_is_canonical() {
+    let a = ImageTransform { width: Some(100), format: Some(OutputFormat::Webp), ..Default::default() };
+    let b = ImageTransform { width: Some(100), format: Some(OutputFormat::Webp), ..Default::default() };
+    let c = ImageTransform { width: Some(100), format: Some(OutputFormat::Png), ..Default::default() };
+
+    assert_eq!(a.cache_variant(), b.cache_variant());
+    assert_ne!(a.cache_variant(), c.cache_variant());
+    assert!(!a.cache_variant().contains(','), "Cache variants end up in object keys");
+}
@@ -0,0 +1,196 @@
+#![cfg(test)]
+// Tests for archive inspection
+
+use std::io::{Cursor, Read, Write};
+use bytes::Bytes;
+use chrono::{TimeZone, Utc};
+use zip::write::SimpleFileOptions;
+use crate::rdlib::archive::extract::{ArchiveFormat, ExtractionError};
+use crate::rdlib::archive::inspect::{
+    entry_data_offset, entry_reader, find_central_directory, list_tar, parse_central_directory,
+    parse_zip64_record, read_tar_entry, ChannelReader, DirectoryLocation, ZipEntry, LOCAL_HEADER_LENGTH, MAX_ENTRY_SIZE,
+};
+use crate::rdlib::archive::zip_stream::ZipStreamWriter;
+
+fn sample_zip() -> Vec<u8> {
+    let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    let modified = zip::DateTime::from_date_and_time(2024, 5, 17, 13, 45, 30).unwrap();
+    writer.add_directory("docs/", SimpleFileOptions::default()).unwrap();
+    writer.start_file("docs/readme.txt", SimpleFileOptions::default().last_modified_time(modified)).unwrap();
+    writer.write_all("hello world\n".repeat(100).as_bytes()).unwrap();
+    writer.start_file("photo.jpg", SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored)).unwrap();
+    writer.write_all(&[0xFF, 0xD8, 0xFF, 0xE0]).unwrap();
+    writer.finish().unwrap().into_inner()
+}
+
+/// Reads the central directory like the API does, from a tail of the archive.
+fn read_directory(archive: &[u8], tail_length: usize) -> Vec<ZipEntry> {
+    let tail_offset = archive.len() - tail_length.min(archive.len());
+    let (offset, size) = match find_central_directory(&archive[tail_offset..], tail_offset as u64).unwrap() {
+        DirectoryLocation::Found { offset, size } => (offset, size),
+        DirectoryLocation::Zip64Record(record) => parse_zip64_record(&archive[record as usize..]).unwrap(),
+    };
+    parse_central_directory(&archive[offset as usize..(offset + size) as usize]).unwrap()
+}
+
+/// Reads an entry like the API does, from its local header and compressed data.
+fn read_entry(archive: &[u8], entry: &ZipEntry) -> Result<Vec<u8>, ExtractionError> {
+    let header_end = (entry.header_offset + LOCAL_HEADER_LENGTH) as usize;
+    let start = entry_data_offset(entry, &archive[entry.header_offset as usize..header_end])? as usize;
+    let data = archive[start..start + entry.compressed_size as usize].to_vec();
+
+    let mut output = Vec::new();
+    entry_reader(entry, Cursor::new(data))?
+        .read_to_end(&mut output)
+        .map_err(|e| ExtractionError::Invalid(e.to_string()))?;
+    Ok(output)
+}
+
+/// Reads an entry of a tar archive into memory.
+fn read_tar(reader: ChannelReader, format: ArchiveFormat, name: &str) -> Option<Vec<u8>> {
+    let mut data = Vec::new();
+    let found = read_tar_entry(reader, format, name, |entry| entry.read_to_end(&mut data).map(|_| ())).unwrap();
+    found.then_some(data)
+}
+
+fn channel_reader(data: Vec<u8>, chunk_size: usize) -> ChannelReader {
+    let (tx, rx) = tokio::sync::mpsc::channel(data.len() / chunk_size + 1);
+    for chunk in data.chunks(chunk_size) {
+        tx.try_send(Ok(Bytes::copy_from_slice(chunk))).unwrap();
+    }
+    ChannelReader::new(rx)
+}
+
+#[test]
+fn test_zip_directory_listing() {
+    let archive = sample_zip();
+    let entries = read_directory(&archive, archive.len());
+
+    assert_eq!(entries.len(), 3);
+    assert!(entries[0].is_dir());
+    assert_eq!(entries[1].name, "docs/readme.txt");
+    assert_eq!(entries[1].size, 1200);
+    assert!(entries[1].compressed_size < 1200);
+    assert_eq!(entries[1].modified.as_deref(), Some("2024-05-17T13:45:30+00:00"));
+
+    let listing = entries[2].to_archive_entry();
+    assert_eq!(listing.name, "photo.jpg");
+    assert_eq!(listing.size, 4);
+    assert_eq!(listing.compressed_size, Some(4));
+    assert!(!listing.is_dir);
+}
+
+#[test]
+fn test_zip_entry_can_be_read() {
+    let archive = sample_zip();
+    let entries = read_directory(&archive, archive.len());
+
+    assert_eq!(read_entry(&archive, &entries[1]).unwrap(), "hello world\n".repeat(100).into_bytes());
+    assert_eq!(read_entry(&archive, &entries[2]).unwrap(), vec![0xFF, 0xD8, 0xFF, 0xE0]);
+}
+
+#[test]
+fn test_zip_entry_detects_corruption() {
+    let archive = sample_zip();
+    let mut entry = read_directory(&archive, archive.len()).remove(2);
+    entry.crc32 ^= 1;
+    assert!(matches!(read_entry(&archive, &entry), Err(ExtractionError::Invalid(_))));
+
+    let mut entry = read_directory(&archive, archive.len()).remove(2);
+    entry.encrypted = true;
+    assert!(read_entry(&archive, &entry).is_err());
+
+    let mut entry = read_directory(&archive, archive.len()).remove(1);
+    entry.size = MAX_ENTRY_SIZE;
+    assert!(matches!(read_entry(&archive, &entry), Err(ExtractionError::Invalid(_))), "Entries lying about their size should be rejected");
+
+    let mut entry = read_directory(&archive, archive.len()).remove(1);
+    entry.size = 10;
+    assert!(matches!(read_entry(&archive, &entry), Err(ExtractionError::Invalid(_))), "Entries larger than declared should be rejected");
+}
+
+#[test]
+fn test_zip_entry_offset_cannot_overflow() {
+    let archive = sample_zip();
+    let mut entry = read_directory(&archive, archive.len()).remove(1);
+    let header = archive[entry.header_offset as usize..(entry.header_offset + LOCAL_HEADER_LENGTH) as usize].to_vec();
+
+    entry.header_offset = u64::MAX - 10;
+    assert!(matches!(entry_data_offset(&entry, &header), Err(ExtractionError::Invalid(_))));
+}
+
+#[test]
+fn test_zip64_directory_listing() {
+    let modified = Utc.with_ymd_and_hms(2024, 5, 17, 13, 45, 30).unwrap();
+    let mut zip = ZipStreamWriter::new();
+    let mut archive = Vec::new();
+    archive.extend(zip.start_entry("notes/today.txt", modified));
+    archive.extend(zip.write_data(b"remember the milk"));
+    archive.extend(zip.finish_entry());
+    archive.extend(zip.finish());
+
+    // The streamed archive saturates its sizes and offsets and stores them in ZIP64 fields
+    let entries = read_directory(&archive, archive.len());
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].name, "notes/today.txt");
+    assert_eq!(entries[0].size, 17);
+    assert_eq!(entries[0].header_offset, 0);
+    assert_eq!(read_entry(&archive, &entries[0]).unwrap(), b"remember the milk");
+
+    // A tail that starts after the ZIP64 record points to it instead
+    let location = find_central_directory(&archive[archive.len() - 42..], archive.len() as u64 - 42).unwrap();
+    assert!(matches!(location, DirectoryLocation::Zip64Record(_)));
+    assert_eq!(read_directory(&archive, 42), entries);
+}
+
+#[test]
+fn test_invalid_zip_is_rejected() {
+    assert!(find_central_directory(b"not an archive at all, just text", 0).is_err());
+    assert!(parse_central_directory(b"PK\x01\x02 truncated").is_err());
+    assert!(parse_zip64_record(&[0; 56]).is_err());
+}
+
+#[test]
+fn test_tar_listing_and_entry() {
+    let mut builder = tar::Builder::new(Vec::new());
+    let mut header = tar::Header::new_ustar();
+    header.set_entry_type(tar::EntryType::Directory);
+    header.set_size(0);
+    header.set_mtime(1_715_953_530);
+    builder.append_data(&mut header, "logs/", std::io::empty()).unwrap();
+    let mut header = tar::Header::new_ustar();
+    header.set_size(5);
+    header.set_mtime(1_715_953_530);
+    builder.append_data(&mut header, "logs/app.log", &b"hello"[..]).unwrap();
+    let archive = builder.into_inner().unwrap();
+
+    let entries = list_tar(channel_reader(archive.clone(), 100), ArchiveFormat::Tar).unwrap();
+    assert_eq!(entries.len(), 2);
+    assert!(entries[0].is_dir);
+    assert_eq!(entries[1].name, "logs/app.log");
+    assert_eq!(entries[1].size, 5);
+    assert_eq!(entries[1].compressed_size, None);
+    assert_eq!(entries[1].modified.as_deref(), Some("2024-05-17T13:45:30+00:00"));
+
+    let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    gz.write_all(&archive).unwrap();
+    let compressed = gz.finish().unwrap();
+
+    let data = read_tar(channel_reader(compressed.clone(), 7), ArchiveFormat::TarGz, "logs/app.log");
+    assert_eq!(data, Some(b"hello".to_vec()));
+    assert_eq!(read_tar(channel_reader(compressed, 7), ArchiveFormat::TarGz, "logs/"), None);
+    assert_eq!(read_tar(channel_reader(archive, 512), ArchiveFormat::Tar, "missing.txt"), None);
+}
+
+#[test]
+fn test_channel_reader_reports_errors() {
+    let (tx, rx) = tokio::sync::mpsc::channel(2);
+    tx.try_send(Ok(Bytes::from_static(b"abc"))).unwrap();
+    tx.try_send(Err(std::io::Error::other("connection reset"))).unwrap();
+    drop(tx);
+
+    let mut data = Vec::new();
+    let result = std::io::Read::read_to_end(&mut ChannelReader::new(rx), &mut data);
+    assert!(result.is_err());
+    assert_eq!(data, b"abc");
+}
@@ -0,0 +1,173 @@
+#![cfg(test)]
+// A local OpenID Connect identity provider for the login tests
+// It serves discovery, authorization, token and key endpoints on a random
+// port, checks PKCE like a real provider and signs ID tokens with the
+// ES256 test key
+
+use std::collections::HashMap;
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+
+use actix_web::{get, post, web, App, HttpResponse, HttpServer};
+use chrono::Utc;
+use hyper::header::LOCATION;
+use hyper::Client;
+use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+use serde_json::{json, Value};
+
+use crate::rdlib::oidc::config::OidcConfig;
+use crate::rdlib::oidc::pkce::code_challenge;
+use crate::rdlib::oidc::service::OidcService;
+use crate::tests::jwt_tests::{EC_JWKS, EC_PRIVATE_KEY};
+
+/// The client ID RustDok is registered with at the mock provider
+pub const TEST_CLIENT_ID: &str = "rustdok";
+
+/// Where the mock provider sends the browser back to
+pub const TEST_REDIRECT_URI: &str = "http://rustdok.test/api/v1/auth/oidc/callback";
+
+/// An authorization code that has not been exchanged yet.
+struct IssuedCode {
+    nonce: Option<String>,
+    code_challenge: String,
+    redirect_uri: String,
+}
+
+/// A running mock identity provider.
+pub struct MockIdp {
+    /// The issuer, which is also the base URL of the provider
+    pub issuer: String,
+    claims: Mutex<Value>,
+    codes: Mutex<HashMap<String, IssuedCode>>,
+}
+
+impl MockIdp {
+    /// Sets the claims of the ID tokens issued from now on. They are added
+    /// to `iss`, `aud`, `exp` and `nonce`, which they may override.
+    pub fn set_claims(&self, claims: Value) {
+        *self.claims.lock().unwrap() = claims;
+    }
+
+    /// Follows an authorization URL as a browser would, logging in at once.
+    ///
+    /// # Returns
+    ///
+    /// The query string the provider sends the browser back to RustDok with
+    pub async fn authorize(&self, authorization_url: &str) -> String {
+        let response = Client::new().get(authorization_url.parse().unwrap()).await.unwrap();
+        assert_eq!(response.status(), 302, "The provider must redirect back to RustDok");
+        let location = response.headers()[LOCATION].to_str().unwrap().to_string();
+        let (redirect_uri, query) = location.split_once('?').unwrap();
+        assert_eq!(redirect_uri, TEST_REDIRECT_URI);
+        query.to_string()
+    }
+}
+
+/// Starts a mock identity provider issuing ID tokens with these claims.
+pub async fn start_mock_idp(claims: Value) -> Arc<MockIdp> {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let idp = Arc::new(MockIdp {
+        issuer: format!("http://{}", listener.local_addr().unwrap()),
+        claims: Mutex::new(claims),
+        codes: Mutex::new(HashMap::new()),
+    });
+
+    let data = web::Data::new(idp.clone());
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(data.clone())
+            .service(discovery)
+            .service(authorize)
+            .service(token)
+            .service(jwks)
+    })
+    .workers(1)
+    .disable_signals()
+    .listen(listener)
+    .unwrap()
+    .run();
+    actix_web::rt::spawn(server);
+    idp
+}
+
+/// Creates a service logging in through a mock identity provider.
+pub async fn create_test_oidc_service(idp: &MockIdp, admin_groups: &[&str]) -> Arc<OidcService> {
+    let mut config = OidcConfig::new(&idp.issuer, TEST_CLIENT_ID, TEST_REDIRECT_URI);
+    config.admin_groups = admin_groups.iter().map(|group| group.to_string()).collect();
+    Arc::new(OidcService::discover(config).await.unwrap())
+}
+
+#[get("/.well-known/openid-configuration")]
+async fn discovery(idp: web::Data<Arc<MockIdp>>) -> HttpResponse {
+    HttpResponse::Ok().json(json!({
+        "issuer": idp.issuer,
+        "authorization_endpoint": format!("{}/authorize", idp.issuer),
+        "token_endpoint": format!("{}/token", idp.issuer),
+        "jwks_uri": format!("{}/jwks", idp.issuer),
+        "response_types_supported": ["code"],
+        "subject_types_supported": ["public"],
+        "id_token_signing_alg_values_supported": ["ES256"],
+        "code_challenge_methods_supported": ["S256"],
+    }))
+}
+
+#[get("/authorize")]
+async fn authorize(idp: web::Data<Arc<MockIdp>>, query: web::Query<HashMap<String, String>>) -> HttpResponse {
+    let param = |name: &str| query.get(name).cloned().unwrap_or_default();
+    if param("response_type") != "code"
+        || param("client_id") != TEST_CLIENT_ID
+        || !param("scope").split(' ').any(|scope| scope == "openid")
+        || param("code_challenge_method") != "S256"
+        || param("code_challenge").is_empty()
+    {
+        return HttpResponse::BadRequest().body("invalid authorization request");
+    }
+
+    let code = uuid::Uuid::new_v4().to_string();
+    idp.codes.lock().unwrap().insert(code.clone(), IssuedCode {
+        nonce: query.get("nonce").cloned(),
+        code_challenge: param("code_challenge"),
+        redirect_uri: param("redirect_uri"),
+    });
+    let callback = serde_urlencoded::to_string([("code", code), ("state", param("state"))]).unwrap();
+    HttpResponse::Found().insert_header(("Location", format!("{}?{}", param("redirect_uri"), callback))).finish()
+}
+
+#[post("/token")]
+async fn token(idp: web::Data<Arc<MockIdp>>, form: web::Form<HashMap<String, String>>) -> HttpResponse {
+    let param = |name: &str| form.get(name).cloned().unwrap_or_default();
+    let issued = idp.codes.lock().unwrap().remove(&param("code"));
+    let valid = issued.as_ref().is_some_and(|issued| {
+        param("grant_type") == "authorization_code"
+            && param("client_id") == TEST_CLIENT_ID
+            && param("redirect_uri") == issued.redirect_uri
+            && code_challenge(&param("code_verifier")) == issued.code_challenge
+    });
+    let Some(issued) = issued.filter(|_| valid) else {
+        return HttpResponse::BadRequest().json(json!({ "error": "invalid_grant", "error_description": "Invalid code or verifier" }));
+    };
+
+    let mut claims = json!({
+        "iss": idp.issuer,
+        "aud": TEST_CLIENT_ID,
+        "iat": Utc::now().timestamp(),
+        "exp": Utc::now().timestamp() + 300,
+        "nonce": issued.nonce,
+    });
+    claims.as_object_mut().unwrap().extend(idp.claims.lock().unwrap().as_object().unwrap().clone());
+    let mut header = Header::new(Algorithm::ES256);
+    header.kid = Some("ec-1".to_string());
+    let id_token = encode(&header, &claims, &EncodingKey::from_ec_pem(EC_PRIVATE_KEY.as_bytes()).unwrap()).unwrap();
+
+    HttpResponse::Ok().json(json!({
+        "access_token": "mock-access-token",
+        "token_type": "Bearer",
+        "expires_in": 300,
+        "id_token": id_token,
+    }))
+}
+
+#[get("/jwks")]
+async fn jwks() -> HttpResponse {
+    HttpResponse::Ok().content_type("application/json").body(EC_JWKS)
+}
@@ -0,0 +1,137 @@
+#![cfg(test)]
+// Tests for the OpenID Connect login
+// These tests log in through a mock identity provider on a random local port
+
+use serde_json::json;
+
+use crate::rdlib::oidc::config::OidcConfig;
+use crate::rdlib::oidc::error::OidcError;
+use crate::rdlib::oidc::pkce::{code_challenge, random_token};
+use crate::rdlib::oidc::service::{OidcService, PendingLogin};
+use crate::rdlib::users::user::Role;
+use crate::tests::oidc::mock_idp::{create_test_oidc_service, start_mock_idp, TEST_CLIENT_ID, TEST_REDIRECT_URI};
+
+/// Splits the `code` and `state` from the query string of a callback.
+fn code_and_state(query: &str) -> (String, String) {
+    let params: Vec<(String, String)> = serde_urlencoded::from_str(query).unwrap();
+    let param = |name: &str| params.iter().find(|(key, _)| key == name).map(|(_, value)| value.clone()).unwrap();
+    (param("code"), param("state"))
+}
+
+#[test]
+fn test_code_challenge() {
+    // The example of RFC 7636, appendix B
+    assert_eq!(code_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"), "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM");
+
+    let token = random_token();
+    assert_eq!(token.len(), 43, "Code verifiers must be 43 to 128 characters long");
+    assert!(token.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'));
+    assert_ne!(token, random_token());
+}
+
+#[test]
+fn test_role_for_groups() {
+    let mut config = OidcConfig::new("https://idp.example.com", "rustdok", TEST_REDIRECT_URI);
+    assert_eq!(config.role_for(&["admins".to_string()]), None, "Roles are left to administrators by default");
+
+    config.admin_groups = vec!["admins".to_string()];
+    assert_eq!(config.role_for(&["staff".to_string(), "admins".to_string()]), Some(Role::Admin));
+    assert_eq!(config.role_for(&["staff".to_string()]), Some(Role::User));
+    assert_eq!(config.discovery_url(), "https://idp.example.com/.well-known/openid-configuration");
+}
+
+#[actix_web::test]
+async fn test_login_through_provider() {
+    let idp = start_mock_idp(json!({
+        "sub": "u-1001",
+        "preferred_username": "alice",
+        "email": "alice@example.com",
+        "groups": ["engineering", "admins"],
+    })).await;
+    let oidc = create_test_oidc_service(&idp, &["admins"]).await;
+
+    let (url, pending) = oidc.begin_login();
+    assert!(url.starts_with(&format!("{}/authorize?", idp.issuer)));
+    assert!(url.contains("code_challenge_method=S256"));
+    assert!(url.contains(&format!("client_id={}", TEST_CLIENT_ID)));
+
+    let (code, state) = code_and_state(&idp.authorize(&url).await);
+    assert_eq!(state, pending.state);
+    assert_eq!(oidc.complete_login(&code, "unknown", &pending).await, Err(OidcError::InvalidState));
+    let login = oidc.complete_login(&code, &state, &pending).await.unwrap();
+    assert_eq!(login.identity.issuer, idp.issuer);
+    assert_eq!(login.identity.subject, "u-1001");
+    assert_eq!(login.identity.username, "alice");
+    assert_eq!(login.identity.email.as_deref(), Some("alice@example.com"));
+    assert_eq!(login.identity.role, Some(Role::Admin));
+    assert_eq!(login.groups, vec!["engineering", "admins"]);
+
+    // The provider exchanges each code once
+    assert!(matches!(oidc.complete_login(&code, &state, &pending).await, Err(OidcError::TokenExchange(reason)) if reason.contains("invalid_grant")));
+}
+
+#[test]
+fn test_pending_login_cookie_value() {
+    let login = PendingLogin::from_cookie_value("state.nonce.verifier.1700000000").unwrap();
+    assert_eq!(login.state, "state");
+    assert_eq!(PendingLogin::from_cookie_value(&login.to_cookie_value()), Some(login.clone()));
+    assert!(login.is_expired(chrono::Utc::now()), "Logins expire after ten minutes");
+
+    for malformed in ["", "state.nonce.verifier", "state.nonce.verifier.soon", "state..verifier.1700000000", "a.b.c.1.e"] {
+        assert_eq!(PendingLogin::from_cookie_value(malformed), None, "{}", malformed);
+    }
+}
+
+#[actix_web::test]
+async fn test_rejects_invalid_id_tokens() {
+    let idp = start_mock_idp(json!({ "sub": "u-1001", "preferred_username": "alice" })).await;
+    let oidc = create_test_oidc_service(&idp, &[]).await;
+
+    for (claims, expected) in [
+        (json!({ "sub": "u-1001", "preferred_username": "alice", "nonce": "replayed" }), "nonce"),
+        (json!({ "sub": "u-1001", "preferred_username": "alice", "aud": "another-client" }), "intended"),
+        (json!({ "sub": "u-1001", "preferred_username": "alice", "iss": "https://evil.example.com" }), "issuer"),
+    ] {
+        idp.set_claims(claims);
+        let (url, pending) = oidc.begin_login();
+        let (code, state) = code_and_state(&idp.authorize(&url).await);
+        match oidc.complete_login(&code, &state, &pending).await {
+            Err(OidcError::InvalidIdToken(reason)) => assert!(reason.contains(expected), "{}", reason),
+            other => panic!("Expected an invalid ID token, got {:?}", other),
+        }
+    }
+
+    idp.set_claims(json!({ "sub": "u-1001" }));
+    let (url, pending) = oidc.begin_login();
+    let (code, state) = code_and_state(&idp.authorize(&url).await);
+    assert_eq!(oidc.complete_login(&code, &state, &pending).await, Err(OidcError::MissingClaim("preferred_username".to_string())));
+
+    // An address the provider has not verified is not taken over
+    idp.set_claims(json!({ "sub": "u-1001", "preferred_username": "alice", "email": "bob@example.com", "email_verified": false }));
+    let (url, pending) = oidc.begin_login();
+    let (code, state) = code_and_state(&idp.authorize(&url).await);
+    assert_eq!(oidc.complete_login(&code, &state, &pending).await.unwrap().identity.email, None);
+}
+
+#[actix_web::test]
+async fn test_rejects_code_without_its_verifier() {
+    let idp = start_mock_idp(json!({ "sub": "u-1001", "preferred_username": "alice" })).await;
+    let oidc = create_test_oidc_service(&idp, &[]).await;
+
+    // The code was issued for another login, whose verifier the provider expects
+    let (code, _) = code_and_state(&idp.authorize(&oidc.begin_login().0).await);
+    let (url, pending) = oidc.begin_login();
+    let (_, state) = code_and_state(&idp.authorize(&url).await);
+    assert!(matches!(oidc.complete_login(&code, &state, &pending).await, Err(OidcError::TokenExchange(reason)) if reason.contains("invalid_grant")));
+}
+
+#[actix_web::test]
+async fn test_discovery_checks_issuer() {
+    let idp = start_mock_idp(json!({})).await;
+
+    let config = OidcConfig::new(&format!("{}/", idp.issuer), TEST_CLIENT_ID, TEST_REDIRECT_URI);
+    assert!(matches!(OidcService::discover(config).await, Err(OidcError::Discovery(_))));
+
+    let config = OidcConfig::new("http://127.0.0.1:1", TEST_CLIENT_ID, TEST_REDIRECT_URI);
+    assert!(matches!(OidcService::discover(config).await, Err(OidcError::Discovery(_))));
+}
@@ -1,5 +1,12 @@
 #[cfg(test)]
 pub mod bucket;
 pub mod object;
+#[allow(clippy::empty_line_after_outer_attr)]
 pub mod service_tests;
-pub mod service_test_helpers; 
\ No newline at end of file
+pub mod service_test_helpers;
+pub mod drop_link_tests;
+pub mod policy_tests;
+pub mod derived_tests;
+pub mod resumable_tests;
+pub mod conditional_tests;
+pub mod error_tests;
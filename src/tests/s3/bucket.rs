@@ -1,4 +1,5 @@
 #[cfg(test)]
 pub mod create_tests;
+#[allow(clippy::useless_vec)]
 pub mod list_tests;
 pub mod delete_tests; 
\ No newline at end of file
@@ -0,0 +1,78 @@
+#![cfg(test)]
+// Tests for the ETag preconditions of writes and deletes
+
+use crate::rdlib::s3::types::Preconditions;
+
+fn etags(values: &[&str]) -> Option<Vec<String>> {
+    Some(values.iter().map(|v| v.to_string()).collect())
+}
+
+#[test]
+fn test_parse_preconditions() {
+    let preconditions = Preconditions::parse(Some("\"abc\", W/\"def\""), Some("*")).unwrap();
+    assert_eq!(preconditions.if_match, etags(&["abc", "W/def"]), "If-Match keeps weak ETags apart");
+    assert_eq!(preconditions.if_none_match, etags(&["*"]));
+    assert!(preconditions.requires_absence());
+
+    assert!(Preconditions::parse(None, None).unwrap().is_empty());
+    assert_eq!(Preconditions::parse(Some("abc"), None).unwrap().if_match, etags(&["abc"]), "Unquoted ETags are accepted");
+
+    assert!(Preconditions::parse(Some(""), None).is_err());
+    assert!(Preconditions::parse(Some("\"\""), None).is_err());
+    assert!(Preconditions::parse(None, Some("*, \"abc\"")).is_err());
+    assert!(Preconditions::parse(Some("\"a\"b\""), None).is_err());
+    assert!(Preconditions::parse(Some("W/"), None).is_err());
+    assert_eq!(Preconditions::parse(None, Some("W/\"abc\"")).unwrap().if_none_match, etags(&["abc"]));
+}
+
+#[test]
+fn test_evaluate_if_match() {
+    let preconditions = Preconditions::parse(Some("\"abc\", \"def\""), None).unwrap();
+    assert!(preconditions.evaluate(Some("\"abc\"")));
+    assert!(preconditions.evaluate(Some("def")));
+    assert!(!preconditions.evaluate(Some("\"xyz\"")));
+    assert!(!preconditions.evaluate(None), "If-Match fails for missing objects");
+
+    let weak = Preconditions::parse(Some("W/\"abc\""), None).unwrap();
+    assert!(!weak.evaluate(Some("\"abc\"")), "If-Match uses strong comparison");
+    assert_eq!(weak.write_headers(), None, "Weak ETags are not sent to S3");
+
+    let any = Preconditions::parse(Some("*"), None).unwrap();
+    assert!(any.evaluate(Some("\"xyz\"")));
+    assert!(!any.evaluate(None));
+}
+
+#[test]
+fn test_evaluate_if_none_match() {
+    let create_only = Preconditions::create_only();
+    assert!(create_only.evaluate(None));
+    assert!(!create_only.evaluate(Some("\"abc\"")));
+
+    let preconditions = Preconditions::parse(None, Some("\"abc\"")).unwrap();
+    assert!(preconditions.evaluate(None));
+    assert!(preconditions.evaluate(Some("\"def\"")));
+    assert!(!preconditions.evaluate(Some("\"abc\"")));
+
+    let weak = Preconditions::parse(None, Some("W/\"abc\"")).unwrap();
+    assert!(!weak.evaluate(Some("\"abc\"")), "If-None-Match uses weak comparison");
+
+    assert!(Preconditions::default().evaluate(None));
+    assert!(Preconditions::default().evaluate(Some("\"abc\"")));
+}
+
+#[test]
+fn test_native_condition_headers() {
+    assert_eq!(Preconditions::default().write_headers(), Some((None, None)));
+    assert_eq!(Preconditions::create_only().write_headers(), Some((None, Some("*".to_string()))));
+    assert_eq!(
+        Preconditions::parse(Some("abc"), None).unwrap().write_headers(),
+        Some((Some("\"abc\"".to_string()), None))
+    );
+    assert_eq!(Preconditions::parse(Some("abc"), None).unwrap().delete_header(), Some(Some("\"abc\"".to_string())));
+
+    // S3 cannot enforce these, so they are checked before the write
+    assert_eq!(Preconditions::parse(Some("\"a\", \"b\""), None).unwrap().write_headers(), None);
+    assert_eq!(Preconditions::parse(Some("*"), None).unwrap().write_headers(), None);
+    assert_eq!(Preconditions::parse(None, Some("\"abc\"")).unwrap().write_headers(), None);
+    assert_eq!(Preconditions::create_only().delete_header(), None);
+}
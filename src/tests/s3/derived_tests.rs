@@ -0,0 +1,13 @@
+#![cfg(test)]
+// Tests for cached renditions derived from objects
+
+use crate::rdlib::s3::service::S3Service;
+
+#[test]
+fn test_derived_key_is_hidden_system_key() {
+    let key = S3Service::derived_key("thumbnails/256x256", "photos/cat.jpg");
+
+    assert_eq!(key, ".rustdok/derived/thumbnails/256x256/photos/cat.jpg");
+    assert!(S3Service::is_system_key(&key));
+    assert_eq!(S3Service::derived_key("thumbnails/64x64", "/cat.jpg"), ".rustdok/derived/thumbnails/64x64/cat.jpg");
+}
@@ -0,0 +1,70 @@
+#![cfg(test)]
+// Tests for drop links and the system prefix they are stored under
+
+use chrono::{Duration, Utc};
+use crate::rdlib::s3::service::S3Service;
+use crate::rdlib::s3::types::DropLink;
+
+#[test]
+fn test_system_key_detection() {
+    assert!(S3Service::is_system_key(".rustdok/drops/abc.json"));
+    assert!(S3Service::is_system_key("/.rustdok/drops/"));
+    assert!(S3Service::is_system_key(".rustdok"));
+    assert!(!S3Service::is_system_key("docs/.rustdok/file.txt"));
+    assert!(!S3Service::is_system_key(".rustdoky"));
+    assert!(!S3Service::is_system_key(""));
+}
+
+#[test]
+fn test_new_drop_link() {
+    let link = DropLink::new("bucket1", "incoming/client-a/");
+
+    assert_eq!(link.bucket, "bucket1");
+    assert_eq!(link.prefix, "incoming/client-a/");
+    assert_eq!(link.token.len(), 32, "Token should be a simple UUID");
+    assert!(link.token.chars().all(|c| c.is_ascii_hexdigit()));
+    assert_ne!(link.token, DropLink::new("bucket1", "incoming").token, "Tokens should be unique");
+    assert_eq!(link.upload_count, 0);
+}
+
+#[test]
+fn test_drop_link_expiry_and_upload_limit() {
+    let now = Utc::now();
+    let mut link = DropLink::new("bucket1", "incoming");
+    assert!(link.validate_active(now).is_ok(), "A link without limits should be active");
+    assert_eq!(link.remaining_uploads(), None);
+
+    link.expires_at = Some(now - Duration::seconds(1));
+    assert!(link.is_expired(now));
+    assert!(link.validate_active(now).unwrap_err().contains("expired"));
+
+    link.expires_at = Some(now + Duration::hours(1));
+    link.max_uploads = Some(2);
+    link.upload_count = 1;
+    assert_eq!(link.remaining_uploads(), Some(1));
+    assert!(link.validate_active(now).is_ok());
+
+    link.upload_count = 2;
+    assert_eq!(link.remaining_uploads(), Some(0));
+    assert!(link.validate_active(now).unwrap_err().contains("upload limit"));
+}
+
+#[test]
+fn test_drop_link_allowed_extensions() {
+    let mut link = DropLink::new("bucket1", "incoming");
+    assert!(link.validate_filename("anything.exe").is_ok(), "No extension list should allow everything");
+
+    link.allowed_extensions = Some(DropLink::normalize_extensions(&[
+        ".PDF".to_string(),
+        "docx".to_string(),
+        " ".to_string(),
+    ]));
+    assert_eq!(link.allowed_extensions, Some(vec!["pdf".to_string(), "docx".to_string()]));
+
+    assert!(link.validate_filename("contract.pdf").is_ok());
+    assert!(link.validate_filename("Contract.PDF").is_ok());
+    assert!(link.validate_filename("letter.docx").is_ok());
+    assert!(link.validate_filename("setup.exe").is_err());
+    assert!(link.validate_filename("pdf").is_err(), "A name without extension should be rejected");
+    assert!(link.validate_filename("invoice.pdf.exe").is_err());
+}
@@ -0,0 +1,66 @@
+#![cfg(test)]
+// Tests for the classification of S3 errors
+
+use aws_sdk_s3::config::http::HttpResponse;
+use aws_sdk_s3::error::SdkError;
+use aws_sdk_s3::operation::get_object::GetObjectError;
+use aws_sdk_s3::operation::head_object::HeadObjectError;
+use aws_sdk_s3::primitives::SdkBody;
+use aws_sdk_s3::types::error::{NoSuchKey, NotFound};
+
+use crate::rdlib::s3::error::S3Error;
+
+fn raw_response(status: u16) -> HttpResponse {
+    HttpResponse::new(status.try_into().unwrap(), SdkBody::empty())
+}
+
+#[test]
+fn test_classify_by_error_code() {
+    let message = || "message".to_string();
+
+    assert_eq!(S3Error::from_metadata(Some("NoSuchBucket"), Some(404), message()), S3Error::BucketNotFound(message()));
+    assert_eq!(S3Error::from_metadata(Some("NoSuchKey"), Some(404), message()), S3Error::NotFound(message()));
+    assert_eq!(S3Error::from_metadata(Some("NoSuchUpload"), Some(404), message()), S3Error::NotFound(message()));
+    assert_eq!(S3Error::from_metadata(Some("BucketAlreadyOwnedByYou"), Some(409), message()), S3Error::BucketAlreadyExists(message()));
+    assert_eq!(S3Error::from_metadata(Some("BucketNotEmpty"), Some(409), message()), S3Error::BucketNotEmpty(message()));
+    assert_eq!(S3Error::from_metadata(Some("AccessDenied"), Some(403), message()), S3Error::AccessDenied(message()));
+    assert_eq!(S3Error::from_metadata(Some("InvalidAccessKeyId"), Some(403), message()), S3Error::AccessDenied(message()));
+    assert_eq!(S3Error::from_metadata(Some("SignatureDoesNotMatch"), Some(403), message()), S3Error::AccessDenied(message()));
+    assert_eq!(S3Error::from_metadata(Some("ConditionalRequestConflict"), Some(409), message()), S3Error::PreconditionFailed(message()));
+    assert_eq!(S3Error::from_metadata(Some("SlowDown"), Some(503), message()), S3Error::SlowDown(message()));
+    assert_eq!(S3Error::from_metadata(Some("RequestTimeout"), Some(400), message()), S3Error::Timeout(message()));
+    assert_eq!(S3Error::from_metadata(Some("InvalidBucketName"), Some(400), message()), S3Error::InvalidArgument(message()));
+    assert_eq!(S3Error::from_metadata(Some("InternalError"), Some(500), message()), S3Error::AwsError(message()));
+}
+
+#[test]
+fn test_classify_by_status_without_code() {
+    let message = || "message".to_string();
+
+    assert_eq!(S3Error::from_metadata(None, Some(404), message()), S3Error::NotFound(message()));
+    assert_eq!(S3Error::from_metadata(None, Some(403), message()), S3Error::AccessDenied(message()));
+    assert_eq!(S3Error::from_metadata(None, Some(401), message()), S3Error::AccessDenied(message()));
+    assert_eq!(S3Error::from_metadata(None, Some(412), message()), S3Error::PreconditionFailed(message()));
+    assert_eq!(S3Error::from_metadata(None, Some(429), message()), S3Error::SlowDown(message()));
+    assert_eq!(S3Error::from_metadata(None, Some(500), message()), S3Error::AwsError(message()));
+    assert_eq!(S3Error::from_metadata(None, None, message()), S3Error::AwsError(message()));
+}
+
+#[test]
+fn test_sdk_service_errors_are_classified() {
+    let missing_key = GetObjectError::NoSuchKey(NoSuchKey::builder().message("The specified key does not exist.").build());
+    let error = S3Error::from(SdkError::service_error(missing_key, raw_response(404)));
+    assert!(matches!(error, S3Error::NotFound(_)));
+    assert!(error.to_string().contains("The specified key does not exist."));
+
+    // HEAD responses have no body, so the status decides
+    let missing_head = HeadObjectError::NotFound(NotFound::builder().build());
+    let error = S3Error::from(SdkError::service_error(missing_head, raw_response(404)));
+    assert!(matches!(error, S3Error::NotFound(_)));
+}
+
+#[test]
+fn test_sdk_timeouts_are_classified() {
+    let error = S3Error::from(SdkError::<GetObjectError, HttpResponse>::timeout_error("operation timed out"));
+    assert!(matches!(error, S3Error::Timeout(_)));
+}
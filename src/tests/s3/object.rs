@@ -1,6 +1,8 @@
 #[cfg(test)]
 pub mod validate_tests;
 pub mod put_tests;
+#[allow(clippy::bool_comparison)]
 pub mod get_tests;
 pub mod list_tests;
+#[allow(clippy::unwrap_or_default, clippy::type_complexity)]
 pub mod delete_tests; 
\ No newline at end of file
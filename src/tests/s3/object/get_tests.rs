@@ -1,5 +1,8 @@
 #[cfg(test)]
 
+use crate::rdlib::s3::error::S3Error;
+use crate::tests::s3::service_test_helpers::create_test_s3_service;
+
 #[test]
 fn test_object_data_processing() {
 
@@ -37,6 +40,14 @@ fn test_object_key_normalization() {
     assert!(!normalized4.starts_with("./"), "Normalized key should not start with ./");
 }
 
+#[tokio::test]
+async fn test_ranges_past_the_largest_offset_are_rejected() {
+    let s3 = create_test_s3_service().await;
+
+    let result = s3.get_object_range("archive.zip", "test-bucket", u64::MAX - 10, 30).await;
+    assert!(matches!(result, Err(S3Error::InvalidArgument(_))), "The range should be rejected before S3 is contacted");
+}
+
 #[cfg(test)]
 fn process_object_data(data: Vec<u8>) -> Vec<u8> {
     data
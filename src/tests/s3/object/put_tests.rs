@@ -1,5 +1,7 @@
 #[cfg(test)]
 
+use crate::rdlib::s3::service::S3Service;
+
 #[test]
 fn test_object_data_preparation() {
 
@@ -31,6 +33,16 @@ fn test_content_type_detection() {
     assert_eq!(detect_content_type("no-extension"), "application/octet-stream");
 }
 
+#[test]
+fn test_numbered_key() {
+    assert_eq!(S3Service::numbered_key("report.pdf", 1), "report (1).pdf");
+    assert_eq!(S3Service::numbered_key("docs/report.pdf", 2), "docs/report (2).pdf");
+    assert_eq!(S3Service::numbered_key("archive.tar.gz", 1), "archive.tar (1).gz");
+    assert_eq!(S3Service::numbered_key("README", 3), "README (3)");
+    assert_eq!(S3Service::numbered_key("docs/.env", 1), "docs/.env (1)");
+    assert_eq!(S3Service::numbered_key("v1.2/notes", 1), "v1.2/notes (1)");
+}
+
 #[cfg(test)]
 fn prepare_object_data(data: Vec<u8>) -> Vec<u8> {
     data
@@ -0,0 +1,122 @@
+#![cfg(test)]
+// Tests for per-bucket upload policies
+
+use crate::rdlib::s3::policy::PolicyViolation;
+use crate::rdlib::s3::types::UploadPolicy;
+
+#[test]
+fn test_empty_policy_allows_everything() {
+    let policy = UploadPolicy::default();
+
+    assert!(policy.validate().is_ok());
+    assert!(policy.check_key("any/key.bin").is_ok());
+    assert!(policy.check_file("setup.exe", Some("application/x-msdownload")).is_ok());
+    assert_eq!(policy.size_limit(None), None);
+    assert_eq!(policy.size_limit(Some(10)), Some(10));
+}
+
+#[test]
+fn test_policy_validation() {
+    let policy = UploadPolicy { max_object_size: Some(0), ..Default::default() };
+    assert!(policy.validate().is_err(), "A zero size limit should be rejected");
+
+    let policy = UploadPolicy { allowed_content_types: Some(vec!["image".to_string()]), ..Default::default() };
+    assert!(policy.validate().unwrap_err().contains("Invalid content type"));
+
+    let policy = UploadPolicy { key_prefix_pattern: Some("uploads/../".to_string()), ..Default::default() };
+    assert!(policy.validate().is_err());
+
+    let policy = UploadPolicy { key_prefix_pattern: Some(".rustdok/".to_string()), ..Default::default() };
+    assert!(policy.validate().is_err());
+}
+
+#[test]
+fn test_policy_content_types() {
+    let policy = UploadPolicy {
+        allowed_content_types: Some(vec!["Image/*".to_string(), "application/pdf".to_string()]),
+        denied_content_types: Some(vec!["image/svg+xml".to_string()]),
+        ..Default::default()
+    }.normalized();
+
+    assert!(policy.check_file("photo.png", Some("image/png")).is_ok());
+    assert!(policy.check_file("doc.pdf", Some("application/pdf; charset=binary")).is_ok());
+    assert!(policy.check_file("photo.jpg", None).is_ok(), "Type should be guessed from the extension");
+    assert!(policy.check_file("photo.jpg", Some("application/octet-stream")).is_ok(), "Generic types should fall back to guessing");
+
+    assert!(matches!(policy.check_file("drawing.svg", Some("image/svg+xml")), Err(PolicyViolation::UnsupportedType(_))));
+    assert!(matches!(policy.check_file("notes.txt", Some("text/plain")), Err(PolicyViolation::UnsupportedType(_))));
+    assert!(matches!(policy.check_file("blob", None), Err(PolicyViolation::UnsupportedType(_))));
+}
+
+#[test]
+fn test_policy_checks_renamed_files() {
+    let policy = UploadPolicy {
+        allowed_content_types: Some(vec!["image/*".to_string(), "application/vnd.openxmlformats-officedocument.wordprocessingml.document".to_string()]),
+        denied_content_types: Some(vec!["image/svg+xml".to_string()]),
+        ..Default::default()
+    }.normalized();
+
+    assert!(policy.check_file("drawing.png", Some("image/svg+xml")).is_err(), "The declared type must not be denied");
+    assert!(policy.check_file("drawing.svg", Some("image/png")).is_err(), "The type of the extension must not be denied");
+    assert!(policy.check_file("setup.exe", Some("image/png")).is_err(), "The type of the extension must be allowed");
+
+    let png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+    assert!(policy.check_content("photo.png", png).is_ok());
+    assert!(policy.check_content("photo.png", b"<?xml version=\"1.0\"?><svg xmlns=\"http://www.w3.org/2000/svg\"/>").is_err());
+    assert!(policy.check_content("photo.png", b"MZ\x90\0\x03\0\0\0\x04\0\0\0\xff\xff").is_err());
+    assert!(policy.check_content("report.docx", b"PK\x03\x04\x14\0\0\0\x08\0").is_ok(), "Office documents are ZIP files");
+    assert!(policy.check_content("photo.png", b"plain text").is_ok(), "Data without a signature is not checked");
+}
+
+#[test]
+fn test_policy_extensions() {
+    let policy = UploadPolicy {
+        denied_extensions: Some(vec![".EXE".to_string(), "bat".to_string()]),
+        ..Default::default()
+    }.normalized();
+
+    assert!(policy.check_file("report.pdf", None).is_ok());
+    assert!(policy.check_file("setup.exe", None).is_err());
+    assert!(policy.check_file("Run.BAT", None).is_err());
+
+    let policy = UploadPolicy {
+        allowed_extensions: Some(vec!["csv".to_string()]),
+        ..Default::default()
+    }.normalized();
+
+    assert!(policy.check_file("data.csv", None).is_ok());
+    assert!(policy.check_file("data.xlsx", None).is_err());
+    assert!(policy.check_file("csv", None).is_err());
+}
+
+#[test]
+fn test_policy_key_prefix_pattern() {
+    let policy = UploadPolicy {
+        key_prefix_pattern: Some("projects/*/".to_string()),
+        ..Default::default()
+    };
+
+    assert!(policy.check_key("projects/apollo/plan.pdf").is_ok());
+    assert!(policy.check_key("projects/apollo/docs/plan.pdf").is_ok());
+    assert!(matches!(policy.check_key("projects/plan.pdf"), Err(PolicyViolation::KeyNotAllowed(_))));
+    assert!(matches!(policy.check_key("other/apollo/plan.pdf"), Err(PolicyViolation::KeyNotAllowed(_))));
+
+    let policy = UploadPolicy {
+        key_prefix_pattern: Some("/inbox-*/".to_string()),
+        ..Default::default()
+    };
+
+    assert!(policy.check_key("inbox-2024/mail.eml").is_ok());
+    assert!(policy.check_key("inbox-/mail.eml").is_ok());
+    assert!(policy.check_key("inbox/mail.eml").is_err());
+}
+
+#[test]
+fn test_policy_size_limit() {
+    let policy = UploadPolicy { max_object_size: Some(100), ..Default::default() };
+
+    assert_eq!(policy.size_limit(None), Some(100));
+    assert_eq!(policy.size_limit(Some(50)), Some(50));
+    assert_eq!(policy.size_limit(Some(500)), Some(100));
+    assert_eq!(PolicyViolation::TooLarge(100).to_string(), "Object exceeds the maximum size of 100 bytes allowed in this bucket");
+}
@@ -0,0 +1,75 @@
+#![cfg(test)]
+// Tests for the state of resumable uploads
+
+use std::collections::BTreeMap;
+use chrono::{Duration, Utc};
+use crate::rdlib::s3::resumable::{DEFAULT_PART_SIZE, MAX_PARTS, MAX_UPLOAD_SIZE, UPLOAD_LOCK_SECONDS};
+use crate::rdlib::s3::types::{ResumableUpload, UploadedPart};
+
+#[test]
+fn test_new_resumable_upload() {
+    let upload = ResumableUpload::new("bucket1", "videos/talk.mp4", "video/mp4", "multipart-1", 1024, BTreeMap::new());
+
+    assert_eq!(upload.id.len(), 32, "ID should be a simple UUID");
+    assert!(upload.id.chars().all(|c| c.is_ascii_hexdigit()));
+    assert_eq!(upload.key, "videos/talk.mp4");
+    assert_eq!(upload.part_size, DEFAULT_PART_SIZE);
+    assert_eq!(upload.offset(), 0);
+    assert!(upload.expires_at > upload.created_at);
+}
+
+#[test]
+fn test_part_size_fits_part_limit() {
+    assert_eq!(ResumableUpload::part_size_for(0), DEFAULT_PART_SIZE);
+    assert_eq!(ResumableUpload::part_size_for(DEFAULT_PART_SIZE * MAX_PARTS), DEFAULT_PART_SIZE);
+
+    let part_size = ResumableUpload::part_size_for(MAX_UPLOAD_SIZE);
+    assert!(part_size > DEFAULT_PART_SIZE);
+    assert!(MAX_UPLOAD_SIZE.div_ceil(part_size) <= MAX_PARTS, "The largest upload must fit into the part limit");
+}
+
+#[test]
+fn test_offset_counts_parts_and_pending_data() {
+    let mut upload = ResumableUpload::new("bucket1", "file.bin", "application/octet-stream", "multipart-1", 100, BTreeMap::new());
+    upload.parts.push(UploadedPart { part_number: 1, etag: "\"a\"".to_string(), size: 40 });
+    upload.parts.push(UploadedPart { part_number: 2, etag: "\"b\"".to_string(), size: 40 });
+    upload.pending_size = 7;
+
+    assert_eq!(upload.offset(), 87);
+}
+
+#[test]
+fn test_resumable_upload_expiry() {
+    let now = Utc::now();
+    let mut upload = ResumableUpload::new("bucket1", "file.bin", "application/octet-stream", "multipart-1", 100, BTreeMap::new());
+    assert!(!upload.is_expired(now));
+
+    upload.expires_at = now - Duration::seconds(1);
+    assert!(upload.is_expired(now));
+}
+
+#[test]
+fn test_resumable_upload_lock() {
+    let now = Utc::now();
+    let mut upload = ResumableUpload::new("bucket1", "file.bin", "application/octet-stream", "multipart-1", 100, BTreeMap::new());
+    assert!(!upload.is_locked(now));
+
+    upload.lock(now);
+    assert!(upload.is_locked(now));
+    assert!(!upload.is_locked(now + Duration::seconds(UPLOAD_LOCK_SECONDS)), "Locks of failed servers should run out");
+}
+
+#[test]
+fn test_resumable_upload_state_serialization() {
+    let mut upload = ResumableUpload::new("bucket1", "file.bin", "application/octet-stream", "multipart-1", 100, BTreeMap::new());
+    upload.state_etag = Some("abc".to_string());
+
+    let mut json = serde_json::to_value(&upload).unwrap();
+    assert!(json.get("state_etag").is_none(), "The ETag belongs to the stored state, not into it");
+
+    // State stored before uploads could be locked is still readable
+    json.as_object_mut().unwrap().remove("locked_until");
+    let upload: ResumableUpload = serde_json::from_value(json).unwrap();
+    assert_eq!(upload.locked_until, None);
+    assert_eq!(upload.state_etag, None);
+}
@@ -3,7 +3,10 @@
 //! This module provides test-specific helpers for the S3Service.
 //! It is only included when running tests.
 
+use std::sync::Arc;
+use aws_config::BehaviorVersion;
 use aws_sdk_s3::Client;
+use aws_sdk_s3::config::{Builder, Credentials, Region};
 use crate::rdlib::s3::service::S3Service;
 
 impl S3Service {
@@ -25,4 +28,33 @@ impl S3Service {
             client,
         }
     }
-} 
\ No newline at end of file
+}
+
+/// Creates an S3Service for tests that points at a local endpoint.
+///
+/// Nothing is expected to listen on the endpoint, so the service is only
+/// suitable for tests of what happens before S3 is contacted.
+///
+/// # Returns
+///
+/// A shared `S3Service` instance
+pub(crate) async fn create_test_s3_service() -> Arc<S3Service> {
+    let config = aws_config::defaults(BehaviorVersion::latest())
+        .endpoint_url("http://localhost:7000")
+        .region(Region::new("eu-central-1"))
+        .credentials_provider(Credentials::new(
+            "test-access-key",
+            "test-secret-key",
+            None,
+            None,
+            "test-credentials",
+        ))
+        .load()
+        .await;
+
+    let s3_config = Builder::from(&config)
+        .force_path_style(true)
+        .build();
+
+    Arc::new(S3Service::new_with_client(Client::from_conf(s3_config)))
+}
@@ -0,0 +1,7 @@
+#[cfg(test)]
+pub mod memory_store;
+pub mod user_tests;
+pub mod service_tests;
+pub mod two_factor_tests;
+pub mod session_tests;
+pub mod mysql_tests;
@@ -0,0 +1,278 @@
+#![cfg(test)]
+// An in-memory user store standing in for MySQL in tests
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+use crate::rdlib::auth::tokens::TokenIssuer;
+use crate::rdlib::users::error::UserError;
+use crate::rdlib::users::service::UserService;
+use crate::rdlib::users::session::{NewSession, Session};
+use crate::rdlib::users::store::UserStore;
+use crate::rdlib::users::two_factor::TwoFactorPolicy;
+use crate::rdlib::users::user::{NewUser, RefreshToken, User, UserChanges};
+
+/// The secret access tokens of test user services are signed with
+pub const TEST_JWT_SECRET: &[u8] = b"a-test-secret-of-at-least-32-bytes!";
+
+#[derive(Default)]
+struct State {
+    next_id: u64,
+    users: BTreeMap<u64, User>,
+    identities: BTreeMap<(String, String), u64>,
+    totp_last_steps: BTreeMap<u64, i64>,
+    recovery_codes: BTreeMap<u64, Vec<String>>,
+    setup_tokens: BTreeMap<u64, (String, DateTime<Utc>)>,
+    refresh_tokens: Vec<RefreshToken>,
+    next_session_id: u64,
+    sessions: BTreeMap<u64, Session>,
+}
+
+/// Keeps users in memory, with the same behavior as `MySqlUserStore`.
+#[derive(Default)]
+pub struct MemoryUserStore {
+    state: Mutex<State>,
+}
+
+impl MemoryUserStore {
+    /// The number of stored refresh tokens.
+    pub fn refresh_token_count(&self) -> usize {
+        self.state.lock().unwrap().refresh_tokens.len()
+    }
+
+    /// The number of stored sessions.
+    pub fn session_count(&self) -> usize {
+        self.state.lock().unwrap().sessions.len()
+    }
+
+    /// The number of unused recovery codes of a user.
+    pub fn recovery_code_count(&self, user_id: u64) -> usize {
+        self.state.lock().unwrap().recovery_codes.get(&user_id).map_or(0, Vec::len)
+    }
+}
+
+#[async_trait]
+impl UserStore for MemoryUserStore {
+    async fn create_user(&self, user: NewUser, now: DateTime<Utc>) -> Result<User, UserError> {
+        let mut state = self.state.lock().unwrap();
+        if state.users.values().any(|u| u.username == user.username) {
+            return Err(UserError::UsernameTaken(user.username));
+        }
+        state.next_id += 1;
+        let user = User {
+            id: state.next_id,
+            username: user.username,
+            password_hash: user.password_hash,
+            role: user.role,
+            email: user.email,
+            totp_secret: None,
+            two_factor_enabled: false,
+            disabled: false,
+            created_at: now,
+            updated_at: now,
+            last_login_at: None,
+        };
+        state.users.insert(user.id, user.clone());
+        Ok(user)
+    }
+
+    async fn get_user(&self, id: u64) -> Result<Option<User>, UserError> {
+        Ok(self.state.lock().unwrap().users.get(&id).cloned())
+    }
+
+    async fn find_user(&self, username: &str) -> Result<Option<User>, UserError> {
+        Ok(self.state.lock().unwrap().users.values().find(|u| u.username == username).cloned())
+    }
+
+    async fn find_user_by_identity(&self, issuer: &str, subject: &str) -> Result<Option<User>, UserError> {
+        let state = self.state.lock().unwrap();
+        let user_id = state.identities.get(&(issuer.to_string(), subject.to_string()));
+        Ok(user_id.and_then(|id| state.users.get(id)).cloned())
+    }
+
+    async fn create_linked_user(&self, user: NewUser, issuer: &str, subject: &str, now: DateTime<Utc>) -> Result<User, UserError> {
+        let user = self.create_user(user, now).await?;
+        self.state.lock().unwrap().identities.insert((issuer.to_string(), subject.to_string()), user.id);
+        Ok(user)
+    }
+
+    async fn list_users(&self) -> Result<Vec<User>, UserError> {
+        Ok(self.state.lock().unwrap().users.values().cloned().collect())
+    }
+
+    async fn count_users(&self) -> Result<u64, UserError> {
+        Ok(self.state.lock().unwrap().users.len() as u64)
+    }
+
+    async fn update_user(&self, id: u64, changes: UserChanges, now: DateTime<Utc>) -> Result<Option<User>, UserError> {
+        let mut state = self.state.lock().unwrap();
+        let Some(user) = state.users.get_mut(&id) else {
+            return Ok(None);
+        };
+        if let Some(password_hash) = changes.password_hash {
+            user.password_hash = password_hash;
+        }
+        if let Some(role) = changes.role {
+            user.role = role;
+        }
+        if let Some(disabled) = changes.disabled {
+            user.disabled = disabled;
+        }
+        if let Some(email) = changes.email {
+            user.email = Some(email);
+        }
+        user.updated_at = now;
+        Ok(Some(user.clone()))
+    }
+
+    async fn record_login(&self, id: u64, now: DateTime<Utc>) -> Result<(), UserError> {
+        if let Some(user) = self.state.lock().unwrap().users.get_mut(&id) {
+            user.last_login_at = Some(now);
+        }
+        Ok(())
+    }
+
+    async fn set_totp(&self, id: u64, secret: Option<&str>, enabled: bool, now: DateTime<Utc>) -> Result<bool, UserError> {
+        let mut state = self.state.lock().unwrap();
+        let Some(user) = state.users.get_mut(&id) else {
+            return Ok(false);
+        };
+        user.totp_secret = secret.map(str::to_string);
+        user.two_factor_enabled = enabled;
+        user.updated_at = now;
+        Ok(true)
+    }
+
+    async fn use_totp_step(&self, id: u64, step: i64) -> Result<bool, UserError> {
+        let mut state = self.state.lock().unwrap();
+        if !state.users.contains_key(&id) || state.totp_last_steps.get(&id).is_some_and(|last| *last >= step) {
+            return Ok(false);
+        }
+        state.totp_last_steps.insert(id, step);
+        Ok(true)
+    }
+
+    async fn replace_recovery_codes(&self, user_id: u64, code_hashes: &[String], _now: DateTime<Utc>) -> Result<(), UserError> {
+        self.state.lock().unwrap().recovery_codes.insert(user_id, code_hashes.to_vec());
+        Ok(())
+    }
+
+    async fn replace_setup_token(&self, user_id: u64, token_hash: &str, expires_at: DateTime<Utc>, _now: DateTime<Utc>) -> Result<(), UserError> {
+        self.state.lock().unwrap().setup_tokens.insert(user_id, (token_hash.to_string(), expires_at));
+        Ok(())
+    }
+
+    async fn find_setup_token(&self, token_hash: &str, now: DateTime<Utc>) -> Result<Option<u64>, UserError> {
+        let state = self.state.lock().unwrap();
+        Ok(state.setup_tokens.iter()
+            .find(|(_, (hash, expires_at))| hash == token_hash && *expires_at > now)
+            .map(|(user_id, _)| *user_id))
+    }
+
+    async fn revoke_setup_token(&self, user_id: u64) -> Result<(), UserError> {
+        self.state.lock().unwrap().setup_tokens.remove(&user_id);
+        Ok(())
+    }
+
+    async fn take_recovery_code(&self, user_id: u64, code_hash: &str) -> Result<bool, UserError> {
+        let mut state = self.state.lock().unwrap();
+        let Some(codes) = state.recovery_codes.get_mut(&user_id) else {
+            return Ok(false);
+        };
+        let position = codes.iter().position(|c| c == code_hash);
+        Ok(position.map(|i| codes.remove(i)).is_some())
+    }
+
+    async fn delete_user(&self, id: u64) -> Result<bool, UserError> {
+        let mut state = self.state.lock().unwrap();
+        state.refresh_tokens.retain(|t| t.user_id != id);
+        state.totp_last_steps.remove(&id);
+        state.recovery_codes.remove(&id);
+        state.setup_tokens.remove(&id);
+        state.sessions.retain(|_, s| s.user_id != id);
+        state.identities.retain(|_, user_id| *user_id != id);
+        Ok(state.users.remove(&id).is_some())
+    }
+
+    async fn add_refresh_token(&self, token: RefreshToken, now: DateTime<Utc>) -> Result<(), UserError> {
+        let mut state = self.state.lock().unwrap();
+        state.refresh_tokens.retain(|t| t.expires_at >= now);
+        state.refresh_tokens.push(token);
+        Ok(())
+    }
+
+    async fn take_refresh_token(&self, token_hash: &str) -> Result<Option<RefreshToken>, UserError> {
+        let mut state = self.state.lock().unwrap();
+        let position = state.refresh_tokens.iter().position(|t| t.token_hash == token_hash);
+        Ok(position.map(|i| state.refresh_tokens.remove(i)))
+    }
+
+    async fn revoke_refresh_tokens(&self, user_id: u64) -> Result<(), UserError> {
+        self.state.lock().unwrap().refresh_tokens.retain(|t| t.user_id != user_id);
+        Ok(())
+    }
+
+    async fn create_session(&self, session: NewSession, now: DateTime<Utc>) -> Result<Session, UserError> {
+        let mut state = self.state.lock().unwrap();
+        state.sessions.retain(|_, s| s.expires_at >= now);
+        state.next_session_id += 1;
+        let session = Session {
+            id: state.next_session_id,
+            token_hash: session.token_hash,
+            user_id: session.user_id,
+            csrf_token: session.csrf_token,
+            user_agent: session.user_agent,
+            ip_address: session.ip_address,
+            created_at: now,
+            last_seen_at: now,
+            expires_at: session.expires_at,
+        };
+        state.sessions.insert(session.id, session.clone());
+        Ok(session)
+    }
+
+    async fn find_session(&self, token_hash: &str) -> Result<Option<Session>, UserError> {
+        Ok(self.state.lock().unwrap().sessions.values().find(|s| s.token_hash == token_hash).cloned())
+    }
+
+    async fn touch_session(&self, id: u64, now: DateTime<Utc>, expires_at: DateTime<Utc>) -> Result<(), UserError> {
+        if let Some(session) = self.state.lock().unwrap().sessions.get_mut(&id) {
+            session.last_seen_at = now;
+            session.expires_at = expires_at;
+        }
+        Ok(())
+    }
+
+    async fn list_sessions(&self, user_id: u64, now: DateTime<Utc>) -> Result<Vec<Session>, UserError> {
+        let state = self.state.lock().unwrap();
+        Ok(state.sessions.values().filter(|s| s.user_id == user_id && s.expires_at > now).cloned().collect())
+    }
+
+    async fn delete_session(&self, id: u64) -> Result<bool, UserError> {
+        Ok(self.state.lock().unwrap().sessions.remove(&id).is_some())
+    }
+
+    async fn revoke_sessions(&self, user_id: u64) -> Result<(), UserError> {
+        self.state.lock().unwrap().sessions.retain(|_, s| s.user_id != user_id);
+        Ok(())
+    }
+}
+
+/// Creates a user service on an in-memory store, with a cheap bcrypt cost.
+/// No role requires two-factor authentication, so administrators can log
+/// in with a password alone.
+pub fn create_test_user_service() -> (Arc<UserService>, Arc<MemoryUserStore>) {
+    create_test_user_service_with_policy(TwoFactorPolicy::optional())
+}
+
+/// Creates a user service on an in-memory store with a two-factor policy.
+pub fn create_test_user_service_with_policy(policy: TwoFactorPolicy) -> (Arc<UserService>, Arc<MemoryUserStore>) {
+    let store = Arc::new(MemoryUserStore::default());
+    let service = UserService::new(store.clone(), TokenIssuer::new(TEST_JWT_SECRET))
+        .with_bcrypt_cost(4)
+        .with_two_factor_policy(policy);
+    (Arc::new(service), store)
+}
@@ -0,0 +1,183 @@
+#![cfg(test)]
+// Tests for user management, logins and tokens
+// These tests use the in-memory store in place of MySQL
+
+use crate::rdlib::auth::jwt::JwtVerifier;
+use crate::rdlib::users::error::UserError;
+use crate::rdlib::users::service::{parse_bcrypt_cost, UserUpdate};
+use crate::rdlib::users::session::SessionClient;
+use crate::rdlib::users::user::{ExternalIdentity, Role};
+use crate::tests::users::memory_store::{create_test_user_service, TEST_JWT_SECRET};
+
+#[tokio::test]
+async fn test_create_user() {
+    let (users, _) = create_test_user_service();
+
+    let user = users.create_user("alice", "correct horse", Role::User).await.unwrap();
+    assert_eq!(user.username, "alice");
+    assert!(user.password_hash.starts_with("$2"), "Passwords are stored as bcrypt hashes");
+    assert!(!user.disabled);
+
+    assert_eq!(users.create_user("alice", "another password", Role::User).await, Err(UserError::UsernameTaken("alice".to_string())));
+    assert!(matches!(users.create_user("a b", "correct horse", Role::User).await, Err(UserError::InvalidUsername(_))));
+    assert!(matches!(users.create_user("bob", "short", Role::User).await, Err(UserError::InvalidPassword(_))));
+    assert_eq!(users.list_users().await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_login_issues_verifiable_tokens() {
+    let (users, _) = create_test_user_service();
+    let created = users.create_user("alice", "correct horse", Role::Admin).await.unwrap();
+
+    let tokens = users.login("alice", "correct horse", None).await.unwrap();
+    assert_eq!(tokens.token_type, "Bearer");
+    assert!(tokens.expires_in > 0 && tokens.refresh_expires_in > tokens.expires_in);
+
+    let principal = JwtVerifier::new().with_secret(TEST_JWT_SECRET).verify(&tokens.access_token).unwrap();
+    assert_eq!(principal.subject, "alice");
+    assert_eq!(principal.claims["uid"], created.id);
+    assert!(principal.is_admin());
+    assert!(users.get_user(created.id).await.unwrap().last_login_at.is_some());
+
+    assert_eq!(users.login("alice", "wrong password", None).await.unwrap_err(), UserError::InvalidCredentials);
+    assert_eq!(users.login("nobody", "correct horse", None).await.unwrap_err(), UserError::InvalidCredentials);
+}
+
+#[tokio::test]
+async fn test_refresh_tokens_are_used_once() {
+    let (users, store) = create_test_user_service();
+    users.create_user("alice", "correct horse", Role::User).await.unwrap();
+
+    let tokens = users.login("alice", "correct horse", None).await.unwrap();
+    let refreshed = users.refresh(&tokens.refresh_token).await.unwrap();
+    assert_ne!(refreshed.refresh_token, tokens.refresh_token);
+    assert_eq!(users.refresh(&tokens.refresh_token).await.unwrap_err(), UserError::InvalidRefreshToken);
+    assert_eq!(store.refresh_token_count(), 1);
+
+    users.logout(&refreshed.refresh_token).await.unwrap();
+    assert_eq!(users.refresh(&refreshed.refresh_token).await.unwrap_err(), UserError::InvalidRefreshToken);
+    assert_eq!(users.refresh("made-up").await.unwrap_err(), UserError::InvalidRefreshToken);
+}
+
+#[tokio::test]
+async fn test_disabled_users_cannot_log_in() {
+    let (users, store) = create_test_user_service();
+    let user = users.create_user("alice", "correct horse", Role::User).await.unwrap();
+    let tokens = users.login("alice", "correct horse", None).await.unwrap();
+
+    let update = UserUpdate { disabled: Some(true), ..UserUpdate::default() };
+    assert!(users.update_user(user.id, update).await.unwrap().disabled);
+    assert_eq!(store.refresh_token_count(), 0, "Disabling a user revokes its refresh tokens");
+    assert_eq!(users.refresh(&tokens.refresh_token).await.unwrap_err(), UserError::InvalidRefreshToken);
+    assert_eq!(users.login("alice", "correct horse", None).await.unwrap_err(), UserError::AccountDisabled);
+
+    let update = UserUpdate { disabled: Some(false), password: Some("new password".to_string()), ..UserUpdate::default() };
+    users.update_user(user.id, update).await.unwrap();
+    assert_eq!(users.login("alice", "correct horse", None).await.unwrap_err(), UserError::InvalidCredentials);
+    assert!(users.login("alice", "new password", None).await.is_ok());
+}
+
+#[tokio::test]
+async fn test_demoting_users_ends_their_sessions() {
+    let (users, store) = create_test_user_service();
+    let user = users.create_user("alice", "correct horse", Role::Admin).await.unwrap();
+    let tokens = users.login("alice", "correct horse", None).await.unwrap();
+
+    let update = UserUpdate { role: Some(Role::Admin), ..UserUpdate::default() };
+    users.update_user(user.id, update).await.unwrap();
+    assert_eq!(store.refresh_token_count(), 1, "Keeping the role keeps the refresh tokens");
+
+    let update = UserUpdate { role: Some(Role::User), ..UserUpdate::default() };
+    assert_eq!(users.update_user(user.id, update).await.unwrap().role, Role::User);
+    assert_eq!(store.refresh_token_count(), 0, "Demoting a user revokes its refresh tokens");
+    assert_eq!(users.refresh(&tokens.refresh_token).await.unwrap_err(), UserError::InvalidRefreshToken);
+}
+
+#[test]
+fn test_parse_bcrypt_cost() {
+    assert_eq!(parse_bcrypt_cost("4"), Ok(4));
+    assert_eq!(parse_bcrypt_cost(" 12 "), Ok(12));
+    assert_eq!(parse_bcrypt_cost("31"), Ok(31));
+    assert!(parse_bcrypt_cost("3").is_err());
+    assert!(parse_bcrypt_cost("32").is_err());
+    assert!(parse_bcrypt_cost("twelve").is_err());
+}
+
+#[tokio::test]
+async fn test_delete_user() {
+    let (users, _) = create_test_user_service();
+    let user = users.create_user("alice", "correct horse", Role::User).await.unwrap();
+
+    users.delete_user(user.id).await.unwrap();
+    assert_eq!(users.delete_user(user.id).await, Err(UserError::NotFound));
+    assert_eq!(users.get_user(user.id).await, Err(UserError::NotFound));
+    assert_eq!(users.update_user(user.id, UserUpdate::default()).await, Err(UserError::NotFound));
+}
+
+#[tokio::test]
+async fn test_bootstrap_admin_only_without_users() {
+    let (users, _) = create_test_user_service();
+
+    users.bootstrap_admin("admin", "admin password").await.unwrap();
+    users.bootstrap_admin("other-admin", "admin password").await.unwrap();
+
+    let list = users.list_users().await.unwrap();
+    assert_eq!(list.len(), 1);
+    assert_eq!(list[0].username, "admin");
+    assert_eq!(list[0].role, Role::Admin);
+}
+
+#[tokio::test]
+async fn test_external_sessions_provision_users() {
+    let (users, _) = create_test_user_service();
+    let identity = ExternalIdentity {
+        issuer: "https://idp.example.com".to_string(),
+        subject: "u-1001".to_string(),
+        username: "alice".to_string(),
+        email: Some("alice@example.com".to_string()),
+        role: None,
+    };
+
+    assert_eq!(users.start_external_session(&identity, false, SessionClient::default()).await.unwrap_err(), UserError::UnknownIdentity);
+    assert!(users.list_users().await.unwrap().is_empty());
+    let (user, started) = users.start_external_session(&identity, true, SessionClient::default()).await.unwrap();
+    assert_eq!((user.username.as_str(), user.role), ("alice", Role::User));
+    assert_eq!(user.email.as_deref(), Some("alice@example.com"));
+    assert!(users.get_user(user.id).await.unwrap().last_login_at.is_some());
+    let (session, principal) = users.authenticate_session(&started.token).await.unwrap();
+    assert_eq!((session.user_id, principal.subject.as_str()), (user.id, "alice"));
+    assert_eq!(users.login("alice", "", None).await.unwrap_err(), UserError::InvalidCredentials, "Provisioned users have no password");
+
+    // Later logins find the user by its identity, whatever its username, and update it
+    let renamed = ExternalIdentity {
+        username: "alice.smith".to_string(),
+        email: Some("alice.smith@example.com".to_string()),
+        role: Some(Role::Admin),
+        ..identity.clone()
+    };
+    let (again, _) = users.start_external_session(&renamed, false, SessionClient::default()).await.unwrap();
+    assert_eq!((again.id, again.username.as_str(), again.role), (user.id, "alice", Role::Admin));
+    assert_eq!(again.email.as_deref(), Some("alice.smith@example.com"));
+    assert_eq!(users.list_users().await.unwrap().len(), 1);
+
+    let update = UserUpdate { disabled: Some(true), ..UserUpdate::default() };
+    users.update_user(user.id, update).await.unwrap();
+    assert_eq!(users.start_external_session(&identity, true, SessionClient::default()).await.unwrap_err(), UserError::AccountDisabled);
+}
+
+#[tokio::test]
+async fn test_external_sessions_do_not_take_over_accounts() {
+    let (users, _) = create_test_user_service();
+    users.create_user("alice", "correct horse", Role::Admin).await.unwrap();
+    let identity = ExternalIdentity {
+        issuer: "https://idp.example.com".to_string(),
+        subject: "u-1001".to_string(),
+        username: "alice".to_string(),
+        email: None,
+        role: None,
+    };
+
+    assert_eq!(users.start_external_session(&identity, true, SessionClient::default()).await.unwrap_err(), UserError::UsernameTaken("alice".to_string()));
+    let invalid = ExternalIdentity { username: "alice smith".to_string(), ..identity };
+    assert!(matches!(users.start_external_session(&invalid, true, SessionClient::default()).await, Err(UserError::InvalidUsername(_))));
+}
@@ -0,0 +1,125 @@
+#![cfg(test)]
+// Tests for the web UI sessions of users
+// These tests use the in-memory store in place of MySQL
+
+use chrono::{Duration, TimeZone, Utc};
+
+use crate::rdlib::users::error::UserError;
+use crate::rdlib::users::service::UserUpdate;
+use crate::rdlib::users::session::{generate_session_token, hash_session_token, SessionClient, SessionLifetime};
+use crate::rdlib::users::store::UserStore;
+use crate::rdlib::users::two_factor::TwoFactorPolicy;
+use crate::rdlib::users::user::Role;
+use crate::tests::users::memory_store::{create_test_user_service, create_test_user_service_with_policy};
+use crate::tests::users::two_factor_tests::{current_code, enroll};
+
+fn client() -> SessionClient {
+    SessionClient { user_agent: Some("Mozilla/5.0".to_string()), ip_address: Some("192.0.2.1".to_string()) }
+}
+
+#[test]
+fn test_session_lifetime() {
+    let lifetime = SessionLifetime { idle_timeout: Duration::hours(2), max_age: Duration::hours(24) };
+    let created_at = Utc.with_ymd_and_hms(2025, 1, 1, 8, 0, 0).unwrap();
+
+    assert_eq!(lifetime.expiry(created_at, created_at), created_at + Duration::hours(2));
+    assert_eq!(lifetime.expiry(created_at, created_at + Duration::hours(5)), created_at + Duration::hours(7));
+    assert_eq!(lifetime.expiry(created_at, created_at + Duration::hours(23)), created_at + Duration::hours(24), "Sessions end after the maximum age");
+}
+
+#[test]
+fn test_session_tokens() {
+    let token = generate_session_token();
+    assert_eq!(token.len(), 44);
+    assert_ne!(token, generate_session_token());
+    assert_eq!(hash_session_token(&token).len(), 64);
+    assert_ne!(hash_session_token(&token), token);
+}
+
+#[tokio::test]
+async fn test_start_and_authenticate_session() {
+    let (users, store) = create_test_user_service();
+    let user = users.create_user("alice", "correct horse", Role::Admin).await.unwrap();
+
+    assert_eq!(users.start_session("alice", "wrong password", None, client()).await.unwrap_err(), UserError::InvalidCredentials);
+    let (logged_in, started) = users.start_session("alice", "correct horse", None, client()).await.unwrap();
+    assert_eq!(logged_in.id, user.id);
+    assert_eq!(started.session.user_agent.as_deref(), Some("Mozilla/5.0"));
+    assert_ne!(started.session.csrf_token, started.token);
+    assert_eq!(store.session_count(), 1);
+    assert!(users.get_user(user.id).await.unwrap().last_login_at.is_some());
+
+    let (session, principal) = users.authenticate_session(&started.token).await.unwrap();
+    assert_eq!(session.id, started.session.id);
+    assert_eq!(principal.subject, "alice");
+    assert!(principal.is_admin());
+    assert_eq!(principal.claims["uid"], user.id);
+    assert_eq!(principal.claims["sid"], session.id);
+
+    assert_eq!(users.authenticate_session("made-up").await.unwrap_err(), UserError::InvalidSession);
+    users.end_session(&started.token).await.unwrap();
+    assert_eq!(users.authenticate_session(&started.token).await.unwrap_err(), UserError::InvalidSession);
+    assert_eq!(store.session_count(), 0);
+}
+
+#[tokio::test]
+async fn test_expired_sessions_are_rejected() {
+    let (users, store) = create_test_user_service();
+    let user = users.create_user("alice", "correct horse", Role::User).await.unwrap();
+    let (_, started) = users.start_session("alice", "correct horse", None, client()).await.unwrap();
+
+    let now = Utc::now();
+    store.touch_session(started.session.id, now, now - Duration::seconds(1)).await.unwrap();
+    assert_eq!(users.authenticate_session(&started.token).await.unwrap_err(), UserError::InvalidSession);
+    assert!(users.list_sessions(user.id).await.unwrap().is_empty(), "Expired sessions are not listed");
+}
+
+#[tokio::test]
+async fn test_sessions_need_second_factor() {
+    let (users, _) = create_test_user_service_with_policy(TwoFactorPolicy::default());
+    users.create_user("admin", "admin password", Role::Admin).await.unwrap();
+    assert!(matches!(
+        users.start_session("admin", "admin password", None, client()).await,
+        Err(UserError::TwoFactorSetupRequired(Some(_)))
+    ));
+
+    let (setup, _) = enroll(&users, "admin", "admin password").await;
+    assert_eq!(users.start_session("admin", "admin password", None, client()).await.unwrap_err(), UserError::TwoFactorRequired);
+    assert!(users.start_session("admin", "admin password", Some(&current_code(&setup.secret, 1)), client()).await.is_ok());
+}
+
+#[tokio::test]
+async fn test_list_and_revoke_sessions() {
+    let (users, store) = create_test_user_service();
+    let alice = users.create_user("alice", "correct horse", Role::User).await.unwrap();
+    let bob = users.create_user("bob", "battery staple", Role::User).await.unwrap();
+    let (_, first) = users.start_session("alice", "correct horse", None, client()).await.unwrap();
+    let (_, second) = users.start_session("alice", "correct horse", None, SessionClient::default()).await.unwrap();
+    let (_, bobs) = users.start_session("bob", "battery staple", None, client()).await.unwrap();
+
+    assert_eq!(users.list_sessions(alice.id).await.unwrap().len(), 2);
+    assert_eq!(users.revoke_session(alice.id, bobs.session.id).await, Err(UserError::SessionNotFound), "Users revoke only their own sessions");
+    users.revoke_session(alice.id, first.session.id).await.unwrap();
+    assert_eq!(users.revoke_session(alice.id, first.session.id).await, Err(UserError::SessionNotFound));
+    assert!(users.authenticate_session(&second.token).await.is_ok());
+
+    users.revoke_all_sessions(bob.id).await.unwrap();
+    assert_eq!(users.authenticate_session(&bobs.token).await.unwrap_err(), UserError::InvalidSession);
+    assert_eq!(users.revoke_all_sessions(999).await, Err(UserError::NotFound));
+
+    let update = UserUpdate { password: Some("new correct horse".to_string()), ..UserUpdate::default() };
+    users.update_user(alice.id, update).await.unwrap();
+    assert_eq!(store.session_count(), 0, "Changing the password ends the sessions of the user");
+}
+
+#[tokio::test]
+async fn test_sessions_of_disabled_users_are_rejected() {
+    let (users, store) = create_test_user_service();
+    let user = users.create_user("alice", "correct horse", Role::User).await.unwrap();
+    let (_, started) = users.start_session("alice", "correct horse", None, client()).await.unwrap();
+
+    let update = UserUpdate { disabled: Some(true), ..UserUpdate::default() };
+    users.update_user(user.id, update).await.unwrap();
+    assert_eq!(store.session_count(), 0);
+    assert_eq!(users.authenticate_session(&started.token).await.unwrap_err(), UserError::InvalidSession);
+}
@@ -0,0 +1,240 @@
+#![cfg(test)]
+// Tests for TOTP codes, recovery codes and two-factor logins
+// The service tests use the in-memory store in place of MySQL
+
+use chrono::{TimeZone, Utc};
+
+use crate::rdlib::users::error::UserError;
+use crate::rdlib::users::service::{UserService, UserUpdate};
+use crate::rdlib::users::two_factor::{
+    decode_secret, generate_recovery_codes, generate_secret, hash_recovery_code, otpauth_uri, totp_code, totp_step,
+    verify_totp, AttemptLimiter, ClientThrottle, TotpSetup, TwoFactorPolicy, CLIENT_REQUEST_WINDOW_SECONDS,
+    MAX_CLIENT_REQUESTS, MAX_FAILED_ATTEMPTS, RECOVERY_CODE_COUNT
+};
+use crate::rdlib::users::user::Role;
+use crate::tests::users::memory_store::create_test_user_service_with_policy;
+
+/// The secret of the test vectors of RFC 6238, "12345678901234567890" in base32
+const RFC_SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+/// The code of a secret `offset` steps from now, as an authenticator app shows it.
+pub fn current_code(secret: &str, offset: i64) -> String {
+    totp_code(&decode_secret(secret).unwrap(), totp_step(Utc::now()) + offset)
+}
+
+/// Sets up and enables two-factor authentication of a user, returning its
+/// secret and recovery codes.
+pub async fn enroll(users: &UserService, username: &str, password: &str) -> (TotpSetup, Vec<String>) {
+    let setup_token = users.two_factor_setup_token(username, password).await.unwrap();
+    let setup = users.setup_two_factor(&setup_token).await.unwrap();
+    let recovery_codes = users.enable_two_factor(&setup_token, &current_code(&setup.secret, 0)).await.unwrap();
+    (setup, recovery_codes)
+}
+
+#[test]
+fn test_totp_matches_rfc_6238() {
+    let secret = decode_secret(RFC_SECRET).unwrap();
+    assert_eq!(secret, b"12345678901234567890");
+
+    for (time, code) in [(59, "287082"), (1111111109, "081804"), (1234567890, "005924"), (2000000000, "279037")] {
+        let now = Utc.timestamp_opt(time, 0).unwrap();
+        assert_eq!(totp_code(&secret, totp_step(now)), code, "Code at {}", time);
+    }
+}
+
+#[test]
+fn test_verify_totp_tolerates_clock_skew() {
+    let now = Utc.timestamp_opt(1111111109, 0).unwrap();
+    let step = totp_step(now);
+    let secret = decode_secret(RFC_SECRET).unwrap();
+
+    assert_eq!(verify_totp(RFC_SECRET, "081804", now), Some(step));
+    assert_eq!(verify_totp(&RFC_SECRET.to_lowercase(), " 081804 ", now), Some(step));
+    assert_eq!(verify_totp(RFC_SECRET, &totp_code(&secret, step - 1), now), Some(step - 1));
+    assert_eq!(verify_totp(RFC_SECRET, &totp_code(&secret, step + 1), now), Some(step + 1));
+    assert_eq!(verify_totp(RFC_SECRET, &totp_code(&secret, step + 2), now), None);
+    assert_eq!(verify_totp(RFC_SECRET, "000000", now), None);
+    assert_eq!(verify_totp(RFC_SECRET, "81804", now), None);
+    assert_eq!(verify_totp("not base32!", "081804", now), None);
+}
+
+#[test]
+fn test_generate_secret() {
+    let secret = generate_secret();
+    assert_eq!(secret.len(), 32, "20 bytes are 32 base32 characters");
+    assert_eq!(decode_secret(&secret).unwrap().len(), 20);
+    assert_ne!(secret, generate_secret());
+}
+
+#[test]
+fn test_otpauth_uri() {
+    assert_eq!(
+        otpauth_uri("RustDok", "alice@example.com", "JBSWY3DPEHPK3PXP"),
+        "otpauth://totp/RustDok:alice@example.com?secret=JBSWY3DPEHPK3PXP&issuer=RustDok&algorithm=SHA1&digits=6&period=30"
+    );
+    assert!(otpauth_uri("Rust Dok", "alice", "ABC").starts_with("otpauth://totp/Rust%20Dok:alice?secret=ABC&issuer=Rust+Dok&"));
+}
+
+#[test]
+fn test_recovery_codes() {
+    let codes = generate_recovery_codes();
+    assert_eq!(codes.len(), RECOVERY_CODE_COUNT);
+    for code in &codes {
+        assert_eq!(code.len(), 11);
+        assert_eq!(code.as_bytes()[5], b'-');
+    }
+    assert_ne!(codes[0], codes[1]);
+
+    let hash = hash_recovery_code("k3x9q-7hd2m");
+    assert_eq!(hash.len(), 64);
+    assert_eq!(hash_recovery_code("K3X9Q7HD2M"), hash, "Case and dashes are ignored");
+    assert_eq!(hash_recovery_code(" k3x9q 7hd2m "), hash, "Spaces are ignored");
+    assert_ne!(hash_recovery_code("k3x9q-7hd2n"), hash);
+}
+
+#[test]
+fn test_policy_and_attempt_limiter() {
+    assert!(TwoFactorPolicy::default().requires(Role::Admin));
+    assert!(!TwoFactorPolicy::default().requires(Role::User));
+    assert!(!TwoFactorPolicy::optional().requires(Role::Admin));
+
+    let limiter = AttemptLimiter::default();
+    let now = Utc::now();
+    for _ in 0..MAX_FAILED_ATTEMPTS {
+        assert!(!limiter.is_locked(1, now));
+        limiter.record_failure(1, now);
+    }
+    assert!(limiter.is_locked(1, now));
+    assert!(!limiter.is_locked(2, now), "Each user is counted on its own");
+    assert!(!limiter.is_locked(1, now + chrono::Duration::minutes(16)), "Old failures are forgotten");
+
+    limiter.record_failure(2, now);
+    limiter.clear(2);
+    assert!(!limiter.is_locked(2, now));
+}
+
+#[test]
+fn test_client_throttle() {
+    let throttle = ClientThrottle::default();
+    let now = Utc::now();
+    for _ in 0..MAX_CLIENT_REQUESTS {
+        assert!(throttle.allow("192.0.2.1", now));
+    }
+    assert!(!throttle.allow("192.0.2.1", now));
+    assert!(throttle.allow("192.0.2.2", now), "Each client is counted on its own");
+    assert!(throttle.allow("192.0.2.1", now + chrono::Duration::seconds(CLIENT_REQUEST_WINDOW_SECONDS)), "Old requests are forgotten");
+}
+
+#[tokio::test]
+async fn test_enable_two_factor_and_log_in() {
+    let (users, store) = create_test_user_service_with_policy(TwoFactorPolicy::optional());
+    let user = users.create_user("alice", "correct horse", Role::User).await.unwrap();
+
+    assert_eq!(users.two_factor_setup_token("alice", "wrong password").await, Err(UserError::InvalidCredentials));
+    assert_eq!(users.setup_two_factor("unknown").await, Err(UserError::InvalidSetupToken));
+    let setup_token = users.two_factor_setup_token("alice", "correct horse").await.unwrap();
+    assert_eq!(users.enable_two_factor(&setup_token, "123456").await, Err(UserError::TwoFactorNotSetUp));
+    let setup = users.setup_two_factor(&setup_token).await.unwrap();
+    assert!(setup.otpauth_uri.contains(&format!("secret={}", setup.secret)));
+    assert!(users.login("alice", "correct horse", None).await.is_ok(), "Setting up does not enable two-factor authentication");
+
+    assert_eq!(users.enable_two_factor(&setup_token, "000000").await, Err(UserError::InvalidTwoFactorCode));
+    let recovery_codes = users.enable_two_factor(&setup_token, &current_code(&setup.secret, 0)).await.unwrap();
+    assert_eq!(recovery_codes.len(), RECOVERY_CODE_COUNT);
+    assert_eq!(store.recovery_code_count(user.id), RECOVERY_CODE_COUNT);
+    assert!(users.get_user(user.id).await.unwrap().two_factor_enabled);
+    assert_eq!(users.setup_two_factor(&setup_token).await, Err(UserError::InvalidSetupToken), "Enabling uses up the setup token");
+    assert_eq!(users.two_factor_setup_token("alice", "correct horse").await, Err(UserError::TwoFactorAlreadyEnabled));
+
+    assert_eq!(users.login("alice", "correct horse", None).await.unwrap_err(), UserError::TwoFactorRequired);
+    assert_eq!(users.login("alice", "wrong password", None).await.unwrap_err(), UserError::InvalidCredentials, "The password is checked first");
+    let code = current_code(&setup.secret, 1);
+    assert!(users.login("alice", "correct horse", Some(&code)).await.is_ok());
+    assert_eq!(users.login("alice", "correct horse", Some(&code)).await.unwrap_err(), UserError::InvalidTwoFactorCode, "Codes are accepted once");
+
+    assert!(users.login("alice", "correct horse", Some(&recovery_codes[0].to_uppercase())).await.is_ok());
+    assert_eq!(users.login("alice", "correct horse", Some(&recovery_codes[0])).await.unwrap_err(), UserError::InvalidTwoFactorCode);
+    assert_eq!(store.recovery_code_count(user.id), RECOVERY_CODE_COUNT - 1);
+}
+
+#[tokio::test]
+async fn test_policy_requires_two_factor() {
+    let (users, _) = create_test_user_service_with_policy(TwoFactorPolicy::default());
+    users.create_user("admin", "admin password", Role::Admin).await.unwrap();
+    users.create_user("alice", "correct horse", Role::User).await.unwrap();
+
+    assert!(users.login("alice", "correct horse", None).await.is_ok());
+    assert_eq!(users.login("admin", "wrong password", None).await.unwrap_err(), UserError::InvalidCredentials);
+    let Err(UserError::TwoFactorSetupRequired(Some(setup_token))) = users.login("admin", "admin password", None).await else {
+        panic!("The login must hand out a setup token");
+    };
+
+    // Only the latest setup token of a user is valid
+    let Err(UserError::TwoFactorSetupRequired(Some(latest))) = users.login("admin", "admin password", None).await else {
+        panic!("The login must hand out a setup token");
+    };
+    assert_eq!(users.setup_two_factor(&setup_token).await, Err(UserError::InvalidSetupToken));
+    let setup = users.setup_two_factor(&latest).await.unwrap();
+    let recovery_codes = users.enable_two_factor(&latest, &current_code(&setup.secret, 0)).await.unwrap();
+    let tokens = users.login("admin", "admin password", Some(&current_code(&setup.secret, 1))).await.unwrap();
+    assert!(users.refresh(&tokens.refresh_token).await.is_ok());
+
+    assert_eq!(
+        users.disable_two_factor("admin", "admin password", &recovery_codes[0]).await,
+        Err(UserError::TwoFactorMandatory(Role::Admin))
+    );
+}
+
+#[tokio::test]
+async fn test_refresh_requires_two_factor_of_promoted_users() {
+    let (users, _) = create_test_user_service_with_policy(TwoFactorPolicy::default());
+    let user = users.create_user("alice", "correct horse", Role::User).await.unwrap();
+    let tokens = users.login("alice", "correct horse", None).await.unwrap();
+
+    let update = UserUpdate { role: Some(Role::Admin), ..UserUpdate::default() };
+    users.update_user(user.id, update).await.unwrap();
+    assert_eq!(users.refresh(&tokens.refresh_token).await.unwrap_err(), UserError::TwoFactorSetupRequired(None));
+}
+
+#[tokio::test]
+async fn test_disable_and_reset_two_factor() {
+    let (users, store) = create_test_user_service_with_policy(TwoFactorPolicy::optional());
+    let user = users.create_user("alice", "correct horse", Role::User).await.unwrap();
+    assert_eq!(users.disable_two_factor("alice", "correct horse", "123456").await, Err(UserError::TwoFactorNotEnabled));
+
+    let (_, recovery_codes) = enroll(&users, "alice", "correct horse").await;
+    let new_codes = users.regenerate_recovery_codes("alice", "correct horse", &recovery_codes[0]).await.unwrap();
+    assert_eq!(users.login("alice", "correct horse", Some(&recovery_codes[1])).await.unwrap_err(), UserError::InvalidTwoFactorCode, "Old codes stop working");
+
+    users.disable_two_factor("alice", "correct horse", &new_codes[0]).await.unwrap();
+    assert!(!users.get_user(user.id).await.unwrap().two_factor_enabled);
+    assert_eq!(store.recovery_code_count(user.id), 0);
+    assert!(users.login("alice", "correct horse", None).await.is_ok());
+
+    let setup_token = users.two_factor_setup_token("alice", "correct horse").await.unwrap();
+    let setup = users.setup_two_factor(&setup_token).await.unwrap();
+    let recovery_codes = users.enable_two_factor(&setup_token, &current_code(&setup.secret, 1)).await.unwrap();
+    users.login("alice", "correct horse", Some(&recovery_codes[0])).await.unwrap();
+    assert_eq!(store.refresh_token_count(), 2);
+
+    let reset = users.reset_two_factor(user.id).await.unwrap();
+    assert!(!reset.two_factor_enabled && reset.totp_secret.is_none());
+    assert_eq!(store.refresh_token_count(), 0, "Resetting ends the sessions of the user");
+    assert!(users.login("alice", "correct horse", None).await.is_ok());
+    assert_eq!(users.reset_two_factor(999).await.unwrap_err(), UserError::NotFound);
+}
+
+#[tokio::test]
+async fn test_too_many_invalid_codes_lock_two_factor() {
+    let (users, _) = create_test_user_service_with_policy(TwoFactorPolicy::optional());
+    users.create_user("alice", "correct horse", Role::User).await.unwrap();
+    let (setup, _) = enroll(&users, "alice", "correct horse").await;
+
+    for _ in 0..MAX_FAILED_ATTEMPTS {
+        assert_eq!(users.login("alice", "correct horse", Some("000000")).await.unwrap_err(), UserError::InvalidTwoFactorCode);
+    }
+    assert_eq!(
+        users.login("alice", "correct horse", Some(&current_code(&setup.secret, 1))).await.unwrap_err(),
+        UserError::TooManyTwoFactorAttempts
+    );
+}
@@ -0,0 +1,57 @@
+#![cfg(test)]
+// Tests for usernames, passwords and roles
+
+use chrono::Utc;
+
+use crate::rdlib::users::user::{validate_password, validate_username, Role, User};
+
+#[test]
+fn test_validate_username() {
+    assert!(validate_username("alice").is_ok());
+    assert!(validate_username("alice.smith@example.com").is_ok());
+    assert!(validate_username("a_b-c").is_ok());
+
+    assert!(validate_username("al").is_err());
+    assert!(validate_username(&"a".repeat(65)).is_err());
+    assert!(validate_username(".alice").is_err());
+    assert!(validate_username("alice smith").is_err());
+    assert!(validate_username("alice/../bob").is_err());
+}
+
+#[test]
+fn test_validate_password() {
+    assert!(validate_password("correct horse").is_ok());
+    assert!(validate_password("short").is_err());
+    assert!(validate_password(&"x".repeat(73)).is_err(), "bcrypt ignores bytes after 72");
+}
+
+#[test]
+fn test_roles() {
+    assert_eq!("admin".parse::<Role>(), Ok(Role::Admin));
+    assert_eq!("user".parse::<Role>(), Ok(Role::User));
+    assert!("root".parse::<Role>().is_err());
+    assert_eq!(serde_json::to_value(Role::Admin).unwrap(), "admin");
+}
+
+#[test]
+fn test_secrets_are_not_serialized() {
+    let user = User {
+        id: 1,
+        username: "alice".to_string(),
+        password_hash: "$2b$04$secret".to_string(),
+        role: Role::User,
+        email: None,
+        totp_secret: Some("JBSWY3DPEHPK3PXP".to_string()),
+        two_factor_enabled: true,
+        disabled: false,
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+        last_login_at: None,
+    };
+    let json = serde_json::to_value(&user).unwrap();
+    assert_eq!(json["username"], "alice");
+    assert_eq!(json["role"], "user");
+    assert!(json.get("password_hash").is_none());
+    assert!(json.get("totp_secret").is_none());
+    assert_eq!(json["two_factor_enabled"], true);
+}